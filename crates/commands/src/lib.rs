@@ -1,15 +1,11 @@
-#![feature(slice_range)]
-
-use std::ops::RangeBounds;
-
 use bitflags::bitflags;
-use crossterm::event::{KeyEvent, KeyModifiers};
-use fuzzy_matcher::skim::SkimMatcherV2;
-use fuzzy_matcher::FuzzyMatcher;
 use slotmap::{new_key_type, SlotMap};
 use tokio::sync::mpsc;
 
-use tore::Point;
+use selector::{Direction, FuzzyIndex, QueryInput};
+use tore::key::{InputKey, Key, Modifiers};
+
+pub use selector::{label, label_parts, Indices, Match, MatchedText, Matcher};
 
 #[derive(Debug, Clone)]
 pub enum Command {
@@ -25,7 +21,7 @@ pub enum Mode {
 }
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct EntryMode: u8 {
         const ALWAYS   = 0b0000;
         const VISIBLE  = 0b0001;
@@ -33,185 +29,332 @@ bitflags! {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaneKind {
+    Commands,
+    Editor,
+    Preview,
+    Grep,
+    Outline,
+    Prompt,
+}
+
+/// Snapshot of the panes the commands are run against.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Context {
+    pub focused: Option<PaneKind>,
+    pub visible: Vec<PaneKind>,
+}
+
+impl Context {
+    /// The entry modes satisfied by this context; an entry is available when its mode is
+    /// contained in this set.
+    pub fn modes(&self) -> EntryMode {
+        let mut modes = EntryMode::ALWAYS;
+        if self.visible.contains(&PaneKind::Editor) {
+            modes |= EntryMode::VISIBLE;
+        }
+        if self.focused == Some(PaneKind::Editor) {
+            modes |= EntryMode::VISIBLE | EntryMode::FOCUSED;
+        }
+        modes
+    }
+}
+
+/// A command in the palette, found by the name and aliases its entry in the index has.
 #[derive(Debug)]
 pub struct Entry<T> {
     pub id: EntryId,
-    pub name: String,
-    pub aliases: Vec<String>,
+    pub mode: EntryMode,
     pub command: T,
 }
 
 #[derive(Debug)]
 pub struct ResultEntry<'a, T> {
     pub entry: &'a Entry<T>,
+    pub name: &'a str,
+    pub aliases: &'a [String],
     pub score: i64,
-    pub indices: Vec<usize>,
+    pub matched_text: MatchedText,
+    /// Char indices of the matched chars, relative to `matched_text`.
+    pub indices: &'a [usize],
 }
 
-#[derive(Debug)]
-struct SearchResult {
-    pub entry: EntryId,
-    pub score: i64,
-    pub indices: Vec<usize>,
+impl<'a, T> ResultEntry<'a, T> {
+    /// The text to list this result as; see [`label`].
+    pub fn label(&self) -> String {
+        label(self.name, self.aliases)
+    }
+
+    /// The pieces of [`ResultEntry::label`], to draw it without making it.
+    pub fn label_parts(&self) -> impl Iterator<Item = &'a str> + 'a {
+        label_parts(self.name, self.aliases)
+    }
+
+    /// `indices` as char indices into [`ResultEntry::label`].
+    pub fn label_indices(&self) -> impl Iterator<Item = usize> + 'a {
+        let offset = self.matched_text.label_offset(self.name, self.aliases);
+        self.indices.iter().map(move |idx| offset + idx)
+    }
 }
 
 new_key_type! {
     pub struct EntryId;
 }
 
+/// The command palette: a query typed to fuzzily filter the entries the context allows, and the
+/// one selected to send.
 #[derive(Debug)]
 pub struct Commands<T> {
     pub sender: mpsc::Sender<T>,
-    pub query: String,
-    pub cursor: Point,
+    pub query: QueryInput,
     pub entries: SlotMap<EntryId, Entry<T>>,
+    pub context: Context,
 
     pub selected: Option<EntryId>,
-    filtered: Vec<SearchResult>,
+    index: FuzzyIndex<EntryId>,
 }
 
-const MAX_RESULTS: usize = 32;
-
 impl<T> Commands<T> {
     pub fn new(tx: mpsc::Sender<T>) -> Self {
         Self {
             sender: tx,
-            query: String::new(),
-            cursor: Point::default(),
+            query: QueryInput::default(),
             entries: SlotMap::with_key(),
+            context: Context::default(),
             selected: None,
-            filtered: vec![],
+            index: FuzzyIndex::new(),
         }
     }
 
-    pub fn register(&mut self, command: &str, aliases: Vec<&str>, msg: T) -> EntryId {
-        let command = command.to_string();
+    pub fn register(
+        &mut self,
+        command: &str,
+        aliases: Vec<&str>,
+        mode: EntryMode,
+        msg: T,
+    ) -> EntryId {
+        let id = self
+            .entries
+            .insert_with_key(|id| Entry { id, mode, command: msg });
         let aliases = aliases.iter().map(|s| s.to_string()).collect();
-        self.entries
-            .insert_with_key(|id| Entry { id, name: command, aliases, command: msg })
+        self.index.insert(id, command.to_string(), aliases);
+        id
     }
 
-    pub fn process_key(&mut self, key: KeyEvent) -> Option<Command> {
-        use crossterm::event::KeyCode;
+    pub fn set_context(&mut self, context: Context) {
+        if context != self.context {
+            self.context = context;
+            self.index.invalidate();
+        }
+        self.query_filter();
+    }
 
-        match key.code {
-            KeyCode::Up => {
-                self.select_up();
-                None
+    pub fn process_key(&mut self, key: InputKey) -> Option<Command> {
+        let ctrl = key.modifiers.contains(Modifiers::CTRL);
+        match key.key {
+            Key::Up => self.select_up(),
+            Key::Char('p') if ctrl => self.select_up(),
+            Key::Down => self.select_down(),
+            Key::Char('n') if ctrl => self.select_down(),
+            Key::Backspace => {
+                self.query.delete(Direction::Prev);
+                self.query_filter();
             }
-            KeyCode::Down => {
-                self.select_down();
-                None
+            Key::Delete => {
+                self.query.delete(Direction::Next);
+                self.query_filter();
             }
-            KeyCode::Backspace => {
-                self.query_delete(self.cursor.column - 1..self.cursor.column);
-                None
+            Key::Left => self.query.move_cursor(Direction::Prev),
+            Key::Right => self.query.move_cursor(Direction::Next),
+            Key::Home => self.query.move_to_start(),
+            Key::End => self.query.move_to_end(),
+            Key::Char(c) => {
+                self.query.insert(c);
+                self.query_filter();
             }
-            KeyCode::Char(c) => {
-                if c == 'p' && key.modifiers.contains(KeyModifiers::CONTROL) {
-                    self.select_up();
-                } else if c == 'n' && key.modifiers.contains(KeyModifiers::CONTROL) {
-                    self.select_down();
-                } else {
-                    self.query_insert(self.cursor.column, c);
-                }
-                None
-            }
-            KeyCode::Enter => Some(Command::Select(self.selected?)),
-            _ => None,
+            Key::Enter => return Some(Command::Select(self.selected?)),
+            _ => {}
         }
+        None
     }
 
     pub fn query_reset(&mut self) {
-        self.query = String::new();
-        self.cursor = Point::default();
-        self.selected = None;
-        self.filtered.clear();
+        self.query.clear();
         self.query_filter();
+        self.selected = self.index.ids().next();
     }
 
-    pub fn query_results(&self) -> Vec<ResultEntry<T>> {
-        self.filtered
-            .iter()
-            .map(|SearchResult { entry, score, indices }| ResultEntry {
-                entry: &self.entries[*entry],
-                score: *score,
-                indices: indices.clone(),
-            })
-            .collect()
-    }
-
-    fn query_delete(&mut self, range: impl RangeBounds<usize>) {
-        let range = std::slice::range(range, ..self.query.len());
-        let len = range.len();
-        self.query.drain(range);
-        if len > self.cursor.column {
-            self.cursor.column = 0;
-        } else {
-            self.cursor.column -= len;
-        }
-
-        self.query_filter();
-    }
-
-    fn query_insert(&mut self, offset: usize, c: char) {
-        if offset == self.query.len() {
-            self.query.push(c);
-        } else {
-            self.query.insert(offset, c);
-        }
-        self.cursor.move_next_column();
-        self.query_filter();
+    pub fn query_results(&self) -> impl ExactSizeIterator<Item = ResultEntry<'_, T>> {
+        self.index.results().map(|(entry, m)| ResultEntry {
+            entry: &self.entries[entry.id],
+            name: &entry.name,
+            aliases: &entry.aliases,
+            score: m.score,
+            matched_text: m.matched_text,
+            indices: &m.indices,
+        })
     }
 
+    /// Filters the entries by the query, unless nothing has changed since they last were.
     #[tracing::instrument(skip(self))]
     fn query_filter(&mut self) {
-        let mut results = vec![];
-        if self.query.is_empty() {
-            for (id, _) in &self.entries {
-                if results.len() > MAX_RESULTS {
-                    break;
-                }
-                results.push(SearchResult { entry: id, score: 0, indices: vec![] });
-            }
-        } else {
-            let matcher = SkimMatcherV2::default();
-            for (id, entry) in &self.entries {
-                let result = matcher.fuzzy_indices(&entry.name, &self.query);
-                if let Some((score, indices)) = result {
-                    results.push(SearchResult { entry: id, score, indices });
-                }
-            }
-            results.sort_by_key(|entry| entry.score);
+        let modes = self.context.modes();
+        let entries = &self.entries;
+        let keep = |entry: &selector::FuzzyEntry<EntryId>| modes.contains(entries[entry.id].mode);
+        if self.index.filter(self.query.as_str(), keep) {
+            self.selected = self.index.ids().next();
         }
+    }
 
-        self.selected = results.first().map(|r| r.entry);
-        self.filtered = results;
+    /// Where the selected entry is among the results, or the first if none is.
+    fn selected_index(&self) -> usize {
+        let selected = self.selected;
+        let mut ids = self.index.ids();
+        ids.position(|id| Some(id) == selected).unwrap_or(0)
     }
 
     fn select_up(&mut self) {
-        let idx = self
-            .filtered
-            .iter()
-            .enumerate()
-            .find(|(_, r)| Some(r.entry) == self.selected)
-            .map(|(i, _)| i)
-            .unwrap_or(0);
+        let idx = self.selected_index();
         if idx > 0 {
-            self.selected = self.filtered.get(idx - 1).map(|r| r.entry);
+            self.selected = self.index.ids().nth(idx - 1);
         }
     }
 
     fn select_down(&mut self) {
-        let idx = self
-            .filtered
-            .iter()
-            .enumerate()
-            .find(|(_, r)| Some(r.entry) == self.selected)
-            .map(|(i, _)| i)
-            .unwrap_or(0);
-        if idx + 1 < self.filtered.len() {
-            self.selected = self.filtered.get(idx + 1).map(|r| r.entry);
+        let idx = self.selected_index();
+        if idx + 1 < self.index.results().len() {
+            self.selected = self.index.ids().nth(idx + 1);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names<T>(commands: &Commands<T>) -> Vec<&str> {
+        let mut names: Vec<_> = commands.query_results().map(|r| r.name).collect();
+        names.sort();
+        names
+    }
+
+    fn key(code: Key) -> InputKey {
+        code.into()
+    }
+
+    fn press(commands: &mut Commands<()>, code: Key) -> (String, usize) {
+        commands.process_key(key(code));
+        (commands.query.as_str().to_string(), commands.query.cursor())
+    }
+
+    #[test]
+    fn filter_by_context() {
+        let (tx, _rx) = mpsc::channel(1);
+        let mut commands = Commands::new(tx);
+        commands.register("quit", vec![], EntryMode::ALWAYS, ());
+        commands.register("buffer.close", vec![], EntryMode::FOCUSED, ());
+        commands.register("buffer.list", vec![], EntryMode::VISIBLE, ());
+
+        commands.query_reset();
+        assert_eq!(names(&commands), vec!["quit"]);
+
+        let editor_focused = Context { focused: Some(PaneKind::Editor), visible: vec![] };
+        commands.set_context(editor_focused);
+        assert_eq!(names(&commands), vec!["buffer.close", "buffer.list", "quit"]);
+
+        let commands_focused = Context {
+            focused: Some(PaneKind::Commands),
+            visible: vec![PaneKind::Editor, PaneKind::Commands],
+        };
+        commands.set_context(commands_focused);
+        assert_eq!(names(&commands), vec!["buffer.list", "quit"]);
+
+        press(&mut commands, Key::Char('b'));
+        assert_eq!(names(&commands), vec!["buffer.list"]);
+    }
+
+    fn commands_with_query(query: &str) -> Commands<()> {
+        let (tx, _rx) = mpsc::channel(1);
+        let mut commands = Commands::new(tx);
+        commands.register("buffer.close", vec![], EntryMode::ALWAYS, ());
+        commands.register("quit", vec!["exit"], EntryMode::ALWAYS, ());
+        commands.query_reset();
+        for c in query.chars() {
+            commands.process_key(key(Key::Char(c)));
+        }
+        commands
+    }
+
+    #[test]
+    fn edit_keys_refilter() {
+        let mut commands = commands_with_query("qit");
+        assert!(names(&commands).contains(&"quit"));
+        press(&mut commands, Key::Home);
+        press(&mut commands, Key::Right);
+        assert_eq!(press(&mut commands, Key::Char('u')), ("quit".into(), 2));
+        assert_eq!(names(&commands), vec!["quit"]);
+        assert_eq!(press(&mut commands, Key::Char('z')), ("quzit".into(), 3));
+        assert!(names(&commands).is_empty());
+        assert_eq!(press(&mut commands, Key::Backspace), ("quit".into(), 2));
+        assert_eq!(press(&mut commands, Key::Delete), ("qut".into(), 2));
+        assert_eq!(names(&commands), vec!["quit"]);
+
+        // nothing to delete on an empty query
+        let mut commands = commands_with_query("");
+        assert_eq!(press(&mut commands, Key::Backspace), ("".into(), 0));
+        assert_eq!(press(&mut commands, Key::Delete), ("".into(), 0));
+        // an alias is matched as well as a name
+        assert_eq!(press(&mut commands, Key::Char('x')), ("x".into(), 1));
+        assert_eq!(names(&commands), vec!["quit"]);
+    }
+
+    #[test]
+    fn select_up_and_down() {
+        let (tx, _rx) = mpsc::channel(1);
+        let mut commands = Commands::new(tx);
+        let ids: Vec<_> = (0..40)
+            .map(|i| commands.register(&format!("cmd{}", i), vec![], EntryMode::ALWAYS, ()))
+            .collect();
+        commands.query_reset();
+        assert_eq!(commands.query_results().len(), selector::MAX_RESULTS);
+        assert_eq!(commands.selected, Some(ids[0]));
+        commands.process_key(key(Key::Up));
+        assert_eq!(commands.selected, Some(ids[0]));
+        commands.process_key(key(Key::Down));
+        commands.process_key(InputKey::ctrl('n'));
+        assert_eq!(commands.selected, Some(ids[2]));
+        commands.process_key(InputKey::ctrl('p'));
+        assert_eq!(commands.selected, Some(ids[1]));
+        // no further than the last listed
+        for _ in 0..50 {
+            commands.process_key(key(Key::Down));
+        }
+        assert_eq!(commands.selected, Some(ids[selector::MAX_RESULTS - 1]));
+        assert!(matches!(
+            commands.process_key(key(Key::Enter)),
+            Some(Command::Select(id)) if id == ids[selector::MAX_RESULTS - 1]
+        ));
+
+        // refiltering selects the best match
+        commands.process_key(key(Key::Char('9')));
+        assert_eq!(commands.query_results().next().map(|r| r.name), Some("cmd9"));
+        assert_eq!(commands.selected, Some(ids[9]));
+    }
+
+    #[test]
+    fn results_label_their_matches() {
+        let (tx, _rx) = mpsc::channel(1);
+        let mut commands = Commands::new(tx);
+        commands.register("qq.quick", vec![], EntryMode::ALWAYS, ());
+        commands.register("quit", vec!["q"], EntryMode::ALWAYS, ());
+        commands.query_reset();
+        commands.process_key(key(Key::Char('q')));
+
+        let results: Vec<_> = commands.query_results().collect();
+        assert_eq!(results[0].name, "quit");
+        assert_eq!(results[0].label(), "quit (q)");
+        assert_eq!(results[0].label_indices().collect::<Vec<_>>(), vec![6]);
+        assert_eq!(commands.selected, Some(results[0].entry.id));
+    }
+}
@@ -0,0 +1,109 @@
+//! Counts the heap allocations the palette makes as it's typed into and drawn, with a global
+//! allocator of its own; it's a test binary to itself so nothing else is counted with it.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use commands::{Commands, EntryMode};
+use tokio::sync::mpsc;
+use tore::key::{InputKey, Key};
+
+struct Counting;
+
+thread_local! {
+    /// Allocations made on this thread, so tests running beside each other don't count each
+    /// other's.
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// How many allocations `f` makes.
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+fn registry(len: usize) -> Commands<()> {
+    let (tx, _rx) = mpsc::channel(1);
+    let mut commands = Commands::new(tx);
+    for i in 0..len {
+        commands.register(&format!("buffer.action{}", i), vec![], EntryMode::ALWAYS, ());
+    }
+    commands.register("quit", vec!["q"], EntryMode::ALWAYS, ());
+    commands.register("quick.open", vec![], EntryMode::ALWAYS, ());
+    commands.query_reset();
+    commands
+}
+
+fn type_char(commands: &mut Commands<()>, c: char) {
+    commands.process_key(InputKey::char(c));
+}
+
+#[test]
+fn keystroke_allocates_for_matches_only() {
+    let mut commands = registry(5000);
+    // the matcher sets up its scratch space on the first query
+    type_char(&mut commands, 'q');
+    assert_eq!(commands.query_results().len(), 2);
+
+    // what's left is the matcher's own few for each of the two matches, none for the 5000 that
+    // don't
+    let typed = allocations(|| type_char(&mut commands, 'u'));
+    assert!(typed < 16, "a keystroke made {} allocations", typed);
+    let mut names: Vec<_> = commands.query_results().map(|r| r.name).collect();
+    names.sort();
+    assert_eq!(names, ["quick.open", "quit"]);
+
+    // moving the selection, or setting the context it already has, filters nothing
+    let context = commands.context.clone();
+    let moved = allocations(|| {
+        commands.process_key(Key::Down.into());
+        commands.set_context(context);
+    });
+    assert_eq!(moved, 0);
+}
+
+#[test]
+fn drawing_results_allocates_nothing() {
+    let mut commands = registry(5000);
+    type_char(&mut commands, 'a');
+    // more match than are listed
+    assert_eq!(commands.query_results().len(), 32);
+
+    // a row of cells, as a frame's are, drawn from each result's label and matched chars
+    let mut row = ['\0'; 80];
+    let mut highlighted = [false; 80];
+    let drawn = allocations(|| {
+        for result in commands.query_results().take(32) {
+            let chars = result.label_parts().flat_map(str::chars);
+            for (cell, c) in row.iter_mut().zip(chars) {
+                *cell = c;
+            }
+            highlighted.fill(false);
+            for idx in result.label_indices().filter(|idx| *idx < row.len()) {
+                highlighted[idx] = true;
+            }
+        }
+    });
+    assert_eq!(drawn, 0);
+    assert!(highlighted.contains(&true));
+}
@@ -0,0 +1,354 @@
+//! Keys as toku handles them, apart from how a terminal reports them. A key is made once from a
+//! terminal's event, where it's normalized so one key is only ever told apart one way, and can be
+//! written as text and read back, like `ctrl-shift-p`, `<esc>` or `<space>f`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitflags::bitflags;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Esc,
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    F(u8),
+    /// A key nothing is bound to, like caps lock or a media key.
+    Unknown,
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Modifiers: u8 {
+        const CTRL  = 0b0001;
+        const ALT   = 0b0010;
+        /// Never held with a char: a shifted char is the char it types.
+        const SHIFT = 0b0100;
+    }
+}
+
+/// The names keys are written with, the first of each the one they're written back as.
+const NAMES: [(&str, Key); 19] = [
+    ("esc", Key::Esc),
+    ("escape", Key::Esc),
+    ("enter", Key::Enter),
+    ("cr", Key::Enter),
+    ("return", Key::Enter),
+    ("tab", Key::Tab),
+    ("backspace", Key::Backspace),
+    ("bs", Key::Backspace),
+    ("delete", Key::Delete),
+    ("del", Key::Delete),
+    ("insert", Key::Insert),
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+];
+
+/// Chars written by name, since as themselves they'd be hard to see or read as something else.
+const CHAR_NAMES: [(&str, char); 3] = [("space", ' '), ("lt", '<'), ("gt", '>')];
+
+/// A key, with the modifiers held down with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InputKey {
+    pub key: Key,
+    pub modifiers: Modifiers,
+}
+
+impl InputKey {
+    /// The key, with shift folded into a char: a lower case letter becomes its capital, and
+    /// anything else, which the terminal has shifted already, stays as it is.
+    pub fn new(key: Key, modifiers: Modifiers) -> Self {
+        let (key, modifiers) = match key {
+            Key::Char(c) if modifiers.contains(Modifiers::SHIFT) => {
+                (Key::Char(c.to_ascii_uppercase()), modifiers - Modifiers::SHIFT)
+            }
+            key => (key, modifiers),
+        };
+        Self { key, modifiers }
+    }
+
+    /// The char typed, with no modifiers.
+    pub fn char(c: char) -> Self {
+        Self::new(Key::Char(c), Modifiers::empty())
+    }
+
+    /// The char typed with ctrl held.
+    pub fn ctrl(c: char) -> Self {
+        Self::new(Key::Char(c), Modifiers::CTRL)
+    }
+
+    /// Whether ctrl, and only ctrl, is held.
+    pub fn is_ctrl(&self) -> bool {
+        self.modifiers == Modifiers::CTRL
+    }
+}
+
+impl From<Key> for InputKey {
+    fn from(key: Key) -> Self {
+        Self::new(key, Modifiers::empty())
+    }
+}
+
+impl From<KeyEvent> for InputKey {
+    fn from(event: KeyEvent) -> Self {
+        let mut modifiers = Modifiers::empty();
+        for (from, to) in [
+            (KeyModifiers::CONTROL, Modifiers::CTRL),
+            (KeyModifiers::ALT, Modifiers::ALT),
+            (KeyModifiers::SHIFT, Modifiers::SHIFT),
+        ] {
+            modifiers.set(to, event.modifiers.contains(from));
+        }
+        let key = match event.code {
+            KeyCode::Char(c) => Key::Char(c),
+            // what terminals send for ctrl-space
+            KeyCode::Null => {
+                modifiers |= Modifiers::CTRL;
+                Key::Char(' ')
+            }
+            KeyCode::BackTab => {
+                modifiers |= Modifiers::SHIFT;
+                Key::Tab
+            }
+            KeyCode::Enter => Key::Enter,
+            KeyCode::Esc => Key::Esc,
+            KeyCode::Tab => Key::Tab,
+            KeyCode::Backspace => Key::Backspace,
+            KeyCode::Delete => Key::Delete,
+            KeyCode::Insert => Key::Insert,
+            KeyCode::Up => Key::Up,
+            KeyCode::Down => Key::Down,
+            KeyCode::Left => Key::Left,
+            KeyCode::Right => Key::Right,
+            KeyCode::Home => Key::Home,
+            KeyCode::End => Key::End,
+            KeyCode::PageUp => Key::PageUp,
+            KeyCode::PageDown => Key::PageDown,
+            KeyCode::F(n) => Key::F(n),
+            _ => Key::Unknown,
+        };
+        Self::new(key, modifiers)
+    }
+}
+
+impl FromStr for InputKey {
+    type Err = String;
+
+    /// Reads one key: modifiers joined to it by `-`, like `ctrl-shift-p`, `C-r` or `A-C`, then a
+    /// char or the name of a key, like `esc` or `space`, in any case. It can be in `< >` too.
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let inner = src
+            .strip_prefix('<')
+            .and_then(|inner| inner.strip_suffix('>'))
+            .filter(|inner| !inner.is_empty())
+            .unwrap_or(src);
+        let mut modifiers = Modifiers::empty();
+        let mut rest = inner;
+        while let Some((name, after)) = rest.split_once('-') {
+            let modifier = match name.to_ascii_lowercase().as_str() {
+                "c" | "ctrl" => Modifiers::CTRL,
+                "a" | "m" | "alt" => Modifiers::ALT,
+                "s" | "shift" => Modifiers::SHIFT,
+                _ => break,
+            };
+            // `ctrl--` is ctrl and a `-`
+            if after.is_empty() {
+                break;
+            }
+            modifiers |= modifier;
+            rest = after;
+        }
+
+        let mut chars = rest.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(c), None) => Key::Char(c),
+            _ => {
+                let name = rest.to_ascii_lowercase();
+                let named = NAMES.iter().find(|(n, _)| *n == name).map(|(_, key)| *key);
+                let char = CHAR_NAMES.iter().find(|(n, _)| *n == name);
+                let function = name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok());
+                match (named, char, function) {
+                    (Some(key), _, _) => key,
+                    (_, Some((_, c)), _) => Key::Char(*c),
+                    (_, _, Some(n)) if (1..=24).contains(&n) => Key::F(n),
+                    _ => return Err(format!("unknown key '{}'", src)),
+                }
+            }
+        };
+        if let Key::Char(c) = key {
+            let shifted = modifiers.contains(Modifiers::SHIFT);
+            if shifted && !c.is_ascii_alphabetic() {
+                return Err(format!("'{}': shift only goes with a letter", src));
+            }
+        }
+        Ok(Self::new(key, modifiers))
+    }
+}
+
+impl fmt::Display for InputKey {
+    /// Writes the key as it's read: a char on its own as itself, anything else in `< >`, with
+    /// its modifiers spelled out, and a capital letter with any as shift and the letter.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let char_name = |c: char| CHAR_NAMES.iter().find(|(_, named)| *named == c);
+        if let Key::Char(c) = self.key {
+            if self.modifiers.is_empty() && char_name(c).is_none() {
+                return write!(f, "{}", c);
+            }
+        }
+        f.write_str("<")?;
+        let capital = matches!(self.key, Key::Char(c) if c.is_ascii_uppercase());
+        for (modifier, name) in [
+            (Modifiers::CTRL, "ctrl-"),
+            (Modifiers::ALT, "alt-"),
+            (Modifiers::SHIFT, "shift-"),
+        ] {
+            let shift = modifier == Modifiers::SHIFT && capital && !self.modifiers.is_empty();
+            if self.modifiers.contains(modifier) || shift {
+                f.write_str(name)?;
+            }
+        }
+        match self.key {
+            Key::Char(c) => match char_name(c) {
+                Some((name, _)) => f.write_str(name)?,
+                None if capital && !self.modifiers.is_empty() => {
+                    write!(f, "{}", c.to_ascii_lowercase())?
+                }
+                None => write!(f, "{}", c)?,
+            },
+            Key::F(n) => write!(f, "f{}", n)?,
+            Key::Unknown => f.write_str("unknown")?,
+            key => {
+                let name = NAMES.iter().find(|(_, named)| *named == key);
+                f.write_str(name.expect("every key but chars is named").0)?
+            }
+        }
+        f.write_str(">")
+    }
+}
+
+/// Reads keys typed one after another, like `<space>f` or `gg`, or one key with modifiers
+/// written without `< >`, like `ctrl-shift-p`.
+pub fn parse_keys(src: &str) -> Result<Vec<InputKey>, String> {
+    if !src.contains('<') && src.contains('-') {
+        if let Ok(key) = src.parse::<InputKey>() {
+            if !key.modifiers.is_empty() {
+                return Ok(vec![key]);
+            }
+        }
+    }
+    let mut keys = vec![];
+    let mut rest = src;
+    while let Some(c) = rest.chars().next() {
+        let closed = (c == '<').then(|| rest.find('>')).flatten();
+        // a `<` that isn't closed, or closes straight away, is itself
+        let len = match closed {
+            Some(close) if close > 1 => close + 1,
+            _ => c.len_utf8(),
+        };
+        keys.push(rest[..len].parse()?);
+        rest = &rest[len..];
+    }
+    Ok(keys)
+}
+
+/// Writes `keys` as [`parse_keys`] reads them.
+pub fn format_keys(keys: &[InputKey]) -> String {
+    keys.iter().map(InputKey::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(code: KeyCode, modifiers: KeyModifiers) -> InputKey {
+        KeyEvent::new(code, modifiers).into()
+    }
+
+    #[test]
+    fn chords_round_trip() {
+        let ctrl_shift = Modifiers::CTRL | Modifiers::SHIFT;
+        #[rustfmt::skip]
+        let cases = [
+            ("x", vec![InputKey::char('x')], "x"),
+            ("X", vec![InputKey::char('X')], "X"),
+            ("ctrl-shift-p", vec![InputKey::new(Key::Char('P'), Modifiers::CTRL)], "<ctrl-shift-p>"),
+            ("<C-P>", vec![InputKey::ctrl('P')], "<ctrl-shift-p>"),
+            ("C-r", vec![InputKey::ctrl('r')], "<ctrl-r>"),
+            ("A-C", vec![InputKey::new(Key::Char('C'), Modifiers::ALT)], "<alt-shift-c>"),
+            ("<esc>", vec![Key::Esc.into()], "<esc>"),
+            ("<Escape>", vec![Key::Esc.into()], "<esc>"),
+            ("<space>f", vec![InputKey::char(' '), InputKey::char('f')], "<space>f"),
+            ("<ctrl-space>", vec![InputKey::ctrl(' ')], "<ctrl-space>"),
+            ("<s-tab>", vec![InputKey::new(Key::Tab, Modifiers::SHIFT)], "<shift-tab>"),
+            ("<c-s-f12>", vec![InputKey::new(Key::F(12), ctrl_shift)], "<ctrl-shift-f12>"),
+            ("ctrl--", vec![InputKey::ctrl('-')], "<ctrl-->"),
+            ("g-", vec![InputKey::char('g'), InputKey::char('-')], "g-"),
+            ("<lt>>", vec![InputKey::char('<'), InputKey::char('>')], "<lt><gt>"),
+            ("<", vec![InputKey::char('<')], "<lt>"),
+            ("<>", vec![InputKey::char('<'), InputKey::char('>')], "<lt><gt>"),
+        ];
+        for (src, keys, written) in cases {
+            assert_eq!(parse_keys(src).as_ref(), Ok(&keys), "{}", src);
+            assert_eq!(format_keys(&keys), written, "{}", src);
+            assert_eq!(parse_keys(written), Ok(keys), "{}", written);
+        }
+
+        assert_eq!(parse_keys("<hyper-x>"), Err("unknown key '<hyper-x>'".into()));
+        assert_eq!(parse_keys("<f99>"), Err("unknown key '<f99>'".into()));
+        let shifted = "'<shift-1>': shift only goes with a letter";
+        assert_eq!(parse_keys("<shift-1>"), Err(shifted.into()));
+    }
+
+    #[test]
+    fn terminal_events() {
+        let (none, shift) = (KeyModifiers::NONE, KeyModifiers::SHIFT);
+        let ctrl = KeyModifiers::CONTROL;
+        // a capital is the same key whether or not the terminal says shift was held
+        assert_eq!(event(KeyCode::Char('A'), shift), InputKey::char('A'));
+        assert_eq!(event(KeyCode::Char('a'), shift), InputKey::char('A'));
+        assert_eq!(event(KeyCode::Char('A'), none), InputKey::char('A'));
+        assert_eq!(event(KeyCode::Char('?'), shift), InputKey::char('?'));
+        assert_eq!(event(KeyCode::Char('p'), ctrl | shift), InputKey::ctrl('P'));
+        assert_eq!(
+            event(KeyCode::Char('C'), KeyModifiers::ALT | shift).to_string(),
+            "<alt-shift-c>"
+        );
+
+        // shift-tab, as terminals send it and as it'd be if they didn't
+        let back_tab = InputKey::new(Key::Tab, Modifiers::SHIFT);
+        assert_eq!(event(KeyCode::BackTab, shift), back_tab);
+        assert_eq!(event(KeyCode::BackTab, none), back_tab);
+        assert_eq!(event(KeyCode::Tab, shift), back_tab);
+
+        // ctrl-space, whether it comes as a space or as NUL
+        assert_eq!(event(KeyCode::Char(' '), ctrl), InputKey::ctrl(' '));
+        assert_eq!(event(KeyCode::Null, none), InputKey::ctrl(' '));
+
+        assert_eq!(event(KeyCode::Esc, none), Key::Esc.into());
+        assert!(event(KeyCode::Char('r'), ctrl).is_ctrl());
+        assert!(!event(KeyCode::Char('r'), ctrl | KeyModifiers::ALT).is_ctrl());
+        assert_eq!(event(KeyCode::F(5), none), Key::F(5).into());
+        assert_eq!(event(KeyCode::CapsLock, none), Key::Unknown.into());
+        // modifiers toku doesn't use are dropped
+        assert_eq!(event(KeyCode::Char('x'), KeyModifiers::SUPER), InputKey::char('x'));
+    }
+}
@@ -1,10 +1,41 @@
-#[derive(Debug, Clone, Copy, Default, PartialOrd, PartialEq)]
+use std::ops::Range;
+
+pub mod key;
+
+/// A place in a buffer, by line and column. Points order as they come in the text: by line, then
+/// by column, so `min` and `max` of two are the one that comes first and last.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Point {
     pub line: usize,
     pub column: usize,
 }
 
 impl Point {
+    /// The start of a buffer.
+    pub const fn zero() -> Self {
+        Self { line: 0, column: 0 }
+    }
+
+    pub fn with_line(self, line: usize) -> Self {
+        Self { line, ..self }
+    }
+
+    pub fn with_column(self, column: usize) -> Self {
+        Self { column, ..self }
+    }
+
+    /// The point `lines` lines down, or up for fewer than none, kept between the first line and
+    /// `max`, in the same column.
+    pub fn saturating_add_lines(self, lines: isize, max: usize) -> Self {
+        let line = self.line.saturating_add_signed(lines).min(max);
+        self.with_line(line)
+    }
+
+    /// The point `columns` columns left or right on its line, never left of the first.
+    pub fn saturating_add_columns(self, columns: isize) -> Self {
+        self.with_column(self.column.saturating_add_signed(columns))
+    }
+
     pub fn move_next_column(&mut self) {
         self.column += 1;
     }
@@ -26,10 +57,157 @@ impl Point {
     }
 }
 
+/// The text from `start` up to, but leaving out, `end`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PointRange {
+    pub start: Point,
+    pub end: Point,
+}
+
+impl PointRange {
+    pub fn new(start: Point, end: Point) -> Self {
+        Self { start, end }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    /// Whether `point` is in the range; its end isn't, so an empty range holds nothing.
+    pub fn contains(&self, point: Point) -> bool {
+        self.start <= point && point < self.end
+    }
+
+    /// The range with its ends swapped if it runs backwards, as a selection made leftwards does.
+    pub fn normalize(self) -> Self {
+        Self { start: self.start.min(self.end), end: self.start.max(self.end) }
+    }
+
+    /// The range in offsets, with `offset` mapping each end, like a rope's point to byte offset.
+    pub fn to_offsets(self, offset: impl Fn(Point) -> usize) -> Range<usize> {
+        offset(self.start)..offset(self.end)
+    }
+}
+
+/// The size of a span of text: its length in bytes, and the number of line breaks it contains
+/// plus the byte length of its last line.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Extent {
+    pub len: usize,
+    pub lines: Point,
+}
+
+impl Extent {
+    /// The point reached by advancing over this text from `start`.
+    pub fn end_from(&self, start: Point) -> Point {
+        if self.lines.line == 0 {
+            Point { line: start.line, column: start.column + self.lines.column }
+        } else {
+            Point { line: start.line + self.lines.line, column: self.lines.column }
+        }
+    }
+}
+
+/// Where an edit happened and how much text it removed and inserted there.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EditSummary {
+    pub start_offset: usize,
+    pub start_point: Point,
+    pub removed: Extent,
+    pub inserted: Extent,
+}
+
+impl EditSummary {
+    pub fn old_end_offset(&self) -> usize {
+        self.start_offset + self.removed.len
+    }
+
+    pub fn new_end_offset(&self) -> usize {
+        self.start_offset + self.inserted.len
+    }
+
+    pub fn old_end_point(&self) -> Point {
+        self.removed.end_from(self.start_point)
+    }
+
+    pub fn new_end_point(&self) -> Point {
+        self.inserted.end_from(self.start_point)
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default, PartialOrd, PartialEq)]
 pub struct CursorPoint {
     pub x: u16,
     pub y: u16,
 }
 
-impl CursorPoint {}
+impl CursorPoint {
+    /// A screen position from buffer-space coordinates, clamped to the terminal's range.
+    pub fn new(x: usize, y: usize) -> Self {
+        Self { x: to_screen(x), y: to_screen(y) }
+    }
+}
+
+/// Converts a buffer-space coordinate to a screen one, saturating rather than wrapping. Keep
+/// arithmetic in `usize` and convert only when handing values to the terminal.
+pub fn to_screen(n: usize) -> u16 {
+    u16::try_from(n).unwrap_or(u16::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize, column: usize) -> Point {
+        Point { line, column }
+    }
+
+    #[test]
+    fn points_order_as_in_the_text() {
+        assert!(at(0, 5) < at(0, 6));
+        // any column of a later line comes after every one of an earlier line
+        assert!(at(0, 100) < at(1, 0));
+        assert!(at(2, 0) > at(1, 9));
+        assert_eq!(at(3, 4).cmp(&at(3, 4)), std::cmp::Ordering::Equal);
+        assert_eq!(at(1, 9).min(at(2, 0)), at(1, 9));
+        assert_eq!(at(1, 9).max(at(2, 0)), at(2, 0));
+        assert_eq!(at(4, 2).min(at(4, 1)), at(4, 1));
+
+        let mut points = vec![at(1, 0), at(0, 7), at(1, 0), at(0, 2)];
+        points.sort();
+        points.dedup();
+        assert_eq!(points, [at(0, 2), at(0, 7), at(1, 0)]);
+    }
+
+    #[test]
+    fn builders() {
+        assert_eq!(Point::zero(), Point::default());
+        assert_eq!(at(3, 4).with_line(7), at(7, 4));
+        assert_eq!(at(3, 4).with_column(0), at(3, 0));
+        assert_eq!(at(3, 4).saturating_add_lines(-5, 10), at(0, 4));
+        assert_eq!(at(3, 4).saturating_add_lines(20, 10), at(10, 4));
+        assert_eq!(at(3, 4).saturating_add_columns(-9), at(3, 0));
+        assert_eq!(at(3, 4).saturating_add_columns(2), at(3, 6));
+    }
+
+    #[test]
+    fn ranges() {
+        let range = PointRange::new(at(1, 3), at(2, 1));
+        assert!(range.contains(at(1, 3)));
+        assert!(range.contains(at(1, 80)));
+        assert!(range.contains(at(2, 0)));
+        // the end is left out
+        assert!(!range.contains(at(2, 1)));
+        assert!(!range.contains(at(1, 2)));
+        assert!(!PointRange::new(at(1, 3), at(1, 3)).contains(at(1, 3)));
+
+        let reversed = PointRange::new(at(2, 1), at(1, 3));
+        assert!(reversed.is_empty());
+        assert!(!reversed.contains(at(1, 5)));
+        assert_eq!(reversed.normalize(), range);
+        assert_eq!(range.normalize(), range);
+
+        let offsets = range.to_offsets(|point| point.line * 10 + point.column);
+        assert_eq!(offsets, 13..21);
+    }
+}
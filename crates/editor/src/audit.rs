@@ -0,0 +1,167 @@
+use rope::SummaryMismatch;
+use std::ops::Range;
+
+use crate::buffer::Contents;
+
+/// Contents up to this many bytes have every chunk checked, rather than a sample of them.
+const FULL_CHECK_LEN: usize = 64 * 1024;
+/// How many chunks of larger contents are checked.
+const SAMPLES: usize = 8;
+
+/// A run of the contents whose line breaks the rope's summaries count differently from its text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineCountMismatch {
+    pub bytes: Range<usize>,
+    /// The line breaks in the text.
+    pub counted: usize,
+    /// The line breaks the summaries put there.
+    pub summarized: usize,
+}
+
+impl std::fmt::Display for LineCountMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "bytes {}..{} have {} line breaks, summarized as {}",
+            self.bytes.start, self.bytes.end, self.counted, self.summarized
+        )
+    }
+}
+
+/// What an audit found wrong with the contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditFailure {
+    /// A summary in the rope that differs from what it summarizes.
+    Summary(SummaryMismatch),
+    Lines(LineCountMismatch),
+}
+
+impl std::fmt::Display for AuditFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AuditFailure::Summary(mismatch) => mismatch.fmt(f),
+            AuditFailure::Lines(mismatch) => mismatch.fmt(f),
+        }
+    }
+}
+
+impl Contents {
+    /// Validates the rope's summaries, then audits its line counts. Large contents only have the
+    /// summaries on the way down to the chunks `seed` picks validated, so an audit after every
+    /// edit costs about the same however long they are.
+    pub fn audit(&self, seed: u64) -> Result<(), AuditFailure> {
+        let rope = self.tree();
+        let len = self.len_bytes();
+        let validated = if len <= FULL_CHECK_LEN {
+            rope.validate()
+        } else {
+            samples(seed, len).try_for_each(|offset| rope.validate_at(offset))
+        };
+        validated.map_err(AuditFailure::Summary)?;
+        self.audit_lines(seed).map_err(AuditFailure::Lines)
+    }
+
+    /// Checks the line counts the rope keeps against the line breaks in its text, chunk by
+    /// chunk: all of them in small contents, or a sample `seed` picks in larger ones.
+    pub fn audit_lines(&self, seed: u64) -> Result<(), LineCountMismatch> {
+        self.audit_lines_with(seed, |byte| self.byte_to_line(byte))
+    }
+
+    /// Like `audit_lines`, with the summarized line of a byte offset coming from `line_at`; so
+    /// the audit itself can be checked against summaries known to be wrong.
+    pub fn audit_lines_with(
+        &self,
+        seed: u64,
+        line_at: impl Fn(usize) -> usize,
+    ) -> Result<(), LineCountMismatch> {
+        let check = |chunk: &str, start: usize| {
+            let bytes = start..start + chunk.len();
+            let counted = chunk.bytes().filter(|b| *b == b'\n').count();
+            let summarized = line_at(bytes.end).saturating_sub(line_at(bytes.start));
+            if counted != summarized {
+                return Err(LineCountMismatch { bytes, counted, summarized });
+            }
+            Ok(counted)
+        };
+
+        let len = self.len_bytes();
+        if len <= FULL_CHECK_LEN {
+            let (mut start, mut counted) = (0, 0);
+            for chunk in self.chunks() {
+                counted += check(chunk, start)?;
+                start += chunk.len();
+            }
+            let summarized = self.len_lines() - 1;
+            if counted != summarized {
+                return Err(LineCountMismatch { bytes: 0..len, counted, summarized });
+            }
+            return Ok(());
+        }
+        for offset in samples(seed, len) {
+            let (chunk, start, ..) = self.chunk_at_byte(offset);
+            check(chunk, start)?;
+        }
+        Ok(())
+    }
+}
+
+/// The byte offsets under `len` that `seed` picks for an audit to check.
+fn samples(seed: u64, len: usize) -> impl Iterator<Item = usize> {
+    // xorshift; the sample only has to move around between audits
+    let mut state = seed.max(1);
+    (0..SAMPLES).map(move |_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as usize % len
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audits_line_counts() {
+        let small = Contents::from("a\nb\r\nc\rd\u{2028}e");
+        assert_eq!(small.audit_lines(1), Ok(()));
+        let large = Contents::from("line\r\n".repeat(40_000).as_str());
+        for seed in 0..4 {
+            assert_eq!(large.audit_lines(seed), Ok(()));
+        }
+
+        // summaries that count every line twice
+        let skewed = |byte| small.byte_to_line(byte) * 2;
+        let mismatch = small.audit_lines_with(1, skewed).unwrap_err();
+        assert_eq!(mismatch.to_string(), "bytes 0..12 have 2 line breaks, summarized as 4");
+        let skewed = |byte| large.byte_to_line(byte) * 2;
+        assert!(large.audit_lines_with(1, skewed).is_err());
+    }
+
+    #[test]
+    fn audits_summaries() {
+        // the same contents with the root's summary counting a line too many
+        let corrupt = |contents: &Contents| {
+            let root = contents.tree().root().unwrap();
+            let mut summary = root.summary();
+            summary.stats.lines.line += 1;
+            Contents(rope::Rope::new(root.with_summary(summary)).into())
+        };
+
+        let small = Contents::from("a\nb\n");
+        assert_eq!(small.audit(1), Ok(()));
+        let Err(AuditFailure::Summary(mismatch)) = corrupt(&small).audit(1) else {
+            panic!("a wrong summary passed the audit");
+        };
+        assert_eq!(mismatch.bytes, 0..4);
+        assert_eq!(mismatch.stored.stats.lines.line, 3);
+        assert_eq!(mismatch.expected.stats.lines.line, 2);
+
+        let large = Contents::from("line\r\n".repeat(40_000).as_str());
+        for seed in 0..4 {
+            assert_eq!(large.audit(seed), Ok(()));
+            let failure = corrupt(&large).audit(seed).unwrap_err();
+            assert!(matches!(failure, AuditFailure::Summary(_)), "{:?}", failure);
+        }
+    }
+}
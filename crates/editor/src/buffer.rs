@@ -1,9 +1,21 @@
 use anyhow::Result;
-use rope::{Rope, RopeBuilder};
+use rope::compat::{Rope, RopeSlice};
 use slotmap::new_key_type;
-use std::ops::{Deref, DerefMut};
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut, Range};
 use std::path::PathBuf;
-use tore::Point;
+use std::sync::Arc;
+use tore::{EditSummary, Extent, Point, PointRange};
+
+use crate::diagnostic::Diagnostic;
+use crate::diff::LineChange;
+use crate::encoding::{Encoding, Format};
+use crate::load::{CancellationToken, Loader};
+use crate::settings::{Settings, SettingsOverlay};
+use crate::snapshot::BufferSnapshot;
+use crate::undo::{HistoryAccounting, StateId, UndoTree};
+use crate::words::WordIndex;
+use crate::Mode;
 
 pub type Highlights = iset::IntervalMap<usize, String>;
 
@@ -13,14 +25,95 @@ new_key_type! {
 
 #[derive(Debug, Clone)]
 pub enum Command {
-    Highlight(Highlights),
+    /// Replaces the highlights with those found in the contents at the given version.
+    Highlight(u64, Highlights),
+    /// Replaces the highlights within each range, leaving the rest untouched. The ranges are
+    /// those of the contents at the given version.
+    HighlightDelta(u64, Vec<(Range<usize>, Highlights)>),
+}
+
+/// Why a batch of edits was turned away, leaving the buffer as it was.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditError {
+    /// A range ends past the end of the buffer, which is this many chars long.
+    OutOfBounds(Range<usize>, usize),
+    /// Two ranges, one after the other in the batch, overlap or are out of order.
+    Overlapping(Range<usize>, Range<usize>),
 }
 
+impl std::fmt::Display for EditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EditError::OutOfBounds(range, len) => {
+                write!(f, "{:?} is past the end of the buffer ({} chars)", range, len)
+            }
+            EditError::Overlapping(first, second) => {
+                write!(f, "edits at {:?} and {:?} overlap", first, second)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// How many edits are remembered without highlights to move along, for any on their way.
+const MAX_SHIFTS: usize = 1024;
+
 #[derive(Debug)]
 pub struct Buffer {
     pub id: Id,
+    /// Counting from 1 in the order buffers were opened this session, never reused; None for
+    /// buffers that aren't listed, like previews.
+    pub number: Option<usize>,
+    pub path: Option<PathBuf>,
     pub contents: Contents,
-    pub highlights: Highlights,
+    /// Shared with the snapshots taken of the buffer, so they cost nothing to take; changing
+    /// them copies them only while one is still held.
+    pub highlights: Arc<Highlights>,
+    /// Incremented on every edit to the contents.
+    pub version: u64,
+    /// Whether the contents have changed since they were last read or saved.
+    pub dirty: bool,
+    /// How the file was stored, and how it will be saved.
+    pub format: Format,
+    /// Set when the contents can't be saved back as they were read.
+    pub read_only: bool,
+    /// Whether the contents ended with a line break when they were last read or saved. Saving
+    /// writes them as they are, so a file without one stays without one unless
+    /// `insert_final_newline` is set.
+    pub had_final_newline: bool,
+    /// The file as it was on disk when it was last read or saved, to tell when something else
+    /// has written it since; None if that isn't known.
+    pub disk: Option<DiskStamp>,
+    /// Whether something else has written the file since, as last looked.
+    pub disk_state: DiskState,
+    /// The version of the contents the highlights were found in.
+    highlighted: u64,
+    /// Byte edits made since `highlighted`, each with the version it produced, to move the
+    /// highlights along by once newer ones come.
+    shifts: Vec<(u64, Range<usize>, usize)>,
+    /// Edits not yet taken by `take_edits`, each with the version it produced.
+    edits: Vec<(u64, EditSummary)>,
+    /// Char offsets of the marks set in this buffer, moved along by every edit.
+    marks: BTreeMap<char, usize>,
+    /// The contents as they were last read from or saved to `path`.
+    saved: Option<Contents>,
+    /// How the contents differ from `saved`, moved along by every edit.
+    line_diff: Vec<LineChange>,
+    /// What tools last reported about the contents, moved along by every edit.
+    diagnostics: Vec<Diagnostic>,
+    /// Where each word occurs, kept up with every edit.
+    words: WordIndex,
+    /// Every state the contents have been in, to undo and redo through.
+    undo: UndoTree,
+    /// Whether the next edit joins the current undo state rather than making its own.
+    join_undo: bool,
+    /// The defaults, under what the buffer's project says, under what `.editorconfig` files
+    /// say, under what the user set.
+    settings: Settings,
+    project: SettingsOverlay,
+    editorconfig: SettingsOverlay,
+    overrides: SettingsOverlay,
 }
 
 impl Buffer {
@@ -29,87 +122,717 @@ impl Buffer {
     }
 
     pub fn new(id: Id, contents: Contents) -> Self {
-        Self { id, contents, highlights: Default::default() }
+        Self::with_path(id, None, contents)
     }
 
-    pub async fn read(filename: &PathBuf) -> Result<Contents> {
-        use tokio::fs::File;
-        use tokio::io::AsyncReadExt;
+    /// A buffer of the file at `path`, read as `format` has it. A binary file is only shown, not
+    /// edited, so it's read-only.
+    pub fn open(id: Id, path: PathBuf, contents: Contents, format: Format) -> Self {
+        let saved = Some(contents.clone());
+        let read_only = format.encoding == Encoding::Binary;
+        Self { format, saved, read_only, ..Self::with_path(id, Some(path), contents) }
+    }
 
-        let mut file = File::open(filename).await?;
+    fn with_path(id: Id, path: Option<PathBuf>, contents: Contents) -> Self {
+        let (highlights, shifts) = Default::default();
+        let (format, read_only) = Default::default();
+        let (edits, marks) = Default::default();
+        let (saved, line_diff, diagnostics) = Default::default();
+        let (settings, project, editorconfig, overrides) = Default::default();
+        let words = WordIndex::build(&contents);
+        let undo = UndoTree::new(&contents, std::time::Instant::now());
+        let had_final_newline = contents.ends_with_line_break();
+        Self {
+            id,
+            number: None,
+            path,
+            contents,
+            highlights,
+            version: 0,
+            dirty: false,
+            format,
+            read_only,
+            had_final_newline,
+            disk: None,
+            disk_state: DiskState::Unchanged,
+            highlighted: 0,
+            shifts,
+            edits,
+            marks,
+            saved,
+            line_diff,
+            diagnostics,
+            words,
+            undo,
+            join_undo: false,
+            settings,
+            project,
+            editorconfig,
+            overrides,
+        }
+    }
 
-        const BUFFER_SIZE: usize = rope::MAX_BYTES * 2;
-        let mut buffer = [0u8; BUFFER_SIZE];
-        let mut builder = RopeBuilder::new();
-        let mut fill_idx = 0; // How much `buffer` is currently filled with valid data
-        loop {
-            let read_count = file.read(&mut buffer[fill_idx..]).await?;
-            fill_idx += read_count;
+    /// Replaces the char range `range` with `replacement`; see `apply_edits`. Returns what the
+    /// edit did, as `take_edits` will hand it on.
+    pub fn apply_edit(
+        &mut self,
+        range: Range<usize>,
+        replacement: &str,
+    ) -> Result<EditSummary, EditError> {
+        self.apply_edits(&[(range, replacement)])?;
+        Ok(self
+            .edits
+            .last()
+            .map(|(_, summary)| *summary)
+            .unwrap_or_default())
+    }
 
-            // Determine how much of the buffer is valid utf8.
-            let valid_count = match std::str::from_utf8(&buffer[..fill_idx]) {
-                Ok(_) => fill_idx,
-                Err(e) => e.valid_up_to(),
-            };
+    /// Replaces each char range in `edits` with its text, as a single change to the buffer. This
+    /// is the one way the contents change, so everything kept from them is moved along with
+    /// each edit: the marks, line diff, diagnostics, highlights and word index, and the edits
+    /// `take_edits` hands on for the syntax tree. The version goes up once for the lot.
+    ///
+    /// The ranges must be sorted and disjoint, though they may meet, in the coordinates of the
+    /// buffer before the change; they are applied from last to first so no edit moves the text a
+    /// later one refers to. Returns the char offset each replacement now ends at and the byte
+    /// range covering them all. Fails, leaving the buffer unchanged, if a range is past the end
+    /// of the buffer or the ranges overlap.
+    ///
+    /// The contents after the change are kept as an undo state.
+    pub fn apply_edits(
+        &mut self,
+        edits: &[(Range<usize>, &str)],
+    ) -> Result<(Vec<usize>, Range<usize>), EditError> {
+        let join = std::mem::take(&mut self.join_undo);
+        let applied = self.splice(edits)?;
+        if !edits.is_empty() {
+            let made = &self.edits[self.edits.len() - edits.len()..];
+            let made = made.iter().map(|(_, summary)| *summary).collect();
+            let now = std::time::Instant::now();
+            self.undo.record(&self.contents, made, join, now);
+        }
+        Ok(applied)
+    }
 
-            // Append the valid part of the buffer to the rope.
-            if valid_count > 0 {
-                // The unsafe block here is reinterpreting the bytes as
-                // utf8.  This is safe because the bytes being
-                // reinterpreted have already been validated as utf8
-                // just above.
-                builder.append(unsafe { std::str::from_utf8_unchecked(&buffer[..valid_count]) });
-            }
+    /// Makes the next edit part of the current undo state, so they're undone together, as the
+    /// chars typed in one insert are.
+    pub fn join_undo(&mut self) {
+        self.join_undo = true;
+    }
+
+    /// Whether the buffer holds a binary file, a byte to a char, to be shown as hex.
+    pub fn is_binary(&self) -> bool {
+        self.format.encoding == Encoding::Binary
+    }
+
+    pub fn undo_tree(&self) -> &UndoTree {
+        &self.undo
+    }
+
+    /// Puts the contents back as they were in the undo state `target`, which becomes the
+    /// current one, as a single edit of the text that differs. Returns the char offset it starts
+    /// at and the byte range it changed, or None if nothing did.
+    pub fn undo_to(&mut self, target: StateId) -> Option<(usize, Range<usize>)> {
+        let snapshot = self.undo.goto(target)?;
+        let (removed, inserted) = differing(&self.contents, &snapshot);
+        if removed.is_empty() && inserted.is_empty() {
+            return None;
+        }
+        let text = snapshot.slice(inserted).to_string();
+        let (_, changed) = self.splice(&[(removed.clone(), &text)]).ok()?;
+        Some((removed.start, changed))
+    }
+
+    /// Applies the edits as `apply_edits` does, without keeping an undo state.
+    fn splice(
+        &mut self,
+        edits: &[(Range<usize>, &str)],
+    ) -> Result<(Vec<usize>, Range<usize>), EditError> {
+        let len = self.contents.len_chars();
+        if let Some((range, _)) = edits.iter().find(|(range, _)| range.end > len) {
+            return Err(EditError::OutOfBounds(range.clone(), len));
+        }
+        if let Some(pair) = edits.windows(2).find(|w| w[0].0.end > w[1].0.start) {
+            return Err(EditError::Overlapping(pair[0].0.clone(), pair[1].0.clone()));
+        }
 
-            // Shift the un-read part of the buffer to the beginning.
-            if valid_count < fill_idx {
-                buffer.copy_within(valid_count..fill_idx, 0);
+        // (char offset, char delta, byte offset, byte delta, inserted bytes) of each edit
+        let mut applied = Vec::with_capacity(edits.len());
+        let version = self.version + 1;
+        for (range, text) in edits.iter().rev() {
+            let byte_start = self.contents.char_to_byte(range.start);
+            let removed_bytes = self.contents.char_to_byte(range.end) - byte_start;
+            let summary = EditSummary {
+                start_offset: byte_start,
+                start_point: self.contents.byte_offset_to_point(byte_start),
+                removed: slice_extent(self.contents.slice(range.clone())),
+                inserted: str_extent(text),
+            };
+            let (line, removed, inserted) = (
+                summary.start_point.line,
+                summary.removed.lines.line,
+                summary.inserted.lines.line,
+            );
+            let lines = self.line_diff.iter_mut().map(|change| &mut change.lines);
+            for lines in lines.chain(self.diagnostics.iter_mut().map(|d| &mut d.lines)) {
+                let start = remap_line(lines.start, line, removed, inserted);
+                let end = remap_line(lines.end, line, removed, inserted);
+                *lines = start..end.max(start);
             }
-            fill_idx -= valid_count;
-
-            if fill_idx == BUFFER_SIZE {
-                // Buffer is full and none of it could be consumed.  Utf8
-                // codepoints don't get that large, so it's clearly not
-                // valid text.
-                anyhow::bail!("stream contained invalid UTF-8");
-                // return Err(std::io::Error::new(
-                //     std::io::ErrorKind::InvalidData,
-                //     "stream did not contain valid UTF-8",
-                // ));
+            self.contents.remove(range.clone());
+            self.contents.insert(range.start, text);
+            let removed = byte_start..byte_start + removed_bytes;
+            self.shifts.push((version, removed, text.len()));
+            self.words.edited(&self.contents, &summary);
+            self.edits.push((version, summary));
+            let inserted = text.chars().count();
+            for offset in self.marks.values_mut() {
+                *offset = remap(*offset, range, inserted);
             }
+            let char_delta = inserted as isize - range.len() as isize;
+            let byte_delta = text.len() as isize - removed_bytes as isize;
+            applied.push((range.start + inserted, char_delta, byte_start, byte_delta, text.len()));
+        }
+        applied.reverse();
 
-            // If we're done reading
-            if read_count == 0 {
-                if fill_idx > 0 {
-                    // We couldn't consume all data.
-                    anyhow::bail!("stream contained invalid UTF-8");
-                    // return Err(io::Error::new(
-                    //     io::ErrorKind::InvalidData,
-                    //     "stream contained invalid UTF-8",
-                    // ));
-                } else {
-                    return Ok(Contents(builder.finish()));
-                }
+        let (mut char_shift, mut byte_shift) = (0isize, 0isize);
+        let mut ends = Vec::with_capacity(applied.len());
+        let mut changed: Option<Range<usize>> = None;
+        for (end, char_delta, byte_start, byte_delta, inserted) in applied {
+            ends.push(end.saturating_add_signed(char_shift));
+            let start = byte_start.saturating_add_signed(byte_shift);
+            let range = changed.get_or_insert(start..start);
+            range.end = start + inserted;
+            char_shift += char_delta;
+            byte_shift += byte_delta;
+        }
+        // with nothing to move, the shifts only wait for highlights of an older version; past so
+        // many, those are dropped instead when they come
+        if self.highlights.is_empty() && self.shifts.len() > MAX_SHIFTS {
+            self.shifts.clear();
+            self.highlighted = version;
+        }
+        if !edits.is_empty() {
+            self.version = version;
+            self.dirty = true;
+            // the file changed on disk is no longer all there is to lose
+            if self.disk_state == DiskState::Changed {
+                self.disk_state = DiskState::Conflicted;
+            }
+        }
+        Ok((ends, changed.unwrap_or_default()))
+    }
+
+    /// Takes the edits made since the last call, in the order they were applied, each with the
+    /// version it produced. An edit's offsets are in bytes and hold for the contents as they
+    /// were just before it, so the edits can be replayed one after another onto a syntax tree.
+    pub fn take_edits(&mut self) -> Vec<(u64, EditSummary)> {
+        std::mem::take(&mut self.edits)
+    }
+
+    /// Sets `mark` to `point`, replacing wherever it was.
+    pub fn set_mark(&mut self, mark: char, point: Point) {
+        let offset = self.contents.point_to_char_offset(point);
+        self.marks.insert(mark, offset);
+    }
+
+    /// Where `mark` is now, if it's set.
+    pub fn mark(&self, mark: char) -> Option<Point> {
+        let offset = *self.marks.get(&mark)?;
+        Some(self.contents.char_offset_to_point(offset))
+    }
+
+    pub fn remove_mark(&mut self, mark: char) {
+        self.marks.remove(&mark);
+    }
+
+    /// The contents as they were last read from or saved to disk; None for a buffer that has
+    /// never been on disk.
+    pub fn saved(&self) -> Option<&Contents> {
+        self.saved.as_ref()
+    }
+
+    /// How the lines of the buffer differ from the saved contents. Edits since the diff was
+    /// made only move the changes along with the lines around them, until it's made again.
+    pub fn line_diff(&self) -> &[LineChange] {
+        &self.line_diff
+    }
+
+    /// Keeps the changes found by diffing the contents at `version` against the saved ones. A
+    /// diff of an older version no longer lines up with the buffer, and is dropped.
+    pub fn set_line_diff(&mut self, version: u64, changes: Vec<LineChange>) {
+        if version == self.version {
+            self.line_diff = changes;
+        }
+    }
+
+    /// The problems last reported in the buffer, moved along by edits since.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Replaces the diagnostics with those found in the contents at `version`. Like a line diff,
+    /// diagnostics of an older version are dropped.
+    pub fn set_diagnostics(&mut self, version: u64, diagnostics: Vec<Diagnostic>) {
+        if version == self.version {
+            self.diagnostics = diagnostics;
+        }
+    }
+
+    /// Every highlight covering the byte at `offset`, in the order they're tried when drawing it,
+    /// so the first is the one that shows.
+    pub fn highlights_at(&self, offset: usize) -> Vec<(Range<usize>, &str)> {
+        self.highlights_in(offset..offset + 1)
+    }
+
+    /// Every highlight overlapping the byte `range`, sorted by start and then end, for drawing a
+    /// line with a single lookup.
+    ///
+    /// The highlights are kept where they were found, so they're moved along by the edits made
+    /// since as they're looked up, which costs an edit nothing however many highlights follow it.
+    pub fn highlights_in(&self, range: Range<usize>) -> Vec<(Range<usize>, &str)> {
+        highlights_in(&self.highlights, &self.shifts, range)
+    }
+
+    /// The buffer as it is now, for drawing while it goes on changing.
+    pub fn snapshot(&self) -> BufferSnapshot {
+        BufferSnapshot {
+            id: self.id,
+            path: self.path.clone(),
+            contents: self.contents.clone(),
+            version: self.version,
+            dirty: self.dirty,
+            read_only: self.read_only,
+            format: self.format,
+            disk_state: self.disk_state,
+            settings: self.settings.clone(),
+            highlights: self.highlights.clone(),
+            shifts: self.shifts.as_slice().into(),
+            line_diff: self.line_diff.as_slice().into(),
+            diagnostics: self.diagnostics.as_slice().into(),
+            saved: self.saved.is_some(),
+        }
+    }
+
+    pub fn word_index(&self) -> &WordIndex {
+        &self.words
+    }
+
+    /// The byte range of every occurrence of `word`, sorted; none while the index is disabled or
+    /// being built again.
+    pub fn word_occurrences(&self, word: &str) -> &[Range<usize>] {
+        self.words.occurrences(word)
+    }
+
+    /// Up to `limit` of the words in the buffer starting with `prefix`, the most frequent first.
+    pub fn words_with_prefix(&self, prefix: &str, limit: usize) -> Vec<&str> {
+        self.words.with_prefix(prefix, limit)
+    }
+
+    /// Keeps the word index built from the contents at `version`. Like a line diff, one of an
+    /// older version is dropped.
+    pub fn set_word_index(&mut self, version: u64, words: WordIndex) {
+        if version == self.version {
+            self.words = words;
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Takes on the settings of the project the buffer's file is in, under those of
+    /// `.editorconfig` files.
+    pub fn set_project(&mut self, project: SettingsOverlay) {
+        self.project = project;
+        self.fold_settings();
+    }
+
+    /// Takes on the settings `.editorconfig` files have for the buffer, under any the user set.
+    pub fn set_editorconfig(&mut self, editorconfig: SettingsOverlay) {
+        self.editorconfig = editorconfig;
+        self.fold_settings();
+    }
+
+    /// Sets `key` to `value` for this buffer, over whatever `.editorconfig` files say. Keys and
+    /// values are those of an `.editorconfig`.
+    pub fn set_override(&mut self, key: &str, value: &str) -> Result<(), String> {
+        self.overrides.set(key, value)?;
+        self.fold_settings();
+        Ok(())
+    }
+
+    fn fold_settings(&mut self) {
+        self.settings = Settings::default();
+        self.project.apply(&mut self.settings);
+        self.editorconfig.apply(&mut self.settings);
+        self.overrides.apply(&mut self.settings);
+    }
+
+    /// Makes the edits the settings call for before saving, like trimming trailing whitespace.
+    /// Returns the byte range they changed, if they changed anything.
+    pub fn fix_up(&mut self) -> Option<Range<usize>> {
+        let fixups = self.settings.fixups(&self.contents);
+        let edits: Vec<_> = fixups
+            .iter()
+            .map(|(range, text)| (range.clone(), text.as_str()))
+            .collect();
+        if edits.is_empty() {
+            return None;
+        }
+        self.apply_edits(&edits).ok().map(|(_, range)| range)
+    }
+
+    pub async fn save(&mut self) -> Result<()> {
+        let Some(path) = &self.path else {
+            anyhow::bail!("buffer has no path to save to");
+        };
+        if self.read_only {
+            anyhow::bail!("buffer is read-only");
+        }
+        let format = self.settings.charset.unwrap_or(self.format);
+        let bytes = format.encode(&self.contents.to_string());
+        tokio::fs::write(path, bytes).await?;
+        self.disk = DiskStamp::read(path, &self.contents).await;
+        self.disk_state = DiskState::Unchanged;
+        self.format = format;
+        self.dirty = false;
+        self.had_final_newline = self.contents.ends_with_line_break();
+        self.saved = Some(self.contents.clone());
+        self.line_diff.clear();
+        Ok(())
+    }
+
+    /// Notes that something else has written the file, which conflicts with any unsaved changes.
+    pub fn disk_changed(&mut self) {
+        self.disk_state = if self.dirty {
+            DiskState::Conflicted
+        } else {
+            DiskState::Changed
+        };
+    }
+
+    /// Whether something else has written the file since it was last read or saved. Only a file
+    /// whose time or size moved is read, and it has changed only if it reads differently; if it
+    /// doesn't, its new time and size are kept so it isn't read again. A file that's gone or
+    /// doesn't read hasn't changed, as far as saving over it goes.
+    pub async fn check_disk(&mut self) -> bool {
+        let (Some(path), Some(disk)) = (&self.path, &mut self.disk) else {
+            return false;
+        };
+        let Ok(metadata) = tokio::fs::metadata(path).await else {
+            return false;
+        };
+        if disk.matches(&metadata) {
+            return false;
+        }
+        match Self::read(path).await {
+            Ok((contents, _)) if contents.content_hash() == disk.hash => {
+                *disk = DiskStamp { hash: disk.hash, ..DiskStamp::of(&metadata) };
+                false
             }
+            Ok(_) => true,
+            Err(_) => false,
         }
     }
 
+    /// Reads the file again in place of the contents, unsaved changes and all, as one edit of
+    /// the text that differs, which can be undone. Returns the byte range it changed, or None if
+    /// nothing did.
+    pub async fn reload(&mut self) -> Result<Option<Range<usize>>> {
+        let Some(path) = self.path.clone() else {
+            anyhow::bail!("buffer has no path to reload from");
+        };
+        let (contents, format) = Self::read(&path).await?;
+        let (removed, inserted) = differing(&self.contents, &contents);
+        let mut range = None;
+        if !removed.is_empty() || !inserted.is_empty() {
+            let text = contents.slice(inserted).to_string();
+            range = Some(self.apply_edits(&[(removed, &text)])?.1);
+        }
+        self.disk = DiskStamp::read(&path, &self.contents).await;
+        self.disk_state = DiskState::Unchanged;
+        self.format = format;
+        self.dirty = false;
+        self.had_final_newline = self.contents.ends_with_line_break();
+        self.saved = Some(self.contents.clone());
+        self.line_diff.clear();
+        Ok(range)
+    }
+
+    /// Reads `filename`, decoding it per its BOM. A file that doesn't decode fails with an
+    /// `InvalidEncoding` error.
+    pub async fn read(filename: &PathBuf) -> Result<(Contents, Format)> {
+        let (contents, format, _) = Self::load(filename, false).await?;
+        Ok((contents, format))
+    }
+
+    /// Reads `filename` like `read`, but replaces anything that doesn't decode with U+FFFD,
+    /// returning how many sequences were replaced.
+    pub async fn read_lossy(filename: &PathBuf) -> Result<(Contents, Format, usize)> {
+        Self::load(filename, true).await
+    }
+
+    async fn load(filename: &PathBuf, lossy: bool) -> Result<(Contents, Format, usize)> {
+        let file = tokio::fs::File::open(filename).await?;
+        let total = file.metadata().await.ok().map(|metadata| metadata.len());
+        let mut loader = Loader::new(file, total, lossy, CancellationToken::new());
+        while let Some(read) = loader.next().await {
+            read?;
+        }
+        Ok(loader.finish())
+    }
+
+    /// Applies highlights found in the contents at some version. Those already applied are
+    /// first moved along by the edits made up to that version, so a reparse that finishes after
+    /// more typing still lines up; highlights older than those already applied are dropped.
     pub fn command(&mut self, command: Command) {
+        let version = match &command {
+            Command::Highlight(version, _) | Command::HighlightDelta(version, _) => *version,
+        };
+        if version < self.highlighted {
+            return;
+        }
+        let seen = self
+            .shifts
+            .partition_point(|(edited, ..)| *edited <= version);
+        let seen: Vec<_> = self.shifts.drain(..seen).collect();
+        self.highlighted = version;
         match command {
-            Command::Highlight(hls) => self.highlights = hls,
+            Command::Highlight(_, hls) => self.highlights = Arc::new(hls),
+            Command::HighlightDelta(_, deltas) => {
+                let highlights = Arc::make_mut(&mut self.highlights);
+                shift_highlights(highlights, &seen);
+                for (range, hls) in deltas {
+                    let stale: Vec<_> = highlights.intervals(range).collect();
+                    for interval in stale {
+                        highlights.remove(interval);
+                    }
+                    for (interval, name) in hls.unsorted_into_iter() {
+                        highlights.insert(interval, name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl HistoryAccounting for Buffer {
+    fn retained_bytes(&self) -> usize {
+        self.undo.retained_bytes()
+    }
+
+    fn drop_oldest_state(&mut self, min_depth: usize) -> Option<usize> {
+        self.undo.drop_oldest_state(min_depth)
+    }
+}
+
+/// Moves `highlights` along as each byte range in `shifts` is replaced by as many bytes as it's
+/// paired with, in order. Only those that end at or after the first edit's start can move; one
+/// ending right where text goes in grows over it.
+fn shift_highlights(highlights: &mut Highlights, shifts: &[(u64, Range<usize>, usize)]) {
+    let Some(from) = shifts.iter().map(|(_, removed, _)| removed.start).min() else {
+        return;
+    };
+    let moved: Vec<_> = highlights
+        .intervals(from.saturating_sub(1)..usize::MAX)
+        .collect();
+    let moved: Vec<_> = moved
+        .into_iter()
+        .filter_map(|range| Some((range.clone(), highlights.remove(range)?)))
+        .collect();
+    for (range, name) in moved {
+        let range = shift(range, shifts);
+        if range.start < range.end {
+            highlights.insert(range, name);
         }
     }
 }
 
+/// Where the byte `range` ends up once each range in `shifts` is replaced in turn.
+fn shift(range: Range<usize>, shifts: &[(u64, Range<usize>, usize)]) -> Range<usize> {
+    shifts.iter().fold(range, |range, (_, removed, inserted)| {
+        remap(range.start, removed, *inserted)..remap(range.end, removed, *inserted)
+    })
+}
+
+/// The byte range holding every range that replacing `removed` with `inserted` bytes could move
+/// into `range`; more, but never fewer. One ending just before the removed text counts, since
+/// one ending right where text goes in grows over it.
+fn unshift(range: Range<usize>, removed: &Range<usize>, inserted: usize) -> Range<usize> {
+    let inserted_end = removed.start + inserted;
+    let back = |offset: usize| (offset - inserted_end).saturating_add(removed.end);
+    let start = if range.start < removed.start {
+        range.start
+    } else if range.start < inserted_end {
+        removed.start.saturating_sub(1)
+    } else {
+        back(range.start)
+    };
+    let end = if range.end <= removed.start {
+        range.end
+    } else if range.end <= inserted_end {
+        removed.end
+    } else {
+        back(range.end)
+    };
+    start..end.max(start.saturating_add(1))
+}
+
+/// A file as it was on disk: its time and size, to tell cheaply when it may have been written,
+/// and a hash of what it read as, to tell whether it really was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskStamp {
+    pub modified: Option<std::time::SystemTime>,
+    pub len: u64,
+    pub hash: u64,
+}
+
+impl DiskStamp {
+    /// The stamp of the file at `path` as it is now, which reads as `contents`.
+    pub async fn read(path: &std::path::Path, contents: &Contents) -> Option<Self> {
+        let metadata = tokio::fs::metadata(path).await.ok()?;
+        Some(Self { hash: contents.content_hash(), ..Self::of(&metadata) })
+    }
+
+    fn of(metadata: &std::fs::Metadata) -> Self {
+        Self { modified: metadata.modified().ok(), len: metadata.len(), hash: 0 }
+    }
+
+    fn matches(&self, metadata: &std::fs::Metadata) -> bool {
+        self.modified == metadata.modified().ok() && self.len == metadata.len()
+    }
+}
+
+/// How a buffer's file on disk stands against it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiskState {
+    /// As it was last read or saved, as far as anyone has looked.
+    #[default]
+    Unchanged,
+    /// Written by something else since, with nothing unsaved in the buffer to lose by reading
+    /// it again.
+    Changed,
+    /// Written by something else since, with unsaved changes in the buffer too: saving loses
+    /// the one and reading again the other.
+    Conflicted,
+}
+
 #[derive(Debug, Clone)]
-pub struct Contents(Rope);
+pub struct Contents(pub(crate) Rope);
 
 impl Contents {
+    /// A hash of the text, the same for the same text however it's split into chunks.
+    pub fn content_hash(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = std::hash::DefaultHasher::new();
+        self.0.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Whether the last line ends with a line break; an empty text has no line to end, and
+    /// counts as ending with one.
+    pub fn ends_with_line_break(&self) -> bool {
+        match self.0.len_chars() {
+            0 => true,
+            len => self.0.char(len - 1) == '\n',
+        }
+    }
+
+    /// Whether the text from `point` on starts with `prefix`, compared a chunk at a time rather
+    /// than copied out.
+    pub fn starts_with_at(&self, point: Point, prefix: &str) -> bool {
+        let start = self.point_to_char_offset(point);
+        let mut prefix = prefix.as_bytes();
+        for chunk in self.0.slice(start..).chunks() {
+            if prefix.is_empty() {
+                break;
+            }
+            let len = chunk.len().min(prefix.len());
+            if chunk.as_bytes()[..len] != prefix[..len] {
+                return false;
+            }
+            prefix = &prefix[len..];
+        }
+        prefix.is_empty()
+    }
+
+    /// The number of spaces and tabs `line` starts with, which as they're a byte each is as many
+    /// chars as bytes.
+    pub fn leading_whitespace_len(&self, line: usize) -> usize {
+        let mut len = 0;
+        for chunk in self.0.line(line).chunks() {
+            let blank = chunk
+                .bytes()
+                .take_while(|b| matches!(b, b' ' | b'\t'))
+                .count();
+            len += blank;
+            if blank < chunk.len() {
+                break;
+            }
+        }
+        len
+    }
+
+    /// The number of chars on `line`, not counting its line break.
+    pub fn line_len_chars_excluding_terminator(&self, line: usize) -> usize {
+        let line = self.0.line(line);
+        let len = line.len_chars();
+        match line.chars_at(len).prev() {
+            Some('\n') if len >= 2 && line.char(len - 2) == '\r' => len - 2,
+            Some('\n') => len - 1,
+            _ => len,
+        }
+    }
+
+    /// The furthest column a cursor can be on `line`. Like vim, normal and visual line modes sit on
+    /// the last char, while insert and replace modes can sit just past it to append.
+    pub fn max_cursor_column(&self, line: usize, mode: Mode) -> usize {
+        let len = self.line_len_chars_excluding_terminator(line);
+        match mode {
+            Mode::Normal | Mode::VisualLine => len.saturating_sub(1),
+            Mode::Insert | Mode::Replace => len,
+        }
+    }
+
+    /// Cursors moved by the editor always stay within the buffer, so a bad one is a bug.
+    #[track_caller]
     pub(crate) fn point_to_char_offset(&self, cursor: Point) -> usize {
+        let offset = self.try_point_to_char_offset(cursor);
+        debug_assert!(offset.is_ok(), "cursor {:?} outside buffer: {:?}", cursor, offset);
         let line_offset = self.0.line_to_char(cursor.line);
         line_offset + cursor.column
     }
 
+    pub(crate) fn try_point_to_char_offset(&self, cursor: Point) -> Result<usize, rope::Error> {
+        let line_offset = self.0.try_line_to_char(cursor.line)?;
+        let offset = line_offset + cursor.column;
+        let len = self.0.len_chars();
+        if offset > len {
+            return Err(rope::Error::IndexOutOfBounds { op: "point_to_char_offset", offset, len });
+        }
+        Ok(offset)
+    }
+
+    /// The line of the byte `offset` and its column in bytes, as syntax trees count them.
+    pub fn byte_offset_to_point(&self, offset: usize) -> Point {
+        let line = self.0.byte_to_line(offset);
+        let column = offset - self.0.line_to_byte(line);
+        Point { line, column }
+    }
+
+    /// The byte offset of the char `cursor` is on, or of the end of the contents past them.
+    pub fn point_to_byte_offset(&self, cursor: Point) -> usize {
+        let offset = self.try_point_to_char_offset(cursor);
+        self.0.char_to_byte(offset.unwrap_or(self.0.len_chars()))
+    }
+
+    /// The bytes `range` covers, as `point_to_byte_offset` has each end.
+    pub fn point_range_to_byte_range(&self, range: PointRange) -> Range<usize> {
+        range.to_offsets(|point| self.point_to_byte_offset(point))
+    }
+
     pub(crate) fn char_offset_to_point(&self, offset: usize) -> Point {
         let line = self.0.char_to_line(offset);
         let column = offset - self.0.line_to_char(line);
@@ -117,6 +840,104 @@ impl Contents {
     }
 }
 
+/// Every highlight in `highlights` overlapping the byte `range` once moved along by each edit in
+/// `shifts`, sorted by start and then end.
+pub(crate) fn highlights_in<'a>(
+    highlights: &'a Highlights,
+    shifts: &[(u64, Range<usize>, usize)],
+    range: Range<usize>,
+) -> Vec<(Range<usize>, &'a str)> {
+    if range.is_empty() {
+        return vec![];
+    }
+    if shifts.is_empty() {
+        return highlights
+            .iter(range)
+            .map(|(range, name)| (range, name.as_str()))
+            .collect();
+    }
+    // every highlight that could end up in `range`, then just those that do
+    let found = shifts
+        .iter()
+        .rev()
+        .fold(range.clone(), |found, (_, removed, inserted)| {
+            unshift(found, removed, *inserted)
+        });
+    let mut highlights: Vec<_> = highlights
+        .iter(found)
+        .map(|(moved, name)| (shift(moved, shifts), name.as_str()))
+        .filter(|(moved, _)| {
+            !moved.is_empty() && moved.start < range.end && range.start < moved.end
+        })
+        .collect();
+    // ranges the edits squeezed to the same start may now be out of order by their ends, or the
+    // same range, which only the last one put there keeps
+    highlights.sort_by_key(|(range, _)| (range.start, range.end));
+    highlights.dedup_by(|later, earlier| {
+        let same = later.0 == earlier.0;
+        if same {
+            std::mem::swap(later, earlier);
+        }
+        same
+    });
+    highlights
+}
+
+/// The char ranges of `from` and `to` that differ, between what they start and end with alike.
+fn differing(from: &Rope, to: &Rope) -> (Range<usize>, Range<usize>) {
+    let prefix = from
+        .bytes()
+        .zip(to.bytes())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let most = from.len_bytes().min(to.len_bytes()) - prefix;
+    let (mut from_back, mut to_back) =
+        (from.bytes_at(from.len_bytes()), to.bytes_at(to.len_bytes()));
+    let mut suffix = 0;
+    while suffix < most && from_back.prev().is_some_and(|a| Some(a) == to_back.prev()) {
+        suffix += 1;
+    }
+    // a char that's only partly alike differs
+    let start = from.byte_to_char(prefix);
+    let end = |rope: &Rope| {
+        let end = rope.len_bytes() - suffix;
+        let char = rope.byte_to_char(end);
+        char + usize::from(rope.char_to_byte(char) < end)
+    };
+    (start..end(from), start..end(to))
+}
+
+/// Where `line` ends up once the `removed` line breaks after the start of an edit on `start` are
+/// replaced with `inserted` ones. Lines up to the edit stay put, and those after it shift.
+fn remap_line(line: usize, start: usize, removed: usize, inserted: usize) -> usize {
+    if line <= start {
+        line
+    } else if line <= start + removed {
+        start + (line - start).min(inserted)
+    } else {
+        line + inserted - removed
+    }
+}
+
+/// The size of `text`, with its last line's length in bytes.
+fn slice_extent(text: RopeSlice) -> Extent {
+    let line = text.len_lines() - 1;
+    let column = text.len_bytes() - text.line_to_byte(line);
+    Extent { len: text.len_bytes(), lines: Point { line, column } }
+}
+
+fn str_extent(text: &str) -> Extent {
+    let line = text.matches('\n').count();
+    let column = text.rfind('\n').map_or(text.len(), |i| text.len() - i - 1);
+    Extent { len: text.len(), lines: Point { line, column } }
+}
+
+impl From<&str> for Contents {
+    fn from(text: &str) -> Self {
+        Contents(Rope::from_str(text))
+    }
+}
+
 impl Deref for Contents {
     type Target = Rope;
 
@@ -130,3 +951,298 @@ impl DerefMut for Contents {
         &mut self.0
     }
 }
+
+/// Where `offset` ends up once `removed` is replaced with `inserted` units: before the edit it
+/// stays, within the removed text it goes to where that started, after it it moves along.
+fn remap(offset: usize, removed: &Range<usize>, inserted: usize) -> usize {
+    if offset < removed.start {
+        offset
+    } else if offset < removed.end {
+        removed.start
+    } else {
+        offset + inserted - removed.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UndoMove;
+    use test_support::Rng;
+
+    fn buffer(text: &str) -> Buffer {
+        Buffer::new(Default::default(), text.into())
+    }
+
+    #[test]
+    fn summary_of_a_single_edit() {
+        let mut buffer = buffer("héllo\nworld");
+        let summary = buffer.apply_edit(1..2, "e\nxy").unwrap();
+        assert_eq!(buffer.contents.to_string(), "he\nxyllo\nworld");
+        assert_eq!(
+            summary,
+            EditSummary {
+                start_offset: 1,
+                start_point: Point { line: 0, column: 1 },
+                removed: Extent { len: 2, lines: Point { line: 0, column: 2 } },
+                inserted: Extent { len: 4, lines: Point { line: 1, column: 2 } },
+            }
+        );
+        assert_eq!(buffer.take_edits(), vec![(1, summary)]);
+        assert!(buffer.dirty);
+    }
+
+    #[test]
+    fn byte_ranges_of_point_ranges() {
+        let contents = buffer("héllo\nwörld").contents;
+        let at = |line, column| Point { line, column };
+        let range = |start, end| contents.point_range_to_byte_range(PointRange::new(start, end));
+        assert_eq!(range(at(0, 1), at(0, 2)), 1..3);
+        assert_eq!(range(at(0, 4), at(1, 2)), 5..10);
+        // past the end is the end
+        assert_eq!(range(at(1, 0), at(1, 9)), 7..13);
+    }
+
+    #[test]
+    fn batch_applied_as_one_change() {
+        let mut buffer = buffer("abc def ghi");
+        let (ends, changed) = buffer
+            .apply_edits(&[(0..3, "x"), (4..7, "long word"), (8..11, "")])
+            .unwrap();
+        assert_eq!(buffer.contents.to_string(), "x long word ");
+        assert_eq!(ends, vec![1, 11, 12]);
+        assert_eq!(changed, 0..12);
+        assert_eq!(buffer.version, 1);
+
+        // last to first, each in the coordinates the one before it left
+        let edits = buffer.take_edits();
+        let starts: Vec<_> = edits.iter().map(|(_, e)| e.start_offset).collect();
+        assert_eq!(starts, vec![8, 4, 0]);
+        assert!(edits.iter().all(|(version, _)| *version == 1));
+    }
+
+    #[test]
+    fn bad_batches_change_nothing() {
+        let mut buffer = buffer("abcdef");
+        buffer.set_mark('a', Point { line: 0, column: 4 });
+        let bad: [&[(Range<usize>, &str)]; 4] = [
+            &[(0..3, "x"), (2..4, "y")],
+            &[(4..5, "x"), (0..1, "y")],
+            &[(0..1, "x"), (5..7, "y")],
+            &[(7..7, "x")],
+        ];
+        let errors: Vec<_> = bad
+            .iter()
+            .map(|edits| buffer.apply_edits(edits).unwrap_err())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                EditError::Overlapping(0..3, 2..4),
+                EditError::Overlapping(4..5, 0..1),
+                EditError::OutOfBounds(5..7, 6),
+                EditError::OutOfBounds(7..7, 6),
+            ]
+        );
+        assert_eq!(errors[0].to_string(), "edits at 0..3 and 2..4 overlap");
+        assert_eq!(buffer.contents.to_string(), "abcdef");
+        assert_eq!((buffer.version, buffer.dirty), (0, false));
+        assert!(buffer.take_edits().is_empty());
+        assert_eq!(buffer.mark('a'), Some(Point { line: 0, column: 4 }));
+
+        // ranges that only meet, or insert at the same place, are fine
+        buffer
+            .apply_edits(&[(0..2, "x"), (2..4, "y"), (4..4, "1"), (4..4, "2")])
+            .unwrap();
+        assert_eq!(buffer.contents.to_string(), "xy12ef");
+    }
+
+    #[test]
+    fn version_goes_up_once_per_change() {
+        let mut buffer = buffer("abc");
+        assert_eq!(buffer.apply_edits(&[]), Ok((vec![], 0..0)));
+        assert_eq!((buffer.version, buffer.dirty), (0, false));
+        let mut last = buffer.version;
+        for (edits, ok) in [
+            (&[(0..0, "x"), (1..2, "")][..], true),
+            (&[(9..9, "x")][..], false),
+            (&[(0..1, "")][..], true),
+            (&[(0..0, "")][..], true),
+        ] {
+            assert_eq!(buffer.apply_edits(edits).is_ok(), ok);
+            assert_eq!(buffer.version, last + ok as u64);
+            last = buffer.version;
+        }
+    }
+
+    #[test]
+    fn undoing_edits_restores_the_contents() {
+        let pieces = ["", "a", "é", "\n", "\r\n", "foo bar", "x\ny\n"];
+        for seed in 0..50 {
+            let mut rng = Rng::new(seed);
+            let mut buffer = buffer("one\ntwo é\r\nthree\n");
+            for _ in 0..20 {
+                let before = buffer.contents.to_string();
+                let len = buffer.contents.len_chars();
+                let start = rng.below(len + 1);
+                let end = (start + rng.below(5)).min(len);
+                let text = *rng.pick(&pieces);
+                let removed = buffer.contents.slice(start..end).to_string();
+                let version = buffer.version;
+
+                let summary = buffer.apply_edit(start..end, text).unwrap();
+                let after = buffer.contents.to_string();
+                let spliced = [
+                    &before[..summary.start_offset],
+                    &after[summary.start_offset..summary.new_end_offset()],
+                    &before[summary.old_end_offset()..],
+                ];
+                assert_eq!(spliced.concat(), after, "seed {}", seed);
+                let old = Contents::from(before.as_str());
+                let byte_start = old.char_to_byte(start);
+                assert_eq!(summary.start_offset, byte_start, "seed {}", seed);
+                assert_eq!(summary.start_point, old.byte_offset_to_point(byte_start));
+
+                let inserted = text.chars().count();
+                let undo = buffer
+                    .apply_edit(start..start + inserted, &removed)
+                    .unwrap();
+                assert_eq!(buffer.contents.to_string(), before, "seed {}", seed);
+                assert_eq!(buffer.version, version + 2);
+                assert_eq!(
+                    (undo.start_offset, undo.removed, undo.inserted),
+                    (summary.start_offset, summary.inserted, summary.removed),
+                    "seed {}",
+                    seed
+                );
+
+                let len = buffer.contents.len_chars();
+                let start = rng.below(len + 1);
+                buffer.apply_edit(start..start, text).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn undo_puts_back_only_what_differs() {
+        let mut buffer = buffer("héllo wörld\n");
+        let opened = buffer.undo_tree().current();
+        buffer.apply_edit(1..2, "e").unwrap();
+        buffer.apply_edit(6..11, "there").unwrap();
+        assert_eq!(buffer.contents.to_string(), "hello there\n");
+        assert_eq!(buffer.undo_tree().len(), 3);
+        buffer.take_edits();
+
+        // one edit, from the first char that differs to the last
+        let version = buffer.version;
+        assert_eq!(buffer.undo_to(opened), Some((1, 1..13)));
+        assert_eq!(buffer.contents.to_string(), "héllo wörld\n");
+        assert_eq!(buffer.version, version + 1);
+        assert_eq!(buffer.take_edits().len(), 1);
+        assert_eq!(buffer.undo_tree().current(), opened);
+        assert_eq!(buffer.undo_to(opened), None);
+
+        // editing from there branches, leaving the undone states to go back to
+        buffer.apply_edit(0..0, ">").unwrap();
+        buffer.join_undo();
+        buffer.apply_edit(1..1, " ").unwrap();
+        assert_eq!(buffer.undo_tree().len(), 4);
+        let back = buffer.undo_tree().target(UndoMove::Steps(-1)).unwrap();
+        assert_eq!(buffer.undo_to(back), Some((0, 0..11)));
+        assert_eq!(buffer.contents.to_string(), "hello there\n");
+        let undo = buffer.undo_tree().target(UndoMove::Undo).unwrap();
+        buffer.undo_to(undo);
+        assert_eq!(buffer.contents.to_string(), "hello wörld\n");
+    }
+
+    #[test]
+    fn highlights_and_marks_move_with_edits() {
+        let mut buffer = buffer("let x = 1;\n");
+        for (range, name) in [(0..3, "keyword"), (4..5, "variable"), (8..9, "number")] {
+            Arc::make_mut(&mut buffer.highlights).insert(range, name.to_string());
+        }
+        buffer.set_mark('a', Point { line: 0, column: 8 });
+
+        buffer.apply_edit(4..5, "long").unwrap();
+        assert_eq!(
+            buffer.highlights_in(0..usize::MAX),
+            vec![(0..3, "keyword"), (4..8, "variable"), (11..12, "number")]
+        );
+        assert_eq!(buffer.mark('a'), Some(Point { line: 0, column: 11 }));
+
+        buffer.apply_edit(0..4, "").unwrap();
+        assert_eq!(buffer.contents.to_string(), "long = 1;\n");
+        assert_eq!(buffer.highlights_in(0..usize::MAX), vec![(0..4, "variable"), (7..8, "number")]);
+        assert_eq!(buffer.mark('a'), Some(Point { line: 0, column: 7 }));
+    }
+
+    #[test]
+    fn lookups_move_highlights_as_edits_would() {
+        let pieces = ["", "a", "é", "\n", "foo bar"];
+        for seed in 0..50 {
+            let mut rng = Rng::new(seed);
+            let mut buffer = buffer("fn main() {\n    let s = \"hi\";\n}\n");
+            let mut highlights = Highlights::new();
+            for _ in 0..10 {
+                let start = rng.below(buffer.contents.len_bytes());
+                let end = start + 1 + rng.below(6);
+                highlights.insert(start..end, format!("{}", start));
+            }
+            buffer.command(Command::Highlight(0, highlights.clone()));
+            for _ in 0..5 {
+                let len = buffer.contents.len_chars();
+                let start = rng.below(len + 1);
+                let end = (start + rng.below(4)).min(len);
+                let text = *rng.pick(&pieces);
+                buffer.apply_edit(start..end, text).unwrap();
+            }
+            // the edits leave the highlights as they were, until they're looked up
+            let entries = |highlights: &Highlights| {
+                let entries = highlights
+                    .iter(..)
+                    .map(|(range, name)| (range, name.clone()));
+                entries.collect::<Vec<_>>()
+            };
+            assert_eq!(entries(&buffer.highlights), entries(&highlights));
+
+            let mut moved = highlights.clone();
+            shift_highlights(&mut moved, &buffer.shifts);
+            let len = buffer.contents.len_bytes();
+            for start in 0..=len {
+                for end in start..=len + 1 {
+                    let expected: Vec<_> = moved
+                        .iter(start..end.max(start + 1))
+                        .filter(|_| start < end)
+                        .map(|(range, name)| (range, name.as_str()))
+                        .collect();
+                    let found = buffer.highlights_in(start..end);
+                    assert_eq!(found, expected, "seed {} at {}..{}", seed, start, end);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn edits_over_a_changed_file_conflict() {
+        let mut clean = buffer("one\n");
+        clean.disk_changed();
+        assert_eq!(clean.disk_state, DiskState::Changed);
+        clean.apply_edit(0..0, "zero ").unwrap();
+        assert_eq!(clean.disk_state, DiskState::Conflicted);
+
+        // dirty already, so it conflicts as soon as it's noticed
+        let mut dirty = buffer("one\n");
+        dirty.apply_edit(0..0, "zero ").unwrap();
+        dirty.disk_changed();
+        assert_eq!(dirty.disk_state, DiskState::Conflicted);
+    }
+
+    #[test]
+    fn content_hash_ignores_chunking() {
+        let whole = buffer("hello wörld\n");
+        let mut pieces = buffer("wörld\n");
+        pieces.apply_edit(0..0, "hello ").unwrap();
+        assert_eq!(whole.contents.content_hash(), pieces.contents.content_hash());
+        assert_ne!(whole.contents.content_hash(), buffer("hello world\n").contents.content_hash());
+    }
+}
@@ -0,0 +1,18 @@
+use std::ops::Range;
+
+/// How serious a diagnostic is, least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Hint,
+    Info,
+    Warning,
+    Error,
+}
+
+/// A problem some tool found in a run of lines, numbered as they are in the buffer now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub lines: Range<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
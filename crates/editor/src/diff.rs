@@ -0,0 +1,424 @@
+use std::ops::Range;
+
+use crate::buffer::Contents;
+
+/// How a run of lines differs from the saved version of the buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChangeKind {
+    Added,
+    Modified,
+    /// Lines were removed just before the start of the change, which covers no lines itself.
+    Removed,
+}
+
+/// A run of changed lines, numbered as they are in the buffer now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineChange {
+    pub lines: Range<usize>,
+    pub kind: LineChangeKind,
+}
+
+/// Which of two buffers diffed side by side something is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffSide {
+    Left,
+    Right,
+}
+
+impl DiffSide {
+    pub fn other(self) -> Self {
+        match self {
+            DiffSide::Left => DiffSide::Right,
+            DiffSide::Right => DiffSide::Left,
+        }
+    }
+}
+
+/// A row of two buffers shown side by side: the line of each on it, `None` for filler across
+/// from a line only the other side has, and whether the two differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlignedRow {
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub changed: bool,
+}
+
+impl AlignedRow {
+    pub fn line(&self, side: DiffSide) -> Option<usize> {
+        match side {
+            DiffSide::Left => self.left,
+            DiffSide::Right => self.right,
+        }
+    }
+}
+
+/// How the lines of two buffers line up side by side. Lines the diff leaves alone share a row;
+/// where a run of lines was replaced, the shorter side is padded out with filler so the lines
+/// after it stay across from each other.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffAlignment {
+    rows: Vec<AlignedRow>,
+    /// The row each line of the left side is on.
+    left: Vec<usize>,
+    /// The row each line of the right side is on.
+    right: Vec<usize>,
+}
+
+/// Past this many differing lines the diff stops looking for the shortest edit, and reports
+/// whatever is left between the common prefix and suffix as a single change.
+const MAX_COST: usize = 1024;
+
+/// Diffs the lines of `contents` against those of `saved`.
+pub fn line_diff(saved: &Contents, contents: &Contents) -> Vec<LineChange> {
+    let saved: Vec<_> = saved.lines().collect();
+    let current: Vec<_> = contents.lines().collect();
+    changes(&saved, &current)
+}
+
+/// The edits that turn `old` into `new`, each replacing a run of whole lines of `old`, in chars,
+/// with the lines of `new` that differ there. Sorted and disjoint, for `Buffer::apply_edits`, and
+/// leaving every line the two share as it is, so what's kept on those lines stays put.
+pub fn line_edits(old: &Contents, new: &Contents) -> Vec<(Range<usize>, String)> {
+    let a: Vec<_> = old.lines().collect();
+    let b: Vec<_> = new.lines().collect();
+    let mut edits = vec![];
+    let (mut x, mut y) = (0, 0);
+    for (a_end, b_end) in matches(&a, &b).into_iter().chain([(a.len(), b.len())]) {
+        if x < a_end || y < b_end {
+            let removed = old.line_to_char(x)..old.line_to_char(a_end);
+            let inserted = new.line_to_char(y)..new.line_to_char(b_end);
+            edits.push((removed, new.slice(inserted).to_string()));
+        }
+        (x, y) = (a_end + 1, b_end + 1);
+    }
+    edits
+}
+
+impl DiffAlignment {
+    /// Lines up the lines of `left` with those of `right`.
+    pub fn new(left: &Contents, right: &Contents) -> Self {
+        let left: Vec<_> = left.lines().collect();
+        let right: Vec<_> = right.lines().collect();
+        Self::of(&left, &right)
+    }
+
+    fn of<T: PartialEq>(a: &[T], b: &[T]) -> Self {
+        let mut alignment = Self::default();
+        let (mut x, mut y) = (0, 0);
+        for (a_end, b_end) in matches(a, b).into_iter().chain([(a.len(), b.len())]) {
+            let (removed, inserted) = (a_end - x, b_end - y);
+            for i in 0..removed.max(inserted) {
+                let left = (i < removed).then_some(x + i);
+                let right = (i < inserted).then_some(y + i);
+                alignment.push(AlignedRow { left, right, changed: true });
+            }
+            if a_end < a.len() {
+                let (left, right) = (Some(a_end), Some(b_end));
+                alignment.push(AlignedRow { left, right, changed: false });
+            }
+            (x, y) = (a_end + 1, b_end + 1);
+        }
+        alignment
+    }
+
+    fn push(&mut self, row: AlignedRow) {
+        let at = self.rows.len();
+        if row.left.is_some() {
+            self.left.push(at);
+        }
+        if row.right.is_some() {
+            self.right.push(at);
+        }
+        self.rows.push(row);
+    }
+
+    pub fn rows(&self) -> &[AlignedRow] {
+        &self.rows
+    }
+
+    fn rows_of(&self, side: DiffSide) -> &[usize] {
+        match side {
+            DiffSide::Left => &self.left,
+            DiffSide::Right => &self.right,
+        }
+    }
+
+    /// The row a line of the side is on; a line past the side's last is on the last one's row.
+    pub fn row_of(&self, side: DiffSide, line: usize) -> usize {
+        let rows = self.rows_of(side);
+        rows.get(line).or(rows.last()).copied().unwrap_or(0)
+    }
+
+    /// The line of the side on the row, or where the side only has filler there, the first
+    /// line below it, or failing that the last above.
+    pub fn nearest_line(&self, side: DiffSide, row: usize) -> usize {
+        let rows = self.rows_of(side);
+        let below = rows.partition_point(|&at| at < row);
+        below.min(rows.len().saturating_sub(1))
+    }
+}
+
+/// Groups each run of lines that were removed from `a` or inserted into `b` into changes. Where
+/// lines were both removed and inserted, as many as were replaced count as modified and any
+/// extra inserted ones as added.
+fn changes<T: PartialEq>(a: &[T], b: &[T]) -> Vec<LineChange> {
+    let mut changes = vec![];
+    let (mut x, mut y) = (0, 0);
+    let mut push = |removed: Range<usize>, inserted: Range<usize>| {
+        let modified = inserted.len().min(removed.len());
+        let split = inserted.start + modified;
+        if modified > 0 {
+            changes
+                .push(LineChange { lines: inserted.start..split, kind: LineChangeKind::Modified });
+        }
+        if split < inserted.end {
+            changes.push(LineChange { lines: split..inserted.end, kind: LineChangeKind::Added });
+        } else if inserted.is_empty() && !removed.is_empty() {
+            let at = inserted.start;
+            changes.push(LineChange { lines: at..at, kind: LineChangeKind::Removed });
+        }
+    };
+    for (a_end, b_end) in matches(a, b).into_iter().chain([(a.len(), b.len())]) {
+        if x < a_end || y < b_end {
+            push(x..a_end, y..b_end);
+        }
+        (x, y) = (a_end + 1, b_end + 1);
+    }
+    changes
+}
+
+/// The pairs of lines of `a` and `b` that a shortest edit from `a` to `b` leaves alone, in order.
+fn matches<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut matches: Vec<_> = (0..prefix).map(|i| (i, i)).collect();
+    matches.extend(
+        myers(a_mid, b_mid)
+            .into_iter()
+            .map(|(x, y)| (prefix + x, prefix + y)),
+    );
+    let (a_tail, b_tail) = (a.len() - suffix, b.len() - suffix);
+    matches.extend((0..suffix).map(|i| (a_tail + i, b_tail + i)));
+    matches
+}
+
+/// Myers' O((N+M)D) diff, returning the matching pairs along a shortest edit script. Gives up
+/// with no matches past `MAX_COST` edits.
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    if n == 0 || m == 0 {
+        return vec![];
+    }
+
+    // v[k + max + 1] is the furthest x reached on diagonal k. Round d only reads the diagonals
+    // within d + 1 of the middle, so those are all that's kept of each round to backtrack through.
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace = vec![];
+    let index = |k: isize| (k + max as isize + 1) as usize;
+    'search: for d in 0..=max.min(MAX_COST) as isize {
+        trace.push(v[index(-d - 1)..=index(d + 1)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                (x, y) = (x + 1, y + 1);
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+        if d == MAX_COST as isize {
+            return vec![];
+        }
+    }
+
+    let mut matches = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let (d, k) = (d as isize, x - y);
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            (x, y) = (x - 1, y - 1);
+            matches.push((x as usize, y as usize));
+        }
+        if d > 0 {
+            (x, y) = (prev_x, prev_y);
+        }
+    }
+    matches.reverse();
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use LineChangeKind::*;
+
+    fn diff(a: &str, b: &str) -> Vec<(Range<usize>, LineChangeKind)> {
+        let a: Vec<_> = a.split_terminator('\n').collect();
+        let b: Vec<_> = b.split_terminator('\n').collect();
+        changes(&a, &b)
+            .into_iter()
+            .map(|change| (change.lines, change.kind))
+            .collect()
+    }
+
+    #[test]
+    fn edits_replace_only_lines_that_differ() {
+        let edits = |a: &str, b: &str| {
+            let (old, new) = (Contents::from(a), Contents::from(b));
+            let edits = line_edits(&old, &new);
+            let mut applied = old.to_string();
+            for (range, text) in edits.iter().rev() {
+                let range = old.char_to_byte(range.start)..old.char_to_byte(range.end);
+                applied.replace_range(range, text);
+            }
+            assert_eq!(applied, b);
+            edits
+        };
+        assert_eq!(edits("a\nb\nc\n", "a\nb\nc\n"), vec![]);
+        assert_eq!(edits("a\nb\nc\n", "a\nB\nc\n"), vec![(2..4, "B\n".to_string())]);
+        assert_eq!(
+            edits("é\nb\nc\nd\n", "é\nc\nD\nE\n"),
+            vec![(2..4, String::new()), (6..8, "D\nE\n".to_string())]
+        );
+        // a last line without a line break is a line like any other
+        assert_eq!(edits("a\nb", "a\nB"), vec![(2..3, "B".to_string())]);
+        assert_eq!(edits("", "a\n"), vec![(0..0, "a\n".to_string())]);
+        edits("a\nb\n", "");
+    }
+
+    #[test]
+    fn classify_changes() {
+        assert_eq!(diff("a\nb\nc\n", "a\nb\nc\n"), vec![]);
+        assert_eq!(diff("a\nb\nc\n", "a\nx\nb\nc\n"), vec![(1..2, Added)]);
+        assert_eq!(diff("a\nb\nc\n", "a\nc\n"), vec![(1..1, Removed)]);
+        assert_eq!(diff("a\nb\nc\n", "a\nB\nc\n"), vec![(1..2, Modified)]);
+        assert_eq!(diff("a\nb\nc\n", "b\nc\n"), vec![(0..0, Removed)]);
+        assert_eq!(diff("a\nb\nc\n", "a\nb\n"), vec![(2..2, Removed)]);
+        assert_eq!(diff("", "a\nb\n"), vec![(0..2, Added)]);
+        assert_eq!(diff("a\nb\n", ""), vec![(0..0, Removed)]);
+
+        // a replacement longer than what it replaces ends in added lines
+        assert_eq!(diff("a\nb\nc\n", "a\nB\nB2\nc\n"), vec![(1..2, Modified), (2..3, Added)]);
+        // and a shorter one is all modified
+        assert_eq!(diff("a\nb\nb2\nc\n", "a\nB\nc\n"), vec![(1..2, Modified)]);
+
+        assert_eq!(
+            diff("a\nb\nc\nd\ne\nf\n", "x\na\nc\nD\ne\nf\ng\n"),
+            vec![
+                (0..1, Added),
+                (2..2, Removed),
+                (3..4, Modified),
+                (6..7, Added)
+            ]
+        );
+    }
+
+    #[test]
+    fn align_sides() {
+        let align = |a: &str, b: &str| {
+            let a: Vec<_> = a.split_terminator('\n').collect();
+            let b: Vec<_> = b.split_terminator('\n').collect();
+            DiffAlignment::of(&a, &b)
+        };
+        let rows = |alignment: &DiffAlignment| -> Vec<_> {
+            let rows = alignment.rows().iter();
+            rows.map(|row| (row.left, row.right, row.changed)).collect()
+        };
+        let (s, filler) = (Some, None);
+
+        assert_eq!(
+            rows(&align("a\nb\n", "a\nb\n")),
+            vec![(s(0), s(0), false), (s(1), s(1), false)]
+        );
+        // a line only the right has gets filler across from it on the left
+        let inserted = align("a\nc\n", "a\nb\nc\n");
+        assert_eq!(
+            rows(&inserted),
+            vec![
+                (s(0), s(0), false),
+                (filler, s(1), true),
+                (s(1), s(2), false)
+            ]
+        );
+        // and one only the left has, on the right
+        assert_eq!(
+            rows(&align("a\nb\nc\n", "a\nc\n")),
+            vec![
+                (s(0), s(0), false),
+                (s(1), filler, true),
+                (s(2), s(1), false)
+            ]
+        );
+        // replaced lines share rows as far as the shorter run goes
+        let replaced = align("a\nb\nc\n", "a\nB\nB2\nc\n");
+        assert_eq!(
+            rows(&replaced),
+            vec![
+                (s(0), s(0), false),
+                (s(1), s(1), true),
+                (filler, s(2), true),
+                (s(2), s(3), false)
+            ]
+        );
+        assert_eq!(rows(&align("", "a\n")), vec![(filler, s(0), true)]);
+
+        assert_eq!(inserted.row_of(DiffSide::Left, 1), 2);
+        assert_eq!(inserted.row_of(DiffSide::Right, 1), 1);
+        assert_eq!(inserted.row_of(DiffSide::Left, 9), 2);
+        // filler scrolls to the line below it
+        assert_eq!(inserted.nearest_line(DiffSide::Left, 1), 1);
+        assert_eq!(inserted.nearest_line(DiffSide::Right, 1), 1);
+        assert_eq!(replaced.nearest_line(DiffSide::Left, 2), 2);
+        // or above, past the end of the side
+        let appended = align("a\n", "a\nb\n");
+        assert_eq!(appended.nearest_line(DiffSide::Left, 1), 0);
+    }
+
+    #[test]
+    fn shortest_edit() {
+        let a: Vec<_> = "abcabba".chars().collect();
+        let b: Vec<_> = "cbabac".chars().collect();
+        let matched = myers(&a, &b);
+        // the classic example has a shortest edit of 5, leaving 4 chars in common
+        assert_eq!(matched.len(), 4);
+        assert!(matched
+            .windows(2)
+            .all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+        assert!(matched.iter().all(|&(x, y)| a[x] == b[y]));
+    }
+
+    #[test]
+    fn diff_contents() {
+        let saved = Contents::from("fn a() {\n    1\n}\n");
+        let contents = Contents::from("fn a() {\n    2\n}\n\nfn b() {}\n");
+        assert_eq!(
+            line_diff(&saved, &contents),
+            vec![
+                LineChange { lines: 1..2, kind: Modified },
+                LineChange { lines: 3..5, kind: Added },
+            ]
+        );
+    }
+}
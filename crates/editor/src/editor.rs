@@ -1,18 +1,33 @@
-use std::path::PathBuf;
-
-use crate::{Buffer, BufferId};
-use slotmap::new_key_type;
-use tore::Point;
+use crate::hex;
+use crate::scroll::ScrollAnimation;
+use crate::search::{Search, SearchMode};
+use crate::snapshot::EditorSnapshot;
+use crate::{
+    resolve_operator, Buffer, BufferId, Motion, Operator, TextObject, UndoMove, WrapLayout,
+    WrapSettings,
+};
+use slotmap::{new_key_type, SecondaryMap};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Instant;
+use tore::{Point, PointRange};
 
 new_key_type! {
     pub struct Id;
+    /// Editors that scroll together, like the two sides of a diff.
+    pub struct GroupId;
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum Mode {
     #[default]
     Normal,
     Insert,
+    /// Typed chars overwrite the ones under the cursors instead of being inserted.
+    Replace,
+    /// Selections cover every line they touch, and motions stretch them from their anchors
+    /// instead of moving the cursors, like vim's `V`.
+    VisualLine,
 }
 
 #[derive(Debug, Clone)]
@@ -29,15 +44,165 @@ pub enum CursorJump {
     StartOfLastWord,
     EndOfNearestWord,
     StartOfNearestWord,
+    StartOfBuffer,
+    /// The start of the last line, like vim's `G`.
+    EndOfBuffer,
+    StartOfLine,
+    /// The last char of the line, or just past it in insert mode.
+    EndOfLine,
+    /// The first non-blank of the next line, like vim's Enter.
+    FirstNonBlankOfNextLine,
 }
 
 #[derive(Debug, Clone)]
 pub enum Command {
     SetMode(Mode),
-    SwapBuffer(BufferId),
     CursorMove(Direction),
     CursorJump(CursorJump),
+    /// Moves each cursor up or down by that many lines, as far as the buffer goes, like PageUp
+    /// and PageDown; left and right leave it be.
+    CursorMoveLines(Direction, usize),
     InsertChar(char),
+    /// Breaks the line at each cursor, indenting the new line like the one it was broken off.
+    InsertNewline,
+    /// Inserts a tab, or spaces up to the next indent stop, at each cursor, as the buffer's
+    /// settings have it.
+    InsertIndent,
+    /// Deletes each selection, or the char before each cursor.
+    DeleteBackward,
+    /// Deletes back from each cursor to the start of the word before it, as `b` finds it, but
+    /// no further than the start of the line, like vim's ctrl-w in insert mode.
+    DeleteWordBackward,
+    /// Deletes back from each cursor to where it entered insert mode, or to the start of the
+    /// line if that was on another, like vim's ctrl-u in insert mode.
+    DeleteToInsertStart,
+    /// Deletes each selection, or the char under each cursor; at the end of a line, its line
+    /// break, joining the next line on.
+    DeleteForward,
+    /// Opens a line below each cursor's line with the same indentation, and enters insert mode
+    /// on it.
+    OpenLineBelow,
+    /// Like `OpenLineBelow`, but above.
+    OpenLineAbove,
+    /// Enters insert mode just after each cursor.
+    AppendAfterCursor,
+    /// Enters insert mode at the end of each cursor's line.
+    AppendAtEndOfLine,
+    /// Replaces the char under each cursor, leaving the cursor on it.
+    ReplaceChar(char),
+    AddCursorAbove,
+    AddCursorBelow,
+    /// Selects every occurrence of the text in the buffer.
+    SelectAllMatches(String),
+    /// Drops every selection but the primary, and collapses it to its cursor, leaving visual
+    /// line mode.
+    CollapseSelections,
+    /// Selects the text object at each cursor, like vim's `viw`.
+    SelectTextObject(TextObject),
+    /// Deletes the text object at each cursor, like vim's `diw`.
+    DeleteTextObject(TextObject),
+    /// Yanks, deletes or changes the text from each cursor to where the motion, made that many
+    /// times, takes it, like vim's `dw`, `y$` or `cc`.
+    Operator(Operator, Motion, usize),
+    /// Marks the primary cursor's position in the buffer, like vim's `m`.
+    SetMark(char),
+    /// Moves to a mark set in this buffer, like vim's `'` and `` ` ``.
+    JumpToMark(char, MarkJump),
+    /// Finds every match of the text and moves to the first after the cursor, like vim's `/`.
+    Search(String),
+    /// Moves to the next match of the last search, or with `true` the one before, wrapping
+    /// around the buffer, like vim's `n` and `N`.
+    SearchNext(bool),
+    /// Forgets the last search, like vim's `:nohl`.
+    ClearSearch,
+    /// Enters visual line mode, keeping each selection to stretch by lines, or leaves it with
+    /// just the primary cursor.
+    SelectLine,
+    /// Deletes every line a selection touches, line breaks and all.
+    DeleteLines,
+    /// Copies every line a selection touches into the register, as whole lines.
+    YankLines,
+    /// Puts the register's text after each cursor, or with `true` at it, like vim's `p` and `P`.
+    /// Whole lines go below the cursor's line, or above it.
+    Paste(Register, bool),
+    /// Indents every non-empty line a selection touches by a level.
+    IndentLines,
+    /// Takes a level of indentation, or what there is of one, off every line a selection
+    /// touches.
+    DedentLines,
+    /// Comments out every non-blank line a selection touches with the line comment token, or
+    /// uncomments them if every one already is, as one change.
+    ToggleComment(&'static str),
+    /// Moves the buffer to another of its undo states, leaving a cursor where the text changed.
+    Undo(UndoMove),
+}
+
+/// Where jumping to a mark lands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkJump {
+    /// The start of the marked line.
+    Line,
+    /// The marked char itself.
+    Exact,
+}
+
+/// Text yanked by an editor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    /// Whether the text is whole lines, each ending in a line break.
+    pub linewise: bool,
+}
+
+/// The brackets and quotes auto-pairing closes, each with its closer.
+const PAIRS: [(char, char); 5] = [('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+/// Something the user should be told about because a command could not be applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notify(pub String);
+
+impl std::fmt::Display for Notify {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The text between `anchor` and `head`; `head` is the end the cursor is on, and where motions
+/// move from. An empty selection is just a cursor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: Point,
+    pub head: Point,
+    /// The column on screen `j` and `k` aim the head for, from before they passed through lines
+    /// too short to reach it; any other motion forgets it.
+    pub goal_column: Option<usize>,
+}
+
+impl Selection {
+    pub fn cursor(point: Point) -> Self {
+        Self::new(point, point)
+    }
+
+    pub fn new(anchor: Point, head: Point) -> Self {
+        Self { anchor, head, goal_column: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    pub fn start(&self) -> Point {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> Point {
+        self.anchor.max(self.head)
+    }
+
+    /// The text selected, from whichever of the anchor and head comes first.
+    pub fn range(&self) -> PointRange {
+        PointRange::new(self.anchor, self.head).normalize()
+    }
 }
 
 #[derive(Debug)]
@@ -45,24 +210,322 @@ pub struct Editor {
     pub mode: Mode,
     pub id: Id,
     pub buffer_id: BufferId,
-    pub cursor: Point,
+    /// The buffer shown before this one, which the editor can swap back to.
+    alternate: Option<BufferId>,
+    /// Where the cursor was in each buffer the editor has swapped away from, to put it back.
+    positions: SecondaryMap<BufferId, Point>,
+    /// Sorted, disjoint, and never empty.
+    selections: Vec<Selection>,
+    primary: usize,
+    /// In replace mode, what each char typed overwrote at every cursor, `None` where it was
+    /// appended past the end of the line; backspace puts them back. Anything but typing and
+    /// backspace forgets them.
+    replaced: Vec<Vec<Option<char>>>,
+    /// How the buffer's lines were last laid out on screen in this editor.
+    layout: WrapLayout,
+    /// How lines are laid out into rows on screen, when they can go on over several; `j`, `k`
+    /// and scrolling then count rows rather than lines.
+    pub wrap: Option<WrapSettings>,
+    /// The matches of the last search, until it's cleared. Shared with snapshots, and only ever
+    /// replaced, never changed in place.
+    search: Option<Arc<Search>>,
+    /// How searches match their query.
+    pub search_mode: SearchMode,
+    /// What was last yanked.
+    register: Option<Register>,
+    /// Whether typing an opening bracket or quote in insert mode types its closer too.
+    pub auto_pairs: bool,
+    /// The pairs auto-pairing typed whose closers the cursors may still be just before, innermost
+    /// last. Anything but typing and backspace forgets them.
+    closers: Vec<(char, char)>,
+    /// Where each cursor was when insert mode was last entered.
+    insert_start: Vec<Point>,
+    /// What's been typed since insert mode was last entered.
+    typed: String,
+    /// What was typed the last time insert mode was left, like vim's `.` register.
+    last_inserted: Option<String>,
+    /// The buffer's version after the last edit made since insert or replace mode was entered;
+    /// the next one joins its undo state if nothing else has edited the buffer since.
+    undo_join: Option<u64>,
+    /// The group of editors this one scrolls together with, if any.
+    pub scroll_lock_group: Option<GroupId>,
+    /// The row the view is scrolled to, once it's been laid out on screen: a line, unless `wrap`
+    /// has lines go on over several rows.
+    top: Option<usize>,
+    /// The scroll to `top` still being drawn, if it's smooth.
+    scroll: Option<ScrollAnimation>,
 }
 
 impl Editor {
     pub fn new(id: Id, buffer_id: BufferId) -> Self {
-        Self { id, mode: Mode::default(), buffer_id, cursor: Default::default() }
+        let selections = vec![Selection::default()];
+        let (replaced, search, register) = (vec![], None, None);
+        let layout = WrapLayout::default();
+        let (mode, alternate) = Default::default();
+        Self {
+            id,
+            mode,
+            buffer_id,
+            alternate,
+            positions: SecondaryMap::new(),
+            selections,
+            primary: 0,
+            replaced,
+            layout,
+            wrap: None,
+            search,
+            search_mode: SearchMode::Plain,
+            register,
+            auto_pairs: true,
+            closers: vec![],
+            insert_start: vec![],
+            typed: String::new(),
+            last_inserted: None,
+            undo_join: None,
+            scroll_lock_group: None,
+            top: None,
+            scroll: None,
+        }
+    }
+
+    /// The primary cursor: the one the screen follows.
+    pub fn cursor(&self) -> Point {
+        self.selections[self.primary].head
+    }
+
+    /// Drops every selection, leaving a single cursor at `point`.
+    pub fn set_cursor(&mut self, point: Point) {
+        self.closers.clear();
+        self.selections = vec![Selection::cursor(point)];
+        self.primary = 0;
+    }
+
+    pub fn selections(&self) -> &[Selection] {
+        &self.selections
+    }
+
+    pub fn primary(&self) -> usize {
+        self.primary
+    }
+
+    pub fn layout(&mut self) -> &mut WrapLayout {
+        &mut self.layout
+    }
+
+    /// The screen row `point` is on: its line, or with `wrap` set, the row of its line and column
+    /// counting every row the lines above go on over.
+    pub fn row_of(&mut self, buffer: &Buffer, point: Point) -> usize {
+        let Some(settings) = self.wrap else {
+            return point.line;
+        };
+        let row = self.layout.row_of_line(buffer, settings, point.line);
+        let line = self.layout.line(buffer, settings, point.line);
+        row + line.row_of_column(point.column)
     }
 
-    pub fn swap_buffer(&mut self, buffer_id: BufferId) {
-        self.buffer_id = buffer_id;
+    /// How many screen rows the buffer's lines take.
+    pub fn total_rows(&mut self, buffer: &Buffer) -> usize {
+        match self.wrap {
+            Some(settings) => self.layout.total_rows(buffer, settings),
+            None => buffer.contents.len_lines(),
+        }
     }
 
-    pub fn command(&mut self, buffer: &mut Buffer, command: Command) {
+    /// Follows the view to `top`, the row it's scrolled to. With `smooth`, a jump of more than
+    /// a line scrolls there over a few frames, from wherever it's drawn at `now`; the first
+    /// `top` after the editor shows a buffer never does.
+    pub fn scroll_to(&mut self, top: usize, now: Instant, smooth: bool) {
+        let Some(last) = self.top.replace(top) else {
+            return;
+        };
+        if last == top {
+            return;
+        }
+        let from = self.shown_top(now).unwrap_or(last);
+        self.scroll = (smooth && last.abs_diff(top) > 1 && from != top)
+            .then_some(ScrollAnimation { from, to: top, start: now });
+    }
+
+    /// The line the view is drawn scrolled to at `now`, partway through a smooth scroll, or None
+    /// when it's where it should be.
+    pub fn shown_top(&self, now: Instant) -> Option<usize> {
+        self.scroll.map(|scroll| scroll.sample(now))
+    }
+
+    /// The editor on `buffer`, the one it shows, as it is now: what a frame draws of them, to
+    /// draw while they go on changing.
+    pub fn snapshot(&self, buffer: &Buffer) -> EditorSnapshot {
+        EditorSnapshot {
+            id: self.id,
+            mode: self.mode,
+            buffer: buffer.snapshot(),
+            selections: self.selections.as_slice().into(),
+            primary: self.primary,
+            search: self.search.clone(),
+            scroll: self.scroll,
+        }
+    }
+
+    pub fn is_scrolling(&self) -> bool {
+        self.scroll.is_some()
+    }
+
+    /// Ends a smooth scroll once its last frame is due, or right away with `snap`.
+    pub fn finish_scroll(&mut self, now: Instant, snap: bool) {
+        if snap || self.scroll.is_some_and(|scroll| scroll.is_done(now)) {
+            self.scroll = None;
+        }
+    }
+
+    /// Leaving insert or replace mode pulls every cursor back onto the char it was after, like
+    /// vim. Leaving visual line mode this way keeps the selections, as charwise ones.
+    pub fn set_mode(&mut self, buffer: &Buffer, mode: Mode) {
+        self.replaced.clear();
+        if matches!(self.mode, Mode::Insert | Mode::Replace) && mode == Mode::Normal {
+            self.move_heads(|head| {
+                let max = buffer.contents.max_cursor_column(head.line, mode);
+                Point { column: head.column.saturating_sub(1).min(max), ..head }
+            });
+        }
+        if mode == Mode::Insert && self.mode != Mode::Insert {
+            self.insert_start = self.selections.iter().map(|s| s.head).collect();
+            self.typed.clear();
+        }
+        if self.mode == Mode::Insert && mode != Mode::Insert {
+            self.last_inserted = Some(std::mem::take(&mut self.typed));
+        }
+        if mode != self.mode {
+            self.undo_join = None;
+        }
+        self.mode = mode;
+    }
+
+    /// Enters insert mode with each cursor moved to the column `column` picks, clamped to the end
+    /// of its line.
+    fn append(&mut self, buffer: &Buffer, column: impl Fn(Point) -> usize) {
+        self.move_heads(|head| {
+            let max = buffer.contents.max_cursor_column(head.line, Mode::Insert);
+            Point { column: column(head).min(max), ..head }
+        });
+        self.set_mode(buffer, Mode::Insert);
+    }
+
+    /// Shows `buffer` in place of the one the editor shows, with a cursor where it was when the
+    /// editor last showed it, or at the start, kept within the text. Selections, and visual line,
+    /// insert or replace mode, are left behind with the buffer the editor swaps away from.
+    pub fn swap_buffer(&mut self, buffer: &Buffer) {
+        if buffer.id == self.buffer_id {
+            return;
+        }
+        self.positions.insert(self.buffer_id, self.cursor());
+        self.alternate = Some(self.buffer_id);
+        self.buffer_id = buffer.id;
+        self.search = None;
+        self.undo_join = None;
+        self.replaced.clear();
+        self.insert_start.clear();
+        self.mode = Mode::Normal;
+        // the lines laid out were the other buffer's, and so was the scroll
+        self.layout = WrapLayout::default();
+        (self.top, self.scroll) = (None, None);
+
+        let cursor = self.positions.get(buffer.id).copied().unwrap_or_default();
+        let line = cursor.line.min(buffer.contents.len_lines() - 1);
+        let max = buffer.contents.max_cursor_column(line, self.mode);
+        self.set_cursor(Point { line, column: cursor.column.min(max) });
+    }
+
+    /// The buffer shown before the one the editor shows now, if it has shown another.
+    pub fn alternate(&self) -> Option<BufferId> {
+        self.alternate
+    }
+
+    pub fn register(&self) -> Option<&Register> {
+        self.register.as_ref()
+    }
+
+    /// What was typed the last time insert mode was left, if it ever has been.
+    pub fn last_inserted(&self) -> Option<&str> {
+        self.last_inserted.as_deref()
+    }
+
+    /// The matches of the last search, as they were when last found.
+    pub fn search(&self) -> Option<&Search> {
+        self.search.as_deref()
+    }
+
+    /// Counting from 1, which match of the last search the primary cursor is on, or else the
+    /// last one before it; None without a search, or where the matches around it weren't kept.
+    pub fn search_index(&self, buffer: &Buffer) -> Option<usize> {
+        let search = self.search.as_ref()?;
+        let at = buffer
+            .contents
+            .try_point_to_char_offset(self.cursor())
+            .ok()?;
+        search.position(at)
+    }
+
+    /// Finds the matches of the last search again, around the primary cursor, if the buffer has
+    /// been edited since they were.
+    pub fn refresh_search(&mut self, buffer: &Buffer) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        if search.version == buffer.version {
+            return;
+        }
+        let at = buffer.contents.try_point_to_char_offset(self.cursor());
+        let at = at.unwrap_or(buffer.contents.len_chars());
+        let search = search.again(&buffer.contents, buffer.version, at);
+        self.search = Some(Arc::new(search));
+    }
+
+    /// Applies `command`, returning the byte range of the buffer it edited, if any. A command that
+    /// cannot be applied leaves the editor unchanged and returns what to tell the user.
+    pub fn command(
+        &mut self,
+        buffer: &mut Buffer,
+        command: Command,
+    ) -> Result<Option<Range<usize>>, Notify> {
         debug_assert!(buffer.id == self.buffer_id);
+        if !matches!(command, Command::InsertChar(_) | Command::DeleteBackward) {
+            self.closers.clear();
+            self.replaced.clear();
+        }
+        if self.mode == Mode::Insert && !buffer.read_only {
+            match command {
+                Command::InsertChar(c) => self.typed.push(c),
+                Command::InsertNewline => self.typed.push('\n'),
+                Command::InsertIndent => self.typed.push('\t'),
+                Command::DeleteBackward => drop(self.typed.pop()),
+                _ => {}
+            }
+        }
+        if buffer.is_binary() {
+            // a binary buffer is shown as rows of bytes, and moved around in by them
+            let contents = &buffer.contents;
+            let offset = contents
+                .try_point_to_char_offset(self.cursor())
+                .unwrap_or(0);
+            if let Some(offset) = hex::motion(&command, offset, contents.len_chars()) {
+                self.set_cursor(contents.char_offset_to_point(offset));
+                return Ok(None);
+            }
+        }
         match command {
-            Command::SwapBuffer(buffer_id) => self.swap_buffer(buffer_id),
-            Command::InsertChar(c) => self.insert_char(buffer, c),
-            Command::SetMode(mode) => self.mode = mode,
+            Command::InsertChar(c) => return self.insert_char(buffer, c),
+            Command::InsertNewline => return self.insert_newline(buffer),
+            Command::InsertIndent => return self.insert_indent(buffer),
+            Command::DeleteBackward => return self.delete_backward(buffer),
+            Command::DeleteWordBackward => return self.delete_word_backward(buffer),
+            Command::DeleteToInsertStart => return self.delete_to_insert_start(buffer),
+            Command::DeleteForward => return self.delete_forward(buffer),
+            Command::OpenLineBelow => return self.open_line(buffer, Direction::Down),
+            Command::OpenLineAbove => return self.open_line(buffer, Direction::Up),
+            Command::AppendAfterCursor => self.append(buffer, |head| head.column + 1),
+            Command::AppendAtEndOfLine => self.append(buffer, |_| usize::MAX),
+            Command::ReplaceChar(c) => return self.replace_char(buffer, c),
+            Command::SetMode(mode) => self.set_mode(buffer, mode),
             Command::CursorMove(direction) => match direction {
                 Direction::Up => self.cursor_move_up(buffer),
                 Direction::Down => self.cursor_move_down(buffer),
@@ -74,13 +537,2135 @@ impl Editor {
                 CursorJump::StartOfLastWord => self.cursor_jump_start_of_last_word(buffer),
                 CursorJump::EndOfNearestWord => self.cursor_jump_end_of_nearest_word(buffer),
                 CursorJump::StartOfNearestWord => self.cursor_jump_start_of_nearest_word(buffer),
+                CursorJump::StartOfBuffer => self.move_cursors(|_| Point::default()),
+                CursorJump::EndOfBuffer => self.cursor_jump_end_of_buffer(buffer),
+                CursorJump::StartOfLine => self.cursor_jump_line_zero(buffer),
+                CursorJump::EndOfLine => self.cursor_jump_end_of_line(buffer),
+                CursorJump::FirstNonBlankOfNextLine => {
+                    self.cursor_jump_first_non_blank_of_next_line(buffer)
+                }
             },
+            Command::CursorMoveLines(direction, lines) => {
+                self.cursor_move_lines(buffer, direction, lines)
+            }
+            Command::AddCursorAbove => self.add_cursor(buffer, Direction::Up),
+            Command::AddCursorBelow => self.add_cursor(buffer, Direction::Down),
+            Command::SelectAllMatches(text) => self.select_all_matches(buffer, &text)?,
+            Command::CollapseSelections => self.collapse_selections(buffer),
+            Command::SelectTextObject(object) => self.select_text_object(buffer, object),
+            Command::DeleteTextObject(object) => return self.delete_text_object(buffer, object),
+            Command::Operator(op, motion, count) => return self.operate(buffer, op, motion, count),
+            Command::SetMark(mark) => buffer.set_mark(mark, self.cursor()),
+            Command::JumpToMark(mark, jump) => self.jump_to_mark(buffer, mark, jump)?,
+            Command::Search(query) => self.search_for(buffer, query)?,
+            Command::SearchNext(backward) => self.search_next(buffer, backward)?,
+            Command::ClearSearch => self.search = None,
+            Command::SelectLine if self.mode == Mode::VisualLine => {
+                self.collapse_selections(buffer)
+            }
+            Command::SelectLine => self.set_mode(buffer, Mode::VisualLine),
+            Command::DeleteLines => return self.delete_lines(buffer),
+            Command::YankLines => self.yank_lines(buffer),
+            Command::Paste(register, before) => return self.paste(buffer, register, before),
+            Command::IndentLines => return self.indent_lines(buffer, true),
+            Command::DedentLines => return self.indent_lines(buffer, false),
+            Command::ToggleComment(token) => return self.toggle_comment(buffer, token),
+            Command::Undo(to) => return self.undo(buffer, to),
         };
+        Ok(None)
     }
 
-    pub fn insert_char(&mut self, buffer: &mut Buffer, c: char) {
-        let offset = buffer.contents.point_to_char_offset(self.cursor);
-        self.cursor.move_next_column();
-        buffer.contents.insert_char(offset, c);
+    /// Drops every selection but the primary, collapsing it to its cursor, and leaves visual line
+    /// mode. A charwise selection's cursor can be just past the end of its line; it's pulled back.
+    fn collapse_selections(&mut self, buffer: &Buffer) {
+        let cursor = self.cursor();
+        let max = buffer.contents.max_cursor_column(cursor.line, self.mode);
+        self.set_cursor(Point { column: cursor.column.min(max), ..cursor });
+        if self.mode == Mode::VisualLine {
+            self.mode = Mode::Normal;
+        }
+    }
+
+    fn jump_to_mark(&mut self, buffer: &Buffer, mark: char, jump: MarkJump) -> Result<(), Notify> {
+        let Some(point) = buffer.mark(mark) else {
+            return Err(Notify(format!("mark '{}' is not set", mark)));
+        };
+        let point = match jump {
+            MarkJump::Line => Point { column: 0, ..point },
+            MarkJump::Exact => {
+                let max = buffer.contents.max_cursor_column(point.line, self.mode);
+                Point { column: point.column.min(max), ..point }
+            }
+        };
+        self.set_cursor(point);
+        Ok(())
+    }
+
+    fn search_for(&mut self, buffer: &Buffer, query: String) -> Result<(), Notify> {
+        if query.is_empty() {
+            return Err(Notify("search needs something to search for".to_string()));
+        }
+        let at = buffer.contents.point_to_char_offset(self.cursor());
+        // a binary buffer is searched for the bytes a query of hex pairs spells out
+        let pattern = if buffer.is_binary() {
+            hex::parse_hex_bytes(&query)
+        } else {
+            None
+        };
+        let search = match pattern {
+            Some(pattern) => Search::new(&buffer.contents, buffer.version, &pattern, at),
+            None => {
+                let mode = self.search_mode;
+                Search::with_mode(&buffer.contents, buffer.version, &query, mode, at)
+                    .map_err(Notify)?
+            }
+        };
+        if search.total() == 0 {
+            return Err(Notify(format!("no matches for {:?}", query)));
+        }
+        self.search = Some(Arc::new(search));
+        self.search_next(buffer, false)
+    }
+
+    /// Moves the primary cursor to the start of the next match, or the one before, finding the
+    /// matches again first if the buffer changed or the cursor left the ones kept.
+    fn search_next(&mut self, buffer: &Buffer, backward: bool) -> Result<(), Notify> {
+        let Some(search) = self.search.take() else {
+            return Err(Notify("no search to repeat".to_string()));
+        };
+        let at = buffer.contents.point_to_char_offset(self.cursor());
+        let query = search.query.clone();
+        let mut found = if search.version == buffer.version && search.covers(at) {
+            Arc::unwrap_or_clone(search)
+        } else {
+            search.again(&buffer.contents, buffer.version, at)
+        };
+        let next = found.next(at, backward);
+        if next.is_none() && found.is_truncated() {
+            // wrapping around needs the matches kept at the other end of the buffer
+            found = found.again(
+                &buffer.contents,
+                buffer.version,
+                if backward {
+                    buffer.contents.len_chars()
+                } else {
+                    0
+                },
+            );
+        }
+        let next = next.or_else(|| {
+            let wrapped = if backward {
+                found.matches().last()
+            } else {
+                found.matches().first()
+            };
+            wrapped.cloned()
+        });
+        self.search = Some(Arc::new(found));
+        let Some(next) = next else {
+            return Err(Notify(format!("no matches for {:?}", query)));
+        };
+        let point = buffer.contents.char_offset_to_point(next.start);
+        // any byte of a binary buffer can be the cursor's, line breaks and all
+        let max = if buffer.is_binary() {
+            point.column
+        } else {
+            buffer.contents.max_cursor_column(point.line, self.mode)
+        };
+        self.set_cursor(Point { column: point.column.min(max), ..point });
+        Ok(())
+    }
+
+    pub fn insert_char(
+        &mut self,
+        buffer: &mut Buffer,
+        c: char,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        if self.mode == Mode::Replace {
+            return self.overwrite_char(buffer, c);
+        }
+        let closing = self.closers.last().is_some_and(|(_, closer)| *closer == c);
+        if closing && self.all_around(buffer, |_, after| after == Some(c)) {
+            self.closers.pop();
+            self.move_heads(|head| Point { column: head.column + 1, ..head });
+            return Ok(None);
+        }
+        let pair = PAIRS.iter().find(|(opener, _)| *opener == c).copied();
+        if let Some((_, closer)) = pair.filter(|_| self.auto_pairs && self.mode == Mode::Insert) {
+            // an apostrophe in a word isn't a quote
+            let quote = closer == c;
+            let pairs = !quote
+                || self.all_around(buffer, |before, _| !before.is_some_and(char::is_alphanumeric));
+            if pairs {
+                let text = format!("{}{}", c, closer);
+                let changed = self.edit(buffer, "insert", |range| Some((range, text.as_str())))?;
+                self.move_heads(|head| Point { column: head.column - 1, ..head });
+                self.closers.push((c, closer));
+                return Ok(changed);
+            }
+        }
+        let mut text = [0; 4];
+        let text = &*c.encode_utf8(&mut text);
+        self.edit(buffer, "insert", |range| Some((range, text)))
+    }
+
+    /// Whether every selection is a cursor, and `f` holds of the chars just before and just
+    /// after each.
+    fn all_around(&self, buffer: &Buffer, f: impl Fn(Option<char>, Option<char>) -> bool) -> bool {
+        let contents = &buffer.contents;
+        self.selections.iter().all(|selection| {
+            let Ok(offset) = contents.try_point_to_char_offset(selection.head) else {
+                return false;
+            };
+            let before = offset
+                .checked_sub(1)
+                .and_then(|offset| contents.get_char(offset));
+            selection.is_empty() && f(before, contents.get_char(offset))
+        })
+    }
+
+    fn insert_newline(&mut self, buffer: &mut Buffer) -> Result<Option<Range<usize>>, Notify> {
+        // between braces, the closing one goes down a line too, with the cursor on an indented
+        // line between them
+        let expand =
+            self.auto_pairs && self.all_around(buffer, |b, a| b == Some('{') && a == Some('}'));
+        let settings = buffer.settings().clone();
+        let texts: Vec<String> = self
+            .selections
+            .iter()
+            .map(|selection| {
+                // only the indentation before the cursor carries over
+                let start = selection.start();
+                let indent: String = buffer
+                    .contents
+                    .line(start.line)
+                    .chars()
+                    .take(start.column)
+                    .take_while(|c| matches!(c, ' ' | '\t'))
+                    .collect();
+                if !expand {
+                    return format!("\n{}", indent);
+                }
+                let inner = settings.indent(indent.chars().count());
+                format!("\n{}{}\n{}", indent, inner, indent)
+            })
+            .collect();
+        let mut texts = texts.iter();
+        let changed =
+            self.edit(buffer, "insert", |range| texts.next().map(|text| (range, text.as_str())))?;
+        if expand {
+            // each cursor is after the indentation of its closing brace's line
+            self.move_heads(|head| {
+                let column = head.column + settings.indent(head.column).chars().count();
+                Point { line: head.line - 1, column }
+            });
+        }
+        Ok(changed)
+    }
+
+    fn insert_indent(&mut self, buffer: &mut Buffer) -> Result<Option<Range<usize>>, Notify> {
+        let settings = buffer.settings();
+        let texts: Vec<String> = self
+            .selections
+            .iter()
+            .map(|selection| settings.indent(selection.start().column))
+            .collect();
+        let mut texts = texts.iter();
+        self.edit(buffer, "insert", |range| texts.next().map(|text| (range, text.as_str())))
+    }
+
+    pub fn delete_backward(&mut self, buffer: &mut Buffer) -> Result<Option<Range<usize>>, Notify> {
+        if self.mode == Mode::Replace {
+            return self.restore_replaced(buffer);
+        }
+        if let Some(&(opener, closer)) = self.closers.last() {
+            if self.all_around(buffer, |b, a| b == Some(opener) && a == Some(closer)) {
+                self.closers.pop();
+                return self
+                    .edit(buffer, "delete", |range| Some((range.start - 1..range.end + 1, "")));
+            }
+        }
+        self.edit(buffer, "delete", |range| {
+            if !range.is_empty() {
+                Some((range, ""))
+            } else if range.start == 0 {
+                None
+            } else {
+                Some((range.start - 1..range.end, ""))
+            }
+        })
+    }
+
+    fn delete_word_backward(
+        &mut self,
+        buffer: &mut Buffer,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        let contents = &buffer.contents;
+        let starts = self.selections.iter().map(|selection| {
+            let head = selection.head;
+            let word = crate::movement::cursor_jump_start_of_nearest_word(buffer, head);
+            let column = if word.line == head.line {
+                word.column
+            } else {
+                0
+            };
+            contents.line_to_char(head.line) + column
+        });
+        let starts = starts.collect();
+        self.delete_back_to(buffer, starts)
+    }
+
+    fn delete_to_insert_start(
+        &mut self,
+        buffer: &mut Buffer,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        // cursors added or merged since insert mode was entered can't tell where they were
+        let inserted = if self.insert_start.len() == self.selections.len() {
+            &self.insert_start[..]
+        } else {
+            &[]
+        };
+        let starts = self.selections.iter().enumerate().map(|(i, selection)| {
+            let head = selection.head;
+            let column = match inserted.get(i) {
+                Some(start) if start.line == head.line => start.column.min(head.column),
+                _ => 0,
+            };
+            buffer.contents.line_to_char(head.line) + column
+        });
+        let starts = starts.collect();
+        self.delete_back_to(buffer, starts)
+    }
+
+    /// Deletes from the char offset `starts` has for each cursor up to the cursor, or deletes
+    /// each selection.
+    fn delete_back_to(
+        &mut self,
+        buffer: &mut Buffer,
+        starts: Vec<usize>,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        let mut starts = starts.into_iter();
+        self.edit(buffer, "delete", |range| {
+            let start = starts.next()?;
+            if range.is_empty() {
+                (start < range.start).then_some((start..range.start, ""))
+            } else {
+                Some((range, ""))
+            }
+        })
+    }
+
+    fn delete_forward(&mut self, buffer: &mut Buffer) -> Result<Option<Range<usize>>, Notify> {
+        let contents = &buffer.contents;
+        // how many chars are under each cursor: a CRLF goes as one
+        let lens: Vec<usize> = self
+            .selections
+            .iter()
+            .map(|selection| {
+                let Ok(offset) = contents.try_point_to_char_offset(selection.head) else {
+                    return 0;
+                };
+                match (contents.get_char(offset), contents.get_char(offset + 1)) {
+                    (Some('\r'), Some('\n')) => 2,
+                    (Some(_), _) => 1,
+                    (None, _) => 0,
+                }
+            })
+            .collect();
+        let mut lens = lens.into_iter();
+        self.edit(buffer, "delete", |range| {
+            let len = lens.next()?;
+            if !range.is_empty() {
+                Some((range, ""))
+            } else if len == 0 {
+                None
+            } else {
+                Some((range.start..range.start + len, ""))
+            }
+        })
+    }
+
+    /// Replaces the char under each cursor with `c`, like vim's `r`. Cursors at the end of a line
+    /// have nothing under them and are left alone.
+    fn replace_char(
+        &mut self,
+        buffer: &mut Buffer,
+        c: char,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        let (selections, primary) = (self.selections.clone(), self.primary);
+        let planned: Vec<_> = selections
+            .iter()
+            .map(|s| {
+                s.head.column
+                    < buffer
+                        .contents
+                        .line_len_chars_excluding_terminator(s.head.line)
+            })
+            .collect();
+        let mut text = [0; 4];
+        let text = &*c.encode_utf8(&mut text);
+        let mut planned = planned.into_iter();
+        let changed = self.edit(buffer, "replace", |range| match planned.next() {
+            Some(true) => Some((range.start..range.start + 1, text)),
+            _ => None,
+        })?;
+        // one char for another moves nothing, so every cursor stays where it was
+        self.selections = selections
+            .iter()
+            .map(|s| Selection::cursor(s.head))
+            .collect();
+        self.primary = primary;
+        self.merge_selections();
+        Ok(changed)
+    }
+
+    /// Overwrites the char under each cursor with `c`, or appends it at the end of a line, and
+    /// remembers what was there for `restore_replaced`.
+    fn overwrite_char(
+        &mut self,
+        buffer: &mut Buffer,
+        c: char,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        let overwritten: Vec<_> = self
+            .selections
+            .iter()
+            .map(|selection| {
+                let head = selection.head;
+                let len = buffer
+                    .contents
+                    .line_len_chars_excluding_terminator(head.line);
+                (head.column < len).then(|| {
+                    let offset = buffer.contents.point_to_char_offset(head);
+                    buffer.contents.char(offset)
+                })
+            })
+            .collect();
+        let mut text = [0; 4];
+        let text = &*c.encode_utf8(&mut text);
+        let mut planned = overwritten.iter();
+        let changed = self.edit(buffer, "replace", |range| match planned.next() {
+            Some(Some(_)) => Some((range.start..range.start + 1, text)),
+            _ => Some((range.start..range.start, text)),
+        })?;
+        self.replaced.push(overwritten);
+        Ok(changed)
+    }
+
+    /// Selects the text object at each cursor; cursors with nothing to select are left alone.
+    fn select_text_object(&mut self, buffer: &Buffer, object: TextObject) {
+        let point = |byte| {
+            let offset = buffer.contents.byte_to_char(byte);
+            buffer.contents.char_offset_to_point(offset)
+        };
+        for selection in self.selections.iter_mut() {
+            if let Some(range) = object.range(&buffer.contents, selection.head) {
+                *selection = Selection::new(point(range.start), point(range.end));
+            }
+        }
+        self.merge_selections();
+    }
+
+    /// Deletes the text object at each cursor, leaving the cursor where it started.
+    fn delete_text_object(
+        &mut self,
+        buffer: &mut Buffer,
+        object: TextObject,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        let saved = (self.selections.clone(), self.primary);
+        // only the objects are deleted, not whatever was selected before
+        self.move_heads(|head| head);
+        self.select_text_object(buffer, object);
+        let changed = self.edit(buffer, "delete", |range| Some((range, "")));
+        if changed.is_err() {
+            (self.selections, self.primary) = saved;
+            return changed;
+        }
+        let mode = self.mode;
+        self.move_heads(|head| {
+            let max = buffer.contents.max_cursor_column(head.line, mode);
+            Point { column: head.column.min(max), ..head }
+        });
+        changed
+    }
+
+    /// Applies `op` to the text `motion` takes from each cursor, putting it in the register. Ranges
+    /// that overlap are taken once. Yanking leaves each cursor at the start of its range, or for
+    /// whole lines on the first of them; deleting leaves it where the text was, or for whole lines
+    /// on the first non-blank of the line that takes their place. Changing whole lines keeps the
+    /// indentation of the first and the line break of the last, and the text typed after changing
+    /// undoes along with what was deleted.
+    fn operate(
+        &mut self,
+        buffer: &mut Buffer,
+        op: Operator,
+        motion: Motion,
+        count: usize,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        let contents = &buffer.contents;
+        let mut linewise = false;
+        let mut resolved: Vec<_> = self
+            .selections
+            .iter()
+            .enumerate()
+            .map(|(i, selection)| {
+                let (range, lines) = resolve_operator(op, &motion, count, buffer, selection.head);
+                linewise = lines;
+                let cursor = if lines {
+                    let line = contents.char_to_line(range.start);
+                    let max = contents.max_cursor_column(line, Mode::Normal);
+                    Point { line, column: selection.head.column.min(max) }
+                } else {
+                    contents.char_offset_to_point(range.start)
+                };
+                (range, cursor, i == self.primary)
+            })
+            .collect();
+        resolved.sort_by_key(|(range, _, _)| range.start);
+        let mut merged: Vec<(Range<usize>, Point, bool)> = Vec::with_capacity(resolved.len());
+        for (range, cursor, primary) in resolved {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.0.end => {
+                    last.0.end = last.0.end.max(range.end);
+                    last.2 |= primary;
+                }
+                _ => merged.push((range, cursor, primary)),
+            }
+        }
+        let empty = merged.iter().all(|(range, _, _)| range.is_empty());
+        if empty && !linewise && op != Operator::Change {
+            return Ok(None);
+        }
+
+        let mut text = String::new();
+        for (i, (range, _, _)) in merged.iter().enumerate() {
+            if i > 0 && !linewise {
+                text.push('\n');
+            }
+            text.extend(contents.slice(range.clone()).chars());
+            if linewise && !text.ends_with('\n') {
+                text.push('\n');
+            }
+        }
+        let register = Register { text, linewise };
+        let primary = merged
+            .iter()
+            .position(|(_, _, primary)| *primary)
+            .unwrap_or(0);
+        if op == Operator::Yank {
+            self.register = Some(register);
+            self.selections = merged
+                .iter()
+                .map(|(_, cursor, _)| Selection::cursor(*cursor))
+                .collect();
+            self.primary = primary;
+            self.merge_selections();
+            return Ok(None);
+        }
+
+        let line_end = |line: usize| {
+            contents.line_to_char(line) + contents.line_len_chars_excluding_terminator(line)
+        };
+        let saved = (self.selections.clone(), self.primary);
+        self.selections = merged
+            .iter()
+            .map(|(range, _, _)| {
+                let (mut start, mut end) = (range.start, range.end);
+                let first = contents.char_to_line(start);
+                let ends_line = end > start && contents.char(end - 1) == '\n';
+                if linewise && op == Operator::Change {
+                    start += contents.leading_whitespace_len(first);
+                    end = line_end(contents.char_to_line(end.saturating_sub(1))).max(start);
+                } else if linewise && !ends_line && first > 0 {
+                    // the last line has no line break of its own, so the one before it goes
+                    start = line_end(first - 1);
+                }
+                let point = |offset| contents.char_offset_to_point(offset);
+                Selection::new(point(start), point(end))
+            })
+            .collect();
+        self.primary = primary;
+        let changed = self.edit(buffer, op.name(), |range| Some((range, "")));
+        if changed.is_err() {
+            (self.selections, self.primary) = saved;
+            return changed;
+        }
+        self.register = Some(register);
+        if op == Operator::Change {
+            self.set_mode(buffer, Mode::Insert);
+            self.undo_join = Some(buffer.version);
+            return changed;
+        }
+        let contents = &buffer.contents;
+        self.move_heads(|head| {
+            let max = contents.max_cursor_column(head.line, Mode::Normal);
+            let column = if linewise {
+                contents.leading_whitespace_len(head.line)
+            } else {
+                head.column
+            };
+            Point { column: column.min(max), ..head }
+        });
+        changed
+    }
+
+    /// Undoes the last char typed in replace mode at each cursor, putting back what it
+    /// overwrote. With nothing left to restore, backspace only moves the cursors left.
+    fn restore_replaced(&mut self, buffer: &mut Buffer) -> Result<Option<Range<usize>>, Notify> {
+        let restore = match self.replaced.pop() {
+            Some(restore) if restore.len() == self.selections.len() => restore,
+            _ => {
+                self.replaced.clear();
+                self.move_heads(|head| Point { column: head.column.saturating_sub(1), ..head });
+                return Ok(None);
+            }
+        };
+        let texts: Vec<_> = restore
+            .iter()
+            .map(|c| c.map(String::from).unwrap_or_default())
+            .collect();
+        let mut planned = texts.iter();
+        let changed = self.edit(buffer, "restore", |range| {
+            let text = planned.next()?;
+            (range.start > 0).then(|| (range.start - 1..range.start, text.as_str()))
+        });
+        if changed.is_err() {
+            self.replaced.push(restore);
+            return changed;
+        }
+        // a restored char ends up before the cursor, which goes back onto it
+        if self.selections.len() == restore.len() {
+            for (selection, c) in self.selections.iter_mut().zip(&restore) {
+                if c.is_some() {
+                    selection.head.column = selection.head.column.saturating_sub(1);
+                    selection.anchor = selection.head;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Opens a line above or below each cursor's line, indented like it, and enters insert mode
+    /// with a single cursor per new line, after its indentation. Opening the lines and typing on
+    /// them undo as one.
+    fn open_line(
+        &mut self,
+        buffer: &mut Buffer,
+        direction: Direction,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        let below = matches!(direction, Direction::Down);
+        let saved = (self.selections.clone(), self.primary);
+        // one cursor per line, where the line break goes
+        self.move_heads(|head| {
+            if !below {
+                return Point { line: head.line, column: 0 };
+            }
+            let column = buffer
+                .contents
+                .line_len_chars_excluding_terminator(head.line);
+            Point { line: head.line, column }
+        });
+        let texts: Vec<_> = self
+            .selections
+            .iter()
+            .map(|selection| {
+                let line = buffer.contents.line(selection.head.line);
+                let indent: String = line
+                    .chars()
+                    .take_while(|c| matches!(c, ' ' | '\t'))
+                    .collect();
+                if below {
+                    format!("\n{}", indent)
+                } else {
+                    format!("{}\n", indent)
+                }
+            })
+            .collect();
+
+        let mut texts = texts.iter();
+        let changed =
+            self.edit(buffer, "open line", |range| texts.next().map(|text| (range, text.as_str())));
+        if changed.is_err() {
+            (self.selections, self.primary) = saved;
+            return changed;
+        }
+        if !below {
+            // the cursors end up past the line break, on the line they were opened above
+            self.move_heads(|head| {
+                let line = head.line - 1;
+                let column = buffer.contents.line_len_chars_excluding_terminator(line);
+                Point { line, column }
+            });
+        }
+        self.set_mode(buffer, Mode::Insert);
+        // what's typed on the new lines undoes along with opening them
+        self.undo_join = Some(buffer.version);
+        changed
+    }
+
+    /// Applies the edit `f` makes of each selection's char range to the buffer in one pass,
+    /// leaving a cursor at the end of each. Either every edit is made or none is.
+    fn edit<'t>(
+        &mut self,
+        buffer: &mut Buffer,
+        op: &str,
+        mut f: impl FnMut(Range<usize>) -> Option<(Range<usize>, &'t str)>,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        if buffer.read_only {
+            return Err(Notify(format!("cannot {}: buffer is read-only", op)));
+        }
+        let mut edits = Vec::with_capacity(self.selections.len());
+        for selection in &self.selections {
+            let PointRange { start, end } = selection.range();
+            let range = buffer
+                .contents
+                .try_point_to_char_offset(start)
+                .and_then(|start| Ok(start..buffer.contents.try_point_to_char_offset(end)?));
+            let range = range.map_err(|err| {
+                let (line, column) = (selection.head.line + 1, selection.head.column + 1);
+                Notify(format!("cannot {} at {}:{}: {}", op, line, column, err))
+            })?;
+            edits.push(f(range.clone()).unwrap_or((range.start..range.start, "")));
+        }
+        if edits
+            .iter()
+            .all(|(range, text)| range.is_empty() && text.is_empty())
+        {
+            return Ok(None);
+        }
+
+        let inserting = matches!(self.mode, Mode::Insert | Mode::Replace);
+        if inserting && self.undo_join == Some(buffer.version) {
+            buffer.join_undo();
+        }
+        let (ends, changed) = buffer
+            .apply_edits(&edits)
+            .map_err(|err| Notify(format!("cannot {}: {}", op, err)))?;
+        self.undo_join = inserting.then_some(buffer.version);
+        self.layout.edited(buffer, changed.clone());
+        for (selection, end) in self.selections.iter_mut().zip(ends) {
+            *selection = Selection::cursor(buffer.contents.char_offset_to_point(end));
+        }
+        self.merge_selections();
+        Ok(Some(changed))
+    }
+
+    /// Moves the buffer to the undo state `to` picks, with a cursor at the start of what changed.
+    fn undo(&mut self, buffer: &mut Buffer, to: UndoMove) -> Result<Option<Range<usize>>, Notify> {
+        if buffer.read_only {
+            return Err(Notify("cannot undo: buffer is read-only".to_string()));
+        }
+        let Some(target) = buffer.undo_tree().target(to) else {
+            let newest = matches!(to, UndoMove::Redo | UndoMove::Later(_))
+                || matches!(to, UndoMove::Steps(steps) if steps > 0);
+            let end = if newest { "newest" } else { "oldest" };
+            return Err(Notify(format!("already at the {} change", end)));
+        };
+        let Some((start, changed)) = buffer.undo_to(target) else {
+            return Ok(None);
+        };
+        self.layout.edited(buffer, changed.clone());
+        let cursor = buffer.contents.char_offset_to_point(start);
+        let max = buffer.contents.max_cursor_column(cursor.line, self.mode);
+        self.set_cursor(Point { column: cursor.column.min(max), ..cursor });
+        self.undo_join = None;
+        Ok(Some(changed))
+    }
+
+    /// The lines each selection touches, sorted, with any that overlap or meet merged.
+    fn selected_lines(&self) -> Vec<Range<usize>> {
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(self.selections.len());
+        for selection in &self.selections {
+            let lines = selection.start().line..selection.end().line + 1;
+            match merged.last_mut() {
+                Some(last) if lines.start <= last.end => last.end = last.end.max(lines.end),
+                _ => merged.push(lines),
+            }
+        }
+        merged
+    }
+
+    /// Deletes the selected lines, leaving visual line mode with a cursor at the start of the
+    /// line after each run of them. The last line has no line break of its own, so the one before
+    /// it goes instead, like vim.
+    fn delete_lines(&mut self, buffer: &mut Buffer) -> Result<Option<Range<usize>>, Notify> {
+        let contents = &buffer.contents;
+        let len_lines = contents.len_lines();
+        let selected = self.selected_lines();
+        let edits: Vec<_> = selected
+            .iter()
+            .map(|lines| {
+                let end = lines.end.min(len_lines);
+                let start = match lines.start {
+                    0 => 0,
+                    line if end == len_lines => {
+                        let line = line - 1;
+                        contents.line_to_char(line)
+                            + contents.line_len_chars_excluding_terminator(line)
+                    }
+                    line => contents.line_to_char(line),
+                };
+                (start..contents.line_to_char(end), "")
+            })
+            .collect();
+        let changed = self.edit_lines(buffer, "delete", &edits)?;
+        // each run of lines pulls up those after it
+        let mut deleted = 0;
+        let lines = selected.iter().map(|lines| {
+            let line = lines.start - deleted;
+            deleted += lines.len();
+            line
+        });
+        self.leave_lines(buffer, &selected, lines.collect());
+        Ok(changed)
+    }
+
+    /// Copies the selected lines into the register, leaving visual line mode with a cursor at the
+    /// start of each run of them.
+    fn yank_lines(&mut self, buffer: &Buffer) {
+        let selected = self.selected_lines();
+        let contents = &buffer.contents;
+        let mut text = String::new();
+        for lines in &selected {
+            let end = contents.line_to_char(lines.end.min(contents.len_lines()));
+            text.extend(
+                contents
+                    .slice(contents.line_to_char(lines.start)..end)
+                    .chars(),
+            );
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+        }
+        self.register = Some(Register { text, linewise: true });
+        let lines = selected.iter().map(|lines| lines.start).collect();
+        self.leave_lines(buffer, &selected, lines);
+    }
+
+    /// Puts `register`'s text after each cursor, or with `before` at it, leaving the cursor on the
+    /// last char put. Whole lines go below the cursor's line, or with `before` above it, leaving
+    /// the cursor on the first non-blank of the first of them.
+    fn paste(
+        &mut self,
+        buffer: &mut Buffer,
+        register: Register,
+        before: bool,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        let Register { text, linewise } = register;
+        if text.is_empty() {
+            return Ok(None);
+        }
+        let contents = &buffer.contents;
+        // where each cursor's text goes, the text, and how far into it the lines put start
+        let mut puts: Vec<Option<(usize, String, usize)>> = vec![];
+        for selection in &self.selections {
+            let head = selection.head;
+            let put = match (linewise, before) {
+                (true, true) => (contents.line_to_char(head.line), text.clone(), 0),
+                (true, false) if head.line + 1 < contents.len_lines() => {
+                    (contents.line_to_char(head.line + 1), text.clone(), 0)
+                }
+                // the last line has no line break for the lines to go after
+                (true, false) => {
+                    let lines = text.strip_suffix('\n').unwrap_or(&text);
+                    (contents.len_chars(), format!("\n{}", lines), 1)
+                }
+                (false, true) => (contents.point_to_char_offset(head), text.clone(), 0),
+                (false, false) => {
+                    let len = contents.line_len_chars_excluding_terminator(head.line);
+                    let column = (head.column + 1).min(len);
+                    (contents.line_to_char(head.line) + column, text.clone(), 0)
+                }
+            };
+            // cursors on the same line put whole lines there once
+            let repeated = puts.iter().flatten().any(|(at, ..)| *at == put.0);
+            puts.push((!(linewise && repeated)).then_some(put));
+        }
+
+        let mut next = puts.iter();
+        let changed = self.edit(buffer, "paste", |_| {
+            let (at, text, _) = next.next()?.as_ref()?;
+            Some((*at..*at, text.as_str()))
+        })?;
+        // cursors that ended up together can no longer be told apart
+        if self.selections.len() != puts.len() {
+            return Ok(changed);
+        }
+        let contents = &buffer.contents;
+        let len = text.chars().count();
+        let cursors: Vec<_> = self
+            .selections
+            .iter()
+            .zip(&puts)
+            .map(|(selection, put)| {
+                let end = contents.point_to_char_offset(selection.head);
+                match put {
+                    None => selection.head,
+                    Some((_, _, skip)) if linewise => {
+                        let line = contents.char_to_line(end - len + skip);
+                        let column = contents
+                            .leading_whitespace_len(line)
+                            .min(contents.max_cursor_column(line, self.mode));
+                        Point { line, column }
+                    }
+                    Some(_) => contents.char_offset_to_point(end - 1),
+                }
+            })
+            .collect();
+        for (selection, cursor) in self.selections.iter_mut().zip(cursors) {
+            *selection = Selection::cursor(cursor);
+        }
+        self.merge_selections();
+        Ok(changed)
+    }
+
+    /// Indents the selected lines by a level, or with `indent` false takes one off, in a single
+    /// edit. Empty lines are left alone, and a level comes off as a tab or up to a level's worth
+    /// of spaces.
+    fn indent_lines(
+        &mut self,
+        buffer: &mut Buffer,
+        indent: bool,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        let selected = self.selected_lines();
+        let (contents, settings) = (&buffer.contents, buffer.settings());
+        let level = settings.indent(0);
+        let size = settings.indent_size.max(1);
+        let mut edits = vec![];
+        for line in selected.iter().flat_map(|lines| lines.clone()) {
+            if line >= contents.len_lines() {
+                break;
+            }
+            let start = contents.line_to_char(line);
+            let mut chars = contents.line(line).chars();
+            if indent {
+                if contents.line_len_chars_excluding_terminator(line) > 0 {
+                    edits.push((start..start, level.as_str()));
+                }
+                continue;
+            }
+            let outdent = match chars.next() {
+                Some('\t') => 1,
+                Some(' ') => 1 + chars.take_while(|c| *c == ' ').take(size - 1).count(),
+                _ => 0,
+            };
+            if outdent > 0 {
+                edits.push((start..start + outdent, ""));
+            }
+        }
+        let changed = self.edit_lines(buffer, if indent { "indent" } else { "dedent" }, &edits)?;
+        let lines = selected.iter().map(|lines| lines.start).collect();
+        self.leave_lines(buffer, &selected, lines);
+        Ok(changed)
+    }
+
+    /// Comments out the non-blank selected lines, at the least indentation among them and with
+    /// a space after `token`, unless every one of them is commented already; then takes the
+    /// token, and a space after it, off each.
+    fn toggle_comment(
+        &mut self,
+        buffer: &mut Buffer,
+        token: &str,
+    ) -> Result<Option<Range<usize>>, Notify> {
+        let selected = self.selected_lines();
+        let contents = &buffer.contents;
+        let indent = |line| contents.leading_whitespace_len(line);
+        let lines: Vec<_> = selected
+            .iter()
+            .flat_map(|lines| lines.clone())
+            .take_while(|line| *line < contents.len_lines())
+            .filter(|line| contents.line_len_chars_excluding_terminator(*line) > indent(*line))
+            .collect();
+        let commented = |line| contents.starts_with_at(Point { line, column: indent(line) }, token);
+        let uncomment = !lines.is_empty() && lines.iter().all(|line| commented(*line));
+        let column = lines.iter().map(|line| indent(*line)).min().unwrap_or(0);
+        let comment = format!("{} ", token);
+        let mut edits = vec![];
+        for line in lines {
+            let start = contents.line_to_char(line);
+            if uncomment {
+                let column = indent(line) + token.chars().count();
+                let spaced = contents.starts_with_at(Point { line, column }, " ");
+                let end = start + column + usize::from(spaced);
+                edits.push((start + indent(line)..end, ""));
+            } else {
+                edits.push((start + column..start + column, comment.as_str()));
+            }
+        }
+        let changed = self.edit_lines(buffer, "toggle comment", &edits)?;
+        let lines = selected.iter().map(|lines| lines.start).collect();
+        self.leave_lines(buffer, &selected, lines);
+        Ok(changed)
+    }
+
+    /// Makes `edits` to the buffer as one change, leaving the selections as they are.
+    fn edit_lines(
+        &mut self,
+        buffer: &mut Buffer,
+        op: &str,
+        edits: &[(Range<usize>, &str)],
+    ) -> Result<Option<Range<usize>>, Notify> {
+        if buffer.read_only {
+            return Err(Notify(format!("cannot {}: buffer is read-only", op)));
+        }
+        if edits.is_empty() {
+            return Ok(None);
+        }
+        let (_, changed) = buffer
+            .apply_edits(edits)
+            .map_err(|err| Notify(format!("cannot {}: {}", op, err)))?;
+        self.layout.edited(buffer, changed.clone());
+        Ok(Some(changed))
+    }
+
+    /// Leaves visual line mode with a cursor on the first non-blank char of each of `lines`,
+    /// which stand for the runs of lines `selected`; the primary is the one for the run its
+    /// selection was in.
+    fn leave_lines(&mut self, buffer: &Buffer, selected: &[Range<usize>], lines: Vec<usize>) {
+        let primary_line = self.selections[self.primary].start().line;
+        let primary = selected
+            .iter()
+            .position(|lines| lines.contains(&primary_line))
+            .unwrap_or(0);
+        let last_line = buffer.contents.len_lines().saturating_sub(1);
+        self.selections = lines
+            .into_iter()
+            .map(|line| {
+                let line = line.min(last_line);
+                let column = buffer
+                    .contents
+                    .leading_whitespace_len(line)
+                    .min(buffer.contents.max_cursor_column(line, Mode::Normal));
+                Selection::cursor(Point { line, column })
+            })
+            .collect();
+        self.primary = primary;
+        self.merge_selections();
+        self.mode = Mode::Normal;
+    }
+
+    /// Moves every cursor with `motion`. Selections are dropped, except in visual line mode,
+    /// where their anchors stay put and they stretch to follow.
+    pub(crate) fn move_cursors(&mut self, mut motion: impl FnMut(Point) -> Point) {
+        if self.mode != Mode::VisualLine {
+            return self.move_heads(motion);
+        }
+        for selection in self.selections.iter_mut() {
+            selection.head = motion(selection.head);
+            selection.goal_column = None;
+        }
+        self.merge_selections();
+    }
+
+    /// Moves every cursor up or down like `move_cursors`, with `motion` given the column on
+    /// screen each aims for, if it has one yet, and handing back the one it aimed for, which the
+    /// cursor keeps aiming for.
+    pub(crate) fn move_cursors_vertically(
+        &mut self,
+        mut motion: impl FnMut(Point, Option<usize>) -> (Point, usize),
+    ) {
+        let keep_anchors = self.mode == Mode::VisualLine;
+        for selection in self.selections.iter_mut() {
+            let (head, goal) = motion(selection.head, selection.goal_column);
+            let anchor = if keep_anchors { selection.anchor } else { head };
+            *selection = Selection { anchor, head, goal_column: Some(goal) };
+        }
+        self.merge_selections();
+    }
+
+    /// Moves every cursor with `motion`, dropping any selection.
+    pub(crate) fn move_heads(&mut self, mut motion: impl FnMut(Point) -> Point) {
+        for selection in self.selections.iter_mut() {
+            *selection = Selection::cursor(motion(selection.head));
+        }
+        self.merge_selections();
+    }
+
+    /// Adds a cursor on the line above the topmost selection, or below the bottommost one, and
+    /// makes it the primary.
+    fn add_cursor(&mut self, buffer: &Buffer, direction: Direction) {
+        let last_line = buffer.contents.len_lines().saturating_sub(1);
+        let to = match direction {
+            Direction::Up => match self.selections[0].head {
+                from if from.line == 0 => return,
+                from => crate::movement::cursor_move_up(buffer, from, self.mode),
+            },
+            _ => match self.selections[self.selections.len() - 1].head {
+                from if from.line >= last_line => return,
+                from => crate::movement::cursor_move_down(buffer, from, self.mode),
+            },
+        };
+        self.selections.push(Selection::cursor(to));
+        self.primary = self.selections.len() - 1;
+        self.merge_selections();
+    }
+
+    fn select_all_matches(&mut self, buffer: &Buffer, text: &str) -> Result<(), Notify> {
+        let contents = buffer.contents.to_string();
+        let selections: Vec<_> = if text.is_empty() {
+            vec![]
+        } else {
+            contents
+                .match_indices(text)
+                .map(|(start, _)| {
+                    let point = |byte| {
+                        let offset = buffer.contents.byte_to_char(byte);
+                        buffer.contents.char_offset_to_point(offset)
+                    };
+                    Selection::new(point(start), point(start + text.len()))
+                })
+                .collect()
+        };
+        if selections.is_empty() {
+            return Err(Notify(format!("no matches for {:?}", text)));
+        }
+        // the primary becomes the first match at or after it, wrapping to the first
+        let cursor = self.cursor();
+        self.primary = selections
+            .iter()
+            .position(|s| s.start() >= cursor)
+            .unwrap_or(0);
+        self.selections = selections;
+        self.merge_selections();
+        Ok(())
+    }
+
+    /// Sorts the selections and merges any that overlap, or that are cursors at the same point,
+    /// keeping track of the primary.
+    fn merge_selections(&mut self) {
+        let primary = self.selections[self.primary];
+        self.selections.sort_by_key(Selection::start);
+        let mut merged: Vec<Selection> = Vec::with_capacity(self.selections.len());
+        self.primary = 0;
+        for selection in self.selections.drain(..) {
+            match merged.last_mut() {
+                Some(last)
+                    if last.range().contains(selection.start())
+                        || (selection.anchor, selection.head) == (last.anchor, last.head) =>
+                {
+                    let end = last.end().max(selection.end());
+                    *last = Selection { anchor: last.start(), head: end, ..*last };
+                }
+                _ => merged.push(selection),
+            }
+            if selection == primary {
+                self.primary = merged.len() - 1;
+            }
+        }
+        self.selections = merged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BufferContents;
+
+    /// A buffer holding `marked`'s text, and an editor on it with a cursor at each `|`, the
+    /// first of them the primary.
+    fn editor_on(marked: &str) -> (Buffer, Editor) {
+        let (text, cursors) = test_support::buffer_with_cursors(marked);
+        let buffer = Buffer::new(Default::default(), BufferContents::from(text.as_str()));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.selections = cursors.into_iter().map(Selection::cursor).collect();
+        editor.merge_selections();
+        (buffer, editor)
+    }
+
+    fn cursors(editor: &Editor) -> Vec<(usize, usize)> {
+        let heads = editor
+            .selections()
+            .iter()
+            .map(|s| (s.head.line, s.head.column));
+        heads.collect()
+    }
+
+    #[test]
+    fn insert_out_of_bounds_notifies() {
+        let (mut buffer, mut editor) = editor_on("ab\nc|d");
+        assert_eq!(editor.command(&mut buffer, Command::InsertChar('x')), Ok(Some(4..5)));
+        assert_eq!(editor.cursor(), Point { line: 1, column: 2 });
+
+        editor.set_cursor(Point { line: 1, column: 9 });
+        let Err(Notify(message)) = editor.command(&mut buffer, Command::InsertChar('y')) else {
+            panic!("insert past the end of the buffer should notify");
+        };
+        assert!(message.starts_with("cannot insert at 2:10: "), "{}", message);
+        assert_eq!(editor.cursor(), Point { line: 1, column: 9 });
+        assert_eq!(buffer.contents.to_string(), "ab\ncxd");
+        assert_eq!(buffer.version, 1);
+    }
+
+    #[test]
+    fn insert_at_every_cursor() {
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from("abc\ndef\nghi"));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(Point { line: 0, column: 1 });
+        editor
+            .command(&mut buffer, Command::AddCursorBelow)
+            .unwrap();
+        editor
+            .command(&mut buffer, Command::AddCursorBelow)
+            .unwrap();
+        // there is no line below the last to add one on
+        editor
+            .command(&mut buffer, Command::AddCursorBelow)
+            .unwrap();
+        assert_eq!(cursors(&editor), vec![(0, 1), (1, 1), (2, 1)]);
+        assert_eq!(editor.primary(), 2);
+
+        let changed = editor
+            .command(&mut buffer, Command::InsertChar('x'))
+            .unwrap();
+        assert_eq!(changed, Some(1..12));
+        assert_eq!(buffer.contents.to_string(), "axbc\ndxef\ngxhi");
+        assert_eq!(buffer.version, 1);
+        assert_eq!(cursors(&editor), vec![(0, 2), (1, 2), (2, 2)]);
+        assert_eq!(editor.cursor(), Point { line: 2, column: 2 });
+
+        editor
+            .command(&mut buffer, Command::CollapseSelections)
+            .unwrap();
+        assert_eq!(cursors(&editor), vec![(2, 2)]);
+    }
+
+    #[test]
+    fn overlapping_selections_merge_after_delete() {
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from("aab aab"));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(Point { line: 0, column: 2 });
+
+        editor
+            .command(&mut buffer, Command::SelectAllMatches("a".into()))
+            .unwrap();
+        assert_eq!(cursors(&editor), vec![(0, 1), (0, 2), (0, 5), (0, 6)]);
+        assert_eq!(editor.primary(), 2);
+
+        // deleting each pair of adjacent selections leaves two cursors at the same point
+        let changed = editor
+            .command(&mut buffer, Command::DeleteBackward)
+            .unwrap();
+        assert_eq!(changed, Some(0..2));
+        assert_eq!(buffer.contents.to_string(), "b b");
+        assert_eq!(cursors(&editor), vec![(0, 0), (0, 2)]);
+        assert_eq!(editor.primary(), 1);
+
+        // a cursor at the start of the buffer has nothing to delete
+        editor
+            .command(&mut buffer, Command::DeleteBackward)
+            .unwrap();
+        assert_eq!(buffer.contents.to_string(), "bb");
+        assert_eq!(cursors(&editor), vec![(0, 0), (0, 1)]);
+        editor
+            .command(&mut buffer, Command::CursorMove(Direction::Left))
+            .unwrap();
+        assert_eq!(cursors(&editor), vec![(0, 0)]);
+
+        let err = editor.command(&mut buffer, Command::SelectAllMatches("zz".into()));
+        assert_eq!(err, Err(Notify("no matches for \"zz\"".into())));
+    }
+
+    #[test]
+    fn open_lines() {
+        // (text, cursor, command, text after, cursor after)
+        let fixtures = [
+            (
+                "  fn a() {\n\tx\n}",
+                (0, 3),
+                Command::OpenLineBelow,
+                "  fn a() {\n  \n\tx\n}",
+                (1, 2),
+            ),
+            (
+                "  fn a() {\n\tx\n}",
+                (1, 1),
+                Command::OpenLineAbove,
+                "  fn a() {\n\t\n\tx\n}",
+                (1, 1),
+            ),
+            // the last line has no line break of its own to open after
+            ("ab\n  cd", (1, 3), Command::OpenLineBelow, "ab\n  cd\n  ", (2, 2)),
+            ("  ab\ncd", (0, 1), Command::OpenLineAbove, "  \n  ab\ncd", (0, 2)),
+            ("", (0, 0), Command::OpenLineBelow, "\n", (1, 0)),
+            ("", (0, 0), Command::OpenLineAbove, "\n", (0, 0)),
+        ];
+        for (text, (line, column), command, expected, cursor) in fixtures {
+            let mut buffer = Buffer::new(Default::default(), BufferContents::from(text));
+            let mut editor = Editor::new(Default::default(), buffer.id);
+            editor.set_cursor(Point { line, column });
+            editor.command(&mut buffer, command.clone()).unwrap();
+            assert_eq!(buffer.contents.to_string(), expected, "{:?} in {:?}", command, text);
+            assert_eq!(cursors(&editor), vec![cursor], "{:?} in {:?}", command, text);
+            assert_eq!(editor.mode, Mode::Insert);
+            assert_eq!(buffer.version, 1);
+        }
+
+        // cursors sharing a line open a single line
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from("abc\ndef"));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor
+            .command(&mut buffer, Command::SelectAllMatches("e".into()))
+            .unwrap();
+        editor
+            .command(&mut buffer, Command::AddCursorAbove)
+            .unwrap();
+        editor.command(&mut buffer, Command::OpenLineBelow).unwrap();
+        assert_eq!(buffer.contents.to_string(), "abc\n\ndef\n");
+        assert_eq!(cursors(&editor), vec![(1, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn append_and_leave_insert_mode() {
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from("ab\n\ncd"));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let mut command =
+            |editor: &mut Editor, command| editor.command(&mut buffer, command).unwrap();
+
+        // `a` on the last char appends past it, and Esc comes back to it
+        editor.set_cursor(Point { line: 0, column: 1 });
+        command(&mut editor, Command::AppendAfterCursor);
+        assert_eq!((editor.mode, editor.cursor()), (Mode::Insert, Point { line: 0, column: 2 }));
+        command(&mut editor, Command::SetMode(Mode::Normal));
+        assert_eq!((editor.mode, editor.cursor()), (Mode::Normal, Point { line: 0, column: 1 }));
+        // so repeating it doesn't drift
+        command(&mut editor, Command::AppendAfterCursor);
+        command(&mut editor, Command::SetMode(Mode::Normal));
+        assert_eq!(editor.cursor(), Point { line: 0, column: 1 });
+
+        // `A` on an empty line stays at column 0, and so does Esc
+        editor.set_cursor(Point { line: 1, column: 0 });
+        command(&mut editor, Command::AppendAtEndOfLine);
+        assert_eq!((editor.mode, editor.cursor()), (Mode::Insert, Point { line: 1, column: 0 }));
+        command(&mut editor, Command::SetMode(Mode::Normal));
+        assert_eq!(editor.cursor(), Point { line: 1, column: 0 });
+
+        editor.set_cursor(Point { line: 2, column: 0 });
+        command(&mut editor, Command::AppendAtEndOfLine);
+        assert_eq!(editor.cursor(), Point { line: 2, column: 2 });
+        command(&mut editor, Command::InsertChar('e'));
+        command(&mut editor, Command::SetMode(Mode::Normal));
+        assert_eq!(buffer.contents.to_string(), "ab\n\ncde");
+        assert_eq!(editor.cursor(), Point { line: 2, column: 2 });
+    }
+
+    #[test]
+    fn step_over_replaced_bytes() {
+        // a lone continuation byte, an overlong '/' and a control char, in enough text that the
+        // file isn't taken for binary
+        let (text, ..) = crate::encoding::decode_lossy(b"a\x80\xc0\xafb\x01c\nplain text");
+        assert_eq!(text, "a\u{fffd}\u{fffd}\u{fffd}b\x01c\nplain text");
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from(text.as_str()));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let mut command =
+            |editor: &mut Editor, command| editor.command(&mut buffer, command).unwrap();
+
+        // each takes one column, however many bytes it was or is
+        for column in 1..=6 {
+            command(&mut editor, Command::CursorMove(Direction::Right));
+            assert_eq!(editor.cursor(), Point { line: 0, column });
+        }
+        command(&mut editor, Command::CursorMove(Direction::Left));
+        command(&mut editor, Command::SetMode(Mode::Insert));
+        for _ in 0..4 {
+            command(&mut editor, Command::DeleteBackward);
+        }
+        assert_eq!(buffer.contents.to_string(), "a\x01c\nplain text");
+        assert_eq!(editor.cursor(), Point { line: 0, column: 1 });
+    }
+
+    #[test]
+    fn replace_chars() {
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from("naïve\n\nab"));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let mut command = |editor: &mut Editor, command| editor.command(&mut buffer, command);
+
+        // `r` swaps a multi-byte char for another, staying on it
+        editor.set_cursor(Point { line: 0, column: 2 });
+        assert_eq!(command(&mut editor, Command::ReplaceChar('日')), Ok(Some(2..5)));
+        assert_eq!(command(&mut editor, Command::ReplaceChar('i')), Ok(Some(2..3)));
+        assert_eq!(editor.cursor(), Point { line: 0, column: 2 });
+        assert_eq!(editor.mode, Mode::Normal);
+        // and does nothing on an empty line
+        editor.set_cursor(Point { line: 1, column: 0 });
+        assert_eq!(command(&mut editor, Command::ReplaceChar('x')), Ok(None));
+
+        // `R` types over the line and then appends past its end
+        editor.set_cursor(Point { line: 2, column: 1 });
+        command(&mut editor, Command::SetMode(Mode::Replace)).unwrap();
+        for c in "xyz".chars() {
+            command(&mut editor, Command::InsertChar(c)).unwrap();
+        }
+        assert_eq!(editor.cursor(), Point { line: 2, column: 4 });
+
+        // backspace restores the last char typed over first, and drops appended ones
+        command(&mut editor, Command::DeleteBackward).unwrap();
+        assert_eq!(editor.cursor(), Point { line: 2, column: 3 });
+        command(&mut editor, Command::DeleteBackward).unwrap();
+        assert_eq!(editor.cursor(), Point { line: 2, column: 2 });
+        command(&mut editor, Command::InsertChar('w')).unwrap();
+        command(&mut editor, Command::DeleteBackward).unwrap();
+        command(&mut editor, Command::DeleteBackward).unwrap();
+        assert_eq!(editor.cursor(), Point { line: 2, column: 1 });
+        // with nothing left to restore it only moves
+        command(&mut editor, Command::DeleteBackward).unwrap();
+        assert_eq!(editor.cursor(), Point { line: 2, column: 0 });
+        command(&mut editor, Command::SetMode(Mode::Normal)).unwrap();
+        assert_eq!(buffer.contents.to_string(), "naive\n\nab");
+    }
+
+    #[test]
+    fn marks_follow_edits() {
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from("one\n  two\nthree"));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let mut command = |editor: &mut Editor, command| editor.command(&mut buffer, command);
+
+        editor.set_cursor(Point { line: 1, column: 3 });
+        command(&mut editor, Command::SetMark('a')).unwrap();
+        editor.set_cursor(Point { line: 2, column: 2 });
+        command(&mut editor, Command::SetMark('b')).unwrap();
+
+        // text inserted above carries the mark down with the line
+        editor.set_cursor(Point { line: 0, column: 0 });
+        command(&mut editor, Command::OpenLineAbove).unwrap();
+        command(&mut editor, Command::InsertChar('x')).unwrap();
+        command(&mut editor, Command::SetMode(Mode::Normal)).unwrap();
+        command(&mut editor, Command::JumpToMark('a', MarkJump::Exact)).unwrap();
+        assert_eq!(editor.cursor(), Point { line: 2, column: 3 });
+        command(&mut editor, Command::JumpToMark('a', MarkJump::Line)).unwrap();
+        assert_eq!(editor.cursor(), Point { line: 2, column: 0 });
+
+        // deleting the text around a mark leaves it where the deletion was
+        editor.set_cursor(Point { line: 3, column: 4 });
+        command(&mut editor, Command::SetMode(Mode::Insert)).unwrap();
+        for _ in 0..6 {
+            command(&mut editor, Command::DeleteBackward).unwrap();
+        }
+        assert_eq!(buffer.contents.to_string(), "x\none\n  twe");
+        assert_eq!(buffer.mark('b'), Some(Point { line: 2, column: 4 }));
+        assert_eq!(buffer.mark('a'), Some(Point { line: 2, column: 3 }));
+
+        let jump = editor.command(&mut buffer, Command::JumpToMark('z', MarkJump::Line));
+        assert_eq!(jump, Err(Notify("mark 'z' is not set".into())));
+    }
+
+    #[test]
+    fn insert_indent() {
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from("a\nbc\n"));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_mode(&buffer, Mode::Insert);
+        editor.set_cursor(Point { line: 0, column: 1 });
+        editor
+            .command(&mut buffer, Command::AddCursorBelow)
+            .unwrap();
+        editor.command(&mut buffer, Command::InsertIndent).unwrap();
+        assert_eq!(buffer.contents.to_string(), "a   \nb   c\n");
+
+        buffer.set_override("indent_style", "tab").unwrap();
+        editor.command(&mut buffer, Command::InsertIndent).unwrap();
+        assert_eq!(buffer.contents.to_string(), "a   \t\nb   \tc\n");
+    }
+
+    #[test]
+    fn newlines_keep_indentation() {
+        let (mut buffer, mut editor) = editor_on("  a|b\n\t|c\n |");
+        editor.set_mode(&buffer, Mode::Insert);
+        editor.command(&mut buffer, Command::InsertNewline).unwrap();
+        assert_eq!(buffer.contents.to_string(), "  a\n  b\n\t\n\tc\n \n ");
+        assert_eq!(cursors(&editor), vec![(1, 2), (3, 1), (5, 1)]);
+    }
+
+    #[test]
+    fn auto_pairs() {
+        use Command::{CursorMove, DeleteBackward, InsertChar, InsertNewline};
+
+        let typed = |marked: &str, keys: &[Command]| {
+            let (mut buffer, mut editor) = editor_on(marked);
+            editor.set_mode(&buffer, Mode::Insert);
+            for key in keys {
+                editor.command(&mut buffer, key.clone()).unwrap();
+            }
+            (buffer.contents.to_string(), cursors(&editor), buffer.version)
+        };
+
+        // each pair is typed as one edit, with the cursor left between; typing its closer steps
+        // over it, even after typing between them
+        for (opener, closer) in PAIRS {
+            let pair = format!("a {}{}b", opener, closer);
+            assert_eq!(typed("a |b", &[InsertChar(opener)]), (pair.clone(), vec![(0, 3)], 1));
+            let keys = [InsertChar(opener), InsertChar(closer)];
+            assert_eq!(typed("a |b", &keys), (pair, vec![(0, 4)], 1));
+            let keys = [InsertChar(opener), InsertChar('x'), InsertChar(closer)];
+            let text = format!("a {}x{}b", opener, closer);
+            assert_eq!(typed("a |b", &keys), (text, vec![(0, 5)], 2));
+        }
+        // at every cursor, innermost first
+        let keys = [
+            InsertChar('('),
+            InsertChar('['),
+            InsertChar(']'),
+            InsertChar(')'),
+        ];
+        let expected = ("f([])\nf([])".to_string(), vec![(0, 5), (1, 5)], 2);
+        assert_eq!(typed("f|\nf|", &keys), expected);
+        // moving off a closer forgets it was typed, so typing it again inserts another
+        let keys = [
+            InsertChar('('),
+            CursorMove(Direction::Left),
+            CursorMove(Direction::Right),
+            InsertChar(')'),
+        ];
+        assert_eq!(typed("|", &keys).0, "())");
+
+        // backspace between an empty pair just typed deletes both, then works as ever
+        let keys = [InsertChar('('), InsertChar('['), DeleteBackward];
+        assert_eq!(typed("f|", &keys), ("f()".to_string(), vec![(0, 2)], 3));
+        let keys = [InsertChar('('), DeleteBackward, DeleteBackward];
+        assert_eq!(typed("f|", &keys), ("".to_string(), vec![(0, 0)], 3));
+
+        // enter between braces puts the closing one a line further down, with the cursor on an
+        // indented line between them
+        let keys = [InsertChar('{'), InsertNewline];
+        let expected = ("  fn a() {\n    \n  }".to_string(), vec![(1, 4)], 2);
+        assert_eq!(typed("  fn a() |", &keys), expected);
+        let (text, cursors, _) = typed("{|}\n\t{|}", &[InsertNewline]);
+        assert_eq!(text, "{\n    \n}\n\t{\n\t   \n\t}");
+        assert_eq!(cursors, vec![(1, 4), (4, 4)]);
+
+        // an apostrophe in a word isn't a quote
+        assert_eq!(typed("don|", &[InsertChar('\'')]), ("don'".to_string(), vec![(0, 4)], 1));
+        assert_eq!(typed("(|", &[InsertChar('"')]), ("(\"\"".to_string(), vec![(0, 2)], 1));
+
+        // and none of it once auto-pairing is off
+        let (mut buffer, mut editor) = editor_on("{|}");
+        editor.auto_pairs = false;
+        editor.set_mode(&buffer, Mode::Insert);
+        editor.command(&mut buffer, InsertChar('(')).unwrap();
+        editor.command(&mut buffer, InsertNewline).unwrap();
+        assert_eq!(buffer.contents.to_string(), "{(\n}");
+    }
+
+    #[test]
+    fn delete_words_and_lines_typed() {
+        use Command::*;
+
+        let typed = |marked: &str, keys: &[Command]| {
+            let (mut buffer, mut editor) = editor_on(marked);
+            editor.set_mode(&buffer, Mode::Insert);
+            for key in keys {
+                editor.command(&mut buffer, key.clone()).unwrap();
+            }
+            (buffer.contents.to_string(), cursors(&editor))
+        };
+        let text = |text: &str, cursors: &[(usize, usize)]| (text.to_string(), cursors.to_vec());
+
+        // ctrl-w deletes back to where `b` would go, whitespace and all
+        assert_eq!(typed("foo bar|", &[DeleteWordBackward]), text("foo ", &[(0, 4)]));
+        assert_eq!(typed("foo ba|r", &[DeleteWordBackward]), text("foo r", &[(0, 4)]));
+        assert_eq!(typed("foo   |", &[DeleteWordBackward]), text("", &[(0, 0)]));
+        assert_eq!(typed("foo.bar(|", &[DeleteWordBackward]), text("foo.bar", &[(0, 7)]));
+        // but never past the start of the line
+        assert_eq!(typed("a\n  |x", &[DeleteWordBackward]), text("a\nx", &[(1, 0)]));
+        assert_eq!(typed("a\n|b", &[DeleteWordBackward]), text("a\nb", &[(1, 0)]));
+        assert_eq!(typed("日本語 テキスト|", &[DeleteWordBackward]), text("日本語 ", &[(0, 4)]));
+        assert_eq!(typed("日本|語 x", &[DeleteWordBackward]), text("語 x", &[(0, 0)]));
+        let keys = [DeleteWordBackward];
+        assert_eq!(typed("a b|\nc d|", &keys), text("a \nc ", &[(0, 2), (1, 2)]));
+
+        // ctrl-u deletes what was typed on the line, and no more
+        let keys = [InsertChar('x'), InsertChar('y'), DeleteToInsertStart];
+        assert_eq!(typed("foo |bar", &keys), text("foo bar", &[(0, 4)]));
+        let keys = [InsertChar('d'), DeleteToInsertStart, DeleteToInsertStart];
+        assert_eq!(typed("abc|", &keys), text("abc", &[(0, 3)]));
+        assert_eq!(typed("|abc", &[DeleteToInsertStart]), text("abc", &[(0, 0)]));
+        assert_eq!(
+            typed("日本|", &[InsertChar('語'), DeleteToInsertStart]),
+            text("日本", &[(0, 2)])
+        );
+        // or back to the start of a line begun since
+        let keys = [
+            InsertNewline,
+            InsertChar('c'),
+            InsertChar('d'),
+            DeleteToInsertStart,
+        ];
+        assert_eq!(typed("ab|", &keys), text("ab\n", &[(1, 0)]));
+        // at every cursor
+        let keys = [InsertChar('x'), DeleteToInsertStart];
+        assert_eq!(typed("a|\nb|", &keys), text("a\nb", &[(0, 1), (1, 1)]));
+
+        // nothing before the cursor is nothing to do
+        let (mut buffer, mut editor) = editor_on("|abc");
+        editor.set_mode(&buffer, Mode::Insert);
+        assert_eq!(editor.command(&mut buffer, DeleteWordBackward), Ok(None));
+        assert_eq!(editor.command(&mut buffer, DeleteToInsertStart), Ok(None));
+    }
+
+    #[test]
+    fn delete_forward_joins_lines() {
+        let (mut buffer, mut editor) = editor_on("a|b\nc|\r\nd\ne|");
+        editor.set_mode(&buffer, Mode::Insert);
+        editor.command(&mut buffer, Command::DeleteForward).unwrap();
+        assert_eq!(buffer.contents.to_string(), "a\ncd\ne");
+        assert_eq!(cursors(&editor), vec![(0, 1), (1, 1), (2, 1)]);
+
+        // nothing under any cursor is nothing to do
+        let (mut buffer, mut editor) = editor_on("ab|");
+        editor.set_mode(&buffer, Mode::Insert);
+        assert_eq!(editor.command(&mut buffer, Command::DeleteForward), Ok(None));
+    }
+
+    #[test]
+    fn select_and_delete_words() {
+        let text = "let foo = bar;\nbaz  qux\n";
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from(text));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+
+        editor.set_cursor(Point { line: 0, column: 5 });
+        editor
+            .command(&mut buffer, Command::AddCursorBelow)
+            .unwrap();
+        let select = Command::SelectTextObject(TextObject::InnerWord);
+        editor.command(&mut buffer, select).unwrap();
+        let selected: Vec<_> = editor
+            .selections()
+            .iter()
+            .map(|s| (s.start().line, s.start().column, s.end().column))
+            .collect();
+        assert_eq!(selected, vec![(0, 4, 7), (1, 5, 8)]);
+
+        // words with nothing after them take the whitespace before, and the cursors are pulled
+        // back onto their lines
+        editor.set_cursor(Point { line: 0, column: 11 });
+        editor
+            .command(&mut buffer, Command::AddCursorBelow)
+            .unwrap();
+        let delete = Command::DeleteTextObject(TextObject::AroundWord);
+        editor.command(&mut buffer, delete.clone()).unwrap();
+        assert_eq!(buffer.contents.to_string(), "let foo =;\nbaz\n");
+        assert_eq!(cursors(&editor), vec![(0, 9), (1, 2)]);
+
+        // an empty line has nothing to delete
+        editor.set_cursor(Point { line: 2, column: 0 });
+        assert_eq!(editor.command(&mut buffer, delete), Ok(None));
+    }
+
+    #[test]
+    fn search_tracks_the_cursor() {
+        let text = "one two\ntwo one two\n";
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from(text));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(Point { line: 0, column: 5 });
+
+        // the first match after the cursor, not the one it's on
+        editor
+            .command(&mut buffer, Command::Search("two".into()))
+            .unwrap();
+        assert_eq!(editor.cursor(), Point { line: 1, column: 0 });
+        assert_eq!(editor.search().map(Search::total), Some(3));
+        assert_eq!(editor.search_index(&buffer), Some(2));
+
+        // inside a match, and between it and the next
+        editor.set_cursor(Point { line: 1, column: 2 });
+        assert_eq!(editor.search_index(&buffer), Some(2));
+        editor.set_cursor(Point { line: 1, column: 5 });
+        assert_eq!(editor.search_index(&buffer), Some(2));
+        editor.set_cursor(Point { line: 0, column: 0 });
+        assert_eq!(editor.search_index(&buffer), Some(0));
+
+        editor
+            .command(&mut buffer, Command::SearchNext(true))
+            .unwrap();
+        assert_eq!(editor.cursor(), Point { line: 1, column: 8 });
+        assert_eq!(editor.search_index(&buffer), Some(3));
+        editor
+            .command(&mut buffer, Command::SearchNext(false))
+            .unwrap();
+        assert_eq!(editor.cursor(), Point { line: 0, column: 4 });
+        assert_eq!(editor.search_index(&buffer), Some(1));
+
+        // edits leave the matches as they were until they're found again
+        editor.set_cursor(Point { line: 0, column: 0 });
+        editor
+            .command(&mut buffer, Command::SetMode(Mode::Insert))
+            .unwrap();
+        for c in "two ".chars() {
+            editor.command(&mut buffer, Command::InsertChar(c)).unwrap();
+        }
+        assert_eq!(editor.search().map(Search::total), Some(3));
+        editor.refresh_search(&buffer);
+        assert_eq!(editor.search().map(Search::total), Some(4));
+        assert_eq!(editor.search_index(&buffer), Some(1));
+
+        editor.command(&mut buffer, Command::ClearSearch).unwrap();
+        assert_eq!((editor.search(), editor.search_index(&buffer)), (None, None));
+        let err = editor.command(&mut buffer, Command::SearchNext(false));
+        assert_eq!(err, Err(Notify("no search to repeat".into())));
+        let err = editor.command(&mut buffer, Command::Search("three".into()));
+        assert_eq!(err, Err(Notify("no matches for \"three\"".into())));
+    }
+
+    #[test]
+    fn regex_search() {
+        let text = "fn one() {}\nlet two = one();\n";
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from(text));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let search = |editor: &mut Editor, buffer: &mut Buffer, query: &str| {
+            editor.command(buffer, Command::Search(query.into()))
+        };
+
+        // plain until the mode says otherwise
+        let err = search(&mut editor, &mut buffer, r"^\w+");
+        assert_eq!(err, Err(Notify("no matches for \"^\\\\w+\"".into())));
+        editor.search_mode = SearchMode::Regex;
+        search(&mut editor, &mut buffer, r"^\w+").unwrap();
+        assert_eq!(editor.cursor(), Point { line: 1, column: 0 });
+        assert_eq!(editor.search().unwrap().matches(), [0..2, 12..15]);
+
+        // repeating the search, even after an edit, keeps matching it as a regex
+        buffer.contents.insert(0, "pub ");
+        buffer.version += 1;
+        editor
+            .command(&mut buffer, Command::SearchNext(false))
+            .unwrap();
+        assert_eq!(editor.cursor(), Point { line: 0, column: 0 });
+        assert_eq!(editor.search().unwrap().matches(), [0..3, 16..19]);
+
+        let err = search(&mut editor, &mut buffer, "one(");
+        assert!(matches!(err, Err(Notify(message)) if message.starts_with("invalid regex")));
+    }
+
+    #[test]
+    fn search_wraps_around_too_many_matches() {
+        let count = crate::search::MAX_MATCHES * 2;
+        let text = "ab\n".repeat(count);
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from(text.as_str()));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(Point { line: count - 1, column: 1 });
+
+        editor
+            .command(&mut buffer, Command::Search("a".into()))
+            .unwrap();
+        assert_eq!(editor.cursor(), Point { line: 0, column: 0 });
+        let search = editor.search().unwrap();
+        assert!(search.is_truncated() && !search.counted_all());
+        assert_eq!(editor.search_index(&buffer), Some(1));
+
+        editor
+            .command(&mut buffer, Command::SearchNext(true))
+            .unwrap();
+        assert_eq!(editor.cursor(), Point { line: count - 1, column: 0 });
+        let search = editor.search().unwrap();
+        assert_eq!((search.total(), search.counted_all()), (count, true));
+        assert_eq!(editor.search_index(&buffer), Some(count));
+    }
+
+    #[test]
+    fn visual_line_from_the_bottom_up() {
+        let (mut buffer, mut editor) = editor_on("a\n  b\n|c\n  d\n");
+        let mut command = |editor: &mut Editor, command| editor.command(&mut buffer, command);
+
+        command(&mut editor, Command::SelectLine).unwrap();
+        command(&mut editor, Command::CursorMove(Direction::Up)).unwrap();
+        command(&mut editor, Command::CursorMove(Direction::Right)).unwrap();
+        assert_eq!(editor.mode, Mode::VisualLine);
+        let selection = editor.selections()[0];
+        assert_eq!(selection.anchor, Point { line: 2, column: 0 });
+        assert_eq!(selection.head, Point { line: 1, column: 1 });
+
+        command(&mut editor, Command::IndentLines).unwrap();
+        assert_eq!((editor.mode, editor.cursor()), (Mode::Normal, Point { line: 1, column: 6 }));
+        command(&mut editor, Command::SelectLine).unwrap();
+        command(&mut editor, Command::CursorMove(Direction::Down)).unwrap();
+        command(&mut editor, Command::DedentLines).unwrap();
+        command(&mut editor, Command::SelectLine).unwrap();
+        command(&mut editor, Command::CursorMove(Direction::Down)).unwrap();
+        command(&mut editor, Command::DeleteLines).unwrap();
+        assert_eq!(buffer.contents.to_string(), "a\n  d\n");
+        assert_eq!((editor.mode, editor.cursor()), (Mode::Normal, Point { line: 1, column: 2 }));
+        // each change to the lines is a single edit
+        assert_eq!(buffer.version, 3);
+    }
+
+    #[test]
+    fn toggle_comment() {
+        let (mut buffer, mut editor) = editor_on("fn a() {\n    |// b();\n    c();\n\n}\n");
+        editor.command(&mut buffer, Command::SelectLine).unwrap();
+        for _ in 0..2 {
+            editor
+                .command(&mut buffer, Command::CursorMove(Direction::Down))
+                .unwrap();
+        }
+        // some lines are commented and some aren't, so all of them are; the blank one isn't
+        editor
+            .command(&mut buffer, Command::ToggleComment("//"))
+            .unwrap();
+        let text = "fn a() {\n    // // b();\n    // c();\n\n}\n";
+        assert_eq!(buffer.contents.to_string(), text);
+        assert_eq!((editor.mode, editor.cursor()), (Mode::Normal, Point { line: 1, column: 4 }));
+
+        // now every one is, so each loses a comment
+        editor.command(&mut buffer, Command::SelectLine).unwrap();
+        editor
+            .command(&mut buffer, Command::CursorMove(Direction::Down))
+            .unwrap();
+        editor
+            .command(&mut buffer, Command::ToggleComment("//"))
+            .unwrap();
+        assert_eq!(buffer.contents.to_string(), "fn a() {\n    // b();\n    c();\n\n}\n");
+
+        // and without a selection, it's the cursor's line
+        editor
+            .command(&mut buffer, Command::ToggleComment("//"))
+            .unwrap();
+        assert_eq!(buffer.contents.to_string(), "fn a() {\n    b();\n    c();\n\n}\n");
+        assert!(buffer
+            .contents
+            .starts_with_at(Point { line: 2, column: 4 }, "c()"));
+        assert_eq!(buffer.contents.leading_whitespace_len(2), 4);
+        // each toggle is a single edit
+        assert_eq!(buffer.version, 3);
+    }
+
+    #[test]
+    fn visual_line_on_the_last_line() {
+        let (mut buffer, mut editor) = editor_on("a\nb\n |c");
+        editor.command(&mut buffer, Command::SelectLine).unwrap();
+        editor.command(&mut buffer, Command::YankLines).unwrap();
+        let register = Register { text: " c\n".into(), linewise: true };
+        assert_eq!(editor.register(), Some(&register));
+        assert_eq!((editor.mode, editor.cursor()), (Mode::Normal, Point { line: 2, column: 1 }));
+
+        // the line break before the last line goes with it
+        editor.command(&mut buffer, Command::SelectLine).unwrap();
+        editor
+            .command(&mut buffer, Command::CursorMove(Direction::Up))
+            .unwrap();
+        editor.command(&mut buffer, Command::DeleteLines).unwrap();
+        assert_eq!(buffer.contents.to_string(), "a");
+        assert_eq!(editor.cursor(), Point { line: 0, column: 0 });
+    }
+
+    #[test]
+    fn switch_between_charwise_and_linewise() {
+        let (mut buffer, mut editor) = editor_on("ab |cd\nef\n");
+        let word = Selection::new(Point { line: 0, column: 3 }, Point { line: 0, column: 5 });
+
+        let select = Command::SelectTextObject(TextObject::InnerWord);
+        editor.command(&mut buffer, select).unwrap();
+        editor.command(&mut buffer, Command::SelectLine).unwrap();
+        assert_eq!((editor.mode, editor.selections()), (Mode::VisualLine, &[word][..]));
+
+        // leaving for normal mode keeps the selection, charwise
+        editor
+            .command(&mut buffer, Command::SetMode(Mode::Normal))
+            .unwrap();
+        assert_eq!((editor.mode, editor.selections()), (Mode::Normal, &[word][..]));
+
+        // and `V` again, or escape, drops it
+        editor.command(&mut buffer, Command::SelectLine).unwrap();
+        editor.command(&mut buffer, Command::SelectLine).unwrap();
+        let cursor = Selection::cursor(Point { line: 0, column: 4 });
+        assert_eq!((editor.mode, editor.selections()), (Mode::Normal, &[cursor][..]));
+        editor.command(&mut buffer, Command::SelectLine).unwrap();
+        editor
+            .command(&mut buffer, Command::CollapseSelections)
+            .unwrap();
+        assert_eq!(editor.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn undo_takes_back_each_insert_at_once() {
+        let (mut buffer, mut editor) = editor_on("a|b\n");
+        for typed in ["xy", "z"] {
+            editor
+                .command(&mut buffer, Command::SetMode(Mode::Insert))
+                .unwrap();
+            for c in typed.chars() {
+                editor.command(&mut buffer, Command::InsertChar(c)).unwrap();
+            }
+            editor
+                .command(&mut buffer, Command::SetMode(Mode::Normal))
+                .unwrap();
+        }
+        assert_eq!(buffer.contents.to_string(), "axzyb\n");
+
+        let mut undo = |to| {
+            let result = editor.command(&mut buffer, Command::Undo(to));
+            (result, buffer.contents.to_string(), cursors(&editor))
+        };
+        assert_eq!(undo(UndoMove::Undo), (Ok(Some(2..2)), "axyb\n".into(), vec![(0, 2)]));
+        assert_eq!(undo(UndoMove::Undo), (Ok(Some(1..1)), "ab\n".into(), vec![(0, 1)]));
+        let oldest = Err(Notify("already at the oldest change".into()));
+        assert_eq!(undo(UndoMove::Undo), (oldest, "ab\n".into(), vec![(0, 1)]));
+        assert_eq!(undo(UndoMove::Redo), (Ok(Some(1..3)), "axyb\n".into(), vec![(0, 1)]));
+    }
+
+    #[test]
+    fn undo_takes_back_an_opened_line_with_what_was_typed_on_it() {
+        let (mut buffer, mut editor) = editor_on("  a|b\ncd\n");
+        for open in [Command::OpenLineBelow, Command::OpenLineAbove] {
+            editor.command(&mut buffer, open).unwrap();
+            for c in "xy".chars() {
+                editor.command(&mut buffer, Command::InsertChar(c)).unwrap();
+            }
+            editor
+                .command(&mut buffer, Command::SetMode(Mode::Normal))
+                .unwrap();
+        }
+        assert_eq!(buffer.contents.to_string(), "  ab\n  xy\n  xy\ncd\n");
+
+        let mut undo = || {
+            let result = editor.command(&mut buffer, Command::Undo(UndoMove::Undo));
+            (result.map(|_| ()), buffer.contents.to_string())
+        };
+        assert_eq!(undo(), (Ok(()), "  ab\n  xy\ncd\n".into()));
+        assert_eq!(undo(), (Ok(()), "  ab\ncd\n".into()));
+        let oldest = Err(Notify("already at the oldest change".into()));
+        assert_eq!(undo(), (oldest, "  ab\ncd\n".into()));
+    }
+
+    #[test]
+    fn undo_takes_back_a_replace_session_at_once() {
+        let (mut buffer, mut editor) = editor_on("a|bc\nd\n");
+        let mut command = |editor: &mut Editor, command| editor.command(&mut buffer, command);
+        command(&mut editor, Command::ReplaceChar('x')).unwrap();
+        command(&mut editor, Command::SetMode(Mode::Replace)).unwrap();
+        // typing over the line and past its end, and backspacing over some of it
+        for c in "yzw".chars() {
+            command(&mut editor, Command::InsertChar(c)).unwrap();
+        }
+        command(&mut editor, Command::DeleteBackward).unwrap();
+        command(&mut editor, Command::DeleteBackward).unwrap();
+        command(&mut editor, Command::InsertChar('v')).unwrap();
+        // a line break isn't typed over anything, and what was before it isn't put back after it
+        command(&mut editor, Command::InsertNewline).unwrap();
+        command(&mut editor, Command::DeleteBackward).unwrap();
+        command(&mut editor, Command::InsertChar('u')).unwrap();
+        command(&mut editor, Command::SetMode(Mode::Normal)).unwrap();
+        assert_eq!(buffer.contents.to_string(), "ayv\nu\nd\n");
+
+        let mut undo = || {
+            editor
+                .command(&mut buffer, Command::Undo(UndoMove::Undo))
+                .unwrap();
+            buffer.contents.to_string()
+        };
+        assert_eq!(undo(), "axc\nd\n");
+        assert_eq!(undo(), "abc\nd\n");
+    }
+
+    #[test]
+    fn operators_fill_the_register() {
+        type Outcome = (String, Option<(String, bool)>, Vec<(usize, usize)>, Mode);
+        fn operate(
+            buffer: &mut Buffer,
+            editor: &mut Editor,
+            op: Operator,
+            motion: Motion,
+        ) -> Outcome {
+            editor
+                .command(buffer, Command::Operator(op, motion, 1))
+                .unwrap();
+            let register = editor.register().cloned().map(|r| (r.text, r.linewise));
+            (buffer.contents.to_string(), register, cursors(editor), editor.mode)
+        }
+        let (mut buffer, mut editor) = editor_on("  |foo bar\n    baz\nqux");
+        let w = Motion::Jump(CursorJump::StartOfNextWord);
+        let outcome = operate(&mut buffer, &mut editor, Operator::Delete, w);
+        let register = Some(("foo ".to_string(), false));
+        let expected = ("  bar\n    baz\nqux".to_string(), register, vec![(0, 2)], Mode::Normal);
+        assert_eq!(outcome, expected);
+
+        // yanking leaves the text be
+        let eol = Motion::Jump(CursorJump::EndOfLine);
+        let outcome = operate(&mut buffer, &mut editor, Operator::Yank, eol);
+        let register = Some(("bar".to_string(), false));
+        let expected = ("  bar\n    baz\nqux".to_string(), register, vec![(0, 2)], Mode::Normal);
+        assert_eq!(outcome, expected);
+
+        // the last line takes the line break before it
+        editor.set_cursor(Point { line: 2, column: 1 });
+        let outcome = operate(&mut buffer, &mut editor, Operator::Delete, Motion::Line);
+        let register = Some(("qux\n".to_string(), true));
+        let expected = ("  bar\n    baz".to_string(), register, vec![(1, 4)], Mode::Normal);
+        assert_eq!(outcome, expected);
+
+        // changing lines keeps their indentation, and what's typed undoes with the change
+        let outcome = operate(&mut buffer, &mut editor, Operator::Change, Motion::Line);
+        let register = Some(("    baz\n".to_string(), true));
+        let expected = ("  bar\n    ".to_string(), register, vec![(1, 4)], Mode::Insert);
+        assert_eq!(outcome, expected);
+        editor
+            .command(&mut buffer, Command::InsertChar('x'))
+            .unwrap();
+        editor
+            .command(&mut buffer, Command::SetMode(Mode::Normal))
+            .unwrap();
+        editor
+            .command(&mut buffer, Command::Undo(UndoMove::Undo))
+            .unwrap();
+        assert_eq!(buffer.contents.to_string(), "  bar\n    baz");
+
+        buffer.read_only = true;
+        let delete = Command::Operator(Operator::Delete, Motion::Line, 1);
+        let read_only = Err(Notify("cannot delete: buffer is read-only".into()));
+        assert_eq!(editor.command(&mut buffer, delete), read_only);
+        assert_eq!(editor.register().map(|r| r.text.as_str()), Some("    baz\n"));
+    }
+
+    #[test]
+    fn paste_after_and_before() {
+        fn paste(
+            marked: &str,
+            text: &str,
+            linewise: bool,
+            before: bool,
+        ) -> (String, Vec<(usize, usize)>) {
+            let (mut buffer, mut editor) = editor_on(marked);
+            let register = Register { text: text.to_string(), linewise };
+            editor
+                .command(&mut buffer, Command::Paste(register, before))
+                .unwrap();
+            (buffer.contents.to_string(), cursors(&editor))
+        }
+        // the cursor ends on the last char put
+        assert_eq!(paste("a|bc", "xy", false, false), ("abxyc".into(), vec![(0, 3)]));
+        assert_eq!(paste("a|bc", "xy", false, true), ("axybc".into(), vec![(0, 2)]));
+        assert_eq!(paste("|\nb", "xy", false, false), ("xy\nb".into(), vec![(0, 1)]));
+        assert_eq!(
+            paste("a|b\ncd|e", "x", false, false),
+            ("abx\ncdex".into(), vec![(0, 2), (1, 3)])
+        );
+
+        // lines go below or above, with the cursor on the first non-blank of the first
+        let lines = "  x\ny\n";
+        assert_eq!(
+            paste("a|b\ncd\n", lines, true, false),
+            ("ab\n  x\ny\ncd\n".into(), vec![(1, 2)])
+        );
+        assert_eq!(
+            paste("ab\nc|d\n", lines, true, true),
+            ("ab\n  x\ny\ncd\n".into(), vec![(1, 2)])
+        );
+        // the last line has no line break to put them after
+        assert_eq!(paste("ab\nc|d", lines, true, false), ("ab\ncd\n  x\ny".into(), vec![(2, 2)]));
+        // cursors on one line put them once
+        assert_eq!(
+            paste("|a|b\ncd", "x\n", true, true),
+            ("x\nab\ncd".into(), vec![(0, 0), (1, 1)])
+        );
+
+        let (mut buffer, mut editor) = editor_on("a|b");
+        buffer.read_only = true;
+        let register = Register { text: "x".into(), linewise: false };
+        let read_only = Err(Notify("cannot paste: buffer is read-only".into()));
+        assert_eq!(editor.command(&mut buffer, Command::Paste(register, false)), read_only);
+    }
+
+    #[test]
+    fn last_inserted_is_what_was_typed() {
+        let (mut buffer, mut editor) = editor_on("|ab");
+        assert_eq!(editor.last_inserted(), None);
+        let mut run = |command| editor.command(&mut buffer, command).unwrap();
+        run(Command::SetMode(Mode::Insert));
+        for command in [
+            Command::InsertChar('x'),
+            Command::InsertChar('y'),
+            Command::DeleteBackward,
+            Command::InsertNewline,
+            Command::InsertChar('z'),
+        ] {
+            run(command);
+        }
+        run(Command::SetMode(Mode::Normal));
+        assert_eq!(editor.last_inserted(), Some("x\nz"));
+
+        // a new insert starts over
+        editor
+            .command(&mut buffer, Command::SetMode(Mode::Insert))
+            .unwrap();
+        editor
+            .command(&mut buffer, Command::SetMode(Mode::Normal))
+            .unwrap();
+        assert_eq!(editor.last_inserted(), Some(""));
+    }
+
+    #[test]
+    fn swapping_buffers_keeps_the_cursor_in_each() {
+        let mut buffers = slotmap::SlotMap::with_key();
+        let text = (0..100)
+            .map(|i| format!("line {}\n", i))
+            .collect::<Vec<_>>()
+            .concat();
+        let long = buffers.insert_with_key(|k| Buffer::new(k, text.as_str().into()));
+        let short = buffers.insert_with_key(|k| Buffer::new(k, "a\nbb\nc\nd\ne".into()));
+        let mut editor = Editor::new(Default::default(), long);
+        editor
+            .command(&mut buffers[long], Command::Search("line".into()))
+            .unwrap();
+        editor.set_cursor(Point { line: 90, column: 6 });
+        editor
+            .command(&mut buffers[long], Command::SelectLine)
+            .unwrap();
+        assert_eq!(editor.mode, Mode::VisualLine);
+
+        // a buffer not shown before starts at the top, and nothing carries over to it
+        editor.swap_buffer(&buffers[short]);
+        assert_eq!((editor.buffer_id, editor.alternate()), (short, Some(long)));
+        assert_eq!(cursors(&editor), vec![(0, 0)]);
+        assert_eq!(editor.mode, Mode::Normal);
+        assert!(editor.search().is_none());
+
+        editor.set_cursor(Point { line: 4, column: 0 });
+        editor.swap_buffer(&buffers[long]);
+        assert_eq!(cursors(&editor), vec![(90, 6)]);
+        assert_eq!(editor.mode, Mode::Normal);
+
+        // where the cursor was is kept within what's left of the text
+        buffers[short].contents = "a\nbb\n".into();
+        editor.swap_buffer(&buffers[short]);
+        assert_eq!(cursors(&editor), vec![(2, 0)]);
+        buffers[short].contents = "a\nbbbb".into();
+        editor.set_cursor(Point { line: 1, column: 3 });
+        editor.swap_buffer(&buffers[long]);
+        editor.swap_buffer(&buffers[short]);
+        assert_eq!(cursors(&editor), vec![(1, 3)]);
+        buffers[short].contents = "a\nbb".into();
+        editor.swap_buffer(&buffers[long]);
+        editor.swap_buffer(&buffers[short]);
+        assert_eq!(cursors(&editor), vec![(1, 1)]);
+
+        // nor does the column `j` and `k` were aiming for
+        editor
+            .command(&mut buffers[short], Command::CursorMove(Direction::Up))
+            .unwrap();
+        assert_eq!(editor.selections()[0].goal_column, Some(1));
+        editor.swap_buffer(&buffers[long]);
+        assert_eq!(editor.selections()[0].goal_column, None);
+    }
+
+    #[test]
+    fn binary_buffers_go_by_bytes() {
+        // the bytes of the first row have a line break in them, and so does the match
+        let bytes: Vec<u8> = (0..40).chain([0xde, 0xad, b'\n', 0xbe, 0xef]).collect();
+        let (text, format) = crate::encoding::decode(&bytes).unwrap();
+        let contents = BufferContents::from(text.as_str());
+        let mut buffer = Buffer::open(Default::default(), "a.bin".into(), contents, format);
+        assert!(buffer.is_binary() && buffer.read_only);
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let mut offset = |command| {
+            editor.command(&mut buffer, command).unwrap();
+            buffer.contents.point_to_char_offset(editor.cursor())
+        };
+        assert_eq!(offset(Command::CursorMove(Direction::Down)), 16);
+        assert_eq!(offset(Command::CursorJump(CursorJump::EndOfLine)), 31);
+        assert_eq!(offset(Command::CursorMove(Direction::Right)), 32);
+        // already on the last row
+        assert_eq!(offset(Command::CursorMove(Direction::Down)), 32);
+        assert_eq!(offset(Command::CursorJump(CursorJump::StartOfBuffer)), 0);
+        assert_eq!(offset(Command::Search("de ad".into())), 40);
+        assert_eq!(offset(Command::Search("0abeef".into())), 42);
+
+        let typed = editor.command(&mut buffer, Command::InsertChar('x'));
+        assert_eq!(typed, Err(Notify("cannot insert: buffer is read-only".into())));
     }
 }
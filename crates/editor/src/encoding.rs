@@ -0,0 +1,305 @@
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+const UTF16LE_BOM: &[u8] = b"\xff\xfe";
+const UTF16BE_BOM: &[u8] = b"\xfe\xff";
+/// How much of the start of a file is looked at to tell whether it's binary.
+pub(crate) const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Not text at all, like an executable or an image. Each byte is kept as the char of the
+    /// same value, so the contents can be shown as hex and char offsets are byte offsets.
+    Binary,
+}
+
+/// How a file's text was stored, so saving writes it back the same way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Format {
+    pub encoding: Encoding,
+    pub bom: bool,
+}
+
+impl Format {
+    /// Plain UTF-8, what a file without any marks is taken to be.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Encodes `text` as it was read, BOM included.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(text.len() + 3);
+        match self.encoding {
+            Encoding::Utf8 => {
+                if self.bom {
+                    bytes.extend_from_slice(UTF8_BOM);
+                }
+                bytes.extend_from_slice(text.as_bytes());
+            }
+            Encoding::Utf16Le => {
+                if self.bom {
+                    bytes.extend_from_slice(UTF16LE_BOM);
+                }
+                bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+            }
+            Encoding::Utf16Be => {
+                if self.bom {
+                    bytes.extend_from_slice(UTF16BE_BOM);
+                }
+                bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+            }
+            // only ever latin-1, as binary files are read
+            Encoding::Binary => {
+                bytes.extend(text.chars().map(|c| u8::try_from(c).unwrap_or(b'?')));
+            }
+        }
+        bytes
+    }
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Binary => "binary",
+        })
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.bom {
+            write!(f, "{} bom", self.encoding)
+        } else {
+            write!(f, "{}", self.encoding)
+        }
+    }
+}
+
+/// A file that claims to be, or is assumed to be, Unicode but has bytes that don't decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidEncoding {
+    pub format: Format,
+    /// Byte offset into the file of the first bad sequence.
+    pub offset: usize,
+}
+
+impl std::fmt::Display for InvalidEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid {} at byte {}", self.format.encoding, self.offset)
+    }
+}
+
+impl std::error::Error for InvalidEncoding {}
+
+/// Decodes `bytes`, telling UTF-16 apart from UTF-8 by its BOM. Any BOM is stripped.
+pub fn decode(bytes: &[u8]) -> Result<(String, Format), InvalidEncoding> {
+    let (format, body) = sniff(bytes);
+    let bom_len = bytes.len() - body.len();
+    let text = match format.encoding {
+        Encoding::Utf8 => match std::str::from_utf8(body) {
+            Ok(text) => text.to_string(),
+            Err(err) => {
+                return Err(InvalidEncoding { format, offset: bom_len + err.valid_up_to() })
+            }
+        },
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let mut text = String::with_capacity(body.len() / 2);
+            let mut offset = bom_len;
+            for c in char::decode_utf16(utf16_units(body, format.encoding)) {
+                let Ok(c) = c else {
+                    return Err(InvalidEncoding { format, offset });
+                };
+                text.push(c);
+                offset += c.len_utf16() * 2;
+            }
+            if body.len() % 2 != 0 {
+                return Err(InvalidEncoding { format, offset: bytes.len() - 1 });
+            }
+            text
+        }
+        Encoding::Binary => latin1(body),
+    };
+    Ok((text, format))
+}
+
+/// Like `decode`, but replaces every bad sequence with U+FFFD instead of failing. Returns how
+/// many were replaced.
+pub fn decode_lossy(bytes: &[u8]) -> (String, Format, usize) {
+    let (format, body) = sniff(bytes);
+    let mut text = String::with_capacity(body.len());
+    let mut replaced = 0;
+    match format.encoding {
+        Encoding::Utf8 => {
+            for chunk in body.utf8_chunks() {
+                text.push_str(chunk.valid());
+                if !chunk.invalid().is_empty() {
+                    text.push(char::REPLACEMENT_CHARACTER);
+                    replaced += 1;
+                }
+            }
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            for c in char::decode_utf16(utf16_units(body, format.encoding)) {
+                text.push(c.unwrap_or_else(|_| {
+                    replaced += 1;
+                    char::REPLACEMENT_CHARACTER
+                }));
+            }
+            if body.len() % 2 != 0 {
+                text.push(char::REPLACEMENT_CHARACTER);
+                replaced += 1;
+            }
+        }
+        Encoding::Binary => text = latin1(body),
+    }
+    (text, format, replaced)
+}
+
+/// Whether `bytes` look like anything but text: there's a NUL in the first `BINARY_SNIFF_LEN`
+/// of them, or more than 30% of those are control chars that text doesn't use or aren't UTF-8.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(BINARY_SNIFF_LEN)];
+    if sample.contains(&0) {
+        return true;
+    }
+    let mut odd = 0;
+    for chunk in sample.utf8_chunks() {
+        let controls = chunk.valid().bytes().filter(|b| {
+            // tabs, line breaks, form feeds and escapes are all at home in text
+            matches!(b, 0x01..=0x08 | 0x0e..=0x1a | 0x1c..=0x1f | 0x7f)
+        });
+        odd += controls.count() + chunk.invalid().len();
+    }
+    odd * 10 > sample.len() * 3
+}
+
+/// The text a binary file's bytes are kept as, each the char of the same value.
+pub(crate) fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().copied().map(char::from).collect()
+}
+
+/// How `bytes` are stored, by their BOM or else whether they're binary, and what follows any
+/// BOM.
+pub(crate) fn sniff(bytes: &[u8]) -> (Format, &[u8]) {
+    let boms = [
+        (UTF8_BOM, Encoding::Utf8),
+        (UTF16LE_BOM, Encoding::Utf16Le),
+        (UTF16BE_BOM, Encoding::Utf16Be),
+    ];
+    for (bom, encoding) in boms {
+        if let Some(body) = bytes.strip_prefix(bom) {
+            return (Format { encoding, bom: true }, body);
+        }
+    }
+    if is_binary(bytes) {
+        (Format { encoding: Encoding::Binary, bom: false }, bytes)
+    } else {
+        (Format::default(), bytes)
+    }
+}
+
+fn utf16_units(bytes: &[u8], encoding: Encoding) -> impl Iterator<Item = u16> + '_ {
+    bytes.chunks_exact(2).map(move |pair| match encoding {
+        Encoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+        _ => u16::from_le_bytes([pair[0], pair[1]]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = "héllo\r\nwörld 日本 🦀\n";
+        let fixtures = [
+            (Format { encoding: Encoding::Utf8, bom: false }, text.as_bytes().to_vec()),
+            (
+                Format { encoding: Encoding::Utf8, bom: true },
+                [UTF8_BOM, text.as_bytes()].concat(),
+            ),
+            (
+                Format { encoding: Encoding::Utf16Le, bom: true },
+                [
+                    UTF16LE_BOM.to_vec(),
+                    text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+                ]
+                .concat(),
+            ),
+            (
+                Format { encoding: Encoding::Utf16Be, bom: true },
+                [
+                    UTF16BE_BOM.to_vec(),
+                    text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+                ]
+                .concat(),
+            ),
+        ];
+        for (format, bytes) in fixtures {
+            let (decoded, detected) = decode(&bytes).unwrap();
+            assert_eq!((decoded.as_str(), detected), (text, format));
+            assert_eq!(format.encode(&decoded), bytes, "{}", format);
+        }
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        // a cp1252 curly quote in otherwise UTF-8 text
+        let bytes = b"it\x92s \xef\xbf\xbd ok";
+        let err = decode(bytes).unwrap_err();
+        assert_eq!(err.offset, 2);
+        assert_eq!(err.to_string(), "invalid utf-8 at byte 2");
+
+        let (text, format, replaced) = decode_lossy(bytes);
+        assert_eq!(text, "it\u{fffd}s \u{fffd} ok");
+        assert!(format.is_default());
+        assert_eq!(replaced, 1);
+
+        // a surrogate pair, an unpaired surrogate, then a dangling byte
+        let bytes = b"\xff\xfe\x3e\xd8\x80\xdd\x00\xd8b\x00c";
+        let err = decode(bytes).unwrap_err();
+        assert_eq!(err.to_string(), "invalid utf-16le at byte 6");
+        let (text, _, replaced) = decode_lossy(bytes);
+        assert_eq!((text.as_str(), replaced), ("\u{1f980}\u{fffd}b\u{fffd}", 2));
+    }
+
+    #[test]
+    fn binary_files() {
+        let elf = b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00>\x00";
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+        // no NULs, but mostly bytes that are neither text nor UTF-8
+        let noise: Vec<u8> = (0..200u8).map(|b| b.wrapping_mul(37) | 0x80).collect();
+        for bytes in [&elf[..], &png[..], &noise[..]] {
+            assert!(is_binary(bytes), "{:?}", bytes);
+        }
+        let text = "héllo\twörld\r\n\x1b[1m日本 🦀\x0c\n".repeat(10);
+        // a cp1252 quote here and there isn't enough
+        let cp1252 = b"it\x92s fine, it\x92s text";
+        // a NUL past what's looked at doesn't count
+        let late = [vec![b'a'; BINARY_SNIFF_LEN], vec![0]].concat();
+        for bytes in [text.as_bytes(), &cp1252[..], &late[..], &[]] {
+            assert!(!is_binary(bytes), "{:?}", String::from_utf8_lossy(bytes));
+        }
+
+        // UTF-16 is full of NULs, but its BOM says it's text
+        let utf16: Vec<u8> = [
+            UTF16LE_BOM.to_vec(),
+            "ab".encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        ]
+        .concat();
+        assert_eq!(
+            decode(&utf16).unwrap(),
+            ("ab".to_string(), Format { encoding: Encoding::Utf16Le, bom: true })
+        );
+
+        let (text, format) = decode(elf).unwrap();
+        assert_eq!(format, Format { encoding: Encoding::Binary, bom: false });
+        assert_eq!(text.chars().count(), elf.len());
+        assert_eq!(format.encode(&text), elf);
+    }
+}
@@ -0,0 +1,188 @@
+//! Binary buffers, shown as rows of bytes like `hexdump -C` has them. Each byte is kept in the
+//! contents as the char of the same value, so a char offset is a byte offset.
+
+use crate::editor::Command;
+use crate::{CursorJump, Direction};
+
+/// How many bytes a row shows.
+pub const HEX_ROW_BYTES: usize = 16;
+
+/// Where the hex bytes start in a row, after the offset and two spaces.
+const HEX_START: usize = 10;
+/// Where the ASCII starts in a row, after the hex bytes, the space between their groups, and a
+/// space and a bar.
+const ASCII_START: usize = HEX_START + HEX_ROW_BYTES * 3 + 1 + 2;
+
+/// How many rows `len` bytes take; an empty buffer still shows one, with nothing on it.
+pub fn hex_rows(len: usize) -> usize {
+    len.div_ceil(HEX_ROW_BYTES).max(1)
+}
+
+/// The row of up to `HEX_ROW_BYTES` bytes starting at `offset`: the offset, the bytes in hex in two
+/// groups of eight, and the bytes as ASCII between bars, with a dot for any that isn't printable.
+/// A short row is padded so its ASCII lines up with the rows above it.
+pub fn hex_row(bytes: &[u8], offset: usize) -> String {
+    let mut row = format!("{:08x} ", offset);
+    for i in 0..HEX_ROW_BYTES {
+        if i % 8 == 0 {
+            row.push(' ');
+        }
+        match bytes.get(i) {
+            Some(byte) => row.push_str(&format!("{:02x} ", byte)),
+            None => row.push_str("   "),
+        }
+    }
+    row.push_str(" |");
+    row.extend(bytes.iter().map(|byte| match byte {
+        0x20..=0x7e => char::from(*byte),
+        _ => '.',
+    }));
+    row.push('|');
+    row
+}
+
+/// The column of a row where the hex digits of the byte at `offset` start.
+pub fn hex_column(offset: usize) -> usize {
+    let i = offset % HEX_ROW_BYTES;
+    HEX_START + i * 3 + i / 8
+}
+
+/// The column of a row where the byte at `offset` shows as ASCII.
+pub fn ascii_column(offset: usize) -> usize {
+    ASCII_START + offset % HEX_ROW_BYTES
+}
+
+/// The bytes a query of hex pairs spells out, like `de ad be ef` or `deadbeef`, as the chars a
+/// binary buffer keeps them as; None for anything else.
+pub(crate) fn parse_hex_bytes(query: &str) -> Option<String> {
+    let digits: Vec<u8> = query.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if digits.is_empty() || digits.len() % 2 != 0 || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    let digit = |d: u8| char::from(d).to_digit(16).expect("a hex digit") as u8;
+    let pairs = digits
+        .chunks(2)
+        .map(|pair| char::from(digit(pair[0]) << 4 | digit(pair[1])));
+    Some(pairs.collect())
+}
+
+/// Where `command` takes a cursor on the byte at `offset` of `len`, for the motions that go by
+/// rows and columns; None for anything else, like the word motions, which go by the text as
+/// they would in any buffer. Up and down keep the column, as far as the last byte.
+pub(crate) fn motion(command: &Command, offset: usize, len: usize) -> Option<usize> {
+    let last = len.saturating_sub(1);
+    let start = offset - offset % HEX_ROW_BYTES;
+    let down = |rows: usize| {
+        let row = (offset / HEX_ROW_BYTES + rows).min(last / HEX_ROW_BYTES);
+        (row * HEX_ROW_BYTES + offset % HEX_ROW_BYTES).min(last)
+    };
+    let up = |rows: usize| {
+        let row = (offset / HEX_ROW_BYTES).saturating_sub(rows);
+        row * HEX_ROW_BYTES + offset % HEX_ROW_BYTES
+    };
+    let offset = match command {
+        Command::CursorMove(Direction::Left) => offset.saturating_sub(1),
+        Command::CursorMove(Direction::Right) => (offset + 1).min(last),
+        Command::CursorMove(Direction::Up) => up(1),
+        Command::CursorMove(Direction::Down) => down(1),
+        Command::CursorMoveLines(Direction::Up, rows) => up(*rows),
+        Command::CursorMoveLines(Direction::Down, rows) => down(*rows),
+        Command::CursorJump(CursorJump::StartOfBuffer) => 0,
+        Command::CursorJump(CursorJump::EndOfBuffer) => last / HEX_ROW_BYTES * HEX_ROW_BYTES,
+        Command::CursorJump(CursorJump::StartOfLine) => start,
+        Command::CursorJump(CursorJump::EndOfLine) => (start + HEX_ROW_BYTES - 1).min(last),
+        Command::CursorJump(CursorJump::FirstNonBlankOfNextLine)
+            if start + HEX_ROW_BYTES <= last =>
+        {
+            start + HEX_ROW_BYTES
+        }
+        Command::CursorJump(CursorJump::FirstNonBlankOfNextLine) => offset,
+        _ => return None,
+    };
+    Some(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_like_hexdump() {
+        let bytes = b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00hi\n";
+        assert_eq!(
+            hex_row(&bytes[..16], 0),
+            "00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|"
+        );
+        // the last row is short, but its ASCII still lines up
+        assert_eq!(
+            hex_row(&bytes[16..], 16),
+            "00000010  68 69 0a                                          |hi.|"
+        );
+        assert_eq!(
+            hex_row(&[], 0),
+            "00000000                                                    ||"
+        );
+        assert_eq!((hex_rows(0), hex_rows(16), hex_rows(19)), (1, 1, 2));
+    }
+
+    #[test]
+    fn columns_of_bytes() {
+        let full = hex_row(&[0xab; HEX_ROW_BYTES], 0x120);
+        for offset in [0x120, 0x127, 0x128, 0x12f] {
+            assert_eq!(&full[hex_column(offset)..hex_column(offset) + 2], "ab", "{:x}", offset);
+        }
+        let text = hex_row(b"0123456789abcdef", 0);
+        assert_eq!(&text[ascii_column(0)..ascii_column(0) + 1], "0");
+        assert_eq!(&text[ascii_column(15)..ascii_column(15) + 1], "f");
+    }
+
+    #[test]
+    fn motions_by_byte_offset() {
+        use CursorJump::*;
+        use Direction::*;
+
+        // two full rows and three bytes on a third
+        let len = 35;
+        let cases = [
+            (Command::CursorMove(Left), 0, 0),
+            (Command::CursorMove(Left), 16, 15),
+            (Command::CursorMove(Right), 15, 16),
+            (Command::CursorMove(Right), 34, 34),
+            (Command::CursorMove(Up), 5, 5),
+            (Command::CursorMove(Up), 21, 5),
+            (Command::CursorMove(Down), 5, 21),
+            // down into the short last row stops at its last byte
+            (Command::CursorMove(Down), 20, 34),
+            (Command::CursorMove(Down), 33, 33),
+            (Command::CursorMoveLines(Down, 10), 1, 33),
+            (Command::CursorMoveLines(Up, 10), 33, 1),
+            (Command::CursorJump(StartOfLine), 21, 16),
+            (Command::CursorJump(EndOfLine), 21, 31),
+            (Command::CursorJump(EndOfLine), 33, 34),
+            (Command::CursorJump(StartOfBuffer), 33, 0),
+            (Command::CursorJump(EndOfBuffer), 5, 32),
+            (Command::CursorJump(FirstNonBlankOfNextLine), 5, 16),
+            (Command::CursorJump(FirstNonBlankOfNextLine), 33, 33),
+        ];
+        for (command, offset, expected) in cases {
+            assert_eq!(
+                motion(&command, offset, len),
+                Some(expected),
+                "{:?} from {}",
+                command,
+                offset
+            );
+        }
+        assert_eq!(motion(&Command::CursorJump(StartOfNextWord), 0, len), None);
+        assert_eq!(motion(&Command::CursorMove(Down), 0, 0), Some(0));
+    }
+
+    #[test]
+    fn queries_of_hex_pairs() {
+        assert_eq!(parse_hex_bytes("de ad be ef"), Some("\u{de}\u{ad}\u{be}\u{ef}".to_string()));
+        assert_eq!(parse_hex_bytes("7F454c46"), Some("\u{7f}ELF".to_string()));
+        for query in ["", "abc", "hello", "0x12", "+1"] {
+            assert_eq!(parse_hex_bytes(query), None, "{:?}", query);
+        }
+    }
+}
@@ -1,9 +1,45 @@
+mod audit;
 mod buffer;
+mod diagnostic;
+mod diff;
 mod editor;
+mod encoding;
+mod hex;
+mod load;
 mod movement;
+mod operator;
+mod scroll;
+mod search;
+mod settings;
+mod snapshot;
+mod text_object;
+mod undo;
+mod words;
+mod wrap;
 
+pub use audit::{AuditFailure, LineCountMismatch};
 pub use buffer::{
-    Buffer, Command as BufferCommand, Contents as BufferContents, Highlights, Id as BufferId,
+    Buffer, Command as BufferCommand, Contents as BufferContents, DiskStamp, DiskState, EditError,
+    Highlights, Id as BufferId,
 };
-pub use editor::{Command as EditorCommand, CursorJump, Direction, Editor, Id as EditorId, Mode};
-pub use tore::Point;
+pub use diagnostic::{Diagnostic, Severity};
+pub use diff::{
+    line_diff, line_edits, AlignedRow, DiffAlignment, DiffSide, LineChange, LineChangeKind,
+};
+pub use editor::{
+    Command as EditorCommand, CursorJump, Direction, Editor, GroupId, Id as EditorId, MarkJump,
+    Mode, Notify, Register, Selection,
+};
+pub use encoding::{Encoding, Format, InvalidEncoding};
+pub use hex::{ascii_column, hex_column, hex_row, hex_rows, HEX_ROW_BYTES};
+pub use load::{CancellationToken, Cancelled, Loader};
+pub use operator::{resolve_operator, Motion, MotionKind, Operator};
+pub use scroll::{ScrollAnimation, SMOOTH_SCROLL_TIME};
+pub use search::{Search, SearchMode};
+pub use settings::{IndentStyle, LineEnding, Settings, SettingsOverlay};
+pub use snapshot::{BufferSnapshot, EditorSnapshot};
+pub use text_object::TextObject;
+pub use tore::{Point, PointRange};
+pub use undo::{HistoryAccounting, StateId as UndoStateId, UndoMove, UndoState, UndoTree};
+pub use words::WordIndex;
+pub use wrap::{LineLayout, WrapLayout, WrapSettings};
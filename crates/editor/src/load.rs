@@ -0,0 +1,279 @@
+use anyhow::Result;
+use rope::compat::RopeBuilder;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::buffer::Contents;
+use crate::encoding::{self, Encoding, Format, InvalidEncoding};
+
+/// How much is read at a time; progress is reported, and cancellation checked, once per chunk.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Tells a load to stop. Clones share the one flag, so the loader can keep one while whoever
+/// started it keeps another.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A load that was cancelled before it finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Reads text a chunk at a time, decoding UTF-8 straight into a rope so nothing but the rope
+/// grows with the file. UTF-16 is only told apart by its BOM, and binary files by their bytes,
+/// once the first of them are in. UTF-16 is kept whole until the end to be decoded in one go;
+/// binary files are kept a byte to a char as they come.
+///
+/// Call `next` until it returns None, then `finish` for the contents.
+pub struct Loader<R> {
+    reader: R,
+    total: Option<u64>,
+    lossy: bool,
+    cancel: CancellationToken,
+    /// Bytes read so far.
+    read: u64,
+    /// Bytes of the input before `pending`.
+    decoded: usize,
+    format: Option<Format>,
+    /// Bytes read but not yet decoded: the start of the input until its format can be made out,
+    /// a UTF-8 sequence split by the end of a chunk, or all of a UTF-16 input.
+    pending: Vec<u8>,
+    builder: RopeBuilder,
+    replaced: usize,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> Loader<R> {
+    /// A loader of `reader`, which holds `total` bytes if that's known. A `lossy` load replaces
+    /// whatever doesn't decode with U+FFFD instead of failing.
+    pub fn new(reader: R, total: Option<u64>, lossy: bool, cancel: CancellationToken) -> Self {
+        Self {
+            reader,
+            total,
+            lossy,
+            cancel,
+            read: 0,
+            decoded: 0,
+            format: None,
+            pending: vec![],
+            builder: RopeBuilder::new(),
+            replaced: 0,
+            done: false,
+        }
+    }
+
+    /// How many bytes there are to read, if that's known.
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    /// Reads and decodes the next chunk, returning how many bytes have been read in all, or
+    /// None once the input is exhausted. Fails with `Cancelled` if the load was cancelled
+    /// since the last chunk, or with `InvalidEncoding` at the first bytes that don't decode.
+    pub async fn next(&mut self) -> Option<Result<u64>> {
+        if self.done {
+            return None;
+        }
+        if self.cancel.is_cancelled() {
+            self.done = true;
+            return Some(Err(Cancelled.into()));
+        }
+        let start = self.pending.len();
+        self.pending.resize(start + CHUNK_SIZE, 0);
+        let read = match self.reader.read(&mut self.pending[start..]).await {
+            Ok(read) => read,
+            Err(err) => {
+                self.done = true;
+                return Some(Err(err.into()));
+            }
+        };
+        self.pending.truncate(start + read);
+        self.read += read as u64;
+        let eof = read == 0;
+        if let Err(err) = self.decode(eof) {
+            self.done = true;
+            return Some(Err(err.into()));
+        }
+        if eof {
+            self.done = true;
+            return None;
+        }
+        Some(Ok(self.read))
+    }
+
+    /// The contents read, how they were encoded, and how many bad sequences a lossy load
+    /// replaced.
+    pub fn finish(self) -> (Contents, Format, usize) {
+        let format = self.format.unwrap_or_default();
+        (Contents(self.builder.finish()), format, self.replaced)
+    }
+
+    /// Decodes as much of `pending` as can be, all of it at the end of the input.
+    fn decode(&mut self, eof: bool) -> Result<(), InvalidEncoding> {
+        let format = match self.format {
+            Some(format) => format,
+            // binary files are told apart by their start, which is longer than any BOM
+            None if self.pending.len() < encoding::BINARY_SNIFF_LEN && !eof => return Ok(()),
+            None => {
+                let (format, body) = encoding::sniff(&self.pending);
+                if format.encoding == Encoding::Utf8 {
+                    let bom_len = self.pending.len() - body.len();
+                    self.pending.drain(..bom_len);
+                    self.decoded = bom_len;
+                }
+                self.format = Some(format);
+                format
+            }
+        };
+
+        match format.encoding {
+            Encoding::Utf8 => self.decode_utf8(format, eof),
+            Encoding::Utf16Le | Encoding::Utf16Be if eof => {
+                let text = if self.lossy {
+                    let (text, _, replaced) = encoding::decode_lossy(&self.pending);
+                    self.replaced += replaced;
+                    text
+                } else {
+                    encoding::decode(&self.pending)?.0
+                };
+                self.builder.append(&text);
+                Ok(())
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => Ok(()),
+            Encoding::Binary => {
+                self.builder.append(&encoding::latin1(&self.pending));
+                self.decoded += self.pending.len();
+                self.pending.clear();
+                Ok(())
+            }
+        }
+    }
+
+    fn decode_utf8(&mut self, format: Format, eof: bool) -> Result<(), InvalidEncoding> {
+        let mut start = 0;
+        while start < self.pending.len() {
+            let err = match std::str::from_utf8(&self.pending[start..]) {
+                Ok(text) => {
+                    self.builder.append(text);
+                    start = self.pending.len();
+                    break;
+                }
+                Err(err) => err,
+            };
+            let valid = start + err.valid_up_to();
+            let text = std::str::from_utf8(&self.pending[start..valid]).expect("valid up to here");
+            self.builder.append(text);
+            start = valid;
+            let bad = match err.error_len() {
+                // a sequence cut short by the end of the chunk
+                None if !eof => break,
+                None => self.pending.len() - valid,
+                Some(len) => len,
+            };
+            if !self.lossy {
+                return Err(InvalidEncoding { format, offset: self.decoded + valid });
+            }
+            self.builder
+                .append(char::REPLACEMENT_CHARACTER.encode_utf8(&mut [0; 4]));
+            self.replaced += 1;
+            start += bad;
+        }
+        self.pending.drain(..start);
+        self.decoded += start;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn load(bytes: &[u8], lossy: bool) -> Result<(String, Format, usize)> {
+        let mut loader = Loader::new(bytes, Some(bytes.len() as u64), lossy, Default::default());
+        while let Some(read) = loader.next().await {
+            read?;
+        }
+        let (contents, format, replaced) = loader.finish();
+        Ok((contents.to_string(), format, replaced))
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(future)
+    }
+
+    #[test]
+    fn matches_decode() {
+        // big enough that chunks end partway through the multi-byte chars
+        let text = "héllo wörld 日本 🦀\n".repeat(20_000);
+        let fixtures = [
+            text.as_bytes().to_vec(),
+            [b"\xef\xbb\xbf", text.as_bytes()].concat(),
+            [
+                b"\xff\xfe".to_vec(),
+                text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            ]
+            .concat(),
+            b"".to_vec(),
+            b"a".to_vec(),
+            // binary, and long enough to come in several chunks
+            (0..=255u8).cycle().take(CHUNK_SIZE * 3).collect(),
+        ];
+        for bytes in fixtures {
+            let loaded = block_on(load(&bytes, false)).unwrap();
+            let (text, format) = encoding::decode(&bytes).unwrap();
+            assert_eq!(loaded, (text, format, 0));
+        }
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut bytes = "é".repeat(CHUNK_SIZE).into_bytes();
+        bytes.insert(CHUNK_SIZE + 1, b'\x92');
+        bytes.extend(b"\xe6\x97");
+        let err = block_on(load(&bytes, false)).unwrap_err();
+        let err = err.downcast_ref::<InvalidEncoding>().unwrap();
+        assert_eq!(err, &encoding::decode(&bytes).unwrap_err());
+
+        let loaded = block_on(load(&bytes, true)).unwrap();
+        assert_eq!(loaded, encoding::decode_lossy(&bytes));
+    }
+
+    #[test]
+    fn cancel_between_chunks() {
+        let bytes = vec![b'a'; CHUNK_SIZE * 4];
+        let cancel = CancellationToken::new();
+        let mut loader = Loader::new(&bytes[..], None, false, cancel.clone());
+        block_on(async {
+            assert_eq!(loader.next().await.unwrap().unwrap(), CHUNK_SIZE as u64);
+            cancel.cancel();
+            let err = loader.next().await.unwrap().unwrap_err();
+            assert!(err.is::<Cancelled>());
+            assert!(loader.next().await.is_none());
+        });
+    }
+}
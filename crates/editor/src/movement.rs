@@ -1,257 +1,644 @@
 use tore::Point;
 
-use crate::{Buffer, Editor};
+use crate::{Buffer, CursorJump, Direction, Editor, Mode, WrapLayout, WrapSettings};
 
 impl Editor {
-    pub fn cursor_move_left(&mut self, _buffer: &Buffer) {
-        self.cursor.move_prev_column();
+    pub fn cursor_move_left(&mut self, buffer: &Buffer) {
+        self.move_cursors(|head| cursor_move_left(buffer, head));
     }
 
     pub fn cursor_move_up(&mut self, buffer: &Buffer) {
-        self.cursor.move_prev_line();
-        // match buffer.contents.line(self.cursor.line) {
-        //     None => (),
-        //     Some(line) => {
-        //         let len = if !line.is_empty() { line.len() - 1 } else { 0 };
-        //         self.cursor.column = std::cmp::min(len, self.cursor.column)
-        //     }
-        // }
-        let line = buffer.contents.line(self.cursor.line);
-        let len = line.len_chars();
-        let len = if len == 0 { 0 } else { len - 1 };
-        self.cursor.column = std::cmp::min(len, self.cursor.column);
+        if let Some(settings) = self.wrap {
+            return self.cursor_move_row(buffer, settings, Direction::Up);
+        }
+        self.cursor_move_lines(buffer, Direction::Up, 1);
     }
 
     pub fn cursor_move_right(&mut self, buffer: &Buffer) {
-        self.cursor.move_next_column();
-        // match buffer.contents.char_at(self.cursor) {
-        //     None | Some('\n') => self.cursor.move_prev_column(),
-        //     _ => (),
-        // }
-        let line = buffer.contents.line(self.cursor.line);
-        match line.chars().nth(self.cursor.column) {
-            None | Some('\n') => self.cursor.move_prev_column(),
-            _ => (),
-        }
+        let mode = self.mode;
+        self.move_cursors(|head| cursor_move_right(buffer, head, mode));
     }
 
     pub fn cursor_move_down(&mut self, buffer: &Buffer) {
-        self.cursor.move_next_line();
-        // match buffer.contents.line(self.cursor.line) {
-        //     None => self.cursor.move_prev_line(),
-        //     Some(line) => {
-        //         let len = if !line.is_empty() { line.len() - 1 } else { 0 };
-        //         self.cursor.column = std::cmp::min(len, self.cursor.column);
-        //     }
-        // }
-        let line = buffer.contents.line(self.cursor.line);
-        let len = line.len_chars();
-        let len = if len == 0 { 0 } else { len - 1 };
-        self.cursor.column = std::cmp::min(len, self.cursor.column);
+        if let Some(settings) = self.wrap {
+            return self.cursor_move_row(buffer, settings, Direction::Down);
+        }
+        self.cursor_move_lines(buffer, Direction::Down, 1);
+    }
+
+    /// Moves each cursor a screen row up or down, through the rows wrapped lines go on over.
+    fn cursor_move_row(&mut self, buffer: &Buffer, settings: WrapSettings, direction: Direction) {
+        let mode = self.mode;
+        let mut layout = std::mem::take(self.layout());
+        self.move_cursors_vertically(|head, goal| {
+            cursor_move_row(buffer, &mut layout, settings, head, goal, &direction, mode)
+        });
+        *self.layout() = layout;
+    }
+
+    pub fn cursor_jump_end_of_buffer(&mut self, buffer: &Buffer) {
+        self.move_cursors(|head| cursor_jump_end_of_buffer(buffer, head));
     }
 
-    pub fn cursor_jump_line_zero(&mut self, _buffer: &Buffer) {
-        self.cursor.column = 0;
+    pub fn cursor_jump_line_zero(&mut self, buffer: &Buffer) {
+        self.move_cursors(|head| cursor_jump_line_zero(buffer, head));
+    }
+
+    pub fn cursor_jump_end_of_line(&mut self, buffer: &Buffer) {
+        let mode = self.mode;
+        self.move_cursors(|head| cursor_jump_end_of_line(buffer, head, mode));
+    }
+
+    pub fn cursor_jump_first_non_blank_of_next_line(&mut self, buffer: &Buffer) {
+        let mode = self.mode;
+        self.move_cursors(|head| cursor_jump_first_non_blank_of_next_line(buffer, head, mode));
+    }
+
+    pub fn cursor_move_lines(&mut self, buffer: &Buffer, direction: Direction, lines: usize) {
+        let mode = self.mode;
+        self.move_cursors_vertically(|head, goal| {
+            cursor_move_lines(buffer, head, goal, &direction, lines, mode)
+        });
     }
 
     pub fn cursor_jump_start_of_nearest_word(&mut self, buffer: &Buffer) {
-        let line_offset = buffer.contents.line_to_char(self.cursor.line);
-        let mut offset = line_offset + self.cursor.column;
-
-        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-        enum State {
-            Init,
-            SkipWord,
-            SkipPunctuation,
-            SkipWhitespace,
-            Done,
+        self.move_cursors(|head| cursor_jump_start_of_nearest_word(buffer, head));
+    }
+
+    pub fn cursor_jump_start_of_last_word(&mut self, buffer: &Buffer) {
+        self.move_cursors(|head| cursor_jump_start_of_last_word(buffer, head));
+    }
+
+    pub fn cursor_jump_end_of_nearest_word(&mut self, buffer: &Buffer) {
+        self.move_cursors(|head| cursor_jump_end_of_nearest_word(buffer, head));
+    }
+
+    pub fn cursor_jump_start_of_next_word(&mut self, buffer: &Buffer) {
+        self.move_cursors(|head| cursor_jump_start_of_next_word(buffer, head));
+    }
+}
+
+/// Where moving in `direction` takes a cursor at `cursor` in `mode`.
+pub(crate) fn step(buffer: &Buffer, cursor: Point, direction: &Direction, mode: Mode) -> Point {
+    match direction {
+        Direction::Up => cursor_move_up(buffer, cursor, mode),
+        Direction::Down => cursor_move_down(buffer, cursor, mode),
+        Direction::Left => cursor_move_left(buffer, cursor),
+        Direction::Right => cursor_move_right(buffer, cursor, mode),
+    }
+}
+
+/// Where `jump` takes a cursor at `cursor` in `mode`.
+pub(crate) fn jump(buffer: &Buffer, cursor: Point, jump: &CursorJump, mode: Mode) -> Point {
+    match jump {
+        CursorJump::StartOfNextWord => cursor_jump_start_of_next_word(buffer, cursor),
+        CursorJump::StartOfLastWord => cursor_jump_start_of_last_word(buffer, cursor),
+        CursorJump::EndOfNearestWord => cursor_jump_end_of_nearest_word(buffer, cursor),
+        CursorJump::StartOfNearestWord => cursor_jump_start_of_nearest_word(buffer, cursor),
+        CursorJump::StartOfBuffer => Point::default(),
+        CursorJump::EndOfBuffer => cursor_jump_end_of_buffer(buffer, cursor),
+        CursorJump::StartOfLine => cursor_jump_line_zero(buffer, cursor),
+        CursorJump::EndOfLine => cursor_jump_end_of_line(buffer, cursor, mode),
+        CursorJump::FirstNonBlankOfNextLine => {
+            cursor_jump_first_non_blank_of_next_line(buffer, cursor, mode)
         }
+    }
+}
 
-        let mut state = State::Init;
-        let mut chars = buffer.contents.chars_at(offset);
-        loop {
-            match state {
-                State::Done => break,
-                _ => match chars.prev() {
-                    None => break,
-                    Some(char) => {
-                        offset -= 1;
-                        match state {
-                            State::Done => unreachable!("invalid state"),
-                            State::Init | State::SkipWhitespace => {
-                                if char.is_alphanumeric() {
-                                    state = State::SkipWord;
-                                } else if char.is_ascii_punctuation() {
-                                    state = State::SkipPunctuation;
-                                } else if is_whitespace(char) {
-                                    state = State::SkipWhitespace;
-                                } else {
-                                    state = State::Done;
-                                }
-                            }
-                            State::SkipWord => {
-                                if char.is_alphanumeric() {
-                                    state = State::SkipWord;
-                                } else {
-                                    offset += 1;
-                                    state = State::Done;
-                                }
+fn cursor_move_left(_buffer: &Buffer, mut cursor: Point) -> Point {
+    cursor.move_prev_column();
+    cursor
+}
+
+pub(crate) fn cursor_move_up(buffer: &Buffer, mut cursor: Point, mode: Mode) -> Point {
+    cursor.move_prev_line();
+    cursor.column = cursor
+        .column
+        .min(buffer.contents.max_cursor_column(cursor.line, mode));
+    cursor
+}
+
+fn cursor_move_right(buffer: &Buffer, mut cursor: Point, mode: Mode) -> Point {
+    cursor.move_next_column();
+    cursor.column = cursor
+        .column
+        .min(buffer.contents.max_cursor_column(cursor.line, mode));
+    cursor
+}
+
+pub(crate) fn cursor_move_down(buffer: &Buffer, mut cursor: Point, mode: Mode) -> Point {
+    // the last line has nowhere to go
+    if cursor.line + 1 >= buffer.contents.len_lines() {
+        return cursor;
+    }
+    cursor.move_next_line();
+    cursor.column = cursor
+        .column
+        .min(buffer.contents.max_cursor_column(cursor.line, mode));
+    cursor
+}
+
+/// Where moving a screen row up or down takes a cursor at `cursor`, with the lines laid out by
+/// `layout`: as many chars along the row it lands on as it was along its own, or as `goal` has it
+/// aim for, less the indent either row starts with, and never past the row's last char. Also
+/// hands back how far along it aimed for.
+fn cursor_move_row(
+    buffer: &Buffer,
+    layout: &mut WrapLayout,
+    settings: WrapSettings,
+    cursor: Point,
+    goal: Option<usize>,
+    direction: &Direction,
+    mode: Mode,
+) -> (Point, usize) {
+    let line = layout.line(buffer, settings, cursor.line);
+    let within = line.row_of_column(cursor.column);
+    let (start, indent) = line.row_start(within);
+    let along = goal.unwrap_or(indent + cursor.column - start);
+    let row = layout.row_of_line(buffer, settings, cursor.line) + within;
+    let row = match direction {
+        Direction::Up if row == 0 => return (cursor, along),
+        Direction::Up => row - 1,
+        _ => row + 1,
+    };
+    if row >= layout.total_rows(buffer, settings) {
+        return (cursor, along);
+    }
+    let (line, within) = layout.line_at_row(buffer, settings, row);
+    let rows = layout.line(buffer, settings, line);
+    let (start, indent) = rows.row_start(within);
+    let mut column = start + along.saturating_sub(indent);
+    if let Some(next) = rows.breaks.get(within) {
+        column = column.min(next - 1);
+    }
+    let column = column.min(buffer.contents.max_cursor_column(line, mode));
+    (Point { line, column }, along)
+}
+
+fn cursor_jump_end_of_buffer(buffer: &Buffer, _cursor: Point) -> Point {
+    let line = buffer.contents.len_lines().saturating_sub(1);
+    Point { line, column: 0 }
+}
+
+fn cursor_jump_line_zero(_buffer: &Buffer, mut cursor: Point) -> Point {
+    cursor.column = 0;
+    cursor
+}
+
+fn cursor_jump_end_of_line(buffer: &Buffer, mut cursor: Point, mode: Mode) -> Point {
+    cursor.column = buffer.contents.max_cursor_column(cursor.line, mode);
+    cursor
+}
+
+fn cursor_jump_first_non_blank_of_next_line(buffer: &Buffer, cursor: Point, mode: Mode) -> Point {
+    if cursor.line + 1 >= buffer.contents.len_lines() {
+        return cursor;
+    }
+    let line = cursor.line + 1;
+    let blanks = buffer
+        .contents
+        .line(line)
+        .chars()
+        .take_while(|c| matches!(c, ' ' | '\t'))
+        .count();
+    // a line of nothing but blanks leaves the cursor at its end
+    let column = blanks.min(buffer.contents.max_cursor_column(line, mode));
+    Point { line, column }
+}
+
+/// Where moving `lines` lines up or down takes a cursor at `cursor`, aiming for column `goal`
+/// if it has one rather than its own; and the column it aimed for.
+fn cursor_move_lines(
+    buffer: &Buffer,
+    cursor: Point,
+    goal: Option<usize>,
+    direction: &Direction,
+    lines: usize,
+    mode: Mode,
+) -> (Point, usize) {
+    let goal = goal.unwrap_or(cursor.column);
+    let last = buffer.contents.len_lines().saturating_sub(1);
+    let line = match direction {
+        Direction::Up => cursor.line.saturating_sub(lines),
+        Direction::Down => (cursor.line + lines).min(last),
+        Direction::Left | Direction::Right => return (cursor, goal),
+    };
+    let column = goal.min(buffer.contents.max_cursor_column(line, mode));
+    (Point { line, column }, goal)
+}
+
+pub(crate) fn cursor_jump_start_of_nearest_word(buffer: &Buffer, cursor: Point) -> Point {
+    let line_offset = buffer.contents.line_to_char(cursor.line);
+    let mut offset = line_offset + cursor.column;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum State {
+        Init,
+        SkipWord,
+        SkipPunctuation,
+        SkipWhitespace,
+        Done,
+    }
+
+    let mut state = State::Init;
+    let mut chars = buffer.contents.chars_at(offset);
+    loop {
+        match state {
+            State::Done => break,
+            _ => match chars.prev() {
+                None => break,
+                Some(char) => {
+                    offset -= 1;
+                    match state {
+                        State::Done => unreachable!("invalid state"),
+                        State::Init | State::SkipWhitespace => {
+                            state = match CharClass::of(char) {
+                                CharClass::Word => State::SkipWord,
+                                CharClass::Punctuation => State::SkipPunctuation,
+                                CharClass::Whitespace => State::SkipWhitespace,
+                            };
+                        }
+                        State::SkipWord => {
+                            if CharClass::of(char) == CharClass::Word {
+                                state = State::SkipWord;
+                            } else {
+                                offset += 1;
+                                state = State::Done;
                             }
-                            State::SkipPunctuation => {
-                                if char.is_ascii_punctuation() {
-                                    state = State::SkipPunctuation;
-                                } else {
-                                    offset += 1;
-                                    state = State::Done;
-                                }
+                        }
+                        State::SkipPunctuation => {
+                            if CharClass::of(char) == CharClass::Punctuation {
+                                state = State::SkipPunctuation;
+                            } else {
+                                offset += 1;
+                                state = State::Done;
                             }
                         }
                     }
-                },
-            }
+                }
+            },
         }
-
-        let line = buffer.contents.char_to_line(offset);
-        let column = offset - buffer.contents.line_to_char(line);
-        self.cursor = Point { line, column };
     }
 
-    pub fn cursor_jump_start_of_last_word(&mut self, _buffer: &Buffer) {
-        // ge
-        todo!()
-    }
+    let line = buffer.contents.char_to_line(offset);
+    let column = offset - buffer.contents.line_to_char(line);
+    Point { line, column }
+}
 
-    pub fn cursor_jump_end_of_nearest_word(&mut self, buffer: &Buffer) {
-        let mut offset = buffer.contents.point_to_char_offset(self.cursor);
-
-        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-        enum State {
-            Init,
-            SkipWord,
-            SkipPunctuation,
-            SkipWhitespace,
-            Done,
-        }
+fn cursor_jump_start_of_last_word(_buffer: &Buffer, _cursor: Point) -> Point {
+    // ge
+    todo!()
+}
 
-        offset += 1;
-        let mut state = State::Init;
-        let mut chars = buffer.contents.chars_at(offset);
-        loop {
-            match state {
-                State::Done => break,
-                _ => match chars.next() {
-                    None => break,
-                    Some(char) => {
-                        offset += 1;
-                        match state {
-                            State::Done => unreachable!("invalid state"),
-                            State::Init | State::SkipWhitespace => {
-                                if char.is_alphanumeric() {
-                                    state = State::SkipWord;
-                                } else if char.is_ascii_punctuation() {
-                                    state = State::SkipPunctuation;
-                                } else if is_whitespace(char) {
-                                    state = State::SkipWhitespace;
-                                } else {
-                                    offset -= 1;
-                                    state = State::Done;
-                                }
-                            }
-                            State::SkipWord => {
-                                if char.is_alphanumeric() {
-                                    state = State::SkipWord;
-                                } else {
-                                    offset -= 2;
-                                    state = State::Done;
-                                }
+fn cursor_jump_end_of_nearest_word(buffer: &Buffer, cursor: Point) -> Point {
+    let mut offset = buffer.contents.point_to_char_offset(cursor);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum State {
+        Init,
+        SkipWord,
+        SkipPunctuation,
+        SkipWhitespace,
+        Done,
+    }
+
+    offset += 1;
+    let mut state = State::Init;
+    let mut chars = buffer.contents.chars_at(offset);
+    loop {
+        match state {
+            State::Done => break,
+            _ => match chars.next() {
+                None => {
+                    // the word runs to the end of the buffer; land on its last char.
+                    if matches!(state, State::SkipWord | State::SkipPunctuation) {
+                        offset -= 1;
+                    }
+                    break;
+                }
+                Some(char) => {
+                    offset += 1;
+                    match state {
+                        State::Done => unreachable!("invalid state"),
+                        State::Init | State::SkipWhitespace => {
+                            state = match CharClass::of(char) {
+                                CharClass::Word => State::SkipWord,
+                                CharClass::Punctuation => State::SkipPunctuation,
+                                CharClass::Whitespace => State::SkipWhitespace,
+                            };
+                        }
+                        State::SkipWord => {
+                            if CharClass::of(char) == CharClass::Word {
+                                state = State::SkipWord;
+                            } else {
+                                offset -= 2;
+                                state = State::Done;
                             }
-                            State::SkipPunctuation => {
-                                if char.is_ascii_punctuation() {
-                                    state = State::SkipPunctuation;
-                                } else {
-                                    offset -= 2;
-                                    state = State::Done;
-                                }
+                        }
+                        State::SkipPunctuation => {
+                            if CharClass::of(char) == CharClass::Punctuation {
+                                state = State::SkipPunctuation;
+                            } else {
+                                offset -= 2;
+                                state = State::Done;
                             }
                         }
                     }
-                },
-            }
+                }
+            },
         }
-
-        self.cursor = buffer.contents.char_offset_to_point(offset);
     }
 
-    pub fn cursor_jump_start_of_next_word(&mut self, buffer: &Buffer) {
-        let mut offset = buffer.contents.point_to_char_offset(self.cursor);
-
-        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-        enum State {
-            Init,
-            SkipWord,
-            SkipPunctuation,
-            SkipWhitespace,
-            Done,
-        }
+    buffer.contents.char_offset_to_point(offset)
+}
 
-        let mut chars = buffer.contents.chars_at(offset);
-        let mut state = State::Init;
-        loop {
-            match state {
-                State::Done => break,
-                _ => match chars.next() {
-                    None => break,
-                    Some(char) => {
-                        offset += 1;
-                        match state {
-                            State::Done => unreachable!("invalid state"),
-                            State::Init => {
-                                if char.is_alphanumeric() {
-                                    state = State::SkipWord;
-                                } else if char.is_ascii_punctuation() {
-                                    state = State::SkipPunctuation;
-                                } else if is_whitespace(char) {
-                                    state = State::SkipWhitespace;
-                                } else {
-                                    state = State::Done;
-                                }
-                            }
-                            State::SkipWhitespace => {
-                                if char == ' ' || char == '\t' || char == '\r' || char == '\n' {
-                                    state = State::SkipWhitespace;
-                                } else {
-                                    offset -= 1;
-                                    state = State::Done;
-                                }
+fn cursor_jump_start_of_next_word(buffer: &Buffer, cursor: Point) -> Point {
+    let mut offset = buffer.contents.point_to_char_offset(cursor);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum State {
+        Init,
+        SkipWord,
+        SkipPunctuation,
+        SkipWhitespace,
+        Done,
+    }
+
+    let mut chars = buffer.contents.chars_at(offset);
+    let mut state = State::Init;
+    loop {
+        match state {
+            State::Done => break,
+            _ => match chars.next() {
+                None => break,
+                Some(char) => {
+                    offset += 1;
+                    match state {
+                        State::Done => unreachable!("invalid state"),
+                        State::Init => {
+                            state = match CharClass::of(char) {
+                                CharClass::Word => State::SkipWord,
+                                CharClass::Punctuation => State::SkipPunctuation,
+                                CharClass::Whitespace => State::SkipWhitespace,
+                            };
+                        }
+                        State::SkipWhitespace => {
+                            if CharClass::of(char) == CharClass::Whitespace {
+                                state = State::SkipWhitespace;
+                            } else {
+                                offset -= 1;
+                                state = State::Done;
                             }
-                            State::SkipWord => {
-                                if char.is_alphanumeric() {
-                                    state = State::SkipWord;
-                                } else {
-                                    chars.prev();
-                                    offset -= 1;
-                                    state = State::SkipWhitespace;
-                                }
+                        }
+                        State::SkipWord => {
+                            if CharClass::of(char) == CharClass::Word {
+                                state = State::SkipWord;
+                            } else {
+                                chars.prev();
+                                offset -= 1;
+                                state = State::SkipWhitespace;
                             }
-                            State::SkipPunctuation => {
-                                if char.is_ascii_punctuation() {
-                                    state = State::SkipPunctuation;
-                                } else {
-                                    chars.prev();
-                                    offset -= 1;
-                                    state = State::SkipWhitespace;
-                                }
+                        }
+                        State::SkipPunctuation => {
+                            if CharClass::of(char) == CharClass::Punctuation {
+                                state = State::SkipPunctuation;
+                            } else {
+                                chars.prev();
+                                offset -= 1;
+                                state = State::SkipWhitespace;
                             }
                         }
                     }
-                },
-            }
+                }
+            },
         }
+    }
 
-        self.cursor = buffer.contents.char_offset_to_point(offset);
+    buffer.contents.char_offset_to_point(offset)
+}
+
+/// Character classes word motions move across; a word is a run of characters of the same class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CharClass {
+    Word,
+    Punctuation,
+    Whitespace,
+}
+
+impl CharClass {
+    pub(crate) fn of(char: char) -> Self {
+        if char.is_alphanumeric() || char == '_' {
+            CharClass::Word
+        } else if char.is_whitespace() {
+            CharClass::Whitespace
+        } else {
+            // like vim, anything else that isn't blank (symbols, typographic quotes, dashes,
+            // CJK punctuation) groups together as punctuation.
+            CharClass::Punctuation
+        }
     }
 }
 
-fn is_whitespace(char: char) -> bool {
-    char == ' ' || char == '\t' || char == '\r' || char == '\n'
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Contents;
+    use crate::{CursorJump, Mode};
+
+    fn jump(text: &str, column: usize, jump: CursorJump) -> usize {
+        let mut buffer =
+            Buffer::new(Default::default(), Contents(rope::compat::Rope::from_str(text)));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(Point { line: 0, column });
+        editor
+            .command(&mut buffer, crate::EditorCommand::CursorJump(jump))
+            .unwrap();
+        editor.cursor().column
+    }
+
+    #[test]
+    fn word_motions() {
+        use CursorJump::*;
+        #[rustfmt::skip]
+        let cases = [
+            ("foo_bar baz", 0, StartOfNextWord, 8),
+            ("foo_bar baz", 0, EndOfNearestWord, 6),
+            ("foo_bar baz", 6, EndOfNearestWord, 10),
+            ("foo_bar baz", 8, StartOfNearestWord, 0),
+            ("a\u{a0}b", 0, StartOfNextWord, 2),
+            ("a\u{a0}b", 2, StartOfNearestWord, 0),
+            ("foo\u{2014}bar", 0, StartOfNextWord, 3),
+            ("foo\u{2014}bar", 3, StartOfNextWord, 4),
+            ("foo\u{2014}bar", 0, EndOfNearestWord, 2),
+            ("foo\u{2014}bar", 4, StartOfNearestWord, 3),
+            ("\u{201c}quoted\u{201d} text", 0, StartOfNextWord, 1),
+            ("\u{201c}quoted\u{201d} text", 1, StartOfNextWord, 7),
+            ("\u{201c}quoted\u{201d} text", 1, EndOfNearestWord, 6),
+            ("\u{201c}quoted\u{201d} text", 7, StartOfNextWord, 9),
+            ("文字。次", 0, StartOfNextWord, 2),
+            ("文字。次", 2, StartOfNextWord, 3),
+            ("文字。次", 0, EndOfNearestWord, 1),
+            ("文字。次", 3, StartOfNearestWord, 2),
+            ("文字。次", 2, StartOfNearestWord, 0),
+        ];
+        for (text, column, motion, expected) in cases {
+            let actual = jump(text, column, motion.clone());
+            assert_eq!(actual, expected, "{:?} from {} in {:?}", motion, column, text);
+        }
+    }
+
+    #[test]
+    fn line_motions() {
+        use crate::{Direction, EditorCommand};
+
+        let text = "abc\n  de\n \nfg\r\nh";
+        let mut buffer = Buffer::new(Default::default(), Contents::from(text));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let mut run = |editor: &mut Editor, command| {
+            editor.command(&mut buffer, command).unwrap();
+            let cursor = editor.cursor();
+            (cursor.line, cursor.column)
+        };
+        let jump = EditorCommand::CursorJump;
+
+        editor.set_cursor(Point { line: 0, column: 1 });
+        assert_eq!(run(&mut editor, jump(CursorJump::EndOfLine)), (0, 2));
+        assert_eq!(run(&mut editor, jump(CursorJump::StartOfLine)), (0, 0));
+        // Enter skips the indentation, stops at the end of a blank line, and not past the last
+        let next = || jump(CursorJump::FirstNonBlankOfNextLine);
+        assert_eq!(run(&mut editor, next()), (1, 2));
+        assert_eq!(run(&mut editor, next()), (2, 0));
+        assert_eq!(run(&mut editor, next()), (3, 0));
+        assert_eq!(run(&mut editor, jump(CursorJump::EndOfLine)), (3, 1));
+        assert_eq!(run(&mut editor, next()), (4, 0));
+        assert_eq!(run(&mut editor, next()), (4, 0));
+
+        // pages go as far as the buffer does, keeping to the column where the line allows
+        editor.set_cursor(Point { line: 1, column: 3 });
+        let page = EditorCommand::CursorMoveLines;
+        assert_eq!(run(&mut editor, page(Direction::Down, 2)), (3, 1));
+        assert_eq!(run(&mut editor, page(Direction::Down, 10)), (4, 0));
+        assert_eq!(run(&mut editor, page(Direction::Up, 10)), (0, 2));
+        assert_eq!(run(&mut editor, page(Direction::Right, 10)), (0, 2));
+
+        run(&mut editor, EditorCommand::SetMode(Mode::Insert));
+        assert_eq!(run(&mut editor, jump(CursorJump::EndOfLine)), (0, 3));
+    }
+
+    #[test]
+    fn vertical_motions_keep_their_goal_column() {
+        use crate::{Direction, EditorCommand};
+
+        let text = "abcdef\nab\n\nabcdefgh\nabc";
+        let mut buffer = Buffer::new(Default::default(), Contents::from(text));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let mut run = |editor: &mut Editor, command| {
+            editor.command(&mut buffer, command).unwrap();
+            let cursor = editor.cursor();
+            (cursor.line, cursor.column)
+        };
+        let (j, k) = (
+            EditorCommand::CursorMove(Direction::Down),
+            EditorCommand::CursorMove(Direction::Up),
+        );
+
+        // shorter lines pull the cursor in, and the next long enough line lets it back out
+        editor.set_cursor(Point { line: 0, column: 4 });
+        assert_eq!(run(&mut editor, j.clone()), (1, 1));
+        assert_eq!(run(&mut editor, j.clone()), (2, 0));
+        assert_eq!(run(&mut editor, j.clone()), (3, 4));
+        assert_eq!(run(&mut editor, j.clone()), (4, 2));
+        assert_eq!(run(&mut editor, j.clone()), (4, 2));
+        assert_eq!(run(&mut editor, k.clone()), (3, 4));
+        assert_eq!(run(&mut editor, EditorCommand::CursorMoveLines(Direction::Up, 3)), (0, 4));
+
+        // moving any other way aims for wherever it left the cursor
+        assert_eq!(run(&mut editor, j.clone()), (1, 1));
+        assert_eq!(run(&mut editor, EditorCommand::CursorMove(Direction::Left)), (1, 0));
+        assert_eq!(run(&mut editor, k.clone()), (0, 0));
+        assert_eq!(run(&mut editor, EditorCommand::CursorJump(CursorJump::EndOfLine)), (0, 5));
+        assert_eq!(run(&mut editor, j.clone()), (1, 1));
+        assert_eq!(run(&mut editor, j.clone()), (2, 0));
+        assert_eq!(editor.selections()[0].goal_column, Some(5));
+
+        // each cursor has its own
+        editor.set_cursor(Point { line: 3, column: 6 });
+        run(&mut editor, EditorCommand::AddCursorAbove);
+        let cursors = |editor: &Editor| -> Vec<_> {
+            let selections = editor.selections().iter();
+            selections
+                .map(|s| (s.head.line, s.head.column, s.goal_column))
+                .collect()
+        };
+        run(&mut editor, k.clone());
+        run(&mut editor, k);
+        assert_eq!(cursors(&editor), [(0, 0, Some(0)), (1, 1, Some(6))]);
+        run(&mut editor, j.clone());
+        run(&mut editor, j);
+        assert_eq!(cursors(&editor), [(2, 0, Some(0)), (3, 6, Some(6))]);
+    }
+
+    #[test]
+    fn line_lengths_exclude_terminators() {
+        let contents = Contents::from("abc\n\nde\r\nxyz");
+        let lens: Vec<_> = (0..4)
+            .map(|line| contents.line_len_chars_excluding_terminator(line))
+            .collect();
+        assert_eq!(lens, vec![3, 0, 2, 3]);
+        let max = |mode| -> Vec<_> {
+            (0..4)
+                .map(|line| contents.max_cursor_column(line, mode))
+                .collect()
+        };
+        assert_eq!(max(Mode::Normal), vec![2, 0, 1, 2]);
+        assert_eq!(max(Mode::Insert), vec![3, 0, 2, 3]);
+
+        // a line whose break is in the next chunk of the rope
+        let contents =
+            Contents(test_support::rope_from_parts(&["ab", "c\r\n", "\nd\u{2028}", "\n"]));
+        let lens: Vec<_> = (0..4)
+            .map(|line| contents.line_len_chars_excluding_terminator(line))
+            .collect();
+        // only a line feed ends a line, so a line separator is part of the line
+        assert_eq!(lens, vec![3, 0, 2, 0]);
+    }
+
+    #[test]
+    fn clamp_columns_by_mode() {
+        use crate::{Direction, EditorCommand};
+
+        let mut buffer = Buffer::new(Default::default(), Contents::from("abcd\n\nxyz"));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let mut run = |editor: &mut Editor, command| {
+            editor.command(&mut buffer, command).unwrap();
+            let cursor = editor.cursor();
+            (cursor.line, cursor.column)
+        };
+        let right = || EditorCommand::CursorMove(Direction::Right);
+        let down = || EditorCommand::CursorMove(Direction::Down);
+
+        for _ in 0..5 {
+            run(&mut editor, right());
+        }
+        assert_eq!(editor.cursor(), Point { line: 0, column: 3 });
+        // an empty line only has column 0, the line after it as much of the column as it has, and
+        // the last line is the last
+        assert_eq!(run(&mut editor, down()), (1, 0));
+        assert_eq!(run(&mut editor, down()), (2, 2));
+        assert_eq!(run(&mut editor, down()), (2, 2));
+
+        // the last char of the unterminated last line is reachable, and insert mode can go past it
+        editor.set_cursor(Point { line: 2, column: 1 });
+        assert_eq!(run(&mut editor, right()), (2, 2));
+        assert_eq!(run(&mut editor, right()), (2, 2));
+        run(&mut editor, EditorCommand::SetMode(Mode::Insert));
+        assert_eq!(run(&mut editor, right()), (2, 3));
+        assert_eq!(run(&mut editor, right()), (2, 3));
+
+        // leaving insert mode steps back onto the last char
+        assert_eq!(run(&mut editor, EditorCommand::SetMode(Mode::Normal)), (2, 2));
+        editor.set_cursor(Point { line: 1, column: 0 });
+        run(&mut editor, EditorCommand::SetMode(Mode::Insert));
+        assert_eq!(run(&mut editor, EditorCommand::SetMode(Mode::Normal)), (1, 0));
+    }
 }
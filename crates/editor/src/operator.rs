@@ -0,0 +1,249 @@
+use std::ops::Range;
+
+use tore::Point;
+
+use crate::movement::{self, CharClass};
+use crate::{Buffer, CursorJump, Direction, Mode};
+
+/// What an operator does with the text its motion covers, like vim's `y`, `d` and `c`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Yank,
+    Delete,
+    /// Deletes the text and enters insert mode in its place.
+    Change,
+}
+
+impl Operator {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Operator::Yank => "yank",
+            Operator::Delete => "delete",
+            Operator::Change => "change",
+        }
+    }
+}
+
+/// What an operator takes as its target: the text from the cursor to where a motion lands.
+#[derive(Debug, Clone)]
+pub enum Motion {
+    Move(Direction),
+    Jump(CursorJump),
+    /// The operator typed twice, like `dd`: the cursor's line.
+    Line,
+}
+
+/// How much of the text between the cursor and where a motion lands an operator takes, as vim
+/// has it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionKind {
+    /// Up to the char the motion lands on, leaving it out.
+    Exclusive,
+    /// Up to and taking in the char the motion lands on, but never its line break.
+    Inclusive,
+    /// Every line from the cursor's to the one the motion lands on, line breaks and all.
+    Linewise,
+}
+
+impl Motion {
+    pub fn kind(&self) -> MotionKind {
+        match self {
+            Motion::Move(direction) => direction.kind(),
+            Motion::Jump(jump) => jump.kind(),
+            Motion::Line => MotionKind::Linewise,
+        }
+    }
+}
+
+impl Direction {
+    /// Up and down take whole lines; left and right are exclusive.
+    pub fn kind(&self) -> MotionKind {
+        match self {
+            Direction::Up | Direction::Down => MotionKind::Linewise,
+            Direction::Left | Direction::Right => MotionKind::Exclusive,
+        }
+    }
+}
+
+impl CursorJump {
+    /// `w`, `b` and `0` leave out the char they land on, `e`, `ge` and `$` take it in, and `gg`,
+    /// `G` and Enter take whole lines.
+    pub fn kind(&self) -> MotionKind {
+        match self {
+            CursorJump::StartOfNextWord
+            | CursorJump::StartOfNearestWord
+            | CursorJump::StartOfLine => MotionKind::Exclusive,
+            CursorJump::EndOfNearestWord | CursorJump::StartOfLastWord | CursorJump::EndOfLine => {
+                MotionKind::Inclusive
+            }
+            CursorJump::StartOfBuffer
+            | CursorJump::EndOfBuffer
+            | CursorJump::FirstNonBlankOfNextLine => MotionKind::Linewise,
+        }
+    }
+}
+
+/// The char range `op` takes with `motion` made `count` times from `cursor`, and whether it's
+/// whole lines: those run from the start of the first line to the start of the line after the
+/// last, or to the end of the buffer. Up or down from where there's no line to go to covers
+/// nothing.
+///
+/// Like vim, `w` that goes past the end of a line stops at the end of that line rather than
+/// taking its line break, any other exclusive motion that ends at the start of a line ends at
+/// the end of the one before instead, and `cw` on a word changes only to the end of it, as `ce`
+/// would, but without going on to the next word from its last char.
+pub fn resolve_operator(
+    op: Operator,
+    motion: &Motion,
+    count: usize,
+    buffer: &Buffer,
+    cursor: Point,
+) -> (Range<usize>, bool) {
+    let contents = &buffer.contents;
+    let count = count.max(1);
+    let from = contents.point_to_char_offset(cursor);
+    let line_end = |line: usize| {
+        contents.line_to_char(line) + contents.line_len_chars_excluding_terminator(line)
+    };
+    let inclusive = |to: usize| {
+        let (start, end) = (from.min(to), from.max(to));
+        (start..(end + 1).min(line_end(contents.char_to_line(end))), false)
+    };
+
+    let jump = match motion {
+        Motion::Line => {
+            let end = (cursor.line + count).min(contents.len_lines());
+            return (contents.line_to_char(cursor.line)..contents.line_to_char(end), true);
+        }
+        Motion::Jump(CursorJump::StartOfNextWord) if op == Operator::Change => {
+            match contents.get_char(from).map(CharClass::of) {
+                Some(class) if class != CharClass::Whitespace => {
+                    let run = contents
+                        .chars_at(from + 1)
+                        .take_while(|c| CharClass::of(*c) == class)
+                        .count();
+                    let mut to = contents.char_offset_to_point(from + run);
+                    for _ in 1..count {
+                        to =
+                            movement::jump(buffer, to, &CursorJump::EndOfNearestWord, Mode::Normal);
+                    }
+                    return inclusive(contents.point_to_char_offset(to));
+                }
+                _ => Some(&CursorJump::StartOfNextWord),
+            }
+        }
+        Motion::Jump(jump) => Some(jump),
+        Motion::Move(_) => None,
+    };
+    let step = |point| match (motion, jump) {
+        (_, Some(jump)) => movement::jump(buffer, point, jump, Mode::Normal),
+        // an operator can take the last char of a line with `l`, so it can step past it
+        (Motion::Move(Direction::Right), _) => {
+            movement::step(buffer, point, &Direction::Right, Mode::Insert)
+        }
+        (Motion::Move(direction), _) => movement::step(buffer, point, direction, Mode::Normal),
+        (Motion::Jump(_) | Motion::Line, None) => unreachable!("jumps are resolved above"),
+    };
+    let (mut before, mut to) = (cursor, cursor);
+    for _ in 0..count {
+        before = to;
+        to = step(to);
+    }
+
+    let offset = contents.point_to_char_offset(to);
+    match motion.kind() {
+        MotionKind::Linewise if to == cursor && matches!(motion, Motion::Move(_)) => {
+            (from..from, false)
+        }
+        MotionKind::Linewise => {
+            let (first, last) = (cursor.line.min(to.line), cursor.line.max(to.line));
+            let end = (last + 1).min(contents.len_lines());
+            (contents.line_to_char(first)..contents.line_to_char(end), true)
+        }
+        MotionKind::Inclusive => inclusive(offset),
+        MotionKind::Exclusive => {
+            let (start, mut end) = (from.min(offset), from.max(offset));
+            if matches!(jump, Some(CursorJump::StartOfNextWord)) && to.line > before.line {
+                end = line_end(before.line).max(start);
+            }
+            let end_point = contents.char_offset_to_point(end);
+            if end_point.column == 0 && end_point.line > contents.char_to_line(start) {
+                end = line_end(end_point.line - 1);
+            }
+            (start..end, false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Contents;
+
+    #[test]
+    fn ranges_of_operators_and_motions() {
+        use CursorJump::*;
+        use Direction::*;
+        use Operator::*;
+
+        // columns:   0         1
+        //            0123456789012345678
+        let text = "  foo bar.baz  qux\n  next\nlast";
+        let buffer = Buffer::new(Default::default(), Contents::from(text));
+        let (w, e, b) = (
+            Motion::Jump(StartOfNextWord),
+            Motion::Jump(EndOfNearestWord),
+            Motion::Jump(StartOfNearestWord),
+        );
+        #[rustfmt::skip]
+        let cases = [
+            // (op, motion, count, (line, column), range, linewise)
+            (Delete, &w, 1, (0, 2), 2..6, false),
+            (Yank, &w, 1, (0, 6), 6..9, false),
+            (Delete, &e, 1, (0, 2), 2..5, false),
+            (Delete, &b, 1, (0, 6), 2..6, false),
+            (Delete, &Motion::Jump(StartOfLine), 1, (0, 6), 0..6, false),
+            // `$` takes the last char, but not the line break
+            (Delete, &Motion::Jump(EndOfLine), 1, (0, 6), 6..18, false),
+            (Yank, &Motion::Jump(EndOfLine), 1, (2, 1), 27..30, false),
+            // `cw` on a word is `ce`, except on its last char; on a blank it's `dw`
+            (Change, &w, 1, (0, 2), 2..5, false),
+            (Change, &e, 1, (0, 2), 2..5, false),
+            (Change, &w, 1, (0, 4), 4..5, false),
+            (Change, &e, 1, (0, 4), 4..9, false),
+            (Change, &w, 1, (0, 5), 5..6, false),
+            (Change, &w, 2, (0, 2), 2..9, false),
+            // `w` off the end of a line stops there, and so does `b` from the start of one
+            (Delete, &w, 1, (0, 15), 15..18, false),
+            (Delete, &w, 2, (0, 10), 10..18, false),
+            (Delete, &b, 1, (1, 2), 15..21, false),
+            (Delete, &b, 1, (1, 0), 15..18, false),
+            (Delete, &Motion::Move(Right), 1, (0, 17), 17..18, false),
+            (Delete, &Motion::Move(Right), 3, (0, 6), 6..9, false),
+            (Delete, &Motion::Move(Left), 1, (0, 0), 0..0, false),
+            (Delete, &Motion::Move(Left), 2, (0, 6), 4..6, false),
+            (Delete, &Motion::Move(Down), 1, (0, 6), 0..26, true),
+            (Yank, &Motion::Move(Up), 1, (2, 1), 19..30, true),
+            (Yank, &Motion::Move(Up), 1, (0, 6), 6..6, false),
+            (Delete, &Motion::Jump(FirstNonBlankOfNextLine), 1, (0, 6), 0..26, true),
+            (Delete, &Motion::Jump(StartOfBuffer), 1, (1, 2), 0..26, true),
+            (Delete, &Motion::Jump(StartOfBuffer), 1, (0, 2), 0..19, true),
+            // doubled, the lines from the cursor's, as many as there are
+            (Delete, &Motion::Line, 1, (0, 6), 0..19, true),
+            (Yank, &Motion::Line, 2, (0, 6), 0..26, true),
+            (Change, &Motion::Line, 5, (1, 0), 19..30, true),
+        ];
+        for (op, motion, count, (line, column), range, linewise) in cases {
+            let cursor = Point { line, column };
+            assert_eq!(
+                resolve_operator(op, motion, count, &buffer, cursor),
+                (range, linewise),
+                "{:?} {}{:?} from {:?}",
+                op,
+                count,
+                motion,
+                cursor
+            );
+        }
+    }
+}
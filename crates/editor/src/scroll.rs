@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+/// How long a smooth scroll takes, however far it goes.
+pub const SMOOTH_SCROLL_TIME: Duration = Duration::from_millis(100);
+
+/// A scroll of the view from one top line to another, drawn a frame at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScrollAnimation {
+    pub from: usize,
+    pub to: usize,
+    pub start: Instant,
+}
+
+impl ScrollAnimation {
+    /// The line at the top of the view at `now`: fast out of `from` and easing into `to`, never
+    /// turning back, and `to` exactly once the scroll's time is up.
+    pub fn sample(&self, now: Instant) -> usize {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= SMOOTH_SCROLL_TIME {
+            return self.to;
+        }
+        let t = elapsed.as_secs_f64() / SMOOTH_SCROLL_TIME.as_secs_f64();
+        let eased = 1.0 - (1.0 - t) * (1.0 - t);
+        let distance = self.from.abs_diff(self.to);
+        let moved = ((distance as f64 * eased) as usize).min(distance);
+        if self.to >= self.from {
+            self.from + moved
+        } else {
+            self.from - moved
+        }
+    }
+
+    pub fn is_done(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start) >= SMOOTH_SCROLL_TIME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(scroll: &ScrollAnimation) -> Vec<usize> {
+        let frame = Duration::from_millis(16);
+        let mut now = scroll.start;
+        let mut tops = vec![scroll.sample(now)];
+        while !scroll.is_done(now) {
+            now += frame;
+            tops.push(scroll.sample(now));
+        }
+        tops
+    }
+
+    #[test]
+    fn eases_to_the_target() {
+        let start = Instant::now();
+        for (from, to) in [(0, 1000), (1000, 3), (10, 12), (7, 7)] {
+            let tops = frames(&ScrollAnimation { from, to, start });
+            assert_eq!(tops.first(), Some(&from));
+            assert_eq!(tops.last(), Some(&to));
+            let monotonic = if to >= from {
+                tops.windows(2).all(|pair| pair[0] <= pair[1])
+            } else {
+                tops.windows(2).all(|pair| pair[0] >= pair[1])
+            };
+            assert!(monotonic, "{:?}", tops);
+            // a few frames, not one, and not dozens
+            assert!((5..=9).contains(&tops.len()), "{:?}", tops);
+        }
+
+        // a clock that went backwards, or a frame long overdue
+        let scroll = ScrollAnimation { from: 0, to: 100, start };
+        assert_eq!(scroll.sample(start - Duration::from_secs(1)), 0);
+        assert_eq!(scroll.sample(start + Duration::from_secs(60)), 100);
+    }
+}
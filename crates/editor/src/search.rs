@@ -0,0 +1,247 @@
+use regex_automata::meta::Regex;
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use crate::buffer::Contents;
+
+/// How many matches a search keeps. Past it, only those around where it was made are kept, and
+/// it stops counting once it has enough of them after that point.
+pub const MAX_MATCHES: usize = 10_000;
+/// How many matches either side of the cursor are kept when there are too many to keep them all.
+const WINDOW: usize = 500;
+
+/// How a search matches its query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    /// As the text it is.
+    #[default]
+    Plain,
+    /// As a regex, a line at a time: `^` and `$` match at the start and end of every line, and
+    /// no match spans a line break.
+    Regex,
+}
+
+/// The matches of some text in a buffer, as it was at one version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Search {
+    pub query: String,
+    pub mode: SearchMode,
+    /// The buffer version the matches were found in.
+    pub version: u64,
+    /// The char ranges of the matches kept, in order.
+    matches: Vec<Range<usize>>,
+    /// The index, among all the matches, of the first one kept.
+    first: usize,
+    /// Whether there are matches after the last one kept.
+    more: bool,
+    /// How many matches there are; unless `counted_all`, at least how many.
+    total: usize,
+    counted_all: bool,
+}
+
+impl Search {
+    /// Finds `query` in `contents`, keeping only the matches around the char offset `around` if
+    /// there are too many to keep. Matches don't overlap.
+    pub fn new(contents: &Contents, version: u64, query: &str, around: usize) -> Self {
+        let text = contents.to_string();
+        let found = (!query.is_empty()).then(|| text.match_indices(query));
+        let found = found.into_iter().flatten();
+        let found = found.map(|(start, text)| start..start + text.len());
+        Self::from_matches(contents, version, query, SearchMode::Plain, found, around)
+    }
+
+    /// Like `new`, matching `query` as `mode` says; fails with what's wrong with a regex that
+    /// doesn't compile.
+    pub fn with_mode(
+        contents: &Contents,
+        version: u64,
+        query: &str,
+        mode: SearchMode,
+        around: usize,
+    ) -> Result<Self, String> {
+        if mode == SearchMode::Plain {
+            return Ok(Self::new(contents, version, query, around));
+        }
+        let re = Regex::new(query).map_err(|err| format!("invalid regex {:?}: {}", query, err))?;
+        let found = contents.tree().regex_find_iter(&re, ..);
+        Ok(Self::from_matches(contents, version, query, mode, found, around))
+    }
+
+    /// The same search made again, in `contents` as they are at `version`.
+    pub fn again(&self, contents: &Contents, version: u64, around: usize) -> Self {
+        Self::with_mode(contents, version, &self.query, self.mode, around)
+            .expect("the query compiled when the search was first made")
+    }
+
+    /// Keeps the byte ranges `found`, in order, as `new` does.
+    fn from_matches(
+        contents: &Contents,
+        version: u64,
+        query: &str,
+        mode: SearchMode,
+        found: impl Iterator<Item = Range<usize>>,
+        around: usize,
+    ) -> Self {
+        let mut matches = VecDeque::new();
+        let (mut first, mut total, mut after) = (0, 0, 0);
+        let (mut too_many, mut counted_all) = (false, true);
+        for found in found {
+            if total >= MAX_MATCHES {
+                too_many = true;
+                if after >= WINDOW {
+                    counted_all = false;
+                    break;
+                }
+            }
+            total += 1;
+            let start = contents.byte_to_char(found.start);
+            after += usize::from(start >= around);
+            matches.push_back(start..contents.byte_to_char(found.end));
+            // past the limit, only the last few before `around` are kept
+            while too_many && matches.len() - after > WINDOW {
+                matches.pop_front();
+                first += 1;
+            }
+        }
+        let mut more = !counted_all;
+        if too_many {
+            while matches.len() - after > WINDOW {
+                matches.pop_front();
+                first += 1;
+            }
+            let keep = matches.len() - after + WINDOW;
+            more |= matches.len() > keep;
+            matches.truncate(keep);
+        }
+        let matches = matches.into();
+        let query = query.to_string();
+        Self { query, mode, version, matches, first, more, total, counted_all }
+    }
+
+    /// The char ranges of the matches kept, in order: all of them, unless `is_truncated`.
+    pub fn matches(&self) -> &[Range<usize>] {
+        &self.matches
+    }
+
+    /// How many matches there are; unless `counted_all`, at least how many.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Whether `total` is every match, rather than where counting stopped.
+    pub fn counted_all(&self) -> bool {
+        self.counted_all
+    }
+
+    /// Whether there were too many matches to keep them all.
+    pub fn is_truncated(&self) -> bool {
+        self.first > 0 || self.more
+    }
+
+    /// Counting from 1, which match the char offset `at` is on, or else the last one before it;
+    /// 0 before the first. None where the matches around `at` weren't kept.
+    pub fn position(&self, at: usize) -> Option<usize> {
+        if !self.covers(at) {
+            return None;
+        }
+        let before = self.matches.partition_point(|m| m.start <= at);
+        Some(self.first + before)
+    }
+
+    /// Whether the matches kept reach either side of the char offset `at`, so the ones nearest
+    /// it are known.
+    pub fn covers(&self, at: usize) -> bool {
+        let (Some(first), Some(last)) = (self.matches.first(), self.matches.last()) else {
+            return !self.is_truncated();
+        };
+        (self.first == 0 || first.start <= at) && (!self.more || at < last.start)
+    }
+
+    /// The kept match starting after the char offset `at`, or with `backward` the last one
+    /// starting before it, if any.
+    pub fn next(&self, at: usize, backward: bool) -> Option<Range<usize>> {
+        if backward {
+            let index = self.matches.partition_point(|m| m.start < at);
+            index
+                .checked_sub(1)
+                .map(|index| self.matches[index].clone())
+        } else {
+            let index = self.matches.partition_point(|m| m.start <= at);
+            self.matches.get(index).cloned()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let contents = Contents::from("ab ab\nxab ab");
+        let search = Search::new(&contents, 0, "ab", 0);
+        assert_eq!(search.matches(), [0..2, 3..5, 7..9, 10..12]);
+        assert_eq!((search.total(), search.is_truncated()), (4, false));
+        // on a match, or between one and the next
+        let positions: Vec<_> = (0..13).map(|at| search.position(at).unwrap()).collect();
+        assert_eq!(positions, [1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 4, 4, 4]);
+        assert_eq!(search.position(0), Some(1));
+
+        let search = Search::new(&contents, 0, "xab", 0);
+        assert_eq!((search.position(0), search.position(6)), (Some(0), Some(1)));
+        assert_eq!(search.next(0, false), Some(6..9));
+        assert_eq!(search.next(6, false), None);
+        assert_eq!(search.next(7, true), Some(6..9));
+        assert_eq!(search.next(6, true), None);
+
+        let search = Search::new(&contents, 0, "", 0);
+        assert_eq!((search.total(), search.position(3)), (0, Some(0)));
+    }
+
+    #[test]
+    fn regex_matches() {
+        let contents = Contents::from("fn main() {}\nfn foo_bar() {}\n  fn é1\n");
+        let find = |query| Search::with_mode(&contents, 0, query, SearchMode::Regex, 0);
+        let search = find(r"\bfn \w+").unwrap();
+        assert_eq!(search.matches(), [0..7, 13..23, 31..36]);
+        // anchored to lines, and in chars rather than bytes
+        assert_eq!(find("^fn").unwrap().matches(), [0..2, 13..15]);
+        let digit = find(r"\d$").unwrap();
+        assert_eq!((digit.total(), digit.matches().first()), (1, Some(&(35..36))));
+        assert_eq!(search.again(&contents, 1, 0), Search { version: 1, ..search });
+
+        let err = find("main(").unwrap_err();
+        assert!(err.starts_with("invalid regex \"main(\": "), "{}", err);
+    }
+
+    #[test]
+    fn too_many_matches() {
+        let count = MAX_MATCHES * 3;
+        let contents = Contents::from("a\n".repeat(count).as_str());
+        let middle = count;
+        let search = Search::new(&contents, 0, "a", middle);
+        assert!(search.is_truncated());
+        assert_eq!(search.matches().len(), WINDOW * 2);
+        // it stops counting once it has enough past the cursor, but knows where it is
+        assert_eq!((search.total(), search.counted_all()), (middle / 2 + WINDOW, false));
+        assert_eq!(search.position(middle), Some(middle / 2 + 1));
+        assert_eq!(search.position(0), None);
+        assert_eq!(search.position(count * 2 - 2), None);
+        assert!(search.covers(middle) && !search.covers(0));
+
+        // a window at the start keeps nothing before it
+        let search = Search::new(&contents, 0, "a", 0);
+        assert_eq!(search.matches().len(), WINDOW);
+        assert_eq!(search.matches().first(), Some(&(0..1)));
+        assert_eq!(search.position(0), Some(1));
+        assert_eq!(search.total(), MAX_MATCHES);
+
+        // just too many, with the cursor near the end: every one is counted
+        let contents = Contents::from("a".repeat(MAX_MATCHES + 10).as_str());
+        let search = Search::new(&contents, 0, "a", MAX_MATCHES + 5);
+        assert!(search.is_truncated());
+        assert_eq!((search.total(), search.counted_all()), (MAX_MATCHES + 10, true));
+        assert_eq!(search.matches().len(), WINDOW + 5);
+        assert_eq!(search.position(MAX_MATCHES + 5), Some(MAX_MATCHES + 6));
+    }
+}
@@ -0,0 +1,285 @@
+use std::ops::Range;
+
+use crate::buffer::Contents;
+use crate::encoding::{Encoding, Format};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tab,
+    #[default]
+    Space,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+/// How a buffer is indented, and what's tidied up as it's saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    pub indent_style: IndentStyle,
+    /// Columns per level of indentation.
+    pub indent_size: usize,
+    /// How many columns apart tab stops are.
+    pub tab_width: usize,
+    /// What every line break becomes on save; None leaves each as it is.
+    pub end_of_line: Option<LineEnding>,
+    /// Whether the file must end with a line break on save, or must not; None leaves it be.
+    pub insert_final_newline: Option<bool>,
+    pub trim_trailing_whitespace: bool,
+    /// How to store the file on save, whatever it was read as; None keeps its format.
+    pub charset: Option<Format>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            indent_style: IndentStyle::default(),
+            indent_size: 4,
+            tab_width: 4,
+            end_of_line: None,
+            insert_final_newline: None,
+            trim_trailing_whitespace: false,
+            charset: None,
+        }
+    }
+}
+
+impl Settings {
+    /// What typing a tab at `column` inserts.
+    pub fn indent(&self, column: usize) -> String {
+        match self.indent_style {
+            IndentStyle::Tab => "\t".to_string(),
+            IndentStyle::Space => {
+                let size = self.indent_size.max(1);
+                " ".repeat(size - column % size)
+            }
+        }
+    }
+
+    /// The edits saving `contents` makes first: trailing whitespace trimmed, line breaks made the
+    /// same, and the final one added or removed. Char ranges, sorted and disjoint.
+    pub fn fixups(&self, contents: &Contents) -> Vec<(Range<usize>, String)> {
+        let mut edits = vec![];
+        let lines = contents.len_lines();
+        for line in 0..lines {
+            let start = contents.line_to_char(line);
+            let len = contents.line_len_chars_excluding_terminator(line);
+            let slice = contents.line(line);
+            let trailing = if self.trim_trailing_whitespace {
+                slice
+                    .chars_at(len)
+                    .reversed()
+                    .take_while(|c| matches!(c, ' ' | '\t'))
+                    .count()
+            } else {
+                0
+            };
+            let terminator: String = slice.chars().skip(len).collect();
+            let replacement = match (self.insert_final_newline, self.end_of_line) {
+                // the line break before an empty last line is the final one
+                (Some(false), _)
+                    if line + 2 == lines && contents.line(line + 1).len_chars() == 0 =>
+                {
+                    String::new()
+                }
+                (Some(true), eol)
+                    if line + 1 == lines && terminator.is_empty() && len > trailing =>
+                {
+                    eol.unwrap_or(LineEnding::Lf).as_str().to_string()
+                }
+                (_, Some(eol)) if !terminator.is_empty() => eol.as_str().to_string(),
+                _ => terminator.clone(),
+            };
+            if trailing > 0 || replacement != terminator {
+                let end = start + slice.len_chars();
+                edits.push((start + len - trailing..end, replacement));
+            }
+        }
+        edits
+    }
+}
+
+/// Settings from one place, like an `.editorconfig` or `:set`, that lie over the defaults and
+/// each other. Only those it sets are changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SettingsOverlay {
+    pub indent_style: Option<IndentStyle>,
+    /// None for `tab`: indent by the tab width.
+    pub indent_size: Option<Option<usize>>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<LineEnding>,
+    pub insert_final_newline: Option<bool>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub charset: Option<Format>,
+}
+
+impl SettingsOverlay {
+    /// Sets `key` to `value`, as they're written in an `.editorconfig`; both are taken in any case.
+    /// A value of `unset` leaves the key to whatever lies beneath. Fails with what's wrong with
+    /// them if either isn't understood.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let value = value.to_ascii_lowercase();
+        if value == "unset" {
+            return self.unset(key);
+        }
+        let bad = || format!("bad value for {}: '{}'", key, value);
+        let number = || {
+            value
+                .parse::<usize>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(bad)
+        };
+        let flag = || match value.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(bad()),
+        };
+        match key.to_ascii_lowercase().as_str() {
+            "indent_style" => {
+                self.indent_style = Some(match value.as_str() {
+                    "tab" => IndentStyle::Tab,
+                    "space" => IndentStyle::Space,
+                    _ => return Err(bad()),
+                })
+            }
+            "indent_size" if value == "tab" => self.indent_size = Some(None),
+            "indent_size" => self.indent_size = Some(Some(number()?)),
+            "tab_width" => self.tab_width = Some(number()?),
+            "end_of_line" => {
+                self.end_of_line = Some(match value.as_str() {
+                    "lf" => LineEnding::Lf,
+                    "crlf" => LineEnding::CrLf,
+                    "cr" => LineEnding::Cr,
+                    _ => return Err(bad()),
+                })
+            }
+            "insert_final_newline" => self.insert_final_newline = Some(flag()?),
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace = Some(flag()?),
+            "charset" => {
+                let bom = match value.as_str() {
+                    "utf-8" => false,
+                    "utf-8-bom" => true,
+                    _ => return Err(bad()),
+                };
+                self.charset = Some(Format { encoding: Encoding::Utf8, bom });
+            }
+            key => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+
+    fn unset(&mut self, key: &str) -> Result<(), String> {
+        match key.to_ascii_lowercase().as_str() {
+            "indent_style" => self.indent_style = None,
+            "indent_size" => self.indent_size = None,
+            "tab_width" => self.tab_width = None,
+            "end_of_line" => self.end_of_line = None,
+            "insert_final_newline" => self.insert_final_newline = None,
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace = None,
+            "charset" => self.charset = None,
+            key => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+
+    /// Lays this over `settings`. A numeric indent size with no tab width sets both, like
+    /// editorconfig has it.
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(style) = self.indent_style {
+            settings.indent_style = style;
+        }
+        if let Some(width) = self.tab_width.or(self.indent_size.flatten()) {
+            settings.tab_width = width;
+        }
+        if let Some(size) = self.indent_size {
+            settings.indent_size = size.unwrap_or(settings.tab_width);
+        }
+        if let Some(eol) = self.end_of_line {
+            settings.end_of_line = Some(eol);
+        }
+        if let Some(newline) = self.insert_final_newline {
+            settings.insert_final_newline = Some(newline);
+        }
+        if let Some(trim) = self.trim_trailing_whitespace {
+            settings.trim_trailing_whitespace = trim;
+        }
+        if let Some(charset) = self.charset {
+            settings.charset = Some(charset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlays() {
+        let mut settings = Settings::default();
+        let mut editorconfig = SettingsOverlay::default();
+        editorconfig.set("indent_style", "Tab").unwrap();
+        editorconfig.set("indent_size", "8").unwrap();
+        editorconfig.apply(&mut settings);
+        assert_eq!(
+            (settings.indent_style, settings.indent_size, settings.tab_width),
+            (IndentStyle::Tab, 8, 8)
+        );
+
+        let mut user = SettingsOverlay::default();
+        user.set("tab_width", "2").unwrap();
+        user.set("indent_size", "tab").unwrap();
+        user.apply(&mut settings);
+        assert_eq!((settings.indent_size, settings.tab_width), (2, 2));
+
+        assert_eq!(user.set("indent_size", "0"), Err("bad value for indent_size: '0'".into()));
+        user.set("tab_width", "unset").unwrap();
+        assert_eq!(user.tab_width, None);
+        assert_eq!(user.set("tabs", "2"), Err("unknown setting 'tabs'".into()));
+    }
+
+    #[test]
+    fn fixups_on_save() {
+        let fixed = |settings: &Settings, text: &str| {
+            let mut contents = Contents::from(text);
+            for (range, text) in settings.fixups(&contents).into_iter().rev() {
+                contents.remove(range.clone());
+                contents.insert(range.start, &text);
+            }
+            contents.to_string()
+        };
+        let text = "a  \r\nb\t\n  \nc ";
+        assert_eq!(fixed(&Settings::default(), text), text);
+
+        let mut settings = Settings { trim_trailing_whitespace: true, ..Default::default() };
+        assert_eq!(fixed(&settings, text), "a\r\nb\n\nc");
+        settings.insert_final_newline = Some(true);
+        assert_eq!(fixed(&settings, text), "a\r\nb\n\nc\n");
+        settings.end_of_line = Some(LineEnding::CrLf);
+        assert_eq!(fixed(&settings, text), "a\r\nb\r\n\r\nc\r\n");
+        assert_eq!(fixed(&settings, ""), "");
+
+        let settings = Settings { insert_final_newline: Some(false), ..Default::default() };
+        assert_eq!(fixed(&settings, "a\nb\n"), "a\nb");
+        assert_eq!(fixed(&settings, "a\nb"), "a\nb");
+
+        let spaces = Settings { indent_size: 4, ..Default::default() };
+        assert_eq!(spaces.indent(1), "   ");
+        let tabs = Settings { indent_style: IndentStyle::Tab, ..Default::default() };
+        assert_eq!(tabs.indent(1), "\t");
+    }
+}
@@ -0,0 +1,158 @@
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::buffer::{self, Contents, DiskState, Highlights, Id as BufferId};
+use crate::diagnostic::Diagnostic;
+use crate::diff::LineChange;
+use crate::editor::{Id as EditorId, Mode, Selection};
+use crate::encoding::{Encoding, Format};
+use crate::scroll::ScrollAnimation;
+use crate::search::Search;
+use crate::settings::Settings;
+use crate::Point;
+
+/// What a frame draws of a buffer, as it was when taken. The contents and highlights are shared
+/// with the buffer rather than copied, so taking one is cheap, and editing the buffer after
+/// leaves it as it was.
+#[derive(Debug, Clone)]
+pub struct BufferSnapshot {
+    pub id: BufferId,
+    pub path: Option<PathBuf>,
+    pub contents: Contents,
+    pub version: u64,
+    pub dirty: bool,
+    pub read_only: bool,
+    pub format: Format,
+    pub disk_state: DiskState,
+    pub settings: Settings,
+    pub(crate) highlights: Arc<Highlights>,
+    /// The edits made since the highlights were found, to move them along by.
+    pub(crate) shifts: Arc<[(u64, Range<usize>, usize)]>,
+    pub(crate) line_diff: Arc<[LineChange]>,
+    pub(crate) diagnostics: Arc<[Diagnostic]>,
+    /// Whether the buffer had saved contents to diff against.
+    pub(crate) saved: bool,
+}
+
+impl BufferSnapshot {
+    /// Whether it holds a binary file, a byte to a char, to be shown as hex.
+    pub fn is_binary(&self) -> bool {
+        self.format.encoding == Encoding::Binary
+    }
+
+    /// Every highlight overlapping the byte `range`, sorted by start and then end, as
+    /// `Buffer::highlights_in` has them.
+    pub fn highlights_in(&self, range: Range<usize>) -> Vec<(Range<usize>, &str)> {
+        buffer::highlights_in(&self.highlights, &self.shifts, range)
+    }
+
+    pub fn line_diff(&self) -> &[LineChange] {
+        &self.line_diff
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Whether the buffer was read from or saved to a file, so there's something to diff it
+    /// against.
+    pub fn has_saved(&self) -> bool {
+        self.saved
+    }
+}
+
+/// What a frame draws of an editor and the buffer it shows, as they were when taken, so a frame
+/// can be drawn from it while commands go on changing them.
+#[derive(Debug, Clone)]
+pub struct EditorSnapshot {
+    pub id: EditorId,
+    pub mode: Mode,
+    pub buffer: BufferSnapshot,
+    pub(crate) selections: Arc<[Selection]>,
+    pub(crate) primary: usize,
+    pub(crate) search: Option<Arc<Search>>,
+    pub(crate) scroll: Option<ScrollAnimation>,
+}
+
+impl EditorSnapshot {
+    /// The primary cursor: the one the screen follows.
+    pub fn cursor(&self) -> Point {
+        self.selections[self.primary].head
+    }
+
+    pub fn selections(&self) -> &[Selection] {
+        &self.selections
+    }
+
+    pub fn primary(&self) -> usize {
+        self.primary
+    }
+
+    /// The matches of the last search, as they were when last found.
+    pub fn search(&self) -> Option<&Search> {
+        self.search.as_deref()
+    }
+
+    /// The line the view is drawn scrolled to at `now`, partway through a smooth scroll, or None
+    /// when it's where it should be.
+    pub fn shown_top(&self, now: Instant) -> Option<usize> {
+        self.scroll.map(|scroll| scroll.sample(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Buffer, BufferCommand, Editor, EditorCommand};
+
+    #[test]
+    fn snapshots_keep_what_they_were_taken_of() {
+        let mut buffer = Buffer::new(Default::default(), "let a = a;\nlet b = a;\n".into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let mut highlights = Highlights::new();
+        highlights.insert(0..3, "keyword".to_string());
+        buffer.command(BufferCommand::Highlight(0, highlights));
+        editor
+            .command(&mut buffer, EditorCommand::Search("a;".into()))
+            .unwrap();
+        let snapshot = editor.snapshot(&buffer);
+        let cursor = editor.cursor();
+        assert_eq!(snapshot.cursor(), cursor);
+        // nothing's copied that the buffer and editor still hold
+        assert!(Arc::ptr_eq(&snapshot.buffer.highlights, &buffer.highlights));
+        assert!(std::ptr::eq(snapshot.search().unwrap(), editor.search().unwrap()));
+
+        // an edit moves the highlight along in the buffer, not in the snapshot
+        let insert = |editor: &mut Editor, buffer: &mut Buffer, c| {
+            for cmd in [
+                EditorCommand::SetMode(Mode::Insert),
+                EditorCommand::InsertChar(c),
+                EditorCommand::SetMode(Mode::Normal),
+            ] {
+                editor.command(buffer, cmd).unwrap();
+            }
+        };
+        editor.set_cursor(Point { line: 0, column: 0 });
+        insert(&mut editor, &mut buffer, 'x');
+        editor.refresh_search(&buffer);
+        editor
+            .command(&mut buffer, EditorCommand::AddCursorBelow)
+            .unwrap();
+        assert_eq!(buffer.contents.to_string(), "xlet a = a;\nlet b = a;\n");
+        assert_eq!(buffer.highlights_in(0..usize::MAX), vec![(1..4, "keyword")]);
+        // which moves highlights as they're looked up, so they're still shared
+        assert!(Arc::ptr_eq(&snapshot.buffer.highlights, &buffer.highlights));
+
+        assert_eq!(snapshot.buffer.contents.to_string(), "let a = a;\nlet b = a;\n");
+        assert_eq!(snapshot.buffer.version, 0);
+        assert!(!snapshot.buffer.dirty);
+        assert_eq!(snapshot.buffer.highlights_in(0..usize::MAX), vec![(0..3, "keyword")]);
+        assert_eq!(snapshot.cursor(), cursor);
+        assert_eq!(snapshot.selections().len(), 1);
+        let search = snapshot.search().unwrap();
+        assert_eq!((search.version, search.matches()), (0, &[8..10, 19..21][..]));
+        assert_eq!(editor.search().unwrap().matches(), [9..11, 20..22]);
+    }
+}
@@ -0,0 +1,116 @@
+use std::ops::Range;
+
+use tore::Point;
+
+use crate::buffer::Contents;
+use crate::movement::CharClass;
+
+/// Text around a cursor that commands can select or act on, like vim's `iw` and `aw`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextObject {
+    /// A run of word chars, or of punctuation, as word motions see them.
+    InnerWord,
+    /// A word and the whitespace after it, or before it when there's none after.
+    AroundWord,
+    /// A run of anything but whitespace.
+    InnerBigWord,
+    /// A big word and the whitespace after it, or before it when there's none after.
+    AroundBigWord,
+}
+
+impl TextObject {
+    /// The byte range of the object under `point`, or of the nearest one after it on its line
+    /// when `point` is on whitespace. None when only whitespace follows on the line.
+    pub fn range(self, contents: &Contents, point: Point) -> Option<Range<usize>> {
+        if point.line >= contents.len_lines() {
+            return None;
+        }
+        let len = contents.line_len_chars_excluding_terminator(point.line);
+        let chars: Vec<char> = contents.line(point.line).chars().take(len).collect();
+        let class = |c| match (self, CharClass::of(c)) {
+            (Self::InnerBigWord | Self::AroundBigWord, CharClass::Punctuation) => CharClass::Word,
+            (_, class) => class,
+        };
+        // where the run of `of` that `from` is in or next to starts, and where it ends
+        let run_start = |from: usize, of| {
+            let before = (0..from).rev().find(|&i| class(chars[i]) != of);
+            before.map_or(0, |i| i + 1)
+        };
+        let run_end = |from: usize, of| {
+            let after = (from..len).find(|&i| class(chars[i]) != of);
+            after.unwrap_or(len)
+        };
+
+        let at = run_end(point.column.min(len), CharClass::Whitespace);
+        if at >= len {
+            return None;
+        }
+        let word = class(chars[at]);
+        let (mut start, mut end) = (run_start(at, word), run_end(at, word));
+        if matches!(self, Self::AroundWord | Self::AroundBigWord) {
+            match run_end(end, CharClass::Whitespace) {
+                trailing if trailing > end => end = trailing,
+                _ => start = run_start(start, CharClass::Whitespace),
+            }
+        }
+
+        let line_start = contents.line_to_byte(point.line);
+        let byte = |column: usize| {
+            let len: usize = chars[..column].iter().map(|c| c.len_utf8()).sum();
+            line_start + len
+        };
+        Some(byte(start)..byte(end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_ranges() {
+        use TextObject::*;
+
+        let first = "fn a() {}\n";
+        let line = "let 文字 = foo_bar(x, 🦀y);  end";
+        let contents = Contents::from(format!("{}{}", first, line).as_str());
+        #[rustfmt::skip]
+        let cases = [
+            (0, InnerWord, Some(0..3)),
+            (0, AroundWord, Some(0..4)),
+            // wide chars are word chars, counted in columns and measured in bytes
+            (5, InnerWord, Some(4..10)),
+            (4, AroundWord, Some(4..11)),
+            // on whitespace, the next word over
+            (3, InnerWord, Some(4..10)),
+            (7, InnerWord, Some(11..12)),
+            (7, AroundWord, Some(11..13)),
+            (12, InnerWord, Some(13..20)),
+            (16, InnerWord, Some(20..21)),
+            (20, InnerWord, Some(24..28)),
+            (20, InnerBigWord, Some(24..31)),
+            (17, InnerBigWord, Some(13..23)),
+            (17, AroundBigWord, Some(13..24)),
+            (22, AroundWord, Some(29..33)),
+            // the last word of the buffer has no whitespace after it, so takes what's before
+            (27, AroundWord, Some(31..36)),
+            (26, AroundBigWord, Some(31..36)),
+            (28, InnerWord, Some(33..36)),
+            (24, AroundWord, Some(31..36)),
+            (25, InnerWord, Some(33..36)),
+            (29, InnerWord, None),
+        ];
+        for (column, object, expected) in cases {
+            let point = Point { line: 1, column };
+            let expected = expected.map(|range| first.len() + range.start..first.len() + range.end);
+            assert_eq!(object.range(&contents, point), expected, "{:?} at {}", object, column);
+        }
+
+        let contents = Contents::from("a  \n\nb");
+        for line in 0..3 {
+            let point = Point { line, column: 1 };
+            let range = TextObject::InnerWord.range(&contents, point);
+            assert_eq!(range, None, "line {}", line);
+        }
+    }
+}
@@ -0,0 +1,465 @@
+use slotmap::{new_key_type, SlotMap};
+use std::time::{Duration, Instant};
+use tore::EditSummary;
+
+use crate::buffer::Contents;
+
+new_key_type! {
+    pub struct StateId;
+}
+
+/// Roughly how much of a rope an edit copies rather than shares with the snapshots before it:
+/// the chunk it lands in, and the nodes above that.
+const UNSHARED_BYTES: usize = 1024;
+
+/// Where undoing moves a buffer's contents to, in its tree of undo states.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndoMove {
+    /// Back to the state the current one was made from, like vim's `u`.
+    Undo,
+    /// Forward to the state last undone from this one, or else the newest made from it, like
+    /// vim's ctrl-r.
+    Redo,
+    /// That many states back, or forward, in the order they were made, across branches, like
+    /// vim's `g-` and `g+`.
+    Steps(isize),
+    /// To the last state made that long before the current one, like vim's `:earlier 10s`.
+    Earlier(Duration),
+    /// To the last state made within that long after the current one, like vim's `:later 10s`.
+    Later(Duration),
+}
+
+/// A state of a buffer's contents that it can be moved back or forward to.
+#[derive(Debug, Clone)]
+pub struct UndoState {
+    parent: Option<StateId>,
+    /// Counting from 0 in the order states were made, never reused.
+    seq: u64,
+    time: Instant,
+    /// The edits that made this state from its parent; none for the first.
+    edits: Vec<EditSummary>,
+    /// A snapshot of the contents, sharing most of itself with the snapshots around it.
+    contents: Contents,
+    /// The child that redo goes to.
+    redo: Option<StateId>,
+    /// What the state is estimated to keep alive that the others don't.
+    bytes: usize,
+}
+
+impl UndoState {
+    /// The state this one was made from, if it's still kept.
+    pub fn parent(&self) -> Option<StateId> {
+        self.parent
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// When the state was made, or last added to.
+    pub fn time(&self) -> Instant {
+        self.time
+    }
+
+    pub fn edits(&self) -> &[EditSummary] {
+        &self.edits
+    }
+}
+
+/// Every state a buffer's contents have been in, as a tree: undoing and then editing starts a
+/// branch rather than losing what was undone. Each state keeps a snapshot of the contents, which
+/// is cheap as ropes share what they don't change.
+///
+/// The oldest states beyond `max_states`, or `max_bytes` estimated, are dropped; leaves first,
+/// then the oldest of the current state's ancestors once only they are left.
+#[derive(Debug)]
+pub struct UndoTree {
+    states: SlotMap<StateId, UndoState>,
+    current: StateId,
+    next_seq: u64,
+    bytes: usize,
+    max_states: usize,
+    max_bytes: usize,
+}
+
+impl UndoTree {
+    pub fn new(contents: &Contents, now: Instant) -> Self {
+        let mut states = SlotMap::with_key();
+        let current = states.insert(UndoState {
+            parent: None,
+            seq: 0,
+            time: now,
+            edits: vec![],
+            contents: contents.clone(),
+            redo: None,
+            bytes: 0,
+        });
+        Self { states, current, next_seq: 1, bytes: 0, max_states: 1000, max_bytes: 32 << 20 }
+    }
+
+    pub fn current(&self) -> StateId {
+        self.current
+    }
+
+    pub fn get(&self, id: StateId) -> Option<&UndoState> {
+        self.states.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Every state kept, oldest first.
+    pub fn chronological(&self) -> Vec<(StateId, &UndoState)> {
+        let mut states: Vec<_> = self.states.iter().collect();
+        states.sort_unstable_by_key(|(_, state)| state.seq);
+        states
+    }
+
+    /// Keeps `contents` as the state `edits` made from the current one, which it becomes. With
+    /// `join`, they're added to the current state instead, as the rest of one insert is, unless
+    /// it's the first or others have been made from it.
+    pub fn record(
+        &mut self,
+        contents: &Contents,
+        edits: Vec<EditSummary>,
+        join: bool,
+        now: Instant,
+    ) -> StateId {
+        let bytes: usize = edits
+            .iter()
+            .map(|edit| edit.removed.len + edit.inserted.len + UNSHARED_BYTES)
+            .sum();
+        let current = &mut self.states[self.current];
+        if join && current.parent.is_some() && current.redo.is_none() {
+            current.contents = contents.clone();
+            current.edits.extend(edits);
+            current.time = now;
+            current.bytes += bytes;
+        } else {
+            let state = UndoState {
+                parent: Some(self.current),
+                seq: self.next_seq,
+                time: now,
+                edits,
+                contents: contents.clone(),
+                redo: None,
+                bytes,
+            };
+            self.next_seq += 1;
+            let id = self.states.insert(state);
+            self.states[self.current].redo = Some(id);
+            self.current = id;
+        }
+        self.bytes += bytes;
+        self.evict();
+        self.current
+    }
+
+    /// The state `to` moves to from the current one, if there's one to move to.
+    pub fn target(&self, to: UndoMove) -> Option<StateId> {
+        let current = &self.states[self.current];
+        let target = match to {
+            UndoMove::Undo => current.parent,
+            UndoMove::Redo => current.redo.or_else(|| self.newest_child(self.current)),
+            UndoMove::Steps(steps) => {
+                let states = self.chronological();
+                let at = states.iter().position(|(id, _)| *id == self.current)?;
+                let to = at.saturating_add_signed(steps).min(states.len() - 1);
+                Some(states[to].0)
+            }
+            UndoMove::Earlier(ago) => {
+                let time = current.time.checked_sub(ago);
+                let states = self.chronological();
+                let before = states.iter().rev().find(|(_, s)| Some(s.time) <= time);
+                Some(before.unwrap_or(&states[0]).0)
+            }
+            UndoMove::Later(within) => {
+                let time = current.time + within;
+                let states = self.chronological();
+                let by = states.iter().rev().find(|(_, s)| s.time <= time);
+                by.map(|(id, _)| *id)
+            }
+        };
+        target.filter(|id| *id != self.current)
+    }
+
+    /// Makes `target` the current state, returning its contents. Redo goes back the way undo
+    /// came, from each state on the way up to where the branches meet.
+    pub fn goto(&mut self, target: StateId) -> Option<Contents> {
+        let contents = self.states.get(target)?.contents.clone();
+        let mut child = target;
+        while let Some(parent) = self.states[child].parent {
+            self.states[parent].redo = Some(child);
+            child = parent;
+        }
+        self.current = target;
+        Some(contents)
+    }
+
+    fn newest_child(&self, parent: StateId) -> Option<StateId> {
+        let children = self.states.iter().filter(|(_, s)| s.parent == Some(parent));
+        children.max_by_key(|(_, s)| s.seq).map(|(id, _)| id)
+    }
+
+    /// Drops the oldest states until there are no more than the caps allow, never the current
+    /// one.
+    fn evict(&mut self) {
+        while self.states.len() > self.max_states || self.bytes > self.max_bytes {
+            if self.drop_oldest(0).is_none() {
+                break;
+            }
+        }
+    }
+
+    /// Drops the oldest leaf besides the current state or, once only the current state's
+    /// ancestors are left, the oldest of them while more than `min_depth` are. Returns the bytes
+    /// that freed, or None if there was nothing to drop.
+    fn drop_oldest(&mut self, min_depth: usize) -> Option<usize> {
+        let leaf = self
+            .states
+            .iter()
+            .filter(|(id, _)| *id != self.current)
+            .filter(|(id, _)| self.states.values().all(|s| s.parent != Some(*id)))
+            .min_by_key(|(_, s)| s.seq)
+            .map(|(id, _)| id);
+        match leaf {
+            Some(leaf) => Some(self.remove(leaf)),
+            None => self.remove_root(min_depth),
+        }
+    }
+
+    /// Drops a state, returning its estimated bytes.
+    fn remove(&mut self, id: StateId) -> usize {
+        let Some(state) = self.states.remove(id) else {
+            return 0;
+        };
+        self.bytes -= state.bytes;
+        if let Some(parent) = state.parent.and_then(|parent| self.states.get_mut(parent)) {
+            if parent.redo == Some(id) {
+                parent.redo = None;
+            }
+        }
+        state.bytes
+    }
+
+    /// Drops the oldest of the current state's ancestors, when they're all that's left and
+    /// there are more than `min_depth` of them, so its child is the oldest state there is to
+    /// undo to.
+    fn remove_root(&mut self, min_depth: usize) -> Option<usize> {
+        let mut root = self.current;
+        let mut depth = 0;
+        while let Some(parent) = self.states[root].parent {
+            root = parent;
+            depth += 1;
+        }
+        if depth <= min_depth {
+            return None;
+        }
+        let children: Vec<_> = self
+            .states
+            .iter()
+            .filter(|(_, s)| s.parent == Some(root))
+            .map(|(id, _)| id)
+            .collect();
+        for child in children {
+            self.states[child].parent = None;
+        }
+        Some(self.remove(root))
+    }
+}
+
+/// How much a buffer's history keeps alive, and a way to have it keep less, so a budget can be
+/// shared between buffers.
+pub trait HistoryAccounting {
+    /// The bytes its undo states are estimated to keep alive, that their edits left unshared.
+    fn retained_bytes(&self) -> usize;
+
+    /// Drops its oldest undo state, keeping at least `min_depth` to undo back to from the
+    /// current one. Returns the bytes that freed, or None if nothing more can go.
+    fn drop_oldest_state(&mut self, min_depth: usize) -> Option<usize>;
+}
+
+impl HistoryAccounting for UndoTree {
+    fn retained_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    fn drop_oldest_state(&mut self, min_depth: usize) -> Option<usize> {
+        self.drop_oldest(min_depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(text: &str) -> Contents {
+        text.into()
+    }
+
+    fn text(tree: &UndoTree, id: StateId) -> String {
+        tree.states[id].contents.to_string()
+    }
+
+    /// Records each of `texts` in turn, a second after the last.
+    fn record(tree: &mut UndoTree, start: Instant, texts: &[&str]) -> Vec<StateId> {
+        texts
+            .iter()
+            .map(|t| {
+                let now = start + Duration::from_secs(tree.next_seq);
+                tree.record(&contents(t), vec![EditSummary::default()], false, now)
+            })
+            .collect()
+    }
+
+    fn move_to(tree: &mut UndoTree, to: UndoMove) -> Option<String> {
+        let target = tree.target(to)?;
+        tree.goto(target).map(|contents| contents.to_string())
+    }
+
+    #[test]
+    fn editing_after_undo_branches() {
+        let start = Instant::now();
+        let mut tree = UndoTree::new(&contents(""), start);
+        let a = record(&mut tree, start, &["a", "ab", "abc"])[0];
+        assert_eq!(move_to(&mut tree, UndoMove::Undo).as_deref(), Some("ab"));
+        assert_eq!(move_to(&mut tree, UndoMove::Undo).as_deref(), Some("a"));
+        let ax = record(&mut tree, start, &["ax"])[0];
+        assert_eq!(tree.states[ax].parent, Some(a));
+        assert_eq!(tree.len(), 5);
+
+        // every state is still there, in the order made
+        let mut seen = vec![text(&tree, tree.current)];
+        while let Some(text) = move_to(&mut tree, UndoMove::Steps(-1)) {
+            seen.push(text);
+        }
+        assert_eq!(seen, ["ax", "abc", "ab", "a", ""]);
+        let mut seen = vec![];
+        while let Some(text) = move_to(&mut tree, UndoMove::Steps(1)) {
+            seen.push(text);
+        }
+        assert_eq!(seen, ["a", "ab", "abc", "ax"]);
+
+        // redo follows the branch last come back along
+        assert_eq!(move_to(&mut tree, UndoMove::Steps(-1)).as_deref(), Some("abc"));
+        assert_eq!(move_to(&mut tree, UndoMove::Undo).as_deref(), Some("ab"));
+        assert_eq!(move_to(&mut tree, UndoMove::Undo).as_deref(), Some("a"));
+        assert_eq!(move_to(&mut tree, UndoMove::Redo).as_deref(), Some("ab"));
+        assert_eq!(move_to(&mut tree, UndoMove::Redo).as_deref(), Some("abc"));
+        assert_eq!(move_to(&mut tree, UndoMove::Redo), None);
+        assert_eq!(move_to(&mut tree, UndoMove::Steps(-2)).as_deref(), Some("a"));
+        assert_eq!(move_to(&mut tree, UndoMove::Redo).as_deref(), Some("ab"));
+        assert_eq!(move_to(&mut tree, UndoMove::Steps(9)).as_deref(), Some("ax"));
+        assert_eq!(tree.current, ax);
+    }
+
+    #[test]
+    fn earlier_and_later_by_time() {
+        let start = Instant::now();
+        let mut tree = UndoTree::new(&contents(""), start);
+        record(&mut tree, start, &["a", "ab", "abc", "abcd"]);
+        let earlier = |secs| UndoMove::Earlier(Duration::from_secs(secs));
+        let later = |secs| UndoMove::Later(Duration::from_secs(secs));
+        assert_eq!(move_to(&mut tree, earlier(2)).as_deref(), Some("ab"));
+        assert_eq!(move_to(&mut tree, later(1)).as_deref(), Some("abc"));
+        assert_eq!(move_to(&mut tree, earlier(60)).as_deref(), Some(""));
+        assert_eq!(move_to(&mut tree, earlier(1)), None);
+        assert_eq!(move_to(&mut tree, later(60)).as_deref(), Some("abcd"));
+        assert_eq!(move_to(&mut tree, later(60)), None);
+    }
+
+    #[test]
+    fn joined_edits_are_one_state() {
+        let start = Instant::now();
+        let mut tree = UndoTree::new(&contents(""), start);
+        // the first edit never joins the state the buffer started in
+        tree.record(&contents("a"), vec![], true, start);
+        tree.record(&contents("ab"), vec![], true, start);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(move_to(&mut tree, UndoMove::Undo).as_deref(), Some(""));
+        assert_eq!(move_to(&mut tree, UndoMove::Redo).as_deref(), Some("ab"));
+
+        // nor one that others have been made from
+        tree.record(&contents("abc"), vec![], false, start);
+        move_to(&mut tree, UndoMove::Undo);
+        tree.record(&contents("abx"), vec![], true, start);
+        assert_eq!(tree.len(), 4);
+        assert_eq!(move_to(&mut tree, UndoMove::Undo).as_deref(), Some("ab"));
+    }
+
+    #[test]
+    fn eviction_keeps_to_the_caps_and_the_current_ancestry() {
+        let start = Instant::now();
+        let mut tree = UndoTree::new(&contents(""), start);
+        tree.max_states = 4;
+        let [a, ab, abc] = record(&mut tree, start, &["a", "ab", "abc"])[..] else {
+            unreachable!()
+        };
+        move_to(&mut tree, UndoMove::Undo);
+        move_to(&mut tree, UndoMove::Undo);
+        // the oldest leaf, the abandoned branch's tip, goes first
+        let ax = record(&mut tree, start, &["ax"])[0];
+        assert_eq!(tree.len(), 4);
+        assert!(!tree.states.contains_key(abc));
+        assert_eq!(tree.states[a].redo, Some(ax));
+        let axy = record(&mut tree, start, &["axy"])[0];
+        assert!(!tree.states.contains_key(ab));
+
+        // with only the ancestry left, its oldest go
+        record(&mut tree, start, &["axyz", "axyzw"]);
+        assert_eq!(tree.len(), 4);
+        let mut ancestry = vec![text(&tree, tree.current)];
+        while let Some(text) = move_to(&mut tree, UndoMove::Undo) {
+            ancestry.push(text);
+        }
+        assert_eq!(ancestry, ["axyzw", "axyz", "axy", "ax"]);
+        assert_eq!(tree.states[ax].parent, None);
+        assert_eq!(tree.target(UndoMove::Steps(-1)), None);
+        assert!(tree.states.contains_key(axy));
+
+        // and the same by size, counting what each edit leaves unshared
+        let mut tree = UndoTree::new(&contents(""), start);
+        tree.max_bytes = 3 * UNSHARED_BYTES;
+        record(&mut tree, start, &["a", "ab", "abc", "abcd"]);
+        assert_eq!(tree.len(), 3);
+        assert!(tree.bytes <= tree.max_bytes);
+        assert_eq!(text(&tree, tree.current), "abcd");
+        let kept: usize = tree.states.values().map(|s| s.bytes).sum();
+        assert_eq!(kept, tree.bytes);
+    }
+
+    #[test]
+    fn dropping_keeps_the_minimum_depth() {
+        let start = Instant::now();
+        let mut tree = UndoTree::new(&contents(""), start);
+        record(&mut tree, start, &["a", "ab", "abc"]);
+        move_to(&mut tree, UndoMove::Undo);
+        record(&mut tree, start, &["abx", "abxy"]);
+        assert_eq!(tree.retained_bytes(), 5 * UNSHARED_BYTES);
+
+        // the abandoned branch goes first, then the oldest ancestors down to the minimum
+        let mut freed = 0;
+        while let Some(bytes) = tree.drop_oldest_state(2) {
+            freed += bytes;
+        }
+        assert_eq!(freed, 2 * UNSHARED_BYTES);
+        assert_eq!(tree.retained_bytes(), 3 * UNSHARED_BYTES);
+        let mut ancestry = vec![text(&tree, tree.current)];
+        while let Some(text) = move_to(&mut tree, UndoMove::Undo) {
+            ancestry.push(text);
+        }
+        assert_eq!(ancestry, ["abxy", "abx", "ab"]);
+
+        // back at "ab", what could be redone isn't on the way back, so it can go too
+        assert_eq!(tree.drop_oldest_state(1), Some(UNSHARED_BYTES));
+        assert_eq!(tree.drop_oldest_state(1), Some(UNSHARED_BYTES));
+        assert_eq!(tree.drop_oldest_state(1), None);
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.retained_bytes(), UNSHARED_BYTES);
+    }
+}
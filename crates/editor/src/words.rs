@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use tore::EditSummary;
+
+use crate::buffer::Contents;
+use crate::movement::CharClass;
+
+/// Words longer than this many bytes are left out of the index.
+const MAX_WORD_LEN: usize = 128;
+/// Buffers longer than this many bytes aren't indexed at all.
+const MAX_INDEXED_LEN: usize = 8 << 20;
+/// An edit across more lines than this leaves the index to be built again from scratch.
+const MAX_RESCAN_LINES: usize = 1_000;
+
+/// Where each word in a buffer occurs, for completing words and finding every occurrence of one.
+/// Words are runs of word chars, as word motions see them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WordIndex {
+    /// The byte range of each occurrence of each word, sorted.
+    words: HashMap<String, Vec<Range<usize>>>,
+    disabled: bool,
+    stale: bool,
+}
+
+impl WordIndex {
+    /// Indexes `contents` in a single pass. A buffer too long to index gets a disabled index.
+    pub fn build(contents: &Contents) -> Self {
+        if contents.len_bytes() > MAX_INDEXED_LEN {
+            return Self { disabled: true, ..Self::default() };
+        }
+        let mut index = Self::default();
+        index.scan(contents, 0..contents.len_bytes());
+        index
+    }
+
+    /// Whether the buffer is short enough to be indexed; what uses the index checks first.
+    pub fn is_enabled(&self) -> bool {
+        !self.disabled
+    }
+
+    /// Whether an edit was too large to follow, leaving the index empty until it's built again.
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    /// The byte range of every occurrence of `word`, sorted.
+    pub fn occurrences(&self, word: &str) -> &[Range<usize>] {
+        self.words.get(word).map_or(&[], Vec::as_slice)
+    }
+
+    /// Up to `limit` of the words starting with `prefix`, the most frequent first, then in
+    /// alphabetical order.
+    pub fn with_prefix(&self, prefix: &str, limit: usize) -> Vec<&str> {
+        let mut words: Vec<_> = self
+            .words
+            .iter()
+            .filter(|(word, _)| word.starts_with(prefix))
+            .map(|(word, ranges)| (ranges.len(), word.as_str()))
+            .collect();
+        words.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+        words
+            .into_iter()
+            .take(limit)
+            .map(|(_, word)| word)
+            .collect()
+    }
+
+    /// Catches up with `edit`, just made to `contents`: the lines it touched are scanned again,
+    /// and the occurrences after them moved along.
+    pub(crate) fn edited(&mut self, contents: &Contents, edit: &EditSummary) {
+        if self.disabled || self.stale {
+            return;
+        }
+        if contents.len_bytes() > MAX_INDEXED_LEN {
+            *self = Self { disabled: true, ..Self::default() };
+            return;
+        }
+        if edit.removed.lines.line.max(edit.inserted.lines.line) > MAX_RESCAN_LINES {
+            *self = Self { stale: true, ..Self::default() };
+            return;
+        }
+        // whole lines, as a word never runs across a line break
+        let start = contents.line_to_byte(contents.byte_to_line(edit.start_offset));
+        let last = contents.byte_to_line(edit.start_offset + edit.inserted.len);
+        let end = contents.line_to_byte((last + 1).min(contents.len_lines()));
+        let old_end = end - edit.inserted.len + edit.removed.len;
+        self.words.retain(|_, ranges| {
+            ranges.retain(|range| range.start < start || range.start >= old_end);
+            for range in ranges.iter_mut().filter(|range| range.start >= old_end) {
+                *range = range.start - old_end + end..range.end - old_end + end;
+            }
+            !ranges.is_empty()
+        });
+        self.scan(contents, start..end);
+    }
+
+    /// Adds the words in the byte range `bytes` of `contents`, which must start and end between
+    /// words.
+    fn scan(&mut self, contents: &Contents, bytes: Range<usize>) {
+        let mut word = String::new();
+        let mut offset = bytes.start;
+        // a trailing space ends the last word
+        for c in contents.byte_slice(bytes).chars().chain([' ']) {
+            if CharClass::of(c) == CharClass::Word {
+                if word.len() <= MAX_WORD_LEN {
+                    word.push(c);
+                }
+            } else if !word.is_empty() {
+                if word.len() <= MAX_WORD_LEN {
+                    self.insert(&word, offset - word.len()..offset);
+                }
+                word.clear();
+            }
+            offset += c.len_utf8();
+        }
+    }
+
+    fn insert(&mut self, word: &str, range: Range<usize>) {
+        let ranges = match self.words.get_mut(word) {
+            Some(ranges) => ranges,
+            None => self.words.entry(word.to_string()).or_default(),
+        };
+        let at = ranges.partition_point(|r| r.start < range.start);
+        ranges.insert(at, range);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Buffer;
+    use test_support::Rng;
+
+    #[test]
+    fn edits_match_a_rebuild() {
+        let pieces = [
+            "foo", "bar", " ", "\n", "fo", "o_1", ".", "\r\n", "é", "ba r", "",
+        ];
+        for seed in 0..50 {
+            let mut rng = Rng::new(seed);
+            let mut buffer = Buffer::new(Default::default(), "foo bar\nbaz foo\n".into());
+            for _ in 0..40 {
+                let len = buffer.contents.len_chars();
+                let start = rng.below(len + 1);
+                let end = (start + rng.below(4)).min(len);
+                let text = *rng.pick(&pieces);
+                buffer.apply_edits(&[(start..end, text)]).unwrap();
+                let rebuilt = WordIndex::build(&buffer.contents);
+                assert_eq!(buffer.word_index(), &rebuilt, "seed {}: {:?}", seed, buffer.contents);
+            }
+        }
+    }
+
+    #[test]
+    fn several_edits_at_once() {
+        let mut buffer = Buffer::new(Default::default(), "a b\nc a\na".into());
+        buffer
+            .apply_edits(&[(0..1, "xy"), (4..5, "a a"), (8..9, "")])
+            .unwrap();
+        assert_eq!(buffer.contents.to_string(), "xy b\na a a\n");
+        assert_eq!(buffer.word_index(), &WordIndex::build(&buffer.contents));
+        assert_eq!(buffer.word_occurrences("a"), &[5..6, 7..8, 9..10]);
+    }
+
+    #[test]
+    fn prefixes_by_frequency_then_alphabetically() {
+        let contents = "set settle setup set settle set sets x".into();
+        let index = WordIndex::build(&contents);
+        assert_eq!(index.with_prefix("set", 10), vec!["set", "settle", "sets", "setup"]);
+        assert_eq!(index.with_prefix("sett", 10), vec!["settle"]);
+        assert_eq!(index.with_prefix("", 2), vec!["set", "settle"]);
+    }
+
+    #[test]
+    fn long_words_and_large_edits() {
+        let long = "x".repeat(MAX_WORD_LEN + 1);
+        let mut buffer = Buffer::new(Default::default(), format!("{} y", long).as_str().into());
+        assert_eq!(buffer.word_occurrences(&long), &[] as &[Range<usize>]);
+        assert_eq!(
+            buffer.word_occurrences("y"),
+            [Range { start: MAX_WORD_LEN + 2, end: MAX_WORD_LEN + 3 }]
+        );
+
+        let lines = "z\n".repeat(MAX_RESCAN_LINES + 1);
+        buffer.apply_edits(&[(0..0, lines.as_str())]).unwrap();
+        assert!(buffer.word_index().is_stale());
+        assert_eq!(buffer.word_occurrences("y"), &[] as &[Range<usize>]);
+
+        // a rebuild of an older version is dropped
+        let (version, rebuilt) = (buffer.version, WordIndex::build(&buffer.contents));
+        buffer.set_word_index(version - 1, rebuilt.clone());
+        assert!(buffer.word_index().is_stale());
+        buffer.set_word_index(version, rebuilt);
+        assert_eq!(buffer.word_occurrences("z").len(), MAX_RESCAN_LINES + 1);
+    }
+}
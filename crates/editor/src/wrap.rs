@@ -0,0 +1,490 @@
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use crate::buffer::Contents;
+use crate::Buffer;
+
+/// How lines are laid out into rows on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrapSettings {
+    /// The columns the text has to fit in.
+    pub width: usize,
+    /// How many columns apart tab stops are.
+    pub tab_width: usize,
+    /// Whether lines longer than `width` go on over the rows below, rather than off the side.
+    pub wrap: bool,
+    /// Whether the rows a line goes on over start as far in as the line does, plus `break_shift`
+    /// columns, like vim's `breakindent`.
+    pub break_indent: bool,
+    pub break_shift: usize,
+    /// How many columns the marker before the rows a line goes on over takes, like vim's
+    /// `showbreak`.
+    pub show_break: usize,
+}
+
+/// How one line is split into screen rows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineLayout {
+    /// The char column each row after the first starts at.
+    pub breaks: Vec<usize>,
+    /// The columns before the text of each row after the first: the break indent and marker.
+    pub indent: usize,
+}
+
+impl LineLayout {
+    pub fn new(contents: &Contents, line: usize, settings: WrapSettings) -> Self {
+        let mut breaks = vec![];
+        if !settings.wrap {
+            return Self { breaks, indent: 0 };
+        }
+        let len = contents.line_len_chars_excluding_terminator(line);
+        let width = settings.width.max(1);
+        let tab_width = settings.tab_width.max(1);
+        let indent = Self::continuation_indent(contents, line, settings);
+        let (mut x, mut used, mut room) = (0, 0, width);
+        for (column, c) in contents.line(line).chars().take(len).enumerate() {
+            let w = if c == '\t' {
+                tab_width - x % tab_width
+            } else {
+                1
+            };
+            if used > 0 && used + w > room {
+                breaks.push(column);
+                used = 0;
+                room = width - indent;
+            }
+            x += w;
+            used += w;
+        }
+        Self { breaks, indent }
+    }
+
+    /// How far in the rows after the line's first start: past its own leading whitespace and the
+    /// shift with break indent on, and the marker. At least half the width is left for the
+    /// text, so a deeply indented line in a narrow pane still gets somewhere.
+    fn continuation_indent(contents: &Contents, line: usize, settings: WrapSettings) -> usize {
+        let mut indent = settings.show_break;
+        if settings.break_indent {
+            let tab_width = settings.tab_width.max(1);
+            let leading = contents
+                .line(line)
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t');
+            let columns = leading.fold(0, |x, c| match c {
+                '\t' => x + tab_width - x % tab_width,
+                _ => x + 1,
+            });
+            indent += columns + settings.break_shift;
+        }
+        let width = settings.width.max(1);
+        indent.min(width - width.div_ceil(2))
+    }
+
+    pub fn rows(&self) -> usize {
+        self.breaks.len() + 1
+    }
+
+    /// The row the char at `column` is on.
+    pub fn row_of_column(&self, column: usize) -> usize {
+        self.breaks.partition_point(|start| *start <= column)
+    }
+
+    /// The char column `row` of the line starts at, and how many columns in its text starts on
+    /// screen.
+    pub fn row_start(&self, row: usize) -> (usize, usize) {
+        match row.checked_sub(1) {
+            None => (0, 0),
+            Some(i) => (self.breaks[i], self.indent),
+        }
+    }
+}
+
+/// The layout of every line of a buffer for one editor, kept between frames. An edit only lays
+/// out the lines it touched again; new settings lay out everything. A Fenwick tree over the row
+/// counts makes finding a row, and counting them, logarithmic.
+#[derive(Debug, Default)]
+pub struct WrapLayout {
+    settings: Option<WrapSettings>,
+    /// The buffer version the layouts hold for.
+    version: u64,
+    /// None for the lines still to be laid out.
+    lines: Vec<Option<LineLayout>>,
+    stale: BTreeSet<usize>,
+    /// The rows of each laid out line; stale lines count for none until they're laid out.
+    rows: RowSums,
+}
+
+impl WrapLayout {
+    /// Notes that the buffer's last edit changed the byte range `range` of its contents, as they
+    /// are now. The lines it covers are laid out again when next asked for; an edit that
+    /// doesn't follow on from the version the layout holds for throws everything out.
+    pub fn edited(&mut self, buffer: &Buffer, range: Range<usize>) {
+        let (contents, version) = (&buffer.contents, buffer.version);
+        if self.settings.is_none() || version != self.version + 1 {
+            self.clear(None);
+            return;
+        }
+        self.version = version;
+        let start = contents.byte_to_line(range.start);
+        let end = contents.byte_to_line(range.end) + 1;
+        let delta = contents.len_lines() as isize - self.lines.len() as isize;
+        let old_end = end.saturating_add_signed(-delta).max(start);
+        if delta == 0 {
+            for line in start..end {
+                if let Some(layout) = self.lines[line].take() {
+                    self.rows.add(line, -(layout.rows() as isize));
+                }
+                self.stale.insert(line);
+            }
+            return;
+        }
+        self.lines
+            .splice(start..old_end, (start..end).map(|_| None));
+        self.stale = (0..self.lines.len())
+            .filter(|&line| self.lines[line].is_none())
+            .collect();
+        let rows = self
+            .lines
+            .iter()
+            .map(|layout| layout.as_ref().map_or(0, LineLayout::rows));
+        self.rows = RowSums::new(rows);
+    }
+
+    /// Whether `line` is laid out, and won't be again until something changes it.
+    pub fn is_cached(&self, line: usize) -> bool {
+        self.lines.get(line).is_some_and(Option::is_some)
+    }
+
+    /// How `line` is split into rows.
+    pub fn line(&mut self, buffer: &Buffer, settings: WrapSettings, line: usize) -> &LineLayout {
+        self.sync(buffer, settings);
+        if self.lines[line].is_none() {
+            self.lay_out(&buffer.contents, settings, line);
+        }
+        self.lines[line].as_ref().unwrap()
+    }
+
+    /// How many rows all the lines take.
+    pub fn total_rows(&mut self, buffer: &Buffer, settings: WrapSettings) -> usize {
+        self.sync_all(buffer, settings);
+        self.rows.prefix(self.lines.len())
+    }
+
+    /// The row `line` starts on.
+    pub fn row_of_line(&mut self, buffer: &Buffer, settings: WrapSettings, line: usize) -> usize {
+        self.sync_all(buffer, settings);
+        self.rows.prefix(line.min(self.lines.len()))
+    }
+
+    /// The line on `row`, and which of its rows it is; the last row of the last line when `row`
+    /// is past the end.
+    pub fn line_at_row(
+        &mut self,
+        buffer: &Buffer,
+        settings: WrapSettings,
+        row: usize,
+    ) -> (usize, usize) {
+        let total = self.total_rows(buffer, settings);
+        let row = row.min(total - 1);
+        let line = self.rows.find(row);
+        (line, row - self.rows.prefix(line))
+    }
+
+    fn clear(&mut self, settings: Option<WrapSettings>) {
+        self.settings = settings;
+        self.lines.clear();
+        self.stale.clear();
+        self.rows = RowSums::default();
+    }
+
+    /// Throws out the layouts if they were made with other settings, or for another version.
+    fn sync(&mut self, buffer: &Buffer, settings: WrapSettings) {
+        let len = buffer.contents.len_lines();
+        let synced = self.settings == Some(settings) && self.version == buffer.version;
+        if !synced || self.lines.len() != len {
+            self.clear(Some(settings));
+            self.version = buffer.version;
+            self.lines = vec![None; len];
+            self.stale = (0..len).collect();
+            self.rows = RowSums::new(std::iter::repeat(0).take(len));
+        }
+    }
+
+    fn sync_all(&mut self, buffer: &Buffer, settings: WrapSettings) {
+        self.sync(buffer, settings);
+        while let Some(line) = self.stale.first().copied() {
+            self.lay_out(&buffer.contents, settings, line);
+        }
+    }
+
+    fn lay_out(&mut self, contents: &Contents, settings: WrapSettings, line: usize) {
+        let layout = LineLayout::new(contents, line, settings);
+        self.rows.add(line, layout.rows() as isize);
+        self.lines[line] = Some(layout);
+        self.stale.remove(&line);
+    }
+}
+
+/// Prefix sums over the rows each line takes, as a Fenwick tree.
+#[derive(Debug, Default)]
+struct RowSums(Vec<usize>);
+
+impl RowSums {
+    fn new(rows: impl Iterator<Item = usize>) -> Self {
+        let mut tree: Vec<usize> = rows.collect();
+        for i in 0..tree.len() {
+            let parent = i | (i + 1);
+            if parent < tree.len() {
+                tree[parent] += tree[i];
+            }
+        }
+        Self(tree)
+    }
+
+    fn add(&mut self, line: usize, delta: isize) {
+        let mut i = line;
+        while i < self.0.len() {
+            self.0[i] = self.0[i].saturating_add_signed(delta);
+            i |= i + 1;
+        }
+    }
+
+    /// The rows the lines before `line` take.
+    fn prefix(&self, line: usize) -> usize {
+        let (mut sum, mut i) = (0, line);
+        while i > 0 {
+            sum += self.0[i - 1];
+            i &= i - 1;
+        }
+        sum
+    }
+
+    /// The line whose rows include `row`: the last one with no more than `row` rows before it.
+    fn find(&self, row: usize) -> usize {
+        let (mut line, mut left) = (0, row);
+        let mut step = self.0.len().next_power_of_two();
+        while step > 0 {
+            let next = line + step;
+            if next <= self.0.len() && self.0[next - 1] <= left {
+                line = next;
+                left -= self.0[next - 1];
+            }
+            step /= 2;
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Editor, EditorCommand, Point};
+
+    const SETTINGS: WrapSettings = WrapSettings {
+        width: 4,
+        tab_width: 4,
+        wrap: true,
+        break_indent: false,
+        break_shift: 0,
+        show_break: 0,
+    };
+
+    #[test]
+    fn line_breaks() {
+        let contents = Contents::from("abcdefghij\na\tb\t\tc\n\n");
+        let breaks = |line, settings| LineLayout::new(&contents, line, settings).breaks;
+        assert_eq!(breaks(0, SETTINGS), [4, 8]);
+        // tabs reach the next stop, and one that doesn't fit starts the next row
+        assert_eq!(breaks(1, SETTINGS), [2, 4, 5]);
+        assert_eq!(breaks(2, SETTINGS), []);
+        let unwrapped = WrapSettings { wrap: false, ..SETTINGS };
+        assert_eq!(breaks(0, unwrapped), []);
+    }
+
+    #[test]
+    fn continuation_rows_keep_the_indent() {
+        let contents = Contents::from("        let x = aaaa + bbbb;\n\tab\tcdefghijkl\n");
+        let settings = WrapSettings { width: 12, break_indent: true, show_break: 2, ..SETTINGS };
+        let layout = LineLayout::new(&contents, 0, settings);
+        // the first row has the whole width; the rest lose the indent of 8 and the marker,
+        // which leaves only 2 columns, so the indent is cut to leave half the width
+        assert_eq!(layout.indent, 6);
+        assert_eq!(layout.breaks, [12, 18, 24]);
+        assert_eq!(layout.row_of_column(0), 0);
+        assert_eq!(layout.row_of_column(11), 0);
+        assert_eq!(layout.row_of_column(12), 1);
+        assert_eq!(layout.row_of_column(27), 3);
+        assert_eq!(layout.row_start(0), (0, 0));
+        assert_eq!(layout.row_start(2), (18, 6));
+
+        // a tab's indent counts to its stop, and a shift is added to it
+        let settings = WrapSettings { width: 16, break_shift: 2, ..settings };
+        let layout = LineLayout::new(&contents, 1, settings);
+        assert_eq!(layout.indent, 4 + 2 + 2);
+        assert_eq!(layout.breaks, [12]);
+
+        // with break indent off, only the marker takes room
+        let marked = WrapSettings { break_indent: false, ..settings };
+        let layout = LineLayout::new(&contents, 0, marked);
+        assert_eq!((layout.indent, layout.breaks.len()), (2, 1));
+        let plain = LineLayout::new(&contents, 0, SETTINGS);
+        assert_eq!(plain.indent, 0);
+        assert_eq!(plain.rows(), 7);
+    }
+
+    #[test]
+    fn edits_invalidate_their_lines() {
+        let mut buffer = Buffer::new(Default::default(), "abcdef\nab\nabcdefghi\nx\n".into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let total =
+            |editor: &mut Editor, buffer: &Buffer| editor.layout().total_rows(buffer, SETTINGS);
+        assert_eq!(total(&mut editor, &buffer), 2 + 1 + 3 + 1 + 1);
+        assert!((0..5).all(|line| editor.layout().is_cached(line)));
+
+        editor.set_cursor(Point { line: 1, column: 2 });
+        editor
+            .command(&mut buffer, EditorCommand::InsertChar('c'))
+            .unwrap();
+        let cached: Vec<_> = (0..5).map(|line| editor.layout().is_cached(line)).collect();
+        assert_eq!(cached, [true, false, true, true, true]);
+        assert_eq!(total(&mut editor, &buffer), 8);
+        editor
+            .command(&mut buffer, EditorCommand::InsertChar('d'))
+            .unwrap();
+        editor
+            .command(&mut buffer, EditorCommand::InsertChar('e'))
+            .unwrap();
+        assert_eq!(total(&mut editor, &buffer), 9);
+        assert_eq!(editor.layout().line_at_row(&buffer, SETTINGS, 3), (1, 1));
+
+        // a new line shifts those after it along without laying them out again
+        editor
+            .command(&mut buffer, EditorCommand::InsertChar('\n'))
+            .unwrap();
+        let cached: Vec<_> = (0..6).map(|line| editor.layout().is_cached(line)).collect();
+        assert_eq!(cached, [true, false, false, true, true, true]);
+        assert_eq!(editor.layout().row_of_line(&buffer, SETTINGS, 3), 5);
+
+        // as does an edit some other editor made, though it throws everything out
+        buffer.apply_edits(&[(0..3, "")]).unwrap();
+        assert_eq!(editor.layout().row_of_line(&buffer, SETTINGS, 3), 4);
+        let settings = WrapSettings { width: 10, ..SETTINGS };
+        assert_eq!(editor.layout().total_rows(&buffer, settings), 6);
+    }
+
+    #[test]
+    fn vertical_motions_go_by_rows() {
+        use crate::{CursorJump, Direction::*};
+        let mut buffer = Buffer::new(Default::default(), "abcdefghij\nab\n".into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.wrap = Some(SETTINGS);
+        let (j, k) = (EditorCommand::CursorMove(Down), EditorCommand::CursorMove(Up));
+        let end = EditorCommand::CursorJump(CursorJump::EndOfBuffer);
+        let start = EditorCommand::CursorJump(CursorJump::StartOfBuffer);
+        // each command from where the last left the cursor, and where it goes: its line, column
+        // and row
+        let cases = [
+            (j.clone(), (0, 5, 1)),
+            (j.clone(), (0, 9, 2)),
+            (j.clone(), (1, 1, 3)),
+            (j.clone(), (2, 0, 4)),
+            (j.clone(), (2, 0, 4)),
+            (k.clone(), (1, 1, 3)),
+            (k.clone(), (0, 9, 2)),
+            (k.clone(), (0, 5, 1)),
+            (start, (0, 0, 0)),
+            (end, (2, 0, 4)),
+        ];
+        editor.set_cursor(Point { line: 0, column: 1 });
+        for (command, expected) in cases {
+            editor.command(&mut buffer, command.clone()).unwrap();
+            let cursor = editor.cursor();
+            let row = editor.row_of(&buffer, cursor);
+            assert_eq!((cursor.line, cursor.column, row), expected, "{:?}", command);
+        }
+        assert_eq!(editor.total_rows(&buffer), 5);
+
+        // a short last row keeps the cursor on its last char
+        editor.set_cursor(Point { line: 0, column: 7 });
+        editor.command(&mut buffer, j.clone()).unwrap();
+        assert_eq!(editor.cursor(), Point { line: 0, column: 9 });
+
+        // without wrapping a row is a line
+        editor.wrap = None;
+        editor.command(&mut buffer, j).unwrap();
+        assert_eq!(editor.cursor(), Point { line: 1, column: 1 });
+        assert_eq!(editor.total_rows(&buffer), 3);
+    }
+
+    #[test]
+    fn down_visits_every_row() {
+        let text = "  a b\tc d e f g\n\n\tabcdefghijklmnop\nxy\n";
+        let mut buffer = Buffer::new(Default::default(), text.into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let settings = WrapSettings { width: 6, break_indent: true, show_break: 1, ..SETTINGS };
+        editor.wrap = Some(settings);
+        let total = editor.total_rows(&buffer);
+        let brute: usize = (0..buffer.contents.len_lines())
+            .map(|line| LineLayout::new(&buffer.contents, line, settings).rows())
+            .sum();
+        assert_eq!(total, brute);
+        for row in 1..total {
+            let down = EditorCommand::CursorMove(crate::Direction::Down);
+            editor.command(&mut buffer, down).unwrap();
+            assert_eq!(editor.row_of(&buffer, editor.cursor()), row);
+        }
+    }
+
+    #[test]
+    fn rows_match_brute_force() {
+        // xorshift, so the buffers are random but the same every run
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut random = |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize % n
+        };
+        for _ in 0..20 {
+            let settings = WrapSettings {
+                width: 1 + random(12),
+                tab_width: 1 + random(8),
+                break_indent: random(2) == 0,
+                show_break: random(3),
+                ..SETTINGS
+            };
+            let mut text = String::new();
+            for _ in 0..random(60) {
+                text.push(['a', 'b', ' ', '\t', '\n', '\n'][random(6)]);
+            }
+            let mut buffer = Buffer::new(Default::default(), text.as_str().into());
+            let mut layout = WrapLayout::default();
+            for _ in 0..10 {
+                let rows: Vec<usize> = (0..buffer.contents.len_lines())
+                    .map(|line| LineLayout::new(&buffer.contents, line, settings).rows())
+                    .collect();
+                let total: usize = rows.iter().sum();
+                assert_eq!(layout.total_rows(&buffer, settings), total);
+                let mut row = 0;
+                for (line, &count) in rows.iter().enumerate() {
+                    assert_eq!(layout.row_of_line(&buffer, settings, line), row);
+                    for within in 0..count {
+                        assert_eq!(
+                            layout.line_at_row(&buffer, settings, row + within),
+                            (line, within)
+                        );
+                    }
+                    row += count;
+                }
+                assert_eq!(layout.line_at_row(&buffer, settings, total + 5).0, rows.len() - 1);
+
+                let len = buffer.contents.len_chars();
+                let start = random(len + 1);
+                let end = start + random(len - start + 1).min(8);
+                let text = ["", "x", "\n", "ab\tcd\nef", "\n\n"][random(5)];
+                let (_, range) = buffer.apply_edits(&[(start..end, text)]).unwrap();
+                layout.edited(&buffer, range);
+            }
+        }
+    }
+}
@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use editor::Diagnostic;
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, BufReader};
+use tokio::process::Child;
+use tokio::sync::mpsc;
+
+use crate::codec::{read_message, write_message};
+use crate::protocol::{self, Incoming, INITIALIZE_ID};
+
+/// How long after a server first exits it's started again; each time in a row it exits before
+/// it's initialized doubles it.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// How many times in a row a server is started again before it's given up on.
+const MAX_RETRIES: u32 = 5;
+
+/// What the language servers tell the editor.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The problems a server found in the file, at the version it said if it did.
+    Diagnostics {
+        path: PathBuf,
+        version: Option<u64>,
+        diagnostics: Vec<Diagnostic>,
+    },
+    /// A language's server exited or couldn't be started, why, and how long until it's started
+    /// again, if it ever is.
+    Exited {
+        language: String,
+        error: String,
+        retry: Option<Duration>,
+    },
+}
+
+/// The way to a running server: what it writes, what it reads, and its process, killed when the
+/// connection is dropped.
+pub struct Connection {
+    reader: Box<dyn AsyncBufRead + Unpin + Send>,
+    writer: Box<dyn AsyncWrite + Unpin + Send>,
+    _child: Option<Child>,
+}
+
+impl Connection {
+    pub fn new(
+        reader: impl AsyncRead + Unpin + Send + 'static,
+        writer: impl AsyncWrite + Unpin + Send + 'static,
+    ) -> Self {
+        let reader = Box::new(BufReader::new(reader));
+        Self { reader, writer: Box::new(writer), _child: None }
+    }
+
+    /// Starts the program `command` names with the rest as its arguments, talking to it over
+    /// its standard input and output.
+    pub fn spawn(command: &[String]) -> io::Result<Self> {
+        let Some((program, args)) = command.split_first() else {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "no command"));
+        };
+        let mut child = tokio::process::Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Ok(Self { _child: Some(child), ..Self::new(stdout, stdin) })
+    }
+}
+
+/// A file's text at a version, as the server was last told it.
+#[derive(Debug, Clone)]
+struct Document {
+    version: u64,
+    text: String,
+}
+
+/// One language's server, run in a task that starts it again whenever it exits, and opens every
+/// file it was told about again once it has.
+#[derive(Debug, Clone)]
+pub struct Server {
+    tx: mpsc::UnboundedSender<(PathBuf, Document)>,
+}
+
+impl Server {
+    /// Starts the server with `connect`, sending what it says to `events`. It's shut down once
+    /// every handle on it is dropped.
+    pub fn start<C>(language: String, connect: C, events: mpsc::UnboundedSender<Event>) -> Self
+    where
+        C: FnMut() -> io::Result<Connection> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(language, connect, rx, events));
+        Self { tx }
+    }
+
+    /// Tells the server the file's text at `version`, opening it if the server hasn't seen it.
+    pub fn sync(&self, path: PathBuf, version: u64, text: String) {
+        // a server that was given up on has nobody to tell
+        let _ = self.tx.send((path, Document { version, text }));
+    }
+}
+
+async fn run<C>(
+    language: String,
+    mut connect: C,
+    mut rx: mpsc::UnboundedReceiver<(PathBuf, Document)>,
+    events: mpsc::UnboundedSender<Event>,
+) where
+    C: FnMut() -> io::Result<Connection>,
+{
+    let mut documents = HashMap::new();
+    let mut failures = 0;
+    loop {
+        let mut initialized = false;
+        let result = match connect() {
+            Ok(connection) => {
+                let session = Session { language: &language, events: &events, connection };
+                session.run(&mut rx, &mut documents, &mut initialized).await
+            }
+            Err(err) => Err(err.into()),
+        };
+        let Err(err) = result else {
+            return;
+        };
+        failures = if initialized { 1 } else { failures + 1 };
+        let retry = (failures <= MAX_RETRIES).then(|| RETRY_DELAY * 2u32.pow(failures - 1));
+        tracing::warn!(%language, ?retry, "language server exited: {:#}", err);
+        let error = format!("{:#}", err);
+        let exited = Event::Exited { language: language.clone(), error, retry };
+        if events.send(exited).is_err() {
+            return;
+        }
+        let Some(retry) = retry else {
+            return;
+        };
+
+        // what changes meanwhile is opened with the rest
+        let deadline = tokio::time::sleep(retry);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                _ = &mut deadline => break,
+                document = rx.recv() => match document {
+                    Some((path, document)) => drop(documents.insert(path, document)),
+                    None => return,
+                },
+            }
+        }
+    }
+}
+
+/// A server started once, until it exits.
+struct Session<'a> {
+    language: &'a str,
+    events: &'a mpsc::UnboundedSender<Event>,
+    connection: Connection,
+}
+
+impl Session<'_> {
+    /// Initializes the server, opens every file in `documents`, then keeps it told about each
+    /// change until there's nobody left to send them, or fails with why the server stopped.
+    async fn run(
+        mut self,
+        rx: &mut mpsc::UnboundedReceiver<(PathBuf, Document)>,
+        documents: &mut HashMap<PathBuf, Document>,
+        initialized: &mut bool,
+    ) -> Result<()> {
+        // reading a message can't be cancelled halfway, so it's left to run on its own
+        let (incoming_tx, mut incoming) = mpsc::unbounded_channel();
+        let reader = std::mem::replace(&mut self.connection.reader, Box::new(&b""[..]));
+        let reading = tokio::spawn(read_messages(reader, incoming_tx));
+        let result: Result<()> = async {
+            let root = std::env::current_dir().ok();
+            self.write(&protocol::initialize(root.as_deref())).await?;
+            loop {
+                let body = incoming.recv().await;
+                let body = body.ok_or_else(|| anyhow!("exited before it was initialized"))?;
+                match Incoming::parse(&body) {
+                    Ok(Incoming::Response { id, error }) if id == INITIALIZE_ID => {
+                        if let Some(error) = error {
+                            bail!("failed to initialize: {}", error);
+                        }
+                        break;
+                    }
+                    Ok(message) => self.handle(message).await?,
+                    Err(err) => tracing::warn!(%err, "skipped a malformed message"),
+                }
+            }
+            self.write(&protocol::initialized()).await?;
+            *initialized = true;
+            for (path, document) in documents.iter() {
+                let Document { version, text } = document;
+                self.write(&protocol::did_open(path, self.language, *version, text))
+                    .await?;
+            }
+
+            loop {
+                tokio::select! {
+                    body = incoming.recv() => {
+                        let body = body.ok_or_else(|| anyhow!("exited"))?;
+                        match Incoming::parse(&body) {
+                            Ok(message) => self.handle(message).await?,
+                            Err(err) => tracing::warn!(%err, "skipped a malformed message"),
+                        }
+                    }
+                    document = rx.recv() => match document {
+                        Some((path, document)) => self.sync(documents, path, document).await?,
+                        None => {
+                            // it's killed as the connection's dropped, whether it exits or not
+                            let _ = self.write(&protocol::shutdown(INITIALIZE_ID + 1)).await;
+                            let _ = self.write(&protocol::exit()).await;
+                            return Ok(());
+                        }
+                    },
+                }
+            }
+        }
+        .await;
+        reading.abort();
+        result
+    }
+
+    async fn sync(
+        &mut self,
+        documents: &mut HashMap<PathBuf, Document>,
+        path: PathBuf,
+        document: Document,
+    ) -> Result<()> {
+        let message = match documents.get(&path) {
+            Some(open) if open.version == document.version => return Ok(()),
+            Some(_) => protocol::did_change(&path, document.version, &document.text),
+            None => protocol::did_open(&path, self.language, document.version, &document.text),
+        };
+        // kept first, so that if the server's gone the next one's told
+        documents.insert(path, document);
+        self.write(&message).await
+    }
+
+    async fn handle(&mut self, message: Incoming) -> Result<()> {
+        match message {
+            Incoming::Request { id, method } => {
+                tracing::debug!(%method, "answered a server request with nothing");
+                self.write(&protocol::empty_response(&id)).await?;
+            }
+            Incoming::Notification { method, params } => match method.as_str() {
+                "textDocument/publishDiagnostics" => match protocol::published_diagnostics(&params)
+                {
+                    Ok((path, version, diagnostics)) => {
+                        let _ = self
+                            .events
+                            .send(Event::Diagnostics { path, version, diagnostics });
+                    }
+                    Err(err) => tracing::warn!(%err, "skipped malformed diagnostics"),
+                },
+                _ => tracing::trace!(%method, "ignored a notification"),
+            },
+            Incoming::Response { id, error } => {
+                if let Some(error) = error {
+                    tracing::warn!(%id, %error, "a request failed");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, body: &[u8]) -> Result<()> {
+        write_message(&mut self.connection.writer, body).await?;
+        Ok(())
+    }
+}
+
+/// Sends the body of each message read to `tx`, skipping those that aren't framed right, until
+/// the server stops writing.
+async fn read_messages(
+    mut reader: Box<dyn AsyncBufRead + Unpin + Send>,
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+) {
+    loop {
+        match read_message(&mut reader).await {
+            Ok(Some(body)) => {
+                if tx.send(body).is_err() {
+                    return;
+                }
+            }
+            Ok(None) => return,
+            Err(err) if err.kind() == ErrorKind::InvalidData => {
+                tracing::warn!(%err, "skipped a malformed message");
+            }
+            Err(err) => {
+                tracing::warn!(%err, "stopped reading from the server");
+                return;
+            }
+        }
+    }
+}
+
+/// The servers of each language there's a command for, each started the first time a file in
+/// its language is synced.
+#[derive(Debug)]
+pub struct Clients {
+    commands: HashMap<String, Vec<String>>,
+    servers: HashMap<String, Server>,
+    events: mpsc::UnboundedSender<Event>,
+}
+
+impl Clients {
+    pub fn new(events: mpsc::UnboundedSender<Event>) -> Self {
+        Self { commands: HashMap::new(), servers: HashMap::new(), events }
+    }
+
+    /// Sets the command each language's server is started with. A server already running is
+    /// left to run as it was started.
+    pub fn set_commands(&mut self, commands: HashMap<String, Vec<String>>) {
+        self.commands = commands;
+    }
+
+    /// Whether files in the language have a server to be synced with.
+    pub fn serves(&self, language: &str) -> bool {
+        self.servers.contains_key(language) || self.commands.contains_key(language)
+    }
+
+    /// Tells the language's server the file's text at `version`, starting it first if it isn't
+    /// running.
+    pub fn sync(&mut self, language: &str, path: &Path, version: u64, text: String) {
+        if !self.servers.contains_key(language) {
+            let Some(command) = self.commands.get(language).cloned() else {
+                return;
+            };
+            let connect = move || Connection::spawn(&command);
+            let server = Server::start(language.to_string(), connect, self.events.clone());
+            self.servers.insert(language.to_string(), server);
+        }
+        self.servers[language].sync(path.to_path_buf(), version, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::Severity;
+    use serde_json::{json, Value};
+    use tokio::io::{DuplexStream, ReadHalf, WriteHalf};
+
+    /// The server's end of a connection, scripted by the test.
+    struct Fake {
+        reader: BufReader<ReadHalf<DuplexStream>>,
+        writer: WriteHalf<DuplexStream>,
+    }
+
+    impl Fake {
+        async fn recv(&mut self) -> Value {
+            let body = read_message(&mut self.reader).await.unwrap().unwrap();
+            serde_json::from_slice(&body).unwrap()
+        }
+
+        async fn send(&mut self, message: Value) {
+            let body = message.to_string();
+            write_message(&mut self.writer, body.as_bytes())
+                .await
+                .unwrap();
+        }
+
+        async fn send_raw(&mut self, bytes: &[u8]) {
+            use tokio::io::AsyncWriteExt;
+            self.writer.write_all(bytes).await.unwrap();
+        }
+
+        async fn handshake(&mut self) {
+            let initialize = self.recv().await;
+            assert_eq!(initialize["method"], "initialize");
+            assert_eq!(initialize["params"]["processId"], std::process::id());
+            let result = json!({ "capabilities": { "textDocumentSync": 1 } });
+            self.send(json!({ "jsonrpc": "2.0", "id": initialize["id"], "result": result }))
+                .await;
+            assert_eq!(self.recv().await["method"], "initialized");
+        }
+    }
+
+    /// Connects to a fake server each time, handing its end to the test.
+    fn fakes() -> (impl FnMut() -> io::Result<Connection>, mpsc::UnboundedReceiver<Fake>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let connect = move || {
+            let (client, server) = tokio::io::duplex(1 << 16);
+            let (reader, writer) = tokio::io::split(client);
+            let (server_reader, server_writer) = tokio::io::split(server);
+            let fake = Fake { reader: BufReader::new(server_reader), writer: server_writer };
+            tx.send(fake).unwrap();
+            Ok(Connection::new(reader, writer))
+        };
+        (connect, rx)
+    }
+
+    fn text_document(message: &Value) -> (&str, &Value, &Value) {
+        let method = message["method"].as_str().unwrap();
+        let params = &message["params"];
+        (method, &params["textDocument"], params)
+    }
+
+    #[tokio::test]
+    async fn opens_then_changes_documents() {
+        let (connect, mut fakes) = fakes();
+        let (events, _events) = mpsc::unbounded_channel();
+        let server = Server::start("rust".into(), connect, events);
+        // sent before the server's initialized, and held until it is
+        server.sync("/src/main.rs".into(), 0, "fn main() {}\n".into());
+        let mut fake = fakes.recv().await.unwrap();
+        fake.handshake().await;
+
+        let open = fake.recv().await;
+        let (method, document, _) = text_document(&open);
+        assert_eq!(method, "textDocument/didOpen");
+        assert_eq!(
+            document,
+            &json!({
+                "uri": "file:///src/main.rs",
+                "languageId": "rust",
+                "version": 0,
+                "text": "fn main() {}\n",
+            })
+        );
+
+        server.sync("/src/main.rs".into(), 2, "fn main() { x }\n".into());
+        // the same version again isn't sent
+        server.sync("/src/main.rs".into(), 2, "fn main() { x }\n".into());
+        server.sync("/src/main.rs".into(), 3, "fn main() { xy }\n".into());
+        for (version, text) in [(2, "fn main() { x }\n"), (3, "fn main() { xy }\n")] {
+            let change = fake.recv().await;
+            let (method, document, params) = text_document(&change);
+            assert_eq!(method, "textDocument/didChange");
+            assert_eq!(document, &json!({ "uri": "file:///src/main.rs", "version": version }));
+            assert_eq!(params["contentChanges"], json!([{ "text": text }]));
+        }
+
+        // a request of the server's is answered, if with nothing
+        fake.send(json!({ "jsonrpc": "2.0", "id": "p", "method": "client/registerCapability" }))
+            .await;
+        assert_eq!(fake.recv().await, json!({ "jsonrpc": "2.0", "id": "p", "result": null }));
+
+        drop(server);
+        assert_eq!(fake.recv().await["method"], "shutdown");
+        assert_eq!(fake.recv().await["method"], "exit");
+    }
+
+    #[tokio::test]
+    async fn diagnostics_are_delivered_past_malformed_messages() {
+        let (connect, mut fakes) = fakes();
+        let (events, mut events_rx) = mpsc::unbounded_channel();
+        let _server = Server::start("rust".into(), connect, events);
+        let mut fake = fakes.recv().await.unwrap();
+        fake.handshake().await;
+
+        fake.send_raw(b"Content-Type: text/plain\r\n\r\n").await;
+        fake.send_raw(b"Content-Length: 8\r\n\r\nnot json").await;
+        let params = json!({
+            "uri": "file:///src/main.rs",
+            "version": 4,
+            "diagnostics": [{
+                "range": { "start": { "line": 2, "character": 1 }, "end": { "line": 2, "character": 5 } },
+                "severity": 1,
+                "message": "cannot find value `x`",
+            }],
+        });
+        let method = "textDocument/publishDiagnostics";
+        fake.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+            .await;
+
+        let diagnostic = Diagnostic {
+            lines: 2..3,
+            severity: Severity::Error,
+            message: "cannot find value `x`".into(),
+        };
+        assert_eq!(
+            events_rx.recv().await.unwrap(),
+            Event::Diagnostics {
+                path: "/src/main.rs".into(),
+                version: Some(4),
+                diagnostics: vec![diagnostic],
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restarted_after_a_crash_with_the_latest_text() {
+        let (connect, mut fakes) = fakes();
+        let (events, mut events_rx) = mpsc::unbounded_channel();
+        let server = Server::start("rust".into(), connect, events);
+        server.sync("/a.rs".into(), 0, "a".into());
+        let mut fake = fakes.recv().await.unwrap();
+        fake.handshake().await;
+        assert_eq!(fake.recv().await["params"]["textDocument"]["version"], 0);
+
+        drop(fake);
+        let Event::Exited { language, retry, .. } = events_rx.recv().await.unwrap() else {
+            panic!("expected the server to have exited");
+        };
+        assert_eq!((language.as_str(), retry), ("rust", Some(RETRY_DELAY)));
+        server.sync("/a.rs".into(), 1, "ab".into());
+
+        let mut fake = fakes.recv().await.unwrap();
+        fake.handshake().await;
+        let open = fake.recv().await;
+        let (method, document, _) = text_document(&open);
+        assert_eq!(method, "textDocument/didOpen");
+        assert_eq!((&document["version"], &document["text"]), (&json!(1), &json!("ab")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn given_up_on_after_failing_to_start_again_and_again() {
+        let connect = || Err(io::Error::new(ErrorKind::NotFound, "no such server"));
+        let (events, mut events_rx) = mpsc::unbounded_channel();
+        let _server = Server::start("rust".into(), connect, events);
+
+        let mut retries = vec![];
+        while let Some(Event::Exited { error, retry, .. }) = events_rx.recv().await {
+            assert_eq!(error, "no such server");
+            retries.push(retry.map(|retry| retry.as_secs()));
+        }
+        assert_eq!(retries, [Some(1), Some(2), Some(4), Some(8), Some(16), None]);
+    }
+}
@@ -0,0 +1,119 @@
+use std::io::{Error, ErrorKind, Result};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Reads the body of the next message, framed by a `Content-Length` header and a blank line, or
+/// None at the end of the stream. A header block without a length fails as invalid data, with
+/// the stream left at the next message, so it can be skipped.
+pub async fn read_message<R>(reader: &mut R) -> Result<Option<Vec<u8>>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut length = None;
+    let mut line = String::new();
+    let mut first = true;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            if first {
+                return Ok(None);
+            }
+            return Err(Error::new(ErrorKind::UnexpectedEof, "stream ended in a header"));
+        }
+        first = false;
+        let header = line.trim_end_matches(['\r', '\n']);
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(Error::new(ErrorKind::InvalidData, "no Content-Length header"));
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(body))
+}
+
+/// Writes `body` as a message, with its `Content-Length`.
+pub async fn write_message<W>(writer: &mut W, body: &[u8]) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let header = format!("Content-Length: {}\r\n\r\n", body.len());
+    writer.write_all(header.as_bytes()).await?;
+    writer.write_all(body).await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read_all(mut input: &[u8]) -> Vec<Result<Option<Vec<u8>>>> {
+        let mut messages = vec![];
+        loop {
+            let message = read_message(&mut input).await;
+            let done = matches!(message, Ok(None)) || input.is_empty() && message.is_err();
+            messages.push(message);
+            if done {
+                return messages;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let mut out = vec![];
+        write_message(&mut out, br#"{"a":1}"#).await.unwrap();
+        write_message(&mut out, "{\"é\":2}".as_bytes())
+            .await
+            .unwrap();
+        // the length counts bytes, not chars
+        assert!(out.starts_with(b"Content-Length: 7\r\n\r\n{\"a\":1}Content-Length: 8\r\n"));
+
+        let messages = read_all(&out).await;
+        let messages: Vec<_> = messages.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            messages,
+            [
+                Some(br#"{"a":1}"#.to_vec()),
+                Some("{\"é\":2}".as_bytes().to_vec()),
+                None
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn other_headers_are_ignored() {
+        let input = b"Content-Type: application/vscode-jsonrpc\r\ncontent-length: 2\r\n\r\n{}";
+        let mut input = &input[..];
+        assert_eq!(read_message(&mut input).await.unwrap(), Some(b"{}".to_vec()));
+        assert_eq!(read_message(&mut input).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn a_message_without_a_length_is_skipped() {
+        let input = b"Content-Type: x\r\n\r\nContent-Length: 2\r\n\r\n{}";
+        let messages = read_all(input).await;
+        assert_eq!(messages[0].as_ref().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(messages[1].as_ref().unwrap(), &Some(b"{}".to_vec()));
+        assert!(matches!(messages[2], Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn cut_short() {
+        let mut input = &b"Content-Length: 10\r\n\r\n{}"[..];
+        let err = read_message(&mut input).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        let mut input = &b"Content-Length: 10\r\n"[..];
+        let err = read_message(&mut input).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
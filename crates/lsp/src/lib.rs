@@ -0,0 +1,5 @@
+mod client;
+mod codec;
+mod protocol;
+
+pub use client::{Clients, Connection, Event, Server};
@@ -0,0 +1,286 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use editor::{Diagnostic, Severity};
+use serde_json::{json, Value};
+
+/// The id the `initialize` request is sent with, the first of every session.
+pub const INITIALIZE_ID: i64 = 1;
+
+/// A message from the server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Incoming {
+    /// The answer to a request the client sent.
+    Response {
+        id: Value,
+        error: Option<String>,
+    },
+    /// A request the server sends the client, which must be answered.
+    Request {
+        id: Value,
+        method: String,
+    },
+    Notification {
+        method: String,
+        params: Value,
+    },
+}
+
+impl Incoming {
+    /// Parses a message's body, failing if it isn't a JSON-RPC message.
+    pub fn parse(body: &[u8]) -> Result<Self> {
+        let message: Value = serde_json::from_slice(body)?;
+        let method = message
+            .get("method")
+            .and_then(Value::as_str)
+            .map(String::from);
+        let id = message.get("id").cloned();
+        match (id, method) {
+            (Some(id), Some(method)) => Ok(Incoming::Request { id, method }),
+            (None, Some(method)) => {
+                let params = message.get("params").cloned().unwrap_or(Value::Null);
+                Ok(Incoming::Notification { method, params })
+            }
+            (Some(id), None) => {
+                let error = message.get("error").map(|error| {
+                    let message = error.get("message").and_then(Value::as_str);
+                    message.unwrap_or("unknown error").to_string()
+                });
+                Ok(Incoming::Response { id, error })
+            }
+            (None, None) => Err(anyhow!("neither a request, a response nor a notification")),
+        }
+    }
+}
+
+fn request(id: i64, method: &str, params: Value) -> Vec<u8> {
+    let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+    message.to_string().into_bytes()
+}
+
+fn notification(method: &str, params: Value) -> Vec<u8> {
+    let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+    message.to_string().into_bytes()
+}
+
+/// The answer to a request of the server's the client doesn't handle: an empty result, which is
+/// what the few a server without the client's capabilities sends expect.
+pub fn empty_response(id: &Value) -> Vec<u8> {
+    let message = json!({ "jsonrpc": "2.0", "id": id, "result": null });
+    message.to_string().into_bytes()
+}
+
+/// The `initialize` request, for a workspace at `root`.
+pub fn initialize(root: Option<&Path>) -> Vec<u8> {
+    let params = json!({
+        "processId": std::process::id(),
+        "rootUri": root.map(uri),
+        "capabilities": {
+            "textDocument": {
+                "synchronization": { "dynamicRegistration": false },
+                "publishDiagnostics": { "versionSupport": true },
+            },
+        },
+    });
+    request(INITIALIZE_ID, "initialize", params)
+}
+
+pub fn initialized() -> Vec<u8> {
+    notification("initialized", json!({}))
+}
+
+pub fn shutdown(id: i64) -> Vec<u8> {
+    request(id, "shutdown", Value::Null)
+}
+
+pub fn exit() -> Vec<u8> {
+    notification("exit", Value::Null)
+}
+
+pub fn did_open(path: &Path, language: &str, version: u64, text: &str) -> Vec<u8> {
+    let document = json!({
+        "uri": uri(path),
+        "languageId": language,
+        "version": version,
+        "text": text,
+    });
+    notification("textDocument/didOpen", json!({ "textDocument": document }))
+}
+
+/// A `didChange` with the whole text, as the server's told to expect.
+pub fn did_change(path: &Path, version: u64, text: &str) -> Vec<u8> {
+    let params = json!({
+        "textDocument": { "uri": uri(path), "version": version },
+        "contentChanges": [{ "text": text }],
+    });
+    notification("textDocument/didChange", params)
+}
+
+/// The file, the version of it if the server said, and the diagnostics of a
+/// `textDocument/publishDiagnostics` notification's `params`.
+pub fn published_diagnostics(params: &Value) -> Result<(PathBuf, Option<u64>, Vec<Diagnostic>)> {
+    let uri = params.get("uri").and_then(Value::as_str);
+    let uri = uri.ok_or_else(|| anyhow!("diagnostics without a uri"))?;
+    let path = path(uri).ok_or_else(|| anyhow!("diagnostics for {}, not a file", uri))?;
+    let version = params.get("version").and_then(Value::as_u64);
+    let diagnostics = params.get("diagnostics").and_then(Value::as_array);
+    let diagnostics = diagnostics.ok_or_else(|| anyhow!("no diagnostics for {}", uri))?;
+    let diagnostics = diagnostics.iter().map(diagnostic).collect::<Result<_>>()?;
+    Ok((path, version, diagnostics))
+}
+
+/// A diagnostic on the lines its range covers, leaving out a last line it ends at the start of.
+fn diagnostic(diagnostic: &Value) -> Result<Diagnostic> {
+    let position = |end: &str, field: &str| {
+        let value = diagnostic.pointer(&format!("/range/{}/{}", end, field));
+        value.and_then(Value::as_u64).map(|n| n as usize)
+    };
+    let start = position("start", "line").ok_or_else(|| anyhow!("diagnostic without a range"))?;
+    let end = position("end", "line").unwrap_or(start);
+    let end = if end > start && position("end", "character") == Some(0) {
+        end
+    } else {
+        end + 1
+    };
+    let severity = match diagnostic.get("severity").and_then(Value::as_u64) {
+        Some(2) => Severity::Warning,
+        Some(3) => Severity::Info,
+        Some(4) => Severity::Hint,
+        _ => Severity::Error,
+    };
+    let message = diagnostic
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    Ok(Diagnostic { lines: start..end, severity, message: message.to_string() })
+}
+
+/// The `file:` URI of an absolute path, with what a URI can't hold percent-encoded.
+pub fn uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// The path a `file:` URI names, or None for any other URI.
+pub fn path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let hex = encoded
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.filter(|_| encoded[i] == b'%') {
+            Some(hex) => {
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uris() {
+        let path = Path::new("/home/a b/ü.rs");
+        assert_eq!(uri(path), "file:///home/a%20b/%C3%BC.rs");
+        assert_eq!(self::path(&uri(path)).as_deref(), Some(path));
+        assert_eq!(self::path("untitled:1"), None);
+    }
+
+    #[test]
+    fn incoming() {
+        let parse = |body: &str| Incoming::parse(body.as_bytes());
+        assert_eq!(
+            parse(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#).unwrap(),
+            Incoming::Response { id: json!(1), error: None }
+        );
+        assert_eq!(
+            parse(r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32600,"message":"no"}}"#).unwrap(),
+            Incoming::Response { id: json!(1), error: Some("no".into()) }
+        );
+        assert_eq!(
+            parse(r#"{"jsonrpc":"2.0","id":"a","method":"window/workDoneProgress/create"}"#)
+                .unwrap(),
+            Incoming::Request { id: json!("a"), method: "window/workDoneProgress/create".into() }
+        );
+        assert_eq!(
+            parse(r#"{"jsonrpc":"2.0","method":"$/progress","params":[1]}"#).unwrap(),
+            Incoming::Notification { method: "$/progress".into(), params: json!([1]) }
+        );
+        assert!(parse(r#"{"jsonrpc":"2.0"}"#).is_err());
+        assert!(parse("Content-Length").is_err());
+    }
+
+    #[test]
+    fn diagnostics() {
+        let params = json!({
+            "uri": "file:///src/main.rs",
+            "version": 3,
+            "diagnostics": [
+                {
+                    "range": { "start": { "line": 1, "character": 4 }, "end": { "line": 1, "character": 9 } },
+                    "severity": 2,
+                    "message": "unused",
+                },
+                {
+                    "range": { "start": { "line": 2, "character": 0 }, "end": { "line": 5, "character": 0 } },
+                    "message": "mismatched types",
+                },
+            ],
+        });
+        let (path, version, diagnostics) = published_diagnostics(&params).unwrap();
+        assert_eq!(path, Path::new("/src/main.rs"));
+        assert_eq!(version, Some(3));
+        assert_eq!(
+            diagnostics,
+            [
+                Diagnostic { lines: 1..2, severity: Severity::Warning, message: "unused".into() },
+                // ending at the start of line 5 leaves it out
+                Diagnostic {
+                    lines: 2..5,
+                    severity: Severity::Error,
+                    message: "mismatched types".into()
+                },
+            ]
+        );
+
+        let unversioned = json!({ "uri": "file:///a.rs", "diagnostics": [] });
+        assert_eq!(published_diagnostics(&unversioned).unwrap().1, None);
+        assert!(published_diagnostics(&json!({ "uri": "file:///a.rs" })).is_err());
+        assert!(published_diagnostics(&json!({ "uri": "https://a", "diagnostics": [] })).is_err());
+    }
+
+    #[test]
+    fn document_sync() {
+        let open: Value =
+            serde_json::from_slice(&did_open(Path::new("/a.rs"), "rust", 0, "x")).unwrap();
+        assert_eq!(open["method"], "textDocument/didOpen");
+        assert_eq!(
+            open["params"]["textDocument"],
+            json!({ "uri": "file:///a.rs", "languageId": "rust", "version": 0, "text": "x" })
+        );
+
+        let change: Value =
+            serde_json::from_slice(&did_change(Path::new("/a.rs"), 2, "xy")).unwrap();
+        assert_eq!(change["method"], "textDocument/didChange");
+        assert_eq!(change["params"]["textDocument"]["version"], 2);
+        assert_eq!(change["params"]["contentChanges"], json!([{ "text": "xy" }]));
+    }
+}
@@ -0,0 +1,98 @@
+#![feature(test)]
+extern crate test;
+
+use rope::{Rope, Slab, SlabAllocator};
+use test::Bencher;
+
+// roughly what loading a 1MB log file in small reads looks like
+const READS: usize = 16 * 1024;
+const READ: &[u8] = b"2024-05-28T12:00:00Z INFO request handled in 12ms\n";
+
+fn load(mut append: impl FnMut(Rope, &mut SlabAllocator, &[u8]) -> Rope) -> Rope {
+    let mut buffer = SlabAllocator::new();
+    (0..READS).fold(Rope::empty(), |rope, _| append(rope, &mut buffer, READ))
+}
+
+fn write_all(buffer: &mut SlabAllocator, mut bytes: &[u8]) -> Vec<Slab> {
+    let mut slabs = vec![];
+    while !bytes.is_empty() {
+        let (slab, written) = buffer.append(bytes).unwrap();
+        bytes = &bytes[written..];
+        slabs.push(slab);
+    }
+    slabs
+}
+
+fn report(name: &str, rope: &Rope) {
+    eprintln!("{}: {} bytes in {} leaves", name, rope.len(), rope.chunks(.., 0).count());
+}
+
+#[bench]
+fn insert_at_end(b: &mut Bencher) {
+    let insert = |rope: Rope, buffer: &mut SlabAllocator, bytes: &[u8]| {
+        write_all(buffer, bytes)
+            .into_iter()
+            .fold(rope, |rope, slab| {
+                let len = rope.len();
+                rope.insert(len, slab).unwrap()
+            })
+    };
+    report("insert_at_end", &load(insert));
+    b.iter(|| load(insert));
+}
+
+#[bench]
+fn append(b: &mut Bencher) {
+    let append = |rope: Rope, buffer: &mut SlabAllocator, bytes: &[u8]| {
+        write_all(buffer, bytes)
+            .into_iter()
+            .fold(rope, |rope, slab| rope.append(slab).unwrap())
+    };
+    report("append", &load(append));
+    b.iter(|| load(append));
+}
+
+#[bench]
+fn append_many(b: &mut Bencher) {
+    let load = || {
+        let mut buffer = SlabAllocator::new();
+        let slabs: Vec<_> = (0..READS)
+            .flat_map(|_| write_all(&mut buffer, READ))
+            .collect();
+        Rope::empty().append_many(slabs).unwrap()
+    };
+    report("append_many", &load());
+    b.iter(load);
+}
+
+// loading a 100MB file, in blocks of one size for everything against big blocks for bulk reads
+const FILE_LEN: usize = 100 * 1024 * 1024;
+
+fn big_file() -> Vec<u8> {
+    READ.iter().copied().cycle().take(FILE_LEN).collect()
+}
+
+fn load_in(mut buffer: SlabAllocator, file: &[u8], read: usize) -> Rope {
+    let slabs: Vec<_> = file
+        .chunks(read)
+        .flat_map(|bytes| write_all(&mut buffer, bytes))
+        .collect();
+    Rope::empty().append_many(slabs).unwrap()
+}
+
+#[bench]
+fn load_big_file_fixed_blocks(b: &mut Bencher) {
+    let file = big_file();
+    // reads just short of a block, so none of them counts as bulk
+    let load = || load_in(SlabAllocator::with_capacity(4096), &file, 4000);
+    report("load_big_file_fixed_blocks", &load());
+    b.iter(load);
+}
+
+#[bench]
+fn load_big_file_bulk_blocks(b: &mut Bencher) {
+    let file = big_file();
+    let load = || load_in(SlabAllocator::new(), &file, 64 * 1024);
+    report("load_big_file_bulk_blocks", &load());
+    b.iter(load);
+}
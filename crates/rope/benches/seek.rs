@@ -0,0 +1,66 @@
+#![feature(test)]
+extern crate test;
+
+use rope::{Rope, Slab, SlabAllocator};
+use test::Bencher;
+
+// a 16MB log in 1KB slabs, as edits allocate them; run with `--features wide` to compare
+const LINES: usize = 320 * 1024;
+const LINE: &[u8] = b"2024-05-28T12:00:00Z INFO request handled in 12ms\n";
+const SEEKS: usize = 1000;
+
+fn open() -> Rope {
+    rope(&LINE.repeat(LINES))
+}
+
+fn rope(contents: &[u8]) -> Rope {
+    let mut buffer = SlabAllocator::new();
+    let mut bytes = contents;
+    let mut slabs: Vec<Slab> = vec![];
+    while !bytes.is_empty() {
+        let (slab, written) = buffer.append(bytes).unwrap();
+        bytes = &bytes[written..];
+        slabs.push(slab);
+    }
+    let rope = Rope::empty().append_many(slabs).unwrap();
+    let leaves = rope.chunks(.., 0).count();
+    eprintln!("seek: {} bytes in {} leaves, {} deep", rope.len(), leaves, rope.depth());
+    rope
+}
+
+/// Offsets spread over the whole rope, in no order, so no seek starts where the last ended.
+fn targets(len: usize) -> impl Iterator<Item = usize> {
+    (0..SEEKS).map(move |i| i * 7919 % SEEKS * (len / SEEKS))
+}
+
+#[bench]
+fn offset_to_point(b: &mut Bencher) {
+    let rope = open();
+    b.iter(|| {
+        for offset in targets(rope.len()) {
+            test::black_box(rope.offset_to_point(offset));
+        }
+    });
+}
+
+/// Points on one line as long as a minified file, where no line break cuts short the scan of the
+/// leaf for the column.
+#[bench]
+fn offset_to_point_long_line(b: &mut Bencher) {
+    let rope = rope(&b"var a=1;".repeat(5 << 17));
+    b.iter(|| {
+        for offset in targets(rope.len()) {
+            test::black_box(rope.offset_to_point(offset));
+        }
+    });
+}
+
+#[bench]
+fn line(b: &mut Bencher) {
+    let rope = open();
+    b.iter(|| {
+        for line in targets(rope.len_lines()) {
+            test::black_box(rope.line(line));
+        }
+    });
+}
@@ -0,0 +1,41 @@
+#![feature(test)]
+extern crate test;
+
+use rope::{Rope, Slab, SlabAllocator};
+use test::Bencher;
+
+const KEYSTROKES: usize = 10_000;
+const LINE: &[u8] = b"2024-05-28T12:00:00Z INFO request handled in 12ms\n";
+
+/// A 50KB file, loaded the way the editor reads one.
+fn open() -> Rope {
+    let mut buffer = SlabAllocator::new();
+    let contents = LINE.repeat(1024);
+    let mut bytes = &contents[..];
+    let mut slabs: Vec<Slab> = vec![];
+    while !bytes.is_empty() {
+        let (slab, written) = buffer.append(bytes).unwrap();
+        bytes = &bytes[written..];
+        slabs.push(slab);
+    }
+    Rope::empty().append_many(slabs).unwrap()
+}
+
+/// Types one char at a time in the middle of the file, each allocated as it's typed.
+fn type_in_middle(rope: Rope) -> Rope {
+    let mut keys = SlabAllocator::new();
+    let start = rope.len() / 2;
+    (0..KEYSTROKES).fold(rope, |rope, i| {
+        let (slab, _) = keys.append(b"x").unwrap();
+        rope.insert(start + i, slab).unwrap()
+    })
+}
+
+#[bench]
+fn typing(b: &mut Bencher) {
+    let rope = open();
+    let before = rope.chunks(.., 0).count();
+    let typed = type_in_middle(rope.clone());
+    eprintln!("typing: {} leaves before, {} after", before, typed.chunks(.., 0).count());
+    b.iter(|| type_in_middle(rope.clone()));
+}
@@ -0,0 +1,231 @@
+//! Applies a sequence of operations decoded from the fuzzer's bytes to a `Rope` and to a plain
+//! `String`, checking after every step that the two agree.
+//!
+//! Every operand is folded into bounds of the model as it is decoded, so any input is a valid
+//! sequence of operations and the fuzzer spends its time on deep edit histories rather than
+//! rejected ones. Offsets are also snapped down to char boundaries, since the text holds
+//! multi-byte chars.
+//!
+//! Run it from `crates/rope`, with the rope crate building as a workspace member, using a nightly
+//! toolchain and `cargo install cargo-fuzz`:
+//!
+//!     cargo fuzz run rope_ops -- -max_total_time=60
+//!
+//! `corpus/rope_ops` is seeded with the inserts of the `basic_tests` rope test, so runs start
+//! from a tree several levels deep. A failing input is saved under `artifacts/rope_ops/` and can
+//! be replayed with `cargo fuzz run rope_ops <file>`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rope::{Rope, Slab, SlabAllocator};
+use std::ops::Range;
+use tore::Point;
+
+/// What inserted text is made of: enough to exercise line breaks, CRLFs, and multi-byte chars.
+const ALPHABET: [char; 9] = ['a', 'b', ' ', '\t', '\n', '\r', 'é', '日', '🦀'];
+const MAX_TEXT: usize = 24;
+
+#[derive(Debug)]
+enum Op {
+    Insert(usize, String),
+    Delete(Range<usize>),
+    Replace(Range<usize>, String),
+    /// Splits the rope and keeps the left or the right half.
+    Split(usize, bool),
+    OffsetToPoint(usize),
+    PointToOffset(Point),
+    Line(usize),
+    Chunks(Range<usize>),
+}
+
+struct Input<'a>(&'a [u8]);
+
+impl<'a> Input<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(byte)
+    }
+
+    fn u16(&mut self) -> Option<usize> {
+        Some(u16::from_le_bytes([self.byte()?, self.byte()?]).into())
+    }
+
+    /// An offset within `text`, end included, on a char boundary.
+    fn offset(&mut self, text: &str) -> Option<usize> {
+        let mut offset = self.u16()? % (text.len() + 1);
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        Some(offset)
+    }
+
+    fn range(&mut self, text: &str) -> Option<Range<usize>> {
+        let (a, b) = (self.offset(text)?, self.offset(text)?);
+        Some(a.min(b)..a.max(b))
+    }
+
+    fn text(&mut self) -> Option<String> {
+        let len = usize::from(self.byte()?) % MAX_TEXT + 1;
+        (0..len)
+            .map(|_| Some(ALPHABET[usize::from(self.byte()?) % ALPHABET.len()]))
+            .collect()
+    }
+
+    fn op(&mut self, model: &Model) -> Option<Op> {
+        let text = &model.text;
+        let op = match self.byte()? % 10 {
+            // inserting is weighted up so ropes grow past a single leaf
+            0..=2 => Op::Insert(self.offset(text)?, self.text()?),
+            3 => Op::Delete(self.range(text)?),
+            4 => Op::Replace(self.range(text)?, self.text()?),
+            5 => Op::Split(self.offset(text)?, self.byte()? % 2 == 0),
+            6 => Op::OffsetToPoint(self.offset(text)?),
+            7 => {
+                // reaching a line and a column past the end checks what's out of bounds too
+                let line = usize::from(self.byte()?) % (model.len_lines() + 2);
+                let column = usize::from(self.byte()?) % (MAX_TEXT * 4);
+                Op::PointToOffset(Point { line, column })
+            }
+            8 => Op::Line(usize::from(self.byte()?) % (model.len_lines() + 1)),
+            _ => Op::Chunks(self.range(text)?),
+        };
+        Some(op)
+    }
+}
+
+/// The reference the rope is checked against, with the rope's conventions for lines: a line is
+/// everything up to and including a `\n`, and text after the last `\n` isn't counted as a line.
+struct Model {
+    text: String,
+}
+
+impl Model {
+    fn len_lines(&self) -> usize {
+        self.text.matches('\n').count()
+    }
+
+    /// Byte ranges of each line, terminator included.
+    fn lines(&self) -> Vec<Range<usize>> {
+        let mut start = 0;
+        let mut lines = vec![];
+        for (idx, _) in self.text.match_indices('\n') {
+            lines.push(start..idx + 1);
+            start = idx + 1;
+        }
+        lines
+    }
+
+    fn offset_to_point(&self, offset: usize) -> Option<Point> {
+        if offset > self.text.len() {
+            return None;
+        }
+        let before = &self.text[..offset];
+        let line = before.matches('\n').count();
+        let column = offset - before.rfind('\n').map_or(0, |idx| idx + 1);
+        Some(Point { line, column })
+    }
+
+    fn point_to_offset(&self, point: Point) -> Option<usize> {
+        let line = self.lines().into_iter().nth(point.line)?;
+        (point.column < line.len()).then_some(line.start + point.column)
+    }
+
+    /// A line without its terminator.
+    fn line(&self, line: usize) -> Option<&str> {
+        let range = self.lines().into_iter().nth(line)?;
+        let line = &self.text[range];
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        Some(line.strip_suffix('\r').unwrap_or(line))
+    }
+}
+
+fn slabs(allocator: &mut SlabAllocator, mut bytes: &[u8]) -> Vec<Slab> {
+    let mut slabs = vec![];
+    while !bytes.is_empty() {
+        let (slab, written) = allocator.append(bytes).unwrap();
+        bytes = &bytes[written..];
+        slabs.push(slab);
+    }
+    slabs
+}
+
+fn insert(rope: Rope, allocator: &mut SlabAllocator, at: usize, text: &str) -> Rope {
+    let mut at = at;
+    slabs(allocator, text.as_bytes())
+        .into_iter()
+        .fold(rope, |rope, slab| {
+            let len = slab.len();
+            let rope = rope.insert(at, slab).unwrap();
+            at += len;
+            rope
+        })
+}
+
+fn concat<'a>(chunks: impl Iterator<Item = &'a [u8]>) -> Vec<u8> {
+    chunks.flatten().copied().collect()
+}
+
+fn check(rope: &Rope, model: &Model, op: &Op) {
+    assert_eq!(concat(rope.chunks(.., 0)), model.text.as_bytes(), "after {:?}", op);
+    assert_eq!(rope.len(), model.text.len(), "after {:?}", op);
+    assert_eq!(rope.len_lines(), model.len_lines(), "after {:?}", op);
+    assert!(rope.is_balanced(), "unbalanced after {:?}", op);
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut input = Input(data);
+    let mut allocator = SlabAllocator::new();
+    let mut rope = Rope::empty();
+    let mut model = Model { text: String::new() };
+
+    while let Some(op) = input.op(&model) {
+        match &op {
+            Op::Insert(at, text) => {
+                rope = insert(rope, &mut allocator, *at, text);
+                model.text.insert_str(*at, text);
+            }
+            Op::Delete(range) => {
+                let (updated, deleted, _) = rope.delete(range.clone()).unwrap();
+                let expected: String = model.text.drain(range.clone()).collect();
+                assert_eq!(concat(deleted.chunks(.., 0)), expected.as_bytes());
+                rope = updated;
+            }
+            Op::Replace(range, text) => {
+                let (updated, _, _) = rope.delete(range.clone()).unwrap();
+                rope = insert(updated, &mut allocator, range.start, text);
+                model.text.replace_range(range.clone(), text);
+            }
+            Op::Split(at, keep_left) => {
+                let (left, right) = rope.split(*at).unwrap();
+                assert_eq!(concat(left.chunks(.., 0)), model.text[..*at].as_bytes());
+                assert_eq!(concat(right.chunks(.., 0)), model.text[*at..].as_bytes());
+                (rope, model.text) = if keep_left {
+                    (left, model.text[..*at].to_string())
+                } else {
+                    (right, model.text[*at..].to_string())
+                };
+            }
+            Op::OffsetToPoint(offset) => {
+                let point = rope.offset_to_point(*offset);
+                assert_eq!(point, model.offset_to_point(*offset), "{:?}", op);
+                if let Some(point) = point.filter(|point| point.line < model.len_lines()) {
+                    assert_eq!(rope.point_to_offset(point), Some(*offset), "{:?}", op);
+                }
+            }
+            Op::PointToOffset(point) => {
+                assert_eq!(rope.point_to_offset(*point), model.point_to_offset(*point), "{:?}", op);
+            }
+            Op::Line(line) => {
+                let actual = rope.line(*line).map(|slice| concat(slice.chunks(0)));
+                let expected = model.line(*line).map(|line| line.as_bytes().to_vec());
+                assert_eq!(actual, expected, "{:?}", op);
+            }
+            Op::Chunks(range) => {
+                let expected = &model.text.as_bytes()[range.clone()];
+                assert_eq!(concat(rope.chunks(range.clone(), 0)), expected, "{:?}", op);
+            }
+        }
+        check(&rope, &model, &op);
+    }
+});
@@ -0,0 +1,845 @@
+//! An editable rope indexed by chars and lines the way ropey's is, over the persistent
+//! [`crate::Rope`] underneath, for code written against ropey's API. Edits go through a slab
+//! allocator the rope keeps, and every slab holds whole chars, so chunks come out as `&str`.
+//!
+//! Lines end at `\n`, a CRLF taking its `\r` along; unlike ropey, a lone `\r` or a Unicode line
+//! separator doesn't end one. As with ropey, the text after the last line break is a line of its
+//! own, even when it's empty, and indices past the end panic.
+
+use std::hash::{Hash, Hasher};
+use std::ops::{Bound, Range, RangeBounds};
+
+use crate::error::Error;
+use crate::slab::{Slab, SlabAllocator, BULK_BLOCK_CAPACITY};
+use crate::util::counted;
+
+pub mod iter {
+    pub use super::{Bytes, Chars, Chunks, Lines};
+}
+
+/// A char-indexed rope. Clones share their text, and each writes its own edits.
+pub struct Rope {
+    rope: crate::Rope,
+    /// Made on the first edit, so ropes that are only read, like clones kept for undo, don't
+    /// hold on to a block each.
+    allocator: Option<SlabAllocator>,
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Self { rope: crate::Rope::empty(), allocator: None }
+    }
+}
+
+impl Clone for Rope {
+    fn clone(&self) -> Self {
+        Self { rope: self.rope.clone(), allocator: None }
+    }
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(text: &str) -> Self {
+        let mut builder = RopeBuilder::new();
+        builder.append(text);
+        builder.finish()
+    }
+
+    /// The persistent rope the text is kept in.
+    pub fn tree(&self) -> &crate::Rope {
+        &self.rope
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.rope.len()
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    /// The number of lines, counting the one after the last line break.
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines() + 1
+    }
+
+    pub fn insert(&mut self, char_idx: usize, text: &str) {
+        let mut offset = self.char_to_byte(char_idx);
+        let allocator = self.allocator.get_or_insert_with(SlabAllocator::new);
+        let mut text = text;
+        while !text.is_empty() {
+            let (slab, written) = allocator.append_str(text);
+            text = &text[written..];
+            offset += slab.len();
+            let at = offset - slab.len();
+            self.rope = self.rope.insert(at, slab).expect("insert within the rope");
+        }
+    }
+
+    pub fn insert_char(&mut self, char_idx: usize, c: char) {
+        self.insert(char_idx, c.encode_utf8(&mut [0; 4]));
+    }
+
+    pub fn remove(&mut self, char_range: impl RangeBounds<usize>) {
+        let range = self.byte_range(char_range);
+        let (rope, ..) = self.rope.delete(range).expect("remove within the rope");
+        self.rope = rope;
+    }
+
+    pub fn append(&mut self, other: Self) {
+        self.rope = self.rope.clone().concat(other.rope);
+    }
+
+    /// Splits the rope at `char_idx`, keeping the text before it and returning the rest.
+    pub fn split_off(&mut self, char_idx: usize) -> Self {
+        let at = self.char_to_byte(char_idx);
+        let (left, right) = self.rope.split(at).expect("split within the rope");
+        self.rope = left;
+        Self { rope: right, allocator: None }
+    }
+
+    #[track_caller]
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        self.rope
+            .offset_to_char(byte_idx)
+            .unwrap_or_else(|| out_of_bounds("byte", byte_idx, self.len_bytes()))
+    }
+
+    #[track_caller]
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        self.rope
+            .char_to_offset(char_idx)
+            .unwrap_or_else(|| out_of_bounds("char", char_idx, self.len_chars()))
+    }
+
+    /// The line byte `byte_idx` is on; the end of the rope is on the last line.
+    #[track_caller]
+    pub fn byte_to_line(&self, byte_idx: usize) -> usize {
+        line_of(&self.rope, byte_idx)
+    }
+
+    #[track_caller]
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        self.byte_to_line(self.char_to_byte(char_idx))
+    }
+
+    /// The byte `line_idx` starts at; the line past the last starts at the end of the rope.
+    #[track_caller]
+    pub fn line_to_byte(&self, line_idx: usize) -> usize {
+        line_start(&self.rope, line_idx)
+            .unwrap_or_else(|| out_of_bounds("line", line_idx, self.len_lines()))
+    }
+
+    #[track_caller]
+    pub fn line_to_char(&self, line_idx: usize) -> usize {
+        self.byte_to_char(self.line_to_byte(line_idx))
+    }
+
+    pub fn try_line_to_char(&self, line_idx: usize) -> Result<usize, Error> {
+        match line_start(&self.rope, line_idx) {
+            Some(byte) => Ok(self.byte_to_char(byte)),
+            None => {
+                let len = self.len_lines();
+                Err(Error::IndexOutOfBounds { op: "line_to_char", offset: line_idx, len })
+            }
+        }
+    }
+
+    #[track_caller]
+    pub fn char(&self, char_idx: usize) -> char {
+        self.get_char(char_idx)
+            .unwrap_or_else(|| out_of_bounds("char", char_idx, self.len_chars()))
+    }
+
+    pub fn get_char(&self, char_idx: usize) -> Option<char> {
+        let byte = self.rope.char_to_offset(char_idx)?;
+        Chars::new(&self.rope, byte..self.len_bytes(), byte).next()
+    }
+
+    /// The whole chunk byte `byte_idx` is in, and the byte, char and line it starts at.
+    #[track_caller]
+    pub fn chunk_at_byte(&self, byte_idx: usize) -> (&str, usize, usize, usize) {
+        self.get_chunk_at_byte(byte_idx)
+            .unwrap_or_else(|| out_of_bounds("byte", byte_idx, self.len_bytes()))
+    }
+
+    /// Like `chunk_at_byte`, or None at or past the end of the rope.
+    pub fn get_chunk_at_byte(&self, byte_idx: usize) -> Option<(&str, usize, usize, usize)> {
+        let (chunk, start) = self.rope.chunk_at(byte_idx)?;
+        Some((as_str(chunk), start, self.byte_to_char(start), self.byte_to_line(start)))
+    }
+
+    pub fn slice(&self, char_range: impl RangeBounds<usize>) -> RopeSlice<'_> {
+        self.full().slice(char_range)
+    }
+
+    pub fn byte_slice(&self, byte_range: impl RangeBounds<usize>) -> RopeSlice<'_> {
+        self.full().byte_slice(byte_range)
+    }
+
+    /// The text of `line_idx`, with its line break.
+    #[track_caller]
+    pub fn line(&self, line_idx: usize) -> RopeSlice<'_> {
+        self.full().line(line_idx)
+    }
+
+    pub fn lines(&self) -> Lines<'_> {
+        self.full().lines()
+    }
+
+    pub fn lines_at(&self, line_idx: usize) -> Lines<'_> {
+        Lines { slice: self.full(), line: line_idx, end: self.len_lines() }
+    }
+
+    pub fn chars(&self) -> Chars<'_> {
+        self.full().chars()
+    }
+
+    pub fn chars_at(&self, char_idx: usize) -> Chars<'_> {
+        self.full().chars_at(char_idx)
+    }
+
+    pub fn bytes(&self) -> Bytes<'_> {
+        self.full().bytes()
+    }
+
+    pub fn bytes_at(&self, byte_idx: usize) -> Bytes<'_> {
+        self.full().bytes_at(byte_idx)
+    }
+
+    pub fn chunks(&self) -> Chunks<'_> {
+        self.full().chunks()
+    }
+
+    pub fn write_to(&self, mut writer: impl std::io::Write) -> std::io::Result<()> {
+        for chunk in self.chunks() {
+            writer.write_all(chunk.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn full(&self) -> RopeSlice<'_> {
+        RopeSlice { rope: &self.rope, start: 0, end: self.rope.len() }
+    }
+
+    #[track_caller]
+    fn byte_range(&self, char_range: impl RangeBounds<usize>) -> Range<usize> {
+        let (start, end) = char_bounds(char_range, self.len_chars());
+        self.char_to_byte(start)..self.char_to_byte(end)
+    }
+}
+
+/// Builds a rope out of text that comes a piece at a time, like a file as it's read, into
+/// blocks of the bulk size.
+pub struct RopeBuilder {
+    allocator: SlabAllocator,
+    slabs: Vec<Slab>,
+}
+
+impl Default for RopeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RopeBuilder {
+    pub fn new() -> Self {
+        Self { allocator: SlabAllocator::with_capacity(BULK_BLOCK_CAPACITY), slabs: vec![] }
+    }
+
+    pub fn append(&mut self, mut text: &str) {
+        while !text.is_empty() {
+            let (slab, written) = self.allocator.append_str(text);
+            text = &text[written..];
+            self.slabs.push(slab);
+        }
+    }
+
+    pub fn finish(self) -> Rope {
+        let rope = crate::Rope::empty()
+            .append_many(self.slabs)
+            .expect("appending never fails");
+        Rope { rope, allocator: None }
+    }
+}
+
+/// Part of a rope, indexed from its own start.
+#[derive(Clone, Copy)]
+pub struct RopeSlice<'a> {
+    rope: &'a crate::Rope,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> RopeSlice<'a> {
+    pub fn len_bytes(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.char_at(self.end) - self.char_at(self.start)
+    }
+
+    pub fn len_lines(&self) -> usize {
+        line_of(self.rope, self.end) - line_of(self.rope, self.start) + 1
+    }
+
+    #[track_caller]
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        if byte_idx > self.len_bytes() {
+            out_of_bounds("byte", byte_idx, self.len_bytes());
+        }
+        self.char_at(self.start + byte_idx) - self.char_at(self.start)
+    }
+
+    #[track_caller]
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        self.byte_of(char_idx) - self.start
+    }
+
+    #[track_caller]
+    pub fn char(&self, char_idx: usize) -> char {
+        self.get_char(char_idx)
+            .unwrap_or_else(|| out_of_bounds("char", char_idx, self.len_chars()))
+    }
+
+    pub fn get_char(&self, char_idx: usize) -> Option<char> {
+        let byte = self
+            .rope
+            .char_to_offset(self.char_at(self.start) + char_idx)?;
+        if byte >= self.end {
+            return None;
+        }
+        Chars::new(self.rope, byte..self.end, byte).next()
+    }
+
+    pub fn slice(&self, char_range: impl RangeBounds<usize>) -> RopeSlice<'a> {
+        let (start, end) = char_bounds(char_range, self.len_chars());
+        let (start, end) = (self.byte_of(start), self.byte_of(end));
+        RopeSlice { rope: self.rope, start, end }
+    }
+
+    #[track_caller]
+    pub fn byte_slice(&self, byte_range: impl RangeBounds<usize>) -> RopeSlice<'a> {
+        let (start, end) = char_bounds(byte_range, self.len_bytes());
+        RopeSlice { rope: self.rope, start: self.start + start, end: self.start + end }
+    }
+
+    /// The byte `line_idx` of the slice starts at; the line past the last starts at the end.
+    #[track_caller]
+    pub fn line_to_byte(&self, line_idx: usize) -> usize {
+        if line_idx > self.len_lines() {
+            out_of_bounds("line", line_idx, self.len_lines());
+        }
+        let first = line_of(self.rope, self.start);
+        let start = line_start(self.rope, first + line_idx).unwrap_or(self.end);
+        start.clamp(self.start, self.end) - self.start
+    }
+
+    /// The text of `line_idx` of the slice, with its line break.
+    #[track_caller]
+    pub fn line(&self, line_idx: usize) -> RopeSlice<'a> {
+        let first = line_of(self.rope, self.start);
+        let start = line_start(self.rope, first + line_idx)
+            .filter(|_| line_idx < self.len_lines())
+            .unwrap_or_else(|| out_of_bounds("line", line_idx, self.len_lines()));
+        let end = line_start(self.rope, first + line_idx + 1).unwrap_or(self.end);
+        RopeSlice { rope: self.rope, start: start.max(self.start), end: end.min(self.end) }
+    }
+
+    pub fn lines(&self) -> Lines<'a> {
+        Lines { slice: *self, line: 0, end: self.len_lines() }
+    }
+
+    pub fn chars(&self) -> Chars<'a> {
+        Chars::new(self.rope, self.start..self.end, self.start)
+    }
+
+    #[track_caller]
+    pub fn chars_at(&self, char_idx: usize) -> Chars<'a> {
+        Chars::new(self.rope, self.start..self.end, self.byte_of(char_idx))
+    }
+
+    pub fn bytes(&self) -> Bytes<'a> {
+        Bytes(Cursor::new(self.rope, self.start..self.end, self.start))
+    }
+
+    #[track_caller]
+    pub fn bytes_at(&self, byte_idx: usize) -> Bytes<'a> {
+        if byte_idx > self.len_bytes() {
+            out_of_bounds("byte", byte_idx, self.len_bytes());
+        }
+        Bytes(Cursor::new(self.rope, self.start..self.end, self.start + byte_idx))
+    }
+
+    pub fn chunks(&self) -> Chunks<'a> {
+        Chunks(self.rope.chunks(self.start..self.end, 0))
+    }
+
+    /// The chars before byte `byte` of the rope.
+    fn char_at(&self, byte: usize) -> usize {
+        self.rope
+            .offset_to_char(byte)
+            .expect("slice within the rope")
+    }
+
+    /// The byte of the rope the slice's char `char_idx` starts at.
+    #[track_caller]
+    fn byte_of(&self, char_idx: usize) -> usize {
+        let len = self.len_chars();
+        if char_idx > len {
+            out_of_bounds("char", char_idx, len);
+        }
+        let chars = self.char_at(self.start) + char_idx;
+        self.rope
+            .char_to_offset(chars)
+            .expect("slice within the rope")
+    }
+}
+
+/// The byte each line starts at, up to the line past the last, which starts at the end.
+fn line_start(rope: &crate::Rope, line: usize) -> Option<usize> {
+    match line {
+        0 => Some(0),
+        n if n <= rope.len_lines() => rope.line(n - 1).map(|line| line.range.end),
+        n if n == rope.len_lines() + 1 => Some(rope.len()),
+        _ => None,
+    }
+}
+
+/// The line breaks before `byte`.
+#[track_caller]
+fn line_of(rope: &crate::Rope, byte: usize) -> usize {
+    if byte >= rope.len() {
+        if byte > rope.len() {
+            out_of_bounds("byte", byte, rope.len());
+        }
+        return rope.len_lines();
+    }
+    rope.offset_to_point(byte)
+        .expect("offset within the rope")
+        .line
+}
+
+#[track_caller]
+fn char_bounds(range: impl RangeBounds<usize>, len: usize) -> (usize, usize) {
+    let start = match range.start_bound() {
+        Bound::Included(&n) => n,
+        Bound::Excluded(&n) => n + 1,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&n) => n + 1,
+        Bound::Excluded(&n) => n,
+        Bound::Unbounded => len,
+    };
+    if start > end || end > len {
+        panic!("range {}..{} out of bounds for length {}", start, end, len);
+    }
+    (start, end)
+}
+
+#[track_caller]
+fn out_of_bounds(what: &str, index: usize, len: usize) -> ! {
+    panic!("{} index {} out of bounds for length {}", what, index, len)
+}
+
+/// Slabs written through the allocator only ever end on a char boundary, so only a range cut
+/// inside a char makes a chunk that isn't text.
+#[track_caller]
+fn as_str(chunk: &[u8]) -> &str {
+    std::str::from_utf8(chunk).expect("chunk cut inside a char")
+}
+
+/// A position in a range of a rope that can step either way, reading a slab at a time.
+#[derive(Clone)]
+struct Cursor<'a> {
+    rope: &'a crate::Rope,
+    range: Range<usize>,
+    offset: usize,
+    chunk: &'a [u8],
+    chunk_start: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(rope: &'a crate::Rope, range: Range<usize>, offset: usize) -> Self {
+        Self { rope, range, offset, chunk: &[], chunk_start: 0 }
+    }
+
+    /// The chunk with byte `offset` in it, loaded unless it already is.
+    fn load(&mut self, offset: usize) -> &'a [u8] {
+        let loaded = self.chunk_start..self.chunk_start + self.chunk.len();
+        if !loaded.contains(&offset) {
+            counted!(leaves);
+            let (chunk, start) = self.rope.chunk_at(offset).expect("offset within the rope");
+            (self.chunk, self.chunk_start) = (chunk, start);
+        }
+        &self.chunk[offset - self.chunk_start..]
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        counted!(steps);
+        if self.offset >= self.range.end {
+            return None;
+        }
+        let b = self.load(self.offset)[0];
+        self.offset += 1;
+        Some(b)
+    }
+
+    fn prev_byte(&mut self) -> Option<u8> {
+        counted!(steps);
+        if self.offset <= self.range.start {
+            return None;
+        }
+        self.offset -= 1;
+        Some(self.load(self.offset)[0])
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        counted!(steps);
+        if self.offset >= self.range.end {
+            return None;
+        }
+        let (c, len) = bstr::decode_utf8(self.load(self.offset));
+        self.offset += len.max(1);
+        Some(c.unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn prev_char(&mut self) -> Option<char> {
+        counted!(steps);
+        if self.offset <= self.range.start {
+            return None;
+        }
+        let offset = self.offset - 1;
+        self.load(offset);
+        let (c, len) = bstr::decode_last_utf8(&self.chunk[..self.offset - self.chunk_start]);
+        self.offset -= len.max(1);
+        Some(c.unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+}
+
+/// The chars of a rope from a position, forwards or, reversed, backwards; `prev` steps back
+/// over the char `next` last returned.
+#[derive(Clone)]
+pub struct Chars<'a> {
+    cursor: Cursor<'a>,
+    reversed: bool,
+}
+
+impl<'a> Chars<'a> {
+    fn new(rope: &'a crate::Rope, range: Range<usize>, offset: usize) -> Self {
+        Self { cursor: Cursor::new(rope, range, offset), reversed: false }
+    }
+
+    pub fn reversed(mut self) -> Self {
+        self.reversed = !self.reversed;
+        self
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn prev(&mut self) -> Option<char> {
+        if self.reversed {
+            self.cursor.next_char()
+        } else {
+            self.cursor.prev_char()
+        }
+    }
+}
+
+impl<'a> Iterator for Chars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.reversed {
+            self.cursor.prev_char()
+        } else {
+            self.cursor.next_char()
+        }
+    }
+}
+
+/// The bytes of a rope from a position, like `Chars`.
+#[derive(Clone)]
+pub struct Bytes<'a>(Cursor<'a>);
+
+impl<'a> Bytes<'a> {
+    #[allow(clippy::should_implement_trait)]
+    pub fn prev(&mut self) -> Option<u8> {
+        self.0.prev_byte()
+    }
+}
+
+impl<'a> Iterator for Bytes<'a> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        self.0.next_byte()
+    }
+}
+
+/// The text of a rope a slab at a time.
+pub struct Chunks<'a>(crate::Chunks<'a>);
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.0.next().map(as_str)
+    }
+}
+
+/// The lines of a rope, each with its line break.
+pub struct Lines<'a> {
+    slice: RopeSlice<'a>,
+    line: usize,
+    end: usize,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = RopeSlice<'a>;
+
+    fn next(&mut self) -> Option<RopeSlice<'a>> {
+        if self.line >= self.end {
+            return None;
+        }
+        self.line += 1;
+        Some(self.slice.line(self.line - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end.saturating_sub(self.line);
+        (len, Some(len))
+    }
+}
+
+impl<'a> ExactSizeIterator for Lines<'a> {}
+
+/// Whether two runs of chunks hold the same bytes, however each is split.
+fn chunks_eq<'a, 'b>(
+    mut a: impl Iterator<Item = &'a [u8]>,
+    mut b: impl Iterator<Item = &'b [u8]>,
+) -> bool {
+    let (mut x, mut y): (&[u8], &[u8]) = (&[], &[]);
+    loop {
+        if x.is_empty() {
+            x = a.next().unwrap_or_default();
+        }
+        if y.is_empty() {
+            y = b.next().unwrap_or_default();
+        }
+        if x.is_empty() || y.is_empty() {
+            return x.is_empty() && y.is_empty();
+        }
+        let len = x.len().min(y.len());
+        if x[..len] != y[..len] {
+            return false;
+        }
+        (x, y) = (&x[len..], &y[len..]);
+    }
+}
+
+impl<'a> RopeSlice<'a> {
+    fn byte_chunks(&self) -> impl Iterator<Item = &'a [u8]> {
+        self.rope.chunks(self.start..self.end, 0)
+    }
+}
+
+impl PartialEq for RopeSlice<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len_bytes() == other.len_bytes() && chunks_eq(self.byte_chunks(), other.byte_chunks())
+    }
+}
+
+impl Eq for RopeSlice<'_> {}
+
+impl PartialEq<str> for RopeSlice<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.len_bytes() == other.len()
+            && chunks_eq(self.byte_chunks(), std::iter::once(other.as_bytes()))
+    }
+}
+
+impl PartialEq<&str> for RopeSlice<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+impl PartialEq<String> for RopeSlice<'_> {
+    fn eq(&self, other: &String) -> bool {
+        *self == **other
+    }
+}
+
+impl PartialEq for Rope {
+    fn eq(&self, other: &Self) -> bool {
+        self.full() == other.full()
+    }
+}
+
+impl Eq for Rope {}
+
+impl PartialEq<str> for Rope {
+    fn eq(&self, other: &str) -> bool {
+        self.full() == *other
+    }
+}
+
+impl PartialEq<&str> for Rope {
+    fn eq(&self, other: &&str) -> bool {
+        self.full() == **other
+    }
+}
+
+impl PartialEq<String> for Rope {
+    fn eq(&self, other: &String) -> bool {
+        self.full() == **other
+    }
+}
+
+impl PartialEq<Rope> for &str {
+    fn eq(&self, other: &Rope) -> bool {
+        other == self
+    }
+}
+
+impl PartialEq<Rope> for String {
+    fn eq(&self, other: &Rope) -> bool {
+        other == self
+    }
+}
+
+/// The same for the same text however it's split into chunks.
+impl Hash for Rope {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for chunk in self.chunks() {
+            state.write(chunk.as_bytes());
+        }
+        state.write_u8(0xff);
+    }
+}
+
+impl std::fmt::Display for RopeSlice<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+impl std::fmt::Debug for RopeSlice<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_list().entries(self.chunks()).finish()
+    }
+}
+
+impl std::fmt::Display for Rope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.full().fmt(f)
+    }
+}
+
+impl std::fmt::Debug for Rope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.full(), f)
+    }
+}
+
+/// Takes a rope as it is, slabs and all; each of its slabs must hold whole chars, as those of a
+/// rope written a `&str` at a time through `SlabAllocator::append_str` do.
+impl From<crate::Rope> for Rope {
+    fn from(rope: crate::Rope) -> Self {
+        Self { rope, allocator: None }
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        Self::from_str(text)
+    }
+}
+
+impl From<String> for Rope {
+    fn from(text: String) -> Self {
+        Self::from_str(&text)
+    }
+}
+
+impl<'a> From<RopeSlice<'a>> for String {
+    fn from(slice: RopeSlice<'a>) -> Self {
+        slice.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexes_chars_and_lines() {
+        let rope = Rope::from_str("ab\ncé\r\n\nd");
+        assert_eq!((rope.len_bytes(), rope.len_chars(), rope.len_lines()), (10, 9, 4));
+        assert_eq!(rope.char_to_byte(5), 6);
+        assert_eq!(rope.byte_to_char(7), 6);
+        let starts: Vec<_> = (0..=4).map(|line| rope.line_to_char(line)).collect();
+        assert_eq!(starts, [0, 3, 7, 8, 9]);
+        let lines: Vec<_> = (0..9).map(|c| rope.char_to_line(c)).collect();
+        assert_eq!(lines, [0, 0, 0, 1, 1, 1, 1, 2, 3]);
+        assert_eq!(rope.byte_to_line(rope.len_bytes()), 3);
+        assert_eq!(rope.line(1), "cé\r\n");
+        assert_eq!(
+            rope.lines().map(String::from).collect::<Vec<_>>(),
+            ["ab\n", "cé\r\n", "\n", "d"]
+        );
+        assert!(rope.try_line_to_char(5).is_err());
+
+        let empty = Rope::new();
+        assert_eq!((empty.len_chars(), empty.len_lines()), (0, 1));
+        assert_eq!(empty.line(0), "");
+        assert_eq!(Rope::from_str("a\n").lines().last().unwrap(), "");
+    }
+
+    #[test]
+    fn steps_both_ways_across_chunks() {
+        let mut rope = Rope::from_str("héllo");
+        // each edit's text goes in a slab of its own
+        rope.insert(2, "\u{1f980}");
+        rope.insert_char(0, 'ñ');
+        assert_eq!(rope, "ñh\u{e9}\u{1f980}llo");
+        assert!(rope.chunks().count() > 1);
+
+        let mut chars = rope.chars_at(3);
+        assert_eq!(chars.next(), Some('\u{1f980}'));
+        assert_eq!(chars.prev(), Some('\u{1f980}'));
+        assert_eq!(chars.prev(), Some('\u{e9}'));
+        let back: String = rope.chars_at(4).reversed().collect();
+        assert_eq!(back, "\u{1f980}\u{e9}hñ");
+        let slice = rope.slice(1..4);
+        assert_eq!(slice.chars().collect::<String>(), "h\u{e9}\u{1f980}");
+        assert_eq!(slice.char_to_byte(2), 3);
+        assert_eq!(slice.bytes().count(), 7);
+    }
+
+    #[test]
+    fn edits_leave_clones_be() {
+        let mut rope = Rope::from_str("one two three");
+        let saved = rope.clone();
+        rope.remove(3..7);
+        rope.insert(3, ",");
+        let tail = rope.split_off(4);
+        assert_eq!((rope.to_string(), tail.to_string()), ("one,".into(), " three".into()));
+        rope.append(tail);
+        assert_eq!(rope, "one, three");
+        assert_eq!(saved, "one two three");
+
+        let hash = |rope: &Rope| {
+            let mut hasher = std::hash::DefaultHasher::new();
+            rope.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&rope), hash(&Rope::from_str("one, three")));
+    }
+}
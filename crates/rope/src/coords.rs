@@ -0,0 +1,222 @@
+use std::ops::Range;
+use tore::Point;
+use unicode_width::UnicodeWidthChar;
+
+use sumtree::Node;
+
+use crate::cursor::SlabCursor;
+use crate::Rope;
+
+/// Which side of a tab or wide char a display column inside it snaps to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bias {
+    Left,
+    Right,
+}
+
+/// Display width of `c` when it starts at display column `column`.
+pub fn char_width(c: char, column: usize, tab_width: usize) -> usize {
+    match c {
+        '\t' => tab_width - column % tab_width,
+        c => c.width().unwrap_or(0),
+    }
+}
+
+impl Rope {
+    /// How many chars the rope holds, kept in its summaries rather than counted.
+    pub fn len_chars(&self) -> usize {
+        self.0.as_ref().map_or(0, |tree| tree.summary().stats.chars)
+    }
+
+    /// How many chars come before byte `offset`, or None past the end of the rope. The end
+    /// itself is the char past the last.
+    pub fn offset_to_char(&self, offset: usize) -> Option<usize> {
+        if offset >= self.len() {
+            return (offset == self.len()).then(|| self.len_chars());
+        }
+        let tree = self.0.as_ref()?;
+        let mut cursor = SlabCursor(tree.cursor_with_summary());
+        let pos = cursor.seek_to_byte(offset)?;
+        match pos.leaf.as_ref() {
+            Node::Branch { .. } => unreachable!("sumtree seek must return leaf node"),
+            Node::Leaf { item, .. } => {
+                let before = &item.as_bytes()[..pos.offset];
+                Some(cursor.summary().stats.chars + bytecount::num_chars(before))
+            }
+        }
+    }
+
+    /// The byte offset of the char `chars` from the start, or None past the end of the rope. The
+    /// char past the last is at the end.
+    pub fn char_to_offset(&self, chars: usize) -> Option<usize> {
+        if chars >= self.len_chars() {
+            return (chars == self.len_chars()).then(|| self.len());
+        }
+        let tree = self.0.as_ref()?;
+        let mut cursor = SlabCursor(tree.cursor_with_summary());
+        let pos = cursor.seek_to_char(chars)?;
+        match pos.leaf.as_ref() {
+            Node::Branch { .. } => unreachable!("sumtree seek must return leaf node"),
+            Node::Leaf { item, .. } => {
+                // every byte but a continuation byte starts a char
+                let (offset, _) = item
+                    .as_bytes()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| **b & 0xc0 != 0x80)
+                    .nth(pos.offset)?;
+                Some(cursor.summary().stats.len + offset)
+            }
+        }
+    }
+
+    /// The display column `point` starts at. A point inside a char's combining marks maps to the
+    /// char itself, and columns past the end of the line are clamped to it.
+    pub fn point_to_display_column(&self, point: Point, tab_width: usize) -> usize {
+        let mut display = 0;
+        for (c, range) in self.line_clusters(point.line) {
+            if range.end > point.column {
+                break;
+            }
+            display += char_width(c, display, tab_width);
+        }
+        display
+    }
+
+    /// The point on `line` shown at `display_column`. A column inside a tab or wide char snaps to
+    /// its start or just past it, per `bias`; columns past the end of the line clamp to it.
+    pub fn display_column_to_point(
+        &self,
+        line: usize,
+        display_column: usize,
+        tab_width: usize,
+        bias: Bias,
+    ) -> Point {
+        let mut display = 0;
+        let mut column = 0;
+        for (c, range) in self.line_clusters(line) {
+            let width = char_width(c, display, tab_width);
+            if display + width > display_column {
+                if display < display_column && bias == Bias::Right {
+                    column = range.end;
+                }
+                break;
+            }
+            display += width;
+            column = range.end;
+        }
+        Point { line, column }
+    }
+
+    /// The chars of `line` without its terminator, each with any zero width chars that follow
+    /// it, and their byte ranges relative to the line start.
+    fn line_clusters(&self, line: usize) -> impl Iterator<Item = (char, Range<usize>)> + '_ {
+        let range = self.line_range(line).unwrap_or_default();
+        let start = range.start;
+        let mut chars = self
+            .char_range(range, 0)
+            .take_while(|(c, _)| *c != '\n' && *c != '\r')
+            .map(move |(c, r)| (c, (r.start - start)..(r.end - start)))
+            .peekable();
+        std::iter::from_fn(move || {
+            let (c, mut range) = chars.next()?;
+            while let Some((_, next)) = chars.next_if(|(c, _)| char_width(*c, 0, 1) == 0) {
+                range.end = next.end;
+            }
+            Some((c, range))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SlabAllocator;
+
+    fn rope(text: &str) -> Rope {
+        let mut buffer = SlabAllocator::new();
+        let (slab, written) = buffer.append(text.as_bytes()).unwrap();
+        assert_eq!(written, text.len());
+        Rope::empty().insert(0, slab).unwrap()
+    }
+
+    /// `(byte column, display column)` of every char start and the end of `text`.
+    fn columns(text: &str, tab_width: usize) -> Vec<(usize, usize)> {
+        let mut display = 0;
+        let mut columns = vec![];
+        for (i, c) in text.char_indices() {
+            let width = char_width(c, display, tab_width);
+            match columns.last() {
+                // a combining mark is shown with the char before it
+                Some(&(_, d)) if width == 0 => columns.push((i, d)),
+                _ => columns.push((i, display)),
+            }
+            display += width;
+        }
+        columns.push((text.len(), display));
+        columns
+    }
+
+    #[test]
+    fn display_columns_round_trip() {
+        let lines = [
+            "\tfn a() {}",
+            "a\tb\tc",
+            "日本語ab",
+            "e\u{301}x\u{308}y",
+            "",
+        ];
+        let rope = rope(&lines.join("\n"));
+        for tab_width in [4, 8] {
+            for (line, text) in lines.iter().enumerate() {
+                let columns = columns(text, tab_width);
+                for &(column, display) in columns.iter() {
+                    let point = Point { line, column };
+                    assert_eq!(rope.point_to_display_column(point, tab_width), display);
+
+                    let first = columns.iter().find(|(_, d)| *d == display).unwrap().0;
+                    for bias in [Bias::Left, Bias::Right] {
+                        let actual = rope.display_column_to_point(line, display, tab_width, bias);
+                        assert_eq!(actual, Point { line, column: first }, "{:?}", text);
+                    }
+                }
+
+                let end = columns.last().unwrap().1;
+                for display in 0..=end + 2 {
+                    let left = rope.display_column_to_point(line, display, tab_width, Bias::Left);
+                    let right = rope.display_column_to_point(line, display, tab_width, Bias::Right);
+                    let left_display = rope.point_to_display_column(left, tab_width);
+                    let right_display = rope.point_to_display_column(right, tab_width);
+                    if display > end {
+                        assert_eq!((left_display, right_display), (end, end));
+                    } else {
+                        assert!(left_display <= display && display <= right_display);
+                        let snapped = columns.iter().any(|(_, d)| *d == display);
+                        assert_eq!(snapped, left == right, "{:?} at {}", text, display);
+                    }
+                }
+            }
+        }
+
+        let p = rope.display_column_to_point(2, 3, 4, Bias::Left);
+        assert_eq!(p, Point { line: 2, column: 3 });
+        let p = rope.display_column_to_point(2, 3, 4, Bias::Right);
+        assert_eq!(p, Point { line: 2, column: 6 });
+        let p = rope.display_column_to_point(1, 2, 4, Bias::Right);
+        assert_eq!(p, Point { line: 1, column: 2 });
+    }
+
+    #[test]
+    fn char_at_line_ends() {
+        let rope = rope("ab\n日本\n\nc");
+        assert_eq!(rope.char_at(Point { line: 0, column: 1 }), Some('b'));
+        assert_eq!(rope.char_at(Point { line: 0, column: 2 }), None);
+        assert_eq!(rope.char_at(Point { line: 1, column: 3 }), Some('本'));
+        assert_eq!(rope.char_at(Point { line: 1, column: 4 }), None);
+        assert_eq!(rope.char_at(Point { line: 1, column: 6 }), None);
+        assert_eq!(rope.char_at(Point { line: 2, column: 0 }), None);
+        assert_eq!(rope.char_at(Point { line: 3, column: 0 }), Some('c'));
+        assert_eq!(rope.char_at(Point { line: 3, column: 1 }), None);
+        assert_eq!(rope.char_at(Point { line: 9, column: 0 }), None);
+    }
+}
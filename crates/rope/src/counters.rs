@@ -0,0 +1,40 @@
+//! Counts of the work ropes do on each thread, so tests can bound how much of a rope an
+//! operation touches rather than time it. Only built for the rope's own tests and with the
+//! `counters` feature; otherwise counting compiles to nothing.
+
+use std::cell::Cell;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// Descents from the root of a tree to one of its leaves.
+    pub seeks: usize,
+    /// Leaves read, by an iterator moving on to the next or a cursor loading one.
+    pub leaves: usize,
+    /// Bytes or chars read one at a time rather than a leaf at a time.
+    pub steps: usize,
+}
+
+thread_local! {
+    static COUNTERS: Cell<Counters> = Cell::new(Counters::default());
+}
+
+/// Runs `f`, and what it counted on this thread.
+pub fn count<R>(f: impl FnOnce() -> R) -> (R, Counters) {
+    let before = COUNTERS.with(Cell::get);
+    let result = f();
+    let after = COUNTERS.with(Cell::get);
+    let counted = Counters {
+        seeks: after.seeks - before.seeks,
+        leaves: after.leaves - before.leaves,
+        steps: after.steps - before.steps,
+    };
+    (result, counted)
+}
+
+pub(crate) fn add(f: impl FnOnce(&mut Counters)) {
+    COUNTERS.with(|counters| {
+        let mut counted = counters.get();
+        f(&mut counted);
+        counters.set(counted);
+    });
+}
@@ -1,9 +1,12 @@
 use bstr::ByteSlice;
-use circular_buffer::CircularBuffer;
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
 use std::ops::{Deref, DerefMut, Range};
 
 use sumtree::{CursorDirection, Item, Node, SumTree};
 
+use crate::slab::BULK_BLOCK_CAPACITY;
+use crate::util::counted;
 use crate::{Rope, RopeSlice, Slab};
 
 pub(crate) struct CursorPosition<'a>(pub SlabCursor<'a>, pub Position<'a, Slab>);
@@ -31,10 +34,10 @@ impl<'a> DerefMut for SlabCursor<'a> {
 
 impl<'a> SlabCursor<'a> {
     pub(crate) fn seek_to_byte(&mut self, offset: usize) -> Option<Position<'a, Slab>> {
+        counted!(seeks);
         let mut offset = offset;
-        let leaf = self.0.seek(|node| {
-            let summary = node.summary();
-            let left = summary.left.unwrap_or_default();
+        let leaf = self.0.seek(|left, _| {
+            let left = left.stats;
             if offset < left.len {
                 CursorDirection::Left
             } else {
@@ -46,12 +49,29 @@ impl<'a> SlabCursor<'a> {
         leaf.map(|leaf| Position { leaf, offset })
     }
 
+    /// The leaf the char `chars` from the start is in, and how many chars into it.
+    pub(crate) fn seek_to_char(&mut self, chars: usize) -> Option<Position<'a, Slab>> {
+        counted!(seeks);
+        let mut chars = chars;
+        let leaf = self.0.seek(|left, _| {
+            let left = left.stats;
+            if chars < left.chars {
+                CursorDirection::Left
+            } else {
+                chars -= left.chars;
+                CursorDirection::Right
+            }
+        });
+
+        leaf.map(|leaf| Position { leaf, offset: chars })
+    }
+
     pub(crate) fn seek_to_line(&mut self, line: usize) -> Option<Position<'a, Slab>> {
+        counted!(seeks);
         self.0.reset();
         let mut line = line;
-        let leaf = self.0.seek(|node| {
-            let summary = node.summary();
-            let left = summary.left.unwrap_or_default();
+        let leaf = self.0.seek(|left, _| {
+            let left = left.stats;
             if line <= left.lines.line {
                 CursorDirection::Left
             } else {
@@ -92,7 +112,6 @@ pub struct ChunkAndRanges<'a> {
     range: Range<usize>,
     offset: usize,
     cursor_pos: Option<CursorPosition<'a>>,
-    trim_last_terminator: bool,
 }
 
 impl<'a> ChunkAndRanges<'a> {
@@ -103,7 +122,7 @@ impl<'a> ChunkAndRanges<'a> {
                 .seek_to_byte(range.start + offset)
                 .map(|pos| CursorPosition(cursor, pos))
         });
-        Self { range, offset, cursor_pos, trim_last_terminator: false }
+        Self { range, offset, cursor_pos }
     }
 
     pub(super) fn new_trim_last_terminator(
@@ -111,13 +130,7 @@ impl<'a> ChunkAndRanges<'a> {
         range: Range<usize>,
         offset: usize,
     ) -> Self {
-        let cursor_pos = rope.0.as_ref().and_then(|tree| {
-            let mut cursor = SlabCursor(tree.cursor());
-            cursor
-                .seek_to_byte(range.start + offset)
-                .map(|pos| CursorPosition(cursor, pos))
-        });
-        Self { range, offset, cursor_pos, trim_last_terminator: true }
+        Self::new(rope, without_terminator(rope, range), offset)
     }
 }
 
@@ -134,41 +147,41 @@ impl<'a> Iterator for ChunkAndRanges<'a> {
             None => None,
             Some(CursorPosition(mut cursor, curr_pos)) => {
                 if let Node::Leaf { item: slab, .. } = curr_pos.leaf.as_ref() {
+                    counted!(leaves);
                     let bytes = &slab.as_bytes()[curr_pos.offset..];
-                    let chunk = if bytes.len() < self.range.len() {
-                        Some(bytes)
-                    } else {
-                        Some(&bytes[..(self.range.len())])
-                    };
-
-                    let chunk = if self.trim_last_terminator {
-                        trim_last_terminator(chunk)
-                    } else {
-                        chunk
-                    };
-
-                    if let Some(chunk) = chunk {
-                        let chunk_range = (self.range.start + self.offset)
-                            ..(self.range.start + self.offset + chunk.len());
-                        self.cursor_pos = cursor
-                            .0
-                            .next()
-                            .map(|leaf| Position { leaf, offset: 0 })
-                            .map(|p| CursorPosition(cursor, p));
-                        self.offset += slab.summary().stats.len - curr_pos.offset;
-
-                        Some((chunk, chunk_range))
-                    } else {
-                        None
-                    }
+                    // a slab running on past the range is cut where the range ends
+                    let remaining = self.range.len() - self.offset;
+                    let chunk = &bytes[..bytes.len().min(remaining)];
+                    let chunk_range = (self.range.start + self.offset)
+                        ..(self.range.start + self.offset + chunk.len());
+                    self.cursor_pos = cursor
+                        .0
+                        .next()
+                        .map(|leaf| Position { leaf, offset: 0 })
+                        .map(|p| CursorPosition(cursor, p));
+                    self.offset += slab.summary().stats.len - curr_pos.offset;
+
+                    Some((chunk, chunk_range))
                 } else {
                     unreachable!()
                 }
             }
         }
     }
+
+    /// Every chunk holds at least one byte and at most the biggest block's worth, whatever size
+    /// of block its slab is in, so the bytes left bound the chunks left both ways.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.cursor_pos.is_none() {
+            return (0, Some(0));
+        }
+        let remaining = self.range.len().saturating_sub(self.offset);
+        (remaining.div_ceil(BULK_BLOCK_CAPACITY), Some(remaining))
+    }
 }
 
+impl<'a> FusedIterator for ChunkAndRanges<'a> {}
+
 pub struct Chunks<'a>(ChunkAndRanges<'a>);
 
 impl<'a> Chunks<'a> {
@@ -191,35 +204,66 @@ impl<'a> Iterator for Chunks<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         self.0.next().map(|(chunk, _)| chunk)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
 }
 
+impl<'a> FusedIterator for Chunks<'a> {}
+
 enum CharRangeBufferState {
     Buffering,
     Replaying { offset: usize },
 }
 
 struct CharRangeState<'a> {
+    chunk: &'a [u8],
     chunk_range: Range<usize>,
     chars: bstr::CharIndices<'a>,
     chars_offset: usize,
 }
 
+impl<'a> CharRangeState<'a> {
+    /// The chars of `chunk` from `skip` bytes in.
+    fn new(chunk: &'a [u8], chunk_range: Range<usize>, skip: usize) -> Self {
+        let chunk = &chunk[skip..];
+        let chunk_range = (chunk_range.start + skip)..chunk_range.end;
+        Self { chunk, chunk_range, chars: chunk.char_indices(), chars_offset: 0 }
+    }
+}
+
+/// Whether `bytes` are the start of a UTF-8 sequence that more bytes could complete.
+fn is_incomplete(bytes: &[u8]) -> bool {
+    match std::str::from_utf8(bytes) {
+        Ok(_) => false,
+        Err(err) => err.valid_up_to() == 0 && err.error_len().is_none(),
+    }
+}
+
+/// How many of the chars stepped over `CharRange::prev` can go back through.
+const LOOKBACK: usize = 2;
+
+/// Keeps `c` as the latest char stepped over, forgetting any past the last `LOOKBACK`.
+fn remember(buffer: &mut VecDeque<(char, Range<usize>)>, c: char, range: Range<usize>) {
+    buffer.push_front((c, range));
+    buffer.truncate(LOOKBACK);
+}
+
 pub struct CharRange<'a> {
     chunks: ChunkAndRanges<'a>,
     curr: Option<CharRangeState<'a>>,
-    buffer: CircularBuffer<2, (char, Range<usize>)>,
+    buffer: VecDeque<(char, Range<usize>)>,
     state: CharRangeBufferState,
 }
 
 impl<'a> CharRange<'a> {
     pub(super) fn new(rope: &'a Rope, range: Range<usize>, offset: usize) -> Self {
         let mut chunks = ChunkAndRanges::new(rope, range, offset);
-        let curr = chunks.next().map(|(chunk, chunk_range)| CharRangeState {
-            chunk_range,
-            chars: chunk.char_indices(),
-            chars_offset: 0,
-        });
-        let buffer = CircularBuffer::new();
+        let curr = chunks
+            .next()
+            .map(|(chunk, chunk_range)| CharRangeState::new(chunk, chunk_range, 0));
+        let buffer = VecDeque::with_capacity(LOOKBACK + 1);
         Self { curr, chunks, buffer, state: CharRangeBufferState::Buffering }
     }
 
@@ -248,12 +292,19 @@ impl<'a> CharRange<'a> {
 
     #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<(char, Range<usize>)> {
+        counted!(steps);
         loop {
             match self.curr.as_mut() {
                 None => break None,
                 Some(CharRangeState {
-                    chunk_range, ref mut chars, ref mut chars_offset, ..
+                    chunk,
+                    chunk_range,
+                    ref mut chars,
+                    ref mut chars_offset,
+                    ..
                 }) => {
+                    let chunk: &'a [u8] = chunk;
+                    let chunk_start = chunk_range.start;
                     // abc
                     match self.state {
                         CharRangeBufferState::Replaying { offset } => {
@@ -269,20 +320,25 @@ impl<'a> CharRange<'a> {
                         }
                         CharRangeBufferState::Buffering => match chars.next() {
                             None => {
-                                self.curr =
-                                    self.chunks
-                                        .next()
-                                        .map(|(chunk, chunk_range)| CharRangeState {
-                                            chunk_range,
-                                            chars: chunk.char_indices(),
-                                            chars_offset: 0,
-                                        })
+                                self.curr = self.chunks.next().map(|(chunk, chunk_range)| {
+                                    CharRangeState::new(chunk, chunk_range, 0)
+                                })
+                            }
+                            // leaves split text anywhere, so a char can start in one chunk and
+                            // end in the next
+                            Some((start, end, _))
+                                if end == chunk.len() && is_incomplete(&chunk[start..]) =>
+                            {
+                                *chars_offset += end - start;
+                                let (c, range) =
+                                    self.join_split(chunk_start + start, &chunk[start..]);
+                                remember(&mut self.buffer, c, range.clone());
+                                break Some((c, range));
                             }
                             Some((start, end, c)) => {
-                                let range_offset = chunk_range.start;
-                                let range = (range_offset + start)..(range_offset + end);
+                                let range = (chunk_start + start)..(chunk_start + end);
                                 *chars_offset += range.len();
-                                self.buffer.push_front((c, range.clone()));
+                                remember(&mut self.buffer, c, range.clone());
                                 break Some((c, range));
                             }
                         },
@@ -292,7 +348,88 @@ impl<'a> CharRange<'a> {
         }
     }
 
+    /// Decodes the char starting with `tail`, the last bytes of the current chunk at `start`,
+    /// from as many of the following chunks as it runs into, then carries on after it. Bytes
+    /// that don't complete a char decode as a replacement char spanning just the bad sequence.
+    fn join_split(&mut self, start: usize, tail: &[u8]) -> (char, Range<usize>) {
+        let mut bytes = tail.to_vec();
+        let mut joined = vec![];
+        while is_incomplete(&bytes) {
+            let Some((chunk, chunk_range)) = self.chunks.next() else {
+                break;
+            };
+            let take = (4 - bytes.len()).min(chunk.len());
+            bytes.extend_from_slice(&chunk[..take]);
+            joined.push((chunk, chunk_range));
+        }
+        let (c, len) = bstr::decode_utf8(&bytes);
+        let end = start + len;
+        // resume in whichever chunk the char ends in
+        if let Some((chunk, chunk_range)) = joined
+            .into_iter()
+            .rev()
+            .find(|(_, range)| range.start <= end)
+        {
+            let skip = end - chunk_range.start;
+            self.curr = Some(CharRangeState::new(chunk, chunk_range, skip));
+        }
+        (c.unwrap_or(char::REPLACEMENT_CHARACTER), start..end)
+    }
+
+    /// Skips `n` chars, over as much of a chunk at a time as is valid UTF-8, so only chars split
+    /// across chunks or invalid bytes are decoded one by one. The last chars skipped are kept
+    /// for `prev` as if they'd been read.
+    fn skip(&mut self, mut n: usize) {
+        while n > 0 {
+            let buffering = matches!(self.state, CharRangeBufferState::Buffering);
+            let Some(curr) = self.curr.as_mut().filter(|_| buffering) else {
+                // replaying what was already read, or at the end
+                if self.next().is_none() {
+                    return;
+                }
+                n -= 1;
+                continue;
+            };
+            let chunk: &'a [u8] = curr.chunk;
+            let rest = &chunk[curr.chars_offset..];
+            if rest.is_empty() {
+                self.curr = self
+                    .chunks
+                    .next()
+                    .map(|(chunk, chunk_range)| CharRangeState::new(chunk, chunk_range, 0));
+                continue;
+            }
+            let valid = match std::str::from_utf8(rest) {
+                Ok(_) => rest.len(),
+                Err(err) => err.valid_up_to(),
+            };
+            if valid == 0 {
+                self.next();
+                n -= 1;
+                continue;
+            }
+            let rest = &rest[..valid];
+            // every byte but a continuation byte starts a char
+            let len = rest
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| **b & 0xc0 != 0x80)
+                .nth(n)
+                .map_or(rest.len(), |(i, _)| i);
+            let skipped = &rest[..len];
+            let start = curr.chunk_range.start + curr.chars_offset;
+            let last: Vec<_> = skipped.char_indices().rev().take(2).collect();
+            for (from, to, c) in last.into_iter().rev() {
+                remember(&mut self.buffer, c, (start + from)..(start + to));
+            }
+            let skip = curr.chars_offset + len;
+            *curr = CharRangeState::new(chunk, curr.chunk_range.clone(), skip);
+            n -= bytecount::num_chars(skipped);
+        }
+    }
+
     pub fn prev(&mut self) -> Option<(char, Range<usize>)> {
+        counted!(steps);
         let offset = match self.state {
             CharRangeBufferState::Buffering => 0,
             CharRangeBufferState::Replaying { offset } => offset,
@@ -311,8 +448,15 @@ impl<'a> Iterator for CharRange<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         self.next()
     }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.skip(n);
+        self.next()
+    }
 }
 
+impl<'a> FusedIterator for CharRange<'a> {}
+
 pub struct Chars<'a>(CharRange<'a>);
 
 impl<'a> Chars<'a> {
@@ -340,8 +484,14 @@ impl<'a> Iterator for Chars<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         self.next()
     }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        Iterator::nth(&mut self.0, n).map(|(c, _)| c)
+    }
 }
 
+impl<'a> FusedIterator for Chars<'a> {}
+
 pub struct Lines<'a> {
     rope: &'a Rope,
     cursor_pos: Option<CursorPosition<'a>>,
@@ -386,23 +536,31 @@ impl<'a> Iterator for Lines<'a> {
             }
         }
     }
+
+    /// Every line in range is in the rope, so there are exactly as many left as are in range.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.line_range.len();
+        (len, Some(len))
+    }
 }
 
-fn trim_last_terminator(s: Option<&[u8]>) -> Option<&[u8]> {
-    match s {
-        None => None,
-        Some(mut s) => {
-            if s.last_byte() == Some(b'\n') {
-                s = &s[..s.len() - 1];
-                if s.last_byte() == Some(b'\r') {
-                    s = &s[..s.len() - 1];
-                }
-            }
-            if s.is_empty() {
-                None
-            } else {
-                Some(s)
-            }
+impl<'a> ExactSizeIterator for Lines<'a> {}
+
+impl<'a> FusedIterator for Lines<'a> {}
+
+/// `range` less the `\n` or `\r\n` it ends with, looked up byte by byte so a `\r` in the slab
+/// before its `\n` is found too.
+pub(crate) fn without_terminator(rope: &Rope, range: Range<usize>) -> Range<usize> {
+    let byte_at = |offset: usize| {
+        let (chunk, start) = rope.chunk_at(offset)?;
+        chunk.get(offset - start).copied()
+    };
+    let mut end = range.end;
+    if end > range.start && byte_at(end - 1) == Some(b'\n') {
+        end -= 1;
+        if end > range.start && byte_at(end - 1) == Some(b'\r') {
+            end -= 1;
         }
     }
+    range.start..end
 }
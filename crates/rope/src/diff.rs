@@ -0,0 +1,451 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use sumtree::{Node, SumTree};
+
+use crate::{Rope, Slab};
+
+impl Rope {
+    /// The byte ranges where this rope and `other` differ, in order, each paired with the range of
+    /// `other` that takes its place. Between the ranges the two ropes hold the same bytes, and
+    /// differences that touch are coalesced, so consecutive ranges are at least a byte apart.
+    ///
+    /// Both trees are walked together and subtrees they share are passed over without being
+    /// looked into; only leaves that aren't shared have their bytes compared. After a difference
+    /// the walk picks up where the ropes' slabs share bytes again. So when one rope was made from
+    /// the other by edits, the cost is in proportion to the edited text and the depth of the
+    /// trees, however long the ropes are. Ropes that share nothing are still diffed correctly,
+    /// but slowly, and coarsely: everything from their first difference on is one range.
+    pub fn diff_ranges(&self, other: &Rope) -> Vec<(Range<usize>, Range<usize>)> {
+        RopeDiff::new(self, other).run()
+    }
+}
+
+struct RopeDiff<'a> {
+    old: Walk<'a>,
+    new: Walk<'a>,
+    ranges: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl<'a> RopeDiff<'a> {
+    fn new(old: &'a Rope, new: &'a Rope) -> Self {
+        Self { old: Walk::new(old), new: Walk::new(new), ranges: vec![] }
+    }
+
+    fn run(mut self) -> Vec<(Range<usize>, Range<usize>)> {
+        self.walk();
+        self.ranges
+    }
+
+    fn walk(&mut self) {
+        while let (Some(old), Some(new)) = (self.old.head(), self.new.head()) {
+            if old.skip == 0 && new.skip == 0 && old.tree == new.tree {
+                let len = old.len();
+                self.old.advance(len);
+                self.new.advance(len);
+                continue;
+            }
+
+            let old_branch = matches!(old.tree.as_ref(), Node::Branch { .. });
+            let new_branch = matches!(new.tree.as_ref(), Node::Branch { .. });
+            if old_branch || new_branch {
+                // a subtree starts at the same place in both ropes when they share it, so the
+                // longer head is opened until the shorter turns up in it
+                if old_branch && (!new_branch || old.len() >= new.len()) {
+                    self.old.open();
+                }
+                if new_branch && (!old_branch || new.len() >= old.len()) {
+                    self.new.open();
+                }
+                continue;
+            }
+
+            self.old.visit();
+            self.new.visit();
+            let len = old.len().min(new.len());
+            let (old_piece, new_piece) = (old.piece(), new.piece());
+            let common = if old_piece.0 == new_piece.0 && old_piece.1.start == new_piece.1.start {
+                len
+            } else {
+                let pairs = old.bytes().iter().zip(new.bytes());
+                pairs.take_while(|(old, new)| old == new).count()
+            };
+            self.old.advance(common);
+            self.new.advance(common);
+            if common < len {
+                self.resync();
+            }
+        }
+        // whatever is left of either rope has nothing left in the other to match
+        self.push(self.old.offset..self.old.len, self.new.offset..self.new.len);
+    }
+
+    /// Passes the difference at the walks' offsets, up to where the ropes share bytes again.
+    /// Both ropes are scanned a leaf at a time, each never far ahead of the other, so that a
+    /// short difference is found as soon as a long one.
+    fn resync(&mut self) {
+        let start = (self.old.offset, self.new.offset);
+        let (mut old, mut new) = (self.old.clone(), self.new.clone());
+        let (mut old_seen, mut new_seen) = (Pieces::default(), Pieces::default());
+        let end = loop {
+            let scan_old = match (old.is_done(), new.is_done()) {
+                (true, true) => break None,
+                (false, true) => true,
+                (true, false) => false,
+                (false, false) => old.offset - start.0 <= new.offset - start.1,
+            };
+            if scan_old {
+                let (offset, piece) = old.next_piece();
+                if let Some((at, other)) = new_seen.find(offset, &piece) {
+                    break Some((at, other));
+                }
+                old_seen.insert(offset, piece);
+            } else {
+                let (offset, piece) = new.next_piece();
+                if let Some((at, other)) = old_seen.find(offset, &piece) {
+                    break Some((other, at));
+                }
+                new_seen.insert(offset, piece);
+            }
+        };
+        #[cfg(test)]
+        {
+            self.old.visits = old.visits;
+            self.new.visits = new.visits;
+        }
+
+        let (old_end, new_end) = end.unwrap_or((self.old.len, self.new.len));
+        self.push(start.0..old_end, start.1..new_end);
+        self.old.advance(old_end - start.0);
+        self.new.advance(new_end - start.1);
+    }
+
+    fn push(&mut self, old: Range<usize>, new: Range<usize>) {
+        if old.is_empty() && new.is_empty() {
+            return;
+        }
+        match self.ranges.last_mut() {
+            Some((last_old, last_new))
+                if last_old.end == old.start && last_new.end == new.start =>
+            {
+                last_old.end = old.end;
+                last_new.end = new.end;
+            }
+            _ => self.ranges.push((old, new)),
+        }
+    }
+}
+
+/// One rope's tree walked from left to right.
+#[derive(Clone)]
+struct Walk<'a> {
+    /// The subtrees not yet passed, the next one last.
+    stack: Vec<Head<'a>>,
+    offset: usize,
+    len: usize,
+    /// Branches opened and leaves read, to show what sharing saves.
+    #[cfg(test)]
+    visits: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Head<'a> {
+    tree: &'a SumTree<Slab>,
+    /// How much of the start of a leaf is already passed; always 0 for a branch.
+    skip: usize,
+}
+
+impl<'a> Head<'a> {
+    fn len(&self) -> usize {
+        self.tree.summary().stats.len - self.skip
+    }
+
+    fn bytes(&self) -> &'a [u8] {
+        &self.tree.deref_item().as_bytes()[self.skip..]
+    }
+
+    /// The block the rest of the leaf is in, and where in it.
+    fn piece(&self) -> (usize, Range<usize>) {
+        let (block, range) = self.tree.deref_item().block_range();
+        (block, range.start + self.skip..range.end)
+    }
+}
+
+impl<'a> Walk<'a> {
+    fn new(rope: &'a Rope) -> Self {
+        Self {
+            stack: rope.0.iter().map(|tree| Head { tree, skip: 0 }).collect(),
+            offset: 0,
+            len: rope.len(),
+            #[cfg(test)]
+            visits: 0,
+        }
+    }
+
+    fn head(&self) -> Option<Head<'a>> {
+        self.stack.last().copied()
+    }
+
+    fn is_done(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    fn visit(&mut self) {
+        #[cfg(test)]
+        {
+            self.visits += 1;
+        }
+    }
+
+    /// Replaces the branch at the head with its children.
+    fn open(&mut self) {
+        let head = self.stack.pop().expect("opened past the end");
+        let Node::Branch { .. } = head.tree.as_ref() else {
+            unreachable!("only branches are opened");
+        };
+        self.visit();
+        for child in head.tree.children().rev() {
+            self.stack.push(Head { tree: child, skip: 0 });
+        }
+    }
+
+    /// Passes `n` bytes, opening only the branches the new offset is inside of.
+    fn advance(&mut self, mut n: usize) {
+        self.offset += n;
+        while n > 0 {
+            let head = self.head().expect("advanced past the end");
+            let len = head.len();
+            if n >= len {
+                self.stack.pop();
+                n -= len;
+            } else if let Node::Branch { .. } = head.tree.as_ref() {
+                self.open();
+            } else {
+                self.stack.last_mut().unwrap().skip += n;
+                n = 0;
+            }
+        }
+    }
+
+    /// The rest of the leaf at the offset, and the offset, passing the leaf.
+    fn next_piece(&mut self) -> (usize, (usize, Range<usize>)) {
+        while let Some(Node::Branch { .. }) = self.head().map(|head| head.tree.as_ref()) {
+            self.open();
+        }
+        let head = self.head().expect("scanned past the end");
+        self.visit();
+        let offset = self.offset;
+        self.advance(head.len());
+        (offset, head.piece())
+    }
+}
+
+/// The pieces of blocks one rope was scanned over, and where they are in it.
+#[derive(Default)]
+struct Pieces(BTreeMap<(usize, usize), (usize, usize)>);
+
+impl Pieces {
+    fn insert(&mut self, offset: usize, (block, range): (usize, Range<usize>)) {
+        self.0.insert((block, range.start), (range.end, offset));
+    }
+
+    /// Where `piece`, at `offset` in the other rope, first shares bytes with a piece scanned
+    /// here: the offsets of that first byte in the other rope and in this one. The pieces of one
+    /// rope don't overlap unless it repeats a slab, and then a shared byte may be missed, which
+    /// makes a difference longer but no less true.
+    fn find(
+        &self,
+        offset: usize,
+        (block, range): &(usize, Range<usize>),
+    ) -> Option<(usize, usize)> {
+        let mut found = None;
+        let before_end = self.0.range((*block, 0)..(*block, range.end)).rev();
+        for (&(_, start), &(end, seen)) in before_end {
+            if end <= range.start {
+                break;
+            }
+            let at = start.max(range.start);
+            found = Some((offset + at - range.start, seen + at - start));
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::SlabAllocator;
+    use bstr::ByteSlice;
+
+    fn lines(count: usize) -> Vec<u8> {
+        use std::io::Write;
+        let mut lines = vec![];
+        for i in 0..count {
+            writeln!(lines, "{} request handled in {}ms", i, i % 97).unwrap();
+        }
+        lines
+    }
+
+    fn write_all(allocator: &mut SlabAllocator, mut bytes: &[u8]) -> Vec<Slab> {
+        let mut slabs = vec![];
+        while !bytes.is_empty() {
+            let (slab, written) = allocator.append(bytes).unwrap();
+            bytes = &bytes[written..];
+            slabs.push(slab);
+        }
+        slabs
+    }
+
+    /// A rope of `text` written a line at a time into small blocks, so it has thousands of
+    /// leaves.
+    fn rope(text: &[u8]) -> Rope {
+        let mut allocator = SlabAllocator::with_capacity(64);
+        let slabs: Vec<_> = text
+            .lines_with_terminator()
+            .flat_map(|line| write_all(&mut allocator, line))
+            .collect();
+        Rope::empty().append_many(slabs).unwrap()
+    }
+
+    /// The differences between a text and one edited from it, given where each byte of the
+    /// edited text came from, if anywhere.
+    fn reference_diff(
+        old_len: usize,
+        origins: &[Option<usize>],
+    ) -> Vec<(Range<usize>, Range<usize>)> {
+        let mut ranges = vec![];
+        let (mut old, mut new) = (0, 0);
+        while new < origins.len() || old < old_len {
+            if new < origins.len() && origins[new] == Some(old) {
+                old += 1;
+                new += 1;
+                continue;
+            }
+            let start = (old, new);
+            while new < origins.len() && origins[new].is_none() {
+                new += 1;
+            }
+            old = origins.get(new).copied().flatten().unwrap_or(old_len);
+            ranges.push((start.0..old, start.1..new));
+        }
+        ranges
+    }
+
+    /// Checks that `ranges` are ordered and coalesced, and that the texts are the same outside
+    /// them.
+    #[track_caller]
+    fn assert_describes(old: &[u8], new: &[u8], ranges: &[(Range<usize>, Range<usize>)]) {
+        let (mut at_old, mut at_new) = (0, 0);
+        for (i, (old_range, new_range)) in ranges.iter().enumerate() {
+            assert!(!old_range.is_empty() || !new_range.is_empty(), "{:?}", ranges);
+            assert!(old_range.start >= at_old && new_range.start >= at_new, "{:?}", ranges);
+            assert!(i == 0 || old_range.start > at_old, "not coalesced: {:?}", ranges);
+            assert_eq!(old[at_old..old_range.start], new[at_new..new_range.start]);
+            (at_old, at_new) = (old_range.end, new_range.end);
+        }
+        assert_eq!(old[at_old..], new[at_new..]);
+    }
+
+    fn lens(ranges: &[(Range<usize>, Range<usize>)]) -> Vec<(usize, usize)> {
+        ranges
+            .iter()
+            .map(|(old, new)| (old.len(), new.len()))
+            .collect()
+    }
+
+    #[test]
+    fn edits_match_a_text_diff() {
+        let text = lines(3000);
+        let end = usize::MAX;
+        // the text is lower case, digits and spaces, so nothing inserted matches what it's
+        // next to
+        let scripts: Vec<Vec<(Range<usize>, &str)>> = vec![
+            vec![],
+            vec![(40_000..40_000, "INSERTED")],
+            vec![(40_000..40_100, "")],
+            vec![(40_000..40_100, "REPLACED")],
+            vec![
+                (0..0, "START"),
+                (30_000..30_050, ""),
+                (60_000..60_010, "MIDDLE"),
+                (end..end, "END"),
+            ],
+            vec![(0..100, ""), (text.len() - 200..end, "")],
+            // edits of what was edited before
+            vec![
+                (20_000..20_000, "ABCDEFGH"),
+                (20_003..20_005, "XY"),
+                (20_001..20_010, ""),
+            ],
+            // differences a byte apart
+            vec![(50_000..50_000, "A"), (50_002..50_003, "B")],
+            vec![(0..end, "")],
+            vec![(0..end, "ALL NEW")],
+        ];
+
+        for script in scripts {
+            let old = rope(&text);
+            let (mut new, mut new_text) = (old.clone(), text.clone());
+            let mut origins: Vec<_> = (0..text.len()).map(Some).collect();
+            let mut typing = SlabAllocator::new();
+            for (range, insert) in &script {
+                let len = new_text.len();
+                let range = range.start.min(len)..range.end.min(len);
+                new = new.delete(range.clone()).unwrap().0;
+                let mut offset = range.start;
+                for slab in write_all(&mut typing, insert.as_bytes()) {
+                    let len = slab.len();
+                    new = new.insert(offset, slab).unwrap();
+                    offset += len;
+                }
+                new_text.splice(range.clone(), insert.bytes());
+                origins.splice(range, insert.bytes().map(|_| None));
+            }
+            assert_eq!(new.to_bstring(), new_text);
+
+            // a deleted byte may be matched to its like just after it, so a difference may start
+            // a little later than the edit did, but it's as long
+            let expected = reference_diff(text.len(), &origins);
+            let diff = old.diff_ranges(&new);
+            assert_describes(&text, &new_text, &diff);
+            assert_eq!(lens(&diff), lens(&expected), "{:?}", script);
+
+            let reverse = new.diff_ranges(&old);
+            assert_describes(&new_text, &text, &reverse);
+            let swapped: Vec<_> = lens(&expected).into_iter().map(|(o, n)| (n, o)).collect();
+            assert_eq!(lens(&reverse), swapped, "{:?}", script);
+
+            // ropes that share nothing come out coarser, but still right
+            let unshared = rope(&new_text);
+            assert_describes(&text, &new_text, &old.diff_ranges(&unshared));
+        }
+    }
+
+    #[test]
+    fn shared_subtrees_are_not_looked_into() {
+        let old = rope(&lines(6000));
+        let leaves = old.chunks(.., 0).count();
+        assert!(leaves > 2000, "{}", leaves);
+
+        let mut same = RopeDiff::new(&old, &old);
+        same.walk();
+        assert!(same.ranges.is_empty());
+        assert_eq!(same.old.visits + same.new.visits, 0);
+
+        let at = old.len() / 2;
+        let (slab, _) = SlabAllocator::new().append(b"INSERTED").unwrap();
+        let new = old.insert(at, slab).unwrap();
+        let mut edited = RopeDiff::new(&old, &new);
+        edited.walk();
+        assert_eq!(edited.ranges, vec![(at..at, at..at + 8)]);
+        let visits = edited.old.visits + edited.new.visits;
+        assert!(visits * 10 < leaves, "{} visits of {} leaves", visits, leaves);
+
+        // the same text with no tree shared has every leaf read
+        let copy = rope(&old.to_bstring());
+        let mut unshared = RopeDiff::new(&old, &copy);
+        unshared.walk();
+        assert!(unshared.ranges.is_empty());
+        assert!(unshared.old.visits >= leaves && unshared.new.visits >= leaves);
+    }
+}
@@ -2,26 +2,71 @@ use std::ops::Bound;
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
-    IndexOutOfBounds(usize, usize),
-    RangeOutOfBounds(Bound<usize>, Bound<usize>, usize),
+    /// `op` was given a byte offset past the end of the rope.
+    IndexOutOfBounds {
+        op: &'static str,
+        offset: usize,
+        len: usize,
+    },
+    /// `op` was given a range that is reversed or reaches past the end of the rope.
+    RangeOutOfBounds {
+        op: &'static str,
+        start: Bound<usize>,
+        end: Bound<usize>,
+        len: usize,
+    },
+    /// A byte offset that falls inside a multi-byte char.
+    NonCharBoundary(usize),
 }
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Error::IndexOutOfBounds { op, offset, len } => {
+                write!(f, "{} at {} beyond rope length {}", op, offset, len)
+            }
+            Error::RangeOutOfBounds { op, start, end, len } => {
+                write!(f, "{} of ", op)?;
+                match start {
+                    Bound::Included(n) => write!(f, "{}", n)?,
+                    Bound::Excluded(n) => write!(f, "{}", n + 1)?,
+                    Bound::Unbounded => {}
+                }
+                match end {
+                    Bound::Included(n) => write!(f, "..={}", n)?,
+                    Bound::Excluded(n) => write!(f, "..{}", n)?,
+                    Bound::Unbounded => write!(f, "..")?,
+                }
+                write!(f, " invalid for rope length {}", len)
+            }
+            Error::NonCharBoundary(offset) => {
+                write!(f, "offset {} is not on a char boundary", offset)
+            }
+        }
     }
 }
 
 impl std::error::Error for Error {}
 
-// impl Error {
-//     pub(super) fn deref_bound<T: Copy>(b: Bound<&T>) -> Bound<T> {
-//         use Bound::*;
-//         match b {
-//             Included(x) => Included(*x),
-//             Excluded(x) => Excluded(*x),
-//             Unbounded => Unbounded,
-//         }
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let err = Error::IndexOutOfBounds { op: "insert", offset: 5120, len: 4096 };
+        assert_eq!(err.to_string(), "insert at 5120 beyond rope length 4096");
+
+        let (start, end) = (Bound::Included(10), Bound::Excluded(33));
+        let err = Error::RangeOutOfBounds { op: "delete", start, end, len: 32 };
+        assert_eq!(err.to_string(), "delete of 10..33 invalid for rope length 32");
+
+        let (start, end) = (Bound::Unbounded, Bound::Included(40));
+        let err = Error::RangeOutOfBounds { op: "replace", start, end, len: 32 };
+        assert_eq!(err.to_string(), "replace of ..=40 invalid for rope length 32");
+
+        assert_eq!(Error::NonCharBoundary(3).to_string(), "offset 3 is not on a char boundary");
+    }
+}
@@ -1,21 +1,33 @@
-use std::ops::{Range, RangeBounds};
-use sumtree::{Colour, Node, SumTree};
-use tore::Point;
+use std::ops::{Bound, Range, RangeBounds};
+use sumtree::{Node, SumTree};
+use tore::{EditSummary, Extent, Point};
 
 #[cfg(test)]
 use bstr::{BString, ByteVec};
 
+pub mod compat;
+mod coords;
+#[cfg(any(test, feature = "counters"))]
+pub mod counters;
 mod cursor;
+mod diff;
 mod error;
+#[cfg(feature = "regex")]
+mod regex;
 mod slab;
 mod util;
+mod validate;
 
 use crate::cursor::SlabCursor;
-use crate::error::{Error, Result};
-use crate::slab::Slab;
+use crate::error::Result;
 
+pub use crate::coords::{char_width, Bias};
 pub use crate::cursor::{CharRange, Chars, ChunkAndRanges, Chunks, Lines};
-pub use crate::slab::SlabAllocator;
+pub use crate::error::Error;
+#[cfg(feature = "regex")]
+pub use crate::regex::RegexMatches;
+pub use crate::slab::{Slab, SlabAllocator};
+pub use crate::validate::SummaryMismatch;
 
 #[derive(Debug, Clone)]
 pub struct Rope(pub(crate) Option<SumTree<Slab>>);
@@ -29,6 +41,11 @@ impl Rope {
         Self(None)
     }
 
+    /// The tree the text is kept in; `None` for an empty rope.
+    pub fn root(&self) -> Option<&SumTree<Slab>> {
+        self.0.as_ref()
+    }
+
     pub fn point_to_offset(&self, p: Point) -> Option<usize> {
         match self.line(p.line) {
             None => None,
@@ -42,30 +59,32 @@ impl Rope {
         }
     }
 
+    /// The point at byte `offset`, the end of the rope included; `None` past it.
     pub fn offset_to_point(&self, offset: usize) -> Option<Point> {
-        self.0.as_ref().and_then(|tree| {
-            let mut cursor = SlabCursor(tree.cursor_with_summary());
-            let pos = cursor.seek_to_byte(offset);
-            pos.map(|pos| match pos.leaf.as_ref() {
-                Node::Branch { .. } => unreachable!("sumtree seek must return leaf node"),
-                Node::Leaf { item, .. } => {
-                    let summary = cursor.summary();
-                    let (line_offset, column_offset) =
-                        item.as_bytes()[..pos.offset]
-                            .iter()
-                            .fold((0, 0), |(ls, cs), b| {
-                                if *b == b'\n' {
-                                    (ls + 1, 0)
-                                } else {
-                                    (ls, cs + 1)
-                                }
-                            });
-                    let line = summary.stats.lines.line + line_offset;
-                    let column = summary.stats.lines.column + column_offset;
-                    Point { line, column }
-                }
-            })
-        })
+        if offset > self.len() {
+            return None;
+        }
+        let Some(tree) = &self.0 else {
+            // an empty rope still has its end, at the start
+            return Some(Point { line: 0, column: 0 });
+        };
+        let mut cursor = SlabCursor(tree.cursor_with_summary());
+        let pos = cursor.seek_to_byte(offset)?;
+        match pos.leaf.as_ref() {
+            Node::Branch { .. } => unreachable!("sumtree seek must return leaf node"),
+            Node::Leaf { item, .. } => {
+                let summary = cursor.summary();
+                // only the leaf is scanned, for its line breaks rather than byte by byte, so a
+                // point on a line megabytes long costs no more than one on a short line
+                let before = &item.as_bytes()[..pos.offset];
+                let line = summary.stats.lines.line + memchr::memchr_iter(b'\n', before).count();
+                let column = match memchr::memrchr(b'\n', before) {
+                    Some(i) => pos.offset - i - 1,
+                    None => summary.stats.lines.column + pos.offset,
+                };
+                Some(Point { line, column })
+            }
+        }
     }
 
     pub fn chunks(&self, range: impl RangeBounds<usize>, offset: usize) -> Chunks {
@@ -98,30 +117,52 @@ impl Rope {
         RopeSlice { rope: self, range, trim_last_terminator: false }
     }
 
+    /// The char starting at `point`, or `None` at or past the end of its line, or when `point`
+    /// is not on a char boundary.
     pub fn char_at(&self, point: Point) -> Option<char> {
-        // use bstr::ByteSlice;
-
-        // self.line(point.line).and_then(|line| {
-        //     let mut column = point.column;
-        //     for chunk in line.chunks(0) {
-        //         for c in chunk.chars() {
-        //             if column == 0 {
-        //                 return Some(c);
-        //             }
-        //             column -= 1; // TODO: width compute
-        //         }
-        //     }
-        //     None
-        // })
-        self.point_to_offset(point).and_then(|offset| {
-            /* abd */
-            self.chars(.., offset).next()
-        })
+        let line = self.line_range(point.line)?;
+        let offset = line.start + point.column;
+        let (c, range) = self.char_range(offset..line.end, 0).next()?;
+        // a point inside a char decodes as a replacement char spanning fewer bytes
+        let on_boundary = range.start == offset && range.len() == c.len_utf8();
+        (on_boundary && c != '\n' && c != '\r').then_some(c)
+    }
+
+    /// The whole slab byte `offset` is in, and the offset it starts at; None past the end.
+    pub(crate) fn chunk_at(&self, offset: usize) -> Option<(&[u8], usize)> {
+        let tree = self.0.as_ref()?;
+        let mut cursor = SlabCursor(tree.cursor());
+        let pos = cursor.seek_to_byte(offset)?;
+        match pos.leaf.as_ref() {
+            Node::Branch { .. } => unreachable!("sumtree seek must return leaf node"),
+            Node::Leaf { item, .. } => Some((item.as_bytes(), offset - pos.offset)),
+        }
+    }
+
+    /// Whether `line`, leaving out its terminator, starts with `prefix`.
+    pub fn line_starts_with(&self, line: usize, prefix: &[u8]) -> bool {
+        self.line(line).is_some_and(|line| line.starts_with(prefix))
+    }
+
+    /// The byte range of `line` including its terminator. Unlike `line`, this includes the text
+    /// after the last line break.
+    fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        let len_lines = self.len_lines();
+        if line < len_lines {
+            return self.line(line).map(|l| l.range);
+        }
+        let start = match len_lines {
+            0 => 0,
+            n => self.line(n - 1)?.range.end,
+        };
+        (line == len_lines && start < self.len()).then(|| start..self.len())
     }
 
+    /// Inserts `text` at byte `offset`. Fails with `Error::IndexOutOfBounds` if `offset` is past
+    /// the end of the rope.
     pub fn insert(&self, offset: usize, text: Slab) -> Result<Self> {
         if offset > self.len() {
-            return Err(Error::IndexOutOfBounds(offset, self.len()));
+            return Err(Error::IndexOutOfBounds { op: "insert", offset, len: self.len() });
         }
         if text.is_empty() {
             return Ok(self.clone());
@@ -132,40 +173,201 @@ impl Rope {
                 let mut offset = offset;
                 let mut cursor = tree.cursor();
                 let leaf = cursor
-                    .seek(|node| {
-                        let summary = node.summary();
-                        let left = summary.left.unwrap_or_default();
-                        if offset < left.len {
+                    // an offset between two leaves lands at the end of the left one, where text
+                    // typed after it can extend it
+                    .seek(|left, _| {
+                        let left = left.stats;
+                        if offset <= left.len {
                             sumtree::cursor::Direction::Left
-                        } else if offset >= left.len {
+                        } else {
                             offset -= left.len;
                             sumtree::cursor::Direction::Right
-                        } else {
-                            unreachable!()
                         }
                     })
                     .unwrap();
                 let pos = cursor.into_position();
                 let summary = leaf.summary();
                 let slab = leaf.deref_item();
+                debug_assert!(offset <= summary.stats.len, "seek left {} past its leaf", offset);
                 let tree = if offset == 0 {
                     pos.insert_left(text)
                 } else if offset == summary.stats.len {
-                    pos.insert_right(text)
+                    // consecutive allocator writes extend the leaf instead of adding another
+                    match slab.merge(&text) {
+                        Some(merged) => pos.replace_item(merged),
+                        None => pos.insert_right(text),
+                    }
                 } else {
-                    let left = SumTree::new_leaf(slab.substr(..offset));
-                    let rl = SumTree::new_leaf(text);
-                    let rr = SumTree::new_leaf(slab.substr(offset..));
-                    let right = SumTree::new_branch(Colour::Red, rl, rr);
-                    pos.replace(left, right)
+                    pos.split_insert(slab.substr(..offset), text, slab.substr(offset..))
                 };
                 Ok(Self(Some(tree)))
             }
         }
     }
 
+    /// Appends `text` to the end of the rope. Never fails.
     pub fn append(&self, text: Slab) -> Result<Self> {
-        self.insert(self.len(), text)
+        if text.is_empty() {
+            return Ok(self.clone());
+        }
+        match &self.0 {
+            None => Ok(Self(Some(SumTree::new_leaf(text)))),
+            Some(tree) => {
+                let mut cursor = tree.cursor();
+                let leaf = cursor
+                    .seek(|_, _| sumtree::cursor::Direction::Right)
+                    .unwrap();
+                let pos = cursor.into_position();
+                // consecutive allocator writes extend the last leaf instead of adding another
+                let tree = match leaf.deref_item().merge(&text) {
+                    Some(merged) => pos.replace_item(merged),
+                    None => pos.insert_right(text),
+                };
+                Ok(Self(Some(tree)))
+            }
+        }
+    }
+
+    /// Appends a batch of slabs at once: the batch is built into a balanced subtree and joined
+    /// onto the rope, rather than rebalancing on every slab. Never fails.
+    pub fn append_many(&self, slabs: impl IntoIterator<Item = Slab>) -> Result<Self> {
+        let batch = slabs.into_iter().filter(|slab| !slab.is_empty()).fold(
+            Vec::<Slab>::new(),
+            |mut batch, slab| {
+                match batch.last().and_then(|last| last.merge(&slab)) {
+                    Some(merged) => *batch.last_mut().unwrap() = merged,
+                    None => batch.push(slab),
+                }
+                batch
+            },
+        );
+        let mut batch = batch.into_iter();
+        let rope = match batch.next() {
+            None => return Ok(self.clone()),
+            Some(first) => self.append(first)?,
+        };
+        match (rope.0, SumTree::from_items(batch)) {
+            (Some(tree), Some(tail)) => Ok(Self(Some(SumTree::join(tree, tail)))),
+            (tree, _) => Ok(Self(tree)),
+        }
+    }
+
+    /// Splits the rope into `..at` and `at..`. Fails with `Error::IndexOutOfBounds` if `at` is past
+    /// the end of the rope.
+    pub fn split(&self, at: usize) -> Result<(Self, Self)> {
+        if at > self.len() {
+            return Err(Error::IndexOutOfBounds { op: "split", offset: at, len: self.len() });
+        }
+        Ok(self.split_unchecked(at))
+    }
+
+    /// The text in `range` as a rope of its own, which can outlive this one or be sent to another
+    /// thread. It shares slabs with this rope: only the slabs at either end are re-sliced, so no
+    /// bytes are copied and it takes O(log n). Fails with `Error::RangeOutOfBounds` if `range` is
+    /// reversed or reaches past the end of the rope.
+    pub fn sub_rope(&self, range: impl RangeBounds<usize>) -> Result<Self> {
+        let range = self.checked_range("sub_rope", range)?;
+        let (_, rest) = self.split_unchecked(range.start);
+        let (sub, _) = rest.split_unchecked(range.len());
+        Ok(sub)
+    }
+
+    /// A rope of `text`, written to `allocator`; for tests and small ropes built in code.
+    pub fn from_str(text: &str, allocator: &mut SlabAllocator) -> Self {
+        let mut bytes = text.as_bytes();
+        let mut slabs = vec![];
+        while !bytes.is_empty() {
+            let (slab, written) = allocator
+                .append(bytes)
+                .expect("writing to a slab block never fails");
+            bytes = &bytes[written..];
+            slabs.push(slab);
+        }
+        Self::empty()
+            .append_many(slabs)
+            .expect("appending never fails")
+    }
+
+    /// Removes `range`, returning the updated rope, the removed text, and a summary of the edit
+    /// taken from the tree summaries of the split. Fails with `Error::RangeOutOfBounds` if
+    /// `range` is reversed or reaches past the end of the rope.
+    pub fn delete(&self, range: impl RangeBounds<usize>) -> Result<(Self, Self, EditSummary)> {
+        let range = self.checked_range("delete", range)?;
+        Ok(self.delete_unchecked(range))
+    }
+
+    /// Replaces `range` with `text`, returning the updated rope, the removed text, and a summary
+    /// of the edit. Fails with `Error::RangeOutOfBounds` if `range` is reversed or reaches past
+    /// the end of the rope.
+    pub fn replace(
+        &self,
+        range: impl RangeBounds<usize>,
+        text: Slab,
+    ) -> Result<(Self, Self, EditSummary)> {
+        let range = self.checked_range("replace", range)?;
+        let (updated, deleted, mut edit) = self.delete_unchecked(range);
+        edit.inserted = sumtree::Item::summary(&text).stats.into();
+        let updated = updated.insert(edit.start_offset, text)?;
+        Ok((updated, deleted, edit))
+    }
+
+    /// Offsets here come from ranges already checked against the rope, so a bad one is a bug.
+    #[track_caller]
+    fn split_unchecked(&self, at: usize) -> (Self, Self) {
+        debug_assert!(at <= self.len(), "split at {} beyond rope length {}", at, self.len());
+        match &self.0 {
+            None => (Self::empty(), Self::empty()),
+            Some(tree) => {
+                let (left, right) = split_tree(tree, at);
+                (Self(left), Self(right))
+            }
+        }
+    }
+
+    #[track_caller]
+    fn delete_unchecked(&self, range: Range<usize>) -> (Self, Self, EditSummary) {
+        let (left, rest) = self.split_unchecked(range.start);
+        let (deleted, right) = rest.split_unchecked(range.len());
+        let edit = EditSummary {
+            start_offset: range.start,
+            start_point: left.stats().lines,
+            removed: deleted.stats().into(),
+            inserted: Extent::default(),
+        };
+        (left.concat(right), deleted, edit)
+    }
+
+    fn concat(self, other: Self) -> Self {
+        Self(join(self.0, other.0))
+    }
+
+    fn stats(&self) -> Stats {
+        self.0
+            .as_ref()
+            .map(|tree| tree.summary().stats)
+            .unwrap_or_default()
+    }
+
+    fn checked_range(
+        &self,
+        op: &'static str,
+        range: impl RangeBounds<usize>,
+    ) -> Result<Range<usize>> {
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => self.len(),
+        };
+        if start > end || end > self.len() {
+            let (start, end) = (range.start_bound().cloned(), range.end_bound().cloned());
+            return Err(Error::RangeOutOfBounds { op, start, end, len: self.len() });
+        }
+        Ok(start..end)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -192,23 +394,32 @@ impl Rope {
             None => b"".into(),
             Some(tree) => match tree.as_ref() {
                 Node::Leaf { item, .. } => item.as_bytes().into(),
-                Node::Branch { left, right, .. } => {
-                    let mut bstr = Rope::new(left.clone()).to_bstring();
-                    bstr.push_str(Rope::new(right.clone()).to_bstring());
+                Node::Branch { .. } => {
+                    let mut bstr = BString::from("");
+                    for child in tree.children() {
+                        bstr.push_str(Rope::new(child.clone()).to_bstring());
+                    }
                     bstr
                 }
             },
         }
     }
 
-    #[cfg(test)]
-    pub(crate) fn is_balanced(&self) -> bool {
+    /// Whether the tree keeps its balance invariants, red-black or B-tree as the `wide` feature
+    /// has it; for tests and fuzzing.
+    pub fn is_balanced(&self) -> bool {
         match self.0 {
             None => true,
             Some(ref tree) => tree.is_balanced(),
         }
     }
 
+    /// The most branches on the way down to a leaf, which every seek descends through; for
+    /// benchmarks.
+    pub fn depth(&self) -> usize {
+        self.0.as_ref().map_or(0, |tree| tree.depth())
+    }
+
     #[cfg(test)]
     pub(crate) fn write_dot(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
         match self.0 {
@@ -218,6 +429,53 @@ impl Rope {
     }
 }
 
+#[track_caller]
+fn split_tree(tree: &SumTree<Slab>, at: usize) -> (Option<SumTree<Slab>>, Option<SumTree<Slab>>) {
+    let len = tree.summary().stats.len;
+    debug_assert!(at <= len, "split at {} beyond subtree length {}", at, len);
+    if at == 0 {
+        return (None, Some(tree.clone()));
+    } else if at == len {
+        return (Some(tree.clone()), None);
+    }
+    match tree.as_ref() {
+        Node::Leaf { item, .. } => {
+            let left = SumTree::new_leaf(item.substr(..at));
+            let right = SumTree::new_leaf(item.substr(at..));
+            (Some(left), Some(right))
+        }
+        Node::Branch { .. } => {
+            // the children before the one split join its left part, and those after its right
+            let (mut before, mut at) = (None, at);
+            let mut children = tree.children();
+            while let Some(child) = children.next() {
+                let child_len = child.summary().stats.len;
+                if at <= child_len {
+                    let (left, right) = split_tree(child, at);
+                    let after = children.fold(right, |after, next| join(after, Some(next.clone())));
+                    return (join(before, left), after);
+                }
+                before = join(before, Some(child.clone()));
+                at -= child_len;
+            }
+            unreachable!("split beyond the branch's children")
+        }
+    }
+}
+
+fn join(left: Option<SumTree<Slab>>, right: Option<SumTree<Slab>>) -> Option<SumTree<Slab>> {
+    match (left, right) {
+        (Some(left), Some(right)) => Some(SumTree::join(left, right)),
+        (left, right) => left.or(right),
+    }
+}
+
+impl From<Stats> for Extent {
+    fn from(stats: Stats) -> Self {
+        Extent { len: stats.len, lines: stats.lines }
+    }
+}
+
 pub struct RopeSlice<'a> {
     rope: &'a Rope,
     range: Range<usize>,
@@ -256,19 +514,71 @@ impl<'a> RopeSlice<'a> {
             Chunks::new(self.rope, self.range.clone(), offset)
         }
     }
+
+    /// Whether the slice starts with `prefix`, which may run across any number of chunks.
+    pub fn starts_with(&self, prefix: &[u8]) -> bool {
+        self.matches_at(0, prefix)
+    }
+
+    /// Whether the slice ends with `suffix`. A line's slice ends before its terminator.
+    pub fn ends_with(&self, suffix: &[u8]) -> bool {
+        let len = self.len_without_terminator();
+        len.checked_sub(suffix.len())
+            .is_some_and(|offset| self.matches_at(offset, suffix))
+    }
+
+    /// The number of bytes of spaces and tabs the slice starts with.
+    pub fn leading_whitespace_len(&self) -> usize {
+        let mut len = 0;
+        for chunk in self.chunks(0) {
+            let blank = chunk
+                .iter()
+                .take_while(|b| matches!(b, b' ' | b'\t'))
+                .count();
+            len += blank;
+            if blank < chunk.len() {
+                break;
+            }
+        }
+        len
+    }
+
+    /// Whether the slice holds `bytes` from `offset` on, comparing them a chunk at a time.
+    fn matches_at(&self, offset: usize, mut bytes: &[u8]) -> bool {
+        for chunk in self.chunks(offset) {
+            if bytes.is_empty() {
+                break;
+            }
+            let len = chunk.len().min(bytes.len());
+            if chunk[..len] != bytes[..len] {
+                return false;
+            }
+            bytes = &bytes[len..];
+        }
+        bytes.is_empty()
+    }
+
+    /// The length of what the slice's chunks cover, which for a line's slice leaves out its
+    /// terminator.
+    fn len_without_terminator(&self) -> usize {
+        if !self.trim_last_terminator {
+            return self.len();
+        }
+        cursor::without_terminator(self.rope, self.range.clone()).len()
+    }
 }
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
 pub struct Stats {
     pub len: usize,
+    pub chars: usize,
     pub lines: Point,
     pub len_first_line: usize,
     pub len_last_line: usize,
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
 pub struct Metrics {
     pub stats: Stats,
-    pub left: Option<Stats>,
 }
 
 impl std::fmt::Debug for Metrics {
@@ -292,25 +602,26 @@ impl sumtree::Item for Slab {
     fn summary(&self) -> Self::Summary {
         let bs = self.as_bytes();
         let len = bs.len();
-        let (len_lines, len_last_line) =
-            bs.iter()
-                .fold((vec![], 0_usize), |(counts, last_count), ch| {
-                    if *ch == b'\n' {
-                        ([counts, [last_count].to_vec()].concat(), 0)
-                    } else {
-                        (counts, last_count + 1)
-                    }
-                });
-        let len_first_line = *len_lines.first().unwrap_or(&0);
-        let lines = Point { line: len_lines.len(), column: len_last_line };
-        let stats = Stats { len, lines, len_first_line, len_last_line };
-        Metrics { stats, left: None }
+        let chars = bytecount::num_chars(bs);
+        let line = memchr::memchr_iter(b'\n', bs).count();
+        let len_first_line = memchr::memchr(b'\n', bs).unwrap_or(0);
+        let len_last_line = memchr::memrchr(b'\n', bs).map_or(len, |i| len - i - 1);
+        let lines = Point { line, column: len_last_line };
+        let stats = Stats { len, chars, lines, len_first_line, len_last_line };
+        Metrics { stats }
+    }
+
+    fn preview(&self, max_len: usize) -> String {
+        use bstr::ByteSlice;
+        let text = self.as_bytes().to_str_lossy();
+        format!("Slab({}/{})", sumtree::truncate(&text, max_len), self.len())
     }
 }
 
 impl sumtree::Summary for Metrics {
     fn combine(&self, rhs: &Self) -> Self {
         let len = self.stats.len + rhs.stats.len;
+        let chars = self.stats.chars + rhs.stats.chars;
         let (len_first_line, lines, len_last_line) = if rhs.stats.lines.line == 0 {
             let line = self.stats.lines.line;
             let column = self.stats.lines.column + rhs.stats.lines.column;
@@ -326,36 +637,8 @@ impl sumtree::Summary for Metrics {
             let len_last_line = rhs.stats.len_last_line;
             (len_first_line, lines, len_last_line)
         };
-        let stats = Stats { len, lines, len_first_line, len_last_line };
-        Metrics { stats, left: Some(self.stats) }
-    }
-
-    fn scan_branch(&mut self, from: &Self) {
-        let left = from.left.expect("branch must have left stats");
-        self.stats.len += left.len;
-        if left.lines.line == 0 {
-            self.stats.lines.column += left.lines.column;
-            self.stats.len_first_line += left.len_first_line;
-            self.stats.len_last_line += left.len_last_line;
-        } else {
-            self.stats.lines.line += left.lines.line;
-            self.stats.lines.column = left.len_last_line;
-            self.stats.len_first_line += left.len_first_line;
-        };
-    }
-
-    fn scan_leaf(&mut self, from: &Self) {
-        let left = from.stats;
-        self.stats.len += left.len;
-        if left.lines.line == 0 {
-            self.stats.lines.column += left.lines.column;
-            self.stats.len_first_line += left.len_first_line;
-            self.stats.len_last_line += left.len_last_line;
-        } else {
-            self.stats.lines.line += left.lines.line;
-            self.stats.lines.column = left.len_last_line;
-            self.stats.len_first_line += left.len_first_line;
-        };
+        let stats = Stats { len, chars, lines, len_first_line, len_last_line };
+        Metrics { stats }
     }
 }
 
@@ -363,13 +646,12 @@ impl sumtree::Summary for Metrics {
 mod tests {
     use super::*;
 
+    use crate::slab::{BULK_BLOCK_CAPACITY, EDIT_BLOCK_CAPACITY};
     use bstr::ByteSlice;
-    use circular_buffer::CircularBuffer;
+    use std::collections::VecDeque;
 
     #[test]
     fn basic_tests() {
-        let _ = std::fs::remove_dir_all("target/tests/");
-        std::fs::create_dir_all("target/tests/").expect("create directory");
         let parts = vec![
             (0, "Some "),
             (5, "people "),
@@ -407,13 +689,7 @@ mod tests {
             (172, "us"),
             (186, "\n"),
         ];
-        let contents: BString = "This is the song that never ends.\n\
-                 It just goes 'round and 'round, my friends.\n\
-                 Some people started singing it\n\
-                 not knowing what it was;\n\
-                 and they continue singing it forever just because...\n\
-             "
-        .into();
+        let contents: BString = test_support::SONG.into();
         let mut lines: Vec<_> = contents.lines().collect();
         lines.push("".as_bytes());
 
@@ -426,23 +702,24 @@ mod tests {
             assert_eq!(w, p.len());
             rope = rope.insert(*at, block).unwrap();
 
-            let mut file = std::fs::File::create(format!("target/tests/insert{:02}.dot", i))
-                .expect("create file");
-            rope.write_dot(&mut file).expect("write dot file");
+            test_support::dump(&format!("insert{:02}.dot", i), |w| rope.write_dot(w));
 
             assert!(rope.is_balanced());
         }
         assert!(rope.is_balanced());
         assert_eq!(rope.to_bstring(), contents);
+        // the same script builds the same text the reference way
+        assert_eq!(test_support::rope_from_insert_script(&parts), test_support::SONG);
 
         let mut chars = rope.char_range(.., 0);
-        let mut lookback = CircularBuffer::<2, _>::new();
+        let mut lookback = VecDeque::new();
         for (start, end, c) in contents.char_indices() {
             assert_eq!(chars.next(), Some((c, start..end)));
             assert_eq!(chars.offset(), end);
             lookback.push_front((c, start..end));
+            lookback.truncate(2);
 
-            assert_eq!(chars.prev().as_ref(), lookback.get(0));
+            assert_eq!(chars.prev().as_ref(), lookback.front());
             if start > 1 {
                 assert_eq!(chars.prev().as_ref(), lookback.get(1));
                 _ = chars.next();
@@ -451,7 +728,7 @@ mod tests {
             assert_eq!(chars.next(), Some((c, start..end)));
         }
         assert_eq!(chars.next(), None);
-        assert_eq!(chars.prev().as_ref(), lookback.get(0));
+        assert_eq!(chars.prev().as_ref(), lookback.front());
         assert_eq!(chars.prev().as_ref(), lookback.get(1));
 
         let line_offsets = [0, 34, 78, 109, 134, 187];
@@ -500,9 +777,11 @@ mod tests {
         let parts = vec![
             "This ", "is", " the", " ", "song ", "that", " never ", "ends.\n",
             "It ", "just ", "goes ", "'round ", "and", " 'round", ", my", " ", "fr", "i", "ends.\n",
-            "Some ", "people ", "started ", "singing ", "it\n",
+            // "people " was written right after "Some " and inserted right after it, so it
+            // extends that leaf
+            "Some people ", "started ", "singing ", "it\n",
             "not ", "knowing ", "what", " it", " was;\n",
-            "and ", "they", " ", "continue", " singing", " ", "i", "t", " ", "forever", " j", "us", "t ", "because...", "\n",
+            "and they", " ", "continue", " singing", " ", "i", "t", " ", "forever", " j", "us", "t ", "because...", "\n",
         ];
         for (i, actual) in rope.chunks(.., 0).enumerate() {
             let expected = parts.get(i).unwrap_or(&"");
@@ -533,98 +812,594 @@ mod tests {
         }
         assert_eq!(char_indicies.next(), None);
     }
-}
 
-// #[cfg(test)]
-// mod tests {
-//     use bstr::{BString, ByteSlice};
-
-//     use super::slab::SlabAllocator;
-//     use super::Rope;
-
-//     #[test]
-//     fn basic_tests() {
-//         for at in 0..rope.len() {
-//             let (split_left, split_right) = rope.split(at).expect("split rope");
-
-//             // let mut file = std::fs::File::create(format!("target/tests/split_left{:02}.dot", at))
-//             //     .expect("create file");
-//             // split_left.write_dot(&mut file).expect("write dot file");
-//             // let mut file = std::fs::File::create(format!("target/tests/split_right{:02}.dot", at))
-//             //     .expect("create file");
-//             // split_right.write_dot(&mut file).expect("write dot file");
-
-//             assert_eq!(split_left.to_bstring(), contents[..at].as_bstr());
-//             assert_eq!(split_right.to_bstring(), contents[at..].as_bstr());
-
-//             assert!(split_left.is_balanced(), "unbalanced left; split at {}", at);
-//             assert!(split_right.is_balanced(), "unbalaced right; split at {}", at);
-//         }
-
-//         // delete from start of rope
-//         (1..=rope.len()).fold(rope.clone(), |rope, i| {
-//             let (updated, deleted) = rope.delete(0..1).expect("delete rope");
-
-//             // let mut file =
-//             //     std::fs::File::create(format!("target/tests/delete_updated{:02}.dot", i))
-//             //         .expect("create file");
-//             // updated.write_dot(&mut file).expect("write dot file");
-//             // let mut file =
-//             //     std::fs::File::create(format!("target/tests/delete_deleted{:02}.dot", i))
-//             //         .expect("create file");
-//             // deleted.write_dot(&mut file).expect("write dot file");
-
-//             assert_eq!(updated.to_bstring(), contents[i..].as_bstr());
-//             assert_eq!(deleted.to_bstring(), [contents[i - 1]].as_bstr());
-//             assert!(updated.is_balanced());
-//             assert!(deleted.is_balanced());
-//             updated
-//         });
-
-//         // delete from end of string
-//         (1..=rope.len()).fold(rope.clone(), |rope, i| {
-//             let (updated, deleted) = rope.delete(rope.len() - 1..).expect("delete rope");
-
-//             // let mut file =
-//             //     std::fs::File::create(format!("target/tests/delete_updated{:02}.dot", i))
-//             //         .expect("create file");
-//             // updated.write_dot(&mut file).expect("write dot file");
-//             // let mut file =
-//             //     std::fs::File::create(format!("target/tests/delete_deleted{:02}.dot", i))
-//             //         .expect("create file");
-//             // deleted.write_dot(&mut file).expect("write dot file");
-
-//             assert_eq!(updated.to_bstring(), contents[..(rope.len() - 1)].as_bstr());
-//             assert_eq!(deleted.to_bstring(), [contents[rope.len() - 1]].as_bstr());
-//             assert!(updated.is_balanced(), "unbalanced left node; delete end {}", i);
-//             assert!(deleted.is_balanced(), "unbalanced right node; delete end {}", i);
-//             updated
-//         });
-
-//         // delete from middle of string
-//         (1..=rope.len()).fold(rope.clone(), |rope, i| {
-//             let middle = rope.len() / 2;
-//             let (updated, deleted) = rope.delete(middle..middle + 1).expect("delete rope");
-
-//             // let mut file =
-//             //     std::fs::File::create(format!("target/tests/delete_updated{:02}.dot", i))
-//             //         .expect("create file");
-//             // updated.write_dot(&mut file).expect("write dot file");
-//             // let mut file =
-//             //     std::fs::File::create(format!("target/tests/delete_deleted{:02}.dot", i))
-//             //         .expect("create file");
-//             // deleted.write_dot(&mut file).expect("write dot file");
-
-//             let updated_str = updated.to_bstring();
-//             assert_eq!(updated_str[..middle].as_bstr(), contents[..middle].as_bstr());
-//             assert_eq!(updated_str[middle..].as_bstr(), contents[(middle + i)..].as_bstr());
-//             // assert_eq!(
-//             //     deleted.to_string(),
-//             //     String::from_utf8(vec![contents.as_bytes()[middle]]).expect("utf8 string")
-//             // );
-//             assert!(updated.is_balanced(), "unbalanced left node; delete middle {}", i);
-//             assert!(deleted.is_balanced(), "unbalanced right node; delete middle {}", i);
-//             updated
-//         });
-//     }
-// }
+    fn log_lines(count: usize) -> BString {
+        use std::io::Write;
+        let mut lines = BString::default();
+        for i in 0..count {
+            writeln!(lines, "{} request handled in {}ms", i, i % 97).unwrap();
+        }
+        lines
+    }
+
+    fn write_all(buffer: &mut SlabAllocator, mut bytes: &[u8]) -> Vec<Slab> {
+        let mut slabs = vec![];
+        while !bytes.is_empty() {
+            let (slab, written) = buffer.append(bytes).unwrap();
+            bytes = &bytes[written..];
+            slabs.push(slab);
+        }
+        slabs
+    }
+
+    #[test]
+    fn append_merges_adjacent_slabs() {
+        let contents = log_lines(2000);
+        let mut buffer = SlabAllocator::new();
+        let mut rope = Rope::empty();
+        for line in contents.lines_with_terminator() {
+            for slab in write_all(&mut buffer, line) {
+                rope = rope.append(slab).unwrap();
+            }
+        }
+        let blocks = contents.len().div_ceil(EDIT_BLOCK_CAPACITY);
+        assert!(rope.is_balanced());
+        assert_eq!(rope.to_bstring(), contents);
+        assert_eq!(rope.chunks(.., 0).count(), blocks);
+
+        // slabs from another allocator are never adjacent
+        let mut other = SlabAllocator::new();
+        let (slab, _) = other.append(b"tail").unwrap();
+        let rope = rope.append(slab).unwrap();
+        assert_eq!(rope.chunks(.., 0).count(), blocks + 1);
+    }
+
+    #[test]
+    fn append_many() {
+        let contents = log_lines(2000);
+        let mut buffer = SlabAllocator::new();
+        let slabs: Vec<_> = contents
+            .lines_with_terminator()
+            .flat_map(|line| write_all(&mut buffer, line))
+            .collect();
+        let blocks = contents.len().div_ceil(EDIT_BLOCK_CAPACITY);
+
+        let rope = Rope::empty().append_many(slabs.clone()).unwrap();
+        assert!(rope.is_balanced());
+        assert_eq!(rope.to_bstring(), contents);
+        assert_eq!(rope.chunks(.., 0).count(), blocks);
+
+        // batches joined onto existing ropes of every size stay balanced
+        let mut other = SlabAllocator::new();
+        for split in [1, 7, 100, slabs.len() / 2, slabs.len() - 1] {
+            let (head, tail) = slabs.split_at(split);
+            let mut rope = Rope::empty();
+            for copy in head
+                .iter()
+                .flat_map(|slab| write_all(&mut other, slab.as_bytes()))
+            {
+                rope = rope.insert(rope.len(), copy).unwrap();
+            }
+            let rope = rope.append_many(tail.iter().cloned()).unwrap();
+            assert!(rope.is_balanced(), "unbalanced after split at {}", split);
+            assert_eq!(rope.to_bstring(), contents, "split at {}", split);
+        }
+
+        let empty = Rope::empty().append_many(std::iter::empty()).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn typing_extends_leaves() {
+        let mut contents = log_lines(200);
+        let mut file = SlabAllocator::new();
+        let rope = Rope::empty()
+            .append_many(write_all(&mut file, &contents))
+            .unwrap();
+        let leaves = rope.chunks(.., 0).count();
+
+        // keystrokes are allocated one at a time, each right after the last
+        let mut typing = SlabAllocator::new();
+        let keystrokes: usize = 10_000;
+        let mut at = contents.len() / 2;
+        let rope = (0..keystrokes).fold(rope, |rope, i| {
+            let key = [b'a' + (i % 26) as u8];
+            let (slab, _) = typing.append(&key).unwrap();
+            contents.insert(at, key[0]);
+            let rope = rope.insert(at, slab).unwrap();
+            at += 1;
+            rope
+        });
+        assert!(rope.is_balanced());
+        assert_eq!(rope.to_bstring(), contents);
+        // the split leaf, and a leaf for each block the typing filled
+        let typed_blocks = keystrokes.div_ceil(EDIT_BLOCK_CAPACITY);
+        assert_eq!(rope.chunks(.., 0).count(), leaves + 1 + typed_blocks);
+    }
+
+    #[test]
+    fn char_range_across_leaves() {
+        let fixtures: [&[&[u8]]; 5] = [
+            // multi-byte chars split one byte at a time
+            &[b"a\xe6", b"\x97", b"\xa5b", b"\xf0\x9f", b"\xa6\x80"],
+            &[b"\xf0", b"\x9f", b"\xa6", b"\x80", b"\xc3", b"\xa9"],
+            // truncated sequences, cut off by more text, another lead byte, or the end
+            &[b"a\xe6\x97", b"b", b"\xf0\x9f", b"\xe6\x97\xa5", b"\xe6"],
+            // lone continuation bytes, overlong and surrogate encodings
+            &[b"\x80a\xc0", b"\xafb\xed\xa0", b"\x80\xbf"],
+            &[b"\xff", b"\xc3", b"\xff\xa9", b"\xe6\x97\xa5"],
+        ];
+        for parts in fixtures {
+            // a fresh allocator for every part so none of them merge into one leaf
+            let rope = parts.iter().fold(Rope::empty(), |rope, part| {
+                let (slab, _) = SlabAllocator::new().append(part).unwrap();
+                rope.append(slab).unwrap()
+            });
+            assert_eq!(rope.chunks(.., 0).count(), parts.len());
+
+            let contents = parts.concat();
+            let expected: Vec<_> = contents
+                .char_indices()
+                .map(|(start, end, c)| (c, start..end))
+                .collect();
+            let mut chars = rope.char_range(.., 0);
+            let mut actual = vec![];
+            while let Some((c, range)) = chars.next() {
+                assert_eq!(chars.offset(), range.end, "{:?}", contents.as_bstr());
+                actual.push((c, range));
+            }
+            assert_eq!(actual, expected, "{:?}", contents.as_bstr());
+
+            // and stepping back replays what was just read
+            let mut chars = rope.char_range(.., 0);
+            for expected in expected.windows(2) {
+                chars.next();
+                assert_eq!(chars.next().as_ref(), Some(&expected[1]));
+                assert_eq!(chars.prev().as_ref(), Some(&expected[1]));
+                assert_eq!(chars.prev().as_ref(), Some(&expected[0]));
+                chars.next();
+            }
+        }
+    }
+
+    #[test]
+    fn prefixes_across_leaves() {
+        // a fresh allocator for every part so none of them merge into one leaf
+        let parts: [&[u8]; 5] = [b"  \t", b" fn", b" main() {\n    // x", b"\r\n", b"}"];
+        let rope = parts.iter().fold(Rope::empty(), |rope, part| {
+            let (slab, _) = SlabAllocator::new().append(part).unwrap();
+            rope.append(slab).unwrap()
+        });
+        assert_eq!(rope.chunks(.., 0).count(), parts.len());
+
+        let line = rope.line(0).unwrap();
+        assert!(line.starts_with(b""));
+        assert!(line.starts_with(b"  \t fn m"));
+        assert!(!line.starts_with(b"  \t fm"));
+        assert!(!line.starts_with(b"  \t fn main() {\n"));
+        assert!(line.ends_with(b"n() {"));
+        assert!(!line.ends_with(b"{\n"));
+        assert_eq!(line.leading_whitespace_len(), 4);
+
+        // the line starts partway into a leaf and ends partway into the next
+        let line = rope.line(1).unwrap();
+        assert!(line.starts_with(b"    // x"));
+        assert!(line.ends_with(b"// x"));
+        assert!(!line.ends_with(b"x\r"));
+        assert_eq!(line.leading_whitespace_len(), 4);
+        assert!(rope.line_starts_with(1, b"    //"));
+        assert!(!rope.line_starts_with(2, b"//"));
+        assert!(!rope.line_starts_with(3, b""));
+
+        let all = rope.slice(..);
+        assert!(all.starts_with(&parts[..3].concat()));
+        assert!(all.ends_with(b"x\r\n}"));
+        assert!(!all.ends_with(b"  \t fn main() {\n    // x\r\n}!"));
+        assert_eq!(rope.slice(3..).leading_whitespace_len(), 1);
+        assert_eq!(Rope::empty().slice(..).leading_whitespace_len(), 0);
+    }
+
+    #[test]
+    fn size_hints_bound_counts() {
+        let contents = log_lines(2000);
+        let (head, tail) = contents.split_at(contents.len() / 2);
+        // a leaf for every line of the head, and a block's worth each for the tail
+        let rope = head
+            .lines_with_terminator()
+            .flat_map(|line| write_all(&mut SlabAllocator::new(), line))
+            .chain(write_all(&mut SlabAllocator::new(), tail))
+            .fold(Rope::empty(), |rope, slab| rope.append(slab).unwrap());
+        assert_eq!(rope.to_bstring(), contents);
+
+        fn assert_hints<I: Iterator>(mut iter: I, context: &str) {
+            let mut hints = vec![iter.size_hint()];
+            while iter.next().is_some() {
+                hints.push(iter.size_hint());
+            }
+            assert!(iter.next().is_none(), "{}", context);
+            let count = hints.len() - 1;
+            for (i, (lower, upper)) in hints.into_iter().enumerate() {
+                let remaining = count - i;
+                assert!(lower <= remaining, "{} lower {} > {}", context, lower, remaining);
+                let upper = upper.expect("bounded above");
+                assert!(remaining <= upper, "{} upper {} < {}", context, upper, remaining);
+            }
+        }
+
+        let mut seed = 0x2545f4914f6cdd1du64;
+        let mut random = |bound: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % bound as u64) as usize
+        };
+        for _ in 0..200 {
+            let start = random(rope.len() + 1);
+            let end = start + random(rope.len() - start + 1);
+            let context = format!("bytes {}..{}", start, end);
+            assert_hints(rope.chunks(start..end, 0), &context);
+            assert_hints(rope.chunks(start..end, random(end - start + 1)), &context);
+            assert_hints(rope.slice(start..end).chunk_and_ranges(0), &context);
+
+            let start = random(rope.len_lines() + 1);
+            let end = start + random(rope.len_lines() - start + 1);
+            let mut lines = rope.lines(start..end);
+            for remaining in (0..=(end - start)).rev() {
+                assert_eq!(lines.len(), remaining, "lines {}..{}", start, end);
+                let Some(line) = lines.next() else {
+                    break;
+                };
+                assert_hints(line.chunks(0), &format!("line {}", end - remaining));
+            }
+            assert!(lines.next().is_none());
+        }
+    }
+
+    #[test]
+    fn nth_skips_chunks() {
+        let line = "aé日🦀".repeat(25_000);
+        let contents = format!("{}\n", line);
+        let rope = Rope::empty()
+            .append_many(write_all(&mut SlabAllocator::new(), contents.as_bytes()))
+            .unwrap();
+        let leaves = rope.chunks(.., 0).count();
+        let expected: Vec<_> = contents
+            .char_indices()
+            .map(|(i, c)| (c, i..i + c.len_utf8()))
+            .collect();
+        assert_eq!(expected.len(), 100_001);
+
+        for column in [0, 1, 2, 3, 4, 1637, 4097, 50_000, 99_999, 100_000, 100_001] {
+            let mut chars = rope.char_range(.., 0);
+            let (found, counted) = counters::count(|| chars.nth(column));
+            assert_eq!(found, expected.get(column).cloned(), "{}", column);
+            // only the char returned and chars split across leaves are decoded one at a time
+            assert!(counted.steps <= leaves + 1, "{} steps to {}", counted.steps, column);
+            let Some((_, range)) = expected.get(column) else {
+                assert_eq!(chars.next(), None);
+                continue;
+            };
+            assert_eq!(chars.offset(), range.end, "{}", column);
+            // the chars skipped can be stepped back over as if they'd been read
+            if column > 0 {
+                assert_eq!(chars.prev().as_ref(), expected.get(column), "{}", column);
+                assert_eq!(chars.prev().as_ref(), expected.get(column - 1), "{}", column);
+                chars.next();
+                chars.next();
+            }
+            assert_eq!(chars.next(), expected.get(column + 1).cloned(), "{}", column);
+
+            let c = rope.chars(.., 0).nth(column);
+            assert_eq!(c, expected.get(column).map(|(c, _)| *c), "{}", column);
+        }
+
+        // skipping resumes from wherever the chars were left
+        let mut chars = rope.chars(1.., 0);
+        assert_eq!(chars.next(), Some('é'));
+        assert_eq!(chars.nth(4), Some('日'));
+        assert_eq!(chars.nth(99_992), Some('🦀'));
+        assert_eq!(chars.next(), Some('\n'));
+        assert_eq!(chars.nth(3), None);
+        assert_eq!(chars.next(), None);
+    }
+
+    fn naive_point(text: &[u8]) -> Point {
+        let line = text.iter().filter(|&&b| b == b'\n').count();
+        let column = text.len() - text.rfind_byte(b'\n').map_or(0, |i| i + 1);
+        Point { line, column }
+    }
+
+    #[test]
+    fn delete_summary() {
+        let contents = log_lines(200);
+        let mut buffer = SlabAllocator::new();
+        let rope = contents
+            .lines_with_terminator()
+            .flat_map(|line| write_all(&mut buffer, line))
+            .fold(Rope::empty(), |rope, slab| rope.insert(rope.len(), slab).unwrap());
+
+        let second = contents.find_byte(b'\n').unwrap() + 1;
+        let ranges = [
+            (3..9, 0),
+            (second - 4..second + 2, 1),
+            (second..second, 0),
+            (100..3000, contents[100..3000].find_iter("\n").count()),
+            (0..contents.len(), 200),
+        ];
+        for (range, newlines) in ranges {
+            let (updated, deleted, edit) = rope.delete(range.clone()).unwrap();
+            let removed = &contents[range.clone()];
+            assert!(updated.is_balanced(), "{:?}", range);
+            assert!(deleted.is_balanced(), "{:?}", range);
+            assert_eq!(deleted.to_bstring(), removed);
+            assert_eq!(
+                updated.to_bstring(),
+                [&contents[..range.start], &contents[range.end..]].concat()
+            );
+
+            assert_eq!(edit.start_offset, range.start);
+            assert_eq!(edit.start_point, naive_point(&contents[..range.start]));
+            assert_eq!(edit.removed.len, range.len());
+            assert_eq!(edit.removed.lines.line, newlines, "{:?}", range);
+            assert_eq!(edit.removed.lines, naive_point(removed));
+            assert_eq!(edit.old_end_point(), naive_point(&contents[..range.end]));
+            assert_eq!(edit.new_end_point(), edit.start_point);
+            assert_eq!(edit.inserted, Extent::default());
+        }
+
+        assert!(matches!(
+            rope.delete(10..contents.len() + 1),
+            Err(Error::RangeOutOfBounds { op: "delete", .. })
+        ));
+    }
+
+    #[test]
+    fn replace_summary() {
+        let contents = log_lines(20);
+        let mut buffer = SlabAllocator::new();
+        let rope = write_all(&mut buffer, &contents)
+            .into_iter()
+            .fold(Rope::empty(), |rope, slab| rope.append(slab).unwrap());
+
+        let (text, _) = buffer.append(b"one\ntwo\nthree").unwrap();
+        let (updated, deleted, edit) = rope.replace(10..40, text).unwrap();
+        let expected = [&contents[..10], b"one\ntwo\nthree", &contents[40..]].concat();
+        assert_eq!(updated.to_bstring(), expected);
+        assert_eq!(deleted.to_bstring(), &contents[10..40]);
+        assert_eq!(edit.inserted.len, 13);
+        assert_eq!(edit.inserted.lines, Point { line: 2, column: 5 });
+        assert_eq!(edit.new_end_offset(), 23);
+        assert_eq!(edit.new_end_point(), naive_point(&expected[..23]));
+        assert_eq!(edit.old_end_point(), naive_point(&contents[..40]));
+    }
+
+    #[test]
+    fn sub_ropes_share_slabs() {
+        let contents = log_lines(600);
+        let mut rope = Rope::from_str(contents.to_str().unwrap(), &mut SlabAllocator::new());
+        // slabs from another allocator in between, so there are leaves of every size
+        let mut other = SlabAllocator::new();
+        let mut contents = contents.to_vec();
+        for at in (0..contents.len()).step_by(3000).rev() {
+            let (slab, _) = other.append(b"x\n").unwrap();
+            rope = rope.insert(at, slab).unwrap();
+            contents.splice(at..at, *b"x\n");
+        }
+        assert_eq!(rope.to_bstring(), contents);
+
+        let chunks: Vec<&[u8]> = rope.chunks(.., 0).collect();
+        assert!(chunks.len() > 10, "{} chunks", chunks.len());
+        let mut offsets = vec![0, contents.len()];
+        let mut end = 0;
+        for chunk in &chunks {
+            end += chunk.len();
+            offsets.extend([end - 1, end, (end + 1).min(contents.len())]);
+        }
+        offsets.sort();
+        offsets.dedup();
+
+        let within = |part: &[u8]| {
+            chunks.iter().any(|chunk| {
+                let chunk = chunk.as_ptr_range();
+                chunk.start <= part.as_ptr() && part.as_ptr_range().end <= chunk.end
+            })
+        };
+        for (i, &start) in offsets.iter().enumerate() {
+            for &end in &offsets[i..] {
+                let sub = rope.sub_rope(start..end).unwrap();
+                let slice: Vec<u8> = rope
+                    .slice(start..end)
+                    .chunks(0)
+                    .flatten()
+                    .copied()
+                    .collect();
+                assert_eq!(sub.to_bstring(), &contents[start..end], "{}..{}", start, end);
+                assert_eq!(sub.to_bstring(), slice, "{}..{}", start, end);
+                assert_eq!(sub.len(), end - start);
+                assert!(sub.is_balanced(), "{}..{}", start, end);
+
+                // only the ends are re-sliced; the slabs in between are the very same
+                let parts: Vec<&[u8]> = sub.chunks(.., 0).collect();
+                assert!(parts.iter().all(|&part| within(part)), "{}..{}", start, end);
+                for part in parts.iter().skip(1).take(parts.len().saturating_sub(2)) {
+                    let same = |chunk: &&[u8]| std::ptr::eq(*chunk, *part);
+                    assert!(chunks.iter().any(same), "{}..{}", start, end);
+                }
+            }
+        }
+
+        let len = contents.len();
+        for range in [(10, 5), (0, len + 1), (len + 1, len + 2)] {
+            assert!(matches!(
+                rope.sub_rope(range.0..range.1),
+                Err(Error::RangeOutOfBounds { op: "sub_rope", .. })
+            ));
+        }
+        assert_eq!(rope.sub_rope(..).unwrap().to_bstring(), contents);
+        assert!(Rope::empty().sub_rope(..).unwrap().is_empty());
+    }
+
+    #[test]
+    fn slabs_of_every_size_mix() {
+        // a file loaded in bulk is a few big leaves; lines written one by one fill small blocks
+        let file = log_lines(20_000);
+        let rope = Rope::from_str(file.to_str().unwrap(), &mut SlabAllocator::new());
+        let bulk_blocks = file.len().div_ceil(BULK_BLOCK_CAPACITY);
+        assert!(rope.chunks(.., 0).count() <= bulk_blocks + 1);
+        assert_eq!(rope.to_bstring(), file);
+
+        let mut contents = file.to_vec();
+        let mut typing = SlabAllocator::new();
+        let mut tiny = SlabAllocator::with_capacity(7);
+        let mut rope = rope;
+        for (i, at) in (0..contents.len()).step_by(9973).enumerate() {
+            let text: &[u8] = match i % 3 {
+                0 => b"x\n",
+                1 => b"typed",
+                _ => b"blocks of seven bytes",
+            };
+            let allocator = if i % 3 == 2 { &mut tiny } else { &mut typing };
+            let slabs = write_all(allocator, text);
+            let mut offset = at;
+            for slab in slabs {
+                let len = slab.len();
+                rope = rope.insert(offset, slab).unwrap();
+                offset += len;
+            }
+            contents.splice(at..at, text.iter().copied());
+        }
+        for at in (0..contents.len() - 100).step_by(31_337).rev() {
+            let (updated, deleted, _) = rope.delete(at..at + 100).unwrap();
+            assert_eq!(deleted.to_bstring(), &contents[at..at + 100]);
+            contents.drain(at..at + 100);
+            rope = updated;
+        }
+        assert!(rope.is_balanced());
+        assert_eq!(rope.to_bstring(), contents);
+        let chunks: Vec<u8> = rope.chunks(.., 0).flatten().copied().collect();
+        assert_eq!(chunks, contents);
+        let chars: String = rope.chars(.., 0).collect();
+        assert_eq!(chars, contents.to_str().unwrap());
+
+        for at in [
+            0,
+            1,
+            1000,
+            contents.len() / 2,
+            contents.len() - 1,
+            contents.len(),
+        ] {
+            let (head, tail) = rope.split(at).unwrap();
+            assert!(head.is_balanced() && tail.is_balanced(), "split at {}", at);
+            assert_eq!(head.to_bstring(), &contents[..at]);
+            assert_eq!(tail.to_bstring(), &contents[at..]);
+            let sub = rope.sub_rope(at / 2..at).unwrap();
+            assert_eq!(sub.to_bstring(), &contents[at / 2..at]);
+        }
+    }
+
+    /// A rope with each of `parts` in a leaf of its own.
+    fn rope_of_leaves(parts: &[&str]) -> Rope {
+        parts.iter().fold(Rope::empty(), |rope, part| {
+            let (slab, _) = SlabAllocator::new().append(part.as_bytes()).unwrap();
+            rope.append(slab).unwrap()
+        })
+    }
+
+    #[test]
+    fn chunks_stop_at_range_end() {
+        let rope = rope_of_leaves(&["abc", "def", "ghi"]);
+        let text = "abcdefghi";
+        for start in 0..=text.len() {
+            for end in start..=text.len() {
+                let chunks: Vec<u8> = rope.chunks(start..end, 0).flatten().copied().collect();
+                assert_eq!(chunks.as_bstr(), &text[start..end], "{}..{}", start, end);
+            }
+        }
+    }
+
+    #[test]
+    fn offset_to_point_at_the_ends() {
+        assert_eq!(Rope::empty().offset_to_point(0), Some(Point { line: 0, column: 0 }));
+        assert_eq!(Rope::empty().offset_to_point(1), None);
+        let rope = rope_of_leaves(&["ab\n", "cd"]);
+        assert_eq!(rope.offset_to_point(5), Some(Point { line: 1, column: 2 }));
+        assert_eq!(rope.offset_to_point(6), None);
+    }
+
+    #[test]
+    fn crlf_split_across_leaves() {
+        let rope = rope_of_leaves(&["ab\r", "\ncd\r", "\n"]);
+        let lines: Vec<Vec<u8>> = rope
+            .lines(..)
+            .map(|line| line.chunks(0).flatten().copied().collect())
+            .collect();
+        assert_eq!(lines, [b"ab".to_vec(), b"cd".to_vec()]);
+        let line = rope.line(0).unwrap();
+        assert!(line.ends_with(b"ab") && !line.ends_with(b"\r"));
+    }
+
+    #[test]
+    fn edits_keep_the_tree_balanced() {
+        let mut rng = test_support::Rng::new(7);
+        let mut allocator = SlabAllocator::new();
+        let mut rope = Rope::empty();
+        let mut model: Vec<u8> = vec![];
+        for step in 0..2000 {
+            let len = rope.len();
+            let (a, b) = (rng.below(len + 1), rng.below(len + 1));
+            let range = a.min(b)..a.max(b);
+            match rng.below(4) {
+                0 => {
+                    rope = rope.delete(range.clone()).unwrap().0;
+                    model.drain(range);
+                }
+                1 => {
+                    let (left, right) = rope.split(a).unwrap();
+                    (rope, model) = if b % 2 == 0 {
+                        (left, model[..a].to_vec())
+                    } else {
+                        (right, model[a..].to_vec())
+                    };
+                }
+                _ => {
+                    let (slab, _) = allocator.append(b"xyz\n").unwrap();
+                    rope = rope.insert(a, slab).unwrap();
+                    model.splice(a..a, b"xyz\n".iter().copied());
+                }
+            }
+            assert!(rope.is_balanced(), "unbalanced at step {}", step);
+            assert_eq!(rope.to_bstring(), model.as_bstr(), "at step {}", step);
+        }
+    }
+
+    #[test]
+    fn dump_escapes_slab_contents() {
+        let mut buffer = SlabAllocator::new();
+        let (quoted, _) = buffer.append(b"say \"hi\"\n").unwrap();
+        // from another allocator, so it isn't merged into the leaf before it
+        let (path, _) = SlabAllocator::new()
+            .append(b"C:\\dir\\ and a long tail")
+            .unwrap();
+        let rope = Rope::empty().insert(0, quoted).unwrap();
+        let rope = rope.insert(rope.len(), path).unwrap();
+        let tree = rope.0.unwrap();
+
+        let mut dot = vec![];
+        tree.write_dot_with_preview(&mut dot, 10).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains(r#"label="Slab(say \"hi\"\n/9)""#), "{}", dot);
+        assert!(dot.contains(r#"label="Slab(C:\\dir\\ an…/23)""#), "{}", dot);
+
+        let mut text = vec![];
+        tree.write_text(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        // a branch over the two leaves; what it's called depends on the kind of tree sumtree was
+        // built with, which cargo decides for the whole build, not this crate's `wide` feature
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", text);
+        assert!(!lines[0].starts_with(' ') && !lines[0].starts_with("leaf "), "{}", text);
+        assert!(lines[1..].iter().all(|line| line.starts_with("  leaf ")), "{}", text);
+        assert!(text.contains(r#""Slab(say \"hi\"\n/9)""#), "{}", text);
+    }
+}
@@ -0,0 +1,208 @@
+use regex_automata::meta::Regex;
+use std::collections::VecDeque;
+use std::ops::{Range, RangeBounds};
+
+use crate::{util, ChunkAndRanges, Rope};
+
+impl Rope {
+    /// Finds the non-overlapping matches of `re` within the byte `range`, in order.
+    ///
+    /// The rope is searched a line at a time: `^` and `$` match at the start and end of every
+    /// line (`$` before any `\r\n`), and no match spans a line break. A line that lies within one
+    /// chunk is searched in place; only lines split across chunks are copied, one at a time, so
+    /// the whole rope is never materialised and every byte is handed to `re` exactly once.
+    pub fn regex_find_iter<'a>(
+        &'a self,
+        re: &'a Regex,
+        range: impl RangeBounds<usize>,
+    ) -> RegexMatches<'a> {
+        let range = util::bound_range(&range, 0..self.len());
+        RegexMatches {
+            re,
+            end: range.end,
+            chunks: ChunkAndRanges::new(self, range.clone(), 0),
+            chunk: &[],
+            chunk_start: range.start,
+            line: Vec::new(),
+            line_start: range.start,
+            matches: VecDeque::new(),
+            done: false,
+            #[cfg(test)]
+            searched: 0,
+        }
+    }
+}
+
+pub struct RegexMatches<'a> {
+    re: &'a Regex,
+    end: usize,
+    chunks: ChunkAndRanges<'a>,
+    /// What's left of the current chunk, and where it starts in the rope.
+    chunk: &'a [u8],
+    chunk_start: usize,
+    /// The start of a line split across chunks, carried until its terminator turns up.
+    line: Vec<u8>,
+    line_start: usize,
+    /// Matches on the last line searched, not yet returned.
+    matches: VecDeque<Range<usize>>,
+    done: bool,
+    #[cfg(test)]
+    searched: usize,
+}
+
+impl<'a> RegexMatches<'a> {
+    /// Searches the next whole line, or returns false once the range is exhausted.
+    fn search_next_line(&mut self) -> bool {
+        loop {
+            if self.chunk.is_empty() {
+                match self.chunks.next() {
+                    Some((chunk, range)) => {
+                        let len = chunk.len().min(self.end.saturating_sub(range.start));
+                        self.chunk = &chunk[..len];
+                        self.chunk_start = range.start;
+                        if self.chunk.is_empty() {
+                            continue;
+                        }
+                    }
+                    None if self.done => return false,
+                    None => {
+                        // the last line, which has no terminator
+                        self.done = true;
+                        self.search(None);
+                        return true;
+                    }
+                }
+            }
+
+            match memchr::memchr(b'\n', self.chunk) {
+                Some(idx) => {
+                    let (line, rest) = self.chunk.split_at(idx + 1);
+                    let start = self.chunk_start;
+                    self.chunk = rest;
+                    self.chunk_start += line.len();
+                    self.search(Some((line, start)));
+                    self.line_start = self.chunk_start;
+                    return true;
+                }
+                None => {
+                    self.line.extend_from_slice(self.chunk);
+                    self.chunk_start += self.chunk.len();
+                    self.chunk = &[];
+                }
+            }
+        }
+    }
+
+    /// Queues the matches on a line: `tail` and anything carried over from earlier chunks.
+    fn search(&mut self, tail: Option<(&[u8], usize)>) {
+        let (line, start) = match tail {
+            Some((tail, start)) if self.line.is_empty() => (tail, start),
+            tail => {
+                self.line
+                    .extend_from_slice(tail.map_or(&[][..], |(tail, _)| tail));
+                (&self.line[..], self.line_start)
+            }
+        };
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        #[cfg(test)]
+        {
+            self.searched += line.len();
+        }
+        for m in self.re.find_iter(line) {
+            self.matches
+                .push_back((start + m.start())..(start + m.end()));
+        }
+        self.line.clear();
+    }
+}
+
+impl<'a> Iterator for RegexMatches<'a> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(m) = self.matches.pop_front() {
+                return Some(m);
+            }
+            if !self.search_next_line() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::SlabAllocator;
+
+    /// A rope with each of `parts` in a slab of its own.
+    fn rope(parts: &[&str]) -> Rope {
+        let slabs = parts.iter().map(|part| {
+            let (slab, written) = SlabAllocator::new().append(part.as_bytes()).unwrap();
+            assert_eq!(written, part.len());
+            slab
+        });
+        let rope = Rope::empty().append_many(slabs).unwrap();
+        assert_eq!(rope.chunks(.., 0).count(), parts.len());
+        rope
+    }
+
+    fn find(rope: &Rope, pattern: &str, range: Range<usize>) -> Vec<String> {
+        let re = Regex::new(pattern).unwrap();
+        let text = rope.to_bstring();
+        rope.regex_find_iter(&re, range)
+            .map(|m| String::from_utf8(text[m].to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn match_spanning_slabs() {
+        let rope = rope(&["let fo", "obarb", "az = 1;\nfoob", "arbaz\n"]);
+        assert_eq!(find(&rope, "foo\\w+", 0..rope.len()), vec!["foobarbaz", "foobarbaz"]);
+
+        let re = Regex::new("foo\\w+").unwrap();
+        let matches: Vec<_> = rope.regex_find_iter(&re, ..).collect();
+        assert_eq!(matches, vec![4..13, 19..28]);
+
+        // the range cuts a match short rather than reading past it
+        assert_eq!(find(&rope, "foo\\w+", 2..10), vec!["foobar"]);
+        assert_eq!(find(&rope, "foo\\w+", 21..rope.len()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn line_anchors() {
+        let rope = rope(&["one\r", "\ntw", "o\nthr", "ee"]);
+        assert_eq!(find(&rope, "^\\w+$", 0..rope.len()), vec!["one", "two", "three"]);
+        assert_eq!(find(&rope, "^t", 0..rope.len()), vec!["t", "t"]);
+        assert_eq!(find(&rope, "e$", 0..rope.len()), vec!["e", "e"]);
+        // a pattern never matches across a line break
+        assert_eq!(find(&rope, "e\\s+t", 0..rope.len()), Vec::<String>::new());
+
+        // an empty final line can still match
+        let rope = self::rope(&["a\n", "\n"]);
+        let re = Regex::new("^$").unwrap();
+        let matches: Vec<_> = rope.regex_find_iter(&re, ..).collect();
+        assert_eq!(matches, vec![2..2, 3..3]);
+    }
+
+    #[test]
+    fn pathological_pattern_scans_once() {
+        let parts: Vec<String> = (0..64).map(|_| "a".repeat(100)).collect();
+        let parts: Vec<_> = parts.iter().map(String::as_str).collect();
+        let rope = rope(&parts);
+        let re = Regex::new("(a|aa)*b").unwrap();
+        let mut matches = rope.regex_find_iter(&re, ..);
+        assert_eq!(matches.next(), None);
+        assert_eq!(matches.searched, rope.len());
+
+        let lines: Vec<String> = (0..64).map(|i| format!("{}b\n", "a".repeat(i))).collect();
+        let lines: Vec<_> = lines.iter().map(String::as_str).collect();
+        let rope = self::rope(&lines);
+        let mut matches = rope.regex_find_iter(&re, ..);
+        assert_eq!(matches.by_ref().count(), 64);
+        assert_eq!(matches.searched, rope.len() - 64);
+    }
+}
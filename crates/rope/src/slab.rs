@@ -6,10 +6,25 @@ use bstr::ByteSlice;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 
-const BLOCK_CAPACITY: usize = 4096;
+/// The size of the blocks an allocator writes to unless it's given another: small, so the
+/// slabs typing makes don't each keep much more alive than they hold.
+pub(crate) const EDIT_BLOCK_CAPACITY: usize = 1024;
+/// The size of the blocks bulk writes, like reading a file, get, and the most any block holds:
+/// large, so a big file makes few slabs and a shallow tree.
+pub(crate) const BULK_BLOCK_CAPACITY: usize = 64 * 1024;
 
 #[derive(Debug)]
-struct SlabBlock([u8; BLOCK_CAPACITY]); // TODO: tune size of byte array
+struct SlabBlock(Box<[u8]>);
+
+impl SlabBlock {
+    fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self(vec![0; capacity].into_boxed_slice()))
+    }
+
+    fn capacity(&self) -> usize {
+        self.0.len()
+    }
+}
 
 #[derive(Clone)]
 pub struct Slab(Arc<SlabBlock>, Range<usize>);
@@ -52,11 +67,32 @@ impl Slab {
 
         Self(self.0.clone(), start..end)
     }
+
+    /// The block the slab's bytes are in, by address, and where in it they are. Slabs whose
+    /// ranges overlap in one block share those bytes, as unedited text in two versions of a rope
+    /// does.
+    pub(crate) fn block_range(&self) -> (usize, Range<usize>) {
+        (Arc::as_ptr(&self.0) as usize, self.1.clone())
+    }
+
+    /// Joins `self` with `other` when `other` continues the same block right where `self` ends,
+    /// as consecutive writes through a `SlabAllocator` do.
+    pub(crate) fn merge(&self, other: &Slab) -> Option<Slab> {
+        if Arc::ptr_eq(&self.0, &other.0) && self.1.end == other.1.start {
+            Some(Self(self.0.clone(), self.1.start..other.1.end))
+        } else {
+            None
+        }
+    }
 }
 
+/// Writes slabs one after another into shared blocks. Blocks are the allocator's capacity, but
+/// bulk writes, like reads from a file or text that fills a block on its own, get blocks of
+/// `BULK_BLOCK_CAPACITY` if that's bigger, so slabs of both sizes can end up in one rope.
 pub struct SlabAllocator {
     block: Arc<SlabBlock>,
     head: usize,
+    capacity: usize,
 }
 
 impl Default for SlabAllocator {
@@ -67,15 +103,21 @@ impl Default for SlabAllocator {
 
 impl SlabAllocator {
     pub fn new() -> Self {
-        Self { block: Arc::new(SlabBlock([0; BLOCK_CAPACITY])), head: 0 }
+        Self::with_capacity(EDIT_BLOCK_CAPACITY)
+    }
+
+    /// An allocator of blocks of `bytes`, kept between one byte and `BULK_BLOCK_CAPACITY`.
+    pub fn with_capacity(bytes: usize) -> Self {
+        let capacity = bytes.clamp(1, BULK_BLOCK_CAPACITY);
+        Self { block: SlabBlock::new(capacity), head: 0, capacity }
     }
 
     pub fn append(&mut self, val: &[u8]) -> std::io::Result<(Slab, usize)> {
         use std::io::Write;
-        let (block, head, rem) = self.block_remaining();
+        let (block, head, rem) = self.block_remaining(val.len() >= self.capacity);
         let len = min(val.len(), rem);
         let mut bytes: &mut [u8] = unsafe {
-            let bytes = (&block.as_ref().0 as *const u8) as *mut u8;
+            let bytes = block.0.as_ptr() as *mut u8;
             std::slice::from_raw_parts_mut(bytes.add(head), len)
         };
         let written = bytes.write(&val[..len])?;
@@ -84,10 +126,37 @@ impl SlabAllocator {
         Ok((Slab(block.clone(), range), written))
     }
 
+    /// Like `append`, but a slab only ever ends on a char boundary of `text`, so each holds
+    /// whole chars; a char that doesn't fit in what's left of the block goes in a new one.
+    pub fn append_str(&mut self, text: &str) -> (Slab, usize) {
+        let bulk = text.len() >= self.capacity;
+        let (mut block, mut head, mut rem) = self.block_remaining(bulk);
+        let fits = |rem: usize| {
+            let len = min(text.len(), rem);
+            (0..=len)
+                .rev()
+                .find(|&i| text.is_char_boundary(i))
+                .unwrap_or(0)
+        };
+        let mut len = fits(rem);
+        if len == 0 && !text.is_empty() {
+            self.head = block.capacity();
+            (block, head, rem) = self.block_remaining(bulk);
+            len = fits(rem);
+        }
+        let bytes: &mut [u8] = unsafe {
+            let bytes = block.0.as_ptr() as *mut u8;
+            std::slice::from_raw_parts_mut(bytes.add(head), len)
+        };
+        bytes.copy_from_slice(&text.as_bytes()[..len]);
+        self.head += len;
+        (Slab(block.clone(), head..(head + len)), len)
+    }
+
     pub async fn read(&mut self, file: &mut File) -> std::io::Result<(Slab, usize)> {
-        let (block, head, rem) = self.block_remaining();
+        let (block, head, rem) = self.block_remaining(true);
         let bytes: &mut [u8] = unsafe {
-            let bytes = (&block.as_ref().0 as *const u8) as *mut u8;
+            let bytes = block.0.as_ptr() as *mut u8;
             std::slice::from_raw_parts_mut(bytes.add(head), rem)
         };
         let written = file.read(bytes).await?;
@@ -96,13 +165,18 @@ impl SlabAllocator {
         Ok((Slab(block.clone(), range), written))
     }
 
-    fn block_remaining(&mut self) -> (Arc<SlabBlock>, usize, usize) {
-        if self.head >= BLOCK_CAPACITY {
-            // new block please
-            self.block = Arc::new(SlabBlock([0; BLOCK_CAPACITY]));
+    /// The block to write to, where in it, and how much room it has left; a new one if the last
+    /// is full, of the bulk size if `bulk`.
+    fn block_remaining(&mut self, bulk: bool) -> (Arc<SlabBlock>, usize, usize) {
+        if self.head >= self.block.capacity() {
+            let capacity = if bulk {
+                self.capacity.max(BULK_BLOCK_CAPACITY)
+            } else {
+                self.capacity
+            };
+            self.block = SlabBlock::new(capacity);
             self.head = 0;
         }
-        (self.block.clone(), self.head, BLOCK_CAPACITY - self.head)
+        (self.block.clone(), self.head, self.block.capacity() - self.head)
     }
 }
-
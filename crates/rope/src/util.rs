@@ -16,9 +16,22 @@ pub(super) fn bound_range(range: &impl RangeBounds<usize>, bounds: Range<usize>)
     std::cmp::min(start, bounds.end)..std::cmp::min(end, bounds.end)
 }
 
+/// Adds one, or `n`, to the counter `field` of `crate::counters`, when they're kept.
+macro_rules! counted {
+    ($field:ident) => {
+        counted!($field, 1)
+    };
+    ($field:ident, $n:expr) => {
+        #[cfg(any(test, feature = "counters"))]
+        $crate::counters::add(|counters| counters.$field += $n);
+    };
+}
+pub(crate) use counted;
+
 #[cfg(test)]
 mod tests {
     #[test]
+    #[allow(clippy::reversed_empty_ranges)]
     fn to_range() {
         assert_eq!(super::bound_range(&(0..0), 0..10), 0..0);
         assert_eq!(super::bound_range(&(0..5), 0..10), 0..5);
@@ -46,4 +59,4 @@ mod tests {
         assert_eq!(super::bound_range(&(..8), 0..10), 0..8);
         assert_eq!(super::bound_range(&(..), 0..10), 0..10);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,117 @@
+use std::ops::Range;
+use sumtree::{Item, Node, SumTree, Summary};
+
+use crate::{Metrics, Rope, Slab};
+
+/// A summary in a rope's tree that differs from the one its slab, or its children's summaries,
+/// make.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SummaryMismatch {
+    /// The bytes the summary is of.
+    pub bytes: Range<usize>,
+    pub stored: Metrics,
+    pub expected: Metrics,
+}
+
+impl std::fmt::Display for SummaryMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "bytes {}..{} summarized as {:?} rather than {:?}",
+            self.bytes.start, self.bytes.end, self.stored, self.expected
+        )
+    }
+}
+
+impl Rope {
+    /// Checks every summary in the tree against what it summarizes: a leaf's against its slab,
+    /// a branch's against its children's combined. The deepest wrong summary is the one reported,
+    /// since the ones above it are only wrong for counting it.
+    pub fn validate(&self) -> Result<(), SummaryMismatch> {
+        match &self.0 {
+            None => Ok(()),
+            Some(tree) => validate(tree, 0, None),
+        }
+    }
+
+    /// Like `validate`, checking only the summaries on the way down to the leaf holding byte
+    /// `offset`; a few per level, however long the rope is.
+    pub fn validate_at(&self, offset: usize) -> Result<(), SummaryMismatch> {
+        match &self.0 {
+            None => Ok(()),
+            Some(tree) => validate(tree, 0, Some(offset)),
+        }
+    }
+}
+
+/// Validates `tree`, which starts at byte `start`, and its children, or with an `offset` only
+/// the child that holds it.
+fn validate(
+    tree: &SumTree<Slab>,
+    start: usize,
+    offset: Option<usize>,
+) -> Result<(), SummaryMismatch> {
+    let mut end = start;
+    let mut combined: Option<Metrics> = None;
+    for child in tree.children() {
+        let summary = child.summary();
+        let bytes = end..end + summary.stats.len;
+        if offset.map_or(true, |offset| bytes.contains(&offset)) {
+            validate(child, bytes.start, offset)?;
+        }
+        end = bytes.end;
+        combined = Some(combined.map_or(summary, |combined| combined.combine(&summary)));
+    }
+    let expected = match tree.as_ref() {
+        Node::Leaf { item, .. } => item.summary(),
+        Node::Branch { .. } => combined.unwrap_or_default(),
+    };
+    let stored = tree.summary();
+    if stored != expected {
+        let bytes = start..start + stored.stats.len;
+        return Err(SummaryMismatch { bytes, stored, expected });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::SlabAllocator;
+
+    fn leaf(text: &str) -> SumTree<Slab> {
+        let (slab, _) = SlabAllocator::new().append(text.as_bytes()).unwrap();
+        SumTree::new_leaf(slab)
+    }
+
+    #[test]
+    fn validates_summaries() {
+        assert_eq!(Rope::empty().validate(), Ok(()));
+        let (first, second) = (leaf("one\ntwo\n"), leaf("three"));
+        let tree = SumTree::join(first.clone(), second.clone());
+        assert_eq!(Rope::new(tree.clone()).validate(), Ok(()));
+
+        // a root that counts a line too many
+        let mut summary = tree.summary();
+        summary.stats.lines.line += 1;
+        let rope = Rope::new(tree.with_summary(summary));
+        let mismatch = rope.validate().unwrap_err();
+        assert_eq!(mismatch.bytes, 0..13);
+        assert_eq!((mismatch.stored, mismatch.expected), (summary, tree.summary()));
+        assert_eq!(
+            mismatch.to_string(),
+            "bytes 0..13 summarized as (13,3/5,3/5) rather than (13,2/5,3/5)"
+        );
+
+        // a leaf that does, under a branch summed from it; only the leaf is wrong
+        let mut summary = second.summary();
+        summary.stats.lines.line += 1;
+        let rope = Rope::new(SumTree::join(first, second.with_summary(summary)));
+        let mismatch = rope.validate().unwrap_err();
+        assert_eq!((mismatch.bytes, mismatch.stored), (8..13, summary));
+        assert_eq!(rope.validate_at(9).unwrap_err().bytes, 8..13);
+        // which a check on the way to another leaf passes over
+        assert_eq!(rope.validate_at(2), Ok(()));
+    }
+}
@@ -0,0 +1,374 @@
+use std::cmp::Reverse;
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use smallvec::SmallVec;
+
+/// The most results a query lists.
+pub const MAX_RESULTS: usize = 32;
+
+/// Which of an entry's strings a query matched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchedText {
+    Name,
+    Alias(usize),
+}
+
+impl MatchedText {
+    /// The char offset of the matched string within `label(name, aliases)`.
+    pub fn label_offset<S: AsRef<str>>(self, name: &str, aliases: &[S]) -> usize {
+        match self {
+            MatchedText::Name => 0,
+            MatchedText::Alias(idx) => {
+                let before: usize = aliases[..idx]
+                    .iter()
+                    .map(|alias| alias.as_ref().chars().count() + ", ".len())
+                    .sum();
+                name.chars().count() + " (".len() + before
+            }
+        }
+    }
+}
+
+/// How an entry is listed in results: its name, followed by its aliases if it has any, e.g.
+/// "quit (q)".
+pub fn label<S: AsRef<str>>(name: &str, aliases: &[S]) -> String {
+    label_parts(name, aliases).collect()
+}
+
+/// The pieces of [`label`], borrowed from `name` and `aliases`, to draw it without making it.
+pub fn label_parts<'a, S: AsRef<str>>(
+    name: &'a str,
+    aliases: &'a [S],
+) -> impl Iterator<Item = &'a str> + 'a {
+    let close = (!aliases.is_empty()).then_some(")");
+    let aliases = aliases.iter().enumerate().flat_map(|(idx, alias)| {
+        let separator = if idx == 0 { " (" } else { ", " };
+        [separator, alias.as_ref()]
+    });
+    std::iter::once(name).chain(aliases).chain(close)
+}
+
+/// Char indices of matched chars; a query long enough to need more is rare.
+pub type Indices = SmallVec<[usize; 16]>;
+
+/// A query's match against an entry's name or one of its aliases.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    pub matched_text: MatchedText,
+    pub score: i64,
+    /// Char indices of the matched chars, relative to the matched string.
+    pub indices: Indices,
+    /// Whether the query is a prefix of an alias, which outranks any fuzzy match.
+    pub alias_prefix: bool,
+}
+
+impl Match {
+    /// Matches `query` against `name` and `aliases` with a matcher made for the one call; see
+    /// [`Matcher::find`].
+    pub fn find<S: AsRef<str>>(name: &str, aliases: &[S], query: &str) -> Option<Self> {
+        Matcher::default().find(name, aliases, query)
+    }
+
+    /// Sorts better matches first.
+    pub fn rank(&self) -> (Reverse<bool>, Reverse<i64>) {
+        (Reverse(self.alias_prefix), Reverse(self.score))
+    }
+
+    /// `indices` as char indices into `label(name, aliases)`.
+    pub fn label_indices<'a, S: AsRef<str>>(
+        &'a self,
+        name: &str,
+        aliases: &[S],
+    ) -> impl Iterator<Item = usize> + 'a {
+        let offset = self.matched_text.label_offset(name, aliases);
+        self.indices.iter().map(move |idx| offset + idx)
+    }
+}
+
+/// Matches queries against entries, keeping the fuzzy matcher and its scratch space between
+/// calls so a query run over every entry doesn't set them up for each.
+#[derive(Default)]
+pub struct Matcher {
+    skim: SkimMatcherV2,
+}
+
+impl std::fmt::Debug for Matcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Matcher").finish_non_exhaustive()
+    }
+}
+
+impl Matcher {
+    /// Matches `query` against `name` and `aliases`: an alias the query is a prefix of wins,
+    /// otherwise whichever string matches best fuzzily. An empty query matches the name.
+    pub fn find<S: AsRef<str>>(&self, name: &str, aliases: &[S], query: &str) -> Option<Match> {
+        if query.is_empty() {
+            return Some(Match {
+                matched_text: MatchedText::Name,
+                score: 0,
+                indices: Indices::new(),
+                alias_prefix: false,
+            });
+        }
+        let prefixed = aliases
+            .iter()
+            .position(|alias| alias.as_ref().starts_with(query));
+        if let Some(idx) = prefixed {
+            return Some(Match {
+                matched_text: MatchedText::Alias(idx),
+                score: i64::MAX,
+                indices: (0..query.chars().count()).collect(),
+                alias_prefix: true,
+            });
+        }
+
+        let texts = std::iter::once((MatchedText::Name, name)).chain(
+            aliases
+                .iter()
+                .enumerate()
+                .map(|(idx, alias)| (MatchedText::Alias(idx), alias.as_ref())),
+        );
+        texts
+            // the matcher allocates for any text it looks at, so most are turned away first
+            .filter(|(_, text)| is_subsequence(text, query))
+            .filter_map(|(matched_text, text)| {
+                let (score, indices) = self.skim.fuzzy_indices(text, query)?;
+                let indices = indices.into_iter().collect();
+                Some(Match { matched_text, score, indices, alias_prefix: false })
+            })
+            // the first of equally good matches, so a name beats its aliases
+            .fold(None, |best: Option<Match>, m| match best {
+                Some(best) if best.score >= m.score => Some(best),
+                _ => Some(m),
+            })
+    }
+}
+
+/// Whether the chars of `query` are all in `text`, in order: what the fuzzy matcher needs to
+/// match at all. Its case is ignored unless it has an upper case letter, as the matcher's is.
+fn is_subsequence(text: &str, query: &str) -> bool {
+    let ignore_case = !query.chars().any(|c| c.is_ascii_uppercase());
+    let mut text = text.chars();
+    query.chars().all(|q| {
+        text.any(|c| {
+            if ignore_case {
+                c.eq_ignore_ascii_case(&q)
+            } else {
+                c == q
+            }
+        })
+    })
+}
+
+/// An entry of a [`FuzzyIndex`], with the strings it's found by.
+#[derive(Debug)]
+pub struct FuzzyEntry<Id, S = String> {
+    pub id: Id,
+    pub name: S,
+    pub aliases: Vec<S>,
+}
+
+/// Entries matched fuzzily against a query, best first. The matcher and the matches are kept
+/// between queries, and a query that only adds to the end of the last one matches again just
+/// what that one matched: nothing it didn't match can match a longer query.
+#[derive(Debug)]
+pub struct FuzzyIndex<Id, S = String> {
+    entries: Vec<FuzzyEntry<Id, S>>,
+    matcher: Matcher,
+    /// Every entry the last query matched, by its index in `entries`, best first.
+    matches: Vec<(usize, Match)>,
+    /// The query `matches` are for.
+    query: String,
+    /// Whether the entries, or which of them are kept, changed since they were last matched.
+    stale: bool,
+}
+
+impl<Id, S> Default for FuzzyIndex<Id, S> {
+    fn default() -> Self {
+        let (entries, matcher, matches, query) = Default::default();
+        Self { entries, matcher, matches, query, stale: true }
+    }
+}
+
+impl<Id: Copy, S: AsRef<str>> FuzzyIndex<Id, S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: Id, name: S, aliases: Vec<S>) {
+        self.entries.push(FuzzyEntry { id, name, aliases });
+        self.stale = true;
+    }
+
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = &FuzzyEntry<Id, S>> {
+        self.entries.iter()
+    }
+
+    /// Has the next filter match every entry afresh, for when which it keeps has changed.
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    /// Matches the entries `keep` keeps against `query`, unless neither has changed since they
+    /// last were. Returns whether they were matched again.
+    pub fn filter(&mut self, query: &str, keep: impl Fn(&FuzzyEntry<Id, S>) -> bool) -> bool {
+        if !self.stale && query == self.query {
+            return false;
+        }
+        let (matcher, entries) = (&self.matcher, &self.entries);
+        let find = |idx: usize| {
+            let entry = &entries[idx];
+            matcher.find(entry.name.as_ref(), &entry.aliases, query)
+        };
+        if !self.stale && query.starts_with(self.query.as_str()) {
+            self.matches.retain_mut(|(idx, m)| match find(*idx) {
+                Some(found) => {
+                    *m = found;
+                    true
+                }
+                None => false,
+            });
+        } else {
+            self.matches.clear();
+            let kept = entries.iter().enumerate().filter(|(_, entry)| keep(entry));
+            let matches = kept.filter_map(|(idx, _)| Some((idx, find(idx)?)));
+            self.matches.extend(matches);
+        }
+        // equally good matches keep the order the entries were added in; a stable sort would
+        // allocate
+        self.matches
+            .sort_unstable_by_key(|(idx, m)| (m.rank(), *idx));
+        self.query.clear();
+        self.query.push_str(query);
+        self.stale = false;
+        true
+    }
+
+    /// The best of the last query's matches, at most `MAX_RESULTS` of them.
+    pub fn results(&self) -> impl ExactSizeIterator<Item = (&FuzzyEntry<Id, S>, &Match)> {
+        let matches = self.matches.iter().take(MAX_RESULTS);
+        matches.map(|(idx, m)| (&self.entries[*idx], m))
+    }
+
+    /// The ids of [`FuzzyIndex::results`].
+    pub fn ids(&self) -> impl ExactSizeIterator<Item = Id> + '_ {
+        self.results().map(|(entry, _)| entry.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(entries: &[(&'static str, &[&'static str])]) -> FuzzyIndex<usize, &'static str> {
+        let mut index = FuzzyIndex::new();
+        for (id, (name, aliases)) in entries.iter().enumerate() {
+            index.insert(id, *name, aliases.to_vec());
+        }
+        index
+    }
+
+    fn names<'a>(index: &'a FuzzyIndex<usize, &'static str>) -> Vec<&'a str> {
+        index.results().map(|(entry, _)| entry.name).collect()
+    }
+
+    #[test]
+    fn matched_text_for_name_and_alias() {
+        let m = Match::find("quit", &["q", "exit"], "qt").unwrap();
+        assert_eq!(m.matched_text, MatchedText::Name);
+        assert_eq!(m.indices.as_slice(), [0, 3]);
+        let label_indices = |m: &Match| m.label_indices("quit", &["q", "exit"]).collect::<Vec<_>>();
+        assert_eq!(label_indices(&m), vec![0, 3]);
+
+        let m = Match::find("quit", &["q", "exit"], "ex").unwrap();
+        assert_eq!(m.matched_text, MatchedText::Alias(1));
+        assert!(m.alias_prefix);
+        assert_eq!(label("quit", &["q", "exit"]), "quit (q, exit)");
+        assert_eq!(label_indices(&m), vec![9, 10]);
+
+        let m = Match::find("quit", &["q", "exit"], "xt").unwrap();
+        assert_eq!(m.matched_text, MatchedText::Alias(1));
+        assert!(!m.alias_prefix);
+        assert_eq!(m.indices.as_slice(), [1, 3]);
+
+        assert_eq!(Match::find("quit", &["q"], "z"), None);
+        assert_eq!(label::<&str>("quit", &[]), "quit");
+    }
+
+    #[test]
+    fn alias_prefix_ranks_first() {
+        let mut index = index(&[
+            ("qq.quick", &[]),
+            ("quit", &["q"]),
+            ("buffer.quote", &["bq"]),
+        ]);
+        index.filter("q", |_| true);
+        assert_eq!(names(&index)[0], "quit");
+        let (_, m) = index.results().next().unwrap();
+        assert_eq!(m.matched_text, MatchedText::Alias(0));
+        assert_eq!(m.indices.as_slice(), [0]);
+        assert_eq!(m.label_indices("quit", &["q"]).collect::<Vec<_>>(), vec![6]);
+    }
+
+    #[test]
+    fn aliases_are_matched() {
+        let mut index = index(&[("buffer.close", &["bd", "delete"]), ("quit", &["exit"])]);
+        // by an alias alone, fuzzily as well as by prefix
+        index.filter("dlt", |_| true);
+        assert_eq!(names(&index), ["buffer.close"]);
+        let (_, m) = index.results().next().unwrap();
+        assert_eq!(m.matched_text, MatchedText::Alias(1));
+        index.filter("ex", |_| true);
+        assert_eq!(names(&index), ["quit"]);
+        index.filter("bd", |_| true);
+        assert_eq!(names(&index), ["buffer.close"]);
+        index.filter("xyz", |_| true);
+        assert!(names(&index).is_empty());
+    }
+
+    #[test]
+    fn narrowing_matches_what_matching_afresh_would() {
+        let names: Vec<String> = (0..200).map(|i| format!("cmd.{}.{}", i % 7, i)).collect();
+        let mut narrowed = FuzzyIndex::new();
+        for (id, name) in names.iter().enumerate() {
+            narrowed.insert(id, name.as_str(), vec![]);
+        }
+        narrowed.filter("", |_| true);
+        // an empty query lists the entries in the order they came, as many as fit
+        assert_eq!(narrowed.ids().collect::<Vec<_>>(), (0..MAX_RESULTS).collect::<Vec<_>>());
+
+        let mut query = String::new();
+        for c in "c3.1".chars() {
+            query.push(c);
+            assert!(narrowed.filter(&query, |_| true));
+            let mut afresh = FuzzyIndex::new();
+            for (id, name) in names.iter().enumerate() {
+                afresh.insert(id, name.as_str(), vec![]);
+            }
+            afresh.filter(&query, |_| true);
+            assert_eq!(narrowed.matches, afresh.matches, "{:?}", query);
+        }
+        // the same query again matches nothing again
+        assert!(!narrowed.filter(&query, |_| true));
+    }
+
+    #[test]
+    fn invalidated_by_what_is_kept() {
+        let mut index = index(&[("quit", &[]), ("quick.open", &[]), ("buffer.close", &[])]);
+        index.filter("", |entry| entry.id != 0);
+        assert_eq!(names(&index), ["quick.open", "buffer.close"]);
+        // what's kept only counts once the index is told it changed
+        index.filter("q", |_| true);
+        assert_eq!(names(&index), ["quick.open"]);
+        index.invalidate();
+        index.filter("q", |_| true);
+        let mut matched = names(&index);
+        matched.sort();
+        assert_eq!(matched, ["quick.open", "quit"]);
+        // and so do new entries
+        index.insert(3, "q2", vec![]);
+        index.filter("q", |_| true);
+        assert_eq!(index.results().len(), 3);
+    }
+}
@@ -1,4 +1,10 @@
-use tore::Point;
+mod fuzzy;
+mod query;
+
+pub use fuzzy::{
+    label, label_parts, FuzzyEntry, FuzzyIndex, Indices, Match, MatchedText, Matcher, MAX_RESULTS,
+};
+pub use query::QueryInput;
 
 #[derive(Debug, Clone)]
 pub enum Mode {
@@ -14,80 +20,216 @@ pub enum Direction {
 #[derive(Debug, Clone)]
 pub enum Command<Id> {
     Focus(Direction),
+    FocusFirst,
+    FocusLast,
+    /// Moves the focus a page of entries, as many as `set_page_size` says are in view.
+    FocusPage(Direction),
     Delete(Direction),
     Insert(char),
+    /// Moves the cursor in the query a char.
+    MoveCursor(Direction),
     SetEntries(Vec<Id>),
 }
 
 #[derive(Debug)]
 pub struct Selector<Id: Eq + Copy> {
     pub query_prefix: &'static str,
-    pub query: String,
-    pub cursor: Point,
+    pub query: QueryInput,
     pub entries: Vec<Id>,
     pub focused: Option<Id>,
+    /// Whether moving the focus past either end of the entries carries on from the other.
+    pub wrap: bool,
+    /// The index of the first entry in view.
+    scroll: usize,
+    /// How many entries are in view at once.
+    page_size: usize,
 }
 
 impl<Id: Eq + Copy> Selector<Id> {
     pub fn new(query_prefix: &'static str) -> Self {
-        let query = String::default();
-        let cursor = Point::default();
+        let query = QueryInput::default();
         let focused = None;
         let entries = vec![];
-        Self { query_prefix, query, cursor, entries, focused }
+        let (wrap, scroll, page_size) = (false, 0, 1);
+        Self { query_prefix, query, entries, focused, wrap, scroll, page_size }
+    }
+
+    pub fn with_wrap(self, wrap: bool) -> Self {
+        Self { wrap, ..self }
+    }
+
+    /// The index of the first entry in view, moved along with the focus to keep it in view.
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    /// Sets how many entries are in view; only the pane showing them knows.
+    pub fn set_page_size(&mut self, page_size: usize) {
+        self.page_size = page_size.max(1);
+        self.scroll_to_focused();
+    }
+
+    pub fn focused_index(&self) -> Option<usize> {
+        let focused = self.focused?;
+        self.entries.iter().position(|id| *id == focused)
+    }
+
+    /// Clears the query and focus, as if newly opened; entries are left for the owner to refill.
+    pub fn reset(&mut self) {
+        self.query.clear();
+        self.focused = None;
+        self.scroll = 0;
     }
 
     pub fn command(&mut self, command: Command<Id>) {
         match command {
-            Command::Focus(dir) => self.focus(dir),
-            Command::Delete(dir) => self.delete(dir),
-            Command::Insert(c) => self.insert(c),
+            Command::Focus(dir) => self.focus(dir, 1),
+            Command::FocusFirst => self.focus_index(0),
+            Command::FocusLast => self.focus_index(self.entries.len().saturating_sub(1)),
+            Command::FocusPage(dir) => self.focus(dir, self.page_size),
+            Command::Delete(dir) => {
+                self.query.delete(dir);
+            }
+            Command::Insert(c) => self.query.insert(c),
+            Command::MoveCursor(dir) => self.query.move_cursor(dir),
             Command::SetEntries(es) => self.set_entries(es),
         }
     }
 
-    pub fn insert(&mut self, c: char) {
-        if self.cursor.column == self.query.len() {
-            self.query.push(c);
-        } else {
-            self.query.insert(self.cursor.column, c);
-        }
-        self.cursor.move_next_column();
+    /// Moves the focus `step` entries, stopping at either end. With `wrap`, a move from an end
+    /// towards the outside goes to the other end instead.
+    fn focus(&mut self, direction: Direction, step: usize) {
+        let Some(last) = self.entries.len().checked_sub(1) else {
+            return;
+        };
+        let Some(index) = self.focused_index() else {
+            return self.focus_index(0);
+        };
+        let index = match direction {
+            Direction::Next if index == last && self.wrap => 0,
+            Direction::Next => (index + step).min(last),
+            Direction::Prev if index == 0 && self.wrap => last,
+            Direction::Prev => index.saturating_sub(step),
+        };
+        self.focus_index(index);
     }
 
-    fn delete(&mut self, dir: Direction) {
-        let range = match dir {
-            Direction::Next => self.cursor.column..self.cursor.column + 1,
-            Direction::Prev => self.cursor.column - 1..self.cursor.column,
+    fn focus_index(&mut self, index: usize) {
+        self.focused = self.entries.get(index).copied();
+        self.scroll_to_focused();
+    }
+
+    /// Scrolls just far enough to bring the focused entry into view.
+    fn scroll_to_focused(&mut self) {
+        let Some(index) = self.focused_index() else {
+            self.scroll = 0;
+            return;
         };
-        self.query.drain(range);
-        self.cursor.move_prev_column();
+        if index < self.scroll {
+            self.scroll = index;
+        } else if index >= self.scroll + self.page_size {
+            self.scroll = index + 1 - self.page_size;
+        }
     }
 
-    fn focus(&mut self, direction: Direction) {
-        let mut prev: Option<&Id> = None;
-        let mut iter = self.entries.iter();
-        loop {
-            match iter.next() {
-                None => break,
-                Some(res) => {
-                    if Some(*res) == self.focused {
-                        let focused = match direction {
-                            Direction::Prev => prev,
-                            Direction::Next => iter.next(),
-                        };
-                        if focused.is_some() {
-                            self.focused = focused.copied();
-                        }
-                        break;
-                    }
-                    prev = Some(res);
-                }
+    /// Replaces the entries, keeping the focus on the same entry if it's still there and on the
+    /// first one otherwise. The entries' vec is refilled, not made anew.
+    pub fn set_entries(&mut self, entries: impl IntoIterator<Item = Id>) {
+        self.entries.clear();
+        self.entries.extend(entries);
+        match self.focused_index() {
+            Some(_) => self.scroll_to_focused(),
+            None => {
+                self.scroll = 0;
+                self.focus_index(0);
             }
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn focused_after(selector: &mut Selector<usize>, commands: &[Command<usize>]) -> Vec<usize> {
+        commands
+            .iter()
+            .map(|command| {
+                selector.command(command.clone());
+                selector.focused.unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn page_through_entries() {
+        let mut selector = Selector::new(":");
+        selector.command(Command::SetEntries((0..100).collect()));
+        selector.set_page_size(12);
+        assert_eq!(selector.focused, Some(0));
+
+        let down = vec![Command::FocusPage(Direction::Next); 10];
+        let expected = vec![12, 24, 36, 48, 60, 72, 84, 96, 99, 99];
+        assert_eq!(focused_after(&mut selector, &down), expected);
+        assert_eq!(selector.scroll(), 88);
+        let up = vec![Command::FocusPage(Direction::Prev); 3];
+        assert_eq!(focused_after(&mut selector, &up), vec![87, 75, 63]);
+        assert_eq!(selector.scroll(), 63);
+        let ends = [
+            Command::FocusLast,
+            Command::Focus(Direction::Next),
+            Command::FocusFirst,
+        ];
+        assert_eq!(focused_after(&mut selector, &ends), vec![99, 99, 0]);
+        assert_eq!(selector.scroll(), 0);
+        assert_eq!(focused_after(&mut selector, &[Command::Focus(Direction::Prev)]), vec![0]);
+
+        // wrapping carries on from the other end, a page at a time too
+        let mut selector = selector.with_wrap(true);
+        let up = [
+            Command::Focus(Direction::Prev),
+            Command::FocusPage(Direction::Prev),
+            Command::Focus(Direction::Next),
+            Command::FocusPage(Direction::Next),
+            Command::FocusPage(Direction::Prev),
+        ];
+        assert_eq!(focused_after(&mut selector, &up), vec![99, 87, 88, 99, 87]);
+        let down = vec![Command::FocusPage(Direction::Next); 3];
+        assert_eq!(focused_after(&mut selector, &down), vec![99, 0, 12]);
+        assert_eq!(selector.scroll(), 1);
+    }
+
+    #[test]
+    fn query_edits() {
+        let mut selector = Selector::<usize>::new(":");
+        // nothing to delete is left alone, at either end
+        selector.command(Command::Delete(Direction::Prev));
+        selector.command(Command::Delete(Direction::Next));
+        for c in "añb".chars() {
+            selector.command(Command::Insert(c));
+        }
+        selector.command(Command::MoveCursor(Direction::Prev));
+        selector.command(Command::Delete(Direction::Prev));
+        assert_eq!(selector.query.as_str(), "ab");
+        assert_eq!(selector.query.cursor_column(), 1);
+        selector.reset();
+        assert!(selector.query.is_empty());
+    }
+
+    #[test]
+    fn new_entries_keep_focus() {
+        let mut selector = Selector::new(":");
+        selector.set_page_size(5);
+        selector.command(Command::SetEntries((0..20).collect()));
+        selector.command(Command::FocusPage(Direction::Next));
+        selector.command(Command::FocusPage(Direction::Next));
+        assert_eq!((selector.focused, selector.scroll()), (Some(10), 6));
 
-    fn set_entries(&mut self, entries: Vec<Id>) {
-        self.entries = entries;
+        selector.command(Command::SetEntries((5..20).collect()));
+        assert_eq!((selector.focused, selector.focused_index()), (Some(10), Some(5)));
+        selector.command(Command::SetEntries((11..20).collect()));
+        assert_eq!((selector.focused, selector.scroll()), (Some(11), 0));
+        selector.command(Command::SetEntries(vec![]));
+        assert_eq!(selector.focused, None);
     }
 }
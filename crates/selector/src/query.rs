@@ -0,0 +1,174 @@
+use crate::Direction;
+
+/// The text typed into a selector or palette to pick from its entries, and where in it the
+/// cursor is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryInput {
+    text: String,
+    /// A byte offset into `text`, always on a char boundary.
+    cursor: usize,
+}
+
+impl QueryInput {
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// The byte offset of the cursor.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// How many chars are before the cursor, which is the column it's drawn at.
+    pub fn cursor_column(&self) -> usize {
+        self.text[..self.cursor].chars().count()
+    }
+
+    /// Replaces the text, with the cursor at its end.
+    pub fn set(&mut self, text: &str) {
+        self.text.clear();
+        self.text.push_str(text);
+        self.cursor = self.text.len();
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    /// Types `c` at the cursor, leaving the cursor after it.
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Deletes the char before the cursor, or the one under it for `Next`. Returns whether
+    /// there was one to delete.
+    pub fn delete(&mut self, direction: Direction) -> bool {
+        let range = match direction {
+            Direction::Prev => self.prev_boundary().map(|prev| prev..self.cursor),
+            Direction::Next => self.next_boundary().map(|next| self.cursor..next),
+        };
+        let Some(range) = range else {
+            return false;
+        };
+        self.cursor = range.start;
+        self.text.drain(range);
+        true
+    }
+
+    /// Moves the cursor a char, as far as either end.
+    pub fn move_cursor(&mut self, direction: Direction) {
+        let moved = match direction {
+            Direction::Prev => self.prev_boundary(),
+            Direction::Next => self.next_boundary(),
+        };
+        self.cursor = moved.unwrap_or(self.cursor);
+    }
+
+    pub fn move_to_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_to_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// Byte offset of the char before the cursor, if any.
+    fn prev_boundary(&self) -> Option<usize> {
+        let before = &self.text[..self.cursor];
+        before.char_indices().next_back().map(|(i, _)| i)
+    }
+
+    /// Byte offset just past the char under the cursor, if any.
+    fn next_boundary(&self) -> Option<usize> {
+        let after = &self.text[self.cursor..];
+        after.chars().next().map(|c| self.cursor + c.len_utf8())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy)]
+    enum Edit {
+        Insert(char),
+        Backspace,
+        Delete,
+        Left,
+        Right,
+        Home,
+        End,
+    }
+
+    fn edited(text: &str, edits: &[Edit]) -> (String, usize) {
+        let mut query = QueryInput::default();
+        query.set(text);
+        for edit in edits {
+            match *edit {
+                Edit::Insert(c) => query.insert(c),
+                Edit::Backspace => {
+                    query.delete(Direction::Prev);
+                }
+                Edit::Delete => {
+                    query.delete(Direction::Next);
+                }
+                Edit::Left => query.move_cursor(Direction::Prev),
+                Edit::Right => query.move_cursor(Direction::Next),
+                Edit::Home => query.move_to_start(),
+                Edit::End => query.move_to_end(),
+            }
+        }
+        (query.as_str().to_string(), query.cursor())
+    }
+
+    #[test]
+    fn edits() {
+        use Edit::*;
+        #[rustfmt::skip]
+        let cases = [
+            ("", vec![Left, Right, Home, End], ("", 0)),
+            // nothing to delete at either end
+            ("", vec![Backspace, Delete], ("", 0)),
+            ("q", vec![Backspace], ("", 0)),
+            ("q", vec![Delete], ("q", 1)),
+            ("q", vec![Left, Delete], ("", 0)),
+            ("q", vec![Left, Backspace], ("q", 0)),
+            ("q", vec![Left, Left], ("q", 0)),
+            ("q", vec![Home, Right, Right], ("q", 1)),
+            ("quit", vec![Left, Backspace], ("qut", 2)),
+            ("quit", vec![Home, Delete], ("uit", 0)),
+            ("quit", vec![Home, Right, Insert('x')], ("qxuit", 2)),
+            ("quit", vec![Home, End, Left], ("quit", 3)),
+            // multi-byte chars are stepped over and deleted whole
+            ("qé", vec![Left, Insert('x')], ("qxé", 2)),
+            ("qé", vec![Backspace], ("q", 1)),
+            ("qé", vec![Home, Right, Delete], ("q", 1)),
+            ("", vec![Insert('é'), Insert('x')], ("éx", 3)),
+        ];
+        for (text, edits, (expected, cursor)) in cases {
+            let actual = edited(text, &edits);
+            assert_eq!(actual, (expected.to_string(), cursor), "{:?} on {:?}", edits, text);
+        }
+    }
+
+    #[test]
+    fn cursor_column_counts_chars() {
+        let mut query = QueryInput::default();
+        query.set("añb");
+        assert_eq!((query.cursor(), query.cursor_column()), (4, 3));
+        query.move_cursor(Direction::Prev);
+        query.move_cursor(Direction::Prev);
+        assert_eq!((query.cursor(), query.cursor_column()), (1, 1));
+        assert!(query.delete(Direction::Next));
+        assert_eq!(query.as_str(), "ab");
+        query.clear();
+        assert!(query.is_empty());
+        assert!(!query.delete(Direction::Prev));
+    }
+}
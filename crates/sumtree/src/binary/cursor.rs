@@ -1,4 +1,5 @@
-use crate::{Colour, Item, Node, SumTree, Summary};
+use super::{Colour, Node, SumTree};
+use crate::{Item, Summary};
 
 #[derive(Debug)]
 pub enum Direction {
@@ -54,9 +55,12 @@ impl<'a, T: Item> Cursor<'a, T> {
         self.curr
     }
 
+    /// Descends to a leaf, asking `seek_fn` at each branch whether to go left or right. It is
+    /// passed the summary of the branch's left child, i.e. what going right would skip over, and
+    /// the branch itself. A summarizing cursor adds everything skipped to its summary.
     pub fn seek(
         &mut self,
-        mut seek_fn: impl FnMut(&'a SumTree<T>) -> Direction,
+        mut seek_fn: impl FnMut(&T::Summary, &'a SumTree<T>) -> Direction,
     ) -> Option<&'a SumTree<T>> {
         if self.curr.is_none() {
             self.curr = Some(self.tree);
@@ -64,7 +68,7 @@ impl<'a, T: Item> Cursor<'a, T> {
         while let Some(next) = self.curr {
             match next.0.as_ref() {
                 Node::Leaf { .. } => break,
-                Node::Branch { .. } => match seek_fn(next) {
+                Node::Branch { left, .. } => match seek_fn(&left.summary(), next) {
                     Direction::Left => self.goto_next_left_node_from(next),
                     Direction::Right => self.goto_next_right_node_from(next),
                 },
@@ -84,11 +88,9 @@ impl<'a, T: Item> Cursor<'a, T> {
     }
 
     fn goto_next_right_node_from(&mut self, from: &'a SumTree<T>) {
-        if let Some(ref mut summary) = self.summary {
-            match from.0.as_ref() {
-                Node::Leaf { .. } => summary.scan_leaf(&from.summary()),
-                Node::Branch { .. } => summary.scan_branch(&from.summary()),
-            }
+        match from.0.as_ref() {
+            Node::Leaf { .. } => self.skip(from),
+            Node::Branch { left, .. } => self.skip(left),
         }
         match from.0.as_ref() {
             Node::Leaf { .. } => {
@@ -118,6 +120,13 @@ impl<'a, T: Item> Cursor<'a, T> {
         }
     }
 
+    /// Adds `node`, which the cursor is moving past, to the summary of everything before it.
+    fn skip(&mut self, node: &SumTree<T>) {
+        if let Some(ref mut summary) = self.summary {
+            *summary = summary.combine(&node.summary());
+        }
+    }
+
     fn goto_next_leaf(&mut self) {
         match self.curr {
             None => self.goto_leftmost_leaf_from(self.tree),
@@ -143,12 +152,7 @@ impl<'a, T: Item> Cursor<'a, T> {
     }
 
     fn goto_next_leaf_from(&mut self, from: &'a SumTree<T>) {
-        if let Some(ref mut summary) = self.summary {
-            match from.0.as_ref() {
-                Node::Leaf { .. } => summary.scan_leaf(&from.summary()),
-                Node::Branch { .. } => summary.scan_branch(&from.summary()),
-            }
-        }
+        self.skip(from);
         let mut maybe_from = Some(from);
         while maybe_from.is_some() && !self.ancestors.is_empty() {
             let from = maybe_from.unwrap();
@@ -199,6 +203,19 @@ impl<'a, T: Item> CursorPosition<'a, T> {
         self.balance(tree)
     }
 
+    /// Replaces the leaf with `before`, `item` and `after`, in that order, as when an item is
+    /// inserted into the middle of the one that was there.
+    pub fn split_insert(self, before: T, item: T, after: T) -> SumTree<T> {
+        let (item, after) = (SumTree::new_leaf(item), SumTree::new_leaf(after));
+        let right = SumTree::new_branch(Colour::Red, item, after);
+        self.replace(SumTree::new_leaf(before), right)
+    }
+
+    pub fn replace_item(self, item: T) -> SumTree<T> {
+        let tree = SumTree::new_leaf(item);
+        self.balance(tree)
+    }
+
     fn balance(mut self, new: SumTree<T>) -> SumTree<T> {
         let mut old = self.curr;
         let mut new = new;
@@ -206,11 +223,18 @@ impl<'a, T: Item> CursorPosition<'a, T> {
             match parent.0.as_ref() {
                 Node::Leaf { .. } => unreachable!("leaf node on ancestors stack"),
                 Node::Branch { colour, left, right, .. } => {
+                    // the root ends up black either way, so it's balanced as one: a red root, as
+                    // a split can leave, would otherwise keep a red child's red child
+                    let colour = if self.ancestors.is_empty() {
+                        Colour::Black
+                    } else {
+                        *colour
+                    };
                     if old == left {
-                        let (t, _) = balance(*colour, new, right.clone());
+                        let (t, _) = balance(colour, new, right.clone());
                         new = t;
                     } else if old == right {
-                        let (t, _) = balance(*colour, left.clone(), new);
+                        let (t, _) = balance(colour, left.clone(), new);
                         new = t;
                     } else {
                         unreachable!("parent is not left or right");
@@ -225,7 +249,7 @@ impl<'a, T: Item> CursorPosition<'a, T> {
     }
 }
 
-fn make_black<T: Item>(tree: SumTree<T>) -> SumTree<T> {
+pub(crate) fn make_black<T: Item>(tree: SumTree<T>) -> SumTree<T> {
     match tree.0.as_ref() {
         Node::Branch { colour: Colour::Red, left, right, .. } => {
             SumTree::new_branch(Colour::Black, left.clone(), right.clone())
@@ -234,7 +258,11 @@ fn make_black<T: Item>(tree: SumTree<T>) -> SumTree<T> {
     }
 }
 
-fn balance<T: Item>(colour: Colour, left: SumTree<T>, right: SumTree<T>) -> (SumTree<T>, bool) {
+pub(crate) fn balance<T: Item>(
+    colour: Colour,
+    left: SumTree<T>,
+    right: SumTree<T>,
+) -> (SumTree<T>, bool) {
     use Colour::*;
 
     if colour == Red {
@@ -366,34 +394,103 @@ mod tests {
          *         1 2 3 4 5 6
          */
         {
+            // each branch along with the summary of its left child
             let mut directions = vec![
-                (&b2, Direction::Right),
-                (&b3, Direction::Right),
-                (&b6, Direction::Left),
+                (&b2, Sum(3), Direction::Right),
+                (&b3, Sum(3), Direction::Right),
+                (&b6, Sum(10), Direction::Left),
             ];
             let mut cursor = tree.cursor_with_summary();
             assert_eq!(
-                cursor.seek(|node| {
-                    let (expected, direction) = directions.pop().unwrap();
-                    assert_eq!(node, expected);
+                cursor.seek(|left, node| {
+                    let (expected, expected_left, direction) = directions.pop().unwrap();
+                    assert_eq!((node, *left), (expected, expected_left));
                     direction
                 }),
                 Some(&v4)
             );
-            assert_eq!(cursor.summary(), Sum(17));
+            // 1 + 2 + 3, only what was skipped to reach 4
+            assert_eq!(cursor.summary(), Sum(6));
         }
         {
             let mut directions = vec![(&b5, Direction::Right), (&b6, Direction::Right)];
             let mut cursor = tree.cursor_with_summary();
             assert_eq!(
-                cursor.seek(|node| {
+                cursor.seek(|_, node| {
                     let (expected, direction) = directions.pop().unwrap();
                     assert_eq!(node, expected);
                     direction
                 }),
                 Some(&v7)
             );
-            assert_eq!(cursor.summary(), Sum(46));
+            assert_eq!(cursor.summary(), Sum(21));
+        }
+    }
+
+    #[test]
+    fn split_insert_under_red_root() {
+        // a red root, as a split can leave, over the leaf split around the new item
+        let tree = branch_r!(leaf!(V(1)), leaf!(V(2)));
+        let mut cursor = tree.cursor();
+        cursor.next();
+        cursor.next();
+        let tree = cursor.into_position().split_insert(V(3), V(4), V(5));
+        assert!(tree.is_balanced());
+        let mut cursor = tree.cursor();
+        let items: Vec<_> =
+            std::iter::from_fn(|| cursor.next().map(|n| n.deref_item().0)).collect();
+        assert_eq!(items, [1, 3, 4, 5]);
+    }
+
+    /// A summary with nothing but its own totals, unlike rope metrics.
+    #[derive(Debug, Default, Clone, Copy, PartialEq)]
+    struct Stretch {
+        count: usize,
+        max: u32,
+    }
+
+    impl Summary for Stretch {
+        fn combine(&self, rhs: &Self) -> Self {
+            Stretch { count: self.count + rhs.count, max: self.max.max(rhs.max) }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct W(u32);
+
+    impl Item for W {
+        type Summary = Stretch;
+
+        fn summary(&self) -> Self::Summary {
+            Stretch { count: 1, max: self.0 }
+        }
+    }
+
+    #[test]
+    fn seek_with_any_summary() {
+        let values: Vec<u32> = (0..50).map(|i| i * 7 % 13).collect();
+        let tree = SumTree::from_items(values.iter().copied().map(W)).unwrap();
+        let before =
+            |n: usize| Stretch { count: n, max: values[..n].iter().copied().max().unwrap_or(0) };
+
+        for n in [0, 1, 20, 49] {
+            let mut remaining = n;
+            let mut cursor = tree.cursor_with_summary();
+            let leaf = cursor.seek(|left, _| {
+                if remaining < left.count {
+                    Direction::Left
+                } else {
+                    remaining -= left.count;
+                    Direction::Right
+                }
+            });
+            assert_eq!(leaf.map(|leaf| leaf.deref_item()), Some(&W(values[n])));
+            assert_eq!(cursor.summary(), before(n), "seek to {}", n);
+
+            if n + 1 < values.len() {
+                cursor.next();
+                assert_eq!(cursor.summary(), before(n + 1), "next after {}", n);
+            }
         }
     }
 }
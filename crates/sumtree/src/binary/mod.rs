@@ -0,0 +1,285 @@
+use std::sync::Arc;
+use std::{fmt, ops::Deref};
+
+pub mod cursor;
+
+use crate::{Item, Summary};
+use cursor::{balance, make_black, Cursor};
+
+#[derive(Debug)]
+pub enum Error {
+    ConsecutiveRed,
+    DifferingBlackHeight,
+}
+
+#[derive(Debug, Clone)]
+pub struct SumTree<T: Item>(pub(crate) Arc<Node<T>>);
+
+impl<T: Item> Deref for SumTree<T> {
+    type Target = Arc<Node<T>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Item> SumTree<T> {
+    pub fn new_leaf(item: T) -> Self {
+        Self(Arc::new(Node::new_leaf(item)))
+    }
+
+    pub fn new_branch(colour: Colour, left: SumTree<T>, right: SumTree<T>) -> Self {
+        Self(Arc::new(Node::new_branch(colour, left, right)))
+    }
+
+    /// Builds a balanced tree from `items` in a single pass, without rebalancing per item.
+    pub fn from_items(items: impl IntoIterator<Item = T>) -> Option<Self> {
+        let leaves: Vec<_> = items.into_iter().map(SumTree::new_leaf).collect();
+        if leaves.is_empty() {
+            return None;
+        }
+        let depth = leaves.len().ilog2();
+        Some(make_black(build_balanced(&leaves, depth)))
+    }
+
+    /// Concatenates two trees, descending only the spine of the taller one.
+    pub fn join(left: SumTree<T>, right: SumTree<T>) -> Self {
+        let (left, right) = (make_black(left), make_black(right));
+        let (lheight, rheight) = (left.0.spine_height(), right.0.spine_height());
+        let tree = if lheight >= rheight {
+            join_right(left, lheight, right, rheight)
+        } else {
+            join_left(left, lheight, right, rheight)
+        };
+        make_black(tree)
+    }
+
+    pub fn deref_item(&self) -> &T {
+        self.0.deref_item()
+    }
+
+    pub fn summary(&self) -> T::Summary {
+        self.0.summary()
+    }
+
+    /// The same tree with `summary` in place of the root's own, whether or not it's right; so
+    /// checks of a tree's summaries can be tested against one that's wrong.
+    #[doc(hidden)]
+    pub fn with_summary(&self, summary: T::Summary) -> Self {
+        let node = match self.0.as_ref() {
+            Node::Branch { colour, left, right, .. } => {
+                Node::Branch { colour: *colour, left: left.clone(), right: right.clone(), summary }
+            }
+            Node::Leaf { item, .. } => Node::Leaf { item: item.clone(), summary },
+        };
+        Self(Arc::new(node))
+    }
+
+    pub fn cursor(&self) -> Cursor<'_, T> {
+        Cursor::new(self)
+    }
+
+    pub fn cursor_with_summary(&self) -> Cursor<'_, T> {
+        Cursor::with_summary(self)
+    }
+
+    /// Whether the tree keeps the red-black invariants: no red branch under a red branch, and
+    /// as many black branches on the way to every leaf.
+    pub fn is_balanced(&self) -> bool {
+        self.0.black_height().is_ok()
+    }
+
+    /// The most branches on the way from the root to a leaf, red or black.
+    pub fn depth(&self) -> usize {
+        match self.0.as_ref() {
+            Node::Leaf { .. } => 0,
+            Node::Branch { left, right, .. } => 1 + left.depth().max(right.depth()),
+        }
+    }
+}
+
+impl<T: Item> PartialEq for &SumTree<T> {
+    fn eq(&self, other: &&SumTree<T>) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[derive(Debug)]
+pub enum Node<T: Item> {
+    Branch {
+        colour: Colour,
+        left: SumTree<T>,
+        right: SumTree<T>,
+        summary: T::Summary,
+    },
+    Leaf {
+        item: T,
+        summary: T::Summary,
+    },
+}
+
+impl<T: Item> Node<T> {
+    fn new_branch(colour: Colour, left: SumTree<T>, right: SumTree<T>) -> Self {
+        let summary = left.0.summary().combine(&right.0.summary());
+        Node::Branch { colour, left, right, summary }
+    }
+
+    fn new_leaf(item: T) -> Self {
+        let summary = item.summary();
+        Node::Leaf { item, summary }
+    }
+
+    pub fn deref_item(&self) -> &T {
+        match self {
+            Node::Branch { .. } => unreachable!("called `Node::deref_item()` on a `Branch` node"),
+            Node::Leaf { ref item, .. } => item,
+        }
+    }
+
+    /// The branch's left then right child; a leaf has none.
+    pub fn children(&self) -> impl DoubleEndedIterator<Item = &SumTree<T>> {
+        let (left, right) = match self {
+            Node::Branch { left, right, .. } => (Some(left), Some(right)),
+            Node::Leaf { .. } => (None, None),
+        };
+        left.into_iter().chain(right)
+    }
+
+    /// What dumps call a branch: its colour.
+    pub(crate) fn label(&self) -> &'static str {
+        self.dot_colour()
+    }
+
+    /// The colour Graphviz draws a branch in.
+    pub(crate) fn dot_colour(&self) -> &'static str {
+        match self {
+            Node::Branch { colour: Colour::Red, .. } => "red",
+            _ => "black",
+        }
+    }
+
+    fn summary(&self) -> T::Summary {
+        match self {
+            Node::Branch { summary, .. } => *summary,
+            Node::Leaf { summary, .. } => *summary,
+        }
+    }
+
+    fn spine_height(&self) -> usize {
+        match self {
+            Node::Leaf { .. } => 0,
+            Node::Branch { colour, left, .. } => {
+                left.0.spine_height() + (colour.black_height() as usize)
+            }
+        }
+    }
+
+    // fn colour(&self) -> Colour {
+    //     match self {
+    //         Node::Branch { colour, .. } => *colour,
+    //         Node::Leaf { .. } => Colour::Black,
+    //     }
+    // }
+
+    fn black_height(&self) -> Result<usize, Error> {
+        match self {
+            Node::Leaf { .. } => Ok(0),
+            Node::Branch { colour, left, right, .. } => {
+                if *colour == Colour::Red {
+                    if let Node::Branch { colour: Colour::Red, .. } = left.0.as_ref() {
+                        return Err(Error::ConsecutiveRed);
+                    }
+                    if let Node::Branch { colour: Colour::Red, .. } = right.0.as_ref() {
+                        return Err(Error::ConsecutiveRed);
+                    }
+                }
+
+                let lheight = &left.0.black_height()?;
+                let rheight = &right.0.black_height()?;
+                if lheight != rheight {
+                    return Err(Error::DifferingBlackHeight);
+                }
+                Ok(lheight + (colour.black_height() as usize))
+            }
+        }
+    }
+}
+
+// `leaves.len()` must be in `2^depth..=2^(depth + 1)`; leaves that don't fit in the bottom row of
+// black nodes are paired under red branches so every path has the same black height.
+fn build_balanced<T: Item>(leaves: &[SumTree<T>], depth: u32) -> SumTree<T> {
+    if depth == 0 {
+        return match leaves {
+            [leaf] => leaf.clone(),
+            [left, right] => SumTree::new_branch(Colour::Red, left.clone(), right.clone()),
+            _ => unreachable!("too many leaves for depth"),
+        };
+    }
+    let (left, right) = leaves.split_at(leaves.len() / 2);
+    let left = build_balanced(left, depth - 1);
+    let right = build_balanced(right, depth - 1);
+    SumTree::new_branch(Colour::Black, left, right)
+}
+
+fn join_right<T: Item>(
+    left: SumTree<T>,
+    lheight: usize,
+    right: SumTree<T>,
+    rheight: usize,
+) -> SumTree<T> {
+    match left.0.as_ref() {
+        Node::Branch { colour, left: ll, right: lr, .. }
+            if *colour == Colour::Red || lheight > rheight =>
+        {
+            let height = lheight - (colour.black_height() as usize);
+            let joined = join_right(lr.clone(), height, right, rheight);
+            let (tree, _) = balance(*colour, ll.clone(), joined);
+            tree
+        }
+        _ => SumTree::new_branch(Colour::Red, left, right),
+    }
+}
+
+fn join_left<T: Item>(
+    left: SumTree<T>,
+    lheight: usize,
+    right: SumTree<T>,
+    rheight: usize,
+) -> SumTree<T> {
+    match right.0.as_ref() {
+        Node::Branch { colour, left: rl, right: rr, .. }
+            if *colour == Colour::Red || rheight > lheight =>
+        {
+            let height = rheight - (colour.black_height() as usize);
+            let joined = join_left(left, lheight, rl.clone(), height);
+            let (tree, _) = balance(*colour, joined, rr.clone());
+            tree
+        }
+        _ => SumTree::new_branch(Colour::Red, left, right),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Colour {
+    Red,
+    Black,
+}
+
+impl Colour {
+    pub fn black_height(&self) -> u8 {
+        match self {
+            Colour::Red => 0,
+            Colour::Black => 1,
+        }
+    }
+}
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Colour::Red => write!(f, "red")?,
+            Colour::Black => write!(f, "black")?,
+        }
+        Ok(())
+    }
+}
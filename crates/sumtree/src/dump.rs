@@ -0,0 +1,209 @@
+use std::io::{Result, Write};
+
+use crate::{Item, Node, SumTree};
+
+/// How many chars of each item `write_dot` and `write_text` show.
+pub const PREVIEW_LEN: usize = 24;
+
+impl<T: Item> SumTree<T> {
+    pub fn write_dot(&self, w: &mut impl Write) -> Result<()> {
+        self.write_dot_with_preview(w, PREVIEW_LEN)
+    }
+
+    /// Writes the tree as a Graphviz digraph, previewing at most `preview_len` chars per item.
+    pub fn write_dot_with_preview(&self, w: &mut impl Write, preview_len: usize) -> Result<()> {
+        writeln!(w, "digraph G {{")?;
+        self.0.write_dot(w, preview_len)?;
+        writeln!(w, "}}")?;
+        Ok(())
+    }
+
+    pub fn write_text(&self, w: &mut impl Write) -> Result<()> {
+        self.write_text_with_preview(w, PREVIEW_LEN)
+    }
+
+    /// Writes the tree as indented text, one node per line, for reading without Graphviz.
+    pub fn write_text_with_preview(&self, w: &mut impl Write, preview_len: usize) -> Result<()> {
+        self.0.write_text(w, 0, preview_len)
+    }
+}
+
+impl<T: Item> Node<T> {
+    fn write_dot(&self, w: &mut impl Write, preview_len: usize) -> Result<()> {
+        match self {
+            Node::Branch { summary, .. } => {
+                let label = escape_label(&format!("{:?}", summary));
+                let colour = self.dot_colour();
+                writeln!(w, "\tn{:p}[shape=circle,color={},label=\"{}\"];", self, colour, label)?;
+
+                for child in self.children() {
+                    child.0.write_dot(w, preview_len)?;
+                    writeln!(w, "\tn{:p} -> n{:p};", self, child.0.as_ref())?;
+                }
+            }
+            Node::Leaf { item, .. } => {
+                let label = escape_label(&item.preview(preview_len));
+                writeln!(w, "\tn{:p}[shape=square,label=\"{}\"];", self, label)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_text(&self, w: &mut impl Write, depth: usize, preview_len: usize) -> Result<()> {
+        let indent = depth * 2;
+        match self {
+            Node::Branch { summary, .. } => {
+                writeln!(w, "{:indent$}{} {:?}", "", self.label(), summary)?;
+                for child in self.children() {
+                    child.0.write_text(w, depth + 1, preview_len)?;
+                }
+            }
+            Node::Leaf { item, summary } => {
+                let preview = item.preview(preview_len);
+                writeln!(w, "{:indent$}leaf {:?} {:?}", "", summary, preview)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the first `max_len` chars of `s`, marking any cut with an ellipsis.
+pub fn truncate(s: &str, max_len: usize) -> String {
+    match s.char_indices().nth(max_len) {
+        None => s.to_string(),
+        Some((end, _)) => format!("{}…", &s[..end]),
+    }
+}
+
+/// Escapes `s` for use inside a quoted DOT label.
+fn escape_label(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => {
+                escaped.push_str("\\\\");
+                escaped.extend(c.escape_unicode().skip(1));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Sum;
+
+    #[derive(Clone)]
+    struct S(&'static str);
+
+    // like `Slab`, prints its contents raw
+    impl std::fmt::Debug for S {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "S({})", self.0)
+        }
+    }
+
+    impl Item for S {
+        type Summary = Sum;
+
+        fn summary(&self) -> Self::Summary {
+            Sum(self.0.len() as u32)
+        }
+    }
+
+    const ITEMS: [&str; 4] = ["say \"hi\"\n", "C:\\dir\\", "a\r\nb\t{}", "x"];
+
+    fn tree() -> SumTree<S> {
+        SumTree::from_items(ITEMS.map(S)).unwrap()
+    }
+
+    /// Returns the unescaped labels in `dot`, failing on anything that would not parse.
+    fn parse_labels(dot: &str) -> Vec<String> {
+        let mut lines = dot.lines();
+        assert_eq!(lines.next(), Some("digraph G {"));
+        assert_eq!(dot.lines().last(), Some("}"));
+
+        let mut labels = vec![];
+        for line in lines.filter(|l| l.contains('[')) {
+            let (_, rest) = line.split_once("label=\"").expect("node has a label");
+            let mut label = String::new();
+            let mut chars = rest.chars();
+            loop {
+                match chars.next().expect("unterminated label") {
+                    '"' => break,
+                    '\\' => match chars.next().expect("dangling escape") {
+                        'n' => label.push('\n'),
+                        c @ ('"' | '\\') => label.push(c),
+                        c => panic!("unknown escape \\{} in {:?}", c, line),
+                    },
+                    c => label.push(c),
+                }
+            }
+            assert_eq!(chars.as_str(), "];", "{:?}", line);
+            labels.push(label);
+        }
+        labels
+    }
+
+    #[test]
+    fn dot_labels_are_escaped() {
+        let mut dot = vec![];
+        tree().write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+
+        let labels = parse_labels(&dot);
+        // four leaves, under three binary branches or one wide one
+        let branches = if cfg!(feature = "wide") { 1 } else { 3 };
+        assert_eq!(labels.len(), ITEMS.len() + branches, "{}", dot);
+        assert!(labels.contains(&"S(say \"hi\"\n)".to_string()));
+        assert!(labels.contains(&"S(C:\\dir\\)".to_string()));
+        assert!(labels.contains(&"S(a\\u{d}\nb\\u{9}{})".to_string()));
+        assert!(labels.contains(&format!("{:?}", Sum(24))));
+    }
+
+    #[test]
+    fn previews_are_truncated() {
+        let mut dot = vec![];
+        tree().write_dot_with_preview(&mut dot, 4).unwrap();
+        let labels = parse_labels(&String::from_utf8(dot).unwrap());
+        assert!(labels.contains(&"S(sa…".to_string()), "{:?}", labels);
+        assert!(labels.contains(&"S(x)".to_string()), "{:?}", labels);
+
+        let mut text = vec![];
+        tree().write_text_with_preview(&mut text, 4).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        #[cfg(feature = "wide")]
+        assert_eq!(
+            text,
+            [
+                "branch Sum(24)",
+                "  leaf Sum(9) \"S(sa…\"",
+                "  leaf Sum(7) \"S(C:…\"",
+                "  leaf Sum(7) \"S(a\\r…\"",
+                "  leaf Sum(1) \"S(x)\"",
+                "",
+            ]
+            .join("\n")
+        );
+        #[cfg(not(feature = "wide"))]
+        assert_eq!(
+            text,
+            [
+                "black Sum(24)",
+                "  black Sum(16)",
+                "    leaf Sum(9) \"S(sa…\"",
+                "    leaf Sum(7) \"S(C:…\"",
+                "  black Sum(8)",
+                "    leaf Sum(7) \"S(a\\r…\"",
+                "    leaf Sum(1) \"S(x)\"",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
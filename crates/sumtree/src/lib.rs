@@ -1,17 +1,27 @@
-use std::sync::Arc;
-use std::{fmt, ops::Deref};
-
-pub mod cursor;
+use std::fmt;
+
+// A red-black binary tree by default, or with the `wide` feature, a B-tree whose branches hold
+// up to `MAX_ARITY` children, so a large tree is a few levels deep rather than dozens. Both have
+// the same surface, so users needn't know which they're built against.
+#[cfg(not(feature = "wide"))]
+mod binary;
+mod dump;
 mod macros;
+#[cfg(feature = "wide")]
+mod wide;
 
-pub use cursor::{Cursor, Direction as CursorDirection};
+#[cfg(not(feature = "wide"))]
+pub use binary::{cursor, Colour, Error, Node, SumTree};
+pub use cursor::{Cursor, CursorPosition, Direction as CursorDirection};
+pub use dump::{truncate, PREVIEW_LEN};
+#[cfg(feature = "wide")]
+pub use wide::{cursor, Error, Node, SumTree, MAX_ARITY, MIN_ARITY};
 
 pub trait Summary: Default + Clone + Copy + fmt::Debug {
+    /// The summary of `self` followed by `rhs`. Cursors accumulate their position with this too,
+    /// so it is all a summary needs to be seekable.
     fn combine(&self, rhs: &Self) -> Self;
 
-    fn scan_leaf(&mut self, lhs: &Self);
-    fn scan_branch(&mut self, lhs: &Self);
-
     fn empty() -> Self {
         Default::default()
     }
@@ -21,195 +31,21 @@ pub trait Item: Clone + fmt::Debug {
     type Summary: Summary;
 
     fn summary(&self) -> Self::Summary;
-}
-
-#[derive(Debug)]
-pub enum Error {
-    ConsecutiveRed,
-    DifferingBlackHeight,
-}
-
-#[derive(Debug, Clone)]
-pub struct SumTree<T: Item>(Arc<Node<T>>);
-
-impl<T: Item> Deref for SumTree<T> {
-    type Target = Arc<Node<T>>;
-
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
-
-impl<T: Item> SumTree<T> {
-    pub fn new_leaf(item: T) -> Self {
-        Self(Arc::new(Node::new_leaf(item)))
-    }
-
-    pub fn new_branch(colour: Colour, left: SumTree<T>, right: SumTree<T>) -> Self {
-        Self(Arc::new(Node::new_branch(colour, left, right)))
-    }
-
-    pub fn deref_item(&self) -> &T {
-        self.0.deref_item()
-    }
-
-    pub fn summary(&self) -> T::Summary {
-        self.0.summary()
-    }
-
-    pub fn cursor(&self) -> Cursor<'_, T> {
-        Cursor::new(self)
-    }
 
-    pub fn cursor_with_summary(&self) -> Cursor<'_, T> {
-        Cursor::with_summary(self)
-    }
-
-    pub fn is_balanced(&self) -> bool {
-        self.0.black_height().is_ok()
-    }
-
-    pub fn write_dot(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
-        writeln!(w, "digraph G {{")?;
-        self.0.write_dot(w)?;
-        writeln!(w, "}}")?;
-        Ok(())
-    }
-}
-
-impl<T: Item> PartialEq for &SumTree<T> {
-    fn eq(&self, other: &&SumTree<T>) -> bool {
-        Arc::ptr_eq(&self.0, &other.0)
-    }
-}
-
-#[derive(Debug)]
-pub enum Node<T: Item> {
-    Branch {
-        colour: Colour,
-        left: SumTree<T>,
-        right: SumTree<T>,
-        summary: T::Summary,
-    },
-    Leaf {
-        item: T,
-        summary: T::Summary,
-    },
-}
-
-impl<T: Item> Node<T> {
-    fn new_branch(colour: Colour, left: SumTree<T>, right: SumTree<T>) -> Self {
-        let summary = left.0.summary().combine(&right.0.summary());
-        Node::Branch { colour, left, right, summary }
-    }
-
-    fn new_leaf(item: T) -> Self {
-        let summary = item.summary();
-        Node::Leaf { item, summary }
-    }
-
-    pub fn deref_item(&self) -> &T {
-        match self {
-            Node::Branch { .. } => unreachable!("called `Node::deref_item()` on a `Branch` node"),
-            Node::Leaf { ref item, .. } => item,
-        }
-    }
-
-    fn summary(&self) -> T::Summary {
-        match self {
-            Node::Branch { summary, .. } => *summary,
-            Node::Leaf { summary, .. } => *summary,
-        }
-    }
-
-    // fn colour(&self) -> Colour {
-    //     match self {
-    //         Node::Branch { colour, .. } => *colour,
-    //         Node::Leaf { .. } => Colour::Black,
-    //     }
-    // }
-
-    fn black_height(&self) -> Result<usize, Error> {
-        match self {
-            Node::Leaf { .. } => Ok(0),
-            Node::Branch { colour, left, right, .. } => {
-                if *colour == Colour::Red {
-                    if let Node::Branch { colour: Colour::Red, .. } = left.0.as_ref() {
-                        return Err(Error::ConsecutiveRed);
-                    }
-                    if let Node::Branch { colour: Colour::Red, .. } = right.0.as_ref() {
-                        return Err(Error::ConsecutiveRed);
-                    }
-                }
-
-                let lheight = &left.0.black_height()?;
-                let rheight = &right.0.black_height()?;
-                if lheight != rheight {
-                    return Err(Error::DifferingBlackHeight);
-                }
-                Ok(lheight + (colour.black_height() as usize))
-            }
-        }
-    }
-
-    fn write_dot(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
-        match self {
-            Node::Branch { colour, left, right, summary, .. } => {
-                writeln!(
-                    w,
-                    "\tn{:p}[shape=circle,color={},label=\"{:?}\"];",
-                    self, colour, summary
-                )?;
-
-                left.0.write_dot(w)?;
-                writeln!(w, "\tn{:p} -> n{:p};", self, left.0.as_ref())?;
-
-                right.0.write_dot(w)?;
-                writeln!(w, "\tn{:p} -> n{:p};", self, right.0.as_ref())?;
-            }
-            Node::Leaf { item, .. } => {
-                writeln!(w, "\tn{:p}[shape=square,label=\"{:?}\"];", self, item)?;
-            }
-        }
-        Ok(())
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum Colour {
-    Red,
-    Black,
-}
-
-impl Colour {
-    pub fn black_height(&self) -> u8 {
-        match self {
-            Colour::Red => 0,
-            Colour::Black => 1,
-        }
-    }
-}
-
-impl fmt::Display for Colour {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Colour::Red => write!(f, "red")?,
-            Colour::Black => write!(f, "black")?,
-        }
-        Ok(())
+    /// A short description of the item for tree dumps, keeping at most `max_len` chars.
+    fn preview(&self, max_len: usize) -> String {
+        truncate(&format!("{:?}", self), max_len)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cursor::Direction;
     use crate::macros::*;
 
     #[test]
     fn build_tree() {
-        let _ = std::fs::remove_dir_all("target/tests/");
-        std::fs::create_dir_all("target/tests/").expect("create directory");
-
         let tree = leaf!(V(5));
         assert!(tree.is_balanced());
         assert_eq!(tree.summary(), Sum(5));
@@ -227,8 +63,7 @@ mod tests {
 
         cursor.reset();
         let tree = cursor.into_position().insert_left(V(1));
-        let mut file = std::fs::File::create("target/tests/insert01.dot").expect("create file");
-        tree.write_dot(&mut file).expect("write dot file");
+        test_support::dump("insert01.dot", |w| tree.write_dot(w));
         assert!(tree.is_balanced());
         assert_eq!(tree.summary(), Sum(10));
 
@@ -238,8 +73,7 @@ mod tests {
         assert_eq!(cursor.next().map(|n| n.0.deref_item()), Some(&V(5)));
 
         let tree = cursor.into_position().insert_right(V(9));
-        let mut file = std::fs::File::create("target/tests/insert09.dot").expect("create file");
-        tree.write_dot(&mut file).expect("write dot file");
+        test_support::dump("insert09.dot", |w| tree.write_dot(w));
         assert!(tree.is_balanced());
         assert_eq!(tree.summary(), Sum(19));
 
@@ -250,6 +84,46 @@ mod tests {
         assert_eq!(cursor.next().map(|n| n.0.deref_item()), Some(&V(9)));
     }
 
+    fn items(tree: &SumTree<V>) -> Vec<u32> {
+        let mut cursor = tree.cursor();
+        std::iter::from_fn(|| cursor.next().map(|n| n.0.deref_item().0)).collect()
+    }
+
+    #[test]
+    fn from_items() {
+        assert!(SumTree::<V>::from_items(vec![]).is_none());
+        for n in 1..=70 {
+            let tree = SumTree::from_items((0..n).map(V)).expect("non-empty tree");
+            assert!(tree.is_balanced(), "unbalanced tree of {} items", n);
+            assert_eq!(tree.summary(), Sum((0..n).sum()));
+            assert_eq!(items(&tree), (0..n).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn join() {
+        for (lcount, rcount) in [(1, 1), (1, 40), (40, 1), (7, 9), (33, 64), (100, 3)] {
+            let left = SumTree::from_items((0..lcount).map(V)).unwrap();
+            let right = SumTree::from_items((lcount..lcount + rcount).map(V)).unwrap();
+            let tree = SumTree::join(left, right);
+            assert!(tree.is_balanced(), "unbalanced join of {} and {}", lcount, rcount);
+            assert_eq!(items(&tree), (0..lcount + rcount).collect::<Vec<_>>());
+        }
+
+        // trees grown by insertion carry red nodes along their spines
+        let mut left = leaf!(V(0));
+        for i in 1..20 {
+            let mut cursor = left.cursor();
+            cursor.seek(|_, _| Direction::Right);
+            left = cursor.into_position().insert_right(V(i));
+        }
+        assert_eq!(items(&left), (0..20).collect::<Vec<_>>());
+        let right = SumTree::from_items((20..25).map(V)).unwrap();
+        let tree = SumTree::join(left, right);
+        assert!(tree.is_balanced());
+        assert_eq!(items(&tree), (0..25).collect::<Vec<_>>());
+    }
+
     #[derive(Debug, Clone, PartialEq)]
     pub(crate) struct V(pub(crate) u32);
 
@@ -268,13 +142,5 @@ mod tests {
         fn combine(&self, rhs: &Self) -> Self {
             Sum(self.0 + rhs.0)
         }
-
-        fn scan_branch(&mut self, lhs: &Self) {
-            self.0 += lhs.0;
-        }
-
-        fn scan_leaf(&mut self, lhs: &Self) {
-            self.0 += lhs.0;
-        }
     }
 }
@@ -1,3 +1,4 @@
+#[cfg(not(feature = "wide"))]
 #[allow(unused_macros)]
 macro_rules! branch {
     ($colour:expr, $left:expr, $right:expr $(,)?) => {{
@@ -5,6 +6,7 @@ macro_rules! branch {
     }};
 }
 
+#[cfg(not(feature = "wide"))]
 #[allow(unused_macros)]
 macro_rules! branch_r {
     ($left:expr, $right:expr $(,)?) => {{
@@ -12,6 +14,7 @@ macro_rules! branch_r {
     }};
 }
 
+#[cfg(not(feature = "wide"))]
 #[allow(unused_macros)]
 macro_rules! branch_b {
     ($left:expr, $right:expr $(,)?) => {{
@@ -27,4 +30,7 @@ macro_rules! leaf {
 }
 
 #[allow(unused_imports)]
-pub(super) use {branch, branch_b, branch_r, leaf};
+pub(super) use leaf;
+#[cfg(not(feature = "wide"))]
+#[allow(unused_imports)]
+pub(super) use {branch, branch_b, branch_r};
@@ -0,0 +1,260 @@
+use super::{grouped, root, SumTree};
+use crate::{Item, Summary};
+
+#[derive(Debug)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+#[derive(Debug)]
+pub struct Cursor<'a, T: Item> {
+    tree: &'a SumTree<T>,
+    /// The branches above `curr`, each with the index of the child the cursor went into.
+    ancestors: Vec<(&'a SumTree<T>, usize)>,
+    curr: Option<&'a SumTree<T>>,
+    summary: Option<T::Summary>,
+}
+
+impl<'a, T: Item> Cursor<'a, T> {
+    pub fn new(tree: &'a SumTree<T>) -> Self {
+        Self { tree, ancestors: vec![], curr: None, summary: None }
+    }
+
+    pub fn with_summary(tree: &'a SumTree<T>) -> Self {
+        Self { tree, ancestors: vec![], curr: None, summary: Some(T::Summary::default()) }
+    }
+
+    pub fn curr(&self) -> Option<&'a SumTree<T>> {
+        self.curr
+    }
+
+    pub fn summary(&self) -> T::Summary {
+        self.summary
+            .expect("Cursor::summary() invoked on non-summarizing cursor")
+    }
+
+    pub fn into_position(mut self) -> CursorPosition<'a, T> {
+        if self.curr.is_none() {
+            self.goto_leftmost_leaf_from(self.tree);
+        }
+        match self.curr {
+            None => unreachable!("empty sum tree"),
+            Some(curr) => CursorPosition::new(curr, self.ancestors),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.ancestors.clear();
+        self.curr = None;
+        self.summary = self.summary.map(|_| T::Summary::default());
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&'a SumTree<T>> {
+        self.goto_next_leaf();
+        self.curr
+    }
+
+    /// Descends to a leaf, asking `seek_fn` at each branch about its children in turn whether to
+    /// go into one (left) or past it (right). It is passed the summary of the child, i.e. what
+    /// going right would skip over, and the branch itself. The last child is gone into without
+    /// asking, as there's nowhere past it to go, so a branch of two is asked once, as a binary
+    /// one is. A summarizing cursor adds everything skipped to its summary.
+    pub fn seek(
+        &mut self,
+        mut seek_fn: impl FnMut(&T::Summary, &'a SumTree<T>) -> Direction,
+    ) -> Option<&'a SumTree<T>> {
+        if self.curr.is_none() {
+            self.curr = Some(self.tree);
+        }
+        while let Some(next) = self.curr {
+            let children = next.0.children().as_slice();
+            if children.is_empty() {
+                break;
+            }
+            let mut index = 0;
+            while index + 1 < children.len() {
+                match seek_fn(&children[index].summary(), next) {
+                    Direction::Left => break,
+                    Direction::Right => {
+                        self.skip(&children[index]);
+                        index += 1;
+                    }
+                }
+            }
+            self.ancestors.push((next, index));
+            self.curr = Some(&children[index]);
+        }
+        self.curr
+    }
+
+    /// Adds `node`, which the cursor is moving past, to the summary of everything before it.
+    fn skip(&mut self, node: &SumTree<T>) {
+        if let Some(ref mut summary) = self.summary {
+            *summary = summary.combine(&node.summary());
+        }
+    }
+
+    fn goto_next_leaf(&mut self) {
+        match self.curr {
+            None => self.goto_leftmost_leaf_from(self.tree),
+            Some(curr) => self.goto_next_leaf_from(curr),
+        }
+    }
+
+    fn goto_leftmost_leaf_from(&mut self, from: &'a SumTree<T>) {
+        let mut node = from;
+        while let Some(first) = node.0.children().next() {
+            self.ancestors.push((node, 0));
+            node = first;
+        }
+        self.curr = Some(node);
+    }
+
+    fn goto_next_leaf_from(&mut self, from: &'a SumTree<T>) {
+        self.skip(from);
+        while let Some((parent, index)) = self.ancestors.pop() {
+            if let Some(sibling) = parent.0.children().nth(index + 1) {
+                self.ancestors.push((parent, index + 1));
+                self.goto_leftmost_leaf_from(sibling);
+                return;
+            }
+        }
+        self.curr = None;
+    }
+}
+
+pub struct CursorPosition<'a, T: Item> {
+    ancestors: Vec<(&'a SumTree<T>, usize)>,
+    curr: &'a SumTree<T>,
+}
+
+impl<'a, T: Item> CursorPosition<'a, T> {
+    pub fn new(curr: &'a SumTree<T>, ancestors: Vec<(&'a SumTree<T>, usize)>) -> Self {
+        Self { ancestors, curr }
+    }
+
+    pub fn insert_left(self, item: T) -> SumTree<T> {
+        let curr = self.curr.clone();
+        self.splice(vec![SumTree::new_leaf(item), curr])
+    }
+
+    pub fn insert_right(self, item: T) -> SumTree<T> {
+        let curr = self.curr.clone();
+        self.splice(vec![curr, SumTree::new_leaf(item)])
+    }
+
+    /// Replaces the leaf with the nodes of `left` and `right`, which must be leaves or, for a
+    /// leaf with no branch above it, trees of any height.
+    pub fn replace(self, left: SumTree<T>, right: SumTree<T>) -> SumTree<T> {
+        if self.ancestors.is_empty() {
+            SumTree::join(left, right)
+        } else {
+            self.splice(vec![left, right])
+        }
+    }
+
+    /// Replaces the leaf with `before`, `item` and `after`, in that order, as when an item is
+    /// inserted into the middle of the one that was there.
+    pub fn split_insert(self, before: T, item: T, after: T) -> SumTree<T> {
+        let leaves = [before, item, after].map(SumTree::new_leaf);
+        self.splice(leaves.into())
+    }
+
+    pub fn replace_item(self, item: T) -> SumTree<T> {
+        self.splice(vec![SumTree::new_leaf(item)])
+    }
+
+    /// Puts `nodes` where the leaf was, splitting each branch on the way up that overflows, and
+    /// growing a new root if the old one did.
+    fn splice(mut self, mut nodes: Vec<SumTree<T>>) -> SumTree<T> {
+        while let Some((parent, index)) = self.ancestors.pop() {
+            let children = parent.0.children().as_slice();
+            let mut spliced = Vec::with_capacity(children.len() + nodes.len());
+            spliced.extend_from_slice(&children[..index]);
+            spliced.append(&mut nodes);
+            spliced.extend_from_slice(&children[index + 1..]);
+            nodes = grouped(spliced);
+        }
+        root(nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macros::*;
+    use crate::tests::*;
+    use crate::MAX_ARITY;
+
+    #[test]
+    fn iterate_tests() {
+        let leaves: Vec<_> = (1..=7).map(|i| leaf!(V(i))).collect();
+        let b1 = SumTree::new_branch(leaves[..3].iter().cloned());
+        let b2 = SumTree::new_branch(leaves[3..].iter().cloned());
+        let tree = SumTree::new_branch([b1, b2]);
+        /*
+         *              tree
+         *           /        \
+         *         b1          b2
+         *       / | \      / | | \
+         *      1  2  3    4  5 6  7
+         */
+        let mut cursor = tree.cursor_with_summary();
+        for (leaf, before) in leaves.iter().zip([0, 1, 3, 6, 10, 15, 21]) {
+            assert_eq!(cursor.next(), Some(leaf));
+            assert_eq!(cursor.summary(), Sum(before));
+        }
+        assert_eq!(cursor.next(), None);
+        assert_eq!(cursor.summary(), Sum(28));
+    }
+
+    #[test]
+    fn seek_asks_each_child_in_turn() {
+        let leaves: Vec<_> = (1..=7).map(|i| leaf!(V(i))).collect();
+        let b1 = SumTree::new_branch(leaves[..3].iter().cloned());
+        let b2 = SumTree::new_branch(leaves[3..].iter().cloned());
+        let tree = SumTree::new_branch([b1.clone(), b2.clone()]);
+
+        // each branch along with the summary of the child asked about
+        let mut directions = vec![
+            (&b2, Sum(5), Direction::Left),
+            (&b2, Sum(4), Direction::Right),
+            (&tree, Sum(6), Direction::Right),
+        ];
+        let mut cursor = tree.cursor_with_summary();
+        let leaf = cursor.seek(|summary, node| {
+            let (expected, expected_summary, direction) = directions.pop().unwrap();
+            assert_eq!((node, *summary), (expected, expected_summary));
+            direction
+        });
+        assert_eq!(leaf, Some(&leaves[4]));
+        assert!(directions.is_empty());
+        // 1 + 2 + 3 + 4, only what was skipped to reach 5
+        assert_eq!(cursor.summary(), Sum(10));
+
+        // past every child asked about, the last is gone into
+        cursor.reset();
+        assert_eq!(cursor.seek(|_, _| Direction::Right), Some(&leaves[6]));
+        assert_eq!(cursor.summary(), Sum(21));
+    }
+
+    #[test]
+    fn splits_propagate_to_a_new_root() {
+        let tree = SumTree::from_items((0..MAX_ARITY as u32).map(V)).unwrap();
+        assert_eq!(tree.depth(), 1);
+
+        let mut cursor = tree.cursor();
+        cursor.next();
+        let tree = cursor.into_position().split_insert(V(100), V(101), V(102));
+        assert!(tree.is_balanced());
+        assert_eq!(tree.depth(), 2);
+        let mut cursor = tree.cursor();
+        let items: Vec<_> =
+            std::iter::from_fn(|| cursor.next().map(|n| n.deref_item().0)).collect();
+        let mut expected = vec![100, 101, 102];
+        expected.extend(1..MAX_ARITY as u32);
+        assert_eq!(items, expected);
+    }
+}
@@ -0,0 +1,347 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use smallvec::SmallVec;
+
+pub mod cursor;
+
+use crate::{Item, Summary};
+use cursor::Cursor;
+
+/// The most children a branch holds. Sixteen keeps a rope over hundreds of megabytes to a handful
+/// of levels while a branch's children still fit in a few cache lines.
+pub const MAX_ARITY: usize = 16;
+
+/// The fewest children a branch other than the root holds.
+pub const MIN_ARITY: usize = MAX_ARITY / 2;
+
+type Children<T> = SmallVec<[SumTree<T>; MAX_ARITY]>;
+
+#[derive(Debug)]
+pub enum Error {
+    Overfull,
+    Underfull,
+    DifferingDepth,
+}
+
+#[derive(Debug, Clone)]
+pub struct SumTree<T: Item>(pub(crate) Arc<Node<T>>);
+
+impl<T: Item> Deref for SumTree<T> {
+    type Target = Arc<Node<T>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Item> SumTree<T> {
+    pub fn new_leaf(item: T) -> Self {
+        Self(Arc::new(Node::new_leaf(item)))
+    }
+
+    /// A branch over `children`, which must be as deep as each other, and between one and
+    /// `MAX_ARITY` of them.
+    pub fn new_branch(children: impl IntoIterator<Item = SumTree<T>>) -> Self {
+        Self(Arc::new(Node::new_branch(children.into_iter().collect())))
+    }
+
+    /// Builds a balanced tree from `items` a level at a time, each branch as full as its
+    /// siblings.
+    pub fn from_items(items: impl IntoIterator<Item = T>) -> Option<Self> {
+        let leaves: Vec<_> = items.into_iter().map(SumTree::new_leaf).collect();
+        if leaves.is_empty() {
+            return None;
+        }
+        Some(root(leaves))
+    }
+
+    /// Concatenates two trees, descending only the spine of the taller one to the level the
+    /// shorter one's children sit at.
+    pub fn join(left: SumTree<T>, right: SumTree<T>) -> Self {
+        let (lheight, rheight) = (left.height(), right.height());
+        let nodes = if lheight >= rheight {
+            append(&left, lheight, right, rheight)
+        } else {
+            prepend(left, lheight, &right, rheight)
+        };
+        root(nodes)
+    }
+
+    pub fn deref_item(&self) -> &T {
+        self.0.deref_item()
+    }
+
+    pub fn summary(&self) -> T::Summary {
+        self.0.summary()
+    }
+
+    /// The same tree with `summary` in place of the root's own, whether or not it's right; so
+    /// checks of a tree's summaries can be tested against one that's wrong.
+    #[doc(hidden)]
+    pub fn with_summary(&self, summary: T::Summary) -> Self {
+        let node = match self.0.as_ref() {
+            Node::Branch { children, .. } => Node::Branch { children: children.clone(), summary },
+            Node::Leaf { item, .. } => Node::Leaf { item: item.clone(), summary },
+        };
+        Self(Arc::new(node))
+    }
+
+    pub fn cursor(&self) -> Cursor<'_, T> {
+        Cursor::new(self)
+    }
+
+    pub fn cursor_with_summary(&self) -> Cursor<'_, T> {
+        Cursor::with_summary(self)
+    }
+
+    /// Whether the tree keeps the B-tree invariants: every leaf as deep as the others, and every
+    /// branch between `MIN_ARITY` and `MAX_ARITY` children full, or at least two for the root.
+    pub fn is_balanced(&self) -> bool {
+        self.0.check(true).is_ok()
+    }
+
+    /// The most branches on the way from the root to a leaf, which is as many as on the way to
+    /// any leaf.
+    pub fn depth(&self) -> usize {
+        self.height()
+    }
+
+    fn height(&self) -> usize {
+        let mut height = 0;
+        let mut node = self;
+        while let Some(first) = node.children().next() {
+            height += 1;
+            node = first;
+        }
+        height
+    }
+}
+
+impl<T: Item> PartialEq for &SumTree<T> {
+    fn eq(&self, other: &&SumTree<T>) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[derive(Debug)]
+pub enum Node<T: Item> {
+    Branch {
+        children: Children<T>,
+        summary: T::Summary,
+    },
+    Leaf {
+        item: T,
+        summary: T::Summary,
+    },
+}
+
+impl<T: Item> Node<T> {
+    fn new_branch(children: Children<T>) -> Self {
+        debug_assert!((1..=MAX_ARITY).contains(&children.len()), "{} children", children.len());
+        let mut summaries = children.iter().map(|child| child.summary());
+        let first = summaries.next().expect("a branch has children");
+        let summary = summaries.fold(first, |summary, child| summary.combine(&child));
+        Node::Branch { children, summary }
+    }
+
+    fn new_leaf(item: T) -> Self {
+        let summary = item.summary();
+        Node::Leaf { item, summary }
+    }
+
+    pub fn deref_item(&self) -> &T {
+        match self {
+            Node::Branch { .. } => unreachable!("called `Node::deref_item()` on a `Branch` node"),
+            Node::Leaf { ref item, .. } => item,
+        }
+    }
+
+    /// The branch's children, first to last; a leaf has none.
+    pub fn children(&self) -> std::slice::Iter<'_, SumTree<T>> {
+        match self {
+            Node::Branch { children, .. } => children.iter(),
+            Node::Leaf { .. } => Default::default(),
+        }
+    }
+
+    /// What dumps call a branch.
+    pub(crate) fn label(&self) -> &'static str {
+        "branch"
+    }
+
+    /// The colour Graphviz draws a branch in.
+    pub(crate) fn dot_colour(&self) -> &'static str {
+        "black"
+    }
+
+    fn summary(&self) -> T::Summary {
+        match self {
+            Node::Branch { summary, .. } => *summary,
+            Node::Leaf { summary, .. } => *summary,
+        }
+    }
+
+    /// The height of the node, if every leaf under it is that far down and every branch under it
+    /// is neither over- nor underfull.
+    fn check(&self, is_root: bool) -> Result<usize, Error> {
+        let children = match self {
+            Node::Leaf { .. } => return Ok(0),
+            Node::Branch { children, .. } => children,
+        };
+        if children.len() > MAX_ARITY {
+            return Err(Error::Overfull);
+        }
+        let min = if is_root { 2 } else { MIN_ARITY };
+        if children.len() < min {
+            return Err(Error::Underfull);
+        }
+        let mut heights = children.iter().map(|child| child.0.check(false));
+        let height = heights.next().expect("a branch has children")?;
+        for other in heights {
+            if other? != height {
+                return Err(Error::DifferingDepth);
+            }
+        }
+        Ok(height + 1)
+    }
+}
+
+/// Gathers `nodes`, all as deep as each other, under as few branches as will hold them, sharing
+/// them out evenly so none holds more than `MAX_ARITY` and, given enough to go round, none fewer
+/// than `MIN_ARITY`.
+fn grouped<T: Item>(nodes: Vec<SumTree<T>>) -> Vec<SumTree<T>> {
+    let groups = nodes.len().div_ceil(MAX_ARITY);
+    let (size, extra) = (nodes.len() / groups, nodes.len() % groups);
+    let mut nodes = nodes.into_iter();
+    (0..groups)
+        .map(|i| SumTree::new_branch(nodes.by_ref().take(size + usize::from(i < extra))))
+        .collect()
+}
+
+/// The tree over `nodes`, as deep as each other: the one node, or branches over them grown until
+/// there's one.
+fn root<T: Item>(mut nodes: Vec<SumTree<T>>) -> SumTree<T> {
+    while nodes.len() > 1 {
+        nodes = grouped(nodes);
+    }
+    nodes.pop().expect("a tree has a root")
+}
+
+/// The children of two nodes as deep as each other, regrouped under one or two branches, or the
+/// two nodes themselves if they're leaves.
+fn merge<T: Item>(left: &SumTree<T>, right: SumTree<T>) -> Vec<SumTree<T>> {
+    if let Node::Leaf { .. } = left.0.as_ref() {
+        return vec![left.clone(), right];
+    }
+    let children = left.children().chain(right.children()).cloned().collect();
+    grouped(children)
+}
+
+/// `tree` joined onto the end of `node`, `height` high, as one or two nodes that high. `tree`
+/// must be no higher.
+fn append<T: Item>(
+    node: &SumTree<T>,
+    height: usize,
+    tree: SumTree<T>,
+    tree_height: usize,
+) -> Vec<SumTree<T>> {
+    if height == tree_height {
+        return merge(node, tree);
+    }
+    let mut children: Vec<_> = node.children().cloned().collect();
+    let last = children.pop().expect("a branch has children");
+    children.extend(append(&last, height - 1, tree, tree_height));
+    grouped(children)
+}
+
+/// `tree` joined onto the start of `node`, `height` high, as one or two nodes that high. `tree`
+/// must be lower.
+fn prepend<T: Item>(
+    tree: SumTree<T>,
+    tree_height: usize,
+    node: &SumTree<T>,
+    height: usize,
+) -> Vec<SumTree<T>> {
+    if height == tree_height {
+        return merge(&tree, node.clone());
+    }
+    let mut children = node.children();
+    let first = children.next().expect("a branch has children");
+    let mut joined = prepend(tree, tree_height, first, height - 1);
+    joined.extend(children.cloned());
+    grouped(joined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macros::*;
+    use crate::tests::*;
+
+    fn items(tree: &SumTree<V>) -> Vec<u32> {
+        let mut cursor = tree.cursor();
+        std::iter::from_fn(|| cursor.next().map(|n| n.0.deref_item().0)).collect()
+    }
+
+    /// Inserts `item` at `index`, walking there a leaf at a time.
+    fn insert_at(tree: SumTree<V>, index: usize, item: u32) -> SumTree<V> {
+        let mut cursor = tree.cursor();
+        let len = items(&tree).len();
+        for _ in 0..=index.min(len - 1) {
+            cursor.next();
+        }
+        if index < len {
+            cursor.into_position().insert_left(V(item))
+        } else {
+            cursor.into_position().insert_right(V(item))
+        }
+    }
+
+    #[test]
+    fn full_branches_split() {
+        // inserts land at the front, in the middle and at the back in turn
+        let mut tree = leaf!(V(0));
+        let mut expected = vec![0];
+        for i in 1..2000 {
+            let index = match i % 3 {
+                0 => 0,
+                1 => expected.len() / 2,
+                _ => expected.len(),
+            };
+            tree = insert_at(tree, index, i);
+            expected.insert(index, i);
+            assert!(tree.is_balanced(), "unbalanced after {} inserts", i);
+        }
+        assert_eq!(items(&tree), expected);
+        assert_eq!(tree.summary(), Sum((0..2000).sum()));
+        // no deeper than if every branch were only half full
+        let deepest = (2000f64.ln() / (MIN_ARITY as f64).ln()).ceil() as usize;
+        assert!(tree.depth() <= deepest, "{} deep", tree.depth());
+    }
+
+    #[test]
+    fn joins_of_any_heights() {
+        for lcount in [1, 2, 15, 16, 17, 100, 257, 4000] {
+            for rcount in [1, 8, 16, 33, 300, 5000] {
+                let left = SumTree::from_items((0..lcount).map(V)).unwrap();
+                let right = SumTree::from_items((lcount..lcount + rcount).map(V)).unwrap();
+                let tree = SumTree::join(left.clone(), right.clone());
+                assert!(tree.is_balanced(), "unbalanced join of {} and {}", lcount, rcount);
+                assert_eq!(items(&tree), (0..lcount + rcount).collect::<Vec<_>>());
+                // and the other way round, the shorter on the left as often as not
+                let tree = SumTree::join(right, left);
+                assert!(tree.is_balanced(), "unbalanced join of {} and {}", rcount, lcount);
+                assert_eq!(tree.summary(), Sum((0..lcount + rcount).sum()));
+            }
+        }
+    }
+
+    #[test]
+    fn shallower_than_binary() {
+        let tree = SumTree::from_items((0..100_000).map(|_| V(1))).unwrap();
+        assert!(tree.is_balanced());
+        // a balanced binary tree of as many would be 17 deep
+        assert_eq!(tree.depth(), 5);
+    }
+}
@@ -1,12 +1,15 @@
 use anyhow::Result;
 use futures::Stream;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use tokio::sync::mpsc;
 use tree_sitter as ts;
 
 use crate::BufferContentsTextProvider;
-use crate::{highlighter, Language};
-use editor::{BufferContents, BufferId, Highlights};
+use crate::{highlighter, Language, Symbols};
+use editor::{BufferContents, BufferId, CancellationToken, Highlights};
 
 #[derive(Debug)]
 pub enum Command {
@@ -14,20 +17,49 @@ pub enum Command {
         buffer_id: BufferId,
         contents: BufferContents,
         language: Language,
+        /// The buffer version the contents are at.
+        version: u64,
+        /// Edits made since some earlier parse, each with the version it produced, in order. The
+        /// ones the worker's last tree for the buffer already reflects are skipped.
+        edits: Vec<(u64, ts::InputEdit)>,
     },
 }
 
 #[derive(Debug)]
 pub enum Event {
-    Parsed(BufferId, ts::Tree),
-    Hightlight(BufferId, Highlights),
+    /// The tree parsed from the given version of the buffer.
+    Parsed(BufferId, u64, ts::Tree),
+    /// The definitions in the tree parsed from the given version, sent right after it.
+    Symbols(BufferId, u64, Symbols),
+    /// Highlights of the given version of the buffer.
+    Hightlight(BufferId, u64, Highlights),
+    /// Highlights recomputed only for the ranges that changed since the previous parse, in the
+    /// given version of the buffer.
+    HighlightDelta(BufferId, u64, Vec<(Range<usize>, Highlights)>),
 }
 
+/// Work handed off to run on another thread, like highlighting.
+pub type Job = Box<dyn FnOnce() + Send>;
+
 #[derive(Debug)]
 struct Worker(thread::JoinHandle<Result<()>>);
 
+/// A highlight handed off to run in the background, which a newer parse of its buffer cancels.
+struct Highlighting {
+    cancel: CancellationToken,
+    /// Whether it sent its highlights. Held while it checks for cancellation and sends, so once
+    /// the worker has it and has cancelled the highlight, it either was sent or never will be.
+    sent: Arc<Mutex<bool>>,
+}
+
 impl Worker {
-    fn spawn(mut rx: mpsc::Receiver<Command>, tx: mpsc::Sender<Event>) -> Self {
+    /// Parses on a thread of its own, handing each highlight to `spawn` to run elsewhere so a
+    /// long one doesn't hold up parses behind it.
+    fn spawn(
+        mut rx: mpsc::UnboundedReceiver<Command>,
+        tx: mpsc::Sender<Event>,
+        spawn: impl Fn(Job) + Send + 'static,
+    ) -> Self {
         // let thread_handle = thread::spawn(move || {
         let thread_handle = thread::Builder::new()
             .name(String::from("syntax"))
@@ -35,24 +67,64 @@ impl Worker {
                 let rt = tokio::runtime::Builder::new_current_thread().build()?;
                 rt.block_on(async {
                     let mut parser = ts::Parser::new();
+                    let mut trees: HashMap<BufferId, (u64, ts::Tree)> = HashMap::new();
+                    let mut highlighting: HashMap<BufferId, Highlighting> = HashMap::new();
 
                     while let Some(ev) = rx.recv().await {
                         use Command::*;
                         match ev {
-                            Parse { buffer_id, contents, language } => {
+                            Parse { buffer_id, contents, language, version, edits } => {
                                 let span = tracing::info_span!("parse_ts_tree").entered();
                                 parser.set_language(language.ts)?;
+                                let retained = trees.remove(&buffer_id);
+                                let edits = match &retained {
+                                    Some((parsed, _)) => unseen_edits(*parsed, version, &edits),
+                                    None => None,
+                                };
+                                let old_tree =
+                                    retained.zip(edits.as_ref()).map(|((_, mut tree), edits)| {
+                                        edits.iter().for_each(|edit| tree.edit(edit));
+                                        tree
+                                    });
+                                let edits = edits.unwrap_or_default();
                                 let ts_text = BufferContentsTextProvider(&contents);
-                                let ts_tree =
-                                    parser.parse_with(&mut ts_text.parse_callback(), None);
+                                let ts_tree = parser
+                                    .parse_with(&mut ts_text.parse_callback(), old_tree.as_ref());
                                 drop(span);
                                 match ts_tree {
                                     None => todo!(),
                                     Some(tree) => {
-                                        tx.send(Event::Parsed(buffer_id, tree.clone())).await?;
-                                        let highlights =
-                                            highlighter::highlight(&contents, language, tree);
-                                        tx.send(Event::Hightlight(buffer_id, highlights)).await?;
+                                        let parsed =
+                                            Event::Parsed(buffer_id, version, tree.clone());
+                                        tx.send(parsed).await?;
+                                        let symbols = crate::symbols(&contents, &language, &tree);
+                                        tx.send(Event::Symbols(buffer_id, version, symbols))
+                                            .await?;
+                                        // highlights only change those of the last that was sent
+                                        let sent = match highlighting.remove(&buffer_id) {
+                                            Some(previous) => previous.cancel(),
+                                            None => true,
+                                        };
+                                        let ranges = old_tree
+                                            .filter(|_| sent)
+                                            .map(|old| changed_ranges(&old, &tree, &edits));
+                                        let cancel = CancellationToken::new();
+                                        let sent = Arc::new(Mutex::new(false));
+                                        let highlight = Highlight {
+                                            buffer_id,
+                                            version,
+                                            contents,
+                                            language,
+                                            tree: tree.clone(),
+                                            ranges,
+                                            cancel: cancel.clone(),
+                                            sent: sent.clone(),
+                                        };
+                                        let tx = tx.clone();
+                                        spawn(Box::new(move || highlight.run(tx)));
+                                        highlighting
+                                            .insert(buffer_id, Highlighting { cancel, sent });
+                                        trees.insert(buffer_id, (version, tree));
                                     }
                                 }
                             }
@@ -62,28 +134,121 @@ impl Worker {
                 })?;
 
                 Ok(())
-            }).expect("failed to spawn syntax worker");
+            })
+            .expect("failed to spawn syntax worker");
         Self(thread_handle)
     }
 }
 
+impl Highlighting {
+    /// Stops the highlight if it's still running, returning whether it sent its highlights.
+    fn cancel(self) -> bool {
+        let sent = self.sent.lock().unwrap();
+        self.cancel.cancel();
+        *sent
+    }
+}
+
+/// A highlight of the contents, in every range that changed since the last or everywhere for
+/// None, to run elsewhere.
+struct Highlight {
+    buffer_id: BufferId,
+    /// The version of the contents, which the highlights are sent with.
+    version: u64,
+    contents: BufferContents,
+    language: Language,
+    tree: ts::Tree,
+    ranges: Option<Vec<Range<usize>>>,
+    cancel: CancellationToken,
+    sent: Arc<Mutex<bool>>,
+}
+
+impl Highlight {
+    /// Sends the highlights as an event, unless cancelled before they're done or sent.
+    fn run(self, tx: mpsc::Sender<Event>) {
+        let Self { buffer_id, version, contents, language, tree, ranges, cancel, sent } = self;
+        let event = match ranges {
+            None => highlighter::highlight(&contents, &language, &tree, &cancel)
+                .map(|highlights| Event::Hightlight(buffer_id, version, highlights)),
+            Some(ranges) => {
+                highlighter::highlight_ranges(&contents, &language, &tree, ranges, &cancel)
+                    .map(|deltas| Event::HighlightDelta(buffer_id, version, deltas))
+            }
+        };
+        let mut sent = sent.lock().unwrap();
+        if let Some(event) = event.filter(|_| !cancel.is_cancelled()) {
+            *sent = tx.blocking_send(event).is_ok();
+        }
+    }
+}
+
+/// The edits that take a tree parsed from version `parsed` to version `version`, or None if
+/// `edits` doesn't cover every version in between and the tree can't be reused.
+fn unseen_edits(
+    parsed: u64,
+    version: u64,
+    edits: &[(u64, ts::InputEdit)],
+) -> Option<Vec<ts::InputEdit>> {
+    let unseen: Vec<_> = edits
+        .iter()
+        .filter(|(edited, _)| *edited > parsed)
+        .collect();
+    let mut expected = parsed;
+    for (edited, _) in unseen.iter() {
+        match *edited {
+            edited if edited == expected || edited == expected + 1 => expected = edited,
+            _ => return None,
+        }
+    }
+    (expected == version).then(|| unseen.into_iter().map(|(_, edit)| *edit).collect())
+}
+
+/// Byte ranges whose highlights may differ between `old_tree` (already edited) and `new_tree`:
+/// the ranges tree-sitter reports as structurally changed plus the edited text itself, sorted
+/// and merged.
+fn changed_ranges(
+    old_tree: &ts::Tree,
+    new_tree: &ts::Tree,
+    edits: &[ts::InputEdit],
+) -> Vec<Range<usize>> {
+    let mut ranges: Vec<_> = old_tree
+        .changed_ranges(new_tree)
+        .map(|r| r.start_byte..r.end_byte)
+        .chain(edits.iter().map(|e| e.start_byte..e.new_end_byte))
+        .filter(|r| !r.is_empty())
+        .collect();
+    ranges.sort_by_key(|r| r.start);
+    ranges
+        .into_iter()
+        .fold(vec![], |mut merged: Vec<Range<usize>>, range| {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+            merged
+        })
+}
+
 #[derive(Debug)]
 pub struct Syntax {
-    cmd_tx: mpsc::Sender<Command>,
+    cmd_tx: mpsc::UnboundedSender<Command>,
     event_rx: mpsc::Receiver<Event>,
     worker: Worker,
 }
 
 impl Syntax {
-    pub fn spawn() -> Self {
-        let (cmd_tx, cmd_rx) = mpsc::channel(1);
+    /// Starts the worker that parses, with `spawn` running the highlights it hands off.
+    pub fn spawn(spawn: impl Fn(Job) + Send + 'static) -> Self {
+        // commands are unbounded so that callers draining events never block on a worker
+        // that is itself blocked sending events.
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
         let (event_tx, event_rx) = mpsc::channel(1);
-        let worker = Worker::spawn(cmd_rx, event_tx);
+        let worker = Worker::spawn(cmd_rx, event_tx, spawn);
         Syntax { cmd_tx, event_rx, worker }
     }
 
     pub async fn command(&self, command: Command) -> Result<()> {
-        self.cmd_tx.send(command).await?;
+        self.cmd_tx.send(command)?;
         Ok(())
     }
 
@@ -104,3 +269,307 @@ impl Stream for Syntax {
         self.as_mut().event_rx.poll_recv(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::{Buffer, BufferCommand};
+    use futures::StreamExt;
+
+    fn entries(highlights: &Highlights) -> Vec<(Range<usize>, String)> {
+        highlights
+            .iter(..)
+            .map(|(r, name)| (r, name.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn highlight_delta_only_changed_ranges() -> Result<()> {
+        let source = "fn f{}() -> u32 {\n    let x = 1;\n    x\n}\n";
+        let source: String = (0..1000)
+            .map(|i| source.replace("{}", &i.to_string()))
+            .collect();
+        let mut buffer = Buffer::new(Default::default(), source.as_str().into());
+        let mut syntax = Syntax::spawn(|job| drop(thread::spawn(job)));
+
+        let contents = buffer.contents.clone();
+        let language = Language::try_from(&buffer)?;
+        let (version, edits) = (0, vec![]);
+        syntax
+            .command(Command::Parse { buffer_id: buffer.id, contents, language, version, edits })
+            .await?;
+        assert!(matches!(syntax.next().await, Some(Event::Parsed(_, 0, _))));
+        let before = match (syntax.next().await, syntax.next().await) {
+            (Some(Event::Symbols(_, 0, symbols)), Some(Event::Hightlight(_, 0, hls))) => {
+                assert_eq!(symbols.len(), 1000);
+                hls
+            }
+            events => panic!("unexpected events {:?}", events),
+        };
+        buffer.command(BufferCommand::Highlight(0, before.clone()));
+
+        // turn `let x = 1;` into `let x = y;` in the middle of the file
+        let fn_start = source.find("fn f500()").unwrap();
+        let fn_end = fn_start + source[fn_start..].find("}\n").unwrap() + 2;
+        let start = fn_start + source[fn_start..].find('1').unwrap();
+        let row = source[..start].matches('\n').count();
+        let column = start - source[..start].rfind('\n').unwrap() - 1;
+        buffer.contents.remove(start..start + 1);
+        buffer.contents.insert(start, "y");
+        let edit = ts::InputEdit {
+            start_byte: start,
+            old_end_byte: start + 1,
+            new_end_byte: start + 1,
+            start_position: ts::Point { row, column },
+            old_end_position: ts::Point { row, column: column + 1 },
+            new_end_position: ts::Point { row, column: column + 1 },
+        };
+
+        let contents = buffer.contents.clone();
+        let language = Language::try_from(&buffer)?;
+        let (version, edits) = (1, vec![(1, edit)]);
+        syntax
+            .command(Command::Parse { buffer_id: buffer.id, contents, language, version, edits })
+            .await?;
+        let tree = match syntax.next().await {
+            Some(Event::Parsed(_, 1, tree)) => tree,
+            event => panic!("unexpected event {:?}", event),
+        };
+        let deltas = match (syntax.next().await, syntax.next().await) {
+            (Some(Event::Symbols(_, 1, _)), Some(Event::HighlightDelta(_, 1, deltas))) => deltas,
+            events => panic!("unexpected events {:?}", events),
+        };
+        let ranges: Vec<_> = deltas.iter().map(|(r, _)| r.clone()).collect();
+        assert!(!ranges.is_empty());
+        for range in ranges.iter() {
+            assert!(fn_start <= range.start && range.end <= fn_end, "{:?} outside edit", range);
+        }
+        buffer.command(BufferCommand::HighlightDelta(1, deltas));
+
+        let outside = |highlights: &Highlights| -> Vec<_> {
+            entries(highlights)
+                .into_iter()
+                .filter(|(r, _)| !ranges.iter().any(|c| r.start < c.end && c.start < r.end))
+                .collect()
+        };
+        assert_eq!(outside(&before), outside(&buffer.highlights));
+        assert_ne!(entries(&before), entries(&buffer.highlights));
+
+        let language = Language::try_from(&buffer)?;
+        let cancel = CancellationToken::new();
+        let full = crate::highlighter::highlight(&buffer.contents, &language, &tree, &cancel);
+        assert_eq!(entries(&full.unwrap()), entries(&buffer.highlights));
+        Ok(())
+    }
+
+    /// A syntax worker whose highlights are held until the test runs them.
+    fn held() -> (Syntax, Arc<Mutex<Vec<Job>>>) {
+        let jobs = Arc::new(Mutex::new(vec![]));
+        let held = jobs.clone();
+        let syntax = Syntax::spawn(move |job| held.lock().unwrap().push(job));
+        (syntax, jobs)
+    }
+
+    async fn parse(
+        syntax: &Syntax,
+        buffer: &Buffer,
+        version: u64,
+        edits: Vec<(u64, ts::InputEdit)>,
+    ) -> Result<()> {
+        let contents = buffer.contents.clone();
+        let language = Language::try_from(buffer)?;
+        let buffer_id = buffer.id;
+        syntax
+            .command(Command::Parse { buffer_id, contents, language, version, edits })
+            .await
+    }
+
+    /// Waits for the worker to hand off `len` highlights in all.
+    async fn handed_off(jobs: &Mutex<Vec<Job>>, len: usize) {
+        while jobs.lock().unwrap().len() < len {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn superseded_highlight_sends_nothing() -> Result<()> {
+        let buffer = Buffer::new(Default::default(), "fn a() {}\n".into());
+        let (mut syntax, jobs) = held();
+
+        parse(&syntax, &buffer, 0, vec![]).await?;
+        assert!(matches!(syntax.next().await, Some(Event::Parsed(_, 0, _))));
+        assert!(matches!(syntax.next().await, Some(Event::Symbols(_, 0, _))));
+        handed_off(&jobs, 1).await;
+        // an edit that changes nothing, so the first tree is reused
+        let start = ts::Point { row: 0, column: 0 };
+        let edit = ts::InputEdit {
+            start_byte: 0,
+            old_end_byte: 0,
+            new_end_byte: 0,
+            start_position: start,
+            old_end_position: start,
+            new_end_position: start,
+        };
+        parse(&syntax, &buffer, 1, vec![(1, edit)]).await?;
+        assert!(matches!(syntax.next().await, Some(Event::Parsed(_, 1, _))));
+        assert!(matches!(syntax.next().await, Some(Event::Symbols(_, 1, _))));
+        handed_off(&jobs, 2).await;
+
+        let mut held: Vec<_> = jobs.lock().unwrap().drain(..).collect();
+        let newer = held.pop().unwrap();
+        let stale = held.pop().unwrap();
+        // highlights send blocking, so they run off the runtime as they would on the pool
+        thread::spawn(stale).join().unwrap();
+        assert!(syntax.event_rx.try_recv().is_err());
+        // as the first never sent its highlights, the newer can't be a delta on top of them
+        thread::spawn(newer).join().unwrap();
+        assert!(matches!(syntax.event_rx.try_recv(), Ok(Event::Hightlight(..))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn parses_go_on_while_highlighting() -> Result<()> {
+        let mut buffers = slotmap::SlotMap::with_key();
+        let source = "fn f() -> u32 {\n    let x = 1;\n    x\n}\n".repeat(10_000);
+        let long = buffers.insert_with_key(|k| Buffer::new(k, source.as_str().into()));
+        let short = buffers.insert_with_key(|k| Buffer::new(k, "fn a() {}\n".into()));
+        let (mut syntax, jobs) = held();
+
+        // the long buffer's highlight is held for as long as the test likes, and still the
+        // short buffer is parsed
+        parse(&syntax, &buffers[long], 0, vec![]).await?;
+        parse(&syntax, &buffers[short], 0, vec![]).await?;
+        for id in [long, short] {
+            assert!(matches!(syntax.next().await, Some(Event::Parsed(parsed, ..)) if parsed == id));
+            assert!(matches!(syntax.next().await, Some(Event::Symbols(found, ..)) if found == id));
+        }
+        handed_off(&jobs, 2).await;
+
+        for job in jobs.lock().unwrap().drain(..) {
+            thread::spawn(job);
+        }
+        let mut highlighted = vec![];
+        for _ in 0..2 {
+            match syntax.next().await {
+                Some(Event::Hightlight(id, ..)) => highlighted.push(id),
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+        highlighted.sort();
+        let mut expected = vec![long, short];
+        expected.sort();
+        assert_eq!(highlighted, expected);
+        Ok(())
+    }
+
+    /// Inserts ASCII `text` at byte `offset` of the ASCII `buffer`, returning the edit a parse
+    /// takes.
+    fn insert(buffer: &mut Buffer, offset: usize, text: &str) -> ts::InputEdit {
+        let before = buffer.contents.to_string();
+        let row = before[..offset].matches('\n').count();
+        let column = offset - before[..offset].rfind('\n').map_or(0, |n| n + 1);
+        buffer
+            .apply_edit(offset..offset, text)
+            .expect("offset within the buffer");
+        ts::InputEdit {
+            start_byte: offset,
+            old_end_byte: offset,
+            new_end_byte: offset + text.len(),
+            start_position: ts::Point { row, column },
+            old_end_position: ts::Point { row, column },
+            new_end_position: ts::Point { row, column: column + text.len() },
+        }
+    }
+
+    #[tokio::test]
+    async fn highlight_delta_after_edits_in_flight() -> Result<()> {
+        let source = "fn f() -> u32 {\n    let x = 1;\n    x\n}\n\nfn g() -> u32 {\n    2\n}\n";
+        let mut buffer = Buffer::new(Default::default(), source.into());
+        let mut syntax = Syntax::spawn(|job| drop(thread::spawn(job)));
+        let parse = |buffer: &Buffer, edits| -> Result<Command> {
+            let (buffer_id, contents, version) =
+                (buffer.id, buffer.contents.clone(), buffer.version);
+            let language = Language::try_from(buffer)?;
+            Ok(Command::Parse { buffer_id, contents, language, version, edits })
+        };
+
+        syntax.command(parse(&buffer, vec![])?).await?;
+        let events = (syntax.next().await, syntax.next().await, syntax.next().await);
+        match events {
+            (
+                Some(Event::Parsed(..)),
+                Some(Event::Symbols(..)),
+                Some(Event::Hightlight(_, 0, hls)),
+            ) => buffer.command(BufferCommand::Highlight(0, hls)),
+            events => panic!("unexpected events {:?}", events),
+        };
+
+        // `1` becomes `12`, and while that's parsed, a space goes in before `fn g`
+        let edit = insert(&mut buffer, source.find('1').unwrap() + 1, "2");
+        syntax.command(parse(&buffer, vec![(1, edit)])?).await?;
+        let parsed = buffer.contents.clone();
+        let g = buffer.contents.to_string().find("fn g").unwrap();
+        let edit = insert(&mut buffer, g, " ");
+        let events = (syntax.next().await, syntax.next().await, syntax.next().await);
+        let tree = match events {
+            (
+                Some(Event::Parsed(_, 1, tree)),
+                Some(Event::Symbols(..)),
+                Some(Event::HighlightDelta(_, 1, deltas)),
+            ) => {
+                buffer.command(BufferCommand::HighlightDelta(1, deltas));
+                tree
+            }
+            events => panic!("unexpected events {:?}", events),
+        };
+        // what was highlighted lines up with the contents that were parsed
+        let (language, cancel) = (Language::try_from(&buffer)?, CancellationToken::new());
+        let full = crate::highlighter::highlight(&parsed, &language, &tree, &cancel);
+        assert_eq!(entries(&full.unwrap()), entries(&buffer.highlights));
+
+        // and once the space is parsed too, with the contents as they are
+        syntax.command(parse(&buffer, vec![(2, edit)])?).await?;
+        let events = (syntax.next().await, syntax.next().await, syntax.next().await);
+        let tree = match events {
+            (
+                Some(Event::Parsed(_, 2, tree)),
+                Some(Event::Symbols(..)),
+                Some(Event::HighlightDelta(_, 2, deltas)),
+            ) => {
+                buffer.command(BufferCommand::HighlightDelta(2, deltas));
+                tree
+            }
+            events => panic!("unexpected events {:?}", events),
+        };
+        let full = crate::highlighter::highlight(&buffer.contents, &language, &tree, &cancel);
+        let full = full.unwrap();
+        assert_eq!(entries(&full), entries(&buffer.highlights));
+
+        // highlights of a version older than those applied are dropped
+        buffer.command(BufferCommand::Highlight(1, Highlights::new()));
+        assert_eq!(entries(&full), entries(&buffer.highlights));
+        Ok(())
+    }
+
+    #[test]
+    fn unseen_edits_cover_versions() {
+        let edit = ts::InputEdit {
+            start_byte: 0,
+            old_end_byte: 0,
+            new_end_byte: 1,
+            start_position: ts::Point { row: 0, column: 0 },
+            old_end_position: ts::Point { row: 0, column: 0 },
+            new_end_position: ts::Point { row: 0, column: 1 },
+        };
+        let edits: Vec<_> = [1, 2, 2, 3].into_iter().map(|v| (v, edit)).collect();
+        let count =
+            |parsed, version, edits: &[_]| unseen_edits(parsed, version, edits).map(|e| e.len());
+        assert_eq!(count(0, 3, &edits), Some(4));
+        assert_eq!(count(2, 3, &edits), Some(1));
+        assert_eq!(count(3, 3, &edits), Some(0));
+        // edits for version 1 went missing
+        assert_eq!(count(0, 3, &edits[1..]), None);
+        // the contents are newer than the edits describe
+        assert_eq!(count(0, 4, &edits), None);
+    }
+}
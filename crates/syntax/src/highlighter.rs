@@ -1,25 +1,103 @@
+use std::ops::Range;
 use tree_sitter as ts;
 
 use crate::Language;
-use editor::BufferContents;
+use editor::{BufferContents, CancellationToken};
 
+/// How many query matches go by between checks for cancellation.
+const CANCEL_CHECK_INTERVAL: usize = 64;
+
+/// Highlights the whole tree, or None if `cancel` was set before it was done.
 #[tracing::instrument(skip_all)]
 pub fn highlight(
     buffer: &BufferContents,
-    language: Language,
-    tree: ts::Tree,
-) -> editor::Highlights {
+    language: &Language,
+    tree: &ts::Tree,
+    cancel: &CancellationToken,
+) -> Option<editor::Highlights> {
+    highlight_range(buffer, language, tree, None, cancel, || {})
+}
+
+/// Highlights only the captures intersecting each of `ranges`, or None if cancelled.
+#[tracing::instrument(skip_all)]
+pub fn highlight_ranges(
+    buffer: &BufferContents,
+    language: &Language,
+    tree: &ts::Tree,
+    ranges: Vec<Range<usize>>,
+    cancel: &CancellationToken,
+) -> Option<Vec<(Range<usize>, editor::Highlights)>> {
+    ranges
+        .into_iter()
+        .map(|range| {
+            let highlights =
+                highlight_range(buffer, language, tree, Some(range.clone()), cancel, || {})?;
+            Some((range, highlights))
+        })
+        .collect()
+}
+
+/// Highlights the captures in `range`, or everywhere, calling `on_match` for each query match.
+fn highlight_range(
+    buffer: &BufferContents,
+    language: &Language,
+    tree: &ts::Tree,
+    range: Option<Range<usize>>,
+    cancel: &CancellationToken,
+    mut on_match: impl FnMut(),
+) -> Option<editor::Highlights> {
     let query = ts::Query::new(language.ts, &language.highlight_query).expect("invalid query");
     let mut cursor = ts::QueryCursor::new();
+    if let Some(range) = range {
+        cursor.set_byte_range(range);
+    }
     let mut highlights = iset::IntervalMap::new();
     let captures =
         cursor.captures(&query, tree.root_node(), crate::BufferContentsTextProvider(buffer));
-    for (query_match, _) in captures {
+    for (i, (query_match, _)) in captures.enumerate() {
+        if i % CANCEL_CHECK_INTERVAL == 0 && cancel.is_cancelled() {
+            return None;
+        }
+        on_match();
         for capture in query_match.captures {
             let capture_name = &query.capture_names()[capture.index as usize];
             let capture_range = capture.node.byte_range();
             highlights.insert(capture_range, capture_name.clone());
         }
     }
-    highlights
+    Some(highlights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::Buffer;
+
+    #[test]
+    fn cancelled_mid_way() {
+        let source = "fn f() -> u32 {\n    let x = 1;\n    x\n}\n".repeat(200);
+        let buffer = Buffer::new(Default::default(), source.as_str().into());
+        let language = Language::try_from(&buffer).unwrap();
+        let mut parser = ts::Parser::new();
+        parser.set_language(language.ts).unwrap();
+        let tree = parser.parse(&source, None).unwrap();
+        let contents = &buffer.contents;
+
+        let cancel = CancellationToken::new();
+        let mut total = 0;
+        let full = highlight_range(contents, &language, &tree, None, &cancel, || total += 1);
+        assert!(full.is_some());
+
+        let mut matched = 0;
+        let cancelled = highlight_range(contents, &language, &tree, None, &cancel, || {
+            matched += 1;
+            if matched == 3 {
+                cancel.cancel();
+            }
+        });
+        assert!(cancelled.is_none());
+        // it stops at the next check rather than going through every match
+        assert_eq!(matched, CANCEL_CHECK_INTERVAL);
+        assert!(matched < total, "{} of {}", matched, total);
+    }
 }
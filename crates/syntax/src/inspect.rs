@@ -0,0 +1,122 @@
+use std::ops::Range;
+use tree_sitter as ts;
+
+use editor::Buffer;
+
+/// What decides how the text at a byte offset is highlighted, for working out why it's drawn the
+/// way it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inspection {
+    pub offset: usize,
+    /// The highlights covering the offset and their captures, the one that shows first.
+    pub scopes: Vec<(Range<usize>, String)>,
+    /// The kinds of the nodes containing the offset, from the root down to the leaf; empty
+    /// without a tree.
+    pub path: Vec<&'static str>,
+}
+
+/// Inspects `buffer` at byte `offset`, with `tree` from its latest parse if there's one.
+pub fn inspect(buffer: &Buffer, tree: Option<&ts::Tree>, offset: usize) -> Inspection {
+    let scopes = buffer.highlights_at(offset);
+    let scopes = scopes
+        .into_iter()
+        .map(|(range, name)| (range, name.to_string()))
+        .collect();
+    let path = tree.map_or(vec![], |tree| node_path(tree, offset));
+    Inspection { offset, scopes, path }
+}
+
+/// The kinds of the nodes of `tree` containing byte `offset`, descending from the root to the
+/// smallest one. The root is always there, even for an offset past its end.
+pub fn node_path(tree: &ts::Tree, offset: usize) -> Vec<&'static str> {
+    let mut cursor = tree.walk();
+    let mut path = vec![cursor.node().kind()];
+    // the first child ending past the offset contains it, unless it starts past it too; the one
+    // found may end right at the offset, and then it's a sibling that does
+    while cursor.goto_first_child_for_byte(offset).is_some() {
+        while cursor.node().end_byte() <= offset && cursor.goto_next_sibling() {}
+        let node = cursor.node();
+        if node.start_byte() > offset || node.end_byte() <= offset {
+            break;
+        }
+        path.push(cursor.node().kind());
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Language;
+    use editor::BufferContents;
+
+    fn parsed(text: &str) -> (Buffer, ts::Tree) {
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from(text));
+        let language = Language::try_from(&buffer).unwrap();
+        let mut parser = ts::Parser::new();
+        parser.set_language(language.ts).unwrap();
+        let tree = parser.parse(text, None).unwrap();
+        let cancel = editor::CancellationToken::new();
+        let highlights = crate::highlighter::highlight(&buffer.contents, &language, &tree, &cancel);
+        let highlights = highlights.unwrap();
+        buffer.command(editor::BufferCommand::Highlight(0, highlights));
+        (buffer, tree)
+    }
+
+    fn scopes(inspection: &Inspection) -> Vec<&str> {
+        inspection
+            .scopes
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn scopes_and_path_at_known_positions() {
+        let text = "fn main() {\n    let s = \"hi\";\n}\n";
+        let (buffer, tree) = parsed(text);
+
+        let at_fn = inspect(&buffer, Some(&tree), 0);
+        assert_eq!(scopes(&at_fn), vec!["keyword"]);
+        assert_eq!(at_fn.path, vec!["source_file", "function_item", "fn"]);
+
+        let at_name = inspect(&buffer, Some(&tree), text.find("main").unwrap() + 1);
+        assert_eq!(at_name.scopes, vec![(3..7, "function".to_string())]);
+        assert_eq!(at_name.path, vec!["source_file", "function_item", "identifier"]);
+
+        let at_string = inspect(&buffer, Some(&tree), text.find("hi").unwrap());
+        assert_eq!(scopes(&at_string), vec!["string"]);
+        let path = ["source_file", "function_item", "block", "let_declaration"];
+        assert_eq!(at_string.path[..4], path);
+        assert_eq!(at_string.path.last(), Some(&"string_literal"));
+    }
+
+    #[test]
+    fn path_where_one_node_ends_and_the_next_starts() {
+        let text = "fn main() {}\n";
+        let (_, tree) = parsed(text);
+
+        // `main` ends where the parameters start, and the offset is in the parameters
+        let path = node_path(&tree, text.find('(').unwrap());
+        assert_eq!(path, vec!["source_file", "function_item", "parameters", "("]);
+        let path = node_path(&tree, text.find('{').unwrap());
+        assert_eq!(path, vec!["source_file", "function_item", "block", "{"]);
+    }
+
+    #[test]
+    fn nothing_to_inspect() {
+        let text = "fn main() {}\n";
+        let (buffer, tree) = parsed(text);
+
+        // between tokens there's no highlight, but still the nodes around them
+        let at_space = inspect(&buffer, Some(&tree), 2);
+        assert!(at_space.scopes.is_empty());
+        assert_eq!(at_space.path, vec!["source_file", "function_item"]);
+
+        // before the first parse there's no tree, and past the end only the root
+        let unparsed = Buffer::new(Default::default(), BufferContents::from(text));
+        let inspection = inspect(&unparsed, None, 0);
+        assert!(inspection.scopes.is_empty() && inspection.path.is_empty());
+        assert_eq!(inspect(&buffer, Some(&tree), text.len()).path, vec!["source_file"]);
+    }
+}
@@ -3,10 +3,35 @@ use tree_sitter as ts;
 
 use editor::Buffer;
 
+/// The grammar's own tags query leaves out impl blocks, and has calls and references besides,
+/// which an outline has no use for. A function in an impl or trait is told from others by where
+/// it is, rather than by a pattern of its own.
+const RUST_TAGS_QUERY: &str = r#"
+(function_item name: (identifier) @name) @definition.function
+(function_signature_item name: (identifier) @name) @definition.function
+(struct_item name: (type_identifier) @name) @definition.struct
+(union_item name: (type_identifier) @name) @definition.struct
+(enum_item name: (type_identifier) @name) @definition.enum
+(trait_item name: (type_identifier) @name) @definition.trait
+(impl_item type: (_) @name) @definition.impl
+(mod_item name: (identifier) @name) @definition.module
+(macro_definition name: (identifier) @name) @definition.macro
+(type_item name: (type_identifier) @name) @definition.type
+(const_item name: (identifier) @name) @definition.constant
+(static_item name: (identifier) @name) @definition.constant
+"#;
+
 #[derive(Debug)]
 pub struct Language {
+    /// What the language is called, as buffers in it are listed.
+    pub name: &'static str,
+    /// What starts a line comment, if the language has them.
+    pub comment_token: Option<&'static str>,
     pub ts: ts::Language,
     pub highlight_query: String,
+    /// Finds the definitions an outline lists: each match captures a definition as
+    /// `definition.<kind>` and what it's called as `name`.
+    pub tags_query: String,
 }
 
 impl TryFrom<&Buffer> for Language {
@@ -14,8 +39,11 @@ impl TryFrom<&Buffer> for Language {
 
     fn try_from(_: &Buffer) -> Result<Self> {
         Ok(Language {
+            name: "rust",
+            comment_token: Some("//"),
             ts: tree_sitter_rust::language(),
             highlight_query: tree_sitter_rust::HIGHLIGHT_QUERY.into(),
+            tags_query: RUST_TAGS_QUERY.into(),
         })
     }
 }
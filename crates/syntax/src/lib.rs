@@ -1,14 +1,33 @@
 mod client;
 mod highlighter;
+mod inspect;
 mod language;
+mod state;
+mod symbols;
 
-pub use client::{Command, Event, Syntax};
+pub use client::{Command, Event, Job, Syntax};
+pub use inspect::{inspect, node_path, Inspection};
 pub use language::Language;
+pub use state::SyntaxState;
+pub use symbols::{symbols, Symbol, SymbolKind, Symbols};
 
 use editor::BufferContents;
-use rope::iter::Chunks;
+use rope::compat::iter::Chunks;
 use tree_sitter as ts;
 
+/// Converts a rope edit summary into the tree-sitter edit used for incremental reparsing.
+pub fn input_edit(edit: &tore::EditSummary) -> ts::InputEdit {
+    let point = |p: tore::Point| ts::Point { row: p.line, column: p.column };
+    ts::InputEdit {
+        start_byte: edit.start_offset,
+        old_end_byte: edit.old_end_offset(),
+        new_end_byte: edit.new_end_offset(),
+        start_position: point(edit.start_point),
+        old_end_position: point(edit.old_end_point()),
+        new_end_position: point(edit.new_end_point()),
+    }
+}
+
 #[derive(Debug)]
 struct BufferContentsTextProvider<'a>(&'a BufferContents);
 
@@ -43,3 +62,27 @@ impl<'a> ts::TextProvider<'a> for BufferContentsTextProvider<'a> {
         ByteSliceChunks(chunks)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tore::{EditSummary, Extent, Point};
+
+    #[test]
+    fn input_edit_from_summary() {
+        // replaces "1\n}" with "x" in "fn a() {\n    1\n}"
+        let edit = EditSummary {
+            start_offset: 13,
+            start_point: Point { line: 1, column: 4 },
+            removed: Extent { len: 3, lines: Point { line: 1, column: 1 } },
+            inserted: Extent { len: 1, lines: Point { line: 0, column: 1 } },
+        };
+        let edit = input_edit(&edit);
+        assert_eq!(edit.start_byte, 13);
+        assert_eq!(edit.old_end_byte, 16);
+        assert_eq!(edit.new_end_byte, 14);
+        assert_eq!(edit.start_position, ts::Point { row: 1, column: 4 });
+        assert_eq!(edit.old_end_position, ts::Point { row: 2, column: 1 });
+        assert_eq!(edit.new_end_position, ts::Point { row: 1, column: 5 });
+    }
+}
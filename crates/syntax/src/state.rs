@@ -0,0 +1,174 @@
+use anyhow::Result;
+use tree_sitter as ts;
+
+use crate::{input_edit, Command, Language, Symbols};
+use editor::Buffer;
+
+/// What the app knows of a buffer's syntax: the tree from the latest parse, and the edits made
+/// to the buffer since the version that tree was parsed from.
+///
+/// Edits are appended as the buffer changes and sent along with every parse request; the worker
+/// replays the ones its retained tree hasn't seen. Edits are only dropped once a tree covering
+/// them comes back, so the tree here plus the pending edits always describe the buffer.
+#[derive(Debug, Default)]
+pub struct SyntaxState {
+    tree: Option<(u64, ts::Tree)>,
+    /// Edits not yet reflected in `tree`, each with the version it produced, in order.
+    pending: Vec<(u64, ts::InputEdit)>,
+    /// The latest version a parse was requested for.
+    requested: Option<u64>,
+    /// The definitions found in a parsed tree, and the version it was parsed from.
+    symbols: Option<(u64, Symbols)>,
+}
+
+impl SyntaxState {
+    /// The tree of the latest parse and the version it was parsed from.
+    pub fn tree(&self) -> Option<(u64, &ts::Tree)> {
+        self.tree.as_ref().map(|(version, tree)| (*version, tree))
+    }
+
+    /// The definitions found in the latest parse, which may lag behind the buffer.
+    pub fn symbols(&self) -> Option<&Symbols> {
+        self.symbols.as_ref().map(|(_, symbols)| symbols)
+    }
+
+    pub fn pending(&self) -> &[(u64, ts::InputEdit)] {
+        &self.pending
+    }
+
+    /// Takes the edits `buffer` has made since they were last taken.
+    pub fn edited(&mut self, buffer: &mut Buffer) {
+        for (version, edit) in buffer.take_edits() {
+            debug_assert!(self
+                .pending
+                .last()
+                .map_or(true, |(last, _)| *last <= version));
+            self.pending.push((version, input_edit(&edit)));
+        }
+    }
+
+    /// A request to parse `buffer` as it is now, unless one was already made for its version.
+    pub fn parse(&mut self, buffer: &mut Buffer) -> Result<Option<Command>> {
+        self.edited(buffer);
+        if self.requested == Some(buffer.version) {
+            return Ok(None);
+        }
+        self.requested = Some(buffer.version);
+        Ok(Some(Command::Parse {
+            buffer_id: buffer.id,
+            contents: buffer.contents.clone(),
+            language: Language::try_from(&*buffer)?,
+            version: buffer.version,
+            edits: self.pending.clone(),
+        }))
+    }
+
+    /// Keeps the tree parsed from `version` and drops the edits it reflects. A tree older than
+    /// the one held already is ignored.
+    pub fn parsed(&mut self, version: u64, tree: ts::Tree) {
+        if self.tree.as_ref().is_some_and(|(held, _)| *held > version) {
+            return;
+        }
+        self.pending.retain(|(edited, _)| *edited > version);
+        self.tree = Some((version, tree));
+    }
+
+    /// Keeps the symbols found in the tree parsed from `version`, unless newer ones are held.
+    pub fn symbols_found(&mut self, version: u64, symbols: Symbols) {
+        if self
+            .symbols
+            .as_ref()
+            .is_some_and(|(held, _)| *held > version)
+        {
+            return;
+        }
+        self.symbols = Some((version, symbols));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::BufferContents;
+
+    fn tree(text: &str) -> ts::Tree {
+        let mut parser = ts::Parser::new();
+        parser.set_language(tree_sitter_rust::language()).unwrap();
+        parser.parse(text, None).unwrap()
+    }
+
+    fn versions(state: &SyntaxState) -> Vec<u64> {
+        state
+            .pending()
+            .iter()
+            .map(|(version, _)| *version)
+            .collect()
+    }
+
+    fn requested(command: Option<Command>) -> (u64, Vec<u64>) {
+        match command {
+            Some(Command::Parse { version, edits, .. }) => {
+                (version, edits.iter().map(|(version, _)| *version).collect())
+            }
+            None => panic!("no parse requested"),
+        }
+    }
+
+    #[test]
+    fn edits_and_parses_interleaved() -> Result<()> {
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from("fn a() {}\n"));
+        let mut state = SyntaxState::default();
+        assert_eq!(requested(state.parse(&mut buffer)?), (0, vec![]));
+        assert!(state.parse(&mut buffer)?.is_none());
+
+        buffer.apply_edits(&[(3..4, "b")])?;
+        buffer.apply_edits(&[(0..0, "\n"), (3..3, "c")])?;
+        assert_eq!(requested(state.parse(&mut buffer)?), (2, vec![1, 2, 2]));
+
+        // the first parse completes while the buffer moves on
+        state.parsed(0, tree("fn a() {}\n"));
+        buffer.apply_edits(&[(0..1, "")])?;
+        state.edited(&mut buffer);
+        assert_eq!(versions(&state), vec![1, 2, 2, 3]);
+        assert_eq!(requested(state.parse(&mut buffer)?), (3, vec![1, 2, 2, 3]));
+
+        state.parsed(2, tree("\nfnc b() {}\n"));
+        assert_eq!(versions(&state), vec![3]);
+        assert_eq!(state.tree().map(|(version, _)| version), Some(2));
+
+        // a late tree for an older version doesn't replace a newer one
+        state.parsed(1, tree("fn b() {}\n"));
+        assert_eq!(state.tree().map(|(version, _)| version), Some(2));
+        assert_eq!(versions(&state), vec![3]);
+
+        state.parsed(3, tree("fnc b() {}\n"));
+        assert!(state.pending().is_empty());
+        assert!(state.parse(&mut buffer)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn pending_edits_replay_onto_tree() -> Result<()> {
+        let text = "fn a() {}\n";
+        let mut buffer = Buffer::new(Default::default(), BufferContents::from(text));
+        let mut state = SyntaxState::default();
+        state.parse(&mut buffer)?;
+        state.parsed(0, tree(text));
+
+        buffer.apply_edits(&[(3..4, "bb"), (8..8, "1")])?;
+        buffer.apply_edits(&[(0..0, "\n")])?;
+        state.edited(&mut buffer);
+
+        let (_, tree) = state.tree().unwrap();
+        let mut tree = tree.clone();
+        state.pending().iter().for_each(|(_, edit)| tree.edit(edit));
+        let text = buffer.contents.to_string();
+        assert_eq!(text, "\nfn bb() {1}\n");
+        let mut parser = ts::Parser::new();
+        parser.set_language(tree_sitter_rust::language())?;
+        let reparsed = parser.parse(&text, Some(&tree)).unwrap();
+        assert_eq!(reparsed.root_node().to_sexp(), self::tree(&text).root_node().to_sexp());
+        assert_eq!(tree.root_node().end_byte(), text.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+use std::cmp::Reverse;
+use std::ops::Range;
+use tree_sitter as ts;
+
+use crate::Language;
+use editor::BufferContents;
+
+/// What sort of definition a symbol is, from the `definition.<kind>` capture that found it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Struct,
+    Enum,
+    Trait,
+    Impl,
+    Module,
+    Macro,
+    Type,
+    Constant,
+}
+
+impl SymbolKind {
+    fn from_capture(name: &str) -> Option<SymbolKind> {
+        let kind = match name.strip_prefix("definition.")? {
+            "function" => SymbolKind::Function,
+            "method" => SymbolKind::Method,
+            "struct" => SymbolKind::Struct,
+            "enum" => SymbolKind::Enum,
+            "trait" => SymbolKind::Trait,
+            "impl" => SymbolKind::Impl,
+            "module" => SymbolKind::Module,
+            "macro" => SymbolKind::Macro,
+            "type" => SymbolKind::Type,
+            "constant" => SymbolKind::Constant,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// A short word for the kind, as it's shown beside a symbol's name.
+    pub fn label(self) -> &'static str {
+        match self {
+            SymbolKind::Function | SymbolKind::Method => "fn",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Impl => "impl",
+            SymbolKind::Module => "mod",
+            SymbolKind::Macro => "macro",
+            SymbolKind::Type => "type",
+            SymbolKind::Constant => "const",
+        }
+    }
+}
+
+/// A definition in a buffer: what it's called, and the bytes of the whole definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub range: Range<usize>,
+}
+
+/// A buffer's symbols in the order they start, with an outer one before those it contains.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    symbols: Vec<Symbol>,
+    /// The index of the innermost symbol containing each one, if any does.
+    parents: Vec<Option<usize>>,
+}
+
+impl Symbols {
+    pub fn new(mut symbols: Vec<Symbol>) -> Self {
+        symbols.sort_by_key(|symbol| (symbol.range.start, Reverse(symbol.range.end)));
+        let mut parents = Vec::with_capacity(symbols.len());
+        let mut open: Vec<usize> = Vec::new();
+        for (i, symbol) in symbols.iter().enumerate() {
+            while open
+                .last()
+                .is_some_and(|&outer| symbols[outer].range.end < symbol.range.end)
+            {
+                open.pop();
+            }
+            parents.push(open.last().copied());
+            open.push(i);
+        }
+        Symbols { symbols, parents }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// The innermost symbol whose definition covers the byte `offset`.
+    pub fn enclosing(&self, offset: usize) -> Option<&Symbol> {
+        let starts = self
+            .symbols
+            .partition_point(|symbol| symbol.range.start <= offset);
+        // the last symbol starting at or before `offset`, or else one of those it's in
+        let mut i = starts.checked_sub(1)?;
+        loop {
+            if self.symbols[i].range.contains(&offset) {
+                return Some(&self.symbols[i]);
+            }
+            i = self.parents[i]?;
+        }
+    }
+}
+
+/// Finds the definitions in `tree` with the language's tags query.
+#[tracing::instrument(skip_all)]
+pub fn symbols(buffer: &BufferContents, language: &Language, tree: &ts::Tree) -> Symbols {
+    let query = ts::Query::new(language.ts, &language.tags_query).expect("invalid query");
+    let name_index = query.capture_index_for_name("name");
+    let mut cursor = ts::QueryCursor::new();
+    let matches =
+        cursor.matches(&query, tree.root_node(), crate::BufferContentsTextProvider(buffer));
+    let mut symbols = Vec::new();
+    for query_match in matches {
+        let mut definition = None;
+        let mut name = None;
+        for capture in query_match.captures {
+            if Some(capture.index) == name_index {
+                name = Some(capture.node);
+            } else {
+                let capture_name = &query.capture_names()[capture.index as usize];
+                definition =
+                    SymbolKind::from_capture(capture_name).map(|kind| (kind, capture.node));
+            }
+        }
+        let (Some((kind, node)), Some(name)) = (definition, name) else {
+            continue;
+        };
+        // a module's body is a declaration list too, so look at what the list belongs to
+        let block = node.parent().and_then(|list| list.parent());
+        let in_impl = block.is_some_and(|block| matches!(block.kind(), "impl_item" | "trait_item"));
+        let kind = match kind {
+            SymbolKind::Function if in_impl => SymbolKind::Method,
+            kind => kind,
+        };
+        let name = match kind {
+            // `Display for Point` reads better than just the type
+            SymbolKind::Impl => {
+                let start = node.child_by_field_name("trait").unwrap_or(name);
+                start.start_byte()..name.end_byte()
+            }
+            _ => name.byte_range(),
+        };
+        let text: String = buffer.byte_slice(name).chars().collect();
+        symbols.push(Symbol {
+            name: text.split_whitespace().collect::<Vec<_>>().join(" "),
+            kind,
+            range: node.byte_range(),
+        });
+    }
+    Symbols::new(symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::Buffer;
+
+    const SOURCE: &str = "\
+const LIMIT: usize = 3;
+
+struct Point {
+    x: usize,
+}
+
+impl std::fmt::Display
+    for Point {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, \"{}\", self.x)
+    }
+}
+
+mod inner {
+    pub fn helper() {}
+}
+
+trait Shape {
+    fn area(&self) -> f64;
+}
+";
+
+    fn extracted(text: &str) -> Symbols {
+        let buffer = Buffer::new(Default::default(), text.into());
+        let language = Language::try_from(&buffer).unwrap();
+        let mut parser = ts::Parser::new();
+        parser.set_language(language.ts).unwrap();
+        let tree = parser.parse(text, None).unwrap();
+        symbols(&buffer.contents, &language, &tree)
+    }
+
+    #[test]
+    fn definitions_found() {
+        let symbols = extracted(SOURCE);
+        let found: Vec<_> = symbols
+            .iter()
+            .map(|symbol| (symbol.kind, symbol.name.as_str(), &SOURCE[symbol.range.clone()]))
+            .map(|(kind, name, text)| (kind, name, text.lines().next().unwrap()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (SymbolKind::Constant, "LIMIT", "const LIMIT: usize = 3;"),
+                (SymbolKind::Struct, "Point", "struct Point {"),
+                (SymbolKind::Impl, "std::fmt::Display for Point", "impl std::fmt::Display"),
+                (
+                    SymbolKind::Method,
+                    "fmt",
+                    "fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {"
+                ),
+                (SymbolKind::Module, "inner", "mod inner {"),
+                (SymbolKind::Function, "helper", "pub fn helper() {}"),
+                (SymbolKind::Trait, "Shape", "trait Shape {"),
+                (SymbolKind::Method, "area", "fn area(&self) -> f64;"),
+            ]
+        );
+    }
+
+    #[test]
+    fn enclosing_symbol() {
+        let symbols = extracted(SOURCE);
+        let at = |needle: &str| {
+            let offset = SOURCE.find(needle).unwrap();
+            symbols.enclosing(offset).map(|symbol| symbol.name.as_str())
+        };
+        assert_eq!(at("LIMIT"), Some("LIMIT"));
+        assert_eq!(at("\n\nstruct"), None);
+        assert_eq!(at("x: usize"), Some("Point"));
+        assert_eq!(at("write!"), Some("fmt"));
+        // past the method, but still in the impl
+        assert_eq!(at("}\n\nmod"), Some("std::fmt::Display for Point"));
+        assert_eq!(at("helper"), Some("helper"));
+        assert_eq!(at("fn area"), Some("area"));
+        assert_eq!(symbols.enclosing(SOURCE.len()), None);
+    }
+
+    #[test]
+    fn nested_ranges_parented() {
+        let symbol = |name: &str, range: Range<usize>| Symbol {
+            name: name.into(),
+            kind: SymbolKind::Function,
+            range,
+        };
+        let symbols = Symbols::new(vec![
+            symbol("c", 20..30),
+            symbol("a", 0..40),
+            symbol("b", 2..10),
+            symbol("d", 40..50),
+        ]);
+        let names: Vec<_> = symbols.iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b", "c", "d"]);
+        assert_eq!(symbols.parents, vec![None, Some(0), Some(0), None]);
+        let at = |offset| symbols.enclosing(offset).map(|symbol| symbol.name.as_str());
+        assert_eq!(at(5), Some("b"));
+        assert_eq!(at(15), Some("a"));
+        assert_eq!(at(30), Some("a"));
+        assert_eq!(at(40), Some("d"));
+        assert_eq!(at(50), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! Fixtures and helpers shared by the tests of the workspace's crates.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rope::compat::Rope;
+use rope::SlabAllocator;
+use tore::Point;
+
+/// A few lines of text to build ropes and trees out of.
+pub const SONG: &str = "This is the song that never ends.\n\
+    It just goes 'round and 'round, my friends.\n\
+    Some people started singing it\n\
+    not knowing what it was;\n\
+    and they continue singing it forever just because...\n";
+
+/// Where `dump` writes, relative to the crate being tested.
+const DUMP_DIR: &str = "target/tests";
+/// Set to have `dump` write anything at all.
+pub const DUMP_VAR: &str = "TEST_DUMP";
+
+pub fn rope_from_str(text: &str) -> Rope {
+    Rope::from_str(text)
+}
+
+/// A rope with each of `parts` in a chunk of its own, so a test can put a chunk boundary exactly
+/// where it needs one, like in the middle of a line or between a grapheme's chars. The tree is
+/// left as built. Each part must hold whole chars, as every chunk of a rope does.
+pub fn rope_from_parts(parts: &[&str]) -> Rope {
+    let tree =
+        parts
+            .iter()
+            .filter(|part| !part.is_empty())
+            .fold(rope::Rope::empty(), |rope, part| {
+                // an allocator of its own keeps the part from being merged into the chunk before
+                let (slab, _) = SlabAllocator::new().append_str(part);
+                rope.append(slab).expect("appending never fails")
+            });
+    Rope::from(tree)
+}
+
+/// A rope built up by inserting each text at its char offset, in order, starting from nothing.
+pub fn rope_from_insert_script(script: &[(usize, &str)]) -> Rope {
+    let mut rope = Rope::new();
+    for (at, text) in script {
+        rope.insert(*at, text);
+    }
+    rope
+}
+
+/// The text of a buffer and its cursors, from `marked` with a `|` at each cursor, like `"ab|c"`.
+/// Columns count chars, as the editor's do.
+pub fn buffer_with_cursors(marked: &str) -> (String, Vec<Point>) {
+    let (mut text, mut cursors) = (String::with_capacity(marked.len()), vec![]);
+    let mut point = Point::default();
+    for c in marked.chars() {
+        match c {
+            '|' => cursors.push(point),
+            '\n' => {
+                text.push(c);
+                point = Point { line: point.line + 1, column: 0 };
+            }
+            c => {
+                text.push(c);
+                point.column += 1;
+            }
+        }
+    }
+    (text, cursors)
+}
+
+/// Like `buffer_with_cursors`, for text marked with exactly one cursor.
+#[track_caller]
+pub fn buffer_with_cursor(marked: &str) -> (String, Point) {
+    let (text, cursors) = buffer_with_cursors(marked);
+    let [cursor] = cursors[..] else {
+        panic!("expected one cursor in {:?}, found {}", marked, cursors.len());
+    };
+    (text, cursor)
+}
+
+/// A small deterministic generator for property tests, so a failing seed can be run again.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        // the low bits of an LCG repeat quickly
+        self.0 >> 33
+    }
+
+    /// A number below `n`; 0 when `n` is.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n.max(1) as u64) as usize
+    }
+
+    /// One of `items`, which mustn't be empty.
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// A directory of its own for a test's files, removed when dropped however the test ends.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A fresh, empty directory under the system's temp directory. Whatever an earlier run of the
+/// same process id left there is cleared first, so a test never sees it.
+pub fn temp_dir() -> TempDir {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!("toku-test-{}-{n}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).expect("create temp directory");
+    TempDir(path)
+}
+
+/// Writes a file `name` in `target/tests` with `write`, for looking at what a test built, like a
+/// tree as dot; only when `TEST_DUMP` is set, and otherwise does nothing.
+pub fn dump(name: &str, write: impl FnOnce(&mut std::fs::File) -> std::io::Result<()>) {
+    if std::env::var_os(DUMP_VAR).is_none() {
+        return;
+    }
+    let dir = Path::new(DUMP_DIR);
+    std::fs::create_dir_all(dir).expect("create dump directory");
+    let mut file = std::fs::File::create(dir.join(name)).expect("create dump file");
+    write(&mut file).expect("write dump");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_as_given() {
+        let parts = ["ab", "c\u{301}", "\u{301}d\n", "e"];
+        let rope = rope_from_parts(&parts);
+        assert_eq!(rope.chunks().collect::<Vec<_>>(), parts);
+        assert_eq!(rope, rope_from_str("abc\u{301}\u{301}d\ne"));
+
+        let rope = rope_from_insert_script(&[(0, "ac"), (1, "b"), (3, "\n")]);
+        assert_eq!(rope, "abc\n");
+    }
+
+    #[test]
+    fn marked_cursors() {
+        let (text, cursors) = buffer_with_cursors("|ab\nc|é|\n|");
+        assert_eq!(text, "ab\ncé\n");
+        let points = [(0, 0), (1, 1), (1, 2), (2, 0)].map(|(line, column)| Point { line, column });
+        assert_eq!(cursors, points);
+        assert_eq!(buffer_with_cursor("x|"), ("x".to_string(), Point { line: 0, column: 1 }));
+    }
+
+    #[test]
+    fn seeds_repeat() {
+        let (mut a, mut b) = (Rng::new(7), Rng::new(7));
+        let rolls: Vec<_> = (0..10).map(|_| a.below(6)).collect();
+        assert_eq!(rolls, (0..10).map(|_| b.below(6)).collect::<Vec<_>>());
+        assert!(rolls.iter().all(|roll| *roll < 6));
+        assert_eq!(Rng::new(1).below(0), 0);
+    }
+
+    #[test]
+    fn temp_dirs_removed_when_dropped() {
+        let (a, b) = (temp_dir(), temp_dir());
+        assert_ne!(a.path(), b.path());
+        std::fs::write(a.path().join("a.rs"), "fn a() {}\n").unwrap();
+        let path = a.path().to_path_buf();
+        drop(a);
+        assert!(!path.exists());
+    }
+}
@@ -1,52 +1,233 @@
 use anyhow::Result;
+use commands::{EntryMode, PaneKind};
 use crossterm::cursor::{self, SetCursorStyle};
-use crossterm::event::{Event, EventStream, KeyEvent};
-use futures::{Future, FutureExt};
+use crossterm::event::{Event, EventStream};
+use futures::{Future, Stream};
 use ratatui::backend::CrosstermBackend;
 use ratatui::prelude as tui;
 use slotmap::{new_key_type, SecondaryMap, SlotMap};
+use std::collections::{HashMap, VecDeque};
 use std::io::Stdout;
-use std::pin::Pin;
 use std::sync::Arc;
-use tokio::sync::mpsc;
-use tokio::task::JoinHandle;
-use tree_sitter as ts;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::MissedTickBehavior;
 
-use editor::{Buffer, BufferCommand, BufferId, Editor, EditorCommand, EditorId};
-use selector::Selector;
-use syntax::Syntax;
+use editor::{
+    Buffer, BufferCommand, BufferContents, BufferId, CancellationToken, DiffAlignment, DiffSide,
+    DiskState, Editor, EditorCommand, EditorId, GroupId, SearchMode, UndoMove,
+};
+use selector::{FuzzyIndex, Selector};
+use syntax::{Syntax, SyntaxState};
+use tore::key::{InputKey, Key, Modifiers};
 use tore::CursorPoint;
 
+use crate::config::{self, ConfigFiles};
+use crate::editorconfig;
+use crate::events::{self, AppEvent};
+use crate::export;
+use crate::format;
+use crate::grep::{self, GrepResults};
+use crate::history::HistoryBudget;
+use crate::latency::Latency;
+use crate::load;
+use crate::outline::Outline;
+use crate::path;
+use crate::project;
+use crate::prompt::{self, Prompt};
+use crate::recovery::{self, Recovery};
+use crate::registers::{self, Registers};
+use crate::run::{self, Runs};
+use crate::terminal::{self, TerminalGuard};
+use crate::validate::Validator;
+
 type BufferMap = SlotMap<BufferId, Buffer>;
 type EditorMap = SlotMap<EditorId, Editor>;
-type SyntaxTreeMap = SecondaryMap<BufferId, ts::Tree>;
+type SyntaxStateMap = SecondaryMap<BufferId, SyntaxState>;
 type Terminal = ratatui::Terminal<CrosstermBackend<Stdout>>;
 
 #[derive(Debug, Clone)]
 pub enum PaneCommand {
     Open,
     Close,
+    /// Moves the focus to the other side of a diff.
+    SwitchSide,
 }
 
 #[derive(Debug, Clone)]
 pub enum Command {
     Quit,
+    Help,
+    Version,
+    Suspend,
+    Resume,
+    /// The terminal was resized, leaving frames to be drawn in the area.
+    Resize(tui::Rect),
+    /// The terminal gained focus, or with `false`, lost it. Losing it journals every unsaved
+    /// buffer; gaining it looks for files changed on disk while away.
+    FocusChanged(bool),
     FileOpen(Option<EditorId>, std::path::PathBuf),
+    /// Opens a file like `FileOpen` and moves the cursor to the point once it's in. A file
+    /// that's already open is shown as it is in its buffer. An overlay it's opened from closes.
+    FileOpenAt(Option<EditorId>, std::path::PathBuf, tore::Point),
+    /// Opens a file that isn't valid Unicode read-only, replacing what doesn't decode; without
+    /// a path, opens the last file that failed to.
+    FileOpenLossy(Option<EditorId>, Option<std::path::PathBuf>),
+    /// How much of a file loading in the background has been read, and its size if known.
+    FileLoadProgress(LoadId, u64, Option<u64>),
+    /// A background load finished, with the contents, their format, and how many bad sequences
+    /// a lossy load replaced.
+    FileLoaded(LoadId, Result<(BufferContents, editor::Format, usize), Arc<anyhow::Error>>),
+    /// Stops every file still loading in the background.
+    FileOpenCancel,
+    BufferOpen(std::path::PathBuf),
+    /// Opens the two files read-only side by side, scrolled together, with the lines that
+    /// differ shaded.
+    DiffOpen(std::path::PathBuf, std::path::PathBuf),
+    BufferSave(BufferId),
+    /// Opens the file in the default editor; without a path, says one's needed.
+    Edit(Option<std::path::PathBuf>),
+    /// Saves the visible buffer, to the path if there is one, which it's then saved to from then
+    /// on. Its file having changed on disk since it was read or saved stops it, and says so.
+    Write(Option<std::path::PathBuf>),
+    /// Saves the visible buffer like `Write`, even over a file that changed on disk.
+    WriteForce(Option<std::path::PathBuf>),
+    /// Reads the visible buffer's file again, discarding its unsaved changes, as an edit that
+    /// can be undone.
+    Reload,
+    /// Opens the visible buffer's file as it is on disk, read-only, side by side with the
+    /// buffer, which can still be edited.
+    DiffDisk,
+    /// Diffs the buffer against its saved contents in the background.
+    BufferDiff(BufferId),
+    /// The changes a diff found in the buffer at the given version.
+    SetLineDiff(BufferId, u64, Vec<editor::LineChange>),
+    /// Finds the matches of the searches in every editor on the buffer again, after it changed,
+    /// and builds its word index again in the background if an edit was too large to follow.
+    SearchRefresh(BufferId),
+    /// The word index built from the buffer at the given version.
+    SetWordIndex(BufferId, u64, editor::WordIndex),
+    /// Journals the buffer's unsaved contents in the background, to recover after a crash.
+    BufferJournal(BufferId),
+    /// Tells the language server of the buffer's language its contents, if there is one.
+    LspSync(BufferId),
+    /// The problems a language server found in the file at the given version, or at whatever
+    /// version the buffer is now if it didn't say.
+    SetDiagnostics(std::path::PathBuf, Option<u64>, Vec<editor::Diagnostic>),
+    /// Restores the contents journaled for open buffers before a crash, leaving them unsaved.
+    Recover,
+    /// Reads the theme, the key bindings and every open buffer's `.toku.toml` and `.editorconfig`
+    /// files again, keeping the theme or bindings as they are if their file doesn't read.
+    ConfigReload,
+    /// Reads a theme from the file and draws with it, keeping the theme as it is if the file
+    /// doesn't read; `ConfigReload` reads it again from then on. Without a path, says one's
+    /// needed.
+    Theme(Option<std::path::PathBuf>),
+    /// Runs the lines as if typed into the command palette, in order, once no file is still
+    /// loading.
+    Script(Vec<String>),
+    /// Comments out the focused editor's selected lines with its language's line comments, or
+    /// uncomments them.
+    ToggleComment,
+    /// Pipes the focused buffer through its language's formatter, or in visual line mode, only
+    /// the selected lines if the formatter can be told which.
+    Format,
+    /// What the formatter made of the buffer at the given version, or why it failed.
+    Formatted(BufferId, u64, Result<String, String>),
+    /// Lists the open buffers by number, marking which are shown and which are unsaved.
+    ListBuffers,
+    /// Shows the buffer with the number `:ls` lists it by in the editor.
+    SwitchBuffer(String),
+    /// Swaps the editor back to the buffer it showed before.
+    AlternateBuffer(EditorId),
+    /// Opens another editor on the focused editor's buffer, over it, with the cursor where it is.
+    Split,
+    /// Closes the focused pane, or with an overlay focused, it and the pane under it. The last
+    /// editor pane stays open.
+    Close,
+    /// Moves the focused editor's buffer back that many undo states, in the order they were
+    /// made, or one without a count; or as far back in time with `s`, `m` or `h` after it.
+    Earlier(String),
+    /// Like `Earlier`, but forward.
+    Later(String),
+    /// Lists the undo states of the focused editor's buffer, marking the current one.
+    UndoList,
     Pane(PaneId, PaneCommand),
     Buffer(BufferId, BufferCommand),
     Editor(EditorId, EditorCommand),
     FocusedEditor(EditorCommand),
+    /// Marks the editor's cursor; a capital letter names a mark any buffer can jump to.
+    SetMark(EditorId, char),
+    /// Jumps to a mark, switching the editor to the marked buffer for a capital letter.
+    JumpToMark(EditorId, char, editor::MarkJump),
+    /// Runs the editor command, then keeps what it put in the editor's register in the named one
+    /// too, like vim's `"a` before an operator.
+    YankInto(EditorId, char, EditorCommand),
+    /// Puts the named register's text after the cursor, or with `true` before it.
+    Paste(EditorId, char, bool),
+    /// Lists what every register holds, how, and the start of it.
+    Registers,
     Commands(selector::Command<CommandId>),
+    /// Completes the path typed as the palette's argument as far as its candidates agree.
+    CommandsComplete,
+    /// Searches the files under the working directory for the text, listing the matches.
+    Grep(String),
+    /// Turns `title`, `number`, `autopairs`, `regex`, `blockcursor`, `list`, `smoothscroll`,
+    /// `statussymbol` or `showlatency` on, or off when prefixed with `no`; sets what `list` draws
+    /// whitespace as with `listchars=`; or sets `key=value` for the focused buffer, with the keys
+    /// of an `.editorconfig`.
+    Set(String),
+    /// Turns auditing buffers' summaries and line counts after every edit `on` or `off`.
+    Validate(String),
+    /// Matches found by the search with the given id.
+    GrepMatches(u64, Vec<grep::GrepMatch>),
+    /// The search with the given id is done, and whether it stopped short of every match.
+    GrepDone(u64, bool),
+    GrepResults(selector::Command<usize>),
+    /// Runs the shell command, streaming its output into a read-only preview.
+    Run(String),
+    /// Output of the run with the given id, as whole lines.
+    RunOutput(u64, String),
+    /// The run with the given id is done, and how it exited.
+    RunDone(u64, run::Exit),
+    /// Kills the running command.
+    RunKill,
+    /// Shows the highlights and syntax nodes under the cursor of the focused editor.
+    InspectHighlight,
+    /// Shows how long inputs have taken to reach the screen, and where the time went.
+    Profile,
+    /// Writes the focused editor's buffer as the theme draws it, as an HTML page or as text with
+    /// ANSI colors, to the path typed, optionally after a range of lines to write.
+    ExportHtml(String),
+    ExportAnsi(String),
+    /// Lists the symbols of the focused editor's buffer, to jump to one.
+    Outline,
+    OutlineResults(selector::Command<usize>),
+    /// Jumps to the symbol focused in the outline, marking where the cursor was with `'`.
+    OutlineJump,
+    /// Moves the focus between the answers to the prompt.
+    Prompt(selector::Command<prompt::Answer>),
+    /// Runs the command without asking first, once the prompt asking about it was answered yes.
+    Confirmed(Box<Command>),
 }
 
 new_key_type! {
     pub struct PaneId;
+    pub struct LoadId;
 }
 
 #[derive(Debug, Clone)]
 pub enum Pane {
     Commands(PaneId),
     Editor(PaneId, EditorId),
+    /// Read-only command output, shown through the shared preview editor.
+    Preview(PaneId, BufferId),
+    Grep(PaneId),
+    /// The symbols of the buffer under it, to jump to one.
+    Outline(PaneId),
+    /// Asks whether to run a command that would lose work.
+    Prompt(PaneId),
+    /// Two files side by side, scrolled together.
+    Diff(PaneId, GroupId),
 }
 
 impl Pane {
@@ -54,6 +235,30 @@ impl Pane {
         match self {
             Pane::Commands(id, ..) => *id,
             Pane::Editor(id, ..) => *id,
+            Pane::Preview(id, ..) => *id,
+            Pane::Grep(id) => *id,
+            Pane::Outline(id) => *id,
+            Pane::Prompt(id) => *id,
+            Pane::Diff(id, ..) => *id,
+        }
+    }
+
+    fn kind(&self) -> PaneKind {
+        match self {
+            Pane::Commands(..) => PaneKind::Commands,
+            Pane::Editor(..) | Pane::Diff(..) => PaneKind::Editor,
+            Pane::Preview(..) => PaneKind::Preview,
+            Pane::Grep(..) => PaneKind::Grep,
+            Pane::Outline(..) => PaneKind::Outline,
+            Pane::Prompt(..) => PaneKind::Prompt,
+        }
+    }
+
+    /// Overlays sit on top of the layout only while they have focus.
+    fn is_overlay(&self) -> bool {
+        match self {
+            Pane::Commands(..) | Pane::Grep(..) | Pane::Outline(..) | Pane::Prompt(..) => true,
+            Pane::Editor(..) | Pane::Preview(..) | Pane::Diff(..) => false,
         }
     }
 }
@@ -68,6 +273,59 @@ impl Pane {
     fn new_commands(id: PaneId) -> Self {
         Pane::Commands(id)
     }
+
+    fn new_preview(id: PaneId, buffer_id: BufferId) -> Self {
+        Pane::Preview(id, buffer_id)
+    }
+
+    fn new_grep(id: PaneId) -> Self {
+        Pane::Grep(id)
+    }
+
+    fn new_outline(id: PaneId) -> Self {
+        Pane::Outline(id)
+    }
+
+    fn new_prompt(id: PaneId) -> Self {
+        Pane::Prompt(id)
+    }
+
+    fn new_diff(id: PaneId, group: GroupId) -> Self {
+        Pane::Diff(id, group)
+    }
+}
+
+/// Two buffers diffed side by side, each in an editor of its own, scrolled together.
+#[derive(Debug)]
+struct DiffView {
+    /// The editors of the left side and of the right.
+    editors: [EditorId; 2],
+    /// The side keys go to.
+    focused: DiffSide,
+    alignment: DiffAlignment,
+    /// The buffers, and their versions, the alignment was worked out from.
+    aligned: [(BufferId, u64); 2],
+    /// A buffer shown that stays editable, as its file isn't what's diffed.
+    editable: Option<BufferId>,
+}
+
+impl DiffView {
+    fn editor_id(&self, side: DiffSide) -> EditorId {
+        match side {
+            DiffSide::Left => self.editors[0],
+            DiffSide::Right => self.editors[1],
+        }
+    }
+
+    fn focused_editor_id(&self) -> EditorId {
+        self.editor_id(self.focused)
+    }
+
+    fn side_of(&self, editor_id: EditorId) -> Option<DiffSide> {
+        [DiffSide::Left, DiffSide::Right]
+            .into_iter()
+            .find(|side| self.editor_id(*side) == editor_id)
+    }
 }
 
 new_key_type! {
@@ -78,94 +336,229 @@ new_key_type! {
 struct Entry {
     name: &'static str,
     aliases: Vec<&'static str>,
+    mode: EntryMode,
     command: Command,
+    /// Makes the command from what's typed after the name, for entries that take an argument.
+    argument: Option<Argument>,
+}
+
+impl Entry {
+    /// The entry's command, made from `argument` if it takes one; a typed path is relative to
+    /// `base`.
+    fn command_with(&self, argument: Option<&str>, base: Option<&std::path::Path>) -> Command {
+        match (self.argument, argument) {
+            (Some(Argument::Text(command)), Some(argument)) => command(argument.to_string()),
+            (Some(Argument::Path(command)), Some(argument)) if !argument.is_empty() => {
+                command(Some(path::expand(argument, base)))
+            }
+            _ => self.command.clone(),
+        }
+    }
+}
+
+/// How an entry makes its command from the argument typed after its name.
+#[derive(Debug, Clone, Copy)]
+enum Argument {
+    Text(fn(String) -> Command),
+    /// A path, expanded and offered completions as it's typed; None if nothing is.
+    Path(fn(Option<std::path::PathBuf>) -> Command),
+}
+
+/// A row of the command palette's results.
+#[derive(Clone, Copy)]
+enum CommandRow<'a> {
+    Entry(CommandId),
+    /// What the path argument could be completed to; listed after the entries, never focused.
+    Completion(&'a str),
 }
 
 #[derive(Debug)]
 struct CommandRegistry {
     entries: SlotMap<CommandId, Entry>,
     selector: Selector<CommandId>,
-    // filtered: Vec<CommandId>,
+    context: commands::Context,
+    /// The entries' names and aliases, matched against the name typed.
+    index: FuzzyIndex<CommandId, &'static str>,
+    /// Whether the query, entries, context or base changed since the entries were matched.
+    stale: bool,
+    /// What's typed after the first space of the query, for an entry that takes an argument.
+    argument: Option<String>,
+    /// The focused buffer's path when the palette opened, that typed paths are relative to.
+    base: Option<std::path::PathBuf>,
+    /// What the argument could be completed to, when the focused entry takes a path.
+    completions: Vec<String>,
 }
 
 impl CommandRegistry {
     fn new() -> Self {
         let selector = Selector::new(":");
         let entries = SlotMap::with_key();
-        Self { entries, selector }
+        let context = commands::Context::default();
+        let (index, stale) = (FuzzyIndex::new(), true);
+        let (argument, base, completions) = (None, None, vec![]);
+        Self { entries, selector, context, index, stale, argument, base, completions }
     }
 
-    // fn focused(&self) -> Option<Command> {
-    //     self.selector
-    //         .focused
-    //         .map(|id| self.entries[id].command.clone())
-    // }
+    fn focused(&self) -> Option<Command> {
+        let entry = &self.entries[self.selector.focused?];
+        Some(entry.command_with(self.argument.as_deref(), self.base.as_deref()))
+    }
+
+    /// The command `line` names as it would be typed into the palette: a name or alias, then
+    /// the argument after a space for an entry that takes one. None if no entry has the name.
+    fn parse(&self, line: &str) -> Option<Command> {
+        let line = line.trim();
+        let (name, argument) = match line.split_once(' ') {
+            Some((name, argument)) => (name, Some(argument.trim_start())),
+            None => (line, None),
+        };
+        let entry = self
+            .entries
+            .values()
+            .find(|entry| entry.name == name || entry.aliases.contains(&name))?;
+        Some(entry.command_with(argument, None))
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.values()
+    }
 
     fn register(
         &mut self,
         name: &'static str,
         aliases: Vec<&'static str>,
+        mode: EntryMode,
         command: Command,
     ) -> CommandId {
-        self.entries.insert(Entry { name, aliases, command })
-    }
-
-    async fn update(&mut self, query: &str) {
-    }
-
-    // async fn update(&mut self, query: &str) {
-    //     tokio::spawn(async move {
-    //         let mut results = vec![];
-    //         // if self.selector.query.is_empty() {
-    //         // for (id, _) in &self.entries {
-    //         //     // todo: handle max results
-    //         //     if results.len() > 32 {
-    //         //         break;
-    //         //     }
-    //         //     results.push(id);
-    //         // }
-    //         // } else {
-    //         //     let matcher = SkimMatcherV2::default();
-    //         //     for (id, entry) in &self.entries {
-    //         //         let result = matcher.fuzzy_indices(&entry.name, &self.query);
-    //         //         if let Some((score, indices)) = result {
-    //         //             results.push(id)
-    //         //         }
-    //         //     }
-    //         //     // results.sort_by_key(|entry| entry.score);
-    //         // }
-
-    //         // self.selected = results.first().map(|r| r.entry);
-    //         // self.filtered = results;
-    //         results
-    //     });
-    // }
-
-    // fn results(&self) -> Vec<CommandId> {
-    //     let mut results = vec![];
-    //     // if self.selector.query.is_empty() {
-    //     for (id, _) in &self.entries {
-    //         // todo: handle max results
-    //         if results.len() > 32 {
-    //             break;
-    //         }
-    //         results.push(id);
-    //     }
-    //     // } else {
-    //     //     let matcher = SkimMatcherV2::default();
-    //     //     for (id, entry) in &self.entries {
-    //     //         let result = matcher.fuzzy_indices(&entry.name, &self.query);
-    //     //         if let Some((score, indices)) = result {
-    //     //             results.push(id)
-    //     //         }
-    //     //     }
-    //     //     // results.sort_by_key(|entry| entry.score);
-    //     // }
-
-    //     // self.selected = results.first().map(|r| r.entry);
-    //     // self.filtered = results;
-    //     results
-    // }
+        self.insert(Entry { name, aliases, mode, command, argument: None })
+    }
+
+    /// Registers a command that takes an argument, typed after its name and a space; run without
+    /// one, it's made from an empty argument.
+    fn register_with_argument(
+        &mut self,
+        name: &'static str,
+        aliases: Vec<&'static str>,
+        mode: EntryMode,
+        command: fn(String) -> Command,
+    ) -> CommandId {
+        let argument = Some(Argument::Text(command));
+        self.insert(Entry { name, aliases, mode, command: command(String::new()), argument })
+    }
+
+    /// Registers a command that takes a path, like `register_with_argument`; run without one, it's
+    /// made from None.
+    fn register_with_path(
+        &mut self,
+        name: &'static str,
+        aliases: Vec<&'static str>,
+        mode: EntryMode,
+        command: fn(Option<std::path::PathBuf>) -> Command,
+    ) -> CommandId {
+        let argument = Some(Argument::Path(command));
+        self.insert(Entry { name, aliases, mode, command: command(None), argument })
+    }
+
+    fn insert(&mut self, entry: Entry) -> CommandId {
+        let (name, aliases) = (entry.name, entry.aliases.clone());
+        let id = self.entries.insert(entry);
+        self.index.insert(id, name, aliases);
+        self.stale = true;
+        id
+    }
+
+    /// Sets the path of the focused buffer, that paths typed as arguments are relative to.
+    fn set_base(&mut self, base: Option<std::path::PathBuf>) {
+        if base != self.base {
+            self.base = base;
+            self.stale = true;
+        }
+        self.update();
+    }
+
+    /// Completes the path typed as the argument as far as every candidate agrees.
+    fn complete(&mut self) {
+        let Some(argument) = &self.argument else {
+            return;
+        };
+        let prefix = path::common_prefix(&self.completions);
+        if prefix.len() <= argument.len() {
+            return;
+        }
+        let query = self.selector.query.as_str();
+        let name = query.split_once(' ').map_or("", |(name, _)| name);
+        let completed = format!("{} {}", name, prefix);
+        self.selector.query.set(&completed);
+        self.stale = true;
+        self.update();
+    }
+
+    fn set_context(&mut self, context: commands::Context) {
+        if context != self.context {
+            self.context = context;
+            self.index.invalidate();
+            self.stale = true;
+        }
+        self.update();
+    }
+
+    fn command(&mut self, command: selector::Command<CommandId>) {
+        // moving the focus leaves the query as it was, and what it matched
+        self.stale |= matches!(
+            command,
+            selector::Command::Insert(_)
+                | selector::Command::Delete(_)
+                | selector::Command::SetEntries(_)
+        );
+        self.selector.command(command);
+        self.update();
+    }
+
+    fn reset(&mut self) {
+        self.selector.reset();
+        self.stale = true;
+        self.update();
+    }
+
+    /// Matches the entries against the query, unless nothing has changed since they last were.
+    fn update(&mut self) {
+        if !self.stale {
+            return;
+        }
+        self.stale = false;
+        // once there's an argument only the name before it is matched, against the entries that
+        // take one
+        let query = self.selector.query.as_str();
+        let (query, argument) = match query.split_once(' ') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (query, None),
+        };
+        if argument.is_some() != self.argument.is_some() {
+            self.index.invalidate();
+        }
+        let modes = self.context.modes();
+        let entries = &self.entries;
+        self.index.filter(query, |entry| {
+            let entry = &entries[entry.id];
+            modes.contains(entry.mode) && (argument.is_none() || entry.argument.is_some())
+        });
+
+        match (argument, &mut self.argument) {
+            (Some(argument), Some(kept)) => {
+                kept.clear();
+                kept.push_str(argument);
+            }
+            (argument, kept) => *kept = argument.map(str::to_string),
+        }
+        self.selector.set_entries(self.index.ids());
+        let focused = self.selector.focused.map(|id| &self.entries[id]);
+        self.completions = match (focused.and_then(|entry| entry.argument), &self.argument) {
+            (Some(Argument::Path(_)), Some(argument)) => {
+                path::complete(argument, self.base.as_deref())
+            }
+            _ => vec![],
+        };
+    }
 
     fn render(
         &self,
@@ -174,19 +567,39 @@ impl CommandRegistry {
         theme: &ui::Theme,
     ) -> (CursorPoint, SetCursorStyle) {
         let widget = ui::SelectorPane::new(theme, &self.selector);
-        widget.render(buf, area, &self.selector.entries, |area, buf, id| {
-            self.render_result(area, buf, id)
+        let entries = self.selector.entries.iter().copied().map(CommandRow::Entry);
+        let completions = self.completions.iter().map(|c| CommandRow::Completion(c));
+        let rows = entries.chain(completions);
+        widget.render(buf, area, rows, |area, buf, row| match row {
+            CommandRow::Entry(id) => self.render_result(area, buf, id),
+            CommandRow::Completion(completion) => {
+                let style = tui::Style::reset().add_modifier(tui::Modifier::DIM);
+                buf.set_stringn(area.x, area.y, completion, usize::from(area.width), style);
+            }
         })
     }
 
     fn render_result(&self, area: tui::Rect, buf: &mut tui::Buffer, id: CommandId) {
         use bstr::ByteSlice;
         let entry = &self.entries[id];
-        let content = entry.name;
-        let mut graphemes = content.as_bytes().as_bstr().graphemes();
+        // drawn from the entry's own strings, rather than a label made for every row of every
+        // frame
+        let mut graphemes = commands::label_parts(entry.name, &entry.aliases)
+            .flat_map(|part| part.as_bytes().as_bstr().graphemes());
+        let m = self.index.results().find(|(matched, _)| matched.id == id);
+        let mut indices = m
+            .into_iter()
+            .flat_map(|(_, m)| m.label_indices(entry.name, &entry.aliases))
+            .peekable();
         for (idx, x) in (area.left()..area.right()).enumerate() {
             let symbol = graphemes.next().unwrap_or(" ");
-            let style = tui::Style::reset();
+            // the indices go up, so those before this cell are behind it
+            while indices.next_if(|i| *i < idx).is_some() {}
+            let style = if indices.next_if_eq(&idx).is_some() {
+                tui::Style::reset().add_modifier(tui::Modifier::BOLD)
+            } else {
+                tui::Style::reset()
+            };
             buf.get_mut(x, area.top())
                 .set_style(style)
                 .set_symbol(symbol);
@@ -200,21 +613,100 @@ struct State {
 
     buffers: BufferMap,
     editors: EditorMap,
-    syntax_trees: SyntaxTreeMap,
+    /// How many editors show each buffer. A saved buffer is dropped once the last editor showing
+    /// it is closed; an unsaved one stays, hidden, for `:ls` and `:buffer` to get back to.
+    buffer_refs: SecondaryMap<BufferId, usize>,
+    /// Only buffers with a language to parse have one.
+    syntax_states: SyntaxStateMap,
 
     panes: PaneMap,
     visible_panes: Vec<PaneId>,
     focused_pane: PaneId,
+    /// The diffs open, each with the group its editors scroll together in.
+    diffs: SlotMap<GroupId, DiffView>,
 
+    /// The editor files are opened in: that of the editor pane highest in the layout.
     default_editor_id: EditorId,
+    /// Shows whichever preview pane is open; previews are read-only so one editor is enough.
+    preview_editor_id: EditorId,
 
+    commands_pane_id: PaneId,
     command_registry: CommandRegistry,
+
+    grep_pane_id: PaneId,
+    grep: GrepResults,
+
+    outline_pane_id: PaneId,
+    outline: Outline,
+
+    prompt_pane_id: PaneId,
+    prompt: Prompt,
+
+    events: broadcast::Sender<AppEvent>,
+
+    /// The buffer each capital-letter mark is in; the buffer itself keeps where.
+    global_marks: HashMap<char, BufferId>,
+    registers: Registers,
+
+    /// The keys typed so far of a multi-key normal mode binding.
+    pending: String,
+    /// Keys bound in the keys file, each to a line run as if typed into the command palette. In
+    /// normal mode they're looked up before the built-in bindings.
+    keys: HashMap<InputKey, String>,
+    message: Option<String>,
+    /// The last file that could not be opened because it doesn't decode.
+    undecodable: Option<(Option<EditorId>, std::path::PathBuf)>,
+    /// Files being read in the background.
+    loads: SlotMap<LoadId, PendingLoad>,
+    /// What was journaled for open buffers before a crash, newer than what's on disk.
+    recoverable: HashMap<BufferId, String>,
+    /// Whether the terminal's title follows the focused buffer.
+    show_title: bool,
+    /// Whether the cursor is drawn into its cell, with the terminal's own kept hidden.
+    block_cursor: bool,
+    /// Whether whitespace is drawn as `list_chars` has it.
+    list: bool,
+    list_chars: ui::ListChars,
+    /// Whether a jump of the view scrolls there over a few frames rather than all at once.
+    smooth_scroll: bool,
+    /// Whether the status line names the symbol the cursor is in.
+    status_symbol: bool,
+    /// How long inputs took to reach the screen.
+    latency: Latency,
+    /// Whether the status line shows how long the last input took.
+    show_latency: bool,
+    /// Keeps the undo history of every buffer within one budget.
+    history: HistoryBudget,
+    /// Whether the terminal the app is in has focus, as far as it reports.
+    app_focused: bool,
+    /// The columns of the gutter beside each editor, left to right.
+    gutter: Vec<ui::GutterColumn>,
+    /// Audits summaries and line counts after every edit, when turned on.
+    validator: Option<Validator>,
+    /// The shell command run last, whose output is shown in a preview.
+    runs: Runs,
+    /// Where on the terminal frames are drawn.
+    viewport: terminal::Viewport,
+    /// The lines of text an editor shows at once, which PageUp and PageDown move by.
+    page_lines: usize,
+    /// How many buffers have been numbered for `:ls`, so none's number is reused.
+    buffers_listed: usize,
+}
+
+/// A file being read in the background, to be shown in an editor once it's in.
+#[derive(Debug)]
+struct PendingLoad {
+    editor_id: Option<EditorId>,
+    path: std::path::PathBuf,
+    cancel: CancellationToken,
+    /// Where to put the cursor once the file is in.
+    jump: Option<tore::Point>,
 }
 
 impl State {
     fn new() -> Self {
         let theme = ui::Theme::default();
-        let syntax_trees = SecondaryMap::new();
+        let syntax_states = SecondaryMap::new();
         // let commands = Selector::new(":");
 
         let mut buffers = BufferMap::with_key();
@@ -236,424 +728,5741 @@ impl State {
             .next()
             .expect("at least one editor must be active");
 
+        let preview_editor_id = {
+            let buffer_id = buffers.keys().next().expect("scratch buffer must exist");
+            editors.insert_with_key(|k| Editor::new(k, buffer_id))
+        };
+
         let mut command_registry = CommandRegistry::new();
         register_commands(&mut command_registry);
+        command_registry.update();
         let commands_pane_id = panes.insert_with_key(Pane::new_commands);
+        let grep_pane_id = panes.insert_with_key(Pane::new_grep);
+        let outline_pane_id = panes.insert_with_key(Pane::new_outline);
+        let prompt_pane_id = panes.insert_with_key(Pane::new_prompt);
+        let (events, _) = broadcast::channel(64);
 
-        State {
+        let mut state = State {
             theme,
             buffers,
             editors,
-            syntax_trees,
+            buffer_refs: SecondaryMap::new(),
+            syntax_states,
             panes,
             visible_panes,
             focused_pane,
+            diffs: SlotMap::with_key(),
             default_editor_id,
-            command_registry,
+            preview_editor_id,
             commands_pane_id,
+            command_registry,
+            grep_pane_id,
+            grep: GrepResults::new(),
+            outline_pane_id,
+            outline: Outline::new(),
+            prompt_pane_id,
+            prompt: Prompt::new(),
+            events,
+            global_marks: HashMap::new(),
+            registers: Registers::default(),
+            pending: String::new(),
+            keys: HashMap::new(),
+            message: None,
+            undecodable: None,
+            loads: SlotMap::with_key(),
+            recoverable: HashMap::new(),
+            show_title: true,
+            block_cursor: false,
+            list: false,
+            list_chars: ui::ListChars::default(),
+            smooth_scroll: false,
+            status_symbol: true,
+            latency: Latency::default(),
+            show_latency: false,
+            history: HistoryBudget::default(),
+            app_focused: true,
+            gutter: vec![ui::GutterColumn::Signs],
+            validator: None,
+            viewport: terminal::Viewport::Fullscreen,
+            runs: Runs::new(),
+            page_lines: 1,
+            buffers_listed: 0,
+        };
+        for editor_id in [default_editor_id, preview_editor_id] {
+            state.retain_buffer(state.editors[editor_id].buffer_id);
         }
+        state.list_buffer(state.editors[default_editor_id].buffer_id);
+        state.resize(tui::Rect::new(0, 0, 80, 24));
+        state
     }
 
-    fn focused_pane(&self) -> Pane {
-        let pane = self
-            .panes
-            .get(self.focused_pane)
-            .expect("focused pane does not exist");
-        debug_assert!(self.visible_panes.contains(&pane.id()), "focused pane not visible");
-        pane.clone()
+    fn subscribe(&self) -> broadcast::Receiver<AppEvent> {
+        self.events.subscribe()
     }
 
-    fn close_focused_pane(&mut self) {
-        let pane_id = self.visible_panes.pop();
-        debug_assert_eq!(pane_id, Some(self.focused_pane));
-        // self.commands.reset();
-        self.restore_focus_to_last_pane();
+    fn emit(&self, event: AppEvent) {
+        // no subscribers is fine; nobody is listening.
+        let _ = self.events.send(event);
     }
 
-    fn focus_pane(&mut self, pane_id: PaneId) {
-        if let Some(idx) = self.visible_panes.iter().position(|id| *id == pane_id) {
-            self.visible_panes.remove(idx);
+    /// Runs the command in the editor, returning whether it went through; why it didn't becomes
+    /// the message.
+    fn editor_command(&mut self, editor_id: EditorId, cmd: EditorCommand) -> bool {
+        let editor = &mut self.editors[editor_id];
+        let buffer = &mut self.buffers[editor.buffer_id];
+        let mode = editor.mode;
+        let result = editor.command(buffer, cmd);
+        let (buffer_id, edited, ok) = (buffer.id, matches!(result, Ok(Some(_))), result.is_ok());
+        match self.syntax_states.get_mut(buffer.id) {
+            Some(syntax) => syntax.edited(buffer),
+            None => drop(buffer.take_edits()),
         }
-        self.visible_panes.push(pane_id);
-        self.focused_pane = pane_id;
+        match result {
+            Ok(Some(range)) => {
+                let _ = self
+                    .events
+                    .send(AppEvent::BufferChanged(buffer.id, buffer.version, range));
+            }
+            Ok(None) => {}
+            Err(editor::Notify(message)) => self.message = Some(message),
+        }
+        if editor.mode != mode {
+            let _ = self
+                .events
+                .send(AppEvent::ModeChanged(editor_id, editor.mode));
+        }
+        if edited {
+            self.validate(buffer_id);
+            self.history.edited(buffer_id);
+            self.history.enforce(&mut self.buffers);
+        }
+        self.follow_scroll_lock(editor_id);
+        ok
     }
 
-    fn restore_focus_to_last_pane(&mut self) {
-        let last_pane = self.visible_panes.last().expect("visible panes is empty");
-        self.focused_pane = *last_pane;
+    /// Scrolls the other side of the diff the editor is locked into along with it, moving its
+    /// cursor to the line across from the editor's, or to the nearest where there's filler.
+    fn follow_scroll_lock(&mut self, editor_id: EditorId) {
+        let Some(view) = self.editors[editor_id]
+            .scroll_lock_group
+            .and_then(|group| self.diffs.get(group))
+        else {
+            return;
+        };
+        let Some(side) = view.side_of(editor_id) else {
+            return;
+        };
+        let row = view
+            .alignment
+            .row_of(side, self.editors[editor_id].cursor().line);
+        let line = view.alignment.nearest_line(side.other(), row);
+        let other_id = view.editor_id(side.other());
+        let column = self.editors[other_id].cursor().column;
+        self.jump(other_id, tore::Point { line, column });
     }
 
-    #[tracing::instrument(skip(self, frame))]
-    fn draw_frame(&self, frame: &mut ratatui::Frame) -> Option<(CursorPoint, SetCursorStyle)> {
-        let mut cursor: Option<(CursorPoint, SetCursorStyle)> = None;
-
-        let area = frame.size();
-        let fb = frame.buffer_mut();
-        for pane_id in self.visible_panes.iter() {
-            let pane = self.panes.get(*pane_id).expect("pane not found");
-            match &pane {
-                Pane::Commands(pane_id, ..) => {
-                    let c = self.command_registry.render(fb, area, &self.theme);
-                    (cursor.is_none() && self.focused_pane == *pane_id).then(|| cursor = Some(c));
-                }
-                Pane::Editor(pane_id, editor_id) => {
-                    let editor = &self.editors[*editor_id];
-                    let buffer = &self.buffers[editor.buffer_id];
-                    let widget = ui::EditorPane::new(&self.theme, buffer, editor);
-                    let c = widget.render(fb, area);
-                    (cursor.is_none() && self.focused_pane == *pane_id).then(|| cursor = Some(c));
-                }
-            }
+    /// Opens a pane diffing two editors on an empty buffer, for files to be opened into, and
+    /// focuses it.
+    fn open_diff(&mut self) -> GroupId {
+        let buffer_id = self.buffers.insert_with_key(Buffer::empty);
+        let [left, right] = [(); 2].map(|_| self.insert_editor(buffer_id));
+        let contents = &self.buffers[buffer_id].contents;
+        let view = DiffView {
+            editors: [left, right],
+            focused: DiffSide::Left,
+            alignment: DiffAlignment::new(contents, contents),
+            aligned: [(buffer_id, self.buffers[buffer_id].version); 2],
+            editable: None,
+        };
+        let group = self.diffs.insert(view);
+        for editor_id in [left, right] {
+            self.editors[editor_id].scroll_lock_group = Some(group);
         }
+        let pane_id = self.panes.insert_with_key(|k| Pane::new_diff(k, group));
+        self.focus_pane(pane_id);
+        group
+    }
 
-        cursor
+    /// Opens a pane diffing the buffer's file as it was read from disk, in a read-only buffer on
+    /// the left, with the buffer itself on the right, focused and still editable.
+    fn open_disk_diff(&mut self, buffer_id: BufferId, on_disk: BufferContents) {
+        let disk_id = self.buffers.insert_with_key(|k| Buffer::new(k, on_disk));
+        self.buffers[disk_id].read_only = true;
+        let group = self.open_diff();
+        let view = &mut self.diffs[group];
+        view.editable = Some(buffer_id);
+        view.focused = DiffSide::Right;
+        let [left, right] = view.editors;
+        self.swap_buffer(left, disk_id);
+        self.show_buffer(right, buffer_id);
+        self.realign_diffs();
     }
 
-    #[tracing::instrument(skip(ev, self))]
-    fn process_event(&mut self, ev: Event) -> Option<Command> {
-        match ev {
-            Event::FocusGained => todo!(),
-            Event::FocusLost => todo!(),
-            Event::Paste(_) => todo!(),
-            Event::Mouse(_) => todo!(),
-            Event::Resize(_, _) => None,
-            Event::Key(key) => {
-                let command = self.process_key(key);
-                command
+    /// Lines up the sides of every diff whose buffers changed since they last were, like when
+    /// a file is reloaded, and keeps the files they show read-only, bar one left editable.
+    fn realign_diffs(&mut self) {
+        for view in self.diffs.values_mut() {
+            let aligned = view.editors.map(|editor_id| {
+                let buffer = &self.buffers[self.editors[editor_id].buffer_id];
+                (buffer.id, buffer.version)
+            });
+            if aligned == view.aligned {
+                continue;
+            }
+            let [left, right] = aligned.map(|(buffer_id, _)| buffer_id);
+            let (left, right) = (&self.buffers[left], &self.buffers[right]);
+            view.alignment = DiffAlignment::new(&left.contents, &right.contents);
+            view.aligned = aligned;
+            for (buffer_id, _) in aligned {
+                let buffer = &mut self.buffers[buffer_id];
+                buffer.read_only |= buffer.path.is_some() && view.editable != Some(buffer_id);
             }
         }
     }
 
-    fn process_key(&mut self, key: KeyEvent) -> Option<Command> {
-        use crossterm::event::{KeyCode, KeyModifiers};
-
-        let focused_pane = self
-            .panes
-            .get_mut(self.focused_pane)
-            .expect("focused pane does not exist");
+    /// Audits the buffer's summaries and line counts, if validation is on, reporting any that
+    /// drifted from the text rather than failing.
+    fn validate(&mut self, buffer_id: BufferId) {
+        let (Some(validator), Some(buffer)) = (&mut self.validator, self.buffers.get(buffer_id))
+        else {
+            return;
+        };
+        let Err(failure) = validator.audit(&buffer.contents) else {
+            return;
+        };
+        tracing::error!(
+            ?buffer_id,
+            path = ?buffer.path,
+            version = buffer.version,
+            len_bytes = buffer.contents.len_bytes(),
+            len_lines = buffer.contents.len_lines(),
+            ?failure,
+            "summaries drifted from the text"
+        );
+        let name = buffer
+            .path
+            .as_deref()
+            .map_or("[scratch]".into(), |path| path.display().to_string());
+        self.message = Some(format!("summaries drifted in {}: {}", name, failure));
+    }
 
-        match focused_pane {
-            Pane::Commands(_) => match key.code {
-                KeyCode::Up => {
-                    Some(Command::Commands(selector::Command::Focus(selector::Direction::Prev)))
-                }
-                KeyCode::Down => {
-                    Some(Command::Commands(selector::Command::Focus(selector::Direction::Next)))
-                }
-                KeyCode::Backspace => {
-                    Some(Command::Commands(selector::Command::Delete(selector::Direction::Prev)))
-                }
-                KeyCode::Enter => self.command_registry.focused(),
-                KeyCode::Char(c) => {
-                    let ctrl = key.modifiers == KeyModifiers::CONTROL;
-                    if ctrl && c == 'p' {
-                        Some(Command::Commands(selector::Command::Focus(selector::Direction::Prev)))
-                    } else if ctrl && c == 'n' {
-                        Some(Command::Commands(selector::Command::Focus(selector::Direction::Next)))
-                    } else {
-                        Some(Command::Commands(selector::Command::Insert(c)))
-                    }
-                }
-                _ => None,
-            },
-            Pane::Editor(_, editor_id) => {
-                let editor = &mut self.editors[*editor_id];
-                let command = match editor.mode {
-                    editor::Mode::Normal => match key.code {
-                        KeyCode::Up | KeyCode::Char('k') => {
-                            Some(EditorCommand::CursorMove(editor::Direction::Up))
-                        }
-                        KeyCode::Down | KeyCode::Char('j') => {
-                            Some(EditorCommand::CursorMove(editor::Direction::Down))
-                        }
-                        KeyCode::Left | KeyCode::Char('h') => {
-                            Some(EditorCommand::CursorMove(editor::Direction::Left))
-                        }
-                        KeyCode::Right | KeyCode::Char('l') => {
-                            Some(EditorCommand::CursorMove(editor::Direction::Right))
-                        }
-                        KeyCode::Char('w') => {
-                            Some(EditorCommand::CursorJump(editor::CursorJump::StartOfNextWord))
-                        }
-                        KeyCode::Char('e') => {
-                            Some(EditorCommand::CursorJump(editor::CursorJump::EndOfNearestWord))
-                        }
-                        KeyCode::Char('b') => {
-                            Some(EditorCommand::CursorJump(editor::CursorJump::StartOfNearestWord))
-                        }
-                        KeyCode::Char('0') => {
-                            Some(EditorCommand::CursorJump(editor::CursorJump::StartOfNearestWord))
-                        }
-                        KeyCode::Char('i') => Some(EditorCommand::SetMode(editor::Mode::Insert)),
-                        _ => None,
-                    },
-                    editor::Mode::Insert => match key.code {
-                        KeyCode::Esc => Some(EditorCommand::SetMode(editor::Mode::Normal)),
-                        KeyCode::Up => Some(EditorCommand::CursorMove(editor::Direction::Up)),
-                        KeyCode::Down => Some(EditorCommand::CursorMove(editor::Direction::Down)),
-                        KeyCode::Left => Some(EditorCommand::CursorMove(editor::Direction::Left)),
-                        KeyCode::Right => Some(EditorCommand::CursorMove(editor::Direction::Right)),
-                        KeyCode::Char(c) => Some(EditorCommand::InsertChar(c)),
-                        _ => None,
-                    },
-                };
-                command
-                    .map(|c| Command::Editor(*editor_id, c))
-                    .or_else(|| match editor.mode {
-                        editor::Mode::Normal => match key.code {
-                            KeyCode::Char(':') => {
-                                Some(Command::Pane(self.commands_pane_id, PaneCommand::Open))
-                            }
-                            _ => None,
-                        },
-                        _ => None,
-                    })
+    fn set_mark(&mut self, editor_id: EditorId, mark: char) {
+        let buffer_id = self.editors[editor_id].buffer_id;
+        if mark.is_ascii_uppercase() {
+            let previous = self.global_marks.insert(mark, buffer_id);
+            if let Some(buffer) = previous.and_then(|id| self.buffers.get_mut(id)) {
+                buffer.remove_mark(mark);
             }
         }
+        self.editor_command(editor_id, EditorCommand::SetMark(mark));
     }
 
-    fn process_syntax(&mut self, ev: syntax::Event) -> Option<Command> {
-        match ev {
-            syntax::Event::Hightlight(buffer_id, hls) => {
-                Some(Command::Buffer(buffer_id, BufferCommand::Highlight(hls)))
-            }
-            syntax::Event::Parsed(buffer_id, tree) => {
-                self.syntax_trees.insert(buffer_id, tree);
-                None
+    fn jump_to_mark(&mut self, editor_id: EditorId, mark: char, jump: editor::MarkJump) {
+        if mark.is_ascii_uppercase() {
+            let Some(buffer_id) = self.global_marks.get(&mark).copied() else {
+                self.message = Some(format!("mark '{}' is not set", mark));
+                return;
+            };
+            if !self.buffers.contains_key(buffer_id) {
+                self.message = Some(format!("mark '{}' is in a closed buffer", mark));
+                return;
             }
+            self.swap_buffer(editor_id, buffer_id);
         }
+        self.editor_command(editor_id, EditorCommand::JumpToMark(mark, jump));
     }
-}
 
-struct BackgroundExecutor(tokio::runtime::Handle);
+    /// What the register `name` holds for the editor: the unnamed `"` is the editor's own, `%`
+    /// its buffer's path, `.` what was last typed in it and `:` the last command line run.
+    /// Fails with why there's nothing to paste.
+    fn register(&self, editor_id: EditorId, name: char) -> Result<editor::Register, String> {
+        let editor = &self.editors[editor_id];
+        let chars = |text: &str| editor::Register { text: text.to_string(), linewise: false };
+        let register = match name {
+            '"' => editor.register().cloned(),
+            '%' => self.buffers[editor.buffer_id]
+                .path
+                .as_deref()
+                .map(|path| chars(&path.display().to_string())),
+            '.' => editor.last_inserted().map(chars),
+            ':' => self.registers.command.as_deref().map(chars),
+            name => {
+                registers::writable(name)?;
+                self.registers.get(name).cloned()
+            }
+        };
+        register.ok_or_else(|| format!("register {} is empty", name))
+    }
 
-impl BackgroundExecutor {
-    pub fn spawn<F>(&self, future: F) -> tokio::task::JoinHandle<F::Output>
-    where
-        F: Future + Send + 'static,
-        F::Output: Send + 'static,
-    {
-        tokio::task::spawn(future)
+    /// Runs `cmd` in the editor, and keeps what it yanked or deleted in the register `name` too.
+    fn yank_into(&mut self, editor_id: EditorId, name: char, cmd: EditorCommand) {
+        // checked first, so nothing is deleted that couldn't be kept
+        if let Err(message) = registers::writable(name) {
+            self.message = Some(message);
+            return;
+        }
+        if !self.editor_command(editor_id, cmd) || name == '"' {
+            return;
+        }
+        if let Some(register) = self.editors[editor_id].register().cloned() {
+            let _ = self.registers.set(name, register);
+        }
     }
-}
 
-struct AppContext {
-    background: BackgroundExecutor,
-}
+    /// Puts the register `name`'s text after the editor's cursors, or with `before` at them.
+    fn paste(&mut self, editor_id: EditorId, name: char, before: bool) {
+        match self.register(editor_id, name) {
+            Ok(register) => {
+                self.editor_command(editor_id, EditorCommand::Paste(register, before));
+            }
+            Err(message) => self.message = Some(message),
+        }
+    }
 
-impl AppContext {
-    pub fn new() -> Result<Self> {
-        let background_rt = tokio::runtime::Builder::new_multi_thread().build()?;
-        let background = BackgroundExecutor(background_rt.handle().clone());
-        Ok(Self { background })
+    /// Lists, for `:registers`, each register that holds something for the editor.
+    fn register_list(&self, editor_id: EditorId) -> String {
+        let names = std::iter::once('"')
+            .chain(self.registers.named().map(|(name, _)| name))
+            .chain(['%', '.', ':']);
+        let mut list = String::new();
+        for name in names {
+            if let Ok(register) = self.register(editor_id, name) {
+                list.push_str(&registers::listing(name, &register));
+                list.push('\n');
+            }
+        }
+        list
     }
 
-    pub fn background_executor(&self) -> &BackgroundExecutor {
-        &self.background
+    /// Adds an editor showing the buffer.
+    fn insert_editor(&mut self, buffer_id: BufferId) -> EditorId {
+        self.retain_buffer(buffer_id);
+        self.editors.insert_with_key(|k| Editor::new(k, buffer_id))
     }
-}
 
-#[derive()]
-pub struct App {
-    ctx: AppContext,
-    cmd_rx: mpsc::Receiver<Command>,
-    cmd_tx: mpsc::Sender<Command>,
-    term: Terminal,
-    events: EventStream,
-    syntax: syntax::Syntax,
-    state: State,
-}
+    /// Removes the editor, and with it the buffer it showed if no other editor shows it and it
+    /// has nothing unsaved.
+    fn remove_editor(&mut self, editor_id: EditorId) {
+        let Some(editor) = self.editors.remove(editor_id) else {
+            return;
+        };
+        let buffer_id = editor.buffer_id;
+        self.release_buffer(buffer_id);
+        let unused = self.buffer_refs.get(buffer_id) == Some(&0);
+        if unused
+            && self
+                .buffers
+                .get(buffer_id)
+                .is_some_and(|buffer| !buffer.dirty)
+        {
+            self.remove_buffer(buffer_id);
+        }
+    }
 
-impl App {
-    pub fn spawn(paths: Option<Vec<std::path::PathBuf>>) -> Result<()> {
-        let rt = tokio::runtime::Builder::new_current_thread().build()?;
-        let ctx = AppContext::new()?;
-        rt.block_on(async move {
-            let stdout = std::io::stdout();
-            let term = Terminal::new(CrosstermBackend::new(stdout))?;
+    /// The editor if it's still open, or else the default one.
+    fn editor_or_default(&self, editor_id: Option<EditorId>) -> EditorId {
+        let open = editor_id.filter(|editor_id| self.editors.contains_key(*editor_id));
+        open.unwrap_or(self.default_editor_id)
+    }
 
-            let (cmd_tx, cmd_rx) = mpsc::channel(1);
-            let app = Self::new(ctx, term, cmd_tx.clone(), cmd_rx);
-            let app = tokio::spawn(app.run());
-            if let Some(paths) = paths {
-                for p in paths.iter() {
-                    cmd_tx.send(Command::FileOpen(None, p.clone())).await?;
-                }
-            }
+    /// Shows the buffer in the editor in place of the one it showed. The buffer it leaves stays
+    /// open even if nothing else shows it, as the editor's alternate and in `:ls`.
+    fn swap_buffer(&mut self, editor_id: EditorId, buffer_id: BufferId) {
+        let editor = &mut self.editors[editor_id];
+        let previous = editor.buffer_id;
+        editor.swap_buffer(&self.buffers[buffer_id]);
+        // keys typed toward a binding in the buffer left don't carry over
+        self.pending.clear();
+        self.retain_buffer(buffer_id);
+        self.release_buffer(previous);
+    }
 
-            let res = app.await;
-            res?
-        })
+    fn retain_buffer(&mut self, buffer_id: BufferId) {
+        if let Some(entry) = self.buffer_refs.entry(buffer_id) {
+            *entry.or_default() += 1;
+        }
     }
 
-    fn new(ctx: AppContext, term: Terminal, cmd_tx: mpsc::Sender<Command>, cmd_rx: mpsc::Receiver<Command>) -> Self {
-        let events = EventStream::new();
-        let syntax = Syntax::spawn();
-        let state = State::new();
-        Self { ctx, cmd_tx, cmd_rx, term, events, syntax, state }
+    fn release_buffer(&mut self, buffer_id: BufferId) {
+        if let Some(refs) = self.buffer_refs.get_mut(buffer_id) {
+            *refs = refs.saturating_sub(1);
+        }
     }
 
-    async fn run(mut self) -> Result<()> {
-        'main: loop {
-            self.draw_frame()?;
-            let maybe_command = self.select_command().await?;
+    /// Drops the buffer and everything kept about it.
+    fn remove_buffer(&mut self, buffer_id: BufferId) {
+        self.buffers.remove(buffer_id);
+        self.buffer_refs.remove(buffer_id);
+        self.syntax_states.remove(buffer_id);
+        self.recoverable.remove(&buffer_id);
+        self.global_marks.retain(|_, id| *id != buffer_id);
+    }
 
-            if let Some(command) = maybe_command {
-                if let Command::Quit = command {
-                    break 'main;
-                }
-                self.process_command(command).await?;
-            }
+    /// The undo states of the editor's buffer as `:undolist` lists them, oldest first, a line
+    /// each: its number, the one it was made from, how long ago, and the bytes its edits added
+    /// and took away. `>` marks the current one.
+    fn undo_list(&self, editor_id: EditorId, now: std::time::Instant) -> String {
+        let tree = self.buffers[self.editors[editor_id].buffer_id].undo_tree();
+        let mut list = String::new();
+        for (id, state) in tree.chronological() {
+            let current = if id == tree.current() { '>' } else { ' ' };
+            let parent = state.parent().and_then(|parent| tree.get(parent));
+            let parent = parent.map_or(String::new(), |parent| format!("from {}", parent.seq()));
+            let ago = match now.saturating_duration_since(state.time()).as_secs() {
+                secs @ ..=59 => format!("{}s ago", secs),
+                secs @ ..=3599 => format!("{}m ago", secs / 60),
+                secs => format!("{}h ago", secs / 3600),
+            };
+            let edits = state.edits().iter();
+            let (added, removed) = edits.fold((0, 0), |(added, removed), edit| {
+                (added + edit.inserted.len, removed + edit.removed.len)
+            });
+            let change = match state.edits() {
+                [] => String::new(),
+                _ => format!("+{} -{}", added, removed),
+            };
+            let line =
+                format!("{}{:>4} {:<9} {:>7}  {}", current, state.seq(), parent, ago, change);
+            list.push_str(line.trim_end());
+            list.push('\n');
         }
+        list
+    }
 
-        Ok(())
+    /// Numbers the buffer after the last one numbered, listing it for `:ls` and `:buffer`.
+    fn list_buffer(&mut self, buffer_id: BufferId) {
+        self.buffers_listed += 1;
+        self.buffers[buffer_id].number = Some(self.buffers_listed);
     }
 
-    fn draw_frame(&mut self) -> Result<()> {
-        use crossterm::QueueableCommand;
-        use std::io::Write;
+    /// Shows the buffer in the editor, keeping the cursor within it.
+    fn show_buffer(&mut self, editor_id: EditorId, buffer_id: BufferId) {
+        self.swap_buffer(editor_id, buffer_id);
+        let cursor = self.editors[editor_id].cursor();
+        self.jump(editor_id, cursor);
+    }
 
-        let mut cursor: Option<(CursorPoint, SetCursorStyle)> = None;
-        self.term.draw(|frame| {
-            cursor = self.state.draw_frame(frame);
-        })?;
+    /// Shows the buffer numbered `number` in the editor.
+    fn switch_buffer(&mut self, editor_id: EditorId, number: &str) {
+        let number = number.trim();
+        if number.is_empty() {
+            self.message = Some("expected a buffer number".to_string());
+            return;
+        }
+        let Ok(number) = number.parse::<usize>() else {
+            self.message = Some(format!("'{}' is not a buffer number", number));
+            return;
+        };
+        let mut buffers = self.buffers.iter();
+        let Some((buffer_id, _)) = buffers.find(|(_, buffer)| buffer.number == Some(number)) else {
+            self.message = Some(format!("no buffer {}", number));
+            return;
+        };
+        self.show_buffer(editor_id, buffer_id);
+    }
 
-        let (cursor, cursor_style) = cursor.expect("cursor must be set");
-        let backend = self.term.backend_mut();
-        backend
-            .queue(cursor_style)?
-            .queue(cursor::MoveTo(cursor.x, cursor.y))?
-            .queue(cursor::Show)?
-            .flush()?;
-        Ok(())
+    /// Swaps the editor to the buffer it showed before, making the one it leaves the alternate.
+    fn swap_alternate(&mut self, editor_id: EditorId) {
+        let alternate = self.editors[editor_id].alternate();
+        match alternate.filter(|buffer_id| self.buffers.contains_key(*buffer_id)) {
+            Some(buffer_id) => self.show_buffer(editor_id, buffer_id),
+            None => self.message = Some("no alternate buffer".to_string()),
+        }
     }
 
-    async fn select_command(&mut self) -> Result<Option<Command>> {
-        use futures::{future::FutureExt, StreamExt};
+    /// The numbered buffers as `:ls` lists them, a line each: `%` marks the one in the editor and
+    /// `#` its alternate, `a` those on screen and `h` the rest, `=` those read-only and `+` those
+    /// unsaved. Paths under `root` are shown relative to it.
+    fn buffer_list(&self, editor_id: EditorId, root: &std::path::Path) -> String {
+        let editor = &self.editors[editor_id];
+        let mut listed: Vec<_> = self
+            .buffers
+            .values()
+            .filter_map(|buffer| Some((buffer.number?, buffer)))
+            .collect();
+        listed.sort_by_key(|(number, _)| *number);
+        let mut list = String::new();
+        for (number, buffer) in listed {
+            let current = match buffer.id {
+                id if id == editor.buffer_id => '%',
+                id if Some(id) == editor.alternate() => '#',
+                _ => ' ',
+            };
+            let active = if self.buffer_visible(buffer.id) {
+                'a'
+            } else {
+                'h'
+            };
+            let read_only = if buffer.read_only { '=' } else { ' ' };
+            let dirty = if buffer.dirty { '+' } else { ' ' };
+            let name = buffer.path.as_deref().map_or("[scratch]".into(), |path| {
+                let path = grep::normalize(path);
+                path.strip_prefix(root)
+                    .unwrap_or(&path)
+                    .display()
+                    .to_string()
+            });
+            let lines = match buffer.contents.len_lines().max(1) {
+                1 => "1 line".to_string(),
+                n => format!("{} lines", n),
+            };
+            let language = if self.syntax_states.contains_key(buffer.id) {
+                syntax::Language::try_from(buffer).map_or("", |language| language.name)
+            } else {
+                ""
+            };
+            let line = format!(
+                "{:>3} {}{}{}{} \"{}\" {} {}",
+                number, current, active, read_only, dirty, name, lines, language
+            );
+            list.push_str(line.trim_end());
+            list.push('\n');
+        }
+        list
+    }
 
-        let maybe_command = tokio::select! {
-            maybe_command = self.cmd_rx.recv() => { maybe_command }
-            maybe_syntax = self.syntax.next().fuse() => {
-                let syntax = maybe_syntax.expect("syntax thread crashed?");
-                self.state.process_syntax(syntax)
-            },
-            maybe_event = self.events.next().fuse() => match maybe_event {
-                None => Some(Command::Quit),
-                Some(event) => self.state.process_event(event?),
-            },
+    /// Whether `command` can change what is on screen.
+    fn redraws(&self, command: &Command) -> bool {
+        match command {
+            Command::Buffer(buffer_id, _) => self.buffer_visible(*buffer_id),
+            Command::BufferSave(buffer_id)
+            | Command::SetLineDiff(buffer_id, ..)
+            | Command::SearchRefresh(buffer_id) => self.buffer_visible(*buffer_id),
+            Command::BufferOpen(_)
+            | Command::BufferDiff(_)
+            | Command::SetWordIndex(..)
+            | Command::LspSync(_) => false,
+            _ => true,
+        }
+    }
+
+    fn buffer_visible(&self, buffer_id: BufferId) -> bool {
+        self.visible_panes.iter().any(|id| match self.panes[*id] {
+            Pane::Commands(..) | Pane::Grep(..) | Pane::Outline(..) | Pane::Prompt(..) => false,
+            Pane::Editor(_, editor_id) => self.editors[editor_id].buffer_id == buffer_id,
+            Pane::Preview(_, preview_id) => preview_id == buffer_id,
+            Pane::Diff(_, group) => self.diffs[group]
+                .editors
+                .iter()
+                .any(|editor_id| self.editors[*editor_id].buffer_id == buffer_id),
+        })
+    }
+
+    fn focused_pane(&self) -> Pane {
+        let pane = self
+            .panes
+            .get(self.focused_pane)
+            .expect("focused pane does not exist");
+        debug_assert!(self.visible_panes.contains(&pane.id()), "focused pane not visible");
+        pane.clone()
+    }
+
+    /// The editor the focused pane shows, or for an overlay, the one it's over.
+    fn focused_editor_id(&self) -> EditorId {
+        let pane_id = match self.focused_pane() {
+            Pane::Commands(..) | Pane::Grep(..) | Pane::Outline(..) | Pane::Prompt(..) => {
+                if let [.., pane_id, _] = self.visible_panes[..] {
+                    match self.panes[pane_id] {
+                        Pane::Editor(..) | Pane::Preview(..) | Pane::Diff(..) => pane_id,
+                        _ => unreachable!("no focused editor"),
+                    }
+                } else {
+                    unreachable!("no visible panes")
+                }
+            }
+            Pane::Editor(..) | Pane::Preview(..) | Pane::Diff(..) => self.focused_pane,
         };
-        Ok(maybe_command)
+        match self.panes[pane_id] {
+            Pane::Commands(..) | Pane::Grep(..) | Pane::Outline(..) | Pane::Prompt(..) => {
+                unreachable!("focused pane is not an editor")
+            }
+            Pane::Editor(_, editor_id) => editor_id,
+            Pane::Preview(..) => self.preview_editor_id,
+            Pane::Diff(_, group) => self.diffs[group].focused_editor_id(),
+        }
     }
 
-    async fn process_command(&mut self, command: Command) -> Result<()> {
+    /// Closes the focused pane, focusing the one under it. An editor pane or diff takes its
+    /// editors with it; the last editor pane can't be closed, and says so.
+    fn close_focused_pane(&mut self) {
+        let pane_id = self.focused_pane;
+        debug_assert_eq!(self.visible_panes.last(), Some(&pane_id));
+        match self.panes.get(pane_id).cloned() {
+            Some(Pane::Editor(_, editor_id)) => {
+                let mut below = self.visible_panes.iter().rev().skip(1);
+                let Some(under) = below.find_map(|id| match self.panes[*id] {
+                    Pane::Editor(_, under) => Some(under),
+                    _ => None,
+                }) else {
+                    self.message = Some("can't close the last editor".to_string());
+                    return;
+                };
+                if self.default_editor_id == editor_id {
+                    self.default_editor_id = under;
+                }
+                self.panes.remove(pane_id);
+                self.remove_editor(editor_id);
+            }
+            Some(Pane::Diff(_, group)) => {
+                self.panes.remove(pane_id);
+                let editors = self.diffs.remove(group).map(|view| view.editors);
+                for editor_id in editors.into_iter().flatten() {
+                    self.remove_editor(editor_id);
+                }
+            }
+            // previews are ephemeral; drop them along with their scratch buffer.
+            Some(Pane::Preview(_, buffer_id)) => {
+                self.panes.remove(pane_id);
+                self.remove_buffer(buffer_id);
+            }
+            Some(Pane::Commands(..) | Pane::Grep(..) | Pane::Outline(..) | Pane::Prompt(..))
+            | None => self.reset_pane(pane_id),
+        }
+        self.visible_panes.pop();
+        self.restore_focus_to_last_pane();
+    }
+
+    /// Opens an editor pane over the focused editor, on its buffer with the cursor where it is,
+    /// and opens files in it from then on.
+    fn split_pane(&mut self) {
+        let from = self.focused_editor_id();
+        if from == self.preview_editor_id {
+            self.message = Some("can't split a preview".to_string());
+            return;
+        }
+        let (buffer_id, cursor) = (self.editors[from].buffer_id, self.editors[from].cursor());
+        let editor_id = self.insert_editor(buffer_id);
+        self.jump(editor_id, cursor);
+        let pane_id = self
+            .panes
+            .insert_with_key(|k| Pane::new_editor(k, editor_id));
+        self.default_editor_id = editor_id;
+        self.focus_pane(pane_id);
+    }
+
+    /// Forgets what was typed into an overlay, so it opens fresh next time.
+    fn reset_pane(&mut self, pane_id: PaneId) {
+        match self.panes.get(pane_id) {
+            Some(Pane::Commands(..)) => self.command_registry.reset(),
+            Some(Pane::Grep(..)) => self.grep.stop(),
+            Some(Pane::Outline(..)) => self.outline.stop(),
+            Some(Pane::Prompt(..)) => self.prompt.stop(),
+            _ => {}
+        }
+    }
+
+    /// Follows each editor on screen to the line it's scrolled to, to keep its cursor in view.
+    fn follow_scrolls(&mut self, now: std::time::Instant) {
+        for pane_id in &self.visible_panes {
+            let Some(Pane::Editor(_, editor_id)) = self.panes.get(*pane_id) else {
+                continue;
+            };
+            let editor = &mut self.editors[*editor_id];
+            let buffer = &self.buffers[editor.buffer_id];
+            // a hex view scrolls by bytes, on its own
+            if buffer.is_binary() {
+                continue;
+            }
+            // wrapped lines scroll by the rows they go on over
+            let row = editor.row_of(buffer, editor.cursor());
+            let top = ui::scroll_top(row, self.page_lines);
+            editor.scroll_to(top, now, self.smooth_scroll);
+        }
+    }
+
+    fn is_scrolling(&self) -> bool {
+        self.editors.values().any(Editor::is_scrolling)
+    }
+
+    /// Ends the smooth scrolls whose last frame is due by `now`, or every one with `snap`.
+    fn finish_scrolls(&mut self, now: std::time::Instant, snap: bool) {
+        for editor in self.editors.values_mut() {
+            editor.finish_scroll(now, snap);
+        }
+    }
+
+    /// Sizes what depends on the frame to fit `area`.
+    fn resize(&mut self, area: tui::Rect) {
+        let (editor_area, _) = split_status_line(area);
+        self.page_lines = usize::from(editor_area.height).max(1);
+        let rows = self.theme.overlay().rows(area);
+        self.command_registry.selector.set_page_size(rows);
+        self.grep.selector.set_page_size(rows);
+        self.outline.selector.set_page_size(rows);
+        self.prompt.selector.set_page_size(rows);
+    }
+
+    /// The buffer already holding `path`, if any.
+    fn find_buffer(&self, path: &std::path::Path) -> Option<BufferId> {
+        let path = grep::normalize(path);
+        let mut buffers = self.buffers.iter();
+        buffers
+            .find(|(_, buffer)| buffer.path.as_deref().map(grep::normalize).as_ref() == Some(&path))
+            .map(|(buffer_id, _)| buffer_id)
+    }
+
+    /// Replaces the buffer's contents with `contents`, as an edit that leaves it unsaved.
+    fn restore(&mut self, buffer_id: BufferId, contents: &str) {
+        let Some(buffer) = self.buffers.get_mut(buffer_id) else {
+            return;
+        };
+        let len = buffer.contents.len_chars();
+        let Ok((_, range)) = buffer.apply_edits(&[(0..len, contents)]) else {
+            return;
+        };
+        self.edited_elsewhere(buffer_id, range);
+    }
+
+    /// Changes the buffer to what a formatter made of it at `version`, by as few whole lines as
+    /// it can, so what's kept on the rest stays put, in one undo step. A failure is shown, in a
+    /// preview if it's more than a line, and leaves the buffer as it is, as does any edit made
+    /// while it was formatting.
+    fn formatted(&mut self, buffer_id: BufferId, version: u64, formatted: Result<String, String>) {
+        let Some(buffer) = self.buffers.get_mut(buffer_id) else {
+            return;
+        };
+        let text = match formatted {
+            Ok(text) => text,
+            Err(err) if err.lines().count() > 1 => {
+                self.open_preview(&err);
+                self.message = Some("formatter failed".to_string());
+                return;
+            }
+            Err(err) => {
+                self.message = Some(format!("formatter failed: {}", err));
+                return;
+            }
+        };
+        if buffer.version != version {
+            self.message = Some("not formatted: the buffer changed meanwhile".to_string());
+            return;
+        }
+        let edits = editor::line_edits(&buffer.contents, &text.as_str().into());
+        let edits: Vec<_> = edits
+            .iter()
+            .map(|(range, text)| (range.clone(), text.as_str()))
+            .collect();
+        if edits.is_empty() {
+            return;
+        }
+        match buffer.apply_edits(&edits) {
+            Ok((_, range)) => self.edited_elsewhere(buffer_id, range),
+            Err(err) => self.message = Some(format!("cannot format: {}", err)),
+        }
+    }
+
+    /// Makes the edits the buffer's settings call for before it's saved.
+    fn fix_up(&mut self, buffer_id: BufferId) {
+        let Some(buffer) = self.buffers.get_mut(buffer_id) else {
+            return;
+        };
+        if let Some(range) = buffer.fix_up() {
+            self.edited_elsewhere(buffer_id, range);
+        }
+    }
+
+    /// Catches up after an edit to the buffer that no editor made: its syntax is told, and the
+    /// cursors of every editor on it are kept within it.
+    fn edited_elsewhere(&mut self, buffer_id: BufferId, range: std::ops::Range<usize>) {
+        let buffer = &mut self.buffers[buffer_id];
+        match self.syntax_states.get_mut(buffer_id) {
+            Some(syntax) => syntax.edited(buffer),
+            None => drop(buffer.take_edits()),
+        }
+        let version = buffer.version;
+        self.emit(AppEvent::BufferChanged(buffer_id, version, range));
+        self.history.edited(buffer_id);
+        self.history.enforce(&mut self.buffers);
+        self.realign_diffs();
+        let editors: Vec<_> = self
+            .editors
+            .iter()
+            .filter(|(_, editor)| editor.buffer_id == buffer_id)
+            .map(|(editor_id, editor)| (editor_id, editor.cursor()))
+            .collect();
+        for (editor_id, cursor) in editors {
+            self.jump(editor_id, cursor);
+        }
+        self.validate(buffer_id);
+    }
+
+    /// The terminal title naming the focused buffer, or the one under the focused overlay;
+    /// None when titles are turned off.
+    fn title(&self) -> Option<String> {
+        if !self.show_title {
+            return None;
+        }
+        let buffer = &self.buffers[self.visible_buffer_id()?];
+        Some(window_title(buffer.path.as_deref(), buffer.dirty))
+    }
+
+    /// The buffer in the focused pane, or under the focused overlay.
+    fn visible_buffer_id(&self) -> Option<BufferId> {
+        self.visible_panes
+            .iter()
+            .rev()
+            .find_map(|id| match self.panes[*id] {
+                Pane::Commands(..) | Pane::Grep(..) | Pane::Outline(..) | Pane::Prompt(..) => None,
+                Pane::Editor(_, editor_id) => Some(self.editors[editor_id].buffer_id),
+                Pane::Preview(_, buffer_id) => Some(buffer_id),
+                Pane::Diff(_, group) => {
+                    Some(self.editors[self.diffs[group].focused_editor_id()].buffer_id)
+                }
+            })
+    }
+
+    /// What to ask before running `command`, if it would lose work: quitting with unsaved changes
+    /// or a command still running, writing over another file, or killing a running command.
+    fn confirmation(&self, command: &Command) -> Option<String> {
         match command {
-            Command::Quit => unreachable!("handled in main loop"),
-            Command::Commands(cmd) => match cmd {
-                _ => todo!(),
-                // selector::Command::Select(entry_id) => {
-                //     let entry = self.state.commands.entries.get(entry_id).unwrap();
-                //     self.cmd_tx.send(entry.command.clone()).await?;
-                //     self.state.close_focused_pane();
-                // }
-                // selector::Command::Open => {
-                //     self.state.focus_pane(self.state.commands_pane_id);
-                // }
-                // selector::Command::Close => {
-                //     debug_assert_eq!(self.state.focused_pane, self.state.commands_pane_id);
-                //     self.state.close_focused_pane();
-                // }
-            },
-            Command::Pane(pane_id, cmd) => match cmd {
-                PaneCommand::Open => {
-                    self.state.focus_pane(pane_id);
+            Command::Quit => {
+                let mut unsaved: Vec<_> = self
+                    .buffers
+                    .values()
+                    .filter(|buffer| buffer.dirty)
+                    .filter_map(|buffer| buffer.path.as_deref()?.file_name())
+                    .map(|name| name.to_string_lossy())
+                    .collect();
+                unsaved.sort();
+                let mut losing = vec![];
+                if !unsaved.is_empty() {
+                    losing.push(format!("without saving {}", unsaved.join(", ")));
                 }
-                PaneCommand::Close => {
-                    debug_assert_eq!(self.state.focused_pane, pane_id);
-                    self.state.close_focused_pane()
+                if let Some(running) = self.runs.running() {
+                    losing.push(format!("killing '{}'", running));
                 }
-            },
-            Command::Editor(editor_id, cmd) => {
-                let editor = &mut self.state.editors[editor_id];
-                let buffer = &mut self.state.buffers[editor.buffer_id];
-                editor.command(buffer, cmd);
+                (!losing.is_empty()).then(|| format!("Quit {}?", losing.join(" and ")))
             }
-            Command::Buffer(buffer_id, cmd) => {
-                let buffer = &mut self.state.buffers[buffer_id];
-                buffer.command(cmd);
+            Command::Write(Some(path)) | Command::WriteForce(Some(path)) => {
+                let buffer = &self.buffers[self.visible_buffer_id()?];
+                let own = buffer.path.as_deref().map(grep::normalize);
+                let other = path.exists() && own != Some(grep::normalize(path));
+                other.then(|| format!("Overwrite {}?", path.display()))
             }
+            Command::RunKill => {
+                let running = self.runs.running()?;
+                Some(format!("Kill '{}'?", running))
+            }
+            _ => None,
+        }
+    }
 
-            Command::FocusedEditor(cmd) => {
-                let pane_id = match self.state.focused_pane() {
-                    Pane::Commands(..) => {
-                        if let [.., pane_id, _] = self.state.visible_panes[..] {
-                            match self.state.panes[pane_id] {
-                                Pane::Editor(..) => pane_id,
-                                _ => unreachable!("no focused editor"),
-                            }
-                        } else {
-                            unreachable!("no visible panes")
-                        }
-                    }
-                    Pane::Editor(..) => self.state.focused_pane,
-                };
-                let pane = &self.state.panes[pane_id];
-                match pane {
-                    Pane::Commands(..) => {
-                        unreachable!("focused pane is not an editor")
-                    }
-                    Pane::Editor(_, editor_id) => {
-                        let editor = &mut self.state.editors[*editor_id];
-                        let buffer = &mut self.state.buffers[editor.buffer_id];
-                        editor.command(buffer, cmd);
-                    }
+    /// Asks `question` about `command`, which is run once the answer is yes.
+    fn ask(&mut self, question: String, command: Command) {
+        self.prompt.ask(question, command);
+        self.focus_pane(self.prompt_pane_id);
+    }
+
+    /// The editor on top of the visible panes, the one commands for the focused editor go to.
+    fn visible_editor_id(&self) -> Option<EditorId> {
+        self.visible_panes
+            .iter()
+            .rev()
+            .find_map(|id| match self.panes[*id] {
+                Pane::Commands(..) | Pane::Grep(..) | Pane::Outline(..) | Pane::Prompt(..) => None,
+                Pane::Editor(_, editor_id) => Some(editor_id),
+                Pane::Preview(..) => Some(self.preview_editor_id),
+                Pane::Diff(_, group) => Some(self.diffs[group].focused_editor_id()),
+            })
+    }
+
+    /// What decides the highlight under the cursor of the focused editor: every highlight there,
+    /// the scheme entry and color of the one that shows, and the syntax nodes containing it.
+    fn inspect_highlight(&self) -> String {
+        let Some(editor_id) = self.visible_editor_id() else {
+            return "no editor to inspect\n".to_string();
+        };
+        let editor = &self.editors[editor_id];
+        let buffer = &self.buffers[editor.buffer_id];
+        let cursor = editor.cursor();
+        let offset = buffer.contents.point_to_byte_offset(cursor);
+        let tree = self
+            .syntax_states
+            .get(buffer.id)
+            .and_then(SyntaxState::tree);
+        let inspection = syntax::inspect(buffer, tree.map(|(_, tree)| tree), offset);
+
+        let position = format!("{}:{} (byte {})", cursor.line + 1, cursor.column + 1, offset);
+        let mut report = vec![position];
+        let mut scopes = inspection.scopes.iter();
+        // the first is drawn; the others only show where it doesn't cover
+        match scopes.next() {
+            None => report.push("no highlight".to_string()),
+            Some((range, name)) => report.push(match self.theme.scheme_entry(name) {
+                Some((entry, color)) => format!("{} {:?} -> {} {}", name, range, entry, color),
+                None => format!("{} {:?} -> not in the theme", name, range),
+            }),
+        }
+        report.extend(scopes.map(|(range, name)| format!("{} {:?}", name, range)));
+        match tree {
+            None => report.push("no syntax tree".to_string()),
+            Some((version, _)) => {
+                report.push(inspection.path.join(" > "));
+                if version != buffer.version {
+                    let behind =
+                        format!("(tree of version {}, buffer at {})", version, buffer.version);
+                    report.push(behind);
                 }
             }
+        }
+        report.join("\n") + "\n"
+    }
 
-            Command::FileOpen(maybe_editor_id, path) => {
-                let contents = Buffer::read(&path).await?;
-                let buffer_id = self
-                    .state
-                    .buffers
-                    .insert_with_key(|k| Buffer::new(k, contents.clone()));
+    /// The name of the symbol the editor's cursor is in, for the status line.
+    fn enclosing_symbol(&self, editor: &Editor) -> Option<&str> {
+        if !self.status_symbol {
+            return None;
+        }
+        let buffer = &self.buffers[editor.buffer_id];
+        let symbols = self.syntax_states.get(buffer.id)?.symbols()?;
+        let offset = buffer.contents.point_to_byte_offset(editor.cursor());
+        symbols.enclosing(offset).map(|symbol| symbol.name.as_str())
+    }
 
-                let editor_id = maybe_editor_id.unwrap_or(self.state.default_editor_id);
-                let editor = &mut self.state.editors[editor_id];
-                editor.swap_buffer(buffer_id);
+    /// How long the last input took to reach the screen, for the status line.
+    fn last_latency(&self) -> Option<std::time::Duration> {
+        let last = self.latency.last().filter(|_| self.show_latency);
+        last.map(|sample| sample.total())
+    }
 
-                match syntax::Language::try_from(&self.state.buffers[buffer_id]) {
-                    Ok(language) => {
-                        self.syntax
-                            .command(syntax::Command::Parse { buffer_id, contents, language })
-                            .await?;
-                    }
-                    _ => todo!(),
-                };
+    /// Lists the symbols found in the last parse of the focused editor's buffer in the outline.
+    fn open_outline(&mut self) {
+        let editor_id = self.focused_editor_id();
+        let buffer_id = self.editors[editor_id].buffer_id;
+        let symbols = self
+            .syntax_states
+            .get(buffer_id)
+            .and_then(SyntaxState::symbols);
+        match symbols {
+            Some(symbols) if !symbols.is_empty() => {
+                self.outline.open(editor_id, symbols.iter().cloned());
+                self.focus_pane(self.outline_pane_id);
             }
+            Some(_) => self.message = Some("no symbols in this buffer".to_string()),
+            None => self.message = Some("no outline for this buffer".to_string()),
+        }
+    }
+
+    /// Closes the outline and jumps to the symbol focused in it, leaving the `'` mark where the
+    /// cursor was so `''` goes back.
+    fn outline_jump(&mut self) {
+        let Some((editor_id, symbol)) = self.outline.focused() else {
+            return;
         };
+        let offset = symbol.range.start;
+        if self.focused_pane().is_overlay() {
+            self.close_focused_pane();
+        }
+        let contents = &self.buffers[self.editors[editor_id].buffer_id].contents;
+        // the symbols may be from a parse the buffer has since moved on from
+        let offset = contents.byte_to_char(offset.min(contents.len_bytes()));
+        let line = contents.char_to_line(offset);
+        let point = tore::Point { line, column: offset - contents.line_to_char(line) };
+        self.editor_command(editor_id, EditorCommand::SetMark('\''));
+        self.jump(editor_id, point);
+    }
 
-        Ok(())
+    /// Writes the focused editor's buffer to the path in `argument` as `format` has it: the
+    /// lines of the range before the path, or else those selected in visual line mode, or else
+    /// all of them. Lines are numbered when the gutter numbers them.
+    fn export(&mut self, format: export::Format, argument: &str) {
+        let editor = &self.editors[self.focused_editor_id()];
+        let buffer = &self.buffers[editor.buffer_id];
+        let (range, path) = export::split(argument);
+        if path.is_empty() {
+            self.message = Some("expected a path to export to".to_string());
+            return;
+        }
+        let contents = &buffer.contents;
+        let len_lines = contents.len_lines() - usize::from(contents.ends_with_line_break());
+        let lines = match range {
+            Some(range) => export::lines(range, editor.cursor().line, len_lines),
+            None if editor.mode == editor::Mode::VisualLine => {
+                let selections = editor.selections().iter();
+                let first = selections
+                    .clone()
+                    .map(|s| s.start().line)
+                    .min()
+                    .unwrap_or(0);
+                let last = selections.map(|s| s.end().line).max().unwrap_or(0);
+                Ok(first..last + 1)
+            }
+            None => Ok(0..len_lines),
+        };
+        let lines = match lines {
+            Ok(lines) => lines,
+            Err(message) => {
+                self.message = Some(message);
+                return;
+            }
+        };
+        let path = path::expand(path, buffer.path.as_deref());
+        let numbers = self.gutter.contains(&ui::GutterColumn::Numbers);
+        let theme = &self.theme;
+        let written = export::write(&path, |out| match format {
+            export::Format::Html => {
+                let title = buffer.path.as_deref().and_then(|path| path.file_name());
+                let title = title.map_or("[scratch]".into(), |name| name.to_string_lossy());
+                let tab_width = buffer.settings().tab_width;
+                let mut sink = ui::HtmlSink::new(out, theme, &title, tab_width)?;
+                ui::export(buffer, theme, lines, numbers, &mut sink)
+            }
+            export::Format::Ansi => {
+                ui::export(buffer, theme, lines, numbers, &mut ui::AnsiSink::new(out))
+            }
+        });
+        self.message = Some(match written {
+            Ok(()) => format!("exported to {}", path.display()),
+            Err(err) => format!("{}: {}", path.display(), err),
+        });
     }
-}
 
-fn register_commands(registry: &mut CommandRegistry) {
-    use editor::EditorCommand::*;
-    use editor::{CursorJump, Direction};
+    /// The path, version and contents of every buffer with unsaved changes.
+    fn dirty_buffers(&self) -> recovery::Dirty {
+        let dirty = self.buffers.values().filter(|buffer| buffer.dirty);
+        dirty
+            .filter_map(|buffer| {
+                let path = buffer.path.clone()?;
+                Some((path, (buffer.version, buffer.contents.clone())))
+            })
+            .collect()
+    }
 
-    registry.register("quit", vec![], Command::Quit);
+    /// Moves the editor's cursor to `point`, or as near it as its buffer allows; the file may
+    /// have changed since the point was found.
+    fn jump(&mut self, editor_id: EditorId, point: tore::Point) {
+        let editor = &mut self.editors[editor_id];
+        let contents = &self.buffers[editor.buffer_id].contents;
+        let line = point.line.min(contents.len_lines() - 1);
+        let column = point
+            .column
+            .min(contents.max_cursor_column(line, editor.mode));
+        editor.set_cursor(tore::Point { line, column });
+    }
 
-    let cmds = [
-        ("cursor.up", vec![], CursorMove(Direction::Up)),
-        ("cursor.down", vec![], CursorMove(Direction::Down)),
-        ("cursor.left", vec![], CursorMove(Direction::Left)),
-        ("cursor.right", vec![], CursorMove(Direction::Right)),
-        ("cursor.startOfNextWord", vec![], CursorJump(CursorJump::StartOfNextWord)),
-        ("cursor.startOfLastWord", vec![], CursorJump(CursorJump::StartOfLastWord)),
-        ("cursor.startOfNearestWord", vec![], CursorJump(CursorJump::StartOfNearestWord)),
-        ("cursor.endOfNearestWord", vec![], CursorJump(CursorJump::EndOfNearestWord)),
-    ];
-    for (name, aliases, cmd) in cmds {
-        registry.register(name, aliases, Command::FocusedEditor(cmd));
+    /// Reports that `path` doesn't decode, keeping it to be opened lossily on request.
+    fn undecodable(
+        &mut self,
+        editor_id: Option<EditorId>,
+        path: std::path::PathBuf,
+        invalid: &editor::InvalidEncoding,
+    ) {
+        self.message =
+            Some(format!("{}: {}; :open-lossy opens it read-only", path.display(), invalid));
+        self.undecodable = Some((editor_id, path));
     }
 
-    // commands.reset();
+    /// Shows `contents` in a new preview pane on top of the current layout. A commands pane that
+    /// ran the command closes as it loses focus, so closing the preview returns to the pane
+    /// underneath.
+    fn open_preview(&mut self, contents: &str) -> PaneId {
+        let buffer_id = self
+            .buffers
+            .insert_with_key(|k| Buffer::new(k, contents.into()));
+        self.swap_buffer(self.preview_editor_id, buffer_id);
+        self.editors[self.preview_editor_id].set_cursor(Default::default());
+        let pane_id = self
+            .panes
+            .insert_with_key(|k| Pane::new_preview(k, buffer_id));
+        self.focus_pane(pane_id);
+        pane_id
+    }
+
+    /// Shows an empty read-only preview for a run's output, reusing the last run's if it's still
+    /// open.
+    fn open_run_output(&mut self) -> BufferId {
+        let last = self
+            .runs
+            .buffer_id
+            .filter(|id| self.buffers.contains_key(*id));
+        let pane_id = last.and_then(|buffer_id| {
+            self.panes.iter().find_map(|(pane_id, pane)| match pane {
+                Pane::Preview(_, id) if *id == buffer_id => Some(pane_id),
+                _ => None,
+            })
+        });
+        let (Some(buffer_id), Some(pane_id)) = (last, pane_id) else {
+            let pane_id = self.open_preview("");
+            let Pane::Preview(_, buffer_id) = self.panes[pane_id] else {
+                unreachable!("preview pane is not a preview");
+            };
+            self.buffers[buffer_id].read_only = true;
+            return buffer_id;
+        };
+        self.restore(buffer_id, "");
+        self.buffers[buffer_id].dirty = false;
+        self.editors[self.preview_editor_id].set_cursor(Default::default());
+        self.focus_pane(pane_id);
+        buffer_id
+    }
+
+    /// Appends a run's output to its buffer, if it's still open. The preview follows it down
+    /// unless its cursor was moved up off the last line.
+    fn append_output(&mut self, buffer_id: BufferId, output: &str) {
+        let Some(buffer) = self.buffers.get_mut(buffer_id) else {
+            return;
+        };
+        let (last_line, len) = (buffer.contents.len_lines() - 1, buffer.contents.len_chars());
+        let Ok((_, range)) = buffer.apply_edits(&[(len..len, output)]) else {
+            return;
+        };
+        // output isn't a change anyone would want to save
+        buffer.dirty = false;
+        self.edited_elsewhere(buffer_id, range);
+        let editor = &mut self.editors[self.preview_editor_id];
+        if editor.buffer_id == buffer_id && editor.cursor().line == last_line {
+            let line = self.buffers[buffer_id].contents.len_lines() - 1;
+            editor.set_cursor(tore::Point { line, column: 0 });
+        }
+    }
+
+    fn help_text(&self) -> String {
+        use std::fmt::Write;
+
+        let mut help = String::from("Commands\n");
+        for entry in self.command_registry.entries() {
+            let _ = match entry.aliases.as_slice() {
+                [] => writeln!(help, "  :{}", entry.name),
+                aliases => writeln!(help, "  :{} ({})", entry.name, aliases.join(", ")),
+            };
+        }
+        help.push_str("\nKeys\n");
+        for (mode, keys, description) in KEY_BINDINGS {
+            let _ = writeln!(help, "  {:<8}{:<16}{}", mode, keys, description);
+        }
+        help
+    }
+
+    fn pane_context(&self) -> commands::Context {
+        let focused = self.panes.get(self.focused_pane).map(Pane::kind);
+        let visible = self
+            .visible_panes
+            .iter()
+            .map(|id| self.panes[*id].kind())
+            .collect();
+        commands::Context { focused, visible }
+    }
+
+    fn focus_pane(&mut self, pane_id: PaneId) {
+        let previous = self.focused_pane;
+        if previous != pane_id && self.panes.get(previous).is_some_and(Pane::is_overlay) {
+            self.visible_panes.retain(|id| *id != previous);
+            self.reset_pane(previous);
+        }
+        if let Some(idx) = self.visible_panes.iter().position(|id| *id == pane_id) {
+            self.visible_panes.remove(idx);
+        }
+        self.visible_panes.push(pane_id);
+        self.focused_pane = pane_id;
+        self.emit(AppEvent::PaneFocused(pane_id));
+    }
+
+    fn restore_focus_to_last_pane(&mut self) {
+        let last_pane = self.visible_panes.last().expect("visible panes is empty");
+        self.focused_pane = *last_pane;
+        self.emit(AppEvent::PaneFocused(self.focused_pane));
+    }
+
+    #[tracing::instrument(skip(self, frame))]
+    fn draw_frame(&self, frame: &mut ratatui::Frame) -> Option<(CursorPoint, SetCursorStyle)> {
+        let mut cursor: Option<(CursorPoint, SetCursorStyle)> = None;
+
+        let area = frame.size();
+        let fb = frame.buffer_mut();
+        let list = self.list.then_some(&self.list_chars);
+        let now = now();
+        for pane_id in self.visible_panes.iter() {
+            let pane = self.panes.get(*pane_id).expect("pane not found");
+            match &pane {
+                Pane::Commands(pane_id, ..) => {
+                    let c = self.command_registry.render(fb, area, &self.theme);
+                    (cursor.is_none() && self.focused_pane == *pane_id).then(|| cursor = Some(c));
+                }
+                Pane::Editor(pane_id, editor_id) => {
+                    let editor = &self.editors[*editor_id];
+                    let buffer = &self.buffers[editor.buffer_id];
+                    let (editor_area, status_area) = split_status_line(area);
+                    let gutter = ui::Gutter::new(&self.gutter, ui::Gutter::PROVIDERS);
+                    let snapshot = editor.snapshot(buffer);
+                    let widget = ui::EditorPane::new(&self.theme, &snapshot)
+                        .with_gutter(gutter)
+                        .with_list(list)
+                        .scrolled_to(snapshot.shown_top(now));
+                    let c = widget.render(fb, editor_area);
+                    let status = ui::StatusLine::new(&self.theme, buffer, editor)
+                        .message(self.message.as_deref())
+                        .symbol(self.enclosing_symbol(editor))
+                        .latency(self.last_latency())
+                        .focused(self.app_focused);
+                    status.render(fb, status_area);
+                    (cursor.is_none() && self.focused_pane == *pane_id).then(|| cursor = Some(c));
+                }
+                Pane::Grep(pane_id) => {
+                    let c = self.grep.render(fb, area, &self.theme);
+                    (cursor.is_none() && self.focused_pane == *pane_id).then(|| cursor = Some(c));
+                }
+                Pane::Outline(pane_id) => {
+                    let c = self.outline.render(fb, area, &self.theme);
+                    (cursor.is_none() && self.focused_pane == *pane_id).then(|| cursor = Some(c));
+                }
+                Pane::Prompt(pane_id) => {
+                    let c = self.prompt.render(fb, area, &self.theme);
+                    (cursor.is_none() && self.focused_pane == *pane_id).then(|| cursor = Some(c));
+                }
+                Pane::Preview(pane_id, buffer_id) => {
+                    let editor = &self.editors[self.preview_editor_id];
+                    let buffer = &self.buffers[*buffer_id];
+                    let (editor_area, status_area) = split_status_line(area);
+                    let snapshot = editor.snapshot(buffer);
+                    let widget = ui::EditorPane::new(&self.theme, &snapshot).with_list(list);
+                    let c = widget.render(fb, editor_area);
+                    ui::StatusLine::new(&self.theme, buffer, editor)
+                        .focused(self.app_focused)
+                        .render(fb, status_area);
+                    (cursor.is_none() && self.focused_pane == *pane_id).then(|| cursor = Some(c));
+                }
+                Pane::Diff(pane_id, group) => {
+                    let view = &self.diffs[*group];
+                    // both sides keep the row of the focused one's cursor in view
+                    let line = self.editors[view.focused_editor_id()].cursor().line;
+                    let row = view.alignment.row_of(view.focused, line);
+                    let (left, divider, right) = split_sides(area);
+                    for (side, area) in [(DiffSide::Left, left), (DiffSide::Right, right)] {
+                        let editor = &self.editors[view.editor_id(side)];
+                        let buffer = &self.buffers[editor.buffer_id];
+                        let (editor_area, status_area) = split_status_line(area);
+                        let snapshot = editor.snapshot(buffer);
+                        let widget = ui::EditorPane::new(&self.theme, &snapshot)
+                            .aligned(&view.alignment, side, row)
+                            .with_list(list);
+                        let c = widget.render(fb, editor_area);
+                        let focused = side == view.focused;
+                        let status = ui::StatusLine::new(&self.theme, buffer, editor)
+                            .message(self.message.as_deref().filter(|_| focused))
+                            .focused(self.app_focused && focused);
+                        status.render(fb, status_area);
+                        (focused && cursor.is_none() && self.focused_pane == *pane_id)
+                            .then(|| cursor = Some(c));
+                    }
+                    for y in divider.top()..divider.bottom() {
+                        fb.get_mut(divider.x, y)
+                            .set_symbol("│")
+                            .set_style(tui::Style::reset());
+                    }
+                }
+            }
+        }
+
+        if let Some(cursor) = cursor.filter(|_| self.block_cursor) {
+            ui::draw_cell_cursor(fb, area, cursor);
+        }
+        cursor
+    }
+
+    #[tracing::instrument(skip(ev, self))]
+    fn process_event(&mut self, ev: Event) -> Option<Command> {
+        match ev {
+            Event::FocusGained => Some(Command::FocusChanged(true)),
+            Event::FocusLost => Some(Command::FocusChanged(false)),
+            Event::Paste(_) => todo!(),
+            Event::Mouse(_) => todo!(),
+            Event::Resize(width, height) => {
+                // an inline viewport stays at the bottom, clamped to the terminal's height
+                let area = self.viewport.area(width, height);
+                self.resize(area);
+                Some(Command::Resize(area))
+            }
+            Event::Key(key) => self.process_key(key.into()),
+        }
+    }
+
+    fn process_key(&mut self, key: InputKey) -> Option<Command> {
+        let ctrl = key.is_ctrl();
+        self.message = None;
+        // a scroll never holds up what's typed: it's done before the key is
+        self.finish_scrolls(now(), true);
+        #[cfg(unix)]
+        if ctrl && key.key == Key::Char('z') {
+            return Some(Command::Suspend);
+        }
+
+        let focused_pane = self
+            .panes
+            .get_mut(self.focused_pane)
+            .expect("focused pane does not exist");
+
+        // every overlay closes on Esc
+        if key.key == Key::Esc && focused_pane.is_overlay() {
+            return Some(Command::Pane(focused_pane.id(), PaneCommand::Close));
+        }
+
+        match focused_pane {
+            Pane::Commands(_) => selector_motion(key)
+                .map(Command::Commands)
+                .or_else(|| match key.key {
+                    Key::Backspace => Some(Command::Commands(selector::Command::Delete(
+                        selector::Direction::Prev,
+                    ))),
+                    Key::Delete => Some(Command::Commands(selector::Command::Delete(
+                        selector::Direction::Next,
+                    ))),
+                    Key::Left => Some(Command::Commands(selector::Command::MoveCursor(
+                        selector::Direction::Prev,
+                    ))),
+                    Key::Right => Some(Command::Commands(selector::Command::MoveCursor(
+                        selector::Direction::Next,
+                    ))),
+                    Key::Enter => {
+                        let command = self.command_registry.focused();
+                        if command.is_some() {
+                            let line = self.command_registry.selector.query.as_str().trim();
+                            self.registers.command = Some(line.to_string());
+                        }
+                        command
+                    }
+                    Key::Tab => Some(Command::CommandsComplete),
+                    Key::Char('c') if ctrl => {
+                        Some(Command::Pane(self.commands_pane_id, PaneCommand::Close))
+                    }
+                    Key::Char(c) => Some(Command::Commands(selector::Command::Insert(c))),
+                    _ => None,
+                }),
+            Pane::Grep(pane_id) => {
+                let pane_id = *pane_id;
+                selector_motion(key)
+                    .map(Command::GrepResults)
+                    .or_else(|| match key.key {
+                        Key::Enter => self
+                            .grep
+                            .focused()
+                            .map(|m| Command::FileOpenAt(None, m.path.clone(), m.point())),
+                        Key::Char('c') if ctrl => Some(Command::Pane(pane_id, PaneCommand::Close)),
+                        _ => None,
+                    })
+            }
+            Pane::Outline(pane_id) => {
+                let pane_id = *pane_id;
+                let query = |command| Some(Command::OutlineResults(command));
+                selector_motion(key)
+                    .map(Command::OutlineResults)
+                    .or_else(|| match key.key {
+                        Key::Backspace => {
+                            query(selector::Command::Delete(selector::Direction::Prev))
+                        }
+                        Key::Delete => query(selector::Command::Delete(selector::Direction::Next)),
+                        Key::Left => {
+                            query(selector::Command::MoveCursor(selector::Direction::Prev))
+                        }
+                        Key::Right => {
+                            query(selector::Command::MoveCursor(selector::Direction::Next))
+                        }
+                        Key::Enter => Some(Command::OutlineJump),
+                        Key::Char('c') if ctrl => Some(Command::Pane(pane_id, PaneCommand::Close)),
+                        Key::Char(c) => query(selector::Command::Insert(c)),
+                        _ => None,
+                    })
+            }
+            Pane::Prompt(pane_id) => {
+                let pane_id = *pane_id;
+                selector_motion(key).map(Command::Prompt).or_else(|| {
+                    let answer = match key.key {
+                        Key::Enter => self.prompt.focused()?,
+                        Key::Char('c') if ctrl => prompt::Answer::Cancel,
+                        Key::Char('y') => prompt::Answer::Yes,
+                        Key::Char('n') => prompt::Answer::No,
+                        _ => return None,
+                    };
+                    let close = Command::Pane(pane_id, PaneCommand::Close);
+                    Some(self.prompt.answer(answer).unwrap_or(close))
+                })
+            }
+            Pane::Diff(pane_id, _) if key.key == Key::Tab && self.pending.is_empty() => {
+                Some(Command::Pane(*pane_id, PaneCommand::SwitchSide))
+            }
+            // a diff passes keys on to the editor of the side with focus
+            Pane::Editor(..) | Pane::Diff(..) => {
+                let editor_id = &self.focused_editor_id();
+                let page = self.page_lines;
+                // keys.toml binds keys of normal mode that don't end a command already begun
+                let unbegun = self.editors[*editor_id].mode == editor::Mode::Normal
+                    && self.pending.is_empty();
+                if let Some(line) = self.keys.get(&key).filter(|_| unbegun) {
+                    let command = self.command_registry.parse(line);
+                    if command.is_none() {
+                        self.message = Some(format!("unknown command '{}'", line));
+                    }
+                    return command;
+                }
+                let editor = &mut self.editors[*editor_id];
+                let mut pending = std::mem::take(&mut self.pending);
+                // `"` and a register's name go before the keys of a command that uses it
+                let mut register = None;
+                if let Some(name) = pending
+                    .strip_prefix('"')
+                    .and_then(|rest| rest.chars().next())
+                {
+                    register = Some(name);
+                    pending.replace_range(..1 + name.len_utf8(), "");
+                }
+                let prefix = register.map_or(String::new(), |name| format!("\"{}", name));
+                let command = match editor.mode {
+                    // ctrl-c only cancels; quitting is always an explicit `:q`.
+                    editor::Mode::Normal if ctrl && key.key == Key::Char('c') => {
+                        if pending.is_empty() {
+                            self.message = Some("type :q to quit".to_string());
+                        }
+                        return None;
+                    }
+                    editor::Mode::Normal if !pending.is_empty() => {
+                        match (pending.as_str(), key.key) {
+                            ("g", Key::Char('g')) => {
+                                Some(EditorCommand::CursorJump(editor::CursorJump::StartOfBuffer))
+                            }
+                            ("g", Key::Char('-')) => Some(EditorCommand::Undo(UndoMove::Steps(-1))),
+                            ("g", Key::Char('+')) => Some(EditorCommand::Undo(UndoMove::Steps(1))),
+                            ("\"", Key::Char(c)) => {
+                                self.pending = format!("\"{}", c);
+                                return None;
+                            }
+                            ("r", Key::Char(c)) => Some(EditorCommand::ReplaceChar(c)),
+                            ("m", Key::Char(c)) if c.is_ascii_alphabetic() => {
+                                return Some(Command::SetMark(*editor_id, c));
+                            }
+                            // `'` is where the cursor was before the last outline jump
+                            (p @ ("'" | "`"), Key::Char(c))
+                                if c.is_ascii_alphabetic() || c == '\'' =>
+                            {
+                                let jump = match p {
+                                    "'" => editor::MarkJump::Line,
+                                    _ => editor::MarkJump::Exact,
+                                };
+                                return Some(Command::JumpToMark(*editor_id, c, jump));
+                            }
+                            ("v" | "d", Key::Char(c @ ('i' | 'a'))) => {
+                                self.pending = prefix + &pending + &c.to_string();
+                                return None;
+                            }
+                            (p @ ("vi" | "va" | "di" | "da"), Key::Char(c @ ('w' | 'W'))) => {
+                                use editor::TextObject::*;
+                                let object = match (&p[1..], c) {
+                                    ("i", 'w') => InnerWord,
+                                    ("a", 'w') => AroundWord,
+                                    ("i", _) => InnerBigWord,
+                                    _ => AroundBigWord,
+                                };
+                                match &p[..1] {
+                                    "v" => Some(EditorCommand::SelectTextObject(object)),
+                                    _ => Some(EditorCommand::DeleteTextObject(object)),
+                                }
+                            }
+                            ("d" | "y" | "c", Key::Char('g')) => {
+                                self.pending = prefix + &pending + "g";
+                                return None;
+                            }
+                            (p @ ("dg" | "yg" | "cg"), Key::Char('g')) => {
+                                let gg = editor::Motion::Jump(editor::CursorJump::StartOfBuffer);
+                                Some(EditorCommand::Operator(operator(p), gg, 1))
+                            }
+                            (p @ ("d" | "y" | "c"), code) => {
+                                let target = match code {
+                                    Key::Char(c) if p.starts_with(c) => {
+                                        Some((editor::Motion::Line, 1))
+                                    }
+                                    code => operator_motion(code, page),
+                                };
+                                let (motion, count) = target?;
+                                Some(EditorCommand::Operator(operator(p), motion, count))
+                            }
+                            _ => return None,
+                        }
+                    }
+                    // terminals send ctrl-^ as ctrl-6
+                    editor::Mode::Normal if ctrl && matches!(key.key, Key::Char('^' | '6')) => {
+                        return Some(Command::AlternateBuffer(*editor_id));
+                    }
+                    editor::Mode::Normal => match key.key {
+                        Key::Char('r') if ctrl => Some(EditorCommand::Undo(UndoMove::Redo)),
+                        Key::Char('u') => Some(EditorCommand::Undo(UndoMove::Undo)),
+                        Key::Char('i') => Some(EditorCommand::SetMode(editor::Mode::Insert)),
+                        Key::Char('a') => Some(EditorCommand::AppendAfterCursor),
+                        Key::Char('A') => Some(EditorCommand::AppendAtEndOfLine),
+                        Key::Char('o') => Some(EditorCommand::OpenLineBelow),
+                        Key::Char('O') => Some(EditorCommand::OpenLineAbove),
+                        Key::Esc => Some(EditorCommand::CollapseSelections),
+                        Key::Char('C') if key.modifiers.contains(Modifiers::ALT) => {
+                            Some(EditorCommand::AddCursorAbove)
+                        }
+                        Key::Char('C') => Some(EditorCommand::AddCursorBelow),
+                        Key::Char('R') => Some(EditorCommand::SetMode(editor::Mode::Replace)),
+                        Key::Char('V') => Some(EditorCommand::SelectLine),
+                        Key::Char('n') => Some(EditorCommand::SearchNext(false)),
+                        Key::Char('N') => Some(EditorCommand::SearchNext(true)),
+                        Key::Char(c @ ('p' | 'P')) => {
+                            let name = register.unwrap_or('"');
+                            return Some(Command::Paste(*editor_id, name, c == 'P'));
+                        }
+                        Key::Char(
+                            c @ ('g' | 'r' | 'm' | '\'' | '`' | 'v' | 'd' | 'y' | 'c' | '"'),
+                        ) => {
+                            self.pending = prefix + &c.to_string();
+                            None
+                        }
+                        code => motion(code, page),
+                    },
+                    editor::Mode::VisualLine => match key.key {
+                        Key::Char(c) if pending == "\"" => {
+                            self.pending = format!("\"{}", c);
+                            None
+                        }
+                        Key::Esc => Some(EditorCommand::CollapseSelections),
+                        Key::Char('c') if ctrl => Some(EditorCommand::CollapseSelections),
+                        Key::Char('V') => Some(EditorCommand::SelectLine),
+                        Key::Char('v') => Some(EditorCommand::SetMode(editor::Mode::Normal)),
+                        Key::Char('d') => Some(EditorCommand::DeleteLines),
+                        Key::Char('y') => Some(EditorCommand::YankLines),
+                        Key::Char('"') => {
+                            self.pending = "\"".to_string();
+                            None
+                        }
+                        Key::Char('>') => Some(EditorCommand::IndentLines),
+                        Key::Char('<') => Some(EditorCommand::DedentLines),
+                        code => motion(code, page),
+                    },
+                    // replace mode types over the text, which the editor takes care of
+                    editor::Mode::Insert | editor::Mode::Replace => match key.key {
+                        Key::Esc => Some(EditorCommand::SetMode(editor::Mode::Normal)),
+                        Key::Char('c') if ctrl => {
+                            Some(EditorCommand::SetMode(editor::Mode::Normal))
+                        }
+                        Key::Up => Some(EditorCommand::CursorMove(editor::Direction::Up)),
+                        Key::Down => Some(EditorCommand::CursorMove(editor::Direction::Down)),
+                        Key::Left => Some(EditorCommand::CursorMove(editor::Direction::Left)),
+                        Key::Right => Some(EditorCommand::CursorMove(editor::Direction::Right)),
+                        Key::Home | Key::End | Key::PageUp | Key::PageDown => motion(key.key, page),
+                        Key::Backspace => Some(EditorCommand::DeleteBackward),
+                        Key::Char('h') if ctrl => Some(EditorCommand::DeleteBackward),
+                        Key::Char('w') if ctrl => Some(EditorCommand::DeleteWordBackward),
+                        Key::Char('u') if ctrl => Some(EditorCommand::DeleteToInsertStart),
+                        Key::Delete => Some(EditorCommand::DeleteForward),
+                        Key::Tab => Some(EditorCommand::InsertIndent),
+                        Key::Enter => Some(EditorCommand::InsertNewline),
+                        Key::Char(c) => Some(EditorCommand::InsertChar(c)),
+                        _ => None,
+                    },
+                };
+                let yanks = |c: &EditorCommand| {
+                    matches!(
+                        c,
+                        EditorCommand::Operator(..)
+                            | EditorCommand::YankLines
+                            | EditorCommand::DeleteLines
+                    )
+                };
+                command
+                    .map(|c| match register {
+                        Some(name) if yanks(&c) => Command::YankInto(*editor_id, name, c),
+                        _ => Command::Editor(*editor_id, c),
+                    })
+                    .or(match editor.mode {
+                        editor::Mode::Normal | editor::Mode::VisualLine => match key.key {
+                            Key::Char(':') => {
+                                Some(Command::Pane(self.commands_pane_id, PaneCommand::Open))
+                            }
+                            _ => None,
+                        },
+                        _ => None,
+                    })
+            }
+            Pane::Preview(pane_id, _) => match key.key {
+                Key::Esc | Key::Char('q') => Some(Command::Pane(*pane_id, PaneCommand::Close)),
+                Key::Char(':') => Some(Command::Pane(self.commands_pane_id, PaneCommand::Open)),
+                code => motion(code, self.page_lines)
+                    .map(|c| Command::Editor(self.preview_editor_id, c)),
+            },
+        }
+    }
+
+    fn process_syntax(&mut self, ev: syntax::Event) -> Option<Command> {
+        match ev {
+            syntax::Event::Hightlight(buffer_id, version, hls) => {
+                Some(Command::Buffer(buffer_id, BufferCommand::Highlight(version, hls)))
+            }
+            syntax::Event::HighlightDelta(buffer_id, version, deltas) => {
+                let command = BufferCommand::HighlightDelta(version, deltas);
+                Some(Command::Buffer(buffer_id, command))
+            }
+            syntax::Event::Parsed(buffer_id, version, tree) => {
+                if let Some(syntax) = self.syntax_states.get_mut(buffer_id) {
+                    syntax.parsed(version, tree);
+                }
+                None
+            }
+            syntax::Event::Symbols(buffer_id, version, symbols) => {
+                if let Some(syntax) = self.syntax_states.get_mut(buffer_id) {
+                    syntax.symbols_found(version, symbols);
+                }
+                None
+            }
+        }
+    }
+
+    fn process_lsp(&mut self, event: lsp::Event) -> Option<Command> {
+        match event {
+            lsp::Event::Diagnostics { path, version, diagnostics } => {
+                Some(Command::SetDiagnostics(path, version, diagnostics))
+            }
+            lsp::Event::Exited { language, error, retry } => {
+                let retry = match retry {
+                    Some(retry) => format!("restarting in {}s", retry.as_secs()),
+                    None => "given up on".to_string(),
+                };
+                let message = format!("{} language server exited: {}; {}", language, error, retry);
+                self.message = Some(message);
+                None
+            }
+        }
+    }
+}
+
+/// The terminal title for a buffer: its file name, marked when it has unsaved changes.
+fn window_title(path: Option<&std::path::Path>, dirty: bool) -> String {
+    let name = path
+        .and_then(|path| path.file_name())
+        .map_or("[scratch]".into(), |name| name.to_string_lossy());
+    let dirty = if dirty { " ±" } else { "" };
+    format!("{}{} — {}", name, dirty, *crate::PROJECT_NAME)
+}
+
+/// Normal mode keys shared by editors and previews, as listed by `:help`.
+const KEY_BINDINGS: [(&str, &str, &str); 55] = [
+    ("normal", "h, Left", "cursor left"),
+    ("normal", "j, Down", "cursor down"),
+    ("normal", "k, Up", "cursor up"),
+    ("normal", "l, Right", "cursor right"),
+    ("normal", "w", "start of next word"),
+    ("normal", "e", "end of word"),
+    ("normal", "b", "start of word"),
+    ("normal", "0, Home", "start of line"),
+    ("normal", "$, End", "end of line"),
+    ("normal", "Enter", "first non-blank of next line"),
+    ("normal", "PageUp, PageDown", "up, down a page"),
+    ("normal", "gg, G", "start, end of buffer"),
+    ("normal", "C, A-C", "add cursor below, above"),
+    ("normal", "Esc", "keep only the primary cursor"),
+    ("normal", "i", "insert mode"),
+    ("normal", "a, A", "append after cursor, at end of line"),
+    ("normal", "o, O", "open line below, above"),
+    ("normal", "r<char>", "replace char under cursor"),
+    ("normal", "R", "replace mode"),
+    ("normal", "m<letter>", "set mark; capitals work across buffers"),
+    ("normal", "'<letter>, `<letter>", "jump to mark's line, to mark"),
+    ("normal", "'', `'", "back to before the last :outline jump"),
+    ("normal", "C-^", "swap to the alternate buffer, as :ls marks it"),
+    ("normal", "n, N", "next, previous match of :search, a regex after :set regex"),
+    ("normal", "d<motion>, dd", "delete to where the motion goes, lines"),
+    ("normal", "y<motion>, yy", "yank to where the motion goes, lines"),
+    ("normal", "c<motion>, cc", "change to where the motion goes, lines"),
+    ("normal", "p, P", "put the register after, before the cursor"),
+    ("normal", "\"<reg>", "use register a-z, or paste % . : before p or P"),
+    ("normal", "viw, vaw", "select word, with its whitespace"),
+    ("normal", "diw, daw", "delete word, with its whitespace"),
+    ("normal", "iW, aW", "a run of non-blanks instead of a word"),
+    ("normal", "V", "visual line mode"),
+    ("normal", "u, C-r", "undo, redo"),
+    ("normal", "g-, g+", "older, newer change, across undo branches"),
+    ("normal", ":", "commands"),
+    ("commands", "Tab", "complete the path typed, as far as it can be"),
+    ("insert", "Esc, C-c", "normal mode"),
+    ("insert", "Enter", "new line, indented like the one before"),
+    ("insert", "Backspace, C-h", "delete selection or char before cursor"),
+    ("insert", "C-w", "delete the word before the cursor"),
+    ("insert", "C-u", "delete what was typed on the line"),
+    ("insert", "Delete", "delete selection or char under cursor"),
+    ("insert", "Home, End, PageUp, PageDown", "as in normal mode"),
+    ("insert", "Tab", "indent, with a tab or spaces per the settings"),
+    ("replace", "Backspace", "restore the char last typed over"),
+    ("v-line", "d, y", "delete, yank the selected lines"),
+    ("v-line", ">, <", "indent, dedent the selected lines"),
+    ("v-line", "v", "normal mode, keeping the selection"),
+    ("v-line", ":", "commands, like :format, on the selected lines"),
+    ("preview", "q, Esc", "close preview"),
+    ("diff", "Tab", "switch sides"),
+    ("grep", "Enter", "open the file at the match"),
+    ("outline", "Enter", "jump to the symbol"),
+    ("prompt", "y, n, Esc", "answer yes, no, or cancel"),
+];
+
+/// Keys that move the focus through the entries of a selector pane.
+fn selector_motion<Id>(key: InputKey) -> Option<selector::Command<Id>> {
+    use selector::{Command, Direction};
+
+    let ctrl = key.is_ctrl();
+    match key.key {
+        Key::Up => Some(Command::Focus(Direction::Prev)),
+        Key::Down => Some(Command::Focus(Direction::Next)),
+        Key::Char('p') if ctrl => Some(Command::Focus(Direction::Prev)),
+        Key::Char('n') if ctrl => Some(Command::Focus(Direction::Next)),
+        Key::PageUp => Some(Command::FocusPage(Direction::Prev)),
+        Key::PageDown => Some(Command::FocusPage(Direction::Next)),
+        Key::Home => Some(Command::FocusFirst),
+        Key::End => Some(Command::FocusLast),
+        _ => None,
+    }
+}
+
+/// Cursor motions available in normal mode and in previews, paging by `page` lines.
+fn motion(code: Key, page: usize) -> Option<EditorCommand> {
+    use editor::{CursorJump, Direction};
+
+    match code {
+        Key::Up | Key::Char('k') => Some(EditorCommand::CursorMove(Direction::Up)),
+        Key::Down | Key::Char('j') => Some(EditorCommand::CursorMove(Direction::Down)),
+        Key::Left | Key::Char('h') => Some(EditorCommand::CursorMove(Direction::Left)),
+        Key::Right | Key::Char('l') => Some(EditorCommand::CursorMove(Direction::Right)),
+        Key::Char('w') => Some(EditorCommand::CursorJump(CursorJump::StartOfNextWord)),
+        Key::Char('e') => Some(EditorCommand::CursorJump(CursorJump::EndOfNearestWord)),
+        Key::Char('b') => Some(EditorCommand::CursorJump(CursorJump::StartOfNearestWord)),
+        Key::Home | Key::Char('0') => Some(EditorCommand::CursorJump(CursorJump::StartOfLine)),
+        Key::End | Key::Char('$') => Some(EditorCommand::CursorJump(CursorJump::EndOfLine)),
+        Key::Char('G') => Some(EditorCommand::CursorJump(CursorJump::EndOfBuffer)),
+        Key::Enter => Some(EditorCommand::CursorJump(CursorJump::FirstNonBlankOfNextLine)),
+        Key::PageUp => Some(EditorCommand::CursorMoveLines(Direction::Up, page)),
+        Key::PageDown => Some(EditorCommand::CursorMoveLines(Direction::Down, page)),
+        _ => None,
+    }
+}
+
+/// The operator of pending keys starting with `d`, `y` or `c`.
+fn operator(pending: &str) -> editor::Operator {
+    match &pending[..1] {
+        "y" => editor::Operator::Yank,
+        "c" => editor::Operator::Change,
+        _ => editor::Operator::Delete,
+    }
+}
+
+/// What an operator takes when given the motion `code` makes, and how many times it makes it.
+fn operator_motion(code: Key, page: usize) -> Option<(editor::Motion, usize)> {
+    match motion(code, page)? {
+        EditorCommand::CursorMove(direction) => Some((editor::Motion::Move(direction), 1)),
+        EditorCommand::CursorJump(jump) => Some((editor::Motion::Jump(jump), 1)),
+        EditorCommand::CursorMoveLines(direction, lines) => {
+            Some((editor::Motion::Move(direction), lines))
+        }
+        _ => None,
+    }
+}
+
+fn split_status_line(area: tui::Rect) -> (tui::Rect, tui::Rect) {
+    let height = area.height.saturating_sub(1);
+    let editor = tui::Rect { height, ..area };
+    let status = tui::Rect { y: area.y + height, height: area.height - height, ..area };
+    (editor, status)
+}
+
+/// Splits `area` into halves side by side, with the column between them for a divider.
+fn split_sides(area: tui::Rect) -> (tui::Rect, tui::Rect, tui::Rect) {
+    let width = area.width.saturating_sub(1) / 2;
+    let left = tui::Rect { width, ..area };
+    let divider = tui::Rect { x: left.right(), width: area.width.min(1), ..area };
+    let right = tui::Rect { x: divider.right(), width: area.right() - divider.right(), ..area };
+    (left, divider, right)
+}
+
+/// Runs futures on the app's runtime, off the task handling input.
+struct BackgroundExecutor;
+
+impl BackgroundExecutor {
+    pub fn spawn<F>(&self, future: F) -> tokio::task::JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        tokio::task::spawn(future)
+    }
+
+    /// Runs jobs handed to it from any thread on threads of their own, for work that would hold
+    /// up the thread it came from.
+    pub fn blocking_spawner(&self) -> impl Fn(syntax::Job) + Send + 'static {
+        let handle = tokio::runtime::Handle::current();
+        move |job| drop(handle.spawn_blocking(job))
+    }
+}
+
+struct AppContext {
+    background: BackgroundExecutor,
+}
+
+impl AppContext {
+    pub fn new() -> Result<Self> {
+        Ok(Self { background: BackgroundExecutor })
+    }
+
+    pub fn background_executor(&self) -> &BackgroundExecutor {
+        &self.background
+    }
+}
+
+/// How paths given on the command line are laid out at startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpenLayout {
+    /// The first path is shown, the rest are loaded into the buffer list.
+    #[default]
+    Buffers,
+    /// An editor pane per path, each split over the one before, with the first path on top.
+    Splits,
+    /// The two paths diffed side by side.
+    Diff,
+}
+
+/// Redraws are capped to about one per display refresh.
+const FRAME_INTERVAL: std::time::Duration = std::time::Duration::from_millis(16);
+/// Files at least this big are read in the background, showing their progress.
+const LARGE_FILE: u64 = 8 << 20;
+/// How long buffers must go unchanged before they're diffed against their saved contents.
+const LINE_DIFF_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+/// How long buffers must go unchanged before the matches of searches in them are found again.
+const SEARCH_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+/// How long buffers must go unchanged before their language servers are told what changed.
+const LSP_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+/// How often changed buffers are journaled for crash recovery.
+const JOURNAL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+/// How many changes to a buffer get it journaled before the next interval is up.
+const JOURNAL_EDITS: usize = 200;
+/// How often the config files are looked at for changes.
+const CONFIG_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// How to settle a file changed on disk under a buffer, said along with that it has.
+const RESOLVE: &str = ":w! writes over it, :reload! reads it again, :diff-disk compares";
+
+/// The time by tokio's clock, which tests can pause and step.
+fn now() -> std::time::Instant {
+    tokio::time::Instant::now().into_std()
+}
+
+/// Why `App::select_command` returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Wake {
+    /// A frame is due and the screen is out of date.
+    Frame,
+    /// Terminal input, which may change what is on screen even without producing a command.
+    Event,
+    Command,
+}
+
+/// Where frames are drawn.
+trait Screen {
+    fn draw(&mut self, state: &State) -> Result<()>;
+    /// Forgets what is on screen so the next draw repaints everything.
+    fn clear(&mut self) -> Result<()>;
+    /// Draws the next frames in `area` of the terminal.
+    fn resize(&mut self, _area: tui::Rect) -> Result<()> {
+        Ok(())
+    }
+    /// Sets the window title, or clears it for None, unless `last` says it already is.
+    fn title(&mut self, _last: &mut terminal::Title, _title: Option<&str>) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Screen for Terminal {
+    fn draw(&mut self, state: &State) -> Result<()> {
+        use crossterm::QueueableCommand;
+        use std::io::Write;
+
+        let mut cursor: Option<(CursorPoint, SetCursorStyle)> = None;
+        ratatui::Terminal::draw(self, |frame| {
+            cursor = state.draw_frame(frame);
+        })?;
+
+        let (cursor, cursor_style) = cursor.expect("cursor must be set");
+        if state.block_cursor {
+            // the frame has the cursor drawn in; the terminal's own would only get in the way
+            self.backend_mut().queue(cursor::Hide)?.flush()?;
+            return Ok(());
+        }
+        self.backend_mut()
+            .queue(cursor_style)?
+            .queue(cursor::MoveTo(cursor.x, cursor.y))?
+            .queue(cursor::Show)?
+            .flush()?;
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        Ok(ratatui::Terminal::clear(self)?)
+    }
+
+    fn resize(&mut self, area: tui::Rect) -> Result<()> {
+        Ok(ratatui::Terminal::resize(self, area)?)
+    }
+
+    fn title(&mut self, last: &mut terminal::Title, title: Option<&str>) -> Result<()> {
+        use std::io::Write;
+
+        last.update(self.backend_mut(), title)?;
+        Ok(self.backend_mut().flush()?)
+    }
+}
+
+/// What the command line asks for at startup.
+#[derive(Debug, Default)]
+pub struct Startup {
+    /// The files to open, the first one shown.
+    pub paths: Vec<std::path::PathBuf>,
+    /// Where to put the cursor in the first file.
+    pub jump: Option<StartupJump>,
+    /// Whether to open every file with journaled changes too, and restore them.
+    pub recover: bool,
+    /// Commands typed as into the palette, run in order once every file is in.
+    pub commands: Vec<String>,
+}
+
+/// Where `+line` or `+/pattern` puts the cursor in the first file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartupJump {
+    /// The start of the line, counted from 1.
+    Line(usize),
+    /// The first match of the search after the start of the file.
+    Search(String),
+}
+
+pub struct App {
+    ctx: AppContext,
+    /// Where unsaved changes are journaled; None if there's nowhere to.
+    recovery: Option<Recovery>,
+    /// Where the config is read from.
+    config: ConfigFiles,
+    cmd_rx: mpsc::Receiver<Command>,
+    cmd_tx: mpsc::Sender<Command>,
+    syntax: syntax::Syntax,
+    /// The language servers, and what they say.
+    lsp: lsp::Clients,
+    /// The command each language's formatter is run with.
+    formatters: HashMap<String, Vec<String>>,
+    /// The `.toku.toml` of each project a file's been opened in.
+    projects: project::Projects,
+    lsp_events: mpsc::UnboundedReceiver<lsp::Event>,
+    state: State,
+    /// The window title as last set.
+    title: terminal::Title,
+    /// Lines of a script waiting for files to finish loading.
+    script: Vec<String>,
+    /// Commands a script named, run before anything else is waited for.
+    queued: VecDeque<Command>,
+}
+
+impl App {
+    pub fn spawn(
+        mut startup: Startup,
+        layout: OpenLayout,
+        auto_save: Option<std::time::Duration>,
+        validate: bool,
+        viewport: terminal::Viewport,
+        guard: Box<dyn TerminalGuard>,
+    ) -> Result<()> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let ctx = AppContext::new()?;
+        rt.block_on(async move {
+            let stdout = std::io::stdout();
+            let (width, height) = crossterm::terminal::size()?;
+            let area = viewport.area(width, height);
+            let options = ratatui::TerminalOptions {
+                viewport: match viewport {
+                    terminal::Viewport::Fullscreen => ratatui::Viewport::Fullscreen,
+                    terminal::Viewport::Inline(_) => ratatui::Viewport::Fixed(area),
+                },
+            };
+            let term = Terminal::with_options(CrosstermBackend::new(stdout), options)?;
+            let events = EventStream::new();
+
+            let (cmd_tx, cmd_rx) = mpsc::channel(1);
+            #[cfg(unix)]
+            ctx.background_executor()
+                .spawn(terminal::on_continue(cmd_tx.clone()));
+            let mut app = Self::new(ctx, cmd_tx, cmd_rx);
+            app.state.viewport = viewport;
+            app.state.resize(area);
+            app.state.validator = validate.then(Validator::new);
+            // the paths are opened through the app's own queue, like any other command
+            let cmd_tx = app.cmd_tx.clone();
+            app.recovery = Recovery::xdg()
+                .inspect_err(|err| tracing::warn!(%err, "unsaved changes won't be journaled"))
+                .ok();
+            app.config = ConfigFiles::xdg()
+                .inspect_err(|err| tracing::warn!(%err, "the built-in config is used"))
+                .unwrap_or_default();
+            app.load_config().await;
+            let paths = app.config.paths();
+            tokio::spawn(events::watch_config(paths, cmd_tx.clone(), CONFIG_INTERVAL));
+            let journal = events::journal(
+                app.state.subscribe(),
+                cmd_tx.clone(),
+                JOURNAL_INTERVAL,
+                JOURNAL_EDITS,
+            );
+            tokio::spawn(journal);
+            if let Some(delay) = auto_save {
+                tokio::spawn(events::auto_save(app.state.subscribe(), cmd_tx.clone(), delay));
+            }
+            let diff_changed =
+                events::diff_changed(app.state.subscribe(), cmd_tx.clone(), LINE_DIFF_DELAY);
+            tokio::spawn(diff_changed);
+            let refresh =
+                events::refresh_searches(app.state.subscribe(), cmd_tx.clone(), SEARCH_DELAY);
+            tokio::spawn(refresh);
+            let sync = events::sync_documents(app.state.subscribe(), cmd_tx.clone(), LSP_DELAY);
+            tokio::spawn(sync);
+            if startup.recover {
+                let journaled = app.recovery.as_ref().map(Recovery::paths).transpose()?;
+                for path in journaled.unwrap_or_default() {
+                    if !startup.paths.iter().any(|p| grep::normalize(p) == path) {
+                        startup.paths.push(path);
+                    }
+                }
+            }
+            let commands = Self::startup_commands(startup, layout);
+            let session = terminal::Session::new(guard);
+            let app = tokio::spawn(app.run(term, events, session));
+            for command in commands {
+                cmd_tx.send(command).await?;
+            }
+
+            let res = app.await;
+            res?
+        })
+    }
+
+    fn new(
+        ctx: AppContext,
+        cmd_tx: mpsc::Sender<Command>,
+        cmd_rx: mpsc::Receiver<Command>,
+    ) -> Self {
+        let syntax = Syntax::spawn(ctx.background_executor().blocking_spawner());
+        let (lsp_tx, lsp_events) = mpsc::unbounded_channel();
+        let lsp = lsp::Clients::new(lsp_tx);
+        let state = State::new();
+        let title = terminal::Title::default();
+        let (script, queued) = (vec![], VecDeque::new());
+        let config = ConfigFiles::default();
+        Self {
+            ctx,
+            recovery: None,
+            config,
+            cmd_tx,
+            cmd_rx,
+            syntax,
+            lsp,
+            lsp_events,
+            formatters: HashMap::new(),
+            projects: Default::default(),
+            state,
+            title,
+            script,
+            queued,
+        }
+    }
+
+    /// Opens the files, jumping in the first one, or diffs the two, restores them if asked to,
+    /// then runs the commands as a script.
+    fn startup_commands(startup: Startup, layout: OpenLayout) -> Vec<Command> {
+        let diff = layout == OpenLayout::Diff && startup.paths.len() == 2;
+        let open_first = |path| match &startup.jump {
+            Some(StartupJump::Line(line)) => {
+                let point = tore::Point { line: line.saturating_sub(1), column: 0 };
+                Command::FileOpenAt(None, path, point)
+            }
+            _ => Command::FileOpen(None, path),
+        };
+
+        let mut paths = startup.paths.into_iter();
+        let mut script = vec![];
+        let mut commands = vec![];
+        if diff {
+            let sides = paths.next().zip(paths.next());
+            commands.extend(sides.map(|(left, right)| Command::DiffOpen(left, right)));
+        } else if layout == OpenLayout::Splits {
+            // each split opens over the last, so the first path is opened last to be on top
+            let split: Vec<_> = paths.by_ref().enumerate().collect();
+            for (i, path) in split.into_iter().rev() {
+                if !commands.is_empty() {
+                    commands.push(Command::Split);
+                }
+                commands.push(match i {
+                    0 => open_first(path),
+                    _ => Command::FileOpen(None, path),
+                });
+            }
+        } else if let Some(path) = paths.next() {
+            commands.push(open_first(path));
+        }
+        commands.extend(paths.map(Command::BufferOpen));
+        if startup.recover {
+            commands.push(Command::Recover);
+        }
+        if let Some(StartupJump::Search(pattern)) = startup.jump {
+            script.push(format!("search {}", pattern));
+        }
+        script.extend(startup.commands);
+        if !script.is_empty() {
+            commands.push(Command::Script(script));
+        }
+        commands
+    }
+
+    async fn run<S, E>(
+        mut self,
+        mut screen: S,
+        mut events: E,
+        mut session: terminal::Session,
+    ) -> Result<()>
+    where
+        S: Screen,
+        E: Stream<Item = std::io::Result<Event>> + Unpin,
+    {
+        let mut frames = tokio::time::interval(FRAME_INTERVAL);
+        frames.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut dirty = true;
+        'main: loop {
+            let (wake, mut maybe_command) =
+                self.select_command(&mut events, &mut frames, dirty).await?;
+            match wake {
+                Wake::Frame => {
+                    let start = std::time::Instant::now();
+                    screen.draw(&self.state)?;
+                    self.state.latency.drawn(start, std::time::Instant::now());
+                    screen.title(&mut self.title, self.state.title().as_deref())?;
+                    // a scroll is over once its target's been drawn
+                    self.state.finish_scrolls(now(), false);
+                    dirty = false;
+                    continue 'main;
+                }
+                Wake::Event => dirty = true,
+                Wake::Command => {}
+            }
+
+            // handle everything that is already queued before the next frame is drawn.
+            let applying = std::time::Instant::now();
+            while let Some(command) = maybe_command.take() {
+                dirty |= self.state.redraws(&command);
+                match command {
+                    // with work to lose, it's asked about first
+                    Command::Quit if self.state.confirmation(&command).is_none() => break 'main,
+                    Command::Confirmed(command) if matches!(*command, Command::Quit) => break 'main,
+                    #[cfg(unix)]
+                    Command::Suspend => session.suspend(terminal::stop)?,
+                    Command::Resize(area) => screen.resize(area)?,
+                    Command::Resume => {
+                        session.resume(|| screen.clear())?;
+                        // the shell may have set its own title in the meantime
+                        self.title = terminal::Title::default();
+                    }
+                    command => self.process_command(command).await?,
+                }
+                maybe_command = self
+                    .queued
+                    .pop_front()
+                    .or_else(|| self.cmd_rx.try_recv().ok());
+            }
+            self.state.latency.applied(applying.elapsed());
+            self.state.follow_scrolls(now());
+            if let Some(recovery) = &self.recovery {
+                recovery::set_panic_journal(recovery, self.state.dirty_buffers());
+            }
+        }
+
+        for load in self.state.loads.values() {
+            load.cancel.cancel();
+        }
+        // quitting leaves nothing to recover
+        if let Some(recovery) = &self.recovery {
+            for path in self.state.buffers.values().filter_map(|b| b.path.as_ref()) {
+                if let Err(err) = recovery.remove(path) {
+                    tracing::warn!(?path, %err, "failed to remove journal");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the next command, or for the next frame when there is something to draw or a
+    /// smooth scroll is underway.
+    async fn select_command<E>(
+        &mut self,
+        events: &mut E,
+        frames: &mut tokio::time::Interval,
+        dirty: bool,
+    ) -> Result<(Wake, Option<Command>)>
+    where
+        E: Stream<Item = std::io::Result<Event>> + Unpin,
+    {
+        use futures::{future::FutureExt, StreamExt};
+
+        let wake = tokio::select! {
+            biased;
+            _ = frames.tick(), if dirty || self.state.is_scrolling() => (Wake::Frame, None),
+            maybe_command = self.cmd_rx.recv() => (Wake::Command, maybe_command),
+            maybe_syntax = self.syntax.next().fuse() => {
+                let syntax = maybe_syntax.expect("syntax thread crashed?");
+                (Wake::Command, self.state.process_syntax(syntax))
+            },
+            Some(event) = self.lsp_events.recv() => (Wake::Command, self.state.process_lsp(event)),
+            maybe_event = events.next().fuse() => match maybe_event {
+                // nobody is left to answer a prompt
+                None => (Wake::Command, Some(Command::Confirmed(Box::new(Command::Quit)))),
+                Some(event) => {
+                    // timed by the wall clock, which tests stepping tokio's don't stop
+                    let received = std::time::Instant::now();
+                    let command = self.state.process_event(event?);
+                    self.state.latency.input(received, std::time::Instant::now());
+                    (Wake::Event, command)
+                }
+            },
+        };
+        Ok(wake)
+    }
+
+    async fn process_command(&mut self, command: Command) -> Result<()> {
+        let command = match command {
+            Command::Confirmed(command) => {
+                if self.state.focused_pane == self.state.prompt_pane_id {
+                    self.state.close_focused_pane();
+                }
+                *command
+            }
+            command => match self.state.confirmation(&command) {
+                Some(question) => {
+                    self.state.ask(question, command);
+                    return Ok(());
+                }
+                None => command,
+            },
+        };
+        match command {
+            Command::Quit | Command::Suspend | Command::Resume | Command::Resize(_) => {
+                unreachable!("handled in main loop")
+            }
+            Command::Confirmed(_) => unreachable!("confirmed twice"),
+            Command::Prompt(cmd) => self.state.prompt.selector.command(cmd),
+            Command::Commands(cmd) => self.state.command_registry.command(cmd),
+            Command::CommandsComplete => self.state.command_registry.complete(),
+            Command::Help => {
+                let help = self.state.help_text();
+                self.state.open_preview(&help);
+            }
+            Command::Version => {
+                let version = format!("{} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+                self.state.open_preview(&version);
+            }
+            Command::Pane(pane_id, cmd) => match cmd {
+                PaneCommand::Open => {
+                    if pane_id == self.state.commands_pane_id {
+                        let context = self.state.pane_context();
+                        self.state.command_registry.set_context(context);
+                        let base = self.state.visible_buffer_id();
+                        let base = base.and_then(|id| self.state.buffers[id].path.clone());
+                        self.state.command_registry.set_base(base);
+                    }
+                    self.state.focus_pane(pane_id);
+                }
+                PaneCommand::Close => {
+                    debug_assert_eq!(self.state.focused_pane, pane_id);
+                    self.state.close_focused_pane()
+                }
+                PaneCommand::SwitchSide => {
+                    if let Some(Pane::Diff(_, group)) = self.state.panes.get(pane_id) {
+                        let view = &mut self.state.diffs[*group];
+                        view.focused = view.focused.other();
+                    }
+                }
+            },
+            Command::Editor(editor_id, cmd) => {
+                self.state.editor_command(editor_id, cmd);
+                self.parse_changed().await?;
+            }
+            Command::SetMark(editor_id, mark) => self.state.set_mark(editor_id, mark),
+            Command::YankInto(editor_id, name, cmd) => {
+                self.state.yank_into(editor_id, name, cmd);
+                self.parse_changed().await?;
+            }
+            Command::Paste(editor_id, name, before) => {
+                self.state.paste(editor_id, name, before);
+                self.parse_changed().await?;
+            }
+            Command::JumpToMark(editor_id, mark, jump) => {
+                self.state.jump_to_mark(editor_id, mark, jump)
+            }
+            Command::Buffer(buffer_id, cmd) => {
+                let buffer = &mut self.state.buffers[buffer_id];
+                buffer.command(cmd);
+            }
+
+            Command::FocusedEditor(cmd) => {
+                let editor_id = self.state.focused_editor_id();
+                self.state.editor_command(editor_id, cmd);
+                self.parse_changed().await?;
+            }
+            Command::ToggleComment => {
+                let editor_id = self.state.focused_editor_id();
+                let buffer = &self.state.buffers[self.state.editors[editor_id].buffer_id];
+                let language = syntax::Language::try_from(buffer).ok();
+                match language.and_then(|language| language.comment_token) {
+                    Some(token) => {
+                        let toggle = EditorCommand::ToggleComment(token);
+                        self.state.editor_command(editor_id, toggle);
+                        self.parse_changed().await?;
+                    }
+                    None => self.state.message = Some("no line comments here".to_string()),
+                }
+            }
+            Command::Format => {
+                let editor_id = self.state.focused_editor_id();
+                let editor = &self.state.editors[editor_id];
+                let buffer = &self.state.buffers[editor.buffer_id];
+                if buffer.read_only {
+                    self.state.message = Some("cannot format: buffer is read-only".to_string());
+                    return Ok(());
+                }
+                let language = syntax::Language::try_from(buffer).ok();
+                let language =
+                    language.filter(|_| self.state.syntax_states.contains_key(buffer.id));
+                // the user's own formatter, or else the project's
+                let path = buffer.path.as_deref();
+                let project = path.and_then(|path| self.projects.for_file(path).ok().flatten());
+                let argv = language.and_then(|language| {
+                    let formatter = self.formatters.get(language.name);
+                    formatter.or_else(|| project.as_ref()?.formatters.get(language.name))
+                });
+                let Some(argv) = argv else {
+                    self.state.message = Some("no formatter for this buffer".to_string());
+                    return Ok(());
+                };
+                let selected = editor.selections();
+                let lines = (editor.mode == editor::Mode::VisualLine).then(|| {
+                    let (first, last) = (selected[0], selected[selected.len() - 1]);
+                    first.start().line..last.end().line + 1
+                });
+                let len_lines = buffer.contents.len_lines();
+                let argv = match format::expand(argv, path, lines.clone(), len_lines) {
+                    Ok(argv) => argv,
+                    Err(err) => {
+                        self.state.message = Some(format!("cannot format: {}", err));
+                        return Ok(());
+                    }
+                };
+                let (buffer_id, version) = (buffer.id, buffer.version);
+                let contents = buffer.contents.clone();
+                if lines.is_some() {
+                    self.state
+                        .editor_command(editor_id, EditorCommand::CollapseSelections);
+                }
+                let cmd_tx = self.cmd_tx.clone();
+                self.ctx.background_executor().spawn(async move {
+                    let formatted = format::format(&argv, contents).await;
+                    let _ = cmd_tx
+                        .send(Command::Formatted(buffer_id, version, formatted))
+                        .await;
+                });
+            }
+            Command::Formatted(buffer_id, version, formatted) => {
+                self.state.formatted(buffer_id, version, formatted);
+                self.parse_changed().await?;
+            }
+
+            Command::FileOpen(maybe_editor_id, path) => {
+                self.open_file(maybe_editor_id, path, None).await?;
+            }
+            Command::FileOpenAt(maybe_editor_id, path, point) => {
+                if self.state.focused_pane().is_overlay() {
+                    self.state.close_focused_pane();
+                }
+                self.open_file(maybe_editor_id, path, Some(point)).await?;
+            }
+            Command::FileOpenLossy(maybe_editor_id, path) => {
+                let target = match path {
+                    Some(path) => Some((maybe_editor_id, path)),
+                    None => self.state.undecodable.take(),
+                };
+                let Some((maybe_editor_id, path)) = target else {
+                    self.state.message = Some("no file failed to open".to_string());
+                    return Ok(());
+                };
+                if is_large(&path).await {
+                    self.load_in_background(maybe_editor_id, path, true, None);
+                    return Ok(());
+                }
+                let buffer_id = self.open_buffer(path, true).await?;
+                let editor_id = self.state.editor_or_default(maybe_editor_id);
+                self.state.swap_buffer(editor_id, buffer_id);
+            }
+            Command::FileLoadProgress(load_id, read, total) => {
+                if let Some(load) = self.state.loads.get(load_id) {
+                    let message = load::progress_message(&load.path, read, total);
+                    self.state.message = Some(message);
+                }
+            }
+            Command::FileLoaded(load_id, loaded) => {
+                let Some(load) = self.state.loads.remove(load_id) else {
+                    return Ok(());
+                };
+                self.state.message = None;
+                match loaded {
+                    Ok((contents, format, replaced)) => {
+                        let buffer_id = self
+                            .insert_buffer(load.path, contents, format, replaced)
+                            .await?;
+                        let editor_id = self.state.editor_or_default(load.editor_id);
+                        self.state.swap_buffer(editor_id, buffer_id);
+                        if let Some(point) = load.jump {
+                            self.state.jump(editor_id, point);
+                        }
+                        self.state.realign_diffs();
+                    }
+                    Err(err) => match err.downcast_ref::<editor::InvalidEncoding>() {
+                        Some(invalid) => self.state.undecodable(load.editor_id, load.path, invalid),
+                        None if err.is::<editor::Cancelled>() => {
+                            let message = format!("stopped opening {}", load.path.display());
+                            self.state.message = Some(message);
+                        }
+                        None => {
+                            let message = format!("{}: {}", load.path.display(), err);
+                            self.state.message = Some(message);
+                        }
+                    },
+                }
+                self.queue_script();
+            }
+            Command::Script(lines) => {
+                self.script.extend(lines);
+                self.queue_script();
+            }
+            Command::FileOpenCancel => {
+                if self.state.loads.is_empty() {
+                    self.state.message = Some("no file is being opened".to_string());
+                }
+                for load in self.state.loads.values() {
+                    load.cancel.cancel();
+                }
+            }
+            Command::BufferOpen(path) => {
+                self.open_buffer(path, false).await?;
+            }
+            Command::DiffOpen(left, right) => {
+                let group = self.state.open_diff();
+                let editors = self.state.diffs[group].editors;
+                for (editor_id, path) in editors.into_iter().zip([left, right]) {
+                    self.open_file(Some(editor_id), path, None).await?;
+                }
+                self.state.realign_diffs();
+            }
+            Command::BufferSave(buffer_id) => self.save_buffer(buffer_id, false).await?,
+            Command::Edit(None) => self.state.message = Some("expected a path to edit".to_string()),
+            Command::Edit(Some(path)) => {
+                if let Err(err) = self.open_file(None, path.clone(), None).await {
+                    self.state.message = Some(format!("{}: {}", path.display(), err));
+                }
+            }
+            Command::Write(path) => self.write(path, false).await?,
+            Command::WriteForce(path) => self.write(path, true).await?,
+            Command::Reload => {
+                let Some(buffer_id) = self.state.visible_buffer_id() else {
+                    return Ok(());
+                };
+                match self.state.buffers[buffer_id].reload().await {
+                    Ok(changed) => {
+                        self.forget_journal(buffer_id);
+                        if let Some(range) = changed {
+                            self.state.edited_elsewhere(buffer_id, range);
+                            self.parse_changed().await?;
+                        }
+                    }
+                    Err(err) => self.state.message = Some(format!("not reloaded: {:#}", err)),
+                }
+            }
+            Command::DiffDisk => {
+                let Some(buffer_id) = self.state.visible_buffer_id() else {
+                    return Ok(());
+                };
+                let Some(path) = self.state.buffers[buffer_id].path.clone() else {
+                    self.state.message = Some("buffer has no file to diff against".to_string());
+                    return Ok(());
+                };
+                match Buffer::read(&path).await {
+                    Ok((on_disk, _)) => self.state.open_disk_diff(buffer_id, on_disk),
+                    Err(err) => self.state.message = Some(format!("{}: {:#}", path.display(), err)),
+                }
+            }
+            Command::BufferDiff(buffer_id) => {
+                let Some(buffer) = self.state.buffers.get(buffer_id) else {
+                    return Ok(());
+                };
+                let Some(saved) = buffer.saved() else {
+                    return Ok(());
+                };
+                let (saved, contents, version) =
+                    (saved.clone(), buffer.contents.clone(), buffer.version);
+                let cmd_tx = self.cmd_tx.clone();
+                self.ctx.background_executor().spawn(async move {
+                    let changes = editor::line_diff(&saved, &contents);
+                    let _ = cmd_tx
+                        .send(Command::SetLineDiff(buffer_id, version, changes))
+                        .await;
+                });
+            }
+            Command::SetLineDiff(buffer_id, version, changes) => {
+                if let Some(buffer) = self.state.buffers.get_mut(buffer_id) {
+                    buffer.set_line_diff(version, changes);
+                }
+            }
+            Command::SearchRefresh(buffer_id) => {
+                let Some(buffer) = self.state.buffers.get(buffer_id) else {
+                    return Ok(());
+                };
+                let editors = self.state.editors.values_mut();
+                for editor in editors.filter(|editor| editor.buffer_id == buffer_id) {
+                    editor.refresh_search(buffer);
+                }
+                if buffer.word_index().is_stale() {
+                    let (contents, version) = (buffer.contents.clone(), buffer.version);
+                    let cmd_tx = self.cmd_tx.clone();
+                    self.ctx.background_executor().spawn(async move {
+                        let words = editor::WordIndex::build(&contents);
+                        let _ = cmd_tx
+                            .send(Command::SetWordIndex(buffer_id, version, words))
+                            .await;
+                    });
+                }
+            }
+            Command::SetWordIndex(buffer_id, version, words) => {
+                if let Some(buffer) = self.state.buffers.get_mut(buffer_id) {
+                    buffer.set_word_index(version, words);
+                }
+            }
+            Command::BufferJournal(buffer_id) => drop(self.journal(buffer_id)),
+            Command::LspSync(buffer_id) => {
+                let Some(buffer) = self.state.buffers.get(buffer_id) else {
+                    return Ok(());
+                };
+                let Some(path) = &buffer.path else {
+                    return Ok(());
+                };
+                if !self.state.syntax_states.contains_key(buffer_id) {
+                    return Ok(());
+                }
+                let Ok(language) = syntax::Language::try_from(buffer) else {
+                    return Ok(());
+                };
+                if self.lsp.serves(language.name) {
+                    let (path, text) = (grep::normalize(path), buffer.contents.to_string());
+                    self.lsp.sync(language.name, &path, buffer.version, text);
+                }
+            }
+            Command::SetDiagnostics(path, version, diagnostics) => {
+                if let Some(buffer_id) = self.state.find_buffer(&path) {
+                    let buffer = &mut self.state.buffers[buffer_id];
+                    buffer.set_diagnostics(version.unwrap_or(buffer.version), diagnostics);
+                }
+            }
+            Command::FocusChanged(focused) => {
+                self.state.app_focused = focused;
+                self.state.emit(AppEvent::FocusChanged(focused));
+                if focused {
+                    self.check_files().await;
+                } else {
+                    // the app may well be killed before it's come back to
+                    let dirty = self.state.buffers.values().filter(|buffer| buffer.dirty);
+                    let dirty: Vec<_> = dirty.map(|buffer| buffer.id).collect();
+                    for buffer_id in dirty {
+                        drop(self.journal(buffer_id));
+                    }
+                }
+            }
+            Command::Recover => {
+                let recoverable = std::mem::take(&mut self.state.recoverable);
+                self.state.message = Some(match recoverable.len() {
+                    0 => "nothing to recover".to_string(),
+                    1 => "recovered 1 buffer; it's unsaved".to_string(),
+                    n => format!("recovered {} buffers; they're unsaved", n),
+                });
+                for (buffer_id, contents) in recoverable {
+                    self.state.restore(buffer_id, &contents);
+                }
+                self.parse_changed().await?;
+            }
+            Command::ConfigReload => {
+                if self.load_config().await {
+                    self.state.message = Some("config reloaded".to_string());
+                }
+            }
+            Command::Theme(None) => {
+                self.state.message = Some("expected a theme file to load".to_string())
+            }
+            Command::Theme(Some(path)) => {
+                // the loader falls back to the built-in theme without a file, as at startup
+                let loaded = if path.is_file() {
+                    config::load_theme(Some(&path)).await
+                } else {
+                    Err(anyhow::anyhow!("{}: no such file", path.display()))
+                };
+                match loaded {
+                    Ok(theme) => {
+                        self.state.theme = theme;
+                        self.config.theme = Some(path);
+                    }
+                    Err(err) => self.state.message = Some(format!("theme not loaded: {:#}", err)),
+                }
+            }
+            Command::ListBuffers => {
+                let root = std::env::current_dir()?;
+                let list = self.state.buffer_list(self.state.default_editor_id, &root);
+                self.state.open_preview(&list);
+            }
+            Command::SwitchBuffer(number) => {
+                if self.state.focused_pane().is_overlay() {
+                    self.state.close_focused_pane();
+                }
+                self.state
+                    .switch_buffer(self.state.default_editor_id, &number);
+            }
+            Command::AlternateBuffer(editor_id) => self.state.swap_alternate(editor_id),
+            Command::Split => self.state.split_pane(),
+            Command::Earlier(count) => self.undo_by(&count, false).await?,
+            Command::Later(count) => self.undo_by(&count, true).await?,
+            Command::UndoList => {
+                let editor_id = self.state.focused_editor_id();
+                let list = self.state.undo_list(editor_id, std::time::Instant::now());
+                self.state.open_preview(&list);
+            }
+            Command::Registers => {
+                let editor_id = self.state.focused_editor_id();
+                let list = self.state.register_list(editor_id);
+                self.state.open_preview(&list);
+            }
+            Command::Close => {
+                if self.state.focused_pane().is_overlay() {
+                    self.state.close_focused_pane();
+                }
+                self.state.close_focused_pane();
+            }
+            Command::Grep(query) => {
+                if query.is_empty() {
+                    self.state.message = Some("grep needs something to search for".to_string());
+                    return Ok(());
+                }
+                let root = std::env::current_dir()?;
+                let exclude = match self.projects.for_dir(&root) {
+                    Ok(project) => project.map_or_else(Default::default, |p| p.exclude.clone()),
+                    Err(err) => {
+                        self.state.message = Some(format!("project config not loaded: {:#}", err));
+                        return Ok(());
+                    }
+                };
+                let buffers = self
+                    .state
+                    .buffers
+                    .values()
+                    .filter_map(|buffer| {
+                        let path = grep::normalize(buffer.path.as_ref()?);
+                        Some((path, buffer.contents.clone()))
+                    })
+                    .collect();
+                let (search_id, cancel) = self.state.grep.start(root.clone(), query.clone());
+                self.state.message = Some(format!("searching for \"{}\"", query));
+                self.state.focus_pane(self.state.grep_pane_id);
+                let cmd_tx = self.cmd_tx.clone();
+                let grep = grep::grep(search_id, root, exclude, query, buffers, cancel, cmd_tx);
+                self.ctx.background_executor().spawn(grep);
+            }
+            Command::GrepMatches(search_id, matches) => self.state.grep.add(search_id, matches),
+            Command::GrepDone(search_id, truncated) => {
+                if let Some(summary) = self.state.grep.summary(search_id, truncated) {
+                    self.state.message = Some(summary);
+                }
+            }
+            Command::GrepResults(cmd) => self.state.grep.selector.command(cmd),
+            Command::Run(command) if command.trim().is_empty() => {
+                self.state.message = Some("run needs a command".to_string());
+            }
+            Command::Run(command) => match self.state.runs.start(command.clone()) {
+                Err(message) => self.state.message = Some(message),
+                Ok((run_id, kill)) => {
+                    let buffer_id = self.state.open_run_output();
+                    self.state.runs.buffer_id = Some(buffer_id);
+                    self.state.message = Some(format!("running '{}'", command));
+                    let run = run::run(run_id, command, kill, self.cmd_tx.clone());
+                    self.ctx.background_executor().spawn(run);
+                }
+            },
+            Command::RunOutput(run_id, output) => {
+                if let Some(buffer_id) = self.state.runs.buffer_id {
+                    if self.state.runs.is_current(run_id) {
+                        self.state.append_output(buffer_id, &output);
+                    }
+                }
+            }
+            Command::RunDone(run_id, status) => {
+                if let Some(message) = self.state.runs.done(run_id, &status) {
+                    self.state.message = Some(message);
+                }
+            }
+            Command::InspectHighlight => {
+                let report = self.state.inspect_highlight();
+                self.state.open_preview(&report);
+            }
+            Command::Profile => {
+                let report = self.state.latency.report();
+                self.state.open_preview(&report);
+            }
+            Command::ExportHtml(argument) => self.state.export(export::Format::Html, &argument),
+            Command::ExportAnsi(argument) => self.state.export(export::Format::Ansi, &argument),
+            Command::Outline => self.state.open_outline(),
+            Command::OutlineResults(cmd) => self.state.outline.command(cmd),
+            Command::OutlineJump => self.state.outline_jump(),
+            Command::RunKill => {
+                if !self.state.runs.kill() {
+                    self.state.message = Some("nothing is running".to_string());
+                }
+            }
+            Command::Validate(toggle) => match toggle.trim() {
+                "on" => self.state.validator = Some(Validator::new()),
+                "off" => self.state.validator = None,
+                toggle => {
+                    self.state.message = Some(format!("expected on or off, not '{}'", toggle))
+                }
+            },
+            Command::Set(setting) => match setting.trim() {
+                "title" => self.state.show_title = true,
+                "notitle" => self.state.show_title = false,
+                "blockcursor" => self.state.block_cursor = true,
+                "noblockcursor" => self.state.block_cursor = false,
+                "list" => self.state.list = true,
+                "nolist" => self.state.list = false,
+                "smoothscroll" => self.state.smooth_scroll = true,
+                "nosmoothscroll" => self.state.smooth_scroll = false,
+                "statussymbol" => self.state.status_symbol = true,
+                "nostatussymbol" => self.state.status_symbol = false,
+                "showlatency" => self.state.show_latency = true,
+                "noshowlatency" => self.state.show_latency = false,
+                setting if setting.starts_with("historybudget=") => {
+                    let value = setting["historybudget=".len()..].trim();
+                    match value.parse::<usize>() {
+                        Ok(mb) => {
+                            self.state.history.max_bytes = mb << 20;
+                            self.state.history.enforce(&mut self.state.buffers);
+                        }
+                        Err(_) => {
+                            self.state.message =
+                                Some(format!("expected megabytes, not '{}'", value))
+                        }
+                    }
+                }
+                setting if setting.starts_with("listchars=") => {
+                    let value = &setting["listchars=".len()..];
+                    if let Err(message) = self.state.list_chars.set(value.trim()) {
+                        self.state.message = Some(message);
+                    }
+                }
+                "number" if !self.state.gutter.contains(&ui::GutterColumn::Numbers) => {
+                    self.state.gutter.push(ui::GutterColumn::Numbers);
+                }
+                "number" => {}
+                "nonumber" => self
+                    .state
+                    .gutter
+                    .retain(|c| *c != ui::GutterColumn::Numbers),
+                setting @ ("autopairs" | "noautopairs") => {
+                    let on = setting == "autopairs";
+                    for editor in self.state.editors.values_mut() {
+                        editor.auto_pairs = on;
+                    }
+                }
+                setting @ ("regex" | "noregex") => {
+                    let mode = if setting == "regex" {
+                        SearchMode::Regex
+                    } else {
+                        SearchMode::Plain
+                    };
+                    for editor in self.state.editors.values_mut() {
+                        editor.search_mode = mode;
+                    }
+                }
+                setting => {
+                    let buffer_id = self.state.visible_buffer_id();
+                    let buffer = buffer_id.and_then(|id| self.state.buffers.get_mut(id));
+                    let result = match (setting.split_once('='), buffer) {
+                        (Some((key, value)), Some(buffer)) => {
+                            buffer.set_override(key.trim(), value.trim())
+                        }
+                        _ => Err(format!("unknown setting '{}'", setting)),
+                    };
+                    if let Err(message) = result {
+                        self.state.message = Some(message);
+                    }
+                }
+            },
+        };
+
+        Ok(())
+    }
+
+    /// Moves the focused editor's buffer back, or `later` forward, through its undo states as
+    /// `count` says.
+    async fn undo_by(&mut self, count: &str, later: bool) -> Result<()> {
+        match undo_move(count, later) {
+            Ok(to) => {
+                let editor_id = self.state.focused_editor_id();
+                self.state
+                    .editor_command(editor_id, EditorCommand::Undo(to));
+                self.parse_changed().await?;
+            }
+            Err(message) => self.state.message = Some(message),
+        }
+        Ok(())
+    }
+
+    /// Saves the visible buffer, to `path` if given, which it's then saved to from then on;
+    /// `force` saves it even over a file that changed on disk.
+    async fn write(&mut self, path: Option<std::path::PathBuf>, force: bool) -> Result<()> {
+        let Some(buffer_id) = self.state.visible_buffer_id() else {
+            return Ok(());
+        };
+        let buffer = &mut self.state.buffers[buffer_id];
+        if let Some(path) = path {
+            let own = buffer.path.as_deref().map(grep::normalize);
+            if own != Some(grep::normalize(&path)) {
+                // what's on disk at the old path has nothing to say about the new one
+                buffer.disk = None;
+                buffer.disk_state = DiskState::Unchanged;
+            }
+            buffer.path = Some(path);
+            self.resolve_settings(buffer_id);
+        } else if buffer.path.is_none() {
+            self.state.message = Some("expected a path to write to".to_string());
+            return Ok(());
+        }
+        self.save_buffer(buffer_id, force).await
+    }
+
+    /// Saves the buffer, tidying it up first per its settings. Unless `force`d, a file that
+    /// changed on disk since it was read or saved isn't written over; that's said instead.
+    async fn save_buffer(&mut self, buffer_id: BufferId, force: bool) -> Result<()> {
+        if !force && self.changed_on_disk(buffer_id).await {
+            return Ok(());
+        }
+        let buffer = &self.state.buffers[buffer_id];
+        if buffer.path.is_some() && !buffer.read_only {
+            self.state.fix_up(buffer_id);
+            self.parse_changed().await?;
+        }
+        let buffer = &mut self.state.buffers[buffer_id];
+        if buffer.path.is_none() {
+            tracing::warn!(?buffer_id, "not saving buffer without a path");
+        } else if buffer.read_only {
+            tracing::warn!(?buffer_id, "not saving read-only buffer");
+        } else if let Err(err) = buffer.save().await {
+            tracing::error!(?buffer_id, %err, "failed to save buffer");
+        } else {
+            self.forget_journal(buffer_id);
+            self.state.emit(AppEvent::BufferSaved(buffer_id));
+        }
+        Ok(())
+    }
+
+    /// Whether the buffer's file changed on disk since it was read or saved, as already noticed
+    /// or as it is now, saying so if it did.
+    async fn changed_on_disk(&mut self, buffer_id: BufferId) -> bool {
+        let buffer = &mut self.state.buffers[buffer_id];
+        if buffer.disk_state == DiskState::Unchanged {
+            if !buffer.check_disk().await {
+                return false;
+            }
+            buffer.disk_changed();
+        }
+        let name = buffer.path.as_deref().and_then(|path| path.file_name());
+        let name = name.map_or("file".into(), |name| name.to_string_lossy());
+        self.state.message = Some(format!("not saved: {} changed on disk; {}", name, RESOLVE));
+        true
+    }
+
+    /// Drops the buffer's journal, now that there's nothing unsaved in it to recover.
+    fn forget_journal(&mut self, buffer_id: BufferId) {
+        let path = self.state.buffers[buffer_id].path.as_ref();
+        if let Some((recovery, path)) = self.recovery.as_ref().zip(path) {
+            if let Err(err) = recovery.remove(path) {
+                tracing::warn!(?path, %err, "failed to remove journal");
+            }
+        }
+        self.state.recoverable.remove(&buffer_id);
+    }
+
+    /// Queues the commands the script's lines name, once no file is still loading. A line that
+    /// names none is said to be unknown, and the rest still run.
+    fn queue_script(&mut self) {
+        if !self.state.loads.is_empty() {
+            return;
+        }
+        for line in self.script.drain(..) {
+            match self.state.command_registry.parse(&line) {
+                Some(command) => self.queued.push_back(command),
+                None => self.state.message = Some(format!("unknown command '{}'", line)),
+            }
+        }
+    }
+
+    /// Opens `path` in the editor, or the default one, moving the cursor to `jump` once it's in.
+    /// Shows the buffer already holding it if there is one.
+    async fn open_file(
+        &mut self,
+        maybe_editor_id: Option<EditorId>,
+        path: std::path::PathBuf,
+        jump: Option<tore::Point>,
+    ) -> Result<()> {
+        if let Some(buffer_id) = self.state.find_buffer(&path) {
+            let editor_id = self.state.editor_or_default(maybe_editor_id);
+            self.state.swap_buffer(editor_id, buffer_id);
+            if let Some(point) = jump {
+                self.state.jump(editor_id, point);
+            }
+            return Ok(());
+        }
+        if is_large(&path).await {
+            self.load_in_background(maybe_editor_id, path, false, jump);
+            return Ok(());
+        }
+        let buffer_id = match self.open_buffer(path.clone(), false).await {
+            Ok(buffer_id) => buffer_id,
+            Err(err) => match err.downcast_ref::<editor::InvalidEncoding>() {
+                Some(invalid) => {
+                    self.state.undecodable(maybe_editor_id, path, invalid);
+                    return Ok(());
+                }
+                None => return Err(err),
+            },
+        };
+        let editor_id = self.state.editor_or_default(maybe_editor_id);
+        self.state.swap_buffer(editor_id, buffer_id);
+        if let Some(point) = jump {
+            self.state.jump(editor_id, point);
+        }
+        Ok(())
+    }
+
+    /// Reads `path` into a new buffer. A `lossy` read replaces whatever doesn't decode, and
+    /// makes the buffer read-only if anything had to be.
+    async fn open_buffer(&mut self, path: std::path::PathBuf, lossy: bool) -> Result<BufferId> {
+        let read = {
+            let path = path.clone();
+            async move {
+                if lossy {
+                    Buffer::read_lossy(&path).await
+                } else {
+                    Buffer::read(&path)
+                        .await
+                        .map(|(contents, format)| (contents, format, 0))
+                }
+            }
+        };
+        let (contents, format, replaced) = self.ctx.background_executor().spawn(read).await??;
+        self.insert_buffer(path, contents, format, replaced).await
+    }
+
+    /// Reads `path` on the background executor, to be shown in the editor, with the cursor at
+    /// `jump`, once it's in.
+    fn load_in_background(
+        &mut self,
+        editor_id: Option<EditorId>,
+        path: std::path::PathBuf,
+        lossy: bool,
+        jump: Option<tore::Point>,
+    ) {
+        let cancel = CancellationToken::new();
+        let load = PendingLoad { editor_id, path: path.clone(), cancel: cancel.clone(), jump };
+        let load_id = self.state.loads.insert(load);
+        self.state.message = Some(load::progress_message(&path, 0, None));
+        let load = load::load_file(load_id, path, lossy, cancel, self.cmd_tx.clone());
+        self.ctx.background_executor().spawn(load);
+    }
+
+    /// Reads the config files and applies them, as at startup. A theme that doesn't read leaves
+    /// the one in use as it is, and says what's wrong with it and where; returns whether it read.
+    async fn load_config(&mut self) -> bool {
+        match config::load_theme(self.config.theme.as_deref()).await {
+            Ok(theme) => self.state.theme = theme,
+            Err(err) => {
+                self.state.message = Some(format!("theme not loaded: {:#}", err));
+                return false;
+            }
+        }
+        match config::load_commands(self.config.servers.as_deref()).await {
+            Ok(servers) => self.lsp.set_commands(servers),
+            Err(err) => {
+                self.state.message = Some(format!("language servers not loaded: {:#}", err));
+                return false;
+            }
+        }
+        match config::load_commands(self.config.formatters.as_deref()).await {
+            Ok(formatters) => self.formatters = formatters,
+            Err(err) => {
+                self.state.message = Some(format!("formatters not loaded: {:#}", err));
+                return false;
+            }
+        }
+        match config::load_keys(self.config.keys.as_deref()).await {
+            Ok(keys) => {
+                self.state.keys = keys;
+                // keys typed towards a binding may not lead anywhere now
+                self.state.pending.clear();
+            }
+            Err(err) => {
+                self.state.message = Some(format!("key bindings not loaded: {:#}", err));
+                return false;
+            }
+        }
+        self.projects.clear();
+        let buffer_ids: Vec<_> = self.state.buffers.keys().collect();
+        buffer_ids
+            .into_iter()
+            .fold(true, |resolved, buffer_id| self.resolve_settings(buffer_id) && resolved)
+    }
+
+    /// Lays the settings of the project the buffer's file is in under those of the
+    /// `.editorconfig` files above it, under any the user set. Says what's wrong with a project
+    /// config that doesn't read, and returns whether it read.
+    fn resolve_settings(&mut self, buffer_id: BufferId) -> bool {
+        let Some(path) = self.state.buffers[buffer_id].path.clone() else {
+            return true;
+        };
+        let (project, resolved) = match self.projects.for_file(&path) {
+            Ok(project) => (project, true),
+            Err(err) => {
+                self.state.message = Some(format!("project config not loaded: {:#}", err));
+                (None, false)
+            }
+        };
+        let settings = project.map_or_else(Default::default, |project| project.settings.clone());
+        let buffer = &mut self.state.buffers[buffer_id];
+        buffer.set_project(settings);
+        buffer.set_editorconfig(editorconfig::resolve(&path));
+        resolved
+    }
+
+    /// Says which open files have been written by something else since they were read or
+    /// saved, marking their buffers to say so only once, and those with unsaved changes as in
+    /// conflict, with how to settle it.
+    async fn check_files(&mut self) {
+        let (mut changed, mut conflicted) = (vec![], vec![]);
+        for buffer in self.state.buffers.values_mut() {
+            if buffer.disk_state != DiskState::Unchanged || !buffer.check_disk().await {
+                continue;
+            }
+            buffer.disk_changed();
+            let Some(path) = &buffer.path else {
+                continue;
+            };
+            let name = path.file_name().unwrap_or(path.as_os_str());
+            let name = name.to_string_lossy().into_owned();
+            match buffer.disk_state {
+                DiskState::Conflicted => conflicted.push(name),
+                _ => changed.push(name),
+            }
+        }
+        let mut said = vec![];
+        if !changed.is_empty() {
+            changed.sort();
+            said.push(format!("{} changed on disk", changed.join(", ")));
+        }
+        if !conflicted.is_empty() {
+            conflicted.sort();
+            let names = conflicted.join(", ");
+            said.push(format!("{} changed on disk and here; {}", names, RESOLVE));
+        }
+        if !said.is_empty() {
+            self.state.message = Some(said.join("; "));
+        }
+    }
+
+    /// Journals the buffer, if it has a path and unsaved changes, on a blocking thread. Returns
+    /// the write, which logs rather than returns any error.
+    fn journal(&self, buffer_id: BufferId) -> Option<tokio::task::JoinHandle<()>> {
+        let recovery = self.recovery.clone()?;
+        let buffer = self.state.buffers.get(buffer_id)?;
+        let path = buffer.path.clone().filter(|_| buffer.dirty)?;
+        let (version, contents) = (buffer.version, buffer.contents.clone());
+        let write = tokio::task::spawn_blocking(move || {
+            if let Err(err) = recovery.write(&path, version, &contents) {
+                tracing::warn!(?path, %err, "failed to journal buffer");
+            }
+        });
+        Some(write)
+    }
+
+    /// Adds a buffer holding what was read from `path`.
+    async fn insert_buffer(
+        &mut self,
+        path: std::path::PathBuf,
+        contents: BufferContents,
+        format: editor::Format,
+        replaced: usize,
+    ) -> Result<BufferId> {
+        let buffer_id = self
+            .state
+            .buffers
+            .insert_with_key(|k| Buffer::open(k, path.clone(), contents, format));
+        self.state.list_buffer(buffer_id);
+        let buffer = &mut self.state.buffers[buffer_id];
+        buffer.disk = editor::DiskStamp::read(&path, &buffer.contents).await;
+        self.resolve_settings(buffer_id);
+        if replaced > 0 {
+            self.state.buffers[buffer_id].read_only = true;
+            self.state.message = Some(format!(
+                "replaced {} invalid {} sequences; read-only so saving can't lose the originals",
+                replaced, format.encoding
+            ));
+        } else if format.encoding == editor::Encoding::Binary {
+            self.state.message = Some("binary file, shown as hex and read-only".to_string());
+        }
+        let journaled = match &self.recovery {
+            Some(recovery) => recovery.newer_than_disk(&path).unwrap_or_else(|err| {
+                tracing::warn!(?path, %err, "failed to read journal");
+                None
+            }),
+            None => None,
+        };
+        if let Some(journaled) = journaled {
+            self.state.recoverable.insert(buffer_id, journaled.contents);
+            self.state.message = Some(format!(
+                "{} has unsaved changes from before a crash; :recover restores them",
+                path.display()
+            ));
+        }
+        self.state.emit(AppEvent::BufferOpened(buffer_id, path));
+
+        self.state
+            .syntax_states
+            .insert(buffer_id, SyntaxState::default());
+        self.parse_changed().await?;
+        Ok(buffer_id)
+    }
+
+    /// Asks for a parse of every buffer that changed since its last one was asked for.
+    async fn parse_changed(&mut self) -> Result<()> {
+        for (buffer_id, syntax) in self.state.syntax_states.iter_mut() {
+            if let Some(parse) = syntax.parse(&mut self.state.buffers[buffer_id])? {
+                self.syntax.command(parse).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The undo move `:earlier`, or with `later` `:later`, makes for `count`: that many states, or
+/// one without a count, or as long as the count says with `s`, `m` or `h` after it.
+fn undo_move(count: &str, later: bool) -> Result<UndoMove, String> {
+    let count = count.trim();
+    let (digits, unit) = match count.char_indices().last() {
+        Some((at, unit @ ('s' | 'm' | 'h'))) => (&count[..at], Some(unit)),
+        _ => (count, None),
+    };
+    let n = match digits {
+        "" if unit.is_none() => 1,
+        digits => digits
+            .parse::<u64>()
+            .map_err(|_| format!("'{}' is not a count or a time", count))?,
+    };
+    let secs = match unit {
+        None => {
+            let steps = isize::try_from(n).unwrap_or(isize::MAX);
+            return Ok(UndoMove::Steps(if later { steps } else { -steps }));
+        }
+        Some('s') => n,
+        Some('m') => n.saturating_mul(60),
+        Some(_) => n.saturating_mul(60 * 60),
+    };
+    let time = std::time::Duration::from_secs(secs);
+    Ok(if later {
+        UndoMove::Later(time)
+    } else {
+        UndoMove::Earlier(time)
+    })
+}
+
+/// Whether `path` is big enough to be read in the background.
+async fn is_large(path: &std::path::Path) -> bool {
+    let metadata = tokio::fs::metadata(path).await;
+    metadata.is_ok_and(|metadata| metadata.len() >= LARGE_FILE)
+}
+
+fn register_commands(registry: &mut CommandRegistry) {
+    use editor::EditorCommand::*;
+    use editor::{CursorJump, Direction};
+
+    registry.register("quit", vec!["q"], EntryMode::ALWAYS, Command::Quit);
+    registry.register("help", vec![], EntryMode::ALWAYS, Command::Help);
+    registry.register("version", vec![], EntryMode::ALWAYS, Command::Version);
+    let open_lossy = Command::FileOpenLossy(None, None);
+    registry.register("open-lossy", vec![], EntryMode::ALWAYS, open_lossy);
+    let open_cancel = Command::FileOpenCancel;
+    registry.register("open-cancel", vec![], EntryMode::ALWAYS, open_cancel);
+    registry.register_with_argument("grep", vec![], EntryMode::ALWAYS, Command::Grep);
+    registry.register_with_argument("set", vec![], EntryMode::ALWAYS, Command::Set);
+    registry.register("recover", vec![], EntryMode::ALWAYS, Command::Recover);
+    let reload_config = Command::ConfigReload;
+    registry.register("reload-config", vec![], EntryMode::ALWAYS, reload_config);
+    registry.register_with_path("theme", vec![], EntryMode::ALWAYS, Command::Theme);
+    registry.register("ls", vec!["buffers"], EntryMode::ALWAYS, Command::ListBuffers);
+    let switch = Command::SwitchBuffer;
+    registry.register_with_argument("buffer", vec!["b"], EntryMode::ALWAYS, switch);
+    registry.register_with_path("edit", vec!["e"], EntryMode::ALWAYS, Command::Edit);
+    registry.register_with_path("write", vec!["w"], EntryMode::ALWAYS, Command::Write);
+    let write_force = Command::WriteForce;
+    registry.register_with_path("write!", vec!["w!"], EntryMode::ALWAYS, write_force);
+    registry.register("reload!", vec!["e!"], EntryMode::FOCUSED, Command::Reload);
+    registry.register("diff-disk", vec![], EntryMode::FOCUSED, Command::DiffDisk);
+    registry.register_with_argument("validate", vec![], EntryMode::ALWAYS, Command::Validate);
+    registry.register_with_argument("run", vec![], EntryMode::ALWAYS, Command::Run);
+    registry.register("run-kill", vec![], EntryMode::ALWAYS, Command::RunKill);
+    registry.register("profile", vec![], EntryMode::ALWAYS, Command::Profile);
+    let inspect = Command::InspectHighlight;
+    registry.register("inspect-highlight", vec![], EntryMode::FOCUSED, inspect);
+    registry.register("outline", vec![], EntryMode::FOCUSED, Command::Outline);
+    let export_html = Command::ExportHtml;
+    registry.register_with_argument("export-html", vec![], EntryMode::FOCUSED, export_html);
+    let export_ansi = Command::ExportAnsi;
+    registry.register_with_argument("export-ansi", vec![], EntryMode::FOCUSED, export_ansi);
+    let toggle_comment = Command::ToggleComment;
+    registry.register("toggle-comment", vec![], EntryMode::FOCUSED, toggle_comment);
+    registry.register("format", vec!["fmt"], EntryMode::FOCUSED, Command::Format);
+    let search = |query: String| Command::FocusedEditor(Search(query));
+    registry.register_with_argument("search", vec![], EntryMode::FOCUSED, search);
+    registry.register("split", vec!["sp"], EntryMode::FOCUSED, Command::Split);
+    registry.register("close", vec!["clo"], EntryMode::FOCUSED, Command::Close);
+    registry.register_with_argument("earlier", vec![], EntryMode::FOCUSED, Command::Earlier);
+    registry.register_with_argument("later", vec![], EntryMode::FOCUSED, Command::Later);
+    registry.register("undolist", vec![], EntryMode::FOCUSED, Command::UndoList);
+    registry.register("registers", vec!["reg"], EntryMode::FOCUSED, Command::Registers);
+
+    let cmds = [
+        ("cursor.up", vec![], CursorMove(Direction::Up)),
+        ("cursor.down", vec![], CursorMove(Direction::Down)),
+        ("cursor.left", vec![], CursorMove(Direction::Left)),
+        ("cursor.right", vec![], CursorMove(Direction::Right)),
+        ("cursor.startOfNextWord", vec![], CursorJump(CursorJump::StartOfNextWord)),
+        ("cursor.startOfLastWord", vec![], CursorJump(CursorJump::StartOfLastWord)),
+        ("cursor.startOfNearestWord", vec![], CursorJump(CursorJump::StartOfNearestWord)),
+        ("cursor.endOfNearestWord", vec![], CursorJump(CursorJump::EndOfNearestWord)),
+        ("nohlsearch", vec!["nohl"], ClearSearch),
+    ];
+    for (name, aliases, cmd) in cmds {
+        registry.register(name, aliases, EntryMode::FOCUSED, Command::FocusedEditor(cmd));
+    }
+
+    // commands.reset();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How time passes on a test app's runtime.
+    #[derive(Clone, Copy)]
+    enum Clock {
+        Real,
+        /// Stopped until everything waits on a timer, then skipped ahead to it.
+        Paused,
+    }
+
+    /// An app ready for a test to drive on a current-thread runtime of its own, with a directory
+    /// for the test's files that is removed however the test ends.
+    struct TestApp {
+        rt: tokio::runtime::Runtime,
+        app: App,
+        cmd_tx: mpsc::Sender<Command>,
+        dir: test_support::TempDir,
+    }
+
+    impl TestApp {
+        fn new(clock: Clock) -> Result<Self> {
+            let mut rt = tokio::runtime::Builder::new_current_thread();
+            match clock {
+                Clock::Real => rt.enable_all(),
+                Clock::Paused => rt.enable_time().start_paused(true),
+            };
+            let rt = rt.build()?;
+            let (cmd_tx, cmd_rx) = mpsc::channel(1);
+            let mut app = {
+                let _entered = rt.enter();
+                App::new(AppContext::new()?, cmd_tx.clone(), cmd_rx)
+            };
+            if let Clock::Paused = clock {
+                // nothing takes the app's syntax events, so highlights wait to send; they do so
+                // on threads of their own, as blocking tasks would keep time from advancing
+                app.syntax = Syntax::spawn(|job| drop(std::thread::spawn(job)));
+            }
+            let dir = test_support::temp_dir();
+            Ok(Self { rt, app, cmd_tx, dir })
+        }
+    }
+
+    fn entry_names(registry: &CommandRegistry) -> Vec<&'static str> {
+        let entries = registry.selector.entries.iter();
+        entries.map(|id| registry.entries[*id].name).collect()
+    }
+
+    #[test]
+    fn commands_filtered_by_focused_pane() {
+        let always = vec![
+            "quit",
+            "help",
+            "version",
+            "open-lossy",
+            "open-cancel",
+            "grep",
+            "set",
+            "recover",
+            "reload-config",
+            "theme",
+            "ls",
+            "buffer",
+            "edit",
+            "write",
+            "write!",
+            "validate",
+            "run",
+            "run-kill",
+            "profile",
+        ];
+        let mut state = State::new();
+        assert_eq!(entry_names(&state.command_registry), always);
+
+        let context = state.pane_context();
+        state.command_registry.set_context(context);
+        let names = entry_names(&state.command_registry);
+        assert!(names.contains(&"quit"));
+        // past the most the palette lists at once, so it's looked for by name
+        "cursor.up"
+            .chars()
+            .for_each(|c| state.command_registry.command(selector::Command::Insert(c)));
+        assert!(entry_names(&state.command_registry).contains(&"cursor.up"));
+        state.command_registry.reset();
+
+        state.focus_pane(state.commands_pane_id);
+        let context = state.pane_context();
+        state.command_registry.set_context(context);
+        assert_eq!(entry_names(&state.command_registry), always);
+    }
+
+    #[test]
+    fn alias_prefix_listed_first() {
+        let mut registry = CommandRegistry::new();
+        registry.register("sequence", vec![], EntryMode::ALWAYS, Command::Help);
+        registry.register("quit", vec!["q"], EntryMode::ALWAYS, Command::Quit);
+        registry.reset();
+        registry.command(selector::Command::Insert('q'));
+        assert_eq!(entry_names(&registry), vec!["quit", "sequence"]);
+
+        let (quit, m) = registry.index.results().next().expect("quit matches");
+        assert_eq!(quit.id, registry.selector.entries[0]);
+        assert_eq!(m.matched_text, commands::MatchedText::Alias(0));
+        assert_eq!(m.label_indices("quit", &["q"]).collect::<Vec<_>>(), vec![6]);
+    }
+
+    #[test]
+    fn grep_takes_an_argument() {
+        let mut registry = CommandRegistry::new();
+        register_commands(&mut registry);
+        registry.reset();
+        for c in "grep fn main".chars() {
+            registry.command(selector::Command::Insert(c));
+        }
+        // only commands that take an argument are listed once there is one
+        assert_eq!(entry_names(&registry), vec!["grep"]);
+        assert!(matches!(registry.focused(), Some(Command::Grep(query)) if query == "fn main"));
+
+        registry.reset();
+        "quit now"
+            .chars()
+            .for_each(|c| registry.command(selector::Command::Insert(c)));
+        assert_eq!(entry_names(&registry), Vec::<&str>::new());
+        registry.reset();
+        "grep"
+            .chars()
+            .for_each(|c| registry.command(selector::Command::Insert(c)));
+        assert!(matches!(registry.focused(), Some(Command::Grep(query)) if query.is_empty()));
+    }
+
+    #[test]
+    fn search_until_nohl() {
+        let mut state = State::new();
+        let editor_id = state.default_editor_id;
+        state.restore(state.editors[editor_id].buffer_id, "ab ab\nab");
+        let context = state.pane_context();
+        state.command_registry.set_context(context);
+        let run = |state: &mut State, typed: &str| {
+            state.command_registry.reset();
+            for c in typed.chars() {
+                state.command_registry.command(selector::Command::Insert(c));
+            }
+            let Some(Command::FocusedEditor(command)) = state.command_registry.focused() else {
+                panic!("expected an editor command for {:?}", typed);
+            };
+            state.editor_command(editor_id, command);
+        };
+
+        run(&mut state, "search ab");
+        let editor = &state.editors[editor_id];
+        let buffer = &state.buffers[editor.buffer_id];
+        assert_eq!(editor.search().map(editor::Search::total), Some(3));
+        assert_eq!(editor.search_index(buffer), Some(2));
+        run(&mut state, "nohl");
+        assert!(state.editors[editor_id].search().is_none());
+    }
+
+    #[test]
+    fn inspect_highlight_under_cursor() {
+        let mut state = State::new();
+        let editor_id = state.default_editor_id;
+        let buffer_id = state.editors[editor_id].buffer_id;
+        let text = "fn main() {}\n";
+        state.restore(buffer_id, text);
+        state.editors[editor_id].set_cursor(tore::Point { line: 0, column: 4 });
+        assert_eq!(state.inspect_highlight(), "1:5 (byte 4)\nno highlight\nno syntax tree\n");
+
+        let buffer = &mut state.buffers[buffer_id];
+        let mut highlights = editor::Highlights::new();
+        highlights.insert(0..12, "unknown".to_string());
+        highlights.insert(3..7, "function".to_string());
+        buffer.command(BufferCommand::Highlight(buffer.version, highlights.clone()));
+        let language = syntax::Language::try_from(&*buffer).unwrap();
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(language.ts).unwrap();
+        let tree = parser.parse(text, None).unwrap();
+        let mut syntax = SyntaxState::default();
+        syntax.parsed(buffer.version, tree);
+        state.syntax_states.insert(buffer_id, syntax);
+        let report = state.inspect_highlight();
+        let expected = [
+            "1:5 (byte 4)",
+            "unknown 0..12 -> not in the theme",
+            "function 3..7",
+            "source_file > function_item > identifier",
+        ];
+        assert_eq!(report.lines().collect::<Vec<_>>(), expected);
+
+        state.editors[editor_id].set_cursor(tore::Point { line: 0, column: 3 });
+        highlights.remove(0..12);
+        let buffer = &mut state.buffers[buffer_id];
+        buffer.command(BufferCommand::Highlight(buffer.version, highlights));
+        let report = state.inspect_highlight();
+        assert_eq!(report.lines().nth(1), Some("function 3..7 -> green #b8bb26"));
+    }
+
+    /// Runs `command`, processing what it sends back until it's done. Returns the buffer its
+    /// output went into, and what that held after each command.
+    async fn run_to_end(app: &mut App, command: &str) -> Result<(BufferId, Vec<String>)> {
+        app.process_command(Command::Run(command.to_string()))
+            .await?;
+        let buffer_id = app.state.runs.buffer_id.expect("run has a buffer");
+        let mut outputs = vec![];
+        loop {
+            let command = app.cmd_rx.recv().await.expect("run reports back");
+            let done = matches!(command, Command::RunDone(..));
+            app.process_command(command).await?;
+            outputs.push(app.state.buffers[buffer_id].contents.to_string());
+            if done {
+                return Ok((buffer_id, outputs));
+            }
+        }
+    }
+
+    #[test]
+    fn run_streams_into_preview() -> Result<()> {
+        let TestApp { rt, mut app, .. } = TestApp::new(Clock::Real)?;
+        rt.block_on(async {
+            let (buffer_id, outputs) = run_to_end(&mut app, "echo a; sleep 0.1; echo b").await?;
+            assert_eq!(outputs, ["a\n", "a\nb\n", "a\nb\n"]);
+            assert_eq!(app.state.message.as_deref(), Some("'echo a; sleep 0.1; echo b' finished"));
+            assert!(matches!(app.state.focused_pane(), Pane::Preview(_, id) if id == buffer_id));
+            let buffer = &app.state.buffers[buffer_id];
+            assert!(buffer.read_only && !buffer.dirty);
+            // the preview followed the output down
+            let cursor = app.state.editors[app.state.preview_editor_id].cursor();
+            assert_eq!(cursor.line, 2);
+
+            // another run reuses the open preview, and only one runs at a time
+            let (reused, outputs) = run_to_end(&mut app, "echo c; exit 2").await?;
+            assert_eq!((reused, outputs.last().map(String::as_str)), (buffer_id, Some("c\n")));
+            assert_eq!(
+                app.state.message.as_deref(),
+                Some("'echo c; exit 2' failed with exit code 2")
+            );
+            let (run_id, _kill) = app.state.runs.start("sleep 10".into()).unwrap();
+            app.process_command(Command::Run("echo d".into())).await?;
+            let message = app.state.message.take();
+            assert_eq!(message.as_deref(), Some("'sleep 10' is still running; :run-kill stops it"));
+            app.process_command(Command::RunKill).await?;
+            let done = app.state.runs.done(run_id, &Ok(Default::default()));
+            assert!(done.is_some());
+            app.process_command(Command::RunKill).await?;
+            assert_eq!(app.state.message.as_deref(), Some("nothing is running"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn title_follows_focused_buffer() -> Result<()> {
+        use std::path::Path;
+
+        for (path, dirty, title) in [
+            (Some(Path::new("src/main.rs")), false, "main.rs — toku"),
+            (Some(Path::new("src/main.rs")), true, "main.rs ± — toku"),
+            (Some(Path::new("Makefile")), true, "Makefile ± — toku"),
+            (None, false, "[scratch] — toku"),
+            (None, true, "[scratch] ± — toku"),
+        ] {
+            assert_eq!(window_title(path, dirty), title);
+        }
+
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "fn a() {}\n")?;
+
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            assert_eq!(app.state.title().as_deref(), Some("[scratch] — toku"));
+
+            app.process_command(Command::FileOpen(None, path.clone()))
+                .await?;
+            assert_eq!(app.state.title().as_deref(), Some("a.rs — toku"));
+            let insert = EditorCommand::InsertChar('/');
+            app.process_command(Command::Editor(editor_id, insert))
+                .await?;
+            assert_eq!(app.state.title().as_deref(), Some("a.rs ± — toku"));
+
+            // overlays keep the title of the buffer beneath them, previews have their own
+            app.state.focus_pane(app.state.commands_pane_id);
+            assert_eq!(app.state.title().as_deref(), Some("a.rs ± — toku"));
+            app.process_command(Command::Help).await?;
+            assert_eq!(app.state.title().as_deref(), Some("[scratch] — toku"));
+
+            app.process_command(Command::Set("notitle".into())).await?;
+            assert_eq!(app.state.title(), None);
+            app.process_command(Command::Set("title".into())).await?;
+            assert!(app.state.title().is_some());
+            use ui::GutterColumn::*;
+            app.process_command(Command::Set("number".into())).await?;
+            app.process_command(Command::Set("number".into())).await?;
+            assert_eq!(app.state.gutter, [Signs, Numbers]);
+            app.process_command(Command::Set("nonumber".into())).await?;
+            assert_eq!(app.state.gutter, [Signs]);
+            app.process_command(Command::Set("noautopairs".into()))
+                .await?;
+            assert!(app.state.editors.values().all(|editor| !editor.auto_pairs));
+            app.process_command(Command::Set("regex".into())).await?;
+            let mut modes = app.state.editors.values().map(|editor| editor.search_mode);
+            assert!(modes.all(|mode| mode == SearchMode::Regex));
+            app.process_command(Command::Set("blockcursor".into()))
+                .await?;
+            assert!(app.state.block_cursor);
+            app.process_command(Command::Set("list".into())).await?;
+            app.process_command(Command::Set("listchars=eol:$".into()))
+                .await?;
+            assert!(app.state.list);
+            assert_eq!(app.state.list_chars.eol, Some('$'));
+            app.process_command(Command::Set("listchars=tab:>".into()))
+                .await?;
+            assert_eq!(app.state.message.take().as_deref(), Some("bad listchars entry 'tab:>'"));
+            app.process_command(Command::Set("titel".into())).await?;
+            assert_eq!(app.state.message.take().as_deref(), Some("unknown setting 'titel'"));
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn page_through_commands() {
+        let mut state = State::new();
+        state.focus_pane(state.commands_pane_id);
+        let context = state.pane_context();
+        state.command_registry.set_context(context);
+        // a frame too short to show every command at once
+        state.process_event(Event::Resize(80, 8));
+        let last = state.command_registry.selector.entries.len() - 1;
+        let mut press = |code: Key| {
+            let command = state.process_key(code.into());
+            let Some(Command::Commands(command)) = command else {
+                panic!("expected a selector command, got {:?}", command);
+            };
+            state.command_registry.command(command);
+            state.command_registry.selector.focused_index()
+        };
+        assert_eq!(press(Key::PageDown), Some(3));
+        assert_eq!(press(Key::PageDown), Some(6));
+        assert_eq!(press(Key::PageUp), Some(3));
+        assert_eq!(press(Key::Home), Some(0));
+        assert_eq!(press(Key::End), Some(last));
+    }
+
+    #[test]
+    fn ctrl_c_cancels_pending_keys() {
+        let key = InputKey::char;
+        let ctrl_c = InputKey::ctrl('c');
+        let mut state = State::new();
+        let editor_id = state.default_editor_id;
+
+        assert!(state.process_key(key('g')).is_none());
+        assert_eq!(state.pending, "g");
+        assert!(state.process_key(ctrl_c).is_none());
+        assert_eq!(state.pending, "");
+        assert_eq!(state.message, None);
+        // the cancelled prefix does not combine with the next key
+        assert!(state.process_key(key('g')).is_none());
+        assert_eq!(state.pending, "g");
+        assert!(matches!(
+            state.process_key(key('g')),
+            Some(Command::Editor(id, EditorCommand::CursorJump(editor::CursorJump::StartOfBuffer)))
+                if id == editor_id
+        ));
+
+        // with nothing pending ctrl-c only tells the user how to quit
+        assert!(state.process_key(ctrl_c).is_none());
+        assert_eq!(state.message.as_deref(), Some("type :q to quit"));
+        assert!(state.process_key(key('j')).is_some());
+        assert_eq!(state.message, None);
+    }
+
+    #[test]
+    fn replace_keys() {
+        let key = InputKey::char;
+        let mut state = State::new();
+        let editor_id = state.default_editor_id;
+
+        // `r` takes the next key as the char to replace with, even one bound to a command
+        assert!(state.process_key(key('r')).is_none());
+        assert!(matches!(
+            state.process_key(key('i')),
+            Some(Command::Editor(_, EditorCommand::ReplaceChar('i')))
+        ));
+        assert_eq!(state.editors[editor_id].mode, editor::Mode::Normal);
+
+        let Some(Command::Editor(_, command)) = state.process_key(key('R')) else {
+            panic!("R enters replace mode");
+        };
+        state.editor_command(editor_id, command);
+        assert_eq!(state.editors[editor_id].mode, editor::Mode::Replace);
+        assert!(matches!(
+            state.process_key(key('j')),
+            Some(Command::Editor(_, EditorCommand::InsertChar('j')))
+        ));
+    }
+
+    #[test]
+    fn editing_keys_in_each_mode() {
+        use editor::{CursorJump, Direction, EditorCommand as C};
+
+        let mut state = State::new();
+        let editor_id = state.default_editor_id;
+        // a 24 row terminal, less the status line
+        let page = state.page_lines;
+        assert_eq!(page, 23);
+        // commands don't compare, but what they print does
+        let press = |state: &mut State, code: Key| match state.process_key(code.into()) {
+            Some(Command::Editor(id, command)) if id == editor_id => Some(format!("{:?}", command)),
+            _ => None,
+        };
+        let check = |state: &mut State, cases: Vec<(Key, Option<C>)>| {
+            for (code, expected) in cases {
+                let expected = expected.map(|command| format!("{:?}", command));
+                assert_eq!(
+                    press(state, code),
+                    expected,
+                    "{:?} in {:?}",
+                    code,
+                    state.editors[editor_id].mode
+                );
+            }
+        };
+
+        let start = C::CursorJump(CursorJump::StartOfLine);
+        let end = C::CursorJump(CursorJump::EndOfLine);
+        let up = C::CursorMoveLines(Direction::Up, page);
+        let down = C::CursorMoveLines(Direction::Down, page);
+        let normal = vec![
+            (Key::Enter, Some(C::CursorJump(CursorJump::FirstNonBlankOfNextLine))),
+            (Key::Home, Some(start.clone())),
+            (Key::Char('0'), Some(start.clone())),
+            (Key::End, Some(end.clone())),
+            (Key::Char('$'), Some(end.clone())),
+            (Key::PageUp, Some(up.clone())),
+            (Key::PageDown, Some(down.clone())),
+            (Key::Tab, None),
+            (Key::Delete, None),
+        ];
+        check(&mut state, normal);
+
+        state.editor_command(editor_id, C::SetMode(editor::Mode::Insert));
+        let insert = vec![
+            (Key::Tab, Some(C::InsertIndent)),
+            (Key::Enter, Some(C::InsertNewline)),
+            (Key::Delete, Some(C::DeleteForward)),
+            (Key::Backspace, Some(C::DeleteBackward)),
+            (Key::Home, Some(start)),
+            (Key::End, Some(end)),
+            (Key::PageUp, Some(up)),
+            (Key::PageDown, Some(down)),
+            (Key::Char('0'), Some(C::InsertChar('0'))),
+            (Key::Char('$'), Some(C::InsertChar('$'))),
+        ];
+        check(&mut state, insert);
+
+        let ctrl = |state: &mut State, c| {
+            let key = InputKey::ctrl(c);
+            match state.process_key(key) {
+                Some(Command::Editor(_, command)) => Some(format!("{:?}", command)),
+                _ => None,
+            }
+        };
+        for (c, expected) in [
+            ('h', C::DeleteBackward),
+            ('w', C::DeleteWordBackward),
+            ('u', C::DeleteToInsertStart),
+        ] {
+            assert_eq!(ctrl(&mut state, c), Some(format!("{:?}", expected)));
+        }
+    }
+
+    #[test]
+    fn undo_keys_counts_and_list() {
+        use std::time::Duration;
+
+        let mut state = State::new();
+        let (none, ctrl) = (Modifiers::empty(), Modifiers::CTRL);
+        let mut keys = |keys: &[(Key, Modifiers)]| {
+            let keys = keys
+                .iter()
+                .map(|(code, modifiers)| InputKey::new(*code, *modifiers));
+            let commands: Vec<_> = keys.map(|key| state.process_key(key)).collect();
+            match commands.last() {
+                Some(Some(Command::Editor(_, EditorCommand::Undo(to)))) => Some(*to),
+                _ => None,
+            }
+        };
+        assert_eq!(keys(&[(Key::Char('u'), none)]), Some(UndoMove::Undo));
+        assert_eq!(keys(&[(Key::Char('r'), ctrl)]), Some(UndoMove::Redo));
+        let g = (Key::Char('g'), none);
+        assert_eq!(keys(&[g, (Key::Char('-'), none)]), Some(UndoMove::Steps(-1)));
+        assert_eq!(keys(&[g, (Key::Char('+'), none)]), Some(UndoMove::Steps(1)));
+
+        let minute = Duration::from_secs(60);
+        assert_eq!(undo_move("", false), Ok(UndoMove::Steps(-1)));
+        assert_eq!(undo_move(" 3 ", true), Ok(UndoMove::Steps(3)));
+        assert_eq!(undo_move("10s", false), Ok(UndoMove::Earlier(Duration::from_secs(10))));
+        assert_eq!(undo_move("2m", true), Ok(UndoMove::Later(2 * minute)));
+        assert_eq!(undo_move("1h", false), Ok(UndoMove::Earlier(60 * minute)));
+        assert_eq!(undo_move("h", false), Err("'h' is not a count or a time".into()));
+        assert_eq!(undo_move("5d", true), Err("'5d' is not a count or a time".into()));
+
+        let editor_id = state.default_editor_id;
+        let buffer_id = state.editors[editor_id].buffer_id;
+        state.restore(buffer_id, "abc\n");
+        state.restore(buffer_id, "ab\n");
+        state.editor_command(editor_id, EditorCommand::Undo(UndoMove::Undo));
+        assert_eq!(state.buffers[buffer_id].contents.to_string(), "abc\n");
+        let now = std::time::Instant::now() + Duration::from_secs(90);
+        let list = state.undo_list(editor_id, now);
+        let expected = [
+            "    0            1m ago",
+            ">   1 from 0     1m ago  +4 -0",
+            "    2 from 1     1m ago  +3 -4",
+        ];
+        assert_eq!(list.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn text_object_keys() {
+        use editor::TextObject;
+
+        let key = InputKey::char;
+        let mut state = State::new();
+        let mut keys = |keys: &str| {
+            let commands: Vec<_> = keys.chars().map(|c| state.process_key(key(c))).collect();
+            assert!(commands[..keys.len() - 1].iter().all(Option::is_none), "{}", keys);
+            match commands.last() {
+                Some(Some(Command::Editor(_, command))) => Some(command.clone()),
+                _ => None,
+            }
+        };
+
+        for (typed, object) in [
+            ("viw", TextObject::InnerWord),
+            ("vaW", TextObject::AroundBigWord),
+        ] {
+            let command = keys(typed);
+            assert!(
+                matches!(command, Some(EditorCommand::SelectTextObject(o)) if o == object),
+                "{}",
+                typed
+            );
+        }
+        for (typed, object) in [
+            ("diW", TextObject::InnerBigWord),
+            ("daw", TextObject::AroundWord),
+        ] {
+            let command = keys(typed);
+            assert!(
+                matches!(command, Some(EditorCommand::DeleteTextObject(o)) if o == object),
+                "{}",
+                typed
+            );
+        }
+        // anything else cancels the sequence, and is not taken as a key of its own
+        assert!(keys("dij").is_none());
+        assert!(keys("dx").is_none());
+        assert!(keys("j").is_some());
+    }
+
+    #[test]
+    fn operator_keys() {
+        use editor::{CursorJump, Direction, Motion, Operator};
+
+        let key = InputKey::char;
+        let mut state = State::new();
+        let mut keys = |keys: &str| {
+            let commands: Vec<_> = keys.chars().map(|c| state.process_key(key(c))).collect();
+            assert!(commands[..keys.len() - 1].iter().all(Option::is_none), "{}", keys);
+            match commands.last() {
+                Some(Some(Command::Editor(_, EditorCommand::Operator(op, motion, count)))) => {
+                    Some((*op, format!("{:?}", motion), *count))
+                }
+                _ => None,
+            }
+        };
+
+        let expected = [
+            ("yw", Operator::Yank, Motion::Jump(CursorJump::StartOfNextWord)),
+            ("y$", Operator::Yank, Motion::Jump(CursorJump::EndOfLine)),
+            ("d0", Operator::Delete, Motion::Jump(CursorJump::StartOfLine)),
+            ("dj", Operator::Delete, Motion::Move(Direction::Down)),
+            ("dgg", Operator::Delete, Motion::Jump(CursorJump::StartOfBuffer)),
+            ("ce", Operator::Change, Motion::Jump(CursorJump::EndOfNearestWord)),
+            ("dd", Operator::Delete, Motion::Line),
+            ("yy", Operator::Yank, Motion::Line),
+            ("cc", Operator::Change, Motion::Line),
+        ];
+        for (typed, op, motion) in expected {
+            assert_eq!(keys(typed), Some((op, format!("{:?}", motion), 1)), "{}", typed);
+        }
+        // a doubled key has to be the operator's own, and anything else cancels it
+        assert!(keys("dy").is_none());
+        assert!(keys("cx").is_none());
+    }
+
+    #[test]
+    fn marks_jump_across_buffers() {
+        let key = InputKey::char;
+        let mut state = State::new();
+        let editor_id = state.default_editor_id;
+        let first = state.editors[editor_id].buffer_id;
+        let keys = |state: &mut State, keys: &str| {
+            for c in keys.chars() {
+                if let Some(command) = state.process_key(key(c)) {
+                    match command {
+                        Command::SetMark(editor_id, mark) => state.set_mark(editor_id, mark),
+                        Command::JumpToMark(editor_id, mark, jump) => {
+                            state.jump_to_mark(editor_id, mark, jump)
+                        }
+                        Command::Editor(editor_id, cmd) => {
+                            state.editor_command(editor_id, cmd);
+                        }
+                        command => panic!("unexpected {:?}", command),
+                    }
+                }
+            }
+        };
+
+        state.buffers[first].contents = "a\nbcd\n".into();
+        state.editors[editor_id].set_cursor(tore::Point { line: 1, column: 2 });
+        keys(&mut state, "mAma");
+        let second = state
+            .buffers
+            .insert_with_key(|k| Buffer::new(k, "x\ny\n".into()));
+        state.editors[editor_id].swap_buffer(&state.buffers[second]);
+        state.editors[editor_id].set_cursor(tore::Point { line: 1, column: 0 });
+
+        // a lowercase mark belongs to the buffer it was set in
+        keys(&mut state, "`a");
+        assert_eq!(state.message.take(), Some("mark 'a' is not set".into()));
+        keys(&mut state, "`A");
+        assert_eq!(state.editors[editor_id].buffer_id, first);
+        assert_eq!(state.editors[editor_id].cursor(), tore::Point { line: 1, column: 2 });
+
+        keys(&mut state, "'B");
+        assert_eq!(state.message.take(), Some("mark 'B' is not set".into()));
+        state.global_marks.insert('B', second);
+        state.buffers.remove(second);
+        keys(&mut state, "'B");
+        assert_eq!(state.message.take(), Some("mark 'B' is in a closed buffer".into()));
+        assert_eq!(state.editors[editor_id].buffer_id, first);
+    }
+
+    #[test]
+    fn registers_outlast_buffer_switches() {
+        let key = InputKey::from;
+        let mut state = State::new();
+        let editor_id = state.default_editor_id;
+        let editor_pane = state.focused_pane;
+        let first = state.editors[editor_id].buffer_id;
+        let keys = |state: &mut State, keys: &str| {
+            for c in keys.chars() {
+                match state.process_key(key(Key::Char(c))) {
+                    Some(Command::YankInto(editor_id, name, cmd)) => {
+                        state.yank_into(editor_id, name, cmd)
+                    }
+                    Some(Command::Paste(editor_id, name, before)) => {
+                        state.paste(editor_id, name, before)
+                    }
+                    Some(Command::Editor(editor_id, cmd)) => {
+                        state.editor_command(editor_id, cmd);
+                    }
+                    Some(command) => panic!("unexpected {:?}", command),
+                    None => {}
+                }
+            }
+        };
+
+        state.buffers[first].contents = "one\ntwo\n".into();
+        keys(&mut state, "\"ayjj\"by$");
+        let second = state
+            .buffers
+            .insert_with_key(|k| Buffer::new(k, "x\n".into()));
+        state.editors[editor_id].swap_buffer(&state.buffers[second]);
+        state.editors[editor_id].set_cursor(tore::Point::zero());
+        keys(&mut state, "\"ap\"bP");
+        assert_eq!(state.buffers[second].contents.to_string(), "x\ntwoone\ntwo\n");
+        assert_eq!(state.editors[editor_id].cursor(), tore::Point { line: 1, column: 2 });
+
+        // the special registers can be pasted from but not yanked into
+        keys(&mut state, "\".yy");
+        assert_eq!(state.message.take(), Some("register . is read-only".into()));
+        keys(&mut state, "\"%p");
+        assert_eq!(state.message.take(), Some("register % is empty".into()));
+        keys(&mut state, "\"1p");
+        assert_eq!(state.message.take(), Some("no register 1".into()));
+
+        state.buffers[second].path = Some("src/a.rs".into());
+        keys(&mut state, "ihi");
+        state.editor_command(editor_id, EditorCommand::SetMode(editor::Mode::Normal));
+        state.focus_pane(state.commands_pane_id);
+        for c in "ls".chars() {
+            state.command_registry.command(selector::Command::Insert(c));
+        }
+        assert!(matches!(state.process_key(key(Key::Enter)), Some(Command::ListBuffers)));
+        state.focus_pane(editor_pane);
+
+        let register = |name| state.register(editor_id, name).map(|r| r.text);
+        assert_eq!(register('%'), Ok("src/a.rs".into()));
+        assert_eq!(register('.'), Ok("hi".into()));
+        assert_eq!(register(':'), Ok("ls".into()));
+
+        let list = state.register_list(editor_id);
+        let expected = [
+            "\"\"  c  two",
+            "\"a  l  one⏎two⏎",
+            "\"b  c  two",
+            "\"%  c  src/a.rs",
+            "\".  c  hi",
+            "\":  c  ls",
+        ];
+        assert_eq!(list.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn failed_edit_becomes_message() {
+        let mut state = State::new();
+        let editor_id = state.default_editor_id;
+        let mut changes = state.subscribe();
+
+        state.editors[editor_id].set_cursor(tore::Point { line: 3, column: 7 });
+        state.editor_command(editor_id, EditorCommand::InsertChar('x'));
+        let message = state.message.clone().expect("failed insert is reported");
+        assert!(message.starts_with("cannot insert at 4:8: "), "{}", message);
+        assert!(changes.try_recv().is_err());
+    }
+
+    #[test]
+    fn export_writes_the_range_or_the_selection() {
+        let dir = test_support::temp_dir();
+        let mut state = State::new();
+        let editor_id = state.default_editor_id;
+        state.restore(state.editors[editor_id].buffer_id, "a\nb\nc\n");
+        let out = dir.path().join("out.txt");
+        let exported = |state: &mut State, argument: &str| {
+            state.export(export::Format::Ansi, &format!("{} {}", argument, out.display()));
+            std::fs::read_to_string(&out).ok()
+        };
+
+        assert_eq!(exported(&mut state, "2,$").as_deref(), Some("b\nc\n"));
+        assert_eq!(state.message.take(), Some(format!("exported to {}", out.display())));
+        assert_eq!(exported(&mut state, "").as_deref(), Some("a\nb\nc\n"));
+        let editor = &mut state.editors[editor_id];
+        editor.set_cursor(tore::Point { line: 1, column: 0 });
+        editor.mode = editor::Mode::VisualLine;
+        assert_eq!(exported(&mut state, "").as_deref(), Some("b\n"));
+
+        // a bad range writes nothing
+        std::fs::remove_file(&out).unwrap();
+        assert_eq!(exported(&mut state, "4"), None);
+        assert_eq!(state.message.take(), Some("no line '4'".to_string()));
+        state.export(export::Format::Html, " ");
+        assert_eq!(state.message.take(), Some("expected a path to export to".to_string()));
+    }
+
+    #[test]
+    fn outline_jumps_and_marks_the_way_back() {
+        use syntax::{Symbol, SymbolKind, Symbols};
+
+        let key = InputKey::char;
+        let mut state = State::new();
+        let editor_id = state.default_editor_id;
+        let buffer_id = state.editors[editor_id].buffer_id;
+        let run = |state: &mut State, command| match command {
+            Some(Command::OutlineResults(cmd)) => state.outline.command(cmd),
+            Some(Command::OutlineJump) => state.outline_jump(),
+            Some(Command::JumpToMark(editor_id, mark, jump)) => {
+                state.jump_to_mark(editor_id, mark, jump)
+            }
+            None => {}
+            command => panic!("unexpected {:?}", command),
+        };
+
+        state.open_outline();
+        assert_eq!(state.message.take(), Some("no outline for this buffer".into()));
+
+        let text = "fn a() {}\n/* é */ fn b() {}\n";
+        state.buffers[buffer_id].contents = text.into();
+        let symbol = |name: &str| {
+            let start = text.find(&format!("fn {}", name)).unwrap();
+            let range = start..start + "fn a() {}".len();
+            Symbol { name: name.into(), kind: SymbolKind::Function, range }
+        };
+        let mut syntax = SyntaxState::default();
+        syntax.symbols_found(0, Symbols::new(vec![symbol("a"), symbol("b")]));
+        state.syntax_states.insert(buffer_id, syntax);
+        state.editors[editor_id].set_cursor(tore::Point { line: 0, column: 4 });
+        assert_eq!(state.enclosing_symbol(&state.editors[editor_id]), Some("a"));
+
+        state.open_outline();
+        assert_eq!(state.focused_pane, state.outline_pane_id);
+        for typed in [key('b'), InputKey::from(Key::Enter)] {
+            let command = state.process_key(typed);
+            run(&mut state, command);
+        }
+        // the column counts chars, not the bytes the symbol's range does
+        assert!(!state.visible_panes.contains(&state.outline_pane_id));
+        assert_eq!(state.editors[editor_id].cursor(), tore::Point { line: 1, column: 8 });
+        assert_eq!(state.enclosing_symbol(&state.editors[editor_id]), Some("b"));
+
+        for c in "`'".chars() {
+            let command = state.process_key(key(c));
+            run(&mut state, command);
+        }
+        assert_eq!(state.editors[editor_id].cursor(), tore::Point { line: 0, column: 4 });
+        state.status_symbol = false;
+        assert_eq!(state.enclosing_symbol(&state.editors[editor_id]), None);
+    }
+
+    #[test]
+    fn overlays_close_when_focus_moves() {
+        let key = InputKey::from;
+        let mut state = State::new();
+        let editor_pane = state.focused_pane;
+        let palette = state.commands_pane_id;
+
+        state.focus_pane(palette);
+        for c in "qu".chars() {
+            let Some(Command::Commands(cmd)) = state.process_key(key(Key::Char(c))) else {
+                panic!("typing goes to the palette");
+            };
+            state.command_registry.command(cmd);
+        }
+        assert_eq!(state.command_registry.selector.query.as_str(), "qu");
+
+        // focus moving elsewhere by any means closes the palette and forgets the query
+        state.focus_pane(editor_pane);
+        assert_eq!(state.visible_panes, vec![editor_pane]);
+        state.focus_pane(palette);
+        assert_eq!(state.command_registry.selector.query.as_str(), "");
+        assert!(entry_names(&state.command_registry).contains(&"version"));
+
+        assert!(matches!(
+            state.process_key(key(Key::Esc)),
+            Some(Command::Pane(id, PaneCommand::Close)) if id == palette
+        ));
+    }
+
+    #[test]
+    fn help_opens_preview_and_restores_focus() {
+        let key = InputKey::char;
+        let mut state = State::new();
+        let editor_pane = state.focused_pane;
+        let layout = state.visible_panes.clone();
+
+        // `:help` runs from the commands pane
+        state.focus_pane(state.commands_pane_id);
+        let help = state.help_text();
+        let preview = state.open_preview(&help);
+        assert!(matches!(state.focused_pane(), Pane::Preview(..)));
+        let Pane::Preview(_, buffer_id) = state.panes[preview] else {
+            unreachable!()
+        };
+        let contents = state.buffers[buffer_id].contents.to_string();
+        for entry in state.command_registry.entries() {
+            assert!(contents.contains(&format!(":{}", entry.name)), "{}", contents);
+        }
+        assert!(contents.contains(":quit (q)"), "{}", contents);
+        assert!(contents.contains("gg"), "{}", contents);
+
+        // previews navigate like an editor and are read-only
+        let Some(Command::Editor(id, cmd)) = state.process_key(key('j')) else {
+            panic!("expected a motion");
+        };
+        assert_eq!(id, state.preview_editor_id);
+        state.editor_command(id, cmd);
+        assert_eq!(state.editors[id].cursor().line, 1);
+        assert!(state.process_key(key('i')).is_none());
+
+        let close = state.process_key(key('q'));
+        assert!(matches!(close, Some(Command::Pane(id, PaneCommand::Close)) if id == preview));
+        state.close_focused_pane();
+        assert_eq!(state.focused_pane, editor_pane);
+        assert_eq!(state.visible_panes, layout);
+        assert!(!state.panes.contains_key(preview));
+        assert!(!state.buffers.contains_key(buffer_id));
+    }
+
+    /// Counts draws and keeps the last frame and cursor.
+    #[derive(Clone, Default)]
+    struct MockScreen(std::sync::Arc<std::sync::Mutex<(usize, Option<tui::Buffer>, CursorPoint)>>);
+
+    impl Screen for MockScreen {
+        fn draw(&mut self, state: &State) -> Result<()> {
+            let mut term = tui::Terminal::new(ratatui::backend::TestBackend::new(80, 24))?;
+            let mut cursor = None;
+            term.draw(|frame| cursor = state.draw_frame(frame))?;
+            let mut screen = self.0.lock().unwrap();
+            screen.0 += 1;
+            screen.1 = Some(term.backend().buffer().clone());
+            screen.2 = cursor.expect("cursor must be set").0;
+            Ok(())
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    struct NoopGuard;
+
+    impl TerminalGuard for NoopGuard {
+        fn enter(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn exit(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn coalesce_draws() -> Result<()> {
+        use std::time::Duration;
+
+        let TestApp { rt, app, cmd_tx, .. } = TestApp::new(Clock::Paused)?;
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            let screen = MockScreen::default();
+            let session = terminal::Session::new(Box::new(NoopGuard));
+            let events = futures::stream::pending();
+            let app = tokio::spawn(app.run(screen.clone(), events, session));
+
+            let start = tokio::time::Instant::now();
+            for _ in 0..500 {
+                let insert = EditorCommand::InsertChar('x');
+                cmd_tx.send(Command::Editor(editor_id, insert)).await?;
+            }
+            // let the last edits reach the screen
+            tokio::time::sleep(FRAME_INTERVAL * 2).await;
+            let ticks = (start.elapsed().as_millis() / FRAME_INTERVAL.as_millis()) as usize;
+
+            {
+                let screen = screen.0.lock().unwrap();
+                let (draws, frame, cursor) = &*screen;
+                assert!(*draws <= ticks + 1, "{} draws in {} ticks", draws, ticks);
+                let frame = frame.as_ref().expect("drawn at least once");
+                let row: String = (0..80).map(|x| frame.get(x, 0).symbol.as_str()).collect();
+                assert_eq!(row, "x".repeat(79) + " ");
+                assert_eq!(*cursor, CursorPoint { x: 79, y: 0 });
+            }
+
+            // nothing changed, so idling draws nothing
+            let draws = screen.0.lock().unwrap().0;
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            assert_eq!(screen.0.lock().unwrap().0, draws);
+
+            cmd_tx.send(Command::Quit).await?;
+            app.await??;
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    /// Draws like `MockScreen`, but taking `delay` over it, and keeps how long the last input
+    /// took as each frame saw it.
+    #[derive(Clone, Default)]
+    struct SlowScreen {
+        delay: std::time::Duration,
+        screen: MockScreen,
+        seen: std::sync::Arc<std::sync::Mutex<Vec<Option<crate::latency::Sample>>>>,
+    }
+
+    impl Screen for SlowScreen {
+        fn draw(&mut self, state: &State) -> Result<()> {
+            std::thread::sleep(self.delay);
+            self.seen.lock().unwrap().push(state.latency.last());
+            self.screen.draw(state)
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn inputs_timed_to_their_frame() -> Result<()> {
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+        use std::time::Duration;
+
+        let TestApp { rt, app, cmd_tx, .. } = TestApp::new(Clock::Paused)?;
+        rt.block_on(async {
+            let delay = Duration::from_millis(20);
+            let screen = SlowScreen { delay, ..Default::default() };
+            let session = terminal::Session::new(Box::new(NoopGuard));
+            let (event_tx, events) = futures::channel::mpsc::unbounded();
+            let app = tokio::spawn(app.run(screen.clone(), events, session));
+
+            // each key gets a frame of its own, and so a sample
+            for _ in 0..3 {
+                let key = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE);
+                event_tx.unbounded_send(Ok(Event::Key(key)))?;
+                tokio::time::sleep(FRAME_INTERVAL * 2).await;
+            }
+            cmd_tx.send(Command::Profile).await?;
+            tokio::time::sleep(FRAME_INTERVAL * 2).await;
+
+            {
+                let seen = screen.seen.lock().unwrap();
+                let last = seen.last().copied().flatten().expect("an input was timed");
+                // the draw is where the time went; tokio's clock, stepped by the test, isn't
+                // what inputs are timed by, so waiting for the frame took next to none of it
+                assert!(last.draw >= delay, "{:?}", last);
+                assert!(last.input + last.apply + last.wait < delay, "{:?}", last);
+                assert!(last.total() >= last.draw);
+
+                let frame = screen.screen.0.lock().unwrap();
+                let frame = frame.1.as_ref().expect("drawn at least once");
+                let row: String = (0..80).map(|x| frame.get(x, 0).symbol.as_str()).collect();
+                assert!(row.starts_with("the last 3 inputs"), "{:?}", row);
+            }
+
+            cmd_tx.send(Command::Quit).await?;
+            app.await??;
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn smooth_scroll_steps_and_snaps() -> Result<()> {
+        use editor::Direction;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()?;
+        rt.block_on(async {
+            let mut state = State::new();
+            let editor_id = state.default_editor_id;
+            let buffer_id = state.editors[editor_id].buffer_id;
+            let text = (0..1000)
+                .map(|n| format!("line {}\n", n))
+                .collect::<Vec<_>>()
+                .concat();
+            state.restore(buffer_id, &text);
+            state.follow_scrolls(now());
+            let top = |state: &State| {
+                let editor = &state.editors[editor_id];
+                ui::scroll_top(editor.cursor().line, state.page_lines)
+            };
+            let first_row = |state: &State| {
+                let mut term = tui::Terminal::new(ratatui::backend::TestBackend::new(80, 24))?;
+                term.draw(|frame| {
+                    state.draw_frame(frame);
+                })?;
+                let buffer = term.backend().buffer();
+                let row: String = (0..80).map(|x| buffer.get(x, 0).symbol.as_str()).collect();
+                Ok::<String, anyhow::Error>(row.trim().to_string())
+            };
+
+            // off, a jump is drawn all at once
+            let down = EditorCommand::CursorMoveLines(Direction::Down, 500);
+            state.editor_command(editor_id, down.clone());
+            state.follow_scrolls(now());
+            assert!(!state.is_scrolling());
+            let from = top(&state);
+
+            state.smooth_scroll = true;
+            state.editor_command(editor_id, down);
+            state.follow_scrolls(now());
+            let to = top(&state);
+            assert!(state.is_scrolling());
+            let mut tops = vec![];
+            loop {
+                let shown = state.editors[editor_id].shown_top(now());
+                let shown = shown.expect("drawn partway while scrolling");
+                assert_eq!(first_row(&state)?, format!("line {}", shown));
+                tops.push(shown);
+                state.finish_scrolls(now(), false);
+                if !state.is_scrolling() {
+                    break;
+                }
+                tokio::time::advance(FRAME_INTERVAL).await;
+            }
+            assert_eq!(tops.first(), Some(&from));
+            assert_eq!(tops.last(), Some(&to));
+            assert!(tops.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", tops);
+            assert!(tops.len() > 2, "{:?}", tops);
+            assert_eq!(first_row(&state)?, format!("line {}", to));
+
+            // a key pressed partway lands the scroll before it's handled
+            let up = EditorCommand::CursorMoveLines(Direction::Up, 500);
+            state.editor_command(editor_id, up);
+            state.follow_scrolls(now());
+            tokio::time::advance(FRAME_INTERVAL).await;
+            let shown = state.editors[editor_id].shown_top(now());
+            assert!(shown.is_some_and(|shown| top(&state) < shown && shown < to));
+            state.process_key(InputKey::char('j'));
+            assert!(!state.is_scrolling());
+            assert_eq!(state.editors[editor_id].shown_top(now()), None);
+            assert_eq!(first_row(&state)?, format!("line {}", top(&state)));
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn quit_asks_about_unsaved_changes() -> Result<()> {
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+        use futures::StreamExt;
+
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Paused)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "fn a() {}\n")?;
+
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            let editor_pane = app.state.focused_pane;
+            app.process_command(Command::FileOpen(None, path.clone()))
+                .await?;
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+            for cmd in [
+                EditorCommand::SetMode(editor::Mode::Insert),
+                EditorCommand::InsertChar('x'),
+                EditorCommand::SetMode(editor::Mode::Normal),
+            ] {
+                app.process_command(Command::Editor(editor_id, cmd)).await?;
+            }
+
+            app.process_command(Command::Quit).await?;
+            assert!(matches!(app.state.focused_pane(), Pane::Prompt(..)));
+            assert_eq!(app.state.prompt.question(), "Quit without saving a.rs?");
+
+            // cancelling leaves everything as it was
+            let cancel = app.state.process_key(key(KeyCode::Esc).into());
+            app.process_command(cancel.expect("Esc closes the prompt"))
+                .await?;
+            assert_eq!(app.state.visible_panes, vec![editor_pane]);
+            let buffer = &app.state.buffers[buffer_id];
+            assert!(buffer.dirty);
+            assert_eq!(buffer.contents.to_string(), "xfn a() {}\n");
+            assert_eq!(std::fs::read_to_string(&path)?, "fn a() {}\n");
+
+            // no is no different
+            app.process_command(Command::Quit).await?;
+            let no = app.state.process_key(key(KeyCode::Char('n')).into());
+            app.process_command(no.expect("n answers the prompt"))
+                .await?;
+            assert_eq!(app.state.visible_panes, vec![editor_pane]);
+
+            // a yes from the keyboard quits
+            app.process_command(Command::Quit).await?;
+            let yes = futures::stream::iter([Ok(Event::Key(key(KeyCode::Char('y'))))]);
+            let events = yes.chain(futures::stream::pending());
+            let session = terminal::Session::new(Box::new(NoopGuard));
+            let app = tokio::spawn(app.run(MockScreen::default(), events, session));
+            tokio::time::timeout(FRAME_INTERVAL * 10, app).await???;
+            assert_eq!(std::fs::read_to_string(&path)?, "fn a() {}\n");
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn dangerous_commands_ask_first() -> Result<()> {
+        let dir = test_support::temp_dir();
+        let (own, other) = (dir.path().join("own.rs"), dir.path().join("other.rs"));
+        std::fs::write(&other, "")?;
+
+        let mut state = State::new();
+        assert_eq!(state.confirmation(&Command::Quit), None);
+        let buffer_id = state.editors[state.default_editor_id].buffer_id;
+        state.buffers[buffer_id].path = Some(own.clone());
+        std::fs::write(&own, "")?;
+
+        let write = |path: &std::path::Path| Command::Write(Some(path.to_path_buf()));
+        assert_eq!(state.confirmation(&write(&own)), None);
+        assert_eq!(state.confirmation(&write(&dir.path().join("new.rs"))), None);
+        let overwrite = format!("Overwrite {}?", other.display());
+        assert_eq!(state.confirmation(&write(&other)), Some(overwrite));
+
+        assert_eq!(state.confirmation(&Command::RunKill), None);
+        let _run = state.runs.start("make".into());
+        assert_eq!(state.confirmation(&Command::RunKill).as_deref(), Some("Kill 'make'?"));
+        state.buffers[buffer_id].dirty = true;
+        let quit = state.confirmation(&Command::Quit);
+        assert_eq!(quit.as_deref(), Some("Quit without saving own.rs and killing 'make'?"));
+        Ok(())
+    }
+
+    #[test]
+    fn open_multiple_paths() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let paths: Vec<_> = ["a.rs", "b.rs", "c.rs"]
+            .iter()
+            .map(|name| {
+                let path = dir.path().join(name);
+                std::fs::write(&path, format!("// {}\n", name)).expect("write fixture");
+                path
+            })
+            .collect();
+
+        rt.block_on(async {
+            let startup = Startup { paths: paths.clone(), ..Default::default() };
+            for command in App::startup_commands(startup, OpenLayout::Buffers) {
+                app.process_command(command).await?;
+            }
+
+            for path in paths.iter() {
+                let opened = app
+                    .state
+                    .buffers
+                    .values()
+                    .filter(|b| b.path.as_ref() == Some(path));
+                assert_eq!(opened.count(), 1, "{:?} not opened", path);
+            }
+
+            let editor = &app.state.editors[app.state.default_editor_id];
+            let visible = &app.state.buffers[editor.buffer_id];
+            assert_eq!(visible.path.as_ref(), Some(&paths[0]));
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn open_paths_in_splits() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let paths: Vec<_> = ["a.rs", "b.rs", "c.rs"]
+            .iter()
+            .map(|name| {
+                let path = dir.path().join(name);
+                std::fs::write(&path, format!("// {}\n", name)).expect("write fixture");
+                path
+            })
+            .collect();
+
+        rt.block_on(async {
+            let first_pane = app.state.focused_pane;
+            let jump = Some(StartupJump::Line(2));
+            let startup = Startup { paths: paths.clone(), jump, ..Default::default() };
+            for command in App::startup_commands(startup, OpenLayout::Splits) {
+                app.process_command(command).await?;
+            }
+
+            // a pane per path, the first on top with the jump, the last under them all
+            assert_eq!(app.state.visible_panes.len(), 3);
+            assert_eq!(app.state.visible_panes[0], first_pane);
+            let shown = |app: &App| {
+                let editor = &app.state.editors[app.state.focused_editor_id()];
+                let path = app.state.buffers[editor.buffer_id].path.clone();
+                (path, editor.cursor().line)
+            };
+            assert_eq!(shown(&app), (Some(paths[0].clone()), 1));
+            app.process_command(Command::Close).await?;
+            assert_eq!(shown(&app), (Some(paths[1].clone()), 0));
+            app.process_command(Command::Close).await?;
+            assert_eq!(shown(&app), (Some(paths[2].clone()), 0));
+            assert_eq!(app.state.visible_panes, vec![first_pane]);
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn closing_panes_drops_their_editors_and_saved_buffers() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let (a, b) = (dir.path().join("a.rs"), dir.path().join("b.rs"));
+        std::fs::write(&a, "// a\n")?;
+        std::fs::write(&b, "// b\n")?;
+
+        rt.block_on(async {
+            let (first, first_pane) = (app.state.default_editor_id, app.state.focused_pane);
+            let editors = app.state.editors.len();
+
+            // a file opened in a split, split again, is shown by both
+            app.process_command(Command::Split).await?;
+            app.process_command(Command::FileOpen(None, a.clone()))
+                .await?;
+            app.process_command(Command::Split).await?;
+            let (split, a_id) = (app.state.default_editor_id, app.state.visible_buffer_id());
+            let a_id = a_id.expect("a.rs is shown");
+            assert_eq!(app.state.editors[split].buffer_id, a_id);
+            assert_eq!(app.state.editors.len(), editors + 2);
+            assert_eq!(app.state.buffer_refs[a_id], 2);
+
+            // closing one leaves it to the other, closing both drops it
+            app.process_command(Command::Close).await?;
+            assert!(!app.state.editors.contains_key(split));
+            assert_eq!(app.state.buffer_refs[a_id], 1);
+            app.process_command(Command::Close).await?;
+            assert_eq!(app.state.editors.len(), editors);
+            assert!(!app.state.buffers.contains_key(a_id));
+            assert!(!app.state.syntax_states.contains_key(a_id));
+            assert_eq!(app.state.visible_panes, vec![first_pane]);
+            assert_eq!(app.state.default_editor_id, first);
+
+            // the last editor stays
+            app.process_command(Command::Close).await?;
+            assert_eq!(app.state.message.take(), Some("can't close the last editor".into()));
+            assert_eq!(app.state.visible_panes, vec![first_pane]);
+
+            // an unsaved buffer is kept, hidden, when its last editor closes
+            app.process_command(Command::Split).await?;
+            app.process_command(Command::FileOpen(None, b.clone()))
+                .await?;
+            let split = app.state.default_editor_id;
+            for cmd in [
+                EditorCommand::SetMode(editor::Mode::Insert),
+                EditorCommand::InsertChar('x'),
+                EditorCommand::SetMode(editor::Mode::Normal),
+            ] {
+                app.process_command(Command::Editor(split, cmd)).await?;
+            }
+            let b_id = app.state.editors[split].buffer_id;
+            app.process_command(Command::Close).await?;
+            assert!(!app.state.editors.contains_key(split));
+            assert_eq!(app.state.buffer_refs[b_id], 0);
+            let listed = app.state.buffer_list(first, dir.path());
+            assert_eq!(listed.lines().nth(1), Some(r#"  3  h + "b.rs" 2 lines rust"#));
+            app.process_command(Command::SwitchBuffer("3".into()))
+                .await?;
+            assert_eq!(app.state.editors[first].buffer_id, b_id);
+            assert_eq!(app.state.buffers[b_id].contents.to_string(), "x// b\n");
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn diff_scrolls_sides_together() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let paths = vec![dir.path().join("a.txt"), dir.path().join("b.txt")];
+        std::fs::write(&paths[0], "a\nb\nd\ne\n")?;
+        std::fs::write(&paths[1], "a\nB\nc\nd\ne\n")?;
+
+        rt.block_on(async {
+            let startup = Startup { paths: paths.clone(), ..Default::default() };
+            for command in App::startup_commands(startup, OpenLayout::Diff) {
+                app.process_command(command).await?;
+            }
+            let Pane::Diff(pane_id, group) = app.state.focused_pane() else {
+                panic!("no diff open");
+            };
+            let [left, right] = app.state.diffs[group].editors;
+            for (editor_id, path) in [left, right].into_iter().zip(&paths) {
+                let buffer = &app.state.buffers[app.state.editors[editor_id].buffer_id];
+                assert_eq!(buffer.path.as_ref(), Some(path));
+                assert!(buffer.read_only);
+            }
+
+            let lines = |app: &App| [left, right].map(|id| app.state.editors[id].cursor().line);
+            let down = EditorCommand::CursorMove(editor::Direction::Down);
+            app.process_command(Command::FocusedEditor(down.clone()))
+                .await?;
+            assert_eq!(lines(&app), [1, 1]);
+            // the left's `d` is across from the right's, past a line only the right has
+            app.process_command(Command::FocusedEditor(down)).await?;
+            assert_eq!(lines(&app), [2, 3]);
+
+            // scrolling the right to its `c` puts the left on the line below the filler
+            app.process_command(Command::Pane(pane_id, PaneCommand::SwitchSide))
+                .await?;
+            assert_eq!(app.state.focused_editor_id(), right);
+            let up = EditorCommand::CursorMove(editor::Direction::Up);
+            app.process_command(Command::FocusedEditor(up)).await?;
+            assert_eq!(lines(&app), [2, 2]);
+            let page_up = EditorCommand::CursorMoveLines(editor::Direction::Up, 10);
+            app.process_command(Command::FocusedEditor(page_up)).await?;
+            assert_eq!(lines(&app), [0, 0]);
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn focus_pauses_diffs_and_checks_files() -> Result<()> {
+        use std::time::{Duration, SystemTime};
+
+        let TestApp { rt, mut app, cmd_tx, dir, .. } = TestApp::new(Clock::Paused)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "fn a() {}\n")?;
+
+        rt.block_on(async {
+            let delay = Duration::from_millis(100);
+            tokio::spawn(events::diff_changed(app.state.subscribe(), cmd_tx, delay));
+
+            let editor_id = app.state.default_editor_id;
+            app.process_command(Command::FileOpen(None, path.clone()))
+                .await?;
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+            app.process_command(Command::FocusChanged(false)).await?;
+            assert!(!app.state.app_focused);
+            for cmd in [
+                EditorCommand::SetMode(editor::Mode::Insert),
+                EditorCommand::InsertChar('x'),
+            ] {
+                app.process_command(Command::Editor(editor_id, cmd)).await?;
+            }
+
+            // nothing is diffed while the app is out of focus, however long it's away
+            tokio::time::sleep(delay * 10).await;
+            assert!(app.cmd_rx.try_recv().is_err());
+
+            // something else wrote the file meanwhile, over the unsaved change
+            std::fs::write(&path, "fn b() {}\n")?;
+            let file = std::fs::File::options().write(true).open(&path)?;
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000))?;
+            app.process_command(Command::FocusChanged(true)).await?;
+            let conflict = format!("a.rs changed on disk and here; {}", RESOLVE);
+            assert_eq!(app.state.message.take(), Some(conflict));
+            assert_eq!(app.state.buffers[buffer_id].disk_state, DiskState::Conflicted);
+            let diff = tokio::time::timeout(delay * 2, app.cmd_rx.recv()).await?;
+            assert!(matches!(diff, Some(Command::BufferDiff(id)) if id == buffer_id));
+
+            // and it's only said once
+            app.process_command(Command::FocusChanged(true)).await?;
+            assert_eq!(app.state.message, None);
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn conflicts_with_changes_on_disk() -> Result<()> {
+        use std::time::{Duration, SystemTime};
+
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "fn a() {}\n")?;
+        // as something else writes it, moving its time on however coarse the clock
+        let write_elsewhere = |text: &str, secs: u64| -> std::io::Result<()> {
+            std::fs::write(&path, text)?;
+            let file = std::fs::File::options().write(true).open(&path)?;
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+        };
+
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            app.process_command(Command::FileOpen(None, path.clone()))
+                .await?;
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+            let command = |app: &App, line: &str| app.state.command_registry.parse(line).unwrap();
+            let insert = |c| {
+                [
+                    EditorCommand::SetMode(editor::Mode::Insert),
+                    EditorCommand::InsertChar(c),
+                    EditorCommand::SetMode(editor::Mode::Normal),
+                ]
+                .map(|cmd| Command::Editor(editor_id, cmd))
+            };
+
+            // only touched, it reads the same, so saving goes ahead
+            let file = std::fs::File::options().write(true).open(&path)?;
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000))?;
+            app.process_command(Command::FocusChanged(true)).await?;
+            assert_eq!(app.state.message, None);
+            app.process_command(command(&app, "w")).await?;
+            assert_eq!(app.state.message, None);
+
+            // changed while clean, then edited here too
+            write_elsewhere("fn b() {}\n", 2_000_000)?;
+            app.process_command(Command::FocusChanged(true)).await?;
+            assert_eq!(app.state.message.take().as_deref(), Some("a.rs changed on disk"));
+            assert_eq!(app.state.buffers[buffer_id].disk_state, DiskState::Changed);
+            for cmd in insert('x') {
+                app.process_command(cmd).await?;
+            }
+            assert_eq!(app.state.buffers[buffer_id].disk_state, DiskState::Conflicted);
+
+            // :w won't write over it
+            app.process_command(command(&app, "w")).await?;
+            let blocked = format!("not saved: a.rs changed on disk; {}", RESOLVE);
+            assert_eq!(app.state.message.take(), Some(blocked.clone()));
+            assert_eq!(std::fs::read_to_string(&path)?, "fn b() {}\n");
+            assert!(app.state.buffers[buffer_id].dirty);
+
+            // :diff-disk shows what's on disk, read-only, by the buffer, which can still be edited
+            app.process_command(command(&app, "diff-disk")).await?;
+            let Pane::Diff(_, group) = app.state.focused_pane() else {
+                panic!("no diff open");
+            };
+            let view = &app.state.diffs[group];
+            assert_eq!(view.focused_editor_id(), view.editors[1]);
+            let [disk, own] = view
+                .editors
+                .map(|id| &app.state.buffers[app.state.editors[id].buffer_id]);
+            assert_eq!(disk.contents.to_string(), "fn b() {}\n");
+            assert!(disk.read_only);
+            assert_eq!(own.id, buffer_id);
+            assert!(!own.read_only);
+            app.process_command(Command::Close).await?;
+
+            // :w! writes over it, and what's on disk is as it was saved
+            app.process_command(command(&app, "w!")).await?;
+            assert_eq!(app.state.message, None);
+            assert_eq!(std::fs::read_to_string(&path)?, "xfn a() {}\n");
+            let buffer = &app.state.buffers[buffer_id];
+            assert!(!buffer.dirty);
+            assert_eq!(buffer.disk_state, DiskState::Unchanged);
+            let disk = buffer.disk.expect("saved file's stamp");
+            assert_eq!(disk.hash, buffer.contents.content_hash());
+            assert_eq!(disk.len, "xfn a() {}\n".len() as u64);
+
+            // edited, then changed on disk unnoticed until saving
+            for cmd in insert('y') {
+                app.process_command(cmd).await?;
+            }
+            write_elsewhere("fn c() {}\n", 3_000_000)?;
+            app.process_command(command(&app, "w")).await?;
+            assert_eq!(app.state.message.take(), Some(blocked));
+            assert_eq!(app.state.buffers[buffer_id].disk_state, DiskState::Conflicted);
+
+            // :reload! takes what's on disk, in an edit that can be undone
+            app.process_command(command(&app, "reload!")).await?;
+            let buffer = &app.state.buffers[buffer_id];
+            assert_eq!(buffer.contents.to_string(), "fn c() {}\n");
+            assert!(!buffer.dirty);
+            assert_eq!(buffer.disk_state, DiskState::Unchanged);
+            let undo = EditorCommand::Undo(UndoMove::Undo);
+            app.process_command(Command::Editor(editor_id, undo))
+                .await?;
+            assert_eq!(app.state.buffers[buffer_id].contents.to_string(), "yxfn a() {}\n");
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn emit_events_and_auto_save() -> Result<()> {
+        use std::time::Duration;
+        use tokio::sync::broadcast::error::TryRecvError;
+
+        let TestApp { rt, mut app, cmd_tx, dir, .. } = TestApp::new(Clock::Paused)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "fn a() {}\n")?;
+
+        rt.block_on(async {
+            let mut events = app.state.subscribe();
+            let delay = Duration::from_secs(2);
+            tokio::spawn(events::auto_save(app.state.subscribe(), cmd_tx, delay));
+
+            let editor_id = app.state.default_editor_id;
+            app.process_command(Command::FileOpen(None, path.clone()))
+                .await?;
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+            for cmd in [
+                EditorCommand::SetMode(editor::Mode::Insert),
+                EditorCommand::InsertChar('/'),
+                EditorCommand::InsertChar('/'),
+                EditorCommand::SetMode(editor::Mode::Normal),
+            ] {
+                app.process_command(Command::Editor(editor_id, cmd)).await?;
+            }
+
+            let mut emitted = vec![];
+            loop {
+                match events.try_recv() {
+                    Ok(event) => emitted.push(event),
+                    Err(TryRecvError::Empty) => break,
+                    Err(err) => panic!("{:?}", err),
+                }
+            }
+            assert_eq!(
+                emitted,
+                vec![
+                    AppEvent::BufferOpened(buffer_id, path.clone()),
+                    AppEvent::ModeChanged(editor_id, editor::Mode::Insert),
+                    AppEvent::BufferChanged(buffer_id, 1, 0..1),
+                    AppEvent::BufferChanged(buffer_id, 2, 1..2),
+                    AppEvent::ModeChanged(editor_id, editor::Mode::Normal),
+                ]
+            );
+
+            // nothing is saved until the buffer has been quiet for the whole delay
+            tokio::time::sleep(Duration::from_millis(1500)).await;
+            assert!(app.cmd_rx.try_recv().is_err());
+            // leaving insert mode put the cursor back on the second slash
+            let right = EditorCommand::CursorMove(editor::Direction::Right);
+            app.process_command(Command::Editor(editor_id, right))
+                .await?;
+            app.process_command(Command::Editor(editor_id, EditorCommand::InsertChar(' ')))
+                .await?;
+            tokio::time::sleep(Duration::from_millis(1500)).await;
+            assert!(app.cmd_rx.try_recv().is_err());
+            assert_eq!(std::fs::read_to_string(&path)?, "fn a() {}\n");
+
+            let save = tokio::time::timeout(delay, app.cmd_rx.recv()).await?;
+            let Some(save @ Command::BufferSave(id)) = save else {
+                panic!("expected a save, got {:?}", save);
+            };
+            assert_eq!(id, buffer_id);
+            app.process_command(save).await?;
+            assert_eq!(std::fs::read_to_string(&path)?, "// fn a() {}\n");
+            assert!(!app.state.buffers[buffer_id].dirty);
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn gutter_marks_edited_lines() -> Result<()> {
+        use editor::{Direction, Mode};
+
+        let TestApp { rt, mut app, cmd_tx, dir, .. } = TestApp::new(Clock::Paused)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "0\n1\n2\n3\n4\n5\n")?;
+
+        rt.block_on(async {
+            tokio::spawn(events::diff_changed(app.state.subscribe(), cmd_tx, LINE_DIFF_DELAY));
+
+            let editor_id = app.state.default_editor_id;
+            app.process_command(Command::FileOpen(None, path.clone()))
+                .await?;
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+            for down in [0, 2, 3] {
+                let mut cmds = vec![EditorCommand::CursorMove(Direction::Down); down];
+                cmds.extend([
+                    EditorCommand::SetMode(Mode::Insert),
+                    EditorCommand::InsertChar('x'),
+                    EditorCommand::SetMode(Mode::Normal),
+                ]);
+                for cmd in cmds {
+                    app.process_command(Command::Editor(editor_id, cmd)).await?;
+                }
+            }
+            let contents = app.state.buffers[buffer_id].contents.to_string();
+            assert_eq!(contents, "x0\n1\nx2\n3\n4\nx5\n");
+
+            let gutter = |state: &State| -> Result<Vec<String>> {
+                let mut term = tui::Terminal::new(ratatui::backend::TestBackend::new(20, 10))?;
+                term.draw(|frame| {
+                    state.draw_frame(frame);
+                })?;
+                let buf = term.backend().buffer();
+                Ok((0..7).map(|y| buf.get(0, y).symbol.clone()).collect())
+            };
+
+            // the diff is asked for once the buffer settles, and comes back from the background
+            let diff = app.cmd_rx.recv().await;
+            assert!(matches!(diff, Some(Command::BufferDiff(id)) if id == buffer_id));
+            app.process_command(diff.unwrap()).await?;
+            let changes = app.cmd_rx.recv().await;
+            assert!(matches!(changes, Some(Command::SetLineDiff(id, 3, _)) if id == buffer_id));
+            app.process_command(changes.unwrap()).await?;
+            assert_eq!(gutter(&app.state)?, ["▎", " ", "▎", " ", " ", "▎", " "]);
+
+            // saving clears them
+            app.process_command(Command::BufferSave(buffer_id)).await?;
+            assert_eq!(gutter(&app.state)?, [" "; 7]);
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn language_servers_sync_and_report_diagnostics() -> Result<()> {
+        use editor::{Diagnostic, Mode, Severity};
+
+        let TestApp { rt, mut app, cmd_tx, dir, .. } = TestApp::new(Clock::Paused)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "fn a() {}\n")?;
+
+        rt.block_on(async {
+            tokio::spawn(events::sync_documents(app.state.subscribe(), cmd_tx, LSP_DELAY));
+
+            let editor_id = app.state.default_editor_id;
+            app.process_command(Command::FileOpen(None, path.clone()))
+                .await?;
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+            // opening a file syncs it right away
+            let sync = app.cmd_rx.recv().await;
+            assert!(matches!(sync, Some(Command::LspSync(id)) if id == buffer_id));
+            // with no server for its language, that's all
+            app.process_command(sync.unwrap()).await?;
+
+            for cmd in [
+                EditorCommand::SetMode(Mode::Insert),
+                EditorCommand::InsertChar('x'),
+                EditorCommand::InsertChar('y'),
+                EditorCommand::SetMode(Mode::Normal),
+            ] {
+                app.process_command(Command::Editor(editor_id, cmd)).await?;
+            }
+            // and editing it, once it settles
+            let sync = app.cmd_rx.recv().await;
+            assert!(matches!(sync, Some(Command::LspSync(id)) if id == buffer_id));
+            assert!(app.cmd_rx.try_recv().is_err());
+
+            let version = app.state.buffers[buffer_id].version;
+            let diagnostic = |message: &str| Diagnostic {
+                lines: 0..1,
+                severity: Severity::Warning,
+                message: message.into(),
+            };
+            let stale = vec![diagnostic("stale")];
+            let set = Command::SetDiagnostics(path.clone(), Some(version - 1), stale);
+            app.process_command(set).await?;
+            assert!(app.state.buffers[buffer_id].diagnostics().is_empty());
+            let current = vec![diagnostic("current")];
+            let set = Command::SetDiagnostics(path.clone(), Some(version), current.clone());
+            app.process_command(set).await?;
+            assert_eq!(app.state.buffers[buffer_id].diagnostics(), current);
+            // a server that doesn't say which version is taken at its word
+            let unversioned = vec![diagnostic("unversioned")];
+            let set = Command::SetDiagnostics(path.clone(), None, unversioned.clone());
+            app.process_command(set).await?;
+            assert_eq!(app.state.buffers[buffer_id].diagnostics(), unversioned);
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn format_edits_only_the_lines_that_change() -> Result<()> {
+        use editor::{Direction, Point, UndoMove};
+
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "FN A() {\n    x\n}\nx\n")?;
+        let argv = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            app.process_command(Command::FileOpen(None, path.clone()))
+                .await?;
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+            let contents = |app: &App| app.state.buffers[buffer_id].contents.to_string();
+
+            // without a formatter for the language, nothing's run
+            app.process_command(Command::Format).await?;
+            assert_eq!(app.state.message.as_deref(), Some("no formatter for this buffer"));
+
+            // one that uppercases everything only changes the lines that aren't already
+            app.formatters
+                .insert("rust".into(), argv(&["tr", "a-z", "A-Z"]));
+            app.state.editors[editor_id].set_cursor(Point { line: 2, column: 0 });
+            app.state.set_mark(editor_id, 'a');
+            app.state.editors[editor_id].set_cursor(Point { line: 3, column: 0 });
+            app.process_command(Command::Format).await?;
+            let formatted = app.cmd_rx.recv().await.expect("formatted");
+            assert!(matches!(formatted, Command::Formatted(id, _, Ok(_)) if id == buffer_id));
+            app.process_command(formatted).await?;
+            assert_eq!(contents(&app), "FN A() {\n    X\n}\nX\n");
+            let buffer = &app.state.buffers[buffer_id];
+            assert_eq!(buffer.mark('a'), Some(Point { line: 2, column: 0 }));
+            assert_eq!(app.state.editors[editor_id].cursor(), Point { line: 3, column: 0 });
+            // and is undone in one go
+            let undo = EditorCommand::Undo(UndoMove::Undo);
+            app.process_command(Command::Editor(editor_id, undo))
+                .await?;
+            assert_eq!(contents(&app), "FN A() {\n    x\n}\nx\n");
+
+            // a formatter that can be told which lines only formats the selected ones
+            app.formatters
+                .insert("rust".into(), argv(&["sed", "{start},{end}s/x/y/"]));
+            app.state.editors[editor_id].set_cursor(Point { line: 1, column: 0 });
+            for cmd in [
+                EditorCommand::SelectLine,
+                EditorCommand::CursorMove(Direction::Down),
+            ] {
+                app.process_command(Command::Editor(editor_id, cmd)).await?;
+            }
+            app.process_command(Command::Format).await?;
+            let formatted = app.cmd_rx.recv().await.expect("formatted");
+            app.process_command(formatted).await?;
+            assert_eq!(contents(&app), "FN A() {\n    y\n}\nx\n");
+            assert_eq!(app.state.editors[editor_id].mode, editor::Mode::Normal);
+
+            // one that fails says why in a preview, and leaves the buffer as it was
+            let script = "echo 'error: expected `;`' >&2; echo ' --> stdin:2:5' >&2; exit 1";
+            app.formatters
+                .insert("rust".into(), argv(&["sh", "-c", script]));
+            app.process_command(Command::Format).await?;
+            let failed = app.cmd_rx.recv().await.expect("formatted");
+            assert!(matches!(failed, Command::Formatted(_, _, Err(_))));
+            app.process_command(failed).await?;
+            assert_eq!(contents(&app), "FN A() {\n    y\n}\nx\n");
+            assert_eq!(app.state.message.as_deref(), Some("formatter failed"));
+            let Pane::Preview(_, preview) = app.state.focused_pane() else {
+                panic!("expected the error in a preview");
+            };
+            let error = app.state.buffers[preview].contents.to_string();
+            assert_eq!(error, "error: expected `;`\n --> stdin:2:5");
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn load_in_background() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "fn a() {}\n")?;
+
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            let scratch = app.state.editors[editor_id].buffer_id;
+
+            app.load_in_background(None, path.clone(), false, None);
+            let progress = app.cmd_rx.recv().await.unwrap();
+            assert!(matches!(progress, Command::FileLoadProgress(_, 10, Some(10))));
+            app.process_command(progress).await?;
+            let message = app.state.message.clone().unwrap_or_default();
+            assert!(message.ends_with("a.rs: 100%"), "{}", message);
+            assert_eq!(app.state.editors[editor_id].buffer_id, scratch);
+
+            let loaded = app.cmd_rx.recv().await.unwrap();
+            assert!(matches!(loaded, Command::FileLoaded(..)));
+            app.process_command(loaded).await?;
+            assert_eq!(app.state.message, None);
+            assert!(app.state.loads.is_empty());
+            let buffer = &app.state.buffers[app.state.editors[editor_id].buffer_id];
+            assert_eq!(buffer.path.as_ref(), Some(&path));
+            assert_eq!(buffer.contents.to_string(), "fn a() {}\n");
+
+            // a cancelled load leaves the editor as it was
+            app.state.editors[editor_id].swap_buffer(&app.state.buffers[scratch]);
+            app.load_in_background(None, path, false, None);
+            app.process_command(Command::FileOpenCancel).await?;
+            let loaded = app.cmd_rx.recv().await.unwrap();
+            app.process_command(loaded).await?;
+            assert!(app.state.loads.is_empty());
+            assert_eq!(app.state.editors[editor_id].buffer_id, scratch);
+            let message = app.state.message.clone().unwrap_or_default();
+            assert!(message.starts_with("stopped opening"), "{}", message);
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn startup_script() -> Result<()> {
+        let TestApp { rt, app, cmd_tx, dir, .. } = TestApp::new(Clock::Paused)?;
+        let path = dir.path().join("a.rs");
+        let text = (1..=30)
+            .map(|n| format!("line {}\n", n))
+            .collect::<Vec<_>>()
+            .concat();
+        std::fs::write(&path, &text)?;
+        let out = dir.path().join("out.rs");
+
+        rt.block_on(async {
+            let screen = MockScreen::default();
+            let session = terminal::Session::new(Box::new(NoopGuard));
+            let events = futures::stream::pending();
+            let app = tokio::spawn(app.run(screen.clone(), events, session));
+
+            let startup = Startup {
+                paths: vec![path.clone()],
+                jump: Some(StartupJump::Line(10)),
+                commands: vec![format!("w {}", out.display()), "bogus".to_string()],
+                ..Default::default()
+            };
+            for command in App::startup_commands(startup, OpenLayout::Buffers) {
+                cmd_tx.send(command).await?;
+            }
+            tokio::time::sleep(FRAME_INTERVAL * 2).await;
+
+            assert_eq!(std::fs::read_to_string(&out)?, text);
+            {
+                let screen = screen.0.lock().unwrap();
+                let (_, frame, cursor) = &*screen;
+                assert_eq!(cursor.y, 9);
+                let frame = frame.as_ref().expect("drawn at least once");
+                let bottom: String = (0..80).map(|x| frame.get(x, 23).symbol.as_str()).collect();
+                assert!(bottom.contains("unknown command 'bogus'"), "{:?}", bottom);
+            }
+
+            // the script's own quit ends it
+            let startup = Startup { commands: vec!["q".to_string()], ..Default::default() };
+            for command in App::startup_commands(startup, OpenLayout::Buffers) {
+                cmd_tx.send(command).await?;
+            }
+            tokio::time::timeout(FRAME_INTERVAL * 10, app).await???;
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn script_waits_for_loads() -> Result<()> {
+        let TestApp { rt, mut app, .. } = TestApp::new(Clock::Real)?;
+        rt.block_on(async {
+            let path = std::path::PathBuf::from("large.rs");
+            let cancel = CancellationToken::new();
+            let load = PendingLoad { editor_id: None, path, cancel, jump: None };
+            let load_id = app.state.loads.insert(load);
+
+            let script = ["ls", "bogus", "set number"].map(String::from).to_vec();
+            app.process_command(Command::Script(script)).await?;
+            assert!(app.queued.is_empty());
+
+            let cancelled = Err(Arc::new(anyhow::Error::new(editor::Cancelled)));
+            app.process_command(Command::FileLoaded(load_id, cancelled))
+                .await?;
+            let queued: Vec<_> = app.queued.drain(..).collect();
+            assert!(matches!(
+                queued.as_slice(),
+                [Command::ListBuffers, Command::Set(set)] if set == "number"
+            ));
+            assert_eq!(app.state.message.as_deref(), Some("unknown command 'bogus'"));
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn jump_once_file_is_open() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "fn a() {}\nlet needle = 1;\n")?;
+
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            let cursor = |app: &App| app.state.editors[editor_id].cursor();
+
+            // the jump waits for a background load to finish
+            let point = tore::Point { line: 1, column: 4 };
+            app.load_in_background(None, path.clone(), false, Some(point));
+            let progress = app.cmd_rx.recv().await.unwrap();
+            app.process_command(progress).await?;
+            assert_eq!(cursor(&app), tore::Point::default());
+            let loaded = app.cmd_rx.recv().await.unwrap();
+            app.process_command(loaded).await?;
+            assert_eq!(cursor(&app), point);
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+
+            // an open file is jumped around in its buffer, however its path is spelled
+            let buffers = app.state.buffers.len();
+            let dotted = dir.path().join(".").join("a.rs");
+            let point = tore::Point { line: 0, column: 3 };
+            app.process_command(Command::FileOpenAt(None, dotted, point))
+                .await?;
+            assert_eq!(app.state.buffers.len(), buffers);
+            assert_eq!(app.state.editors[editor_id].buffer_id, buffer_id);
+            assert_eq!(cursor(&app), point);
+
+            // a point the file no longer reaches lands as near it as it can
+            let point = tore::Point { line: 9, column: 9 };
+            app.process_command(Command::FileOpenAt(None, path.clone(), point))
+                .await?;
+            assert_eq!(cursor(&app), tore::Point { line: 2, column: 0 });
+
+            // opening a grep match closes the results and jumps to it
+            app.state.buffers[buffer_id].contents = "\n\n  needle\n".into();
+            let buffers = HashMap::from([(grep::normalize(&path), "\n\n  needle\n".into())]);
+            let (search_id, cancel) = app
+                .state
+                .grep
+                .start(dir.path().to_path_buf(), "needle".into());
+            grep::search(
+                dir.path(),
+                &Default::default(),
+                "needle",
+                &buffers,
+                10,
+                &cancel,
+                |matches| {
+                    app.state.grep.add(search_id, matches);
+                    true
+                },
+            );
+            app.state.focus_pane(app.state.grep_pane_id);
+            let enter = InputKey::from(Key::Enter);
+            let open = app.state.process_key(enter).unwrap();
+            app.process_command(open).await?;
+            assert!(!app.state.visible_panes.contains(&app.state.grep_pane_id));
+            assert_eq!(app.state.editors[editor_id].buffer_id, buffer_id);
+            assert_eq!(cursor(&app), tore::Point { line: 2, column: 2 });
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn buffers_listed_by_number() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        for (name, text) in [
+            ("a.rs", "fn a() {}\n"),
+            ("b.rs", "fn b() {\n}\n"),
+            ("c.txt", "c"),
+        ] {
+            std::fs::write(dir.path().join(name), text)?;
+        }
+
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            let list = |app: &App| app.state.buffer_list(editor_id, dir.path());
+            let shown =
+                |app: &App| app.state.buffers[app.state.editors[editor_id].buffer_id].number;
+            let ctrl_caret = InputKey::ctrl('^');
+
+            for name in ["a.rs", "b.rs", "c.txt"] {
+                app.process_command(Command::FileOpen(None, dir.path().join(name)))
+                    .await?;
+            }
+            // a buffer nothing parses lists no language
+            app.state
+                .syntax_states
+                .remove(app.state.editors[editor_id].buffer_id);
+            let b = app.state.find_buffer(&dir.path().join("b.rs")).unwrap();
+            app.state.buffers[b].dirty = true;
+            let expected = [
+                r#"  1  h   "[scratch]" 1 line"#,
+                r#"  2  h   "a.rs" 2 lines rust"#,
+                r#"  3 #h + "b.rs" 3 lines rust"#,
+                r#"  4 %a   "c.txt" 1 line"#,
+            ];
+            assert_eq!(list(&app).lines().collect::<Vec<_>>(), expected);
+
+            // ctrl-^ swaps back and forth between the last two buffers shown
+            let swap = app.state.process_key(ctrl_caret).unwrap();
+            assert!(matches!(swap, Command::AlternateBuffer(id) if id == editor_id));
+            app.process_command(swap).await?;
+            assert_eq!(shown(&app), Some(3));
+            let swap = app.state.process_key(ctrl_caret).unwrap();
+            app.process_command(swap).await?;
+            assert_eq!(shown(&app), Some(4));
+
+            // numbers stay with their buffers, and aren't reused once a buffer is gone
+            app.process_command(Command::SwitchBuffer("2".into()))
+                .await?;
+            assert_eq!(shown(&app), Some(2));
+            let listed = list(&app);
+            let lines: Vec<_> = listed.lines().collect();
+            assert_eq!(lines[1], r#"  2 %a   "a.rs" 2 lines rust"#);
+            assert_eq!(lines[3], r#"  4 #h   "c.txt" 1 line"#);
+            app.state.buffers.remove(b);
+            app.state.syntax_states.remove(b);
+            app.process_command(Command::SwitchBuffer("3".into()))
+                .await?;
+            assert_eq!(app.state.message.take(), Some("no buffer 3".into()));
+            app.process_command(Command::SwitchBuffer("x".into()))
+                .await?;
+            assert_eq!(app.state.message.take(), Some("'x' is not a buffer number".into()));
+            app.process_command(Command::FileOpen(None, dir.path().join("b.rs")))
+                .await?;
+            assert_eq!(shown(&app), Some(5));
+            let alternate = app.state.editors[editor_id].alternate();
+            assert_eq!(alternate.and_then(|id| app.state.buffers[id].number), Some(2));
+
+            // the alternate may have been closed since
+            app.state.buffers.remove(alternate.unwrap());
+            app.state.syntax_states.remove(alternate.unwrap());
+            app.process_command(Command::AlternateBuffer(editor_id))
+                .await?;
+            assert_eq!(app.state.message.take(), Some("no alternate buffer".into()));
+            assert_eq!(shown(&app), Some(5));
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn editorconfig_settings_and_fixups() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let editorconfig = "root = true\n\n[*]\nindent_style = tab\ntab_width = 8\n\
+                            trim_trailing_whitespace = true\ninsert_final_newline = true\n";
+        std::fs::write(dir.path().join(".editorconfig"), editorconfig)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "fn a() {  \n}")?;
+
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            app.process_command(Command::FileOpen(None, path.clone()))
+                .await?;
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+            let settings = app.state.buffers[buffer_id].settings();
+            assert_eq!((settings.indent_style, settings.tab_width), (editor::IndentStyle::Tab, 8));
+
+            let key = InputKey::from;
+            for code in [Key::Char('o'), Key::Tab, Key::Char('x'), Key::Esc] {
+                let command = app.state.process_key(key(code)).unwrap();
+                app.process_command(command).await?;
+            }
+            // what the user sets wins over the file's
+            app.process_command(Command::Set("indent_style = space".into()))
+                .await?;
+            app.process_command(Command::Set("indent_size=2".into()))
+                .await?;
+            for code in [Key::Char('o'), Key::Tab, Key::Char('y'), Key::Esc] {
+                let command = app.state.process_key(key(code)).unwrap();
+                app.process_command(command).await?;
+            }
+            app.process_command(Command::Set("indent_size=none".into()))
+                .await?;
+            let message = app.state.message.take();
+            assert_eq!(message.as_deref(), Some("bad value for indent_size: 'none'"));
+
+            app.process_command(Command::BufferSave(buffer_id)).await?;
+            assert_eq!(std::fs::read_to_string(&path)?, "fn a() {\n\tx\n\t y\n}\n");
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn settings_layered_per_project() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let (one, two) = (dir.path().join("one"), dir.path().join("two"));
+        std::fs::create_dir_all(one.join(".git"))?;
+        std::fs::create_dir_all(two.join("src/vendor"))?;
+        std::fs::write(
+            one.join(project::FILE_NAME),
+            "indent_size = 2
+tab_width = 8
+",
+        )?;
+        let config =
+            "indent_style = \"tab\"\ntab_width = 4\n\n[formatters]\nrust = \"tr a-z A-Z\"\n";
+        std::fs::write(two.join(project::FILE_NAME), config)?;
+        // a nested project's config is its own, not laid over the outer one's
+        std::fs::write(
+            two.join("src/vendor").join(project::FILE_NAME),
+            "tab_width = 3
+",
+        )?;
+        std::fs::write(
+            two.join("src/vendor/.editorconfig"),
+            "[*]
+indent_size = 6
+",
+        )?;
+        for path in ["one/a.rs", "two/src/b.rs", "two/src/vendor/c.rs"] {
+            std::fs::write(dir.path().join(path), "x\n")?;
+        }
+
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            let mut buffer_ids = vec![];
+            for path in ["one/a.rs", "two/src/b.rs", "two/src/vendor/c.rs"] {
+                app.process_command(Command::FileOpen(None, dir.path().join(path)))
+                    .await?;
+                buffer_ids.push(app.state.editors[editor_id].buffer_id);
+            }
+            let settings = |app: &App, i: usize| {
+                let settings = app.state.buffers[buffer_ids[i]].settings();
+                (settings.indent_style, settings.indent_size, settings.tab_width)
+            };
+            use editor::IndentStyle::{Space, Tab};
+            assert_eq!(settings(&app, 0), (Space, 2, 8));
+            assert_eq!(settings(&app, 1), (Tab, 4, 4));
+            // an .editorconfig wins over the project's config
+            assert_eq!(settings(&app, 2), (Space, 6, 6));
+
+            // and :set wins over both, for only the focused buffer
+            app.process_command(Command::Set("tab_width = 5".into()))
+                .await?;
+            assert_eq!(settings(&app, 2), (Space, 6, 5));
+            assert_eq!(settings(&app, 1), (Tab, 4, 4));
+
+            // the project's formatter runs when the user has none of their own
+            app.process_command(Command::FileOpen(None, dir.path().join("two/src/b.rs")))
+                .await?;
+            assert_eq!(app.state.editors[editor_id].buffer_id, buffer_ids[1]);
+            app.process_command(Command::Format).await?;
+            let formatted = app.cmd_rx.recv().await.expect("formatted");
+            app.process_command(formatted).await?;
+            assert_eq!(app.state.buffers[buffer_ids[1]].contents.to_string(), "X\n");
+
+            // a config that's changed is read again on reload, and one that doesn't read says so
+            std::fs::write(one.join(project::FILE_NAME), "indent_size = 3\n")?;
+            std::fs::write(two.join(project::FILE_NAME), "indent_size = x\n")?;
+            assert!(!app.load_config().await);
+            assert_eq!(settings(&app, 0), (Space, 3, 3));
+            assert_eq!(settings(&app, 1), (Space, 4, 4));
+            let message = app.state.message.take().unwrap_or_default();
+            assert!(message.starts_with("project config not loaded: "), "{}", message);
+            assert!(message.ends_with("line 1: bad value for indent_size: 'x'"), "{}", message);
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn final_newline_kept_on_save() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let files = [
+            ("eol.rs", "fn a() {}\r\n"),
+            ("noeol.rs", "fn a() {}\n}"),
+            ("empty.rs", ""),
+        ];
+        for (name, text) in files {
+            std::fs::write(dir.path().join(name), text)?;
+        }
+
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            for (name, text) in files {
+                let path = dir.path().join(name);
+                app.process_command(Command::FileOpen(None, path.clone()))
+                    .await?;
+                let buffer_id = app.state.editors[editor_id].buffer_id;
+                let had_final_newline = name != "noeol.rs";
+                assert_eq!(app.state.buffers[buffer_id].had_final_newline, had_final_newline);
+                app.process_command(Command::BufferSave(buffer_id)).await?;
+                assert_eq!(std::fs::read(&path)?, text.as_bytes(), "{}", name);
+            }
+
+            // unless the settings say there must be one
+            let buffer_id = app.state.find_buffer(&dir.path().join("noeol.rs")).unwrap();
+            app.state.editors[editor_id].swap_buffer(&app.state.buffers[buffer_id]);
+            app.process_command(Command::Set("insert_final_newline=true".into()))
+                .await?;
+            app.process_command(Command::BufferSave(buffer_id)).await?;
+            assert_eq!(std::fs::read_to_string(dir.path().join("noeol.rs"))?, "fn a() {}\n}\n");
+            assert!(app.state.buffers[buffer_id].had_final_newline);
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn edit_and_write_typed_paths() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        std::fs::create_dir_all(dir.path().join("src"))?;
+        for name in ["main.rs", "map.rs"] {
+            std::fs::write(dir.path().join("src").join(name), format!("// {}\n", name))?;
+        }
+
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+            app.process_command(Command::FileOpen(None, dir.path().join("src/main.rs")))
+                .await?;
+
+            // paths typed after the command are relative to the buffer's directory here
+            let key = InputKey::from;
+            for code in ":e ./m\t".chars().map(|c| match c {
+                '\t' => Key::Tab,
+                c => Key::Char(c),
+            }) {
+                let command = app.state.process_key(key(code)).unwrap();
+                app.process_command(command).await?;
+            }
+            let registry = &app.state.command_registry;
+            assert_eq!(registry.selector.query.as_str(), "e ./ma");
+            assert_eq!(registry.completions, ["./main.rs", "./map.rs"]);
+            for code in [Key::Char('p'), Key::Tab, Key::Enter] {
+                let command = app.state.process_key(key(code)).unwrap();
+                app.process_command(command).await?;
+            }
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+            let path = app.state.buffers[buffer_id].path.clone();
+            assert_eq!(path, Some(dir.path().join("src/./map.rs")));
+
+            app.process_command(Command::Write(Some(dir.path().join("copy.rs"))))
+                .await?;
+            assert_eq!(std::fs::read_to_string(dir.path().join("copy.rs"))?, "// map.rs\n");
+            assert_eq!(app.state.buffers[buffer_id].path, Some(dir.path().join("copy.rs")));
+
+            // what can't be opened is said, rather than failing
+            app.process_command(Command::Edit(Some(dir.path().join("nowhere.rs"))))
+                .await?;
+            let message = app.state.message.take().unwrap();
+            assert!(message.starts_with(&dir.path().join("nowhere.rs").display().to_string()));
+            app.process_command(Command::Edit(None)).await?;
+            assert_eq!(app.state.message.take().as_deref(), Some("expected a path to edit"));
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn validation_reports_drifted_summaries() -> Result<()> {
+        let TestApp { rt, mut app, .. } = TestApp::new(Clock::Real)?;
+        rt.block_on(async {
+            let key = InputKey::from;
+            app.process_command(Command::Validate("on".into())).await?;
+            for code in [Key::Char('i'), Key::Char('a'), Key::Char('\n')] {
+                let command = app.state.process_key(key(code)).unwrap();
+                app.process_command(command).await?;
+            }
+            assert_eq!(app.state.message, None);
+
+            // a summary that counts a line too many is caught, not panicked on
+            let buffer_id = app.state.editors[app.state.default_editor_id].buffer_id;
+            let contents = &mut app.state.buffers[buffer_id].contents;
+            let sound = contents.clone();
+            let root = sound.tree().root().unwrap();
+            let mut summary = root.summary();
+            summary.stats.lines.line += 1;
+            **contents = rope::Rope::new(root.with_summary(summary)).into();
+            app.state.validate(buffer_id);
+            let message = app.state.message.take();
+            let expected = "summaries drifted in [scratch]: bytes 0..2 summarized as \
+                            (2,2/0,1/0) rather than (2,1/0,1/0)";
+            assert_eq!(message.as_deref(), Some(expected));
+            app.state.buffers[buffer_id].contents = sound;
+
+            app.process_command(Command::Validate("off".into())).await?;
+            assert!(app.state.validator.is_none());
+            for code in [Key::Char('c'), Key::Esc] {
+                let command = app.state.process_key(key(code)).unwrap();
+                app.process_command(command).await?;
+            }
+            assert_eq!(app.state.message, None);
+            app.process_command(Command::Validate("maybe".into()))
+                .await?;
+            let message = app.state.message.take();
+            assert_eq!(message.as_deref(), Some("expected on or off, not 'maybe'"));
+            Ok::<(), anyhow::Error>(())
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn open_save_round_trips_encodings() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let text = "// héllo 🦀\nfn a() {}\n";
+        let utf16: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let fixtures = [
+            ("utf16.rs", utf16),
+            ("bom.rs", [b"\xef\xbb\xbf", text.as_bytes()].concat()),
+        ];
+        let corrupt = dir.path().join("corrupt.rs");
+        std::fs::write(&corrupt, b"// it\x92s\nfn a() {}\n")?;
+
+        rt.block_on(async {
+            let editor_id = app.state.default_editor_id;
+
+            for (name, bytes) in fixtures {
+                let path = dir.path().join(name);
+                std::fs::write(&path, &bytes)?;
+                app.process_command(Command::FileOpen(None, path.clone()))
+                    .await?;
+                let buffer_id = app.state.editors[editor_id].buffer_id;
+                assert_eq!(app.state.buffers[buffer_id].contents.to_string(), text);
+                std::fs::remove_file(&path)?;
+                app.process_command(Command::BufferSave(buffer_id)).await?;
+                assert_eq!(std::fs::read(&path)?, bytes, "{}", name);
+            }
+
+            // a file that doesn't decode is reported, then opened lossily on request
+            let before = app.state.editors[editor_id].buffer_id;
+            app.process_command(Command::FileOpen(None, corrupt.clone()))
+                .await?;
+            assert_eq!(app.state.editors[editor_id].buffer_id, before);
+            let message = app.state.message.take().unwrap_or_default();
+            assert!(message.contains("invalid utf-8 at byte 5"), "{}", message);
+
+            app.process_command(Command::FileOpenLossy(None, None))
+                .await?;
+            let buffer = &app.state.buffers[app.state.editors[editor_id].buffer_id];
+            assert_eq!(buffer.contents.to_string(), "// it\u{fffd}s\nfn a() {}\n");
+            assert!(buffer.read_only);
+            assert!(app.state.message.is_some());
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn reload_config_when_its_files_change() -> Result<()> {
+        use std::time::{Duration, SystemTime};
+
+        let TestApp { rt, mut app, cmd_tx, dir, .. } = TestApp::new(Clock::Paused)?;
+        let path = dir.path().join("theme.toml");
+        let src = "[palette]\nred = \"#ff0000\"\n[scheme]\nui.linenr = \"red\"\n";
+        let red = ui::Theme::from_toml(src).unwrap().style("ui.linenr");
+        assert_ne!(ui::Theme::default().style("ui.linenr"), red);
+
+        rt.block_on(async {
+            app.config = ConfigFiles { theme: Some(path.clone()), ..Default::default() };
+            // without a theme file, the built-in theme is used
+            assert!(app.load_config().await);
+            let interval = Duration::from_secs(1);
+            tokio::spawn(events::watch_config(app.config.paths(), cmd_tx, interval));
+
+            tokio::time::sleep(interval * 3).await;
+            assert!(app.cmd_rx.try_recv().is_err());
+
+            std::fs::write(&path, src)?;
+            let reload = tokio::time::timeout(interval * 2, app.cmd_rx.recv()).await?;
+            assert!(matches!(reload, Some(Command::ConfigReload)), "{:?}", reload);
+            assert!(app.state.redraws(&Command::ConfigReload));
+            app.process_command(Command::ConfigReload).await?;
+            assert_eq!(app.state.theme.style("ui.linenr"), red);
+            assert_eq!(app.state.message.take().as_deref(), Some("config reloaded"));
+
+            // a theme that doesn't read leaves the last one in place
+            std::fs::write(&path, "[palette]\nred = \"#ff00\"\n")?;
+            let file = std::fs::File::options().write(true).open(&path)?;
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000))?;
+            let reload = tokio::time::timeout(interval * 2, app.cmd_rx.recv()).await?;
+            assert!(matches!(reload, Some(Command::ConfigReload)), "{:?}", reload);
+            app.process_command(Command::ConfigReload).await?;
+            assert_eq!(app.state.theme.style("ui.linenr"), red);
+            let message = app.state.message.take().unwrap_or_default();
+            assert!(message.starts_with("theme not loaded: "), "{:?}", message);
+            assert!(message.ends_with("theme.toml: line 2: '#ff00' isn't a #rrggbb color"));
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn theme_typed_as_a_path() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let src = "[palette]\nred = \"#ff0000\"\n[scheme]\nui.linenr = \"red\"\n";
+        std::fs::write(dir.path().join("red.toml"), src)?;
+        std::fs::write(dir.path().join("bad.toml"), "[palette]\nred = \"#ff00\"\n")?;
+        let red = ui::Theme::from_toml(src).unwrap().style("ui.linenr");
+
+        rt.block_on(async {
+            let key = InputKey::from;
+            let typed = format!(":theme {}/red.toml\n", dir.path().display());
+            for code in typed.chars().map(|c| match c {
+                '\n' => Key::Enter,
+                c => Key::Char(c),
+            }) {
+                let command = app.state.process_key(key(code)).unwrap();
+                app.process_command(command).await?;
+            }
+            assert_eq!(app.state.theme.style("ui.linenr"), red);
+            assert_eq!(app.config.theme, Some(dir.path().join("red.toml")));
+
+            // what doesn't read leaves the theme as it is, and says why
+            for name in ["bad.toml", "nowhere.toml"] {
+                let path = dir.path().join(name);
+                app.process_command(Command::Theme(Some(path))).await?;
+                assert_eq!(app.state.theme.style("ui.linenr"), red);
+                let message = app.state.message.take().unwrap_or_default();
+                assert!(message.starts_with("theme not loaded: "), "{:?}", message);
+            }
+            assert_eq!(app.config.theme, Some(dir.path().join("red.toml")));
+            app.process_command(Command::Theme(None)).await?;
+            let message = app.state.message.take();
+            assert_eq!(message.as_deref(), Some("expected a theme file to load"));
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn reload_key_bindings() -> Result<()> {
+        let TestApp { rt, mut app, dir, .. } = TestApp::new(Clock::Real)?;
+        let path = dir.path().join("keys.toml");
+        let ctrl_t = InputKey::ctrl('t');
+        rt.block_on(async {
+            app.config = ConfigFiles { keys: Some(path.clone()), ..Default::default() };
+            std::fs::write(&path, "ctrl-t = \"set notitle\"\n")?;
+            assert!(app.load_config().await);
+            let command = app.state.process_key(ctrl_t);
+            assert!(matches!(command, Some(Command::Set(set)) if set == "notitle"));
+
+            // a reload rebuilds the bindings, and forgets keys typed towards a built-in one
+            std::fs::write(&path, "ctrl-t = \"set title\"\nX = \"ls\"\n")?;
+            assert!(app.state.process_key(InputKey::char('g')).is_none());
+            app.process_command(Command::ConfigReload).await?;
+            assert_eq!(app.state.pending, "");
+            let command = app.state.process_key(ctrl_t);
+            assert!(matches!(command, Some(Command::Set(set)) if set == "title"));
+            let command = app.state.process_key(InputKey::char('X'));
+            assert!(matches!(command, Some(Command::ListBuffers)));
+
+            // bindings that don't read leave the last ones in place
+            std::fs::write(&path, "hyper-t = \"ls\"\n")?;
+            app.process_command(Command::ConfigReload).await?;
+            let message = app.state.message.take().unwrap_or_default();
+            assert!(message.starts_with("key bindings not loaded: "), "{:?}", message);
+            assert!(message.ends_with("keys.toml: line 1: 'hyper-t' isn't a key"));
+            let command = app.state.process_key(ctrl_t);
+            assert!(matches!(command, Some(Command::Set(set)) if set == "title"));
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    #[test]
+    fn journal_and_recover() -> Result<()> {
+        use std::time::Duration;
+
+        let TestApp { rt, mut app, cmd_tx, dir } = TestApp::new(Clock::Paused)?;
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "fn a() {}\n")?;
+        let recovery = Recovery::new(dir.path().join("recovery"));
+        std::fs::create_dir_all(dir.path().join("recovery"))?;
+
+        let restarted = AppContext::new()?;
+        rt.block_on(async {
+            app.recovery = Some(recovery.clone());
+            let interval = Duration::from_secs(10);
+            tokio::spawn(events::journal(app.state.subscribe(), cmd_tx, interval, 100));
+
+            let editor_id = app.state.default_editor_id;
+            app.process_command(Command::FileOpen(None, path.clone()))
+                .await?;
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+            for cmd in [
+                EditorCommand::SetMode(editor::Mode::Insert),
+                EditorCommand::InsertChar('/'),
+                EditorCommand::InsertChar('/'),
+            ] {
+                app.process_command(Command::Editor(editor_id, cmd)).await?;
+            }
+
+            // nothing is journaled until the interval is up
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            assert!(app.cmd_rx.try_recv().is_err());
+            let journal = tokio::time::timeout(interval, app.cmd_rx.recv()).await?;
+            let Some(Command::BufferJournal(id)) = journal else {
+                panic!("expected a journal, got {:?}", journal);
+            };
+            assert_eq!(id, buffer_id);
+            app.journal(buffer_id)
+                .expect("dirty buffer journaled")
+                .await?;
+            let journaled = recovery.read(&path)?.expect("journal written");
+            assert_eq!(journaled.version, 2);
+            assert_eq!(journaled.contents, "//fn a() {}\n");
+            assert_eq!(std::fs::read_to_string(&path)?, "fn a() {}\n");
+
+            // opening the file again, as after a crash, offers what was journaled
+            let (cmd_tx, cmd_rx) = mpsc::channel(1);
+            let mut app = App::new(restarted, cmd_tx, cmd_rx);
+            app.recovery = Some(recovery.clone());
+            app.process_command(Command::FileOpen(None, path.clone()))
+                .await?;
+            let buffer_id = app.state.editors[editor_id].buffer_id;
+            let message = app.state.message.take().unwrap_or_default();
+            assert!(message.contains(":recover"), "{}", message);
+            assert_eq!(app.state.buffers[buffer_id].contents.to_string(), "fn a() {}\n");
+
+            app.process_command(Command::Recover).await?;
+            let buffer = &app.state.buffers[buffer_id];
+            assert_eq!(buffer.contents.to_string(), "//fn a() {}\n");
+            assert!(buffer.dirty);
+
+            // saving leaves nothing to recover
+            app.process_command(Command::BufferSave(buffer_id)).await?;
+            assert_eq!(std::fs::read_to_string(&path)?, "//fn a() {}\n");
+            assert_eq!(recovery.read(&path)?, None);
+            Ok::<(), anyhow::Error>(())
+        })
+    }
 }
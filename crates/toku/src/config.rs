@@ -0,0 +1,235 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use tore::key::{InputKey, Key, Modifiers};
+
+/// The file the theme is read from, in toku's config directory.
+const THEME_FILE: &str = "theme.toml";
+/// The file the language servers are read from, in toku's config directory.
+const SERVERS_FILE: &str = "languages.toml";
+/// The file the formatters are read from, in toku's config directory.
+const FORMATTERS_FILE: &str = "formatters.toml";
+/// The file key bindings are read from, in toku's config directory.
+const KEYS_FILE: &str = "keys.toml";
+
+/// Where toku's own config files are. Any that isn't there leaves the built-in config in its
+/// place.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigFiles {
+    pub theme: Option<PathBuf>,
+    pub servers: Option<PathBuf>,
+    pub formatters: Option<PathBuf>,
+    pub keys: Option<PathBuf>,
+}
+
+impl ConfigFiles {
+    /// The files in toku's XDG config directory.
+    pub fn xdg() -> Result<Self> {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix(crate::PROJECT_NAME.clone())?;
+        let theme = xdg_dirs.get_config_home().join(THEME_FILE);
+        let servers = xdg_dirs.get_config_home().join(SERVERS_FILE);
+        let formatters = xdg_dirs.get_config_home().join(FORMATTERS_FILE);
+        let keys = xdg_dirs.get_config_home().join(KEYS_FILE);
+        Ok(Self {
+            theme: Some(theme),
+            servers: Some(servers),
+            formatters: Some(formatters),
+            keys: Some(keys),
+        })
+    }
+
+    /// Every file, whether it's there or not, to watch for changes.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let files = self
+            .theme
+            .iter()
+            .chain(&self.servers)
+            .chain(&self.formatters)
+            .chain(&self.keys);
+        files.cloned().collect()
+    }
+}
+
+/// The theme read from `path`, or the built-in one if there's no file there. Fails with the file
+/// and what's wrong in it, and where.
+pub async fn load_theme(path: Option<&Path>) -> Result<ui::Theme> {
+    let Some(path) = path else {
+        return Ok(ui::Theme::default());
+    };
+    match tokio::fs::read_to_string(path).await {
+        Ok(src) => ui::Theme::from_toml(&src).map_err(|err| anyhow!("{}: {}", path.display(), err)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(ui::Theme::default()),
+        Err(err) => Err(anyhow!("{}: {}", path.display(), err)),
+    }
+}
+
+/// The command to run for each language, like the one its language server is started with, read
+/// from `path`, or none if there's no file there. Fails like the theme does.
+pub async fn load_commands(path: Option<&Path>) -> Result<HashMap<String, Vec<String>>> {
+    load(path, parse_commands).await
+}
+
+/// The key bindings read from `path`, each to the line of the command palette it runs, or none
+/// if there's no file there. Fails like the theme does.
+pub async fn load_keys(path: Option<&Path>) -> Result<HashMap<InputKey, String>> {
+    load(path, parse_keys).await
+}
+
+/// What `parse` makes of the file at `path`, or nothing if there's no file there.
+async fn load<T: Default>(
+    path: Option<&Path>,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<T> {
+    let Some(path) = path else {
+        return Ok(T::default());
+    };
+    match tokio::fs::read_to_string(path).await {
+        Ok(src) => parse(&src).map_err(|err| anyhow!("{}: {}", path.display(), err)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(err) => Err(anyhow!("{}: {}", path.display(), err)),
+    }
+}
+
+/// Reads lines of `language = "command args"`, skipping blank ones and `#` comments. The command
+/// is split on whitespace.
+fn parse_commands(src: &str) -> Result<HashMap<String, Vec<String>>, String> {
+    parse_entries(src, "language", |language| Ok(language.to_string()))
+}
+
+/// Reads lines of `key = "command args"`, like `ctrl-s = "write"`, as `parse_commands` does.
+fn parse_keys(src: &str) -> Result<HashMap<InputKey, String>, String> {
+    let keys = parse_entries(src, "key", parse_key)?;
+    Ok(keys
+        .into_iter()
+        .map(|(key, command)| (key, command.join(" ")))
+        .collect())
+}
+
+/// Reads lines of `name = "command args"`, skipping blank ones and `#` comments, with `parse`
+/// making each name into the key it's kept under. The command is split on whitespace.
+fn parse_entries<K: Eq + Hash>(
+    src: &str,
+    what: &str,
+    parse: impl Fn(&str) -> Result<K, String>,
+) -> Result<HashMap<K, Vec<String>>, String> {
+    let mut commands = HashMap::new();
+    for (n, line) in src.lines().enumerate() {
+        let fail = |err: String| format!("line {}: {}", n + 1, err);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((name, command)) = line.split_once('=') else {
+            return Err(fail(format!("expected {} = \"command\"", what)));
+        };
+        let name = name.trim();
+        let command = command.trim();
+        let quoted = command
+            .strip_prefix('"')
+            .and_then(|command| command.strip_suffix('"'));
+        let Some(command) = quoted else {
+            return Err(fail(format!("the command for '{}' isn't quoted", name)));
+        };
+        let command: Vec<String> = command.split_whitespace().map(String::from).collect();
+        if command.is_empty() {
+            return Err(fail(format!("the command for '{}' is empty", name)));
+        }
+        if commands
+            .insert(parse(name).map_err(fail)?, command)
+            .is_some()
+        {
+            return Err(fail(format!("'{}' is set twice", name)));
+        }
+    }
+    Ok(commands)
+}
+
+/// A key written like `x`, `ctrl-s`, `alt-enter` or `f5`.
+fn parse_key(name: &str) -> Result<InputKey, String> {
+    let mut modifiers = Modifiers::empty();
+    let mut rest = name;
+    loop {
+        if let Some(after) = rest.strip_prefix("ctrl-") {
+            modifiers |= Modifiers::CTRL;
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("alt-") {
+            modifiers |= Modifiers::ALT;
+            rest = after;
+        } else {
+            break;
+        }
+    }
+    let mut chars = rest.chars();
+    let key = match (chars.next(), chars.next()) {
+        (Some(c), None) => Key::Char(c),
+        _ => match rest {
+            "enter" => Key::Enter,
+            "esc" => Key::Esc,
+            "tab" => Key::Tab,
+            "backspace" => Key::Backspace,
+            "delete" => Key::Delete,
+            "insert" => Key::Insert,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pageup" => Key::PageUp,
+            "pagedown" => Key::PageDown,
+            _ => match rest.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Some(n) => Key::F(n),
+                None => return Err(format!("'{}' isn't a key", name)),
+            },
+        },
+    };
+    Ok(InputKey::new(key, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let src = "# servers\n\nrust = \"rust-analyzer\"\npython = \"pylsp  -v\"\n";
+        let servers = parse_commands(src).unwrap();
+        assert_eq!(servers["rust"], ["rust-analyzer"]);
+        assert_eq!(servers["python"], ["pylsp", "-v"]);
+        assert_eq!(servers.len(), 2);
+
+        assert_eq!(
+            parse_commands("rust rust-analyzer"),
+            Err("line 1: expected language = \"command\"".into())
+        );
+        assert_eq!(
+            parse_commands("\nrust = rust-analyzer"),
+            Err("line 2: the command for 'rust' isn't quoted".into())
+        );
+        assert_eq!(
+            parse_commands("rust = \" \""),
+            Err("line 1: the command for 'rust' is empty".into())
+        );
+        assert_eq!(
+            parse_commands("rust = \"a\"\nrust = \"b\""),
+            Err("line 2: 'rust' is set twice".into())
+        );
+    }
+
+    #[test]
+    fn keys() {
+        let src = "ctrl-s = \"write\"\nX = \"grep  todo\"\nalt-f12 = \"ls\"\n";
+        let keys = parse_keys(src).unwrap();
+        assert_eq!(keys[&InputKey::ctrl('s')], "write");
+        assert_eq!(keys[&InputKey::char('X')], "grep todo");
+        assert_eq!(keys[&InputKey::new(Key::F(12), Modifiers::ALT)], "ls");
+        assert_eq!(keys.len(), 3);
+
+        assert_eq!(parse_keys("\nhyper-s = \"ls\""), Err("line 2: 'hyper-s' isn't a key".into()));
+        assert_eq!(
+            parse_keys("ctrl-s = \"w\"\nctrl-s = \"ls\""),
+            Err("line 2: 'ctrl-s' is set twice".into())
+        );
+    }
+}
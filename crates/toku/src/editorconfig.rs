@@ -0,0 +1,104 @@
+//! Per-file settings from the `.editorconfig` files a project carries; see
+//! <https://editorconfig.org>.
+
+pub mod glob;
+mod parse;
+
+use std::path::Path;
+
+use editor::SettingsOverlay;
+
+use crate::grep::normalize;
+
+const FILE_NAME: &str = ".editorconfig";
+
+/// The settings the `.editorconfig` files in the directories above `path` have for it, up to
+/// the first that says it's the root. Nearer files win over those further up, and later
+/// sections of a file over earlier ones. Files that can't be read, and properties that aren't
+/// understood, are skipped.
+pub fn resolve(path: &Path) -> SettingsOverlay {
+    let path = normalize(path);
+    let mut files = vec![];
+    for dir in path.ancestors().skip(1) {
+        let file = dir.join(FILE_NAME);
+        let text = match std::fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                tracing::warn!(?file, %err, "failed to read .editorconfig");
+                continue;
+            }
+        };
+        let file = parse::parse(&text);
+        let root = file.root;
+        files.push((dir, file));
+        if root {
+            break;
+        }
+    }
+
+    let mut overlay = SettingsOverlay::default();
+    for (dir, file) in files.iter().rev() {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let relative = relative
+            .to_string_lossy()
+            .replace(std::path::MAIN_SEPARATOR, "/");
+        let sections = file.sections.iter();
+        for section in sections.filter(|section| section_matches(&section.glob, &relative)) {
+            for (key, value) in &section.properties {
+                if let Err(err) = overlay.set(key, value) {
+                    tracing::debug!(?dir, %err, "skipping .editorconfig property");
+                }
+            }
+        }
+    }
+    overlay
+}
+
+/// Whether a section's glob matches `path`, relative to the file's directory. A glob with no
+/// `/` matches file names in any directory; one with a `/` is anchored to the file's.
+fn section_matches(glob: &str, path: &str) -> bool {
+    if glob.contains('/') {
+        glob::matches(glob.strip_prefix('/').unwrap_or(glob), path)
+    } else {
+        glob::matches(&format!("**/{}", glob), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::{IndentStyle, LineEnding, Settings};
+
+    #[test]
+    fn nearest_files_win() -> anyhow::Result<()> {
+        let outer = test_support::temp_dir();
+        let root = outer.path().join("repo");
+        std::fs::create_dir_all(root.join("src/vendor"))?;
+        std::fs::write(outer.path().join(FILE_NAME), "[*]\ncharset = utf-8-bom\n")?;
+        std::fs::write(
+            root.join(FILE_NAME),
+            "root = true\n[*]\nindent_style = space\nindent_size = 2\nend_of_line = lf\n\
+             [*.go]\nindent_style = tab\ntab_width = 8\n[/Makefile]\nindent_style = tab\n",
+        )?;
+        std::fs::write(root.join("src").join(FILE_NAME), "[vendor/**]\nindent_size = 4\n")?;
+
+        let settings = |path: &Path| {
+            let mut settings = Settings::default();
+            resolve(path).apply(&mut settings);
+            (settings.indent_style, settings.indent_size, settings.tab_width)
+        };
+        assert_eq!(settings(&root.join("src/a.rs")), (IndentStyle::Space, 2, 2));
+        assert_eq!(settings(&root.join("src/a.go")), (IndentStyle::Tab, 2, 8));
+        assert_eq!(settings(&root.join("src/vendor/b/c.rs")), (IndentStyle::Space, 4, 4));
+        assert_eq!(settings(&root.join("Makefile")).0, IndentStyle::Tab);
+        assert_eq!(settings(&root.join("src/Makefile")).0, IndentStyle::Space);
+
+        // the root stops the search before the file above it
+        let overlay = resolve(&root.join("a.rs"));
+        assert_eq!((overlay.charset, overlay.end_of_line), (None, Some(LineEnding::Lf)));
+        Ok(())
+    }
+}
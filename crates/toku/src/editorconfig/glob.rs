@@ -0,0 +1,162 @@
+//! The globs `.editorconfig` section names are written in: `*` matches any run of chars but
+//! `/`, `**` any run at all, `?` any one char but `/`, `[abc]`, `[a-z]` and `[!abc]` one char
+//! of a set, and `{a,b}` any of its alternatives. `\` takes the next char as it is.
+
+/// Whether `path`, with `/` between its components, matches all of `pattern`.
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let path: Vec<char> = path.chars().collect();
+    expand(pattern).iter().any(|pattern| {
+        let pattern: Vec<char> = pattern.chars().collect();
+        match_from(&pattern, &path)
+    })
+}
+
+/// Each pattern the alternatives in `pattern` make, braces nested in braces too. A brace with no
+/// comma in it, or that isn't closed, is taken as it is.
+fn expand(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let Some((open, close, commas)) = first_alternation(&chars) else {
+        return vec![pattern.to_string()];
+    };
+    let prefix: String = chars[..open].iter().collect();
+    let suffix: String = chars[close + 1..].iter().collect();
+    let mut bounds = vec![open];
+    bounds.extend(commas);
+    bounds.push(close);
+    let mut expanded = vec![];
+    for pair in bounds.windows(2) {
+        let alternative: String = chars[pair[0] + 1..pair[1]].iter().collect();
+        expanded.extend(expand(&format!("{}{}{}", prefix, alternative, suffix)));
+    }
+    expanded
+}
+
+/// Where the first closed brace with a comma in it opens and closes, and its top-level commas.
+fn first_alternation(chars: &[char]) -> Option<(usize, usize, Vec<usize>)> {
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => {
+                let (mut depth, mut commas) = (0, vec![]);
+                let mut j = i + 1;
+                while j < chars.len() {
+                    match chars[j] {
+                        '\\' => j += 1,
+                        '{' => depth += 1,
+                        '}' if depth > 0 => depth -= 1,
+                        '}' if !commas.is_empty() => return Some((i, j, commas)),
+                        '}' => break,
+                        ',' if depth == 0 => commas.push(j),
+                        _ => {}
+                    }
+                    j += 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn match_from(pattern: &[char], path: &[char]) -> bool {
+    let Some(&first) = pattern.first() else {
+        return path.is_empty();
+    };
+    match first {
+        '*' if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            // `a/**/b` matches `a/b` too
+            if rest.first() == Some(&'/') && match_from(&rest[1..], path) {
+                return true;
+            }
+            (0..=path.len()).any(|skip| match_from(rest, &path[skip..]))
+        }
+        '*' => {
+            let run = path.iter().take_while(|&&c| c != '/').count();
+            (0..=run).any(|skip| match_from(&pattern[1..], &path[skip..]))
+        }
+        '?' => {
+            matches!(path.first(), Some(&c) if c != '/') && match_from(&pattern[1..], &path[1..])
+        }
+        '[' => match (class(&pattern[1..]), path.first()) {
+            (Some((matched, len)), Some(&c)) => {
+                c != '/' && matched(c) && match_from(&pattern[len + 1..], &path[1..])
+            }
+            (Some(_), None) => false,
+            (None, _) => literal('[', &pattern[1..], path),
+        },
+        '\\' if pattern.len() > 1 => literal(pattern[1], &pattern[2..], path),
+        c => literal(c, &pattern[1..], path),
+    }
+}
+
+fn literal(c: char, rest: &[char], path: &[char]) -> bool {
+    path.first() == Some(&c) && match_from(rest, &path[1..])
+}
+
+/// The set a `[...]` matches, given what follows its `[`, and how many chars it takes up after
+/// it; None if it isn't closed.
+fn class(pattern: &[char]) -> Option<(impl Fn(char) -> bool + '_, usize)> {
+    let negated = pattern.first() == Some(&'!');
+    let start = usize::from(negated);
+    // a `]` straight after the `[` is in the set
+    let close = start + 1 + pattern.get(start + 1..)?.iter().position(|&c| c == ']')?;
+    let set = &pattern[start..close];
+    let matched = move |c: char| {
+        let mut i = 0;
+        let mut found = false;
+        while i < set.len() {
+            if i + 2 < set.len() && set[i + 1] == '-' {
+                found |= (set[i]..=set[i + 2]).contains(&c);
+                i += 3;
+            } else {
+                found |= set[i] == c;
+                i += 1;
+            }
+        }
+        found != negated
+    };
+    Some((matched, close + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        #[rustfmt::skip]
+        let cases = [
+            ("*.rs", "main.rs", true),
+            ("*.rs", "src/main.rs", false),
+            ("**.rs", "src/main.rs", true),
+            ("src/**/*.rs", "src/main.rs", true),
+            ("src/**/*.rs", "src/a/b/main.rs", true),
+            ("src/**/*.rs", "lib/main.rs", false),
+            ("a?c", "abc", true),
+            ("a?c", "a/c", false),
+            ("*.{js,ts}", "index.ts", true),
+            ("*.{js,ts}", "index.rs", false),
+            ("{src/{a,b},lib}/x", "src/b/x", true),
+            ("{src/{a,b},lib}/x", "lib/x", true),
+            ("{src/{a,b},lib}/x", "src/c/x", false),
+            ("{only}", "{only}", true),
+            ("{only}", "only", false),
+            ("[abc].txt", "b.txt", true),
+            ("[a-c].txt", "d.txt", false),
+            ("[!a-c].txt", "d.txt", true),
+            ("[!a-c].txt", "a.txt", false),
+            ("[].txt", "[].txt", true),
+            ("[]a].txt", "].txt", true),
+            ("\\*.txt", "*.txt", true),
+            ("\\*.txt", "a.txt", false),
+            ("Makefile", "Makefile", true),
+            ("Makefile", "Makefile.am", false),
+        ];
+        for (pattern, path, expected) in cases {
+            assert_eq!(matches(pattern, path), expected, "{} against {}", pattern, path);
+        }
+    }
+}
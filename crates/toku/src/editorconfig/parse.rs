@@ -0,0 +1,95 @@
+//! The INI dialect of `.editorconfig` files: `key = value` pairs under `[glob]` section
+//! headers, with `#` and `;` starting comment lines. Keys are taken in any case.
+
+/// What one `.editorconfig` file says.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct File {
+    /// Whether the search for files stops at this one.
+    pub root: bool,
+    pub sections: Vec<Section>,
+}
+
+/// The properties for the paths a glob matches.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Section {
+    pub glob: String,
+    /// In the order they're written, keys lowercased.
+    pub properties: Vec<(String, String)>,
+}
+
+/// Parses `text`, skipping lines it can't make sense of as other editors do.
+pub fn parse(text: &str) -> File {
+    let mut file = File::default();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(glob) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            let glob = glob.to_string();
+            file.sections.push(Section { glob, properties: vec![] });
+            continue;
+        }
+        let Some((key, value)) = line.split_once(['=', ':']) else {
+            tracing::debug!(line, "skipping .editorconfig line");
+            continue;
+        };
+        let (key, value) = (key.trim().to_ascii_lowercase(), value.trim().to_string());
+        match file.sections.last_mut() {
+            Some(section) => section.properties.push((key, value)),
+            None if key == "root" => file.root = value.eq_ignore_ascii_case("true"),
+            None => {}
+        }
+    }
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_and_properties() {
+        let text = "\
+# top-most EditorConfig file
+root = TRUE
+ignored = before sections
+
+[*]
+End_Of_Line = lf
+; a comment
+insert_final_newline=true
+
+[*.{js,py}]
+charset = utf-8
+not a property
+[Makefile]
+indent_style: tab
+";
+        let file = parse(text);
+        assert!(file.root);
+        let sections: Vec<_> = file
+            .sections
+            .iter()
+            .map(|section| (section.glob.as_str(), section.properties.clone()))
+            .collect();
+        let props = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            sections,
+            [
+                ("*", props(&[("end_of_line", "lf"), ("insert_final_newline", "true")])),
+                ("*.{js,py}", props(&[("charset", "utf-8")])),
+                ("Makefile", props(&[("indent_style", "tab")])),
+            ]
+        );
+        assert!(!parse("[*]\nroot = true\n").root);
+    }
+}
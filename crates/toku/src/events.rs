@@ -0,0 +1,289 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use editor::{BufferId, EditorId, Mode};
+
+use crate::app::{Command, PaneId};
+
+/// Notifications of what the app has done, for consumers that observe it rather than drive it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppEvent {
+    BufferOpened(BufferId, PathBuf),
+    /// The buffer at the given version, and the byte range that was edited to produce it.
+    BufferChanged(BufferId, u64, Range<usize>),
+    BufferSaved(BufferId),
+    ModeChanged(EditorId, Mode),
+    PaneFocused(PaneId),
+    /// The terminal the app is in gained focus, or with `false`, lost it.
+    FocusChanged(bool),
+}
+
+/// Saves changed buffers once no change has been made to any buffer for `delay`.
+pub async fn auto_save(
+    mut events: broadcast::Receiver<AppEvent>,
+    cmd_tx: mpsc::Sender<Command>,
+    delay: Duration,
+) {
+    let mut changed: HashSet<BufferId> = HashSet::new();
+    let mut deadline = Instant::now() + delay;
+    loop {
+        let event = if changed.is_empty() {
+            events.recv().await
+        } else {
+            match tokio::time::timeout_at(deadline, events.recv()).await {
+                Ok(event) => event,
+                Err(_) => {
+                    for buffer_id in changed.drain() {
+                        if cmd_tx.send(Command::BufferSave(buffer_id)).await.is_err() {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+            }
+        };
+
+        match event {
+            Ok(AppEvent::BufferChanged(buffer_id, ..)) => {
+                changed.insert(buffer_id);
+                deadline = Instant::now() + delay;
+            }
+            Ok(AppEvent::BufferSaved(buffer_id)) => {
+                changed.remove(&buffer_id);
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!(skipped, "auto-save missed events");
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Asks for a diff of each changed buffer against its saved contents once no change has been
+/// made to any buffer for `delay`. A save leaves nothing to diff. Nobody sees the diff while the
+/// app is out of focus, so it waits until `delay` after focus is back.
+pub async fn diff_changed(
+    mut events: broadcast::Receiver<AppEvent>,
+    cmd_tx: mpsc::Sender<Command>,
+    delay: Duration,
+) {
+    let mut changed: HashSet<BufferId> = HashSet::new();
+    let mut deadline = Instant::now() + delay;
+    let mut focused = true;
+    loop {
+        let event = if changed.is_empty() || !focused {
+            events.recv().await
+        } else {
+            match tokio::time::timeout_at(deadline, events.recv()).await {
+                Ok(event) => event,
+                Err(_) => {
+                    for buffer_id in changed.drain() {
+                        if cmd_tx.send(Command::BufferDiff(buffer_id)).await.is_err() {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+            }
+        };
+
+        match event {
+            Ok(AppEvent::BufferChanged(buffer_id, ..)) => {
+                changed.insert(buffer_id);
+                deadline = Instant::now() + delay;
+            }
+            Ok(AppEvent::BufferSaved(buffer_id)) => {
+                changed.remove(&buffer_id);
+            }
+            Ok(AppEvent::FocusChanged(now)) => {
+                focused = now;
+                deadline = Instant::now() + delay;
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!(skipped, "line diff missed events");
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Asks for the searches of each changed buffer to be run again once no change has been made to
+/// any buffer for `delay`, and like diffs, not until `delay` after the app is back in focus.
+pub async fn refresh_searches(
+    mut events: broadcast::Receiver<AppEvent>,
+    cmd_tx: mpsc::Sender<Command>,
+    delay: Duration,
+) {
+    let mut changed: HashSet<BufferId> = HashSet::new();
+    let mut deadline = Instant::now() + delay;
+    let mut focused = true;
+    loop {
+        let event = if changed.is_empty() || !focused {
+            events.recv().await
+        } else {
+            match tokio::time::timeout_at(deadline, events.recv()).await {
+                Ok(event) => event,
+                Err(_) => {
+                    for buffer_id in changed.drain() {
+                        if cmd_tx
+                            .send(Command::SearchRefresh(buffer_id))
+                            .await
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+            }
+        };
+
+        match event {
+            Ok(AppEvent::BufferChanged(buffer_id, ..)) => {
+                changed.insert(buffer_id);
+                deadline = Instant::now() + delay;
+            }
+            Ok(AppEvent::FocusChanged(now)) => {
+                focused = now;
+                deadline = Instant::now() + delay;
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!(skipped, "search refresh missed events");
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Asks for each opened buffer to be synced with its language server right away, and each
+/// changed one once no change has been made to any buffer for `delay`.
+pub async fn sync_documents(
+    mut events: broadcast::Receiver<AppEvent>,
+    cmd_tx: mpsc::Sender<Command>,
+    delay: Duration,
+) {
+    let mut changed: HashSet<BufferId> = HashSet::new();
+    let mut deadline = Instant::now() + delay;
+    loop {
+        let event = if changed.is_empty() {
+            events.recv().await
+        } else {
+            match tokio::time::timeout_at(deadline, events.recv()).await {
+                Ok(event) => event,
+                Err(_) => {
+                    for buffer_id in changed.drain() {
+                        if cmd_tx.send(Command::LspSync(buffer_id)).await.is_err() {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+            }
+        };
+
+        match event {
+            Ok(AppEvent::BufferOpened(buffer_id, _)) => {
+                changed.remove(&buffer_id);
+                if cmd_tx.send(Command::LspSync(buffer_id)).await.is_err() {
+                    return;
+                }
+            }
+            Ok(AppEvent::BufferChanged(buffer_id, ..)) => {
+                changed.insert(buffer_id);
+                deadline = Instant::now() + delay;
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!(skipped, "language server sync missed events");
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Asks for every changed buffer to be journaled for crash recovery each `interval`, and for one
+/// changed `edits` times since it last was right away. A save leaves nothing to journal.
+pub async fn journal(
+    mut events: broadcast::Receiver<AppEvent>,
+    cmd_tx: mpsc::Sender<Command>,
+    interval: Duration,
+    edits: usize,
+) {
+    let mut changed: HashMap<BufferId, usize> = HashMap::new();
+    let mut ticks = tokio::time::interval_at(Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        let event = tokio::select! {
+            _ = ticks.tick() => {
+                for (buffer_id, _) in changed.drain() {
+                    if cmd_tx.send(Command::BufferJournal(buffer_id)).await.is_err() {
+                        return;
+                    }
+                }
+                continue;
+            }
+            event = events.recv() => event,
+        };
+
+        match event {
+            Ok(AppEvent::BufferChanged(buffer_id, ..)) => {
+                let count = changed.entry(buffer_id).or_default();
+                *count += 1;
+                if *count >= edits {
+                    changed.remove(&buffer_id);
+                    if cmd_tx
+                        .send(Command::BufferJournal(buffer_id))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+            Ok(AppEvent::BufferSaved(buffer_id)) => {
+                changed.remove(&buffer_id);
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!(skipped, "journal missed events");
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Asks for the config to be read again whenever any of `paths` is written, created or removed,
+/// looking each `interval`.
+pub async fn watch_config(paths: Vec<PathBuf>, cmd_tx: mpsc::Sender<Command>, interval: Duration) {
+    async fn modified(paths: &[PathBuf]) -> Vec<Option<std::time::SystemTime>> {
+        let mut modified = Vec::with_capacity(paths.len());
+        for path in paths {
+            let metadata = tokio::fs::metadata(path).await;
+            modified.push(metadata.and_then(|m| m.modified()).ok());
+        }
+        modified
+    }
+
+    let mut last = modified(&paths).await;
+    let mut ticks = tokio::time::interval_at(Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        let now = modified(&paths).await;
+        if now != last {
+            last = now;
+            if cmd_tx.send(Command::ConfigReload).await.is_err() {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// What `:export-html` and `:export-ansi` write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Html,
+    Ansi,
+}
+
+/// Splits an export's argument into the range of lines typed before the path, if one is, and the
+/// path. Only a word made of what a range can be is taken as one, so a path may have spaces.
+pub fn split(argument: &str) -> (Option<&str>, &str) {
+    let argument = argument.trim();
+    let is_range = |word: &str| {
+        word.chars()
+            .all(|c| c.is_ascii_digit() || ",.$%".contains(c))
+    };
+    match argument.split_once(char::is_whitespace) {
+        Some((range, path)) if is_range(range) => (Some(range), path.trim_start()),
+        _ => (None, argument),
+    }
+}
+
+/// The lines a range names, from `len_lines`: `%` for all of them, or a line or two
+/// comma-separated, from 1, each a number, `.` for the cursor's line or `$` for the last.
+pub fn lines(range: &str, cursor_line: usize, len_lines: usize) -> Result<Range<usize>, String> {
+    if range == "%" {
+        return Ok(0..len_lines);
+    }
+    let line = |line: &str| match line {
+        "." => Ok(cursor_line),
+        "$" => Ok(len_lines.saturating_sub(1)),
+        n => match n.parse::<usize>() {
+            Ok(n) if (1..=len_lines).contains(&n) => Ok(n - 1),
+            _ => Err(format!("no line '{}'", n)),
+        },
+    };
+    let (first, last) = range.split_once(',').unwrap_or((range, range));
+    let (first, last) = (line(first)?, line(last)?);
+    if first > last {
+        return Err(format!("backwards range '{}'", range));
+    }
+    Ok(first..last + 1)
+}
+
+/// Writes a file whole beside `path` then renames it over it, so `path` is never left half
+/// written, as recovery journals are.
+pub fn write(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<std::fs::File>) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let result = std::fs::File::create(&partial).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()?;
+        writer.into_inner()?.sync_all()?;
+        std::fs::rename(&partial, path)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_before_the_path() {
+        assert_eq!(split("out.html"), (None, "out.html"));
+        assert_eq!(split(" 2,$  my out.html"), (Some("2,$"), "my out.html"));
+        assert_eq!(split("my out.html"), (None, "my out.html"));
+
+        let lines = |range| lines(range, 4, 10);
+        assert_eq!(lines("%"), Ok(0..10));
+        assert_eq!(lines("3"), Ok(2..3));
+        assert_eq!(lines("2,$"), Ok(1..10));
+        assert_eq!(lines(".,7"), Ok(4..7));
+        assert_eq!(lines("11"), Err("no line '11'".to_string()));
+        assert_eq!(lines("5,2"), Err("backwards range '5,2'".to_string()));
+    }
+
+    #[test]
+    fn written_whole_or_not_at_all() {
+        let dir = test_support::temp_dir();
+        let path = dir.path().join("out.html");
+        write(&path, |out| out.write_all(b"first")).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first");
+
+        // a failure partway leaves the last export as it was, and nothing beside it
+        let failed = write(&path, |out| {
+            out.write_all(b"sec")?;
+            Err(std::io::Error::other("interrupted"))
+        });
+        assert!(failed.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first");
+        assert!(!dir.path().join("out.html.partial").exists());
+    }
+}
@@ -0,0 +1,132 @@
+use std::ops::Range;
+use std::path::Path;
+use std::process::Stdio;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use editor::BufferContents;
+
+/// What in a formatter's arguments is replaced with the lines to format, counting from 1.
+const START: &str = "{start}";
+const END: &str = "{end}";
+/// What in a formatter's arguments is replaced with the path of the file it formats.
+const PATH: &str = "{path}";
+
+/// Fills in the placeholders in a formatter's arguments: `{path}` with the file's path, and
+/// `{start}` and `{end}` with the first and last of `lines` counting from 1, or of every line
+/// without any. Fails if there are lines to format but no placeholder to say which.
+pub fn expand(
+    argv: &[String],
+    path: Option<&Path>,
+    lines: Option<Range<usize>>,
+    len_lines: usize,
+) -> Result<Vec<String>, String> {
+    let ranged = argv
+        .iter()
+        .any(|arg| arg.contains(START) || arg.contains(END));
+    if lines.is_some() && !ranged {
+        return Err("the formatter can't format only some lines".to_string());
+    }
+    let lines = lines.unwrap_or(0..len_lines.max(1));
+    let (start, end) = ((lines.start + 1).to_string(), lines.end.to_string());
+    let path = path.map_or(String::new(), |path| path.to_string_lossy().into_owned());
+    let argv = argv.iter().map(|arg| {
+        let arg = arg.replace(START, &start).replace(END, &end);
+        arg.replace(PATH, &path)
+    });
+    Ok(argv.collect())
+}
+
+/// Pipes `contents` through the program `argv` names, chunk by chunk rather than as one string,
+/// and returns what it wrote out. Fails with what it wrote to its standard error if it exited
+/// unsuccessfully, or how it exited if that's nothing.
+pub async fn format(argv: &[String], contents: BufferContents) -> Result<String, String> {
+    let Some((program, args)) = argv.split_first() else {
+        return Err("no formatter command".to_string());
+    };
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| format!("{}: {}", program, err))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+
+    // written while the output's read, so neither side waits on a full pipe
+    let write = async move {
+        for chunk in contents.chunks() {
+            stdin.write_all(chunk.as_bytes()).await?;
+        }
+        // closing it is how the formatter knows it has it all
+        drop(stdin);
+        Ok::<(), std::io::Error>(())
+    };
+    let (mut out, mut err) = (vec![], vec![]);
+    let (written, read_out, read_err) =
+        tokio::join!(write, stdout.read_to_end(&mut out), stderr.read_to_end(&mut err));
+    let status = child
+        .wait()
+        .await
+        .map_err(|err| format!("{}: {}", program, err))?;
+    if !status.success() {
+        let err = String::from_utf8_lossy(&err);
+        return Err(match err.trim_end() {
+            "" => format!("{} exited with {}", program, status),
+            err => err.to_string(),
+        });
+    }
+    let read = written.and(read_out).and(read_err);
+    read.map_err(|err| format!("{}: {}", program, err))?;
+    String::from_utf8(out).map_err(|_| format!("{} wrote invalid UTF-8", program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn placeholders() {
+        let ranged = argv(&["fmt", "--lines={start}:{end}", "{path}"]);
+        let path = Some(Path::new("/a.rs"));
+        assert_eq!(
+            expand(&ranged, path, Some(2..5), 10),
+            Ok(argv(&["fmt", "--lines=3:5", "/a.rs"]))
+        );
+        // every line, without a selection
+        assert_eq!(expand(&ranged, None, None, 10), Ok(argv(&["fmt", "--lines=1:10", ""])));
+
+        let whole = argv(&["fmt", "-"]);
+        assert_eq!(expand(&whole, path, None, 10), Ok(whole.clone()));
+        let err = Err("the formatter can't format only some lines".to_string());
+        assert_eq!(expand(&whole, path, Some(2..5), 10), err);
+    }
+
+    #[tokio::test]
+    async fn piped_through() {
+        // a long text, so it's more than one chunk and more than a pipe holds
+        let text = "fn main() {}\n".repeat(10_000);
+        let upper = format(&argv(&["tr", "a-z", "A-Z"]), text.as_str().into()).await;
+        assert_eq!(upper, Ok(text.to_uppercase()));
+    }
+
+    #[tokio::test]
+    async fn failures() {
+        let script = "cat >/dev/null; echo 'error: expected one of `;`' >&2; exit 1";
+        let failed = format(&argv(&["sh", "-c", script]), "x".into()).await;
+        assert_eq!(failed, Err("error: expected one of `;`".to_string()));
+
+        let silent = format(&argv(&["sh", "-c", "exit 2"]), "x".into()).await;
+        assert_eq!(silent, Err("sh exited with exit status: 2".to_string()));
+
+        let missing = format(&argv(&["no-such-formatter"]), "x".into()).await;
+        assert!(missing.unwrap_err().starts_with("no-such-formatter: "));
+    }
+}
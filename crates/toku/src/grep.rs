@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crossterm::cursor::SetCursorStyle;
+use ratatui::prelude as tui;
+use tokio::sync::mpsc;
+
+use editor::{BufferContents, CancellationToken};
+use selector::Selector;
+use tore::CursorPoint;
+
+use crate::app::Command;
+use crate::project::Exclude;
+
+/// Past this many matches a search stops, and says its results were cut short.
+pub const MAX_MATCHES: usize = 5000;
+/// A file with a NUL byte this near its start is taken to be binary, and skipped.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+/// Where a search found its query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+    pub path: PathBuf,
+    /// Counted from 0.
+    pub line_number: usize,
+    /// The line the match is on, without its line break.
+    pub line_text: String,
+    /// Where the match is in `line_text`.
+    pub byte_range: Range<usize>,
+}
+
+impl GrepMatch {
+    /// Where a cursor on the start of the match is.
+    pub fn point(&self) -> tore::Point {
+        let column = self.line_text[..self.byte_range.start].chars().count();
+        tore::Point { line: self.line_number, column }
+    }
+}
+
+/// The files under a directory, depth first in name order. Hidden files and directories are
+/// left out, as are those the project excludes, and symlinks aren't followed so a link back up
+/// the tree can't loop.
+pub struct Walk {
+    /// Paths still to visit, the next last, and whether each is a directory.
+    stack: Vec<(PathBuf, bool)>,
+    exclude: Exclude,
+}
+
+impl Walk {
+    pub fn new(root: &Path, exclude: Exclude) -> Self {
+        Self { stack: vec![(root.to_path_buf(), true)], exclude }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        while let Some((path, is_dir)) = self.stack.pop() {
+            if !is_dir {
+                return Some(path);
+            }
+            // a directory that can't be read is skipped like a hidden one
+            let Ok(dir) = std::fs::read_dir(&path) else {
+                continue;
+            };
+            let mut entries: Vec<_> = dir
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let hidden = entry.file_name().as_encoded_bytes().starts_with(b".");
+                    let file_type = entry.file_type().ok()?;
+                    let skip = hidden || file_type.is_symlink();
+                    let path = entry.path();
+                    let skip = skip || self.exclude.matches(&path);
+                    (!skip).then_some((path, file_type.is_dir()))
+                })
+                .collect();
+            entries.sort_by(|(a, _), (b, _)| b.cmp(a));
+            self.stack.extend(entries);
+        }
+        None
+    }
+}
+
+/// `path` made absolute against the working directory, for telling whether two paths are the
+/// same file without touching the filesystem.
+pub fn normalize(path: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().map_or(path.to_path_buf(), |dir| dir.join(path))
+    };
+    path.components().collect()
+}
+
+/// Searches the files under `root`, but those `exclude` leaves out, for `query`, handing `emit`
+/// each file's matches as they're found. Files in `buffers`, keyed by their normalized path, are
+/// searched as they are in the editor rather than as they are on disk. Returns whether there
+/// were more than `limit` matches; stops early, as if there weren't, if cancelled or `emit`
+/// returns false.
+pub fn search(
+    root: &Path,
+    exclude: &Exclude,
+    query: &str,
+    buffers: &HashMap<PathBuf, BufferContents>,
+    limit: usize,
+    cancel: &CancellationToken,
+    mut emit: impl FnMut(Vec<GrepMatch>) -> bool,
+) -> bool {
+    let mut found = 0;
+    for path in Walk::new(root, exclude.clone()) {
+        if cancel.is_cancelled() {
+            return false;
+        }
+        let text = match buffers.get(&normalize(&path)) {
+            Some(contents) => contents.to_string(),
+            None => match std::fs::read(&path) {
+                Ok(bytes) if !is_binary(&bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                // gone since the walk, unreadable, or binary
+                _ => continue,
+            },
+        };
+        let (matches, truncated) = find(&path, &text, query, limit - found);
+        found += matches.len();
+        if !matches.is_empty() && !emit(matches) {
+            return false;
+        }
+        if truncated {
+            return true;
+        }
+    }
+    false
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+/// Up to `max` matches of `query` in `text`, and whether there were more.
+fn find(path: &Path, text: &str, query: &str, max: usize) -> (Vec<GrepMatch>, bool) {
+    let mut matches = vec![];
+    for (line_number, line) in text.lines().enumerate() {
+        for (start, _) in line.match_indices(query) {
+            if matches.len() == max {
+                return (matches, true);
+            }
+            matches.push(GrepMatch {
+                path: path.to_path_buf(),
+                line_number,
+                line_text: line.to_string(),
+                byte_range: start..start + query.len(),
+            });
+        }
+    }
+    (matches, false)
+}
+
+/// Runs `search` off the main loop, sending its matches back a file at a time as
+/// `GrepMatches`, then `GrepDone`.
+pub async fn grep(
+    search_id: u64,
+    root: PathBuf,
+    exclude: Exclude,
+    query: String,
+    buffers: HashMap<PathBuf, BufferContents>,
+    cancel: CancellationToken,
+    cmd_tx: mpsc::Sender<Command>,
+) {
+    let search = tokio::task::spawn_blocking(move || {
+        let emit = |matches| {
+            let command = Command::GrepMatches(search_id, matches);
+            cmd_tx.blocking_send(command).is_ok()
+        };
+        let truncated = search(&root, &exclude, &query, &buffers, MAX_MATCHES, &cancel, emit);
+        let _ = cmd_tx.blocking_send(Command::GrepDone(search_id, truncated));
+    });
+    let _ = search.await;
+}
+
+/// The matches of the last search, listed in the grep pane as they come in.
+#[derive(Debug)]
+pub struct GrepResults {
+    pub selector: Selector<usize>,
+    matches: Vec<GrepMatch>,
+    root: PathBuf,
+    query: String,
+    /// Counts searches, so matches still coming from one that was replaced are dropped.
+    search_id: u64,
+    cancel: CancellationToken,
+}
+
+impl GrepResults {
+    pub fn new() -> Self {
+        Self {
+            selector: Selector::new("grep: "),
+            matches: vec![],
+            root: PathBuf::new(),
+            query: String::new(),
+            search_id: 0,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Clears the results for a search for `query` under `root`, stopping the last one. Returns
+    /// the new search's id and what cancels it.
+    pub fn start(&mut self, root: PathBuf, query: String) -> (u64, CancellationToken) {
+        self.stop();
+        self.selector.query.set(&query);
+        self.root = root;
+        self.query = query;
+        self.search_id += 1;
+        self.cancel = CancellationToken::new();
+        (self.search_id, self.cancel.clone())
+    }
+
+    /// Stops the running search, if any, and forgets its results.
+    pub fn stop(&mut self) {
+        self.cancel.cancel();
+        self.matches.clear();
+        self.selector.reset();
+        self.selector.command(selector::Command::SetEntries(vec![]));
+    }
+
+    /// Adds matches found by search `search_id`, if it's still the current one.
+    pub fn add(&mut self, search_id: u64, matches: Vec<GrepMatch>) {
+        if search_id != self.search_id || self.cancel.is_cancelled() {
+            return;
+        }
+        self.matches.extend(matches);
+        let entries = (0..self.matches.len()).collect();
+        self.selector
+            .command(selector::Command::SetEntries(entries));
+    }
+
+    /// What's said about search `search_id` once it's done, if it's still the current one.
+    pub fn summary(&self, search_id: u64, truncated: bool) -> Option<String> {
+        if search_id != self.search_id || self.cancel.is_cancelled() {
+            return None;
+        }
+        let summary = match (self.matches.len(), truncated) {
+            (0, _) => format!("no matches for \"{}\"", self.query),
+            (1, false) => format!("1 match for \"{}\"", self.query),
+            (n, false) => format!("{} matches for \"{}\"", n, self.query),
+            (n, true) => format!("showing the first {} matches for \"{}\"", n, self.query),
+        };
+        Some(summary)
+    }
+
+    pub fn focused(&self) -> Option<&GrepMatch> {
+        self.selector.focused.map(|index| &self.matches[index])
+    }
+
+    pub fn render(
+        &self,
+        buf: &mut tui::Buffer,
+        area: tui::Rect,
+        theme: &ui::Theme,
+    ) -> (CursorPoint, SetCursorStyle) {
+        let widget = ui::SelectorPane::new(theme, &self.selector);
+        widget.render(buf, area, self.selector.entries.iter().copied(), |area, buf, index| {
+            self.render_match(area, buf, &self.matches[index])
+        })
+    }
+
+    /// Draws a match as `path:line: text`, with the matched text in bold.
+    fn render_match(&self, area: tui::Rect, buf: &mut tui::Buffer, m: &GrepMatch) {
+        use bstr::ByteSlice;
+
+        let path = m.path.strip_prefix(&self.root).unwrap_or(&m.path);
+        let location = format!("{}:{}: ", path.display(), m.line_number + 1);
+        let text = m.line_text.trim_start();
+        let trimmed = m.line_text.len() - text.len();
+        let bold = location.len() + m.byte_range.start.saturating_sub(trimmed)
+            ..location.len() + m.byte_range.end.saturating_sub(trimmed);
+        let content = location + text;
+        let mut graphemes = content.as_bytes().as_bstr().grapheme_indices();
+        for x in area.left()..area.right() {
+            let (start, symbol) = match graphemes.next() {
+                Some((start, _, symbol)) => (start, symbol),
+                None => (content.len(), " "),
+            };
+            let style = if bold.contains(&start) {
+                tui::Style::reset().add_modifier(tui::Modifier::BOLD)
+            } else {
+                tui::Style::reset()
+            };
+            buf.get_mut(x, area.top())
+                .set_style(style)
+                .set_symbol(symbol);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_support::TempDir;
+
+    fn fixture() -> TempDir {
+        let dir = test_support::temp_dir();
+        let files: [(&str, &[u8]); 6] = [
+            ("a.rs", b"fn needle() {}\nlet x = needle();\n"),
+            ("b.txt", b"no match here\n"),
+            ("sub/c.rs", b"// needle needle\n"),
+            ("sub/data.bin", b"needle\0\x01\x02"),
+            (".hidden/d.rs", b"needle\n"),
+            ("z.rs", b"\xffneedle\r\n"),
+        ];
+        for (path, contents) in files {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn summarize(matches: &[GrepMatch], root: &Path) -> Vec<(String, usize, Range<usize>)> {
+        let summarize = |m: &GrepMatch| {
+            let path = m.path.strip_prefix(root).unwrap().display().to_string();
+            (path, m.line_number, m.byte_range.clone())
+        };
+        matches.iter().map(summarize).collect()
+    }
+
+    #[test]
+    fn streams_matches_by_file() {
+        let dir = fixture();
+        let root = dir.path();
+        let cancel = CancellationToken::new();
+        let mut batches = vec![];
+        let none = Exclude::default();
+        let truncated = search(root, &none, "needle", &HashMap::new(), 100, &cancel, |matches| {
+            batches.push(summarize(&matches, root));
+            true
+        });
+        assert!(!truncated);
+        // hidden and binary files are skipped, and undecodable bytes don't hide a match
+        let expected = vec![
+            vec![("a.rs".into(), 0, 3..9), ("a.rs".into(), 1, 8..14)],
+            vec![("sub/c.rs".into(), 0, 3..9), ("sub/c.rs".into(), 0, 10..16)],
+            vec![("z.rs".into(), 0, 3..9)],
+        ];
+        assert_eq!(batches, expected);
+
+        // an open buffer is searched as it is in the editor
+        let edited = BufferContents::from("fn a() {}\n\n  needle\n");
+        let buffers = HashMap::from([(normalize(&root.join("a.rs")), edited)]);
+        let mut found = vec![];
+        search(root, &none, "needle", &buffers, 100, &cancel, |matches| {
+            found.extend(matches);
+            true
+        });
+        assert_eq!(found[0].line_text, "  needle");
+        assert_eq!(found[0].point(), tore::Point { line: 2, column: 2 });
+        assert_eq!(found.len(), 4);
+
+        // a search that finds too much stops at the limit and says so
+        let mut found = vec![];
+        let truncated = search(root, &none, "needle", &HashMap::new(), 3, &cancel, |matches| {
+            found.extend(matches);
+            true
+        });
+        assert!(truncated);
+        assert_eq!(found.len(), 3);
+
+        // and one that's cancelled stops at the next file
+        let mut batches = 0;
+        search(root, &none, "needle", &HashMap::new(), 100, &cancel, |_| {
+            batches += 1;
+            cancel.cancel();
+            true
+        });
+        assert_eq!(batches, 1);
+    }
+
+    #[test]
+    fn project_excludes_are_left_out() -> anyhow::Result<()> {
+        let dir = test_support::temp_dir();
+        let root = dir.path();
+        for path in [
+            "a.rs",
+            "target/b.rs",
+            "sub/target/c.rs",
+            "docs/d.md",
+            "sub/docs/e.md",
+        ] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, "needle\n")?;
+        }
+        let config = "exclude = [\"target\", \"/docs\"]\n";
+        std::fs::write(root.join(crate::project::FILE_NAME), config)?;
+
+        let mut projects = crate::project::Projects::default();
+        let project = projects.for_dir(root)?.expect("a project");
+        let cancel = CancellationToken::new();
+        let mut found = vec![];
+        // searched from below the project's root, the excludes are still the project's
+        let sub = root.join("sub");
+        for dir in [root, &sub] {
+            search(dir, &project.exclude, "needle", &HashMap::new(), 10, &cancel, |matches| {
+                found.extend(summarize(&matches, root));
+                true
+            });
+        }
+        let paths: Vec<_> = found.into_iter().map(|(path, _, _)| path).collect();
+        assert_eq!(paths, ["a.rs", "sub/docs/e.md", "sub/docs/e.md"]);
+        Ok(())
+    }
+
+    #[test]
+    fn results_drop_replaced_searches() {
+        let mut results = GrepResults::new();
+        let m = |line_number| GrepMatch {
+            path: "a.rs".into(),
+            line_number,
+            line_text: "needle".into(),
+            byte_range: 0..6,
+        };
+        let (first, cancel) = results.start(PathBuf::new(), "needle".into());
+        results.add(first, vec![m(0), m(1)]);
+        assert_eq!(results.focused(), Some(&m(0)));
+
+        let (second, _) = results.start(PathBuf::new(), "needle".into());
+        assert!(cancel.is_cancelled());
+        results.add(first, vec![m(2)]);
+        assert_eq!(results.selector.entries, Vec::<usize>::new());
+        assert_eq!(results.summary(first, false), None);
+        results.add(second, vec![m(3)]);
+        assert_eq!(results.focused(), Some(&m(3)));
+        assert_eq!(
+            results.summary(second, true).unwrap(),
+            "showing the first 1 matches for \"needle\""
+        );
+    }
+}
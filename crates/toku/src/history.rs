@@ -0,0 +1,130 @@
+use slotmap::{SecondaryMap, SlotMap};
+
+use editor::{BufferId, HistoryAccounting};
+
+/// Keeps the undo history of every buffer together within a budget of estimated bytes. Once
+/// they're over it, the buffers edited longest ago give up their oldest states first.
+#[derive(Debug)]
+pub struct HistoryBudget {
+    pub max_bytes: usize,
+    /// How many states each buffer keeps on the way back from its current one, over budget or
+    /// not.
+    pub min_depth: usize,
+    /// When each buffer was last edited, as a count of edits.
+    edited: SecondaryMap<BufferId, u64>,
+    edits: u64,
+}
+
+impl Default for HistoryBudget {
+    fn default() -> Self {
+        Self { max_bytes: 128 << 20, min_depth: 100, edited: SecondaryMap::new(), edits: 0 }
+    }
+}
+
+impl HistoryBudget {
+    /// The buffer was edited, so it's the last whose history is given up.
+    pub fn edited(&mut self, buffer_id: BufferId) {
+        self.edits += 1;
+        self.edited.insert(buffer_id, self.edits);
+    }
+
+    /// Drops the oldest states of the buffers edited longest ago until their histories are
+    /// within the budget, or each is down to `min_depth`. Returns the bytes still retained.
+    pub fn enforce<B: HistoryAccounting>(&mut self, buffers: &mut SlotMap<BufferId, B>) -> usize {
+        let mut retained: usize = buffers.values().map(|b| b.retained_bytes()).sum();
+        if retained <= self.max_bytes {
+            return retained;
+        }
+        let mut order: Vec<_> = buffers.keys().collect();
+        // a buffer never edited has nothing to give up, but it goes first all the same
+        order.sort_by_key(|buffer_id| self.edited.get(*buffer_id).copied().unwrap_or(0));
+        for buffer_id in order {
+            let buffer = &mut buffers[buffer_id];
+            while retained > self.max_bytes {
+                match buffer.drop_oldest_state(self.min_depth) {
+                    Some(freed) => retained -= freed,
+                    None => break,
+                }
+            }
+        }
+        retained
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::Buffer;
+
+    /// Makes `count` edits to the buffer, each inserting `size` bytes at the end.
+    fn edit(buffer: &mut Buffer, count: usize, size: usize) {
+        let text = "x".repeat(size);
+        for _ in 0..count {
+            let len = buffer.contents.len_chars();
+            buffer.apply_edits(&[(len..len, text.as_str())]).unwrap();
+        }
+    }
+
+    fn undo_depth(buffer: &Buffer) -> usize {
+        let tree = buffer.undo_tree();
+        let mut depth = 0;
+        let mut state = tree.get(tree.current()).and_then(|s| s.parent());
+        while let Some(parent) = state {
+            depth += 1;
+            state = tree.get(parent).and_then(|s| s.parent());
+        }
+        depth
+    }
+
+    #[test]
+    fn least_recently_edited_give_up_history_first() {
+        let mut buffers: SlotMap<BufferId, Buffer> = SlotMap::with_key();
+        let ids: Vec<_> = (0..4)
+            .map(|_| buffers.insert_with_key(|id| Buffer::new(id, "".into())))
+            .collect();
+        let mut budget = HistoryBudget { max_bytes: 140_000, min_depth: 1, ..Default::default() };
+        // each edit keeps 10k bytes and then some, so four buffers of five are over
+        for &buffer_id in &ids {
+            edit(&mut buffers[buffer_id], 5, 10_000);
+            budget.edited(buffer_id);
+        }
+        let before: Vec<_> = ids.iter().map(|id| buffers[*id].retained_bytes()).collect();
+        let total: usize = before.iter().sum();
+        assert!(total > 200_000);
+
+        let retained = budget.enforce(&mut buffers);
+        assert!(retained <= budget.max_bytes, "{}", retained);
+        let after: usize = ids.iter().map(|id| buffers[*id].retained_bytes()).sum();
+        assert_eq!(after, retained);
+        // the first edited are down to their minimum, the third only as far as it took, and
+        // the last untouched
+        assert_eq!(undo_depth(&buffers[ids[0]]), 1);
+        assert_eq!(undo_depth(&buffers[ids[1]]), 1);
+        assert_eq!(undo_depth(&buffers[ids[2]]), 2);
+        assert_eq!(buffers[ids[3]].retained_bytes(), before[3]);
+        assert_eq!(undo_depth(&buffers[ids[3]]), 5);
+
+        // editing the first again makes it the last to give anything up
+        edit(&mut buffers[ids[0]], 2, 10_000);
+        budget.edited(ids[0]);
+        budget.enforce(&mut buffers);
+        assert_eq!(undo_depth(&buffers[ids[0]]), 3);
+        assert_eq!(undo_depth(&buffers[ids[2]]), 1);
+        assert_eq!(undo_depth(&buffers[ids[3]]), 3);
+
+        // the minimum is kept even when that's over budget
+        budget.max_bytes = 0;
+        let retained = budget.enforce(&mut buffers);
+        assert!(retained > 0);
+        for &buffer_id in &ids {
+            assert_eq!(undo_depth(&buffers[buffer_id]), 1);
+        }
+
+        // and what's kept can still be undone to
+        let buffer = &mut buffers[ids[2]];
+        let tree = buffer.undo_tree();
+        let target = tree.get(tree.current()).and_then(|s| s.parent()).unwrap();
+        assert!(buffer.undo_to(target).is_some());
+        assert_eq!(buffer.contents.len_chars(), 40_000);
+    }
+}
@@ -0,0 +1,256 @@
+use std::time::{Duration, Instant};
+
+/// How many of the latest inputs `:profile` reports on.
+pub const SAMPLES: usize = 512;
+/// Reads one stage's duration out of a sample.
+type Stage = fn(&Sample) -> Duration;
+/// The upper bounds of the histogram's buckets; the last bucket has everything slower.
+const BUCKETS: [Duration; 7] = [
+    Duration::from_millis(1),
+    Duration::from_millis(2),
+    Duration::from_millis(4),
+    Duration::from_millis(8),
+    Duration::from_millis(16),
+    Duration::from_millis(32),
+    Duration::from_millis(64),
+];
+
+/// How long an input took to reach the screen, by where the time went.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sample {
+    /// Turning the terminal event into a command.
+    pub input: Duration,
+    /// Running the commands that followed, up to the frame.
+    pub apply: Duration,
+    /// Waiting for the frame, which is drawn at most once an interval.
+    pub wait: Duration,
+    /// Drawing the frame that shows it.
+    pub draw: Duration,
+}
+
+impl Sample {
+    pub fn total(&self) -> Duration {
+        self.input + self.apply + self.wait + self.draw
+    }
+}
+
+/// An input not yet on screen: when the first event since the last frame came in, and the time
+/// spent on it so far. Events that come in before a frame share it, timed from the first.
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    received: Instant,
+    input: Duration,
+    apply: Duration,
+}
+
+/// Times inputs from when they come in to the end of the frame that shows them, keeping the
+/// latest `SAMPLES` in a ring.
+#[derive(Debug)]
+pub struct Latency {
+    samples: Vec<Sample>,
+    /// Where the next sample goes, once the ring is full.
+    next: usize,
+    pending: Option<Pending>,
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Self { samples: Vec::with_capacity(SAMPLES), next: 0, pending: None }
+    }
+}
+
+impl Latency {
+    /// An event came in at `received` and was turned into a command by `handled`.
+    pub fn input(&mut self, received: Instant, handled: Instant) {
+        let elapsed = handled.saturating_duration_since(received);
+        match &mut self.pending {
+            Some(pending) => pending.input += elapsed,
+            None => {
+                let apply = Duration::ZERO;
+                self.pending = Some(Pending { received, input: elapsed, apply });
+            }
+        }
+    }
+
+    /// Commands ran for `elapsed`; it counts against the input waiting for a frame, if any.
+    pub fn applied(&mut self, elapsed: Duration) {
+        if let Some(pending) = &mut self.pending {
+            pending.apply += elapsed;
+        }
+    }
+
+    /// A frame was drawn from `start` to `end`, putting the input waiting for it on screen.
+    pub fn drawn(&mut self, start: Instant, end: Instant) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        let before = start.saturating_duration_since(pending.received);
+        let sample = Sample {
+            input: pending.input,
+            apply: pending.apply,
+            wait: before.saturating_sub(pending.input + pending.apply),
+            draw: end.saturating_duration_since(start),
+        };
+        if self.samples.len() < SAMPLES {
+            self.samples.push(sample);
+        } else {
+            self.samples[self.next] = sample;
+        }
+        self.next = (self.next + 1) % SAMPLES;
+    }
+
+    /// The input drawn last.
+    pub fn last(&self) -> Option<Sample> {
+        let last = self.next.checked_sub(1).unwrap_or(SAMPLES - 1);
+        self.samples.get(last).copied()
+    }
+
+    /// How many of the samples' totals fall in each bucket, the fastest first.
+    fn buckets(&self) -> [usize; BUCKETS.len() + 1] {
+        let mut counts = [0; BUCKETS.len() + 1];
+        for sample in &self.samples {
+            let total = sample.total();
+            counts[BUCKETS.partition_point(|bound| *bound < total)] += 1;
+        }
+        counts
+    }
+
+    /// The p50, p95 and max of one stage over the samples.
+    fn percentiles(&self, stage: impl Fn(&Sample) -> Duration) -> [Duration; 3] {
+        let mut times: Vec<_> = self.samples.iter().map(stage).collect();
+        times.sort_unstable();
+        let rank = |p: usize| times[(times.len() * p).div_ceil(100).max(1) - 1];
+        [rank(50), rank(95), rank(100)]
+    }
+
+    /// What `:profile` shows: each stage's percentiles, and a histogram of the totals.
+    pub fn report(&self) -> String {
+        if self.samples.is_empty() {
+            return "no input has been drawn yet\n".to_string();
+        }
+        let mut report = vec![
+            format!(
+                "the last {} inputs, from the event to the end of the frame showing it",
+                self.samples.len()
+            ),
+            String::new(),
+            format!("{:<8}{:>10}{:>10}{:>10}", "stage", "p50", "p95", "max"),
+        ];
+        let stages: [(&str, Stage); 5] = [
+            ("input", |sample| sample.input),
+            ("apply", |sample| sample.apply),
+            ("wait", |sample| sample.wait),
+            ("draw", |sample| sample.draw),
+            ("total", Sample::total),
+        ];
+        for (name, stage) in stages {
+            let [p50, p95, max] = self.percentiles(stage).map(format_duration);
+            report.push(format!("{:<8}{:>10}{:>10}{:>10}", name, p50, p95, max));
+        }
+        report.push(String::new());
+        let buckets = self.buckets();
+        let most = buckets.iter().copied().max().unwrap_or(0).max(1);
+        for (i, count) in buckets.iter().enumerate() {
+            let bound = match BUCKETS.get(i) {
+                Some(bound) => format!("<= {}", format_duration(*bound)),
+                None => format!("> {}", format_duration(BUCKETS[BUCKETS.len() - 1])),
+            };
+            let bar = "#".repeat((count * 40).div_ceil(most));
+            report.push(format!("{:<10}{:>5} {}", bound, count, bar));
+        }
+        report.join("\n") + "\n"
+    }
+}
+
+/// A duration in milliseconds, with a tenth of one under ten.
+pub fn format_duration(duration: Duration) -> String {
+    let ms = duration.as_secs_f64() * 1000.0;
+    if ms < 10.0 {
+        format!("{:.1}ms", ms)
+    } else {
+        format!("{:.0}ms", ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A clock that only moves when told, standing in for the loop's stages.
+    struct Clock(Instant);
+
+    impl Clock {
+        fn sleep(&mut self, ms: u64) -> Instant {
+            self.0 += Duration::from_millis(ms);
+            self.0
+        }
+    }
+
+    #[test]
+    fn stages_attributed() {
+        let mut clock = Clock(Instant::now());
+        let mut latency = Latency::default();
+        assert_eq!(latency.last(), None);
+
+        // a frame with no input waiting is nobody's
+        let start = clock.0;
+        latency.drawn(start, clock.sleep(5));
+        assert_eq!(latency.last(), None);
+
+        // two keys before a frame are timed from the first
+        let received = clock.0;
+        latency.input(received, clock.sleep(1));
+        latency.applied(Duration::from_millis(3));
+        clock.sleep(3);
+        let received = clock.sleep(2);
+        latency.input(received, clock.sleep(1));
+        clock.sleep(4);
+        let start = clock.0;
+        latency.drawn(start, clock.sleep(6));
+        let sample = latency.last().unwrap();
+        let ms = Duration::from_millis;
+        assert_eq!(
+            (sample.input, sample.apply, sample.wait, sample.draw),
+            (ms(2), ms(3), ms(6), ms(6))
+        );
+        assert_eq!(sample.total(), ms(17));
+
+        // commands run with nothing waiting aren't counted against the next input
+        latency.applied(ms(50));
+        let received = clock.0;
+        latency.input(received, received);
+        let start = clock.sleep(1);
+        latency.drawn(start, clock.sleep(1));
+        assert_eq!(latency.last().map(|sample| sample.apply), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn histogram_of_the_latest() {
+        let mut clock = Clock(Instant::now());
+        let mut latency = Latency::default();
+        let mut frame = |latency: &mut Latency, draw_ms| {
+            let received = clock.0;
+            latency.input(received, received);
+            let start = clock.0;
+            latency.drawn(start, clock.sleep(draw_ms));
+        };
+        for draw_ms in [1, 3, 3, 10, 100] {
+            frame(&mut latency, draw_ms);
+        }
+        assert_eq!(latency.buckets(), [1, 0, 2, 0, 1, 0, 0, 1]);
+        let ms = Duration::from_millis;
+        assert_eq!(latency.percentiles(|sample| sample.draw), [ms(3), ms(100), ms(100)]);
+        let report = latency.report();
+        assert!(report.contains("draw         3.0ms     100ms     100ms"), "{}", report);
+        assert!(report.contains("> 64ms        1 ########"), "{}", report);
+
+        // only the latest are kept, the oldest dropped first
+        for _ in 0..SAMPLES {
+            frame(&mut latency, 2);
+        }
+        assert_eq!(latency.buckets(), [0, SAMPLES, 0, 0, 0, 0, 0, 0]);
+        frame(&mut latency, 20);
+        assert_eq!(latency.last().map(|sample| sample.total()), Some(ms(20)));
+        assert_eq!(latency.buckets()[1..=5], [SAMPLES - 1, 0, 0, 0, 1]);
+    }
+}
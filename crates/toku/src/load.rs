@@ -0,0 +1,144 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tokio::io::AsyncRead;
+use tokio::sync::mpsc;
+
+use editor::{CancellationToken, Loader};
+
+use crate::app::{Command, LoadId};
+
+/// Reads `path` off the main loop, reporting progress as it goes and the contents at the end.
+pub async fn load_file(
+    load_id: LoadId,
+    path: PathBuf,
+    lossy: bool,
+    cancel: CancellationToken,
+    cmd_tx: mpsc::Sender<Command>,
+) {
+    let loaded = match tokio::fs::File::open(&path).await {
+        Ok(file) => {
+            let total = file.metadata().await.ok().map(|metadata| metadata.len());
+            load(load_id, Loader::new(file, total, lossy, cancel), &cmd_tx).await
+        }
+        Err(err) => Err(err.into()),
+    };
+    let _ = cmd_tx
+        .send(Command::FileLoaded(load_id, loaded.map_err(Arc::new)))
+        .await;
+}
+
+/// Drives `loader` to the end, sending a `FileLoadProgress` whenever another percent has been
+/// read, or after every chunk if the size isn't known.
+async fn load<R: AsyncRead + Unpin>(
+    load_id: LoadId,
+    mut loader: Loader<R>,
+    cmd_tx: &mpsc::Sender<Command>,
+) -> anyhow::Result<(editor::BufferContents, editor::Format, usize)> {
+    let total = loader.total();
+    let percent = |read: u64| total.map(|total| read * 100 / total.max(1));
+    let mut reported = None;
+    while let Some(read) = loader.next().await {
+        let read = read?;
+        if total.is_some() && percent(read) == reported {
+            continue;
+        }
+        reported = percent(read);
+        cmd_tx
+            .send(Command::FileLoadProgress(load_id, read, total))
+            .await?;
+    }
+    Ok(loader.finish())
+}
+
+/// What's shown while `path` loads.
+pub fn progress_message(path: &std::path::Path, read: u64, total: Option<u64>) -> String {
+    match total {
+        Some(total) => format!("opening {}: {}%", path.display(), read * 100 / total.max(1)),
+        None => format!("opening {}: {} KiB", path.display(), read / 1024),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::ReadBuf;
+
+    /// Hands out `step` bytes at a time, and only every other time it's polled.
+    struct SlowReader {
+        data: Vec<u8>,
+        pos: usize,
+        step: usize,
+        ready: bool,
+    }
+
+    impl AsyncRead for SlowReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            self.ready = !self.ready;
+            if self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let end = (self.pos + self.step)
+                .min(self.data.len())
+                .min(self.pos + buf.remaining());
+            buf.put_slice(&self.data[self.pos..end]);
+            self.pos = end;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn reader(len: usize) -> SlowReader {
+        SlowReader { data: vec![b'a'; len], pos: 0, step: 100, ready: false }
+    }
+
+    #[test]
+    fn reports_progress() -> anyhow::Result<()> {
+        let rt = tokio::runtime::Builder::new_current_thread().build()?;
+        rt.block_on(async {
+            let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+            let load_id = LoadId::default();
+            let loader = Loader::new(reader(1000), Some(1000), false, CancellationToken::new());
+            let load = tokio::spawn(async move { load(load_id, loader, &cmd_tx).await });
+
+            let mut progress = vec![];
+            while let Some(Command::FileLoadProgress(id, read, total)) = cmd_rx.recv().await {
+                assert_eq!((id, total), (load_id, Some(1000)));
+                progress.push(read);
+            }
+            assert_eq!(progress, (1..=10).map(|i| i * 100).collect::<Vec<_>>());
+            let (contents, ..) = load.await??;
+            assert_eq!(contents.len_bytes(), 1000);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn cancel_stops_load() -> anyhow::Result<()> {
+        let rt = tokio::runtime::Builder::new_current_thread().build()?;
+        rt.block_on(async {
+            let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+            let cancel = CancellationToken::new();
+            let loader = Loader::new(reader(100_000), None, false, cancel.clone());
+            let load = tokio::spawn(async move { load(LoadId::default(), loader, &cmd_tx).await });
+
+            assert!(matches!(cmd_rx.recv().await, Some(Command::FileLoadProgress(_, 100, None))));
+            cancel.cancel();
+            // a chunk already read may still be reported, but nothing after it
+            let mut reported = 0;
+            while let Some(Command::FileLoadProgress(..)) = cmd_rx.recv().await {
+                reported += 1;
+            }
+            assert!(reported <= 1, "{} chunks reported after cancelling", reported);
+            let err = load.await?.unwrap_err();
+            assert!(err.is::<editor::Cancelled>());
+            Ok(())
+        })
+    }
+}
@@ -1,10 +1,29 @@
-use anyhow::{Context, Result};
-use clap::Parser;
+use anyhow::Result;
+use clap::{CommandFactory, Parser};
 use lazy_static::lazy_static;
 
 mod app;
-
-use app::App;
+mod config;
+mod editorconfig;
+mod events;
+mod export;
+mod format;
+mod grep;
+mod history;
+mod latency;
+mod load;
+mod outline;
+mod path;
+mod project;
+mod prompt;
+mod recovery;
+mod registers;
+mod run;
+mod terminal;
+mod validate;
+
+use app::{App, OpenLayout, Startup, StartupJump};
+use terminal::Viewport;
 
 lazy_static! {
     pub(crate) static ref PROJECT_NAME: String = env!("CARGO_CRATE_NAME").to_string();
@@ -15,22 +34,115 @@ lazy_static! {
 
 #[derive(Debug, Parser)]
 pub struct Args {
-    /// Paths to files to open
+    /// Open each file in a split pane of its own, the first on top
+    #[arg(short = 'o')]
+    split: bool,
+    /// Open the two files read-only side by side, scrolled together, with the lines that differ
+    /// shaded
+    #[arg(long, conflicts_with = "split")]
+    diff: bool,
+    /// Seconds without edits before changed buffers are saved; 0 disables auto-save
+    #[arg(long, value_name = "SECS", default_value_t = 2)]
+    auto_save: u64,
+    /// Open every file with unsaved changes left by a crash, and restore them
+    #[arg(long)]
+    recover: bool,
+    /// Audit buffers' summaries after every edit, reporting any drift; also TOKU_VALIDATE=1
+    #[arg(long)]
+    validate: bool,
+    /// Draw in the bottom ROWS rows of the terminal, keeping what's above, instead of taking the
+    /// whole screen
+    #[arg(long, value_name = "ROWS", value_parser = clap::value_parser!(u16).range(1..))]
+    inline: Option<u16>,
+    /// Run COMMAND as if typed into the command palette once every file is open; may be given
+    /// more than once, and they run in order
+    #[arg(short = 'c', value_name = "COMMAND")]
+    commands: Vec<String>,
+    /// Paths to files to open; `+LINE` or `+/PATTERN` puts the cursor in the first one at the
+    /// line or the first match
     paths: Option<Vec<std::path::PathBuf>>,
 }
 
-fn main() -> Result<()> {
-    use crossterm::terminal;
+impl Args {
+    fn layout(&self) -> OpenLayout {
+        if self.diff {
+            OpenLayout::Diff
+        } else if self.split {
+            OpenLayout::Splits
+        } else {
+            OpenLayout::Buffers
+        }
+    }
+
+    /// Fails on what clap can't tell is wrong by itself: a diff of anything but two files.
+    fn check(&self) -> Result<(), clap::Error> {
+        if self.diff && self.startup().paths.len() != 2 {
+            let kind = clap::error::ErrorKind::WrongNumberOfValues;
+            return Err(Self::command().error(kind, "--diff takes two files"));
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> bool {
+        self.validate || std::env::var("TOKU_VALIDATE").is_ok_and(|value| value == "1")
+    }
+
+    fn auto_save(&self) -> Option<std::time::Duration> {
+        (self.auto_save > 0).then(|| std::time::Duration::from_secs(self.auto_save))
+    }
+
+    fn viewport(&self) -> Viewport {
+        self.inline.map_or(Viewport::Fullscreen, Viewport::Inline)
+    }
+
+    /// The files to open and what to run once they are, telling a `+` jump apart from paths.
+    fn startup(&self) -> Startup {
+        let mut startup = Startup {
+            recover: self.recover,
+            commands: self.commands.clone(),
+            ..Default::default()
+        };
+        for path in self.paths.iter().flatten() {
+            match path
+                .to_str()
+                .and_then(|arg| arg.strip_prefix('+'))
+                .and_then(jump)
+            {
+                Some(jump) => startup.jump = Some(jump),
+                None => startup.paths.push(path.clone()),
+            }
+        }
+        startup
+    }
+}
 
+/// The jump a `+LINE` or `+/PATTERN` argument asks for, given what follows the `+`.
+fn jump(arg: &str) -> Option<StartupJump> {
+    match arg.strip_prefix('/') {
+        Some(pattern) if !pattern.is_empty() => Some(StartupJump::Search(pattern.to_string())),
+        Some(_) => None,
+        None => arg.parse().ok().map(StartupJump::Line),
+    }
+}
+
+fn main() -> Result<()> {
     let args = Args::parse();
+    if let Err(err) = args.check() {
+        err.exit();
+    }
     let supports_keyboard_enhancement =
-        matches!(terminal::supports_keyboard_enhancement(), Ok(true));
-    setup_panic_handler(supports_keyboard_enhancement);
+        matches!(crossterm::terminal::supports_keyboard_enhancement(), Ok(true));
+    let viewport = args.viewport();
+    setup_panic_handler(supports_keyboard_enhancement, viewport);
     setup_logging()?;
-    terminal_enter(supports_keyboard_enhancement)?;
-
-    let res = App::spawn(args.paths);
-    terminal_exit(supports_keyboard_enhancement)?;
+    terminal::enter(supports_keyboard_enhancement, viewport)?;
+
+    let layout = args.layout();
+    let auto_save = args.auto_save();
+    let guard = Box::new(terminal::Crossterm::new(supports_keyboard_enhancement, viewport));
+    let validate = args.validate();
+    let res = App::spawn(args.startup(), layout, auto_save, validate, viewport, guard);
+    terminal::exit(supports_keyboard_enhancement, viewport)?;
     res
 }
 
@@ -74,50 +186,61 @@ fn setup_logging() -> Result<()> {
     Ok(())
 }
 
-fn setup_panic_handler(supports_keyboard_enhancement: bool) {
+fn setup_panic_handler(supports_keyboard_enhancement: bool, viewport: Viewport) {
     let default_panic = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
-        _ = terminal_exit(supports_keyboard_enhancement);
+        recovery::flush_panic_journal();
+        _ = terminal::exit(supports_keyboard_enhancement, viewport);
         default_panic(info);
     }));
 }
 
-fn terminal_enter(supports_keyboard_enhancement: bool) -> Result<()> {
-    use crossterm::event::{KeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
-    use crossterm::terminal;
-    use crossterm::QueueableCommand;
-    use std::io::Write;
-
-    let mut stdout = std::io::stdout();
-    terminal::enable_raw_mode().context("enable raw mode")?;
-    let command_queue = stdout.queue(terminal::EnterAlternateScreen)?;
-    if supports_keyboard_enhancement {
-        command_queue.queue(PushKeyboardEnhancementFlags(
-            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
-                | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
-                | KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS
-                | KeyboardEnhancementFlags::REPORT_EVENT_TYPES,
-        ))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_layout() {
+        let args = Args::try_parse_from(["toku", "a.rs", "b.rs"]).unwrap();
+        assert_eq!(args.layout(), OpenLayout::Buffers);
+        assert_eq!(args.paths.unwrap().len(), 2);
+
+        let args = Args::try_parse_from(["toku", "-o", "a.rs", "b.rs"]).unwrap();
+        assert_eq!(args.layout(), OpenLayout::Splits);
+
+        // split panes stack rather than tile, so there's no vertical split to ask for
+        assert!(Args::try_parse_from(["toku", "-O", "a.rs"]).is_err());
+
+        let args = Args::try_parse_from(["toku", "--diff", "a.rs", "b.rs"]).unwrap();
+        assert_eq!(args.layout(), OpenLayout::Diff);
+        assert!(args.check().is_ok());
+        let args = Args::try_parse_from(["toku", "--diff", "a.rs"]).unwrap();
+        assert!(args.check().is_err());
+        assert!(Args::try_parse_from(["toku", "--diff", "-o", "a.rs", "b.rs"]).is_err());
+
+        let args = Args::try_parse_from(["toku", "--inline", "12", "a.rs"]).unwrap();
+        assert_eq!(args.viewport(), Viewport::Inline(12));
+        assert!(Args::try_parse_from(["toku", "--inline", "0"]).is_err());
     }
-    command_queue.flush().context("setup terminal")?;
-    Ok(())
-}
 
-fn terminal_exit(supports_keyboard_enhancement: bool) -> Result<()> {
-    use crossterm::event::PopKeyboardEnhancementFlags;
-    use crossterm::QueueableCommand;
-    use crossterm::{cursor, terminal};
-    use std::io::Write;
-
-    let mut stdout = std::io::stdout();
-    let command_queue = stdout
-        .queue(terminal::Clear(terminal::ClearType::All))?
-        .queue(terminal::LeaveAlternateScreen)?
-        .queue(cursor::Show)?;
-    if supports_keyboard_enhancement {
-        command_queue.queue(PopKeyboardEnhancementFlags)?;
+    #[test]
+    fn parse_startup() {
+        let args = ["toku", "-c", "w out.rs", "a.rs", "+10", "b.rs", "-c", "q"];
+        let startup = Args::try_parse_from(args).unwrap().startup();
+        assert_eq!(startup.paths, ["a.rs", "b.rs"].map(std::path::PathBuf::from));
+        assert_eq!(startup.jump, Some(StartupJump::Line(10)));
+        assert_eq!(startup.commands, ["w out.rs", "q"]);
+
+        let startup = Args::try_parse_from(["toku", "+/fn main", "a.rs"])
+            .unwrap()
+            .startup();
+        assert_eq!(startup.jump, Some(StartupJump::Search("fn main".to_string())));
+
+        // anything else after a `+` is a file like any other
+        let startup = Args::try_parse_from(["toku", "+x.rs", "+/"])
+            .unwrap()
+            .startup();
+        assert_eq!(startup.paths, ["+x.rs", "+/"].map(std::path::PathBuf::from));
+        assert_eq!(startup.jump, None);
     }
-    command_queue.flush().context("reset terminal")?;
-    terminal::disable_raw_mode().context("disable raw mode")?;
-    Ok(())
 }
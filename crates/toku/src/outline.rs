@@ -0,0 +1,161 @@
+use crossterm::cursor::SetCursorStyle;
+use ratatui::prelude as tui;
+
+use editor::EditorId;
+use selector::{FuzzyIndex, Selector};
+use syntax::Symbol;
+use tore::CursorPoint;
+
+/// The symbols of the buffer an editor shows, listed in the outline pane to jump to one.
+#[derive(Debug)]
+pub struct Outline {
+    pub selector: Selector<usize>,
+    symbols: Vec<Symbol>,
+    /// The symbols by name, each by its index in `symbols`.
+    index: FuzzyIndex<usize>,
+    /// The editor the outline was opened from, that a jump moves.
+    editor_id: Option<EditorId>,
+}
+
+impl Outline {
+    pub fn new() -> Self {
+        Self {
+            selector: Selector::new("outline: "),
+            symbols: vec![],
+            index: FuzzyIndex::new(),
+            editor_id: None,
+        }
+    }
+
+    /// Lists `symbols`, in the order they're defined in, for a jump in `editor_id`.
+    pub fn open(&mut self, editor_id: EditorId, symbols: impl IntoIterator<Item = Symbol>) {
+        self.stop();
+        self.symbols.extend(symbols);
+        for (i, symbol) in self.symbols.iter().enumerate() {
+            self.index.insert(i, symbol.name.clone(), vec![]);
+        }
+        self.editor_id = Some(editor_id);
+        self.update();
+    }
+
+    /// Forgets the symbols listed, as when the pane is closed.
+    pub fn stop(&mut self) {
+        self.symbols.clear();
+        self.index = FuzzyIndex::new();
+        self.editor_id = None;
+        self.selector.reset();
+        self.selector.set_entries([]);
+    }
+
+    pub fn command(&mut self, command: selector::Command<usize>) {
+        let filter = matches!(command, selector::Command::Insert(_) | selector::Command::Delete(_));
+        self.selector.command(command);
+        if filter {
+            self.update();
+        }
+    }
+
+    /// Lists every symbol in order until something is typed, then those matching it, best first.
+    fn update(&mut self) {
+        let query = self.selector.query.as_str();
+        self.index.filter(query, |_| true);
+        if query.is_empty() {
+            self.selector.set_entries(0..self.symbols.len());
+        } else {
+            self.selector.set_entries(self.index.ids());
+        }
+    }
+
+    /// The editor to jump in and the focused symbol to jump to.
+    pub fn focused(&self) -> Option<(EditorId, &Symbol)> {
+        let index = self.selector.focused?;
+        Some((self.editor_id?, &self.symbols[index]))
+    }
+
+    pub fn render(
+        &self,
+        buf: &mut tui::Buffer,
+        area: tui::Rect,
+        theme: &ui::Theme,
+    ) -> (CursorPoint, SetCursorStyle) {
+        let widget = ui::SelectorPane::new(theme, &self.selector);
+        widget.render(buf, area, self.selector.entries.iter().copied(), |area, buf, index| {
+            self.render_symbol(area, buf, index)
+        })
+    }
+
+    /// Draws a symbol as its kind and then its name, with the chars the query matched in bold.
+    fn render_symbol(&self, area: tui::Rect, buf: &mut tui::Buffer, index: usize) {
+        use bstr::ByteSlice;
+
+        let entry = &self.symbols[index];
+        let kind = format!("{:<7}", entry.kind.label());
+        let dim = kind.len();
+        let m = self
+            .index
+            .results()
+            .find(|(matched, _)| matched.id == index);
+        let mut indices = m
+            .into_iter()
+            .flat_map(|(_, m)| m.indices.iter().map(|idx| dim + idx))
+            .peekable();
+        let mut graphemes = [kind.as_str(), entry.name.as_str()]
+            .into_iter()
+            .flat_map(|part| part.as_bytes().as_bstr().graphemes());
+        for (idx, x) in (area.left()..area.right()).enumerate() {
+            let symbol = graphemes.next().unwrap_or(" ");
+            while indices.next_if(|i| *i < idx).is_some() {}
+            let style = if indices.next_if_eq(&idx).is_some() {
+                tui::Style::reset().add_modifier(tui::Modifier::BOLD)
+            } else if idx < dim {
+                tui::Style::reset().add_modifier(tui::Modifier::DIM)
+            } else {
+                tui::Style::reset()
+            };
+            buf.get_mut(x, area.top())
+                .set_style(style)
+                .set_symbol(symbol);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::SymbolKind;
+
+    fn names(outline: &Outline) -> Vec<&str> {
+        let entries = outline.selector.entries.iter();
+        entries.map(|&i| outline.symbols[i].name.as_str()).collect()
+    }
+
+    #[test]
+    fn filters_by_name() {
+        let symbol = |name: &str, start| Symbol {
+            name: name.into(),
+            kind: SymbolKind::Function,
+            range: start..start + 1,
+        };
+        let mut outline = Outline::new();
+        let editor_id = EditorId::default();
+        outline.open(editor_id, [symbol("parse", 0), symbol("render", 5), symbol("resize", 9)]);
+        assert_eq!(names(&outline), vec!["parse", "render", "resize"]);
+        assert_eq!(outline.focused().map(|(_, s)| s.range.start), Some(0));
+
+        for c in "rsz".chars() {
+            outline.command(selector::Command::Insert(c));
+        }
+        assert_eq!(names(&outline), vec!["resize"]);
+        assert_eq!(outline.focused().map(|(_, s)| s.range.start), Some(9));
+
+        // with the query gone, every symbol is back in order
+        for _ in 0..3 {
+            outline.command(selector::Command::Delete(selector::Direction::Prev));
+        }
+        assert_eq!(names(&outline), vec!["parse", "render", "resize"]);
+
+        outline.stop();
+        assert!(names(&outline).is_empty());
+        assert!(outline.focused().is_none());
+    }
+}
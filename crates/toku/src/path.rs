@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+
+/// Expands a path typed as a command's argument: `$VAR` and `${VAR}` to the variable's value, a
+/// leading `~` to the home directory, and a leading `%:h`, `./` or `../` to the directory of
+/// `buffer`, the focused buffer's path. Variables that aren't set are left as typed, as is a
+/// relative path with no buffer path to resolve it against.
+pub fn expand(arg: &str, buffer: Option<&Path>) -> PathBuf {
+    expand_with(arg, buffer, |name| std::env::var(name).ok())
+}
+
+/// Like `expand`, with variables looked up by `var`.
+fn expand_with(arg: &str, buffer: Option<&Path>, var: impl Fn(&str) -> Option<String>) -> PathBuf {
+    let expanded = expand_vars(arg, &var);
+    let home = var("HOME");
+    if let Some(home) = home.filter(|_| expanded == "~" || expanded.starts_with("~/")) {
+        return join(Path::new(&home), &expanded[1..]);
+    }
+    let dir = buffer.and_then(Path::parent);
+    if let Some(rest) = expanded.strip_prefix("%:h") {
+        return join(dir.unwrap_or(Path::new(".")), rest);
+    }
+    match dir {
+        Some(dir) if expanded.starts_with("./") || expanded.starts_with("../") => {
+            dir.join(&expanded)
+        }
+        _ => PathBuf::from(expanded),
+    }
+}
+
+/// `dir` with `rest` after it, if there's any more to it than a `/`.
+fn join(dir: &Path, rest: &str) -> PathBuf {
+    match rest.trim_start_matches('/') {
+        "" => dir.to_path_buf(),
+        rest => dir.join(rest),
+    }
+}
+
+fn expand_vars(arg: &str, var: &impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::with_capacity(arg.len());
+    let mut rest = arg;
+    while let Some(at) = rest.find('$') {
+        expanded.push_str(&rest[..at]);
+        let after = &rest[at + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match var(name).filter(|_| !name.is_empty()) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[at..at + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// What a partly typed path argument could go on to be: the entries of the directory it's in
+/// whose names start with what's typed after its last `/`, as the argument would be with each.
+/// Directories end with a `/`; hidden entries are only offered once a `.` is typed.
+pub fn complete(arg: &str, buffer: Option<&Path>) -> Vec<String> {
+    let (typed_dir, stem) = match arg.rfind('/') {
+        Some(at) => arg.split_at(at + 1),
+        None => ("", arg),
+    };
+    let dir = match typed_dir {
+        "" => PathBuf::from("."),
+        typed_dir => expand(typed_dir, buffer),
+    };
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return vec![];
+    };
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(stem) || (name.starts_with('.') && !stem.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", typed_dir, name, slash))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+/// The longest prefix all of `candidates` share; empty when there are none.
+pub fn common_prefix(candidates: &[String]) -> &str {
+    let Some((first, rest)) = candidates.split_first() else {
+        return "";
+    };
+    let len = rest.iter().fold(first.len(), |len, candidate| {
+        let shared = first
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(first.len().min(candidate.len()), |((at, _), _)| at);
+        len.min(shared)
+    });
+    &first[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expansion() {
+        let var = |name: &str| match name {
+            "HOME" => Some("/home/me".to_string()),
+            "PROJECT" => Some("code".to_string()),
+            _ => None,
+        };
+        let expand = |arg, buffer: Option<&str>| {
+            expand_with(arg, buffer.map(Path::new), var)
+                .to_string_lossy()
+                .into_owned()
+        };
+        assert_eq!(expand("~", None), "/home/me");
+        assert_eq!(expand("~/src/a.rs", None), "/home/me/src/a.rs");
+        assert_eq!(expand("a~/b", None), "a~/b");
+        assert_eq!(expand("$HOME/$PROJECT/a.rs", None), "/home/me/code/a.rs");
+        assert_eq!(expand("~/${PROJECT}s", None), "/home/me/codes");
+        // variables that aren't set stay as typed
+        assert_eq!(expand("$NOPE/${NOPE}/$/a", None), "$NOPE/${NOPE}/$/a");
+        assert_eq!(expand("${PROJECT", None), "${PROJECT");
+
+        let buffer = Some("/work/src/main.rs");
+        assert_eq!(expand("./lib.rs", buffer), "/work/src/./lib.rs");
+        assert_eq!(expand("../Cargo.toml", buffer), "/work/src/../Cargo.toml");
+        assert_eq!(expand("%:h/lib.rs", buffer), "/work/src/lib.rs");
+        assert_eq!(expand("%:h", buffer), "/work/src");
+        assert_eq!(expand("src/lib.rs", buffer), "src/lib.rs");
+        // without a buffer path, relative paths are left to the working directory
+        assert_eq!(expand("./lib.rs", None), "./lib.rs");
+        assert_eq!(expand("%:h/lib.rs", None), "./lib.rs");
+    }
+
+    #[test]
+    fn completion() -> std::io::Result<()> {
+        let temp = test_support::temp_dir();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("src/nested"))?;
+        for file in ["src/main.rs", "src/map.rs", "src/lib.rs", "src/.hidden"] {
+            std::fs::write(dir.join(file), "")?;
+        }
+        let root = format!("{}/", dir.display());
+        let complete = |arg: &str, buffer: Option<&Path>| -> Vec<String> {
+            complete(arg, buffer)
+                .into_iter()
+                .map(|candidate| candidate.replace(&root, ""))
+                .collect()
+        };
+
+        let candidates = complete(&format!("{}src/ma", root), None);
+        assert_eq!(candidates, ["src/main.rs", "src/map.rs"]);
+        assert_eq!(common_prefix(&candidates), "src/ma");
+        let candidates = complete(&format!("{}s", root), None);
+        assert_eq!(candidates, ["src/"]);
+        assert_eq!(common_prefix(&candidates), "src/");
+        let candidates = complete(&format!("{}src/", root), None);
+        assert_eq!(candidates, ["src/lib.rs", "src/main.rs", "src/map.rs", "src/nested/"]);
+        assert_eq!(complete(&format!("{}src/.", root), None), ["src/.hidden"]);
+        assert!(complete(&format!("{}nowhere/", root), None).is_empty());
+
+        // relative to the buffer's directory
+        let buffer = dir.join("src/main.rs");
+        assert_eq!(complete("./l", Some(&buffer)), ["./lib.rs"]);
+        assert_eq!(complete("%:h/n", Some(&buffer)), ["%:h/nested/"]);
+
+        assert_eq!(common_prefix(&[]), "");
+        let words = ["abcd".to_string(), "abce".to_string(), "ab".to_string()];
+        assert_eq!(common_prefix(&words), "ab");
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+//! Settings a project carries for everyone who works on it, in a `.toku.toml` at its root.
+//!
+//! ```toml
+//! # settings, by the names `.editorconfig` and `:set` give them
+//! indent_style = "space"
+//! indent_size = 2
+//! # left out of grep: a name with no `/` anywhere, one with a `/` from the root
+//! exclude = ["target", "/docs/build/**"]
+//!
+//! [formatters]
+//! rust = "rustfmt --edition 2021"
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use editor::SettingsOverlay;
+
+use crate::editorconfig::glob;
+use crate::grep::normalize;
+
+pub const FILE_NAME: &str = ".toku.toml";
+
+/// What a project's `.toku.toml` says.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Project {
+    /// The directory the file is in.
+    pub root: PathBuf,
+    /// Settings for every file in the project, under those of `.editorconfig` files and `:set`.
+    pub settings: SettingsOverlay,
+    /// The command each language's formatter is run with, for languages the user hasn't given
+    /// one of their own.
+    pub formatters: HashMap<String, Vec<String>>,
+    pub exclude: Exclude,
+}
+
+/// Paths left out of searches of a project.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Exclude {
+    root: PathBuf,
+    globs: Vec<String>,
+}
+
+impl Exclude {
+    /// Whether `path` is one of those left out, or in a directory that is. A glob with no `/`
+    /// matches a name in any directory; one with a `/` is anchored to the root. Paths outside
+    /// the project are never left out.
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.globs.is_empty() {
+            return false;
+        }
+        let path = normalize(path);
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let relative = relative
+            .to_string_lossy()
+            .replace(std::path::MAIN_SEPARATOR, "/");
+        let dirs = relative.match_indices('/').map(|(i, _)| &relative[..i]);
+        let mut paths = dirs.chain([relative.as_str()]);
+        paths.any(|path| {
+            self.globs
+                .iter()
+                .any(|pattern| match pattern.strip_prefix('/') {
+                    Some(anchored) => glob::matches(anchored, path),
+                    None if pattern.contains('/') => glob::matches(pattern, path),
+                    None => glob::matches(&format!("**/{}", pattern), path),
+                })
+        })
+    }
+}
+
+/// The directory of the nearest `.toku.toml`, in `dir` or one above it. The search stops at
+/// the root of a git repository, so one project's config doesn't reach into another's.
+pub fn find_root(dir: &Path) -> Option<PathBuf> {
+    let dir = normalize(dir);
+    for dir in dir.ancestors() {
+        if dir.join(FILE_NAME).is_file() {
+            return Some(dir.to_path_buf());
+        }
+        if dir.join(".git").exists() {
+            return None;
+        }
+    }
+    None
+}
+
+/// Each project's config as last read, keyed by its root, so every file opened in a project
+/// doesn't read it again.
+#[derive(Debug, Default)]
+pub struct Projects {
+    by_root: HashMap<PathBuf, Arc<Project>>,
+}
+
+impl Projects {
+    /// The project the file at `path` is in, or None if it isn't in one. Fails with the config
+    /// file and what's wrong in it, and where.
+    pub fn for_file(&mut self, path: &Path) -> Result<Option<Arc<Project>>> {
+        let path = normalize(path);
+        match path.parent() {
+            Some(dir) => self.for_dir(dir),
+            None => Ok(None),
+        }
+    }
+
+    /// The project the directory `dir` is in, or None if it isn't in one.
+    pub fn for_dir(&mut self, dir: &Path) -> Result<Option<Arc<Project>>> {
+        let Some(root) = find_root(dir) else {
+            return Ok(None);
+        };
+        if let Some(project) = self.by_root.get(&root) {
+            return Ok(Some(project.clone()));
+        }
+        let file = root.join(FILE_NAME);
+        let src =
+            std::fs::read_to_string(&file).map_err(|err| anyhow!("{}: {}", file.display(), err))?;
+        let project = parse(&root, &src).map_err(|err| anyhow!("{}: {}", file.display(), err))?;
+        let project = Arc::new(project);
+        self.by_root.insert(root, project.clone());
+        Ok(Some(project))
+    }
+
+    /// Forgets every config read, so each is read again the next time it's needed.
+    pub fn clear(&mut self) {
+        self.by_root.clear();
+    }
+}
+
+/// Reads a `.toku.toml`: settings and `exclude` at the top, and a `[formatters]` table of
+/// `language = "command args"`. Blank lines and `#` comments are skipped.
+fn parse(root: &Path, src: &str) -> Result<Project, String> {
+    let mut project = Project { root: root.to_path_buf(), ..Default::default() };
+    project.exclude.root = root.to_path_buf();
+    let mut table = None;
+    for (n, line) in src.lines().enumerate() {
+        let fail = |err: String| format!("line {}: {}", n + 1, err);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            let Some(name) = name.strip_suffix(']') else {
+                return Err(fail("a table header is a name in [ ]".to_string()));
+            };
+            match name.trim() {
+                "formatters" => table = Some("formatters"),
+                name => return Err(fail(format!("unknown table '{}'", name))),
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(fail("expected key = value".to_string()));
+        };
+        let (key, value) = (key.trim(), value.trim());
+        match (table, key) {
+            (Some(_), language) => {
+                let command = string(value)
+                    .ok_or_else(|| fail(format!("the command for '{}' isn't quoted", language)))?;
+                let command: Vec<String> = command.split_whitespace().map(String::from).collect();
+                if command.is_empty() {
+                    return Err(fail(format!("the command for '{}' is empty", language)));
+                }
+                if project
+                    .formatters
+                    .insert(language.to_string(), command)
+                    .is_some()
+                {
+                    return Err(fail(format!("'{}' is set twice", language)));
+                }
+            }
+            (None, "exclude") => {
+                let globs = strings(value)
+                    .ok_or_else(|| fail("exclude is a list of quoted globs".to_string()))?;
+                project.exclude.globs.extend(globs);
+            }
+            (None, key) => {
+                let value = string(value).unwrap_or(value);
+                project.settings.set(key, value).map_err(fail)?;
+            }
+        }
+    }
+    Ok(project)
+}
+
+/// The string in `"quotes"`.
+fn string(value: &str) -> Option<&str> {
+    value.strip_prefix('"')?.strip_suffix('"')
+}
+
+/// The strings in `["a", "b"]`; a comma may follow the last.
+fn strings(value: &str) -> Option<Vec<String>> {
+    let items = value.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let items = items.strip_suffix(',').unwrap_or(items);
+    if items.trim().is_empty() {
+        return Some(vec![]);
+    }
+    let items = items
+        .split(',')
+        .map(|item| string(item.trim()).map(String::from));
+    items.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::IndentStyle;
+
+    #[test]
+    fn parsed() {
+        let src = "# shared\nindent_style = \"space\"\nindent_size = 2\n\
+                   exclude = [\"target\", \"/docs/build/**\",]\n\n\
+                   [formatters]\nrust = \"rustfmt --edition 2021\"\n";
+        let project = parse(Path::new("/p"), src).unwrap();
+        assert_eq!(project.settings.indent_style, Some(IndentStyle::Space));
+        assert_eq!(project.settings.indent_size, Some(Some(2)));
+        assert_eq!(project.formatters["rust"], ["rustfmt", "--edition", "2021"]);
+        assert_eq!(project.exclude.globs, ["target", "/docs/build/**"]);
+
+        let err = |src: &str| parse(Path::new("/p"), src).unwrap_err();
+        assert_eq!(err("tab_width = 0"), "line 1: bad value for tab_width: '0'");
+        assert_eq!(err("\ncolour = 1"), "line 2: unknown setting 'colour'");
+        assert_eq!(err("exclude = \"target\""), "line 1: exclude is a list of quoted globs");
+        assert_eq!(err("[servers]"), "line 1: unknown table 'servers'");
+        assert_eq!(
+            err("[formatters]\nrust = rustfmt"),
+            "line 2: the command for 'rust' isn't quoted"
+        );
+    }
+
+    #[test]
+    fn excludes() {
+        let exclude = Exclude {
+            root: PathBuf::from("/p"),
+            globs: vec!["target".into(), "/docs/build".into(), "*.min.js".into()],
+        };
+        assert!(exclude.matches(Path::new("/p/target")));
+        assert!(exclude.matches(Path::new("/p/sub/target")));
+        assert!(exclude.matches(Path::new("/p/target/debug/a.rs")));
+        assert!(exclude.matches(Path::new("/p/docs/build")));
+        assert!(!exclude.matches(Path::new("/p/sub/docs/build")));
+        assert!(exclude.matches(Path::new("/p/web/a.min.js")));
+        assert!(!exclude.matches(Path::new("/p/web/a.js")));
+        // outside the project
+        assert!(!exclude.matches(Path::new("/q/target")));
+    }
+
+    #[test]
+    fn nearest_config_up_to_the_repository() -> anyhow::Result<()> {
+        let dir = test_support::temp_dir();
+        let outer = dir.path().to_path_buf();
+        let repo = outer.join("repo");
+        let nested = repo.join("crates/nested");
+        std::fs::create_dir_all(nested.join("src"))?;
+        std::fs::create_dir_all(repo.join(".git"))?;
+        std::fs::create_dir_all(outer.join("bare/src"))?;
+        std::fs::write(outer.join(FILE_NAME), "tab_width = 8\n")?;
+        std::fs::write(repo.join(FILE_NAME), "indent_size = 2\n")?;
+        std::fs::write(nested.join(FILE_NAME), "indent_size = 4\n")?;
+
+        let mut projects = Projects::default();
+        let root = |path: &Path, projects: &mut Projects| {
+            let project = projects.for_file(path).unwrap();
+            project.map(|project| project.root.clone())
+        };
+        assert_eq!(root(&nested.join("src/a.rs"), &mut projects), Some(nested.clone()));
+        assert_eq!(root(&repo.join("src/a.rs"), &mut projects), Some(repo.clone()));
+        assert_eq!(root(&outer.join("bare/src/a.rs"), &mut projects), Some(outer.clone()));
+
+        // the repository's root stops the search before the config above it
+        std::fs::remove_file(repo.join(FILE_NAME))?;
+        assert_eq!(root(&repo.join("src/a.rs"), &mut projects), None);
+        // and what was read is kept until it's cleared
+        std::fs::write(repo.join(FILE_NAME), "indent_size = 3\n")?;
+        let project = projects.for_file(&repo.join("a.rs"))?.unwrap();
+        assert_eq!(project.settings.indent_size, Some(Some(2)));
+        projects.clear();
+        let project = projects.for_file(&repo.join("a.rs"))?.unwrap();
+        assert_eq!(project.settings.indent_size, Some(Some(3)));
+
+        std::fs::write(nested.join(FILE_NAME), "indent_size = none\n")?;
+        projects.clear();
+        let err = projects.for_file(&nested.join("a.rs")).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("line 1: bad value for indent_size: 'none'"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+}
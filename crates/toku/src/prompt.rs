@@ -0,0 +1,117 @@
+use crossterm::cursor::SetCursorStyle;
+use ratatui::prelude as tui;
+
+use selector::Selector;
+use tore::CursorPoint;
+
+use crate::app::Command;
+
+/// What a prompt can be answered with, in the order they're listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Answer {
+    Yes,
+    No,
+    Cancel,
+}
+
+impl Answer {
+    const ALL: [Answer; 3] = [Answer::Yes, Answer::No, Answer::Cancel];
+
+    fn label(self) -> &'static str {
+        match self {
+            Answer::Yes => "Yes",
+            Answer::No => "No",
+            Answer::Cancel => "Cancel",
+        }
+    }
+}
+
+/// Asks before a command that would lose work is run, holding on to it until it's answered.
+/// Only a yes runs it; no and cancel, like Esc, leave everything as it was.
+#[derive(Debug)]
+pub struct Prompt {
+    pub selector: Selector<Answer>,
+    /// The command asked about.
+    pending: Option<Command>,
+}
+
+impl Prompt {
+    pub fn new() -> Self {
+        let mut selector = Selector::new("").with_wrap(true);
+        selector.command(selector::Command::SetEntries(Answer::ALL.to_vec()));
+        Self { selector, pending: None }
+    }
+
+    /// Asks `question` about `command`, replacing whatever was asked before. The focus starts on
+    /// no, so a stray Enter doesn't run it.
+    pub fn ask(&mut self, question: String, command: Command) {
+        self.selector.reset();
+        self.selector.query.set(&question);
+        self.selector.focused = Some(Answer::No);
+        self.pending = Some(command);
+    }
+
+    #[cfg(test)]
+    pub fn question(&self) -> &str {
+        self.selector.query.as_str()
+    }
+
+    pub fn focused(&self) -> Option<Answer> {
+        self.selector.focused
+    }
+
+    /// Takes the command asked about, marked as confirmed for a yes; None for anything else.
+    pub fn answer(&mut self, answer: Answer) -> Option<Command> {
+        let command = self.pending.take()?;
+        (answer == Answer::Yes).then(|| Command::Confirmed(Box::new(command)))
+    }
+
+    /// Forgets the question and the command asked about.
+    pub fn stop(&mut self) {
+        self.selector.reset();
+        self.pending = None;
+    }
+
+    pub fn render(
+        &self,
+        buf: &mut tui::Buffer,
+        area: tui::Rect,
+        theme: &ui::Theme,
+    ) -> (CursorPoint, SetCursorStyle) {
+        let widget = ui::SelectorPane::new(theme, &self.selector);
+        widget.render(buf, area, self.selector.entries.iter().copied(), |area, buf, answer| {
+            let style = tui::Style::reset();
+            buf.set_stringn(area.x, area.y, answer.label(), usize::from(area.width), style);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_yes_confirms() {
+        let mut prompt = Prompt::new();
+        assert!(prompt.answer(Answer::Yes).is_none());
+
+        prompt.ask("Quit?".to_string(), Command::Quit);
+        assert_eq!((prompt.question(), prompt.focused()), ("Quit?", Some(Answer::No)));
+        let Some(Command::Confirmed(command)) = prompt.answer(Answer::Yes) else {
+            panic!("expected a confirmed command");
+        };
+        assert!(matches!(*command, Command::Quit));
+        // it's answered once
+        assert!(prompt.answer(Answer::Yes).is_none());
+
+        for answer in [Answer::No, Answer::Cancel] {
+            prompt.ask("Quit?".to_string(), Command::Quit);
+            assert!(prompt.answer(answer).is_none());
+            assert!(prompt.answer(Answer::Yes).is_none());
+        }
+        prompt.ask("Quit?".to_string(), Command::Quit);
+        prompt.stop();
+        assert!(prompt.answer(Answer::Yes).is_none());
+        assert_eq!(prompt.question(), "");
+    }
+}
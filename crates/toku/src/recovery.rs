@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+
+use editor::BufferContents;
+
+use crate::grep::normalize;
+
+/// The first line of every recovery file, naming its format.
+const MAGIC: &str = "toku-recovery 1";
+
+/// Where the unsaved contents of buffers are journaled, one file per buffer, so they can be
+/// recovered after a crash.
+#[derive(Debug, Clone)]
+pub struct Recovery {
+    dir: PathBuf,
+}
+
+/// A buffer's contents as they were journaled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Journaled {
+    pub path: PathBuf,
+    pub version: u64,
+    pub contents: String,
+}
+
+impl Recovery {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Recovery under `$XDG_STATE_HOME/toku/recovery`, created if it isn't there.
+    pub fn xdg() -> Result<Self> {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix(crate::PROJECT_NAME.clone())?;
+        let dir = xdg_dirs.create_state_directory("recovery")?;
+        Ok(Self::new(dir))
+    }
+
+    /// The recovery file of `path`: its file name, to be told apart by eye, and a hash of the
+    /// whole path, to be told apart from files of the same name elsewhere.
+    fn file(&self, path: &Path) -> PathBuf {
+        let path = normalize(path);
+        let name = path
+            .file_name()
+            .map_or("buffer".into(), |name| name.to_string_lossy());
+        // FNV-1a, which unlike the std hasher is the same from one build to the next
+        let hash = path
+            .as_os_str()
+            .as_encoded_bytes()
+            .iter()
+            .fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+            });
+        self.dir.join(format!("{}-{:016x}", name, hash))
+    }
+
+    /// Journals `contents`, at `version`, as what should be in `path`. The file is written
+    /// whole beside its final name then renamed over it, so a crash partway through leaves the
+    /// last journal as it was.
+    pub fn write(&self, path: &Path, version: u64, contents: &BufferContents) -> Result<()> {
+        let file = self.file(path);
+        let partial = file.with_extension("partial");
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&partial)?);
+        writeln!(writer, "{}", MAGIC)?;
+        writeln!(writer, "{}", version)?;
+        writeln!(writer, "{}", normalize(path).display())?;
+        contents.write_to(&mut writer)?;
+        writer.into_inner()?.sync_all()?;
+        std::fs::rename(&partial, &file)?;
+        Ok(())
+    }
+
+    /// Forgets what was journaled for `path`, once there's nothing to recover.
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        match std::fs::remove_file(self.file(path)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// What was journaled for `path`, if anything.
+    pub fn read(&self, path: &Path) -> Result<Option<Journaled>> {
+        match std::fs::File::open(self.file(path)) {
+            Ok(file) => read_journal(file).map(Some),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// What was journaled for `path`, if it's no older than the file and holds something else.
+    /// A journal written within the file system's timestamp resolution of a save counts as newer,
+    /// since it's only kept while there are unsaved changes. A file that's gone since has
+    /// nothing newer.
+    pub fn newer_than_disk(&self, path: &Path) -> Result<Option<Journaled>> {
+        let Some(journaled) = self.read(path)? else {
+            return Ok(None);
+        };
+        let journal_modified = std::fs::metadata(self.file(path))?.modified()?;
+        let newer = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.modified()? <= journal_modified,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => true,
+            Err(err) => return Err(err.into()),
+        };
+        let differs =
+            || std::fs::read(path).map_or(true, |disk| disk != journaled.contents.as_bytes());
+        Ok((newer && differs()).then_some(journaled))
+    }
+
+    /// The paths of every file that has something journaled.
+    pub fn paths(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let file = entry?.path();
+            if file
+                .extension()
+                .is_some_and(|extension| extension == "partial")
+            {
+                continue;
+            }
+            // something else's file, or one cut short, is no journal
+            let Ok(header) = std::fs::File::open(&file)
+                .map_err(Into::into)
+                .and_then(read_header)
+            else {
+                continue;
+            };
+            paths.push(header.1);
+        }
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+/// The version and path at the top of a recovery file.
+fn read_header(file: std::fs::File) -> Result<(u64, PathBuf)> {
+    let mut reader = std::io::BufReader::new(file);
+    header(&mut reader)
+}
+
+fn header(reader: &mut impl BufRead) -> Result<(u64, PathBuf)> {
+    let mut line = || -> Result<String> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        line.strip_suffix('\n')
+            .map(str::to_string)
+            .context("recovery file cut short")
+    };
+    anyhow::ensure!(line()? == MAGIC, "not a recovery file");
+    let version = line()?.parse()?;
+    let path = PathBuf::from(line()?);
+    Ok((version, path))
+}
+
+fn read_journal(file: std::fs::File) -> Result<Journaled> {
+    let mut reader = std::io::BufReader::new(file);
+    let (version, path) = header(&mut reader)?;
+    let mut contents = String::new();
+    std::io::Read::read_to_string(&mut reader, &mut contents)?;
+    Ok(Journaled { path, version, contents })
+}
+
+/// The version and contents of buffers with unsaved changes, by path.
+pub type Dirty = HashMap<PathBuf, (u64, BufferContents)>;
+
+/// The dirty buffers as of the last command, for the panic hook to journal on the way down.
+static PANIC_JOURNAL: Mutex<Option<(Recovery, Dirty)>> = Mutex::new(None);
+
+/// Sets what the panic hook journals: `dirty` buffers' paths, versions and contents, into
+/// `recovery`.
+pub fn set_panic_journal(recovery: &Recovery, dirty: Dirty) {
+    if let Ok(mut journal) = PANIC_JOURNAL.lock() {
+        *journal = Some((recovery.clone(), dirty));
+    }
+}
+
+/// Journals whatever `set_panic_journal` last set, as best it can. Meant for a panic hook, so
+/// it gives up rather than waits if the journal is being set, and ignores failures.
+pub fn flush_panic_journal() {
+    let Ok(journal) = PANIC_JOURNAL.try_lock() else {
+        return;
+    };
+    if let Some((recovery, dirty)) = journal.as_ref() {
+        for (path, (version, contents)) in dirty {
+            let _ = recovery.write(path, *version, contents);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn newer_than_disk() -> Result<()> {
+        let dir = test_support::temp_dir();
+        std::fs::create_dir(dir.path().join("recovery"))?;
+        let recovery = Recovery::new(dir.path().join("recovery"));
+        let path = dir.path().join("a.rs");
+        std::fs::write(&path, "fn a() {}\n")?;
+        let set_modified = |path: &Path, ago: u64| -> Result<()> {
+            let time = SystemTime::now() - Duration::from_secs(ago);
+            std::fs::File::options()
+                .write(true)
+                .open(path)?
+                .set_modified(time)?;
+            Ok(())
+        };
+
+        assert_eq!(recovery.newer_than_disk(&path)?, None);
+        recovery.write(&path, 3, &"fn b() {}\n".into())?;
+        set_modified(&path, 60)?;
+        let journaled = recovery.newer_than_disk(&path)?.unwrap();
+        assert_eq!(journaled.path, path);
+        assert_eq!((journaled.version, journaled.contents.as_str()), (3, "fn b() {}\n"));
+        assert_eq!(recovery.paths()?, vec![path.clone()]);
+
+        // a file saved since the journal was written is newer
+        set_modified(&recovery.file(&path), 120)?;
+        assert_eq!(recovery.newer_than_disk(&path)?, None);
+        // as is one that already holds what was journaled
+        recovery.write(&path, 4, &"fn a() {}\n".into())?;
+        set_modified(&path, 60)?;
+        assert_eq!(recovery.newer_than_disk(&path)?, None);
+        // but a file that's gone has nothing newer
+        std::fs::remove_file(&path)?;
+        assert!(recovery.newer_than_disk(&path)?.is_some());
+
+        recovery.remove(&path)?;
+        recovery.remove(&path)?;
+        assert_eq!(recovery.read(&path)?, None);
+        assert_eq!(recovery.paths()?, Vec::<PathBuf>::new());
+        Ok(())
+    }
+}
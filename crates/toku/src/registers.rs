@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+use editor::Register;
+
+/// How many chars of a register `:registers` shows.
+const PREVIEW_LEN: usize = 60;
+
+/// The registers yanked into and pasted from by name, `a` to `z`, besides each editor's own
+/// unnamed one. They're kept for the session, whichever buffer is shown, but never saved.
+#[derive(Debug, Default)]
+pub struct Registers {
+    named: BTreeMap<char, Register>,
+    /// The last command line run from the palette, that `:` holds.
+    pub command: Option<String>,
+}
+
+impl Registers {
+    pub fn get(&self, name: char) -> Option<&Register> {
+        self.named.get(&name)
+    }
+
+    /// Puts `register` in the one `name` names, failing with why if it can't be yanked into.
+    pub fn set(&mut self, name: char, register: Register) -> Result<(), String> {
+        writable(name)?;
+        self.named.insert(name, register);
+        Ok(())
+    }
+
+    /// The named registers that hold something, by name.
+    pub fn named(&self) -> impl Iterator<Item = (char, &Register)> {
+        self.named.iter().map(|(name, register)| (*name, register))
+    }
+}
+
+/// Whether text can be yanked into the register `name` names: the unnamed `"` and `a` to `z`
+/// can, while `%`, `.` and `:` are only ever filled as things happen.
+pub fn writable(name: char) -> Result<(), String> {
+    match name {
+        '"' | 'a'..='z' => Ok(()),
+        '%' | '.' | ':' => Err(format!("register {} is read-only", name)),
+        _ => Err(format!("no register {}", name)),
+    }
+}
+
+/// A line of `:registers`: the register's name, `l` if it holds whole lines or `c` if not, and
+/// its first chars, with line breaks shown as `⏎`.
+pub fn listing(name: char, register: &Register) -> String {
+    let kind = if register.linewise { 'l' } else { 'c' };
+    let mut chars = register.text.chars();
+    let mut preview: String = chars
+        .by_ref()
+        .take(PREVIEW_LEN)
+        .map(|c| if c == '\n' { '⏎' } else { c })
+        .collect();
+    if chars.next().is_some() {
+        preview.push('…');
+    }
+    format!("\"{}  {}  {}", name, kind, preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_letters_are_written() {
+        let mut registers = Registers::default();
+        let register = Register { text: "x".into(), linewise: false };
+        assert_eq!(registers.set('a', register.clone()), Ok(()));
+        assert_eq!(registers.set('.', register.clone()), Err("register . is read-only".into()));
+        assert_eq!(registers.set('1', register.clone()), Err("no register 1".into()));
+        assert_eq!(registers.named().collect::<Vec<_>>(), vec![('a', &register)]);
+    }
+
+    #[test]
+    fn listing_shows_line_breaks_and_cuts_long_text() {
+        let lines = Register { text: "fn main() {\n    x\n}\n".into(), linewise: true };
+        assert_eq!(listing('a', &lines), "\"a  l  fn main() {⏎    x⏎}⏎");
+
+        let long = Register { text: "ab".repeat(40), linewise: false };
+        let expected = format!("\"b  c  {}…", "ab".repeat(30));
+        assert_eq!(listing('b', &long), expected);
+        // exactly as long as the preview isn't cut
+        let exact = Register { text: "x".repeat(PREVIEW_LEN), linewise: false };
+        assert!(!listing('c', &exact).ends_with('…'));
+    }
+}
@@ -0,0 +1,260 @@
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+
+use tokio::io::AsyncReadExt;
+use tokio::sync::{mpsc, oneshot};
+
+use editor::BufferId;
+
+use crate::app::Command;
+
+/// How much output is read from a command at a time.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// How a command exited, or why it couldn't be run.
+pub type Exit = Result<ExitStatus, Arc<std::io::Error>>;
+
+/// Runs `command` with `sh -c` off the main loop, its stderr merged into its stdout, sending
+/// back the output as whole lines stripped of escape sequences as they come, then how it exited.
+/// `kill` stops it, as does dropping what sends on it.
+pub async fn run(
+    run_id: u64,
+    command: String,
+    kill: oneshot::Receiver<()>,
+    cmd_tx: mpsc::Sender<Command>,
+) {
+    let status = execute(run_id, &command, kill, &cmd_tx).await;
+    let _ = cmd_tx
+        .send(Command::RunDone(run_id, status.map_err(Arc::new)))
+        .await;
+}
+
+async fn execute(
+    run_id: u64,
+    command: &str,
+    mut kill: oneshot::Receiver<()>,
+    cmd_tx: &mpsc::Sender<Command>,
+) -> std::io::Result<ExitStatus> {
+    // redirecting in the shell keeps both streams in one pipe, in the order they were written
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("exec 2>&1\n{}", command))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut chunk = vec![0; CHUNK_SIZE];
+    // what's been read past the last line break
+    let mut pending = vec![];
+    loop {
+        let read = tokio::select! {
+            read = stdout.read(&mut chunk) => read?,
+            _ = &mut kill => {
+                child.kill().await?;
+                break;
+            }
+        };
+        if read == 0 {
+            break;
+        }
+        pending.extend_from_slice(&chunk[..read]);
+        let Some(end) = pending.iter().rposition(|b| *b == b'\n') else {
+            continue;
+        };
+        let lines: Vec<u8> = pending.drain(..=end).collect();
+        if cmd_tx
+            .send(Command::RunOutput(run_id, strip_escapes(&lines)))
+            .await
+            .is_err()
+        {
+            // nobody is left to show the output to
+            child.kill().await?;
+            return child.wait().await;
+        }
+    }
+    if !pending.is_empty() {
+        let _ = cmd_tx
+            .send(Command::RunOutput(run_id, strip_escapes(&pending)))
+            .await;
+    }
+    child.wait().await
+}
+
+/// `output` as text, without the escape sequences terminals take for colors, titles and the
+/// like. Whatever doesn't decode is replaced.
+pub fn strip_escapes(output: &[u8]) -> String {
+    let text = String::from_utf8_lossy(output);
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters and intermediates, up to a final byte
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: up to BEL, or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            // anything else is a single char after the ESC
+            _ => {}
+        }
+    }
+    stripped
+}
+
+/// What's said once `command` exits with `status`.
+pub fn exit_message(command: &str, status: &Exit) -> String {
+    match status {
+        Ok(status) => match status.code() {
+            Some(0) => format!("'{}' finished", command),
+            Some(code) => format!("'{}' failed with exit code {}", command, code),
+            None => format!("'{}' stopped: {}", command, status),
+        },
+        Err(err) => format!("'{}' could not run: {}", command, err),
+    }
+}
+
+/// The command run last, and what kills it while it's running.
+#[derive(Debug, Default)]
+pub struct Runs {
+    /// Counts runs, so output still coming from one that was replaced is dropped.
+    run_id: u64,
+    command: String,
+    kill: Option<oneshot::Sender<()>>,
+    /// The scratch buffer the last run's output went into, while it's open.
+    pub buffer_id: Option<BufferId>,
+}
+
+impl Runs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a run of `command`, returning its id and what kills it; fails with what to tell the
+    /// user while the last run is still going.
+    pub fn start(&mut self, command: String) -> Result<(u64, oneshot::Receiver<()>), String> {
+        if self.kill.is_some() {
+            let message = format!("'{}' is still running; :run-kill stops it", self.command);
+            return Err(message);
+        }
+        let (kill_tx, kill) = oneshot::channel();
+        self.run_id += 1;
+        self.command = command;
+        self.kill = Some(kill_tx);
+        Ok((self.run_id, kill))
+    }
+
+    /// Kills the running command; false if there isn't one.
+    pub fn kill(&mut self) -> bool {
+        match self.kill.take() {
+            Some(kill) => kill.send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    /// The command still running, if there is one.
+    pub fn running(&self) -> Option<&str> {
+        self.kill.as_ref().map(|_| self.command.as_str())
+    }
+
+    /// Whether output from run `run_id` is still wanted.
+    pub fn is_current(&self, run_id: u64) -> bool {
+        run_id == self.run_id
+    }
+
+    /// What's said about run `run_id` once it's done, if it's still the current one.
+    pub fn done(&mut self, run_id: u64, status: &Exit) -> Option<String> {
+        if !self.is_current(run_id) {
+            return None;
+        }
+        self.kill = None;
+        Some(exit_message(&self.command, status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `command` until it exits, returning its output and how it exited. `kill_after` kills
+    /// it once the output has that many lines.
+    async fn collect(command: &str, kill_after: Option<usize>) -> (String, Exit) {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let (kill_tx, kill) = oneshot::channel();
+        tokio::spawn(run(1, command.to_string(), kill, cmd_tx));
+        let (mut output, mut kill_tx) = (String::new(), Some(kill_tx));
+        loop {
+            match cmd_rx.recv().await {
+                Some(Command::RunOutput(1, text)) => output.push_str(&text),
+                Some(Command::RunDone(1, status)) => return (output, status),
+                command => panic!("unexpected {:?}", command),
+            }
+            if kill_after.is_some_and(|lines| output.lines().count() >= lines) {
+                if let Some(kill) = kill_tx.take() {
+                    let _ = kill.send(());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn streams_output_in_order() -> anyhow::Result<()> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        rt.block_on(async {
+            let script = "echo a; printf '\\033[31mb\\033[0m\\n' >&2; sleep 0.1; printf c; exit 3";
+            let (output, status) = collect(script, None).await;
+            assert_eq!(output, "a\nb\nc");
+            assert_eq!(status?.code(), Some(3));
+
+            // killing it stops it before it's done
+            let (output, status) = collect("echo a; sleep 10; echo b", Some(1)).await;
+            assert_eq!(output, "a\n");
+            assert_eq!(status?.code(), None);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn strips_escapes() {
+        let output = b"\x1b[1;31merror\x1b[0m: \x1b]0;title\x07x\x1b]2;t\x1b\\y\x1b=z\xff\n";
+        assert_eq!(strip_escapes(output), "error: xyz\u{fffd}\n");
+    }
+
+    #[test]
+    fn one_run_at_a_time() {
+        let mut runs = Runs::new();
+        assert_eq!(runs.running(), None);
+        let (first, _kill) = runs.start("make".into()).unwrap();
+        assert_eq!(runs.running(), Some("make"));
+        let err = runs.start("make test".into()).unwrap_err();
+        assert_eq!(err, "'make' is still running; :run-kill stops it");
+        assert!(runs.kill());
+        assert!(!runs.kill());
+        assert_eq!(runs.running(), None);
+
+        let (second, _kill) = runs.start("make test".into()).unwrap();
+        // the first run's end is no longer news
+        let status = Err(Arc::new(std::io::Error::other("gone")));
+        assert_eq!(runs.done(first, &status), None);
+        assert!(runs.start("make".into()).is_err());
+        let message = runs.done(second, &status);
+        assert_eq!(message.as_deref(), Some("'make test' could not run: gone"));
+        assert!(runs.start("make".into()).is_ok());
+    }
+}
@@ -0,0 +1,324 @@
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use ratatui::layout::Rect;
+
+/// Puts the terminal into, and takes it out of, the state the editor draws in.
+pub(crate) trait TerminalGuard: Send {
+    fn enter(&mut self) -> Result<()>;
+    fn exit(&mut self) -> Result<()>;
+}
+
+/// Where on the terminal the editor draws.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Viewport {
+    /// All of the alternate screen, leaving the normal one and its scrollback as they were.
+    #[default]
+    Fullscreen,
+    /// The bottom rows of the normal screen, this many or as many as there are, with what was on
+    /// screen scrolled up above them.
+    Inline(u16),
+}
+
+impl Viewport {
+    /// The cells drawn in on a terminal `width` by `height` cells.
+    pub(crate) fn area(self, width: u16, height: u16) -> Rect {
+        match self {
+            Viewport::Fullscreen => Rect::new(0, 0, width, height),
+            Viewport::Inline(rows) => {
+                let rows = rows.min(height);
+                Rect::new(0, height - rows, width, rows)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Crossterm {
+    supports_keyboard_enhancement: bool,
+    viewport: Viewport,
+}
+
+impl Crossterm {
+    pub(crate) fn new(supports_keyboard_enhancement: bool, viewport: Viewport) -> Self {
+        Self { supports_keyboard_enhancement, viewport }
+    }
+}
+
+impl TerminalGuard for Crossterm {
+    fn enter(&mut self) -> Result<()> {
+        enter(self.supports_keyboard_enhancement, self.viewport)
+    }
+
+    fn exit(&mut self) -> Result<()> {
+        exit(self.supports_keyboard_enhancement, self.viewport)
+    }
+}
+
+/// Tracks whether the editor has handed the terminal back to the shell.
+pub(crate) struct Session {
+    guard: Box<dyn TerminalGuard>,
+    suspended: bool,
+}
+
+impl Session {
+    pub(crate) fn new(guard: Box<dyn TerminalGuard>) -> Self {
+        Self { guard, suspended: false }
+    }
+
+    /// Restores the terminal, then calls `stop`, which returns once the process is continued.
+    pub(crate) fn suspend(&mut self, stop: impl FnOnce() -> Result<()>) -> Result<()> {
+        if self.suspended {
+            return Ok(());
+        }
+        self.guard.exit()?;
+        self.suspended = true;
+        stop()
+    }
+
+    /// Takes the terminal back after a suspend and calls `redraw` to repaint it from scratch.
+    /// Does nothing if the session was not suspended.
+    pub(crate) fn resume(&mut self, redraw: impl FnOnce() -> Result<()>) -> Result<()> {
+        if !self.suspended {
+            return Ok(());
+        }
+        self.guard.enter()?;
+        self.suspended = false;
+        redraw()
+    }
+}
+
+/// The terminal's window title as last set, so it's only written again when it changes.
+#[derive(Debug, Default)]
+pub(crate) struct Title {
+    last: Option<String>,
+}
+
+impl Title {
+    /// Queues `title` to `out`, or clears the title for None, unless it's what was set last.
+    pub(crate) fn update(&mut self, out: &mut impl Write, title: Option<&str>) -> Result<()> {
+        use crossterm::{terminal::SetTitle, QueueableCommand};
+
+        if self.last.as_deref() == title {
+            return Ok(());
+        }
+        out.queue(SetTitle(title.unwrap_or_default()))?;
+        self.last = title.map(str::to_string);
+        Ok(())
+    }
+}
+
+/// Saves the window title on the terminal's title stack, for `POP_TITLE` to put back on exit.
+const PUSH_TITLE: &str = "\x1b[22;0t";
+/// Puts back the window title `PUSH_TITLE` saved.
+const POP_TITLE: &str = "\x1b[23;0t";
+
+/// Stops the process the way the shell's job control expects.
+#[cfg(unix)]
+pub(crate) fn stop() -> Result<()> {
+    // SAFETY: raise only delivers a signal to the calling thread.
+    if unsafe { libc::raise(libc::SIGTSTP) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("raise SIGTSTP");
+    }
+    Ok(())
+}
+
+/// Sends `Command::Resume` every time the process is continued.
+#[cfg(unix)]
+pub(crate) async fn on_continue(cmd_tx: tokio::sync::mpsc::Sender<crate::app::Command>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signals = match signal(SignalKind::from_raw(libc::SIGCONT)) {
+        Ok(signals) => signals,
+        Err(err) => {
+            tracing::error!(%err, "cannot listen for SIGCONT");
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        if cmd_tx.send(crate::app::Command::Resume).await.is_err() {
+            break;
+        }
+    }
+}
+
+pub(crate) fn enter(supports_keyboard_enhancement: bool, viewport: Viewport) -> Result<()> {
+    use crossterm::event::{
+        EnableFocusChange, KeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    };
+    use crossterm::style::Print;
+    use crossterm::terminal;
+    use crossterm::{cursor, QueueableCommand};
+
+    let mut stdout = std::io::stdout();
+    terminal::enable_raw_mode().context("enable raw mode")?;
+    match viewport {
+        Viewport::Fullscreen => {
+            stdout.queue(terminal::EnterAlternateScreen)?;
+        }
+        Viewport::Inline(_) => {
+            let (width, height) = terminal::size().context("terminal size")?;
+            let cursor = cursor::position().context("cursor position")?;
+            reserve(&mut stdout, viewport.area(width, height), cursor)?;
+        }
+    }
+    let command_queue = stdout.queue(EnableFocusChange)?.queue(Print(PUSH_TITLE))?;
+    if supports_keyboard_enhancement {
+        command_queue.queue(PushKeyboardEnhancementFlags(
+            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                | KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS
+                | KeyboardEnhancementFlags::REPORT_EVENT_TYPES,
+        ))?;
+    }
+    command_queue.flush().context("setup terminal")?;
+    Ok(())
+}
+
+pub(crate) fn exit(supports_keyboard_enhancement: bool, viewport: Viewport) -> Result<()> {
+    use crossterm::event::{DisableFocusChange, PopKeyboardEnhancementFlags};
+    use crossterm::style::Print;
+    use crossterm::QueueableCommand;
+    use crossterm::{cursor, terminal};
+
+    let mut stdout = std::io::stdout();
+    match viewport {
+        Viewport::Fullscreen => {
+            stdout
+                .queue(terminal::Clear(terminal::ClearType::All))?
+                .queue(terminal::LeaveAlternateScreen)?;
+        }
+        Viewport::Inline(_) => {
+            // the region is wherever the last resize put it: the bottom of the terminal now
+            let (width, height) = terminal::size().context("terminal size")?;
+            release(&mut stdout, viewport.area(width, height))?;
+        }
+    }
+    // terminals without a title stack are left with no title rather than the editor's
+    let command_queue = stdout
+        .queue(cursor::Show)?
+        .queue(DisableFocusChange)?
+        .queue(terminal::SetTitle(""))?
+        .queue(Print(POP_TITLE))?;
+    if supports_keyboard_enhancement {
+        command_queue.queue(PopKeyboardEnhancementFlags)?;
+    }
+    command_queue.flush().context("reset terminal")?;
+    terminal::disable_raw_mode().context("disable raw mode")?;
+    Ok(())
+}
+
+/// Makes room for an inline editor in `area`, with the cursor at `cursor` just after what's on
+/// screen: whatever `area` would cover is scrolled up out of its way, and it's cleared.
+fn reserve(out: &mut impl Write, area: Rect, cursor: (u16, u16)) -> Result<()> {
+    use crossterm::{cursor, terminal, QueueableCommand};
+
+    let (column, row) = cursor;
+    // the cursor's row has output on it too, unless the cursor is at its start
+    let used = row + u16::from(column > 0);
+    let covered = used.saturating_sub(area.top());
+    if covered > 0 {
+        out.queue(terminal::ScrollUp(covered))?;
+    }
+    out.queue(cursor::MoveTo(0, area.top()))?
+        .queue(terminal::Clear(terminal::ClearType::FromCursorDown))?;
+    Ok(())
+}
+
+/// Clears an inline editor's `area`, leaving the cursor at its top: on the row under what was on
+/// screen before it.
+fn release(out: &mut impl Write, area: Rect) -> Result<()> {
+    use crossterm::{cursor, terminal, QueueableCommand};
+
+    out.queue(cursor::MoveTo(0, area.top()))?
+        .queue(terminal::Clear(terminal::ClearType::FromCursorDown))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<&'static str>>>);
+
+    impl TerminalGuard for Recorder {
+        fn enter(&mut self) -> Result<()> {
+            self.0.lock().unwrap().push("enter");
+            Ok(())
+        }
+
+        fn exit(&mut self) -> Result<()> {
+            self.0.lock().unwrap().push("exit");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn suspend_and_resume_order() -> Result<()> {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut session = Session::new(Box::new(Recorder(log.clone())));
+        let record = |event| {
+            let log = log.clone();
+            move || {
+                log.lock().unwrap().push(event);
+                Ok(())
+            }
+        };
+
+        // a SIGCONT without a prior suspend leaves the terminal alone
+        session.resume(record("redraw"))?;
+        assert!(log.lock().unwrap().is_empty());
+
+        session.suspend(record("stop"))?;
+        session.suspend(record("stop"))?;
+        session.resume(record("redraw"))?;
+        session.resume(record("redraw"))?;
+        assert_eq!(*log.lock().unwrap(), vec!["exit", "stop", "enter", "redraw"]);
+        Ok(())
+    }
+
+    #[test]
+    fn title_written_when_changed() -> Result<()> {
+        let mut title = Title::default();
+        let mut update = |text| -> Result<String> {
+            let mut out = vec![];
+            title.update(&mut out, text)?;
+            Ok(String::from_utf8(out)?)
+        };
+
+        // nothing to clear before a title was ever set
+        assert_eq!(update(None)?, "");
+        assert_eq!(update(Some("a.rs — toku"))?, "\x1b]0;a.rs — toku\x07");
+        assert_eq!(update(Some("a.rs — toku"))?, "");
+        assert_eq!(update(Some("a.rs ± — toku"))?, "\x1b]0;a.rs ± — toku\x07");
+        assert_eq!(update(None)?, "\x1b]0;\x07");
+        assert_eq!(update(None)?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn inline_region() -> Result<()> {
+        let viewport = Viewport::Inline(10);
+        assert_eq!(viewport.area(80, 24), Rect::new(0, 14, 80, 10));
+        // a terminal shrunk below the rows asked for is all taken
+        assert_eq!(viewport.area(80, 6), Rect::new(0, 0, 80, 6));
+        assert_eq!(Viewport::Fullscreen.area(80, 24), Rect::new(0, 0, 80, 24));
+
+        let queued = |cursor| -> Result<String> {
+            let mut out = vec![];
+            reserve(&mut out, viewport.area(80, 24), cursor)?;
+            Ok(String::from_utf8(out)?)
+        };
+        // output down to the row above the region stays where it is
+        assert_eq!(queued((0, 14))?, "\x1b[15;1H\x1b[J");
+        // a prompt at the bottom is scrolled up by all the rows, and a half-written row too
+        assert_eq!(queued((0, 23))?, "\x1b[9S\x1b[15;1H\x1b[J");
+        assert_eq!(queued((3, 23))?, "\x1b[10S\x1b[15;1H\x1b[J");
+
+        let mut out = vec![];
+        release(&mut out, viewport.area(80, 20))?;
+        assert_eq!(String::from_utf8(out)?, "\x1b[11;1H\x1b[J");
+        Ok(())
+    }
+}
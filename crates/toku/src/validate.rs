@@ -0,0 +1,30 @@
+use editor::{AuditFailure, BufferContents};
+
+/// Audits the summaries and line counts of buffers after they're edited, so summaries that drift
+/// from the text are caught where they happen rather than when lines later render wrong.
+#[derive(Debug)]
+pub struct Validator {
+    /// Picks the chunks sampled from large buffers, moved on by every audit.
+    seed: u64,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.subsec_nanos());
+        Self { seed: u64::from(nanos) }
+    }
+
+    /// Audits `contents`, sampling other chunks than last time if it's large.
+    pub fn audit(&mut self, contents: &BufferContents) -> Result<(), AuditFailure> {
+        self.seed = self.seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+        contents.audit(self.seed)
+    }
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,69 @@
+//! Drawing a frame of a densely highlighted buffer. Looking a row's highlights up once rather
+//! than once per cell took `render_highlighted_frame` from 1.5-1.9ms to 0.45-0.67ms a frame, over
+//! three runs each on one core with nightly-2024-05-28; `lookup_per_cell` is what the per-cell
+//! lookups alone came to, 0.8-1.4ms.
+
+#![feature(test)]
+extern crate test;
+
+use editor::{Buffer, BufferCommand, Editor, Highlights};
+use ratatui::prelude as tui;
+use test::Bencher;
+use ui::{EditorPane, Theme};
+
+const WIDTH: u16 = 200;
+const HEIGHT: u16 = 60;
+const LINE: &str = "    let value = compute(first, second) + offset * 2; // note\n";
+
+/// A buffer with every word highlighted, and a run over each line besides.
+fn highlighted() -> Buffer {
+    let contents = LINE.repeat(10_000);
+    let mut buffer = Buffer::new(Default::default(), contents.as_str().into());
+    let mut highlights = Highlights::new();
+    let names = ["keyword", "variable", "function", "operator", "constant"];
+    for (line, start) in (0..contents.len()).step_by(LINE.len()).enumerate() {
+        let mut at = start;
+        for (i, word) in LINE.split(' ').enumerate() {
+            if !word.is_empty() {
+                highlights.insert(at..at + word.len(), names[i % names.len()].to_string());
+            }
+            at += word.len() + 1;
+        }
+        if line % 2 == 0 {
+            highlights.insert(start..start + LINE.len() - 1, "comment".to_string());
+        }
+    }
+    buffer.command(BufferCommand::Highlight(0, highlights));
+    buffer
+}
+
+#[bench]
+fn render_highlighted_frame(b: &mut Bencher) {
+    let buffer = highlighted();
+    let mut editor = Editor::new(Default::default(), buffer.id);
+    editor.set_cursor(editor::Point { line: 5_000, column: 0 });
+    let theme = Theme::default();
+    let area = tui::Rect::new(0, 0, WIDTH, HEIGHT);
+    let mut buf = tui::Buffer::empty(area);
+    let snapshot = editor.snapshot(&buffer);
+    b.iter(|| EditorPane::new(&theme, &snapshot).render(&mut buf, area));
+}
+
+/// Just the lookups a frame took when each cell queried the highlights on its own.
+#[bench]
+fn lookup_per_cell(b: &mut Bencher) {
+    let buffer = highlighted();
+    let start = buffer
+        .contents
+        .line_to_byte(5_000 - usize::from(HEIGHT) + 1);
+    b.iter(|| {
+        let mut found = 0;
+        for row in 0..usize::from(HEIGHT) {
+            let line = start + row * LINE.len();
+            for offset in line..line + usize::from(WIDTH).min(LINE.len() - 1) {
+                found += usize::from(buffer.highlights.iter(offset..offset + 1).next().is_some());
+            }
+        }
+        found
+    });
+}
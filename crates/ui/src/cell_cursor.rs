@@ -0,0 +1,55 @@
+use crossterm::cursor::SetCursorStyle;
+use ratatui::prelude as tui;
+use tore::CursorPoint;
+
+/// What a bar cursor is drawn as, over the left edge of its cell.
+const BAR: &str = "▏";
+
+/// Draws the cursor into the cell at `point` rather than leaving it to the terminal, for
+/// terminals that don't put their own where it's asked to be. A block reverses the cell, a bar
+/// takes its left edge, and an underscore underlines it; either way the cell keeps the colors
+/// it was drawn with, so a highlighted or selected cell still shows as one. A point outside
+/// `area`, like the cursor of a pane scrolled past it, is drawn at the nearest cell inside.
+pub fn draw_cell_cursor(
+    buf: &mut tui::Buffer,
+    area: tui::Rect,
+    (point, shape): (CursorPoint, SetCursorStyle),
+) {
+    if area.is_empty() {
+        return;
+    }
+    let x = point.x.clamp(area.left(), area.right() - 1);
+    let y = point.y.clamp(area.top(), area.bottom() - 1);
+    let cell = buf.get_mut(x, y);
+    match shape {
+        SetCursorStyle::BlinkingBar | SetCursorStyle::SteadyBar => {
+            cell.set_symbol(BAR);
+        }
+        SetCursorStyle::BlinkingUnderScore | SetCursorStyle::SteadyUnderScore => {
+            cell.modifier.insert(tui::Modifier::UNDERLINED);
+        }
+        SetCursorStyle::BlinkingBlock
+        | SetCursorStyle::SteadyBlock
+        | SetCursorStyle::DefaultUserShape => {
+            // a cell that's already reversed, like a secondary cursor's, is turned back
+            cell.modifier.toggle(tui::Modifier::REVERSED);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamped_to_area() {
+        let area = tui::Rect::new(0, 0, 4, 2);
+        let mut buf = tui::Buffer::empty(area);
+        let off_screen = CursorPoint { x: 9, y: 5 };
+        draw_cell_cursor(&mut buf, area, (off_screen, SetCursorStyle::BlinkingBlock));
+        assert!(buf.get(3, 1).modifier.contains(tui::Modifier::REVERSED));
+
+        draw_cell_cursor(&mut buf, area, (off_screen, SetCursorStyle::BlinkingBlock));
+        assert!(!buf.get(3, 1).modifier.contains(tui::Modifier::REVERSED));
+    }
+}
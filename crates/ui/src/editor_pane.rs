@@ -1,81 +1,1151 @@
 use crossterm::cursor::SetCursorStyle;
-use editor::{Buffer, Editor};
+use editor::{BufferSnapshot, DiffAlignment, DiffSide, EditorSnapshot};
 use ratatui::prelude as tui;
+use rope::compat::RopeSlice;
 use tore::CursorPoint;
 
-use crate::Theme;
+use crate::hex_pane::HexPane;
+use crate::{Gutter, GutterWidths, ListChars, Scrollbar, Theme};
 
+/// Draws an editor from a snapshot of it, so nothing it draws borrows the live editor or buffer.
 pub struct EditorPane<'a> {
     theme: &'a Theme,
-    buffer: &'a Buffer,
-    editor: &'a Editor,
+    buffer: &'a BufferSnapshot,
+    editor: &'a EditorSnapshot,
+    gutter: Gutter<'a>,
+    aligned: Option<Aligned<'a>>,
+    list: Option<&'a ListChars>,
+    top: Option<usize>,
 }
 
+/// The side of a diff a pane shows, and the row it's scrolled to keep in view.
+#[derive(Clone, Copy)]
+struct Aligned<'a> {
+    alignment: &'a DiffAlignment,
+    side: DiffSide,
+    row: usize,
+}
+
+/// What's drawn on a row of the pane.
+enum Row {
+    /// The line, and whether it differs from the one across from it in a diff.
+    Line(usize, bool),
+    /// Nothing, across from a line only the other side of a diff has.
+    Filler,
+    /// Nothing, past the end of the buffer.
+    End,
+}
+
+/// What filler rows are drawn with, like vim's diff filler.
+const FILLER: char = '-';
+
 impl<'a> EditorPane<'a> {
-    pub fn new(theme: &'a Theme, buffer: &'a Buffer, editor: &'a Editor) -> Self {
-        Self { theme, buffer, editor }
+    pub fn new(theme: &'a Theme, editor: &'a EditorSnapshot) -> Self {
+        let (aligned, list, top) = (None, None, None);
+        let buffer = &editor.buffer;
+        Self { theme, buffer, editor, gutter: Gutter::DEFAULT, aligned, list, top }
+    }
+
+    pub fn with_gutter(mut self, gutter: Gutter<'a>) -> Self {
+        self.gutter = gutter;
+        self
+    }
+
+    /// Draws whitespace as `list` has it, or as itself for none.
+    pub fn with_list(mut self, list: Option<&'a ListChars>) -> Self {
+        self.list = list;
+        self
+    }
+
+    /// Draws the text scrolled to `top`, like partway through a smooth scroll, rather than just
+    /// far enough to keep the cursor in view. The cursor is still drawn where it is.
+    pub fn scrolled_to(mut self, top: Option<usize>) -> Self {
+        self.top = top;
+        self
+    }
+
+    /// Shows the buffer as the `side` of `alignment`, scrolled to keep `row` in view the way it
+    /// would the cursor's, so both sides scrolled to the same row stay across from each other.
+    /// Filler rows put line numbers out of step, so there's no gutter.
+    pub fn aligned(mut self, alignment: &'a DiffAlignment, side: DiffSide, row: usize) -> Self {
+        self.aligned = Some(Aligned { alignment, side, row });
+        self
+    }
+
+    /// The row the line is drawn on, counting from the top of the buffer.
+    fn row_of(&self, line: usize) -> usize {
+        match self.aligned {
+            Some(aligned) => aligned.alignment.row_of(aligned.side, line),
+            None => line,
+        }
+    }
+
+    fn len_rows(&self) -> usize {
+        match self.aligned {
+            Some(aligned) => aligned.alignment.rows().len(),
+            None => self.buffer.contents.len_lines(),
+        }
+    }
+
+    fn row(&self, row: usize) -> Row {
+        let len = self.buffer.contents.len_lines();
+        let Some(aligned) = self.aligned else {
+            return if row < len {
+                Row::Line(row, false)
+            } else {
+                Row::End
+            };
+        };
+        // an alignment a buffer has outgrown shows what's left of it
+        match aligned.alignment.rows().get(row) {
+            Some(aligned_row) => match aligned_row.line(aligned.side) {
+                Some(line) if line < len => Row::Line(line, aligned_row.changed),
+                Some(_) => Row::End,
+                None => Row::Filler,
+            },
+            None => Row::End,
+        }
+    }
+
+    /// The lines on the `height` rows from `top`.
+    fn visible_lines(&self, top: usize, height: u16) -> std::ops::Range<usize> {
+        let end = (top + usize::from(height)).min(self.len_rows());
+        let Some(aligned) = self.aligned else {
+            return top.min(end)..end;
+        };
+        let rows = aligned.alignment.rows().get(top..end).unwrap_or_default();
+        let mut lines = rows.iter().filter_map(|row| row.line(aligned.side));
+        let len = self.buffer.contents.len_lines();
+        match lines.next() {
+            Some(first) => first.min(len)..(lines.last().unwrap_or(first) + 1).min(len),
+            None => 0..0,
+        }
+    }
+
+    /// The screen row of `area` the line is drawn on when scrolled to `top`, if it's in view.
+    fn screen_row(&self, area: tui::Rect, top: usize, line: usize) -> Option<u16> {
+        let y = u16::try_from(self.row_of(line).checked_sub(top)?).ok()?;
+        (y < area.height).then(|| area.top() + y)
     }
 
     fn screen_offset(&self, dims: tui::Rect) -> editor::Point {
-        let cursor = self.editor.cursor;
+        let cursor = self.editor.cursor();
+        let row = self.aligned.map_or(cursor.line, |aligned| aligned.row);
         let width: usize = dims.width.into();
         let height: usize = dims.height.into();
         let column = if cursor.column >= width {
-            cursor.column - width
-        } else {
-            0
-        };
-        let line = if cursor.line >= height {
-            cursor.line - height
+            cursor.column + 1 - width
         } else {
             0
         };
+        let line = self.top.unwrap_or_else(|| scroll_top(row, height));
         editor::Point { line, column }
     }
 
-    fn offset_cursor(&self, _area: tui::Rect, cursor: tore::Point) -> CursorPoint {
-        CursorPoint { x: cursor.column as u16, y: cursor.line as u16 }
+    fn screen_cursor_position(&self, area: tui::Rect, cursor: tore::Point) -> CursorPoint {
+        let offset = self.screen_offset(area);
+        let x = usize::from(area.left()) + cursor.column.saturating_sub(offset.column);
+        let y = usize::from(area.top()) + self.row_of(cursor.line).saturating_sub(offset.line);
+        CursorPoint::new(x, y)
+    }
+
+    /// Splits `dims` into the text and, when the buffer doesn't fit, a scrollbar down the right.
+    fn layout(&self, dims: tui::Rect) -> (tui::Rect, Option<(tui::Rect, Scrollbar)>) {
+        let len = self.len_rows();
+        let viewport = usize::from(dims.height);
+        let offset = self.screen_offset(dims).line;
+        let scrollbar = Scrollbar::new(len, offset, viewport);
+        if !scrollbar.is_visible() || dims.width < 2 {
+            return (dims, None);
+        }
+        let text = tui::Rect { width: dims.width - 1, ..dims };
+        let bar = tui::Rect { x: text.right(), width: 1, ..dims };
+        (text, Some((bar, scrollbar)))
+    }
+
+    /// Splits the gutter off the left of `dims`, with the width of each of its columns. The
+    /// widths are worked out once a frame, and the gutter is left out if the text wouldn't fit.
+    fn gutter(&self, dims: tui::Rect) -> (tui::Rect, Option<(tui::Rect, GutterWidths)>) {
+        let widths = self.gutter.widths(self.buffer);
+        let width: u16 = widths.iter().map(|(_, width)| width).sum();
+        if width == 0 || dims.width <= width {
+            return (dims, None);
+        }
+        let gutter = tui::Rect { width, ..dims };
+        let text = tui::Rect { x: dims.x + width, width: dims.width - width, ..dims };
+        (text, Some((gutter, widths)))
     }
 
     #[tracing::instrument(skip(self, buf))]
     pub fn render(self, buf: &mut tui::Buffer, dims: tui::Rect) -> (CursorPoint, SetCursorStyle) {
         use bstr::ByteSlice;
 
+        if self.buffer.is_binary() {
+            return HexPane::new(self.theme, self.editor).render(buf, dims);
+        }
+        let (dims, scrollbar) = self.layout(dims);
+        if let Some((area, scrollbar)) = scrollbar {
+            let track = self.theme.style("ui.scrollbar");
+            let thumb = self.theme.style("ui.scrollbar.thumb");
+            scrollbar.render(buf, area, track, thumb);
+        }
+        let (dims, gutter) = match self.aligned {
+            Some(_) => (dims, None),
+            None => self.gutter(dims),
+        };
         let offset = self.screen_offset(dims);
-        let mut lines = self.buffer.contents.lines_at(offset.line);
+        if let Some((area, widths)) = gutter {
+            self.gutter
+                .render(buf, area, &widths, self.buffer, offset.line, self.theme);
+        }
+        let background = self.theme.style("ui.background");
+        let virtual_text = self.theme.style("ui.virtual");
+        let invalid = self.theme.style("ui.invalid");
+        let filler = self.theme.style("ui.diff.filler");
+        let changed = match self.aligned.map(|aligned| aligned.side) {
+            Some(DiffSide::Left) => self.theme.style("ui.diff.minus"),
+            _ => self.theme.style("ui.diff.plus"),
+        };
         let x = dims.left();
         for (yoffset, y) in (dims.top()..dims.bottom()).enumerate() {
-            if let Some(line) = lines.next() {
-                let line_offset = self.buffer.contents.line_to_byte(offset.line + yoffset);
+            // an empty buffer still has one (empty) line, so only rows past it get a marker.
+            let shown = self.row(offset.line + yoffset);
+            let (row_style, blank) = match shown {
+                Row::Line(_, true) => (changed, ' '),
+                Row::Filler => (filler, FILLER),
+                Row::Line(..) | Row::End => (background, ' '),
+            };
+            // the buffer is reused between frames; clear whatever the last one left in this row.
+            let row = tui::Rect::new(x, y, dims.width, 1);
+            buf.set_style(row, tui::Style::reset().patch(row_style));
+            for xoffset in 0..dims.width {
+                buf.get_mut(x + xoffset, y).set_char(blank);
+            }
+
+            if let Row::Line(n, _) = shown {
+                let line = self.buffer.contents.line(n);
+                let (slice, slice_offset) = visible_slice(line, offset.column, dims.width);
+                let slice_offset = self.buffer.contents.line_to_byte(n) + slice_offset;
+                // one lookup for the whole row, swept across it
+                let visible = slice_offset..slice_offset + slice.len_bytes();
+                let highlights = self.buffer.highlights_in(visible);
+                let mut sweep = HighlightSweep::new(&highlights);
+                // where trailing whitespace starts is worked out once a line, from its end
+                let trailing_from = match self.list {
+                    Some(_) => self.buffer.contents.line_to_byte(n) + trailing_whitespace(line),
+                    None => usize::MAX,
+                };
                 let mut xoffset = 0;
-                'row_loop: for chunk in line.chunks() {
+                let mut chunk_offset = slice_offset;
+                'row_loop: for chunk in slice.chunks() {
                     for (start, end, grapheme) in chunk.as_bytes().as_bstr().grapheme_indices() {
-                        if x + xoffset >= dims.width || grapheme == "\n" {
+                        if x + xoffset >= dims.right() {
+                            break 'row_loop;
+                        }
+                        if grapheme == "\n" || grapheme == "\r\n" {
+                            if let Some(eol) = self.list.and_then(|list| list.eol) {
+                                buf.get_mut(x + xoffset, y)
+                                    .set_char(eol)
+                                    .set_style(virtual_text);
+                            }
                             break 'row_loop;
                         }
 
                         let cell = buf.get_mut(x + xoffset, y);
-                        let char_range = line_offset + start..line_offset + end;
-                        if let Some((_, name)) = self.buffer.highlights.iter(char_range).next() {
-                            if let Some(color) = self.theme.scheme(name) {
-                                cell.set_fg(color.0);
-                            }
+                        let char_range = chunk_offset + start..chunk_offset + end;
+                        if let Some(name) = sweep.shown(char_range.clone()) {
+                            cell.set_style(self.theme.style(name));
                         }
 
-                        cell.set_symbol(grapheme);
+                        // shown whitespace takes its own color, over any highlight
+                        let trailing = char_range.start >= trailing_from;
+                        let list = self.list.and_then(|list| list.glyph(grapheme, trailing));
+                        match (placeholder(grapheme), list) {
+                            (Some(c), _) => {
+                                cell.set_char(c).set_style(invalid);
+                            }
+                            (None, Some((c, scope))) => {
+                                cell.set_char(c).set_style(self.theme.style(scope));
+                            }
+                            (None, None) => {
+                                cell.set_symbol(grapheme);
+                            }
+                        }
                         xoffset += 1;
                     }
+                    chunk_offset += chunk.len();
                 }
+            } else if let Row::Filler = shown {
+                buf.set_style(row, virtual_text);
             } else {
-                buf.get_mut(x, y).set_char('~');
+                buf.get_mut(x, y).set_char('~').set_style(virtual_text);
             }
         }
 
-        let cursor_pos = self.offset_cursor(dims, self.editor.cursor);
+        self.render_search_matches(buf, dims, offset);
+        self.render_selections(buf, dims, offset);
+
+        let cursor_pos = self.screen_cursor_position(dims, self.editor.cursor());
         let cursor_style = match self.editor.mode {
-            editor::Mode::Normal => SetCursorStyle::BlinkingBlock,
+            editor::Mode::Normal | editor::Mode::VisualLine => SetCursorStyle::BlinkingBlock,
             editor::Mode::Insert => SetCursorStyle::BlinkingBar,
+            editor::Mode::Replace => SetCursorStyle::BlinkingUnderScore,
         };
         (cursor_pos, cursor_style)
     }
 }
+
+impl EditorPane<'_> {
+    /// Shades the matches of the editor's search that are on screen. Matches found before the
+    /// buffer was last edited no longer line up with it, and are left out until they're found
+    /// again.
+    fn render_search_matches(&self, buf: &mut tui::Buffer, area: tui::Rect, offset: editor::Point) {
+        let Some(search) = self.editor.search() else {
+            return;
+        };
+        if search.version != self.buffer.version {
+            return;
+        }
+        let matched = self.theme.style("ui.search");
+        let contents = &self.buffer.contents;
+        let lines = self.visible_lines(offset.line, area.height);
+        let (start, end) = (contents.line_to_char(lines.start), contents.line_to_char(lines.end));
+        let columns = offset.column..offset.column + usize::from(area.width);
+        let matches = search.matches();
+        let first = matches.partition_point(|m| m.end <= start);
+        for m in matches[first..].iter().take_while(|m| m.start < end) {
+            for char in m.start.max(start)..m.end.min(end) {
+                let line = contents.char_to_line(char);
+                let column = char - contents.line_to_char(line);
+                let Some(y) = self.screen_row(area, offset.line, line) else {
+                    continue;
+                };
+                if !columns.contains(&column) {
+                    continue;
+                }
+                let x = area.left() + u16::try_from(column - offset.column).unwrap_or(u16::MAX);
+                buf.get_mut(x, y).set_style(matched);
+            }
+        }
+    }
+
+    /// Shades every selection and shows every cursor but the primary, which is the terminal's.
+    fn render_selections(&self, buf: &mut tui::Buffer, area: tui::Rect, offset: editor::Point) {
+        let selected = self.theme.style("ui.selection");
+        let lines = self.visible_lines(offset.line, area.height);
+        let columns = offset.column..offset.column + usize::from(area.width);
+        let cell = |line: usize, column: usize| {
+            let x = area.left() + u16::try_from(column - offset.column).ok()?;
+            let y = self.screen_row(area, offset.line, line)?;
+            Some((x, y))
+        };
+
+        let linewise = self.editor.mode == editor::Mode::VisualLine;
+        for (i, selection) in self.editor.selections().iter().enumerate() {
+            let editor::PointRange { start, end } = selection.range();
+            for line in start.line.max(lines.start)..(end.line + 1).min(lines.end) {
+                let from = if line == start.line && !linewise {
+                    start.column
+                } else {
+                    0
+                };
+                // a selection running onto the next line covers the line break too, and a
+                // linewise one the whole width of the pane
+                let to = if linewise {
+                    columns.end
+                } else if line == end.line {
+                    end.column
+                } else {
+                    self.buffer.contents.line(line).len_chars()
+                };
+                for column in from.max(columns.start)..to.min(columns.end) {
+                    if let Some((x, y)) = cell(line, column) {
+                        buf.get_mut(x, y).set_style(selected);
+                    }
+                }
+            }
+
+            let head = selection.head;
+            if i != self.editor.primary()
+                && lines.contains(&head.line)
+                && columns.contains(&head.column)
+            {
+                if let Some((x, y)) = cell(head.line, head.column) {
+                    buf.get_mut(x, y).modifier.insert(tui::Modifier::REVERSED);
+                }
+            }
+        }
+    }
+}
+
+/// What's drawn in place of a char that can't be shown as itself: a control char as its control
+/// picture, and one the terminal has no picture for, like bytes that didn't decode, as U+FFFD.
+/// Either still takes the one cell its char does, so columns line up with the cursor.
+fn placeholder(grapheme: &str) -> Option<char> {
+    let mut chars = grapheme.chars();
+    let c = chars.next()?;
+    if chars.next().is_some() {
+        return None;
+    }
+    match c {
+        '\t' => None,
+        '\0'..='\x1f' => char::from_u32(0x2400 + c as u32),
+        '\x7f' => Some('\u{2421}'),
+        '\u{80}'..='\u{9f}' | char::REPLACEMENT_CHARACTER => Some(char::REPLACEMENT_CHARACTER),
+        _ => None,
+    }
+}
+
+/// Finds which of a line's highlights shows at each of its graphemes in turn, with a single
+/// lookup for the line. The highlights come sorted by start and then end, and one that ends
+/// before a grapheme can't cover any after it, so the first not yet ended is the one that shows,
+/// just as a lookup per grapheme would find.
+pub(crate) struct HighlightSweep<'a, 'h> {
+    highlights: &'a [(std::ops::Range<usize>, &'h str)],
+    open: usize,
+}
+
+impl<'a, 'h> HighlightSweep<'a, 'h> {
+    pub(crate) fn new(highlights: &'a [(std::ops::Range<usize>, &'h str)]) -> Self {
+        Self { highlights, open: 0 }
+    }
+
+    /// The highlight shown on the bytes `range`, which must come after any asked about before.
+    pub(crate) fn shown(&mut self, range: std::ops::Range<usize>) -> Option<&'h str> {
+        while self
+            .highlights
+            .get(self.open)
+            .is_some_and(|(highlight, _)| highlight.end <= range.start)
+        {
+            self.open += 1;
+        }
+        let (highlight, name) = self.highlights.get(self.open)?;
+        (highlight.start < range.end).then_some(*name)
+    }
+}
+
+/// The byte offset into `line` of the whitespace it ends with, before its line break; its length
+/// when it ends with none. Only the whitespace is read, from the end.
+fn trailing_whitespace(line: RopeSlice<'_>) -> usize {
+    let mut chars = line.chars_at(line.len_chars()).reversed().peekable();
+    let mut len = line.len_chars();
+    while chars.next_if(|c| matches!(c, '\n' | '\r')).is_some() {
+        len -= 1;
+    }
+    let trailing = chars.take_while(|c| c.is_whitespace()).count();
+    line.char_to_byte(len - trailing)
+}
+
+/// The row at the top of a pane `height` rows tall, scrolled just far enough to show `row`.
+pub fn scroll_top(row: usize, height: usize) -> usize {
+    if row >= height {
+        row + 1 - height
+    } else {
+        0
+    }
+}
+
+/// The part of `line` that can be on screen when scrolled `hscroll` columns in a pane `width`
+/// cells wide, with its byte offset into the line. A grapheme may span several chars, so up to
+/// four chars per cell are kept; everything else is never decoded.
+fn visible_slice(line: RopeSlice<'_>, hscroll: usize, width: u16) -> (RopeSlice<'_>, usize) {
+    let len = line.len_chars();
+    let start = hscroll.min(len);
+    let end = (start + usize::from(width) * 4).min(len);
+    (line.slice(start..end), line.char_to_byte(start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden;
+    use bstr::ByteSlice;
+    use editor::{Buffer, Editor};
+    use ratatui::backend::TestBackend;
+
+    fn render(buffer: &Buffer, editor: &Editor, area: tui::Rect) -> tui::Buffer {
+        let theme = Theme::default();
+        let mut buf = tui::Buffer::empty(area);
+        EditorPane::new(&theme, &editor.snapshot(buffer)).render(&mut buf, area);
+        buf
+    }
+
+    /// The renderer before clipping: decodes every line from column 0.
+    fn render_unclipped(buffer: &Buffer, area: tui::Rect) -> tui::Buffer {
+        let theme = Theme::default();
+        let mut buf = tui::Buffer::empty(area);
+        buf.set_style(area, tui::Style::reset().bg(theme.palette("bg0").unwrap().0));
+        let mut lines = buffer.contents.lines_at(0);
+        for (yoffset, y) in (area.top()..area.bottom()).enumerate() {
+            let Some(line) = lines.next() else {
+                let fg = theme.palette("grey0").unwrap();
+                buf.get_mut(area.left(), y).set_char('~').set_fg(fg.0);
+                continue;
+            };
+            let line_offset = buffer.contents.line_to_byte(yoffset);
+            let mut x = area.left();
+            let bytes: Vec<u8> = line.bytes().collect();
+            for (start, end, grapheme) in bytes.as_bstr().grapheme_indices() {
+                if x >= area.right() || grapheme == "\n" {
+                    break;
+                }
+                let cell = buf.get_mut(x, y);
+                let range = line_offset + start..line_offset + end;
+                if let Some((_, name)) = buffer.highlights.iter(range).next() {
+                    cell.set_style(theme.style(name));
+                }
+                cell.set_symbol(grapheme);
+                x += 1;
+            }
+        }
+        buf
+    }
+
+    #[test]
+    fn long_line_decodes_only_viewport() {
+        let line = "x".repeat(1 << 20);
+        let buffer = Buffer::new(Default::default(), line.as_str().into());
+        let line = buffer.contents.line(0);
+        for hscroll in [0, 1 << 19, (1 << 20) - 10] {
+            let ((decoded, offset), counted) = rope::counters::count(|| {
+                let (slice, offset) = visible_slice(line, hscroll, 80);
+                let decoded: usize = slice.chunks().map(|chunk| chunk.len()).sum();
+                (decoded, offset)
+            });
+            assert_eq!(offset, hscroll);
+            assert!(decoded <= 80 * 4, "decoded {} bytes at {}", decoded, hscroll);
+            // the chars before the scroll are skipped over a leaf at a time, not stepped through
+            assert!(counted.leaves <= 2 && counted.steps <= 80 * 4, "{:?}", counted);
+        }
+
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(tore::Point { line: 0, column: 1 << 19 });
+        let area = tui::Rect::new(0, 0, 80, 24);
+        let (buf, counted) = rope::counters::count(|| render(&buffer, &editor, area));
+        assert!(counted.leaves <= 4 && counted.steps <= 80 * 4, "{:?}", counted);
+        let row: String = (0..80).map(|x| buf.get(x, 0).symbol.as_str()).collect();
+        assert_eq!(row, "x".repeat(80));
+        assert_eq!(buf.get(0, 1).symbol, "~");
+    }
+
+    #[test]
+    fn five_megabyte_line() {
+        use editor::{CursorJump, Direction, EditorCommand};
+
+        // like a minified file: one line, with no line break at its end
+        let text = "var a=1;".repeat(5 << 17);
+        let mut buffer = Buffer::new(Default::default(), text.as_str().into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        // each command reads a few leaves and chars around the cursor, where going along the
+        // line would read thousands of leaves and millions of chars
+        let mut run = |buffer: &mut Buffer, command| {
+            let (result, counted) = rope::counters::count(|| editor.command(buffer, command));
+            result.unwrap();
+            let bounded = counted.seeks <= 16 && counted.leaves <= 4 && counted.steps <= 16;
+            (editor.cursor(), bounded.then_some(()).ok_or(counted))
+        };
+        let at = |column| tore::Point { line: 0, column };
+        let last = text.len() - 1;
+        let moves = [
+            (EditorCommand::CursorJump(CursorJump::EndOfLine), at(last)),
+            (EditorCommand::CursorMove(Direction::Right), at(last)),
+            (EditorCommand::CursorMove(Direction::Down), at(last)),
+            (EditorCommand::CursorMove(Direction::Left), at(last - 1)),
+            // back over `=`, then to `a`
+            (EditorCommand::CursorJump(CursorJump::StartOfNearestWord), at(last - 2)),
+            (EditorCommand::CursorJump(CursorJump::StartOfNearestWord), at(last - 3)),
+            (EditorCommand::CursorMove(Direction::Up), at(last - 3)),
+            (EditorCommand::CursorJump(CursorJump::StartOfLine), at(0)),
+            (EditorCommand::CursorJump(CursorJump::StartOfNextWord), at(4)),
+            (EditorCommand::CursorJump(CursorJump::EndOfLine), at(last)),
+        ];
+        for (move_, expected) in moves {
+            let label = format!("{:?}", move_);
+            assert_eq!(run(&mut buffer, move_), (expected, Ok(())), "{}", label);
+        }
+
+        let theme = Theme::default();
+        let area = tui::Rect::new(0, 0, 80, 24);
+        let (buf, counted) = rope::counters::count(|| render(&buffer, &editor, area));
+        // a frame reads no more than the cells it has room for
+        assert!(counted.leaves <= 4 && counted.steps <= 80 * 24, "{:?}", counted);
+        let row: String = (0..80).map(|x| buf.get(x, 0).symbol.as_str()).collect();
+        assert!(row.trim_end().ends_with("var a=1;"), "{:?}", row);
+        assert_eq!(buf.get(0, 1).symbol, "~");
+
+        let area = tui::Rect::new(0, 0, 60, 1);
+        let mut buf = tui::Buffer::empty(area);
+        let status = crate::StatusLine::new(&theme, &buffer, &editor);
+        let ((), counted) = rope::counters::count(|| status.render(&mut buf, area));
+        assert!(counted.leaves <= 4 && counted.steps <= 16, "{:?}", counted);
+        let line: String = (0..60).map(|x| buf.get(x, 0).symbol.as_str()).collect();
+        assert!(line.contains(" 1/1:>100000 "), "{:?}", line);
+    }
+
+    #[test]
+    fn matches_unclipped_render() {
+        let source = "fn main() {\n    let greeting = \"héllo, wörld\";\n    println!(\"{}\", greeting);\n}\n";
+        let mut buffer = Buffer::new(Default::default(), source.into());
+        let mut highlights = editor::Highlights::new();
+        highlights.insert(0..2, "keyword".to_string());
+        highlights.insert(3..7, "function".to_string());
+        highlights.insert(16..19, "keyword".to_string());
+        highlights.insert(31..47, "string".to_string());
+        buffer.command(editor::BufferCommand::Highlight(0, highlights));
+        let editor = Editor::new(Default::default(), buffer.id);
+
+        assert_eq!(
+            render(&buffer, &editor, tui::Rect::new(0, 0, 80, 24)),
+            render_unclipped(&buffer, tui::Rect::new(0, 0, 80, 24))
+        );
+        // too short for the buffer, so the last column is a scrollbar
+        let rendered = render(&buffer, &editor, tui::Rect::new(0, 0, 20, 3));
+        let unclipped = render_unclipped(&buffer, tui::Rect::new(0, 0, 19, 3));
+        for (x, y) in (0..3).flat_map(|y| (0..19).map(move |x| (x, y))) {
+            assert_eq!(rendered.get(x, y), unclipped.get(x, y), "{}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn highlights_swept_per_row_match_lookups_per_cell() {
+        let fixtures: [(&str, &[_]); 4] = [
+            // nested: the outer one starts first, so it shows throughout
+            ("let x = f(y);\n", &[(0..12, "variable"), (4..5, "keyword"), (8..9, "function")]),
+            // two starting together: the shorter sorts first
+            ("abcdef\nghij", &[(0..3, "keyword"), (0..9, "string"), (2..8, "function")]),
+            // multi-byte chars, and highlights across the line break
+            (
+                "h\u{e9}llo w\u{f6}rld\n\u{65e5}\u{672c}\n",
+                &[(1..6, "string"), (9..17, "type")],
+            ),
+            // touching and overlapping runs
+            ("aaaa bbbb cccc", &[(0..4, "keyword"), (4..9, "string"), (5..14, "comment")]),
+        ];
+        for (text, ranges) in fixtures {
+            let mut buffer = Buffer::new(Default::default(), text.into());
+            let mut highlights = editor::Highlights::new();
+            for (range, name) in ranges {
+                highlights.insert(range.clone(), name.to_string());
+            }
+            buffer.command(editor::BufferCommand::Highlight(0, highlights));
+            let editor = Editor::new(Default::default(), buffer.id);
+            let area = tui::Rect::new(0, 0, 16, 4);
+            assert_eq!(
+                render(&buffer, &editor, area),
+                render_unclipped(&buffer, area),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    fn draw(
+        term: &mut tui::Terminal<TestBackend>,
+        buffer: &Buffer,
+        editor: &Editor,
+    ) -> CursorPoint {
+        let theme = Theme::default();
+        let mut cursor = CursorPoint::default();
+        term.draw(|frame| {
+            let area = frame.size();
+            let snapshot = editor.snapshot(buffer);
+            (cursor, _) = EditorPane::new(&theme, &snapshot).render(frame.buffer_mut(), area);
+        })
+        .unwrap();
+        cursor
+    }
+
+    fn rows(buf: &tui::Buffer) -> Vec<String> {
+        let area = buf.area;
+        let row = |y| {
+            (area.left()..area.right())
+                .map(|x| buf.get(x, y).symbol.as_str())
+                .collect()
+        };
+        (area.top()..area.bottom()).map(row).collect()
+    }
+
+    /// The frame drawn of `editor` on `buffer` in a `width` by `height` pane.
+    fn frame(buffer: &Buffer, editor: &Editor, width: u16, height: u16) -> String {
+        let theme = Theme::default();
+        golden::draw(width, height, |buf, area| {
+            EditorPane::new(&theme, &editor.snapshot(buffer)).render(buf, area)
+        })
+    }
+
+    #[test]
+    fn frames_of_text() {
+        let mut buffer = Buffer::new(Default::default(), "fn main() {\n}\n".into());
+        let editor = Editor::new(Default::default(), buffer.id);
+        // the empty line after the final line break is a line like any other
+        golden::assert_frame("plain", &frame(&buffer, &editor, 12, 4));
+        let unterminated = Buffer::new(Default::default(), "fn main() {\n}".into());
+        golden::assert_frame("no_final_newline", &frame(&unterminated, &editor, 12, 4));
+
+        let mut highlights = editor::Highlights::new();
+        highlights.insert(0..2, "keyword".to_string());
+        highlights.insert(3..7, "function".to_string());
+        for range in [7..9, 10..11, 12..13] {
+            highlights.insert(range, "punctuation.bracket".to_string());
+        }
+        buffer.command(editor::BufferCommand::Highlight(0, highlights));
+        golden::assert_frame("highlighted", &frame(&buffer, &editor, 12, 4));
+    }
+
+    #[test]
+    fn frames_of_cursors() {
+        use editor::EditorCommand;
+
+        let buffer = Buffer::new(Default::default(), "ab\ncd".into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_mode(&buffer, editor::Mode::Insert);
+        editor.set_cursor(tore::Point { line: 1, column: 2 });
+        golden::assert_frame("insert_at_end_of_line", &frame(&buffer, &editor, 8, 3));
+
+        let mut buffer = Buffer::new(Default::default(), "let a = a;\nlet b = a;".into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(tore::Point { line: 0, column: 4 });
+        editor
+            .command(&mut buffer, EditorCommand::AddCursorBelow)
+            .unwrap();
+        golden::assert_frame("secondary_cursor", &frame(&buffer, &editor, 12, 3));
+
+        let (text, cursor) = test_support::buffer_with_cursor("ab\ncde|f\ng");
+        let mut buffer = Buffer::new(Default::default(), text.as_str().into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(cursor);
+        editor
+            .command(&mut buffer, EditorCommand::SelectLine)
+            .unwrap();
+        editor
+            .command(&mut buffer, EditorCommand::CursorMove(editor::Direction::Up))
+            .unwrap();
+        golden::assert_frame("visual_line", &frame(&buffer, &editor, 6, 3));
+    }
+
+    #[test]
+    fn frames_of_snapshots() {
+        use editor::EditorCommand;
+
+        // editing after the snapshot is taken doesn't change what's drawn from it
+        let mut buffer = Buffer::new(Default::default(), "let a = a;\nlet b = a;".into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(tore::Point { line: 0, column: 4 });
+        editor
+            .command(&mut buffer, EditorCommand::AddCursorBelow)
+            .unwrap();
+        let snapshot = editor.snapshot(&buffer);
+        for cmd in [
+            EditorCommand::SetMode(editor::Mode::Insert),
+            EditorCommand::InsertChar('x'),
+            EditorCommand::SetMode(editor::Mode::Normal),
+            EditorCommand::CollapseSelections,
+        ] {
+            editor.command(&mut buffer, cmd).unwrap();
+        }
+        let mut highlights = editor::Highlights::new();
+        highlights.insert(0..3, "keyword".to_string());
+        buffer.command(editor::BufferCommand::Highlight(0, highlights));
+
+        let theme = Theme::default();
+        let frame =
+            golden::draw(12, 3, |buf, area| EditorPane::new(&theme, &snapshot).render(buf, area));
+        golden::assert_frame("secondary_cursor", &frame);
+        // while the live editor draws as it is now
+        assert_ne!(frame, self::frame(&buffer, &editor, 12, 3));
+    }
+
+    /// The frame drawn of `editor` on `buffer` with the cursor drawn into its cell.
+    fn frame_with_cell_cursor(buffer: &Buffer, editor: &Editor, width: u16, height: u16) -> String {
+        let theme = Theme::default();
+        golden::draw(width, height, |buf, area| {
+            let cursor = EditorPane::new(&theme, &editor.snapshot(buffer)).render(buf, area);
+            crate::draw_cell_cursor(buf, area, cursor);
+            cursor
+        })
+    }
+
+    #[test]
+    fn frames_of_cell_cursors() {
+        use editor::EditorCommand;
+
+        // a block keeps the colors of the highlight it's on
+        let mut buffer = Buffer::new(Default::default(), "fn main() {\n}\n".into());
+        let mut highlights = editor::Highlights::new();
+        highlights.insert(0..2, "keyword".to_string());
+        highlights.insert(3..7, "function".to_string());
+        for range in [7..9, 10..11, 12..13] {
+            highlights.insert(range, "punctuation.bracket".to_string());
+        }
+        buffer.command(editor::BufferCommand::Highlight(0, highlights));
+        let editor = Editor::new(Default::default(), buffer.id);
+        let frame = frame_with_cell_cursor(&buffer, &editor, 12, 4);
+        golden::assert_frame("cell_cursor_highlighted", &frame);
+
+        // and of the selection
+        let (text, cursor) = test_support::buffer_with_cursor("ab\ncde|f\ng");
+        let mut buffer = Buffer::new(Default::default(), text.as_str().into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(cursor);
+        editor
+            .command(&mut buffer, EditorCommand::SelectLine)
+            .unwrap();
+        editor
+            .command(&mut buffer, EditorCommand::CursorMove(editor::Direction::Up))
+            .unwrap();
+        let frame = frame_with_cell_cursor(&buffer, &editor, 6, 3);
+        golden::assert_frame("cell_cursor_selected", &frame);
+
+        let buffer = Buffer::new(Default::default(), "ab\ncd".into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_mode(&buffer, editor::Mode::Insert);
+        editor.set_cursor(tore::Point { line: 1, column: 2 });
+        let frame = frame_with_cell_cursor(&buffer, &editor, 8, 3);
+        golden::assert_frame("cell_cursor_insert", &frame);
+    }
+
+    #[test]
+    fn frames_of_small_panes() {
+        // the cursor past the right edge scrolls the pane along
+        let buffer = Buffer::new(Default::default(), "0123456789\nabc".into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(tore::Point { line: 0, column: 7 });
+        golden::assert_frame("scrolled_right", &frame(&buffer, &editor, 4, 3));
+
+        // and past the bottom, with a scrollbar
+        let lines: Vec<_> = (0..10).map(|i| format!("line {}", i)).collect();
+        let buffer = Buffer::new(Default::default(), lines.join("\n").as_str().into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(tore::Point { line: 9, column: 0 });
+        golden::assert_frame("scrolled_down", &frame(&buffer, &editor, 8, 4));
+    }
+
+    #[test]
+    fn frames_of_listed_whitespace() {
+        let theme = Theme::default();
+        let list = ListChars { eol: Some('¬'), ..Default::default() };
+        let frame = |buffer: &Buffer, editor: &Editor| {
+            golden::draw(10, 4, |buf, area| {
+                let snapshot = editor.snapshot(buffer);
+                EditorPane::new(&theme, &snapshot)
+                    .with_list(Some(&list))
+                    .render(buf, area)
+            })
+        };
+        // a tab and a space mid-line, then trailing spaces, over a highlight
+        let mut buffer = Buffer::new(Default::default(), "a\tb c  \nc\u{a0}d \n".into());
+        let mut highlights = editor::Highlights::new();
+        highlights.insert(0..7, "string".to_string());
+        buffer.command(editor::BufferCommand::Highlight(0, highlights));
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        golden::assert_frame("list", &frame(&buffer, &editor));
+
+        // a selection keeps its background under the trailing spaces
+        editor
+            .command(&mut buffer, editor::EditorCommand::SelectLine)
+            .unwrap();
+        golden::assert_frame("list_selected", &frame(&buffer, &editor));
+    }
+
+    #[test]
+    fn highlights_keep_their_modifiers() {
+        let mut buffer = Buffer::new(Default::default(), "x; // y\nz".into());
+        let mut highlights = editor::Highlights::new();
+        // a scope the theme has no entry for is drawn as the one it's under
+        highlights.insert(3..7, "comment.line".to_string());
+        buffer.command(editor::BufferCommand::Highlight(0, highlights));
+        let editor = Editor::new(Default::default(), buffer.id);
+        let buf = render(&buffer, &editor, tui::Rect::new(0, 0, 8, 2));
+        let italic = |y| {
+            (0..8)
+                .map(|x| buf.get(x, y).modifier.contains(tui::Modifier::ITALIC))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(italic(0), [false, false, false, true, true, true, true, false]);
+        assert_eq!(italic(1), [false; 8]);
+        let grey = Theme::default().palette("grey0").unwrap();
+        assert_eq!(buf.get(3, 0).fg, grey.0);
+    }
+
+    #[test]
+    fn placeholders_for_unprintable_chars() {
+        let theme = Theme::default();
+        let red = theme.palette("red").unwrap().0;
+        let buffer = Buffer::new(Default::default(), "a\u{fffd}\x01\x7fb\r\n\x1b[0m\n".into());
+        let editor = Editor::new(Default::default(), buffer.id);
+        let buf = render(&buffer, &editor, tui::Rect::new(0, 0, 6, 3));
+        assert_eq!(rows(&buf), ["a\u{fffd}␁␡b ", "␛[0m  ", "      "]);
+        let fg: Vec<_> = (0..5).map(|x| buf.get(x, 0).fg == red).collect();
+        assert_eq!(fg, [false, true, true, true, false]);
+    }
+
+    #[test]
+    fn redraw_leaves_no_stale_cells() {
+        let mut term = tui::Terminal::new(TestBackend::new(12, 10)).unwrap();
+        let long = Buffer::new(Default::default(), "a long line\nanother one\nthird line".into());
+        let short = Buffer::new(Default::default(), "ab\nc\nd".into());
+        let grey = Theme::default().palette("grey0").unwrap();
+
+        draw(&mut term, &long, &Editor::new(Default::default(), long.id));
+        draw(&mut term, &short, &Editor::new(Default::default(), short.id));
+        let mut expected = vec!["ab          ", "c           ", "d           "];
+        expected.extend(["~           "; 7]);
+        assert_eq!(rows(term.backend().buffer()), expected);
+        assert_eq!(term.backend().buffer().get(0, 3).fg, grey.0);
+
+        // rendering over a buffer that still holds the last frame clears it too
+        let area = tui::Rect::new(0, 0, 12, 10);
+        let mut buf = render(&long, &Editor::new(Default::default(), long.id), area);
+        let theme = Theme::default();
+        let snapshot = Editor::new(Default::default(), short.id).snapshot(&short);
+        EditorPane::new(&theme, &snapshot).render(&mut buf, area);
+        assert_eq!(rows(&buf), expected);
+    }
+
+    #[test]
+    fn sides_of_a_diff() {
+        let theme = Theme::default();
+        let left = Buffer::new(Default::default(), "a\nb\nd\n".into());
+        let right = Buffer::new(Default::default(), "a\nB\nc\nd\n".into());
+        let alignment = DiffAlignment::new(&left.contents, &right.contents);
+        let area = tui::Rect::new(0, 0, 4, 3);
+        let side = |buffer: &Buffer, side, cursor| {
+            let mut editor = Editor::new(Default::default(), buffer.id);
+            editor.set_cursor(tore::Point { line: cursor, column: 0 });
+            let row = alignment.row_of(DiffSide::Left, 2);
+            let mut buf = tui::Buffer::empty(area);
+            let snapshot = editor.snapshot(buffer);
+            let pane = EditorPane::new(&theme, &snapshot).aligned(&alignment, side, row);
+            let cursor = pane.render(&mut buf, area).0;
+            (buf, cursor)
+        };
+
+        // both sides keep the row of the left's `d` in view, with filler across from `c`
+        let (buf, cursor) = side(&left, DiffSide::Left, 2);
+        // the last column is the scrollbar
+        assert_eq!(rows(&buf), ["b   ", "--- ", "d   "]);
+        assert_eq!(cursor, CursorPoint { x: 0, y: 2 });
+        assert_eq!(buf.get(0, 0).bg, theme.palette("bg_diff_red").unwrap().0);
+        assert_eq!(buf.get(0, 1).bg, theme.palette("bg1").unwrap().0);
+        assert_eq!(buf.get(0, 2).bg, theme.palette("bg0").unwrap().0);
+
+        let (buf, cursor) = side(&right, DiffSide::Right, 3);
+        assert_eq!(rows(&buf), ["B   ", "c   ", "d   "]);
+        assert_eq!(cursor, CursorPoint { x: 0, y: 2 });
+        assert_eq!(buf.get(0, 1).bg, theme.palette("bg_diff_green").unwrap().0);
+    }
+
+    #[test]
+    fn empty_buffer() {
+        let mut term = tui::Terminal::new(TestBackend::new(8, 4)).unwrap();
+        let buffer = Buffer::empty(Default::default());
+        let cursor = draw(&mut term, &buffer, &Editor::new(Default::default(), buffer.id));
+        assert_eq!(cursor, CursorPoint { x: 0, y: 0 });
+        assert_eq!(
+            rows(term.backend().buffer()),
+            vec!["        ", "~       ", "~       ", "~       "]
+        );
+    }
+
+    #[test]
+    fn secondary_cursors_and_selections() {
+        use editor::EditorCommand;
+
+        let mut buffer = Buffer::new(Default::default(), "let a = a;\nlet b = a;".into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let area = tui::Rect::new(0, 0, 12, 3);
+        let theme = Theme::default();
+        let selected = theme.palette("bg_visual_blue").unwrap();
+
+        // a selection across a line break shades the break as well
+        let select = EditorCommand::SelectAllMatches("a;\nlet".into());
+        editor.command(&mut buffer, select).unwrap();
+        let buf = render(&buffer, &editor, area);
+        let shaded: Vec<_> = (0..2)
+            .flat_map(|y| (0..12).map(move |x| (x, y)))
+            .filter(|&(x, y)| buf.get(x, y).bg == selected.0)
+            .collect();
+        assert_eq!(shaded, vec![(8, 0), (9, 0), (10, 0), (0, 1), (1, 1), (2, 1)]);
+
+        // the primary cursor is left to the terminal; the others are drawn
+        editor.set_cursor(tore::Point { line: 0, column: 4 });
+        editor
+            .command(&mut buffer, EditorCommand::AddCursorBelow)
+            .unwrap();
+        let mut buf = tui::Buffer::empty(area);
+        let (cursor, _) = EditorPane::new(&theme, &editor.snapshot(&buffer)).render(&mut buf, area);
+        assert_eq!(cursor, CursorPoint { x: 4, y: 1 });
+        assert!(buf.get(4, 0).modifier.contains(tui::Modifier::REVERSED));
+        assert!(!buf.get(4, 1).modifier.contains(tui::Modifier::REVERSED));
+    }
+
+    #[test]
+    fn search_matches() {
+        use editor::EditorCommand;
+
+        let mut buffer = Buffer::new(Default::default(), "let a = a;\nlet b = a;".into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let area = tui::Rect::new(0, 0, 12, 3);
+        let matched = Theme::default().palette("bg_visual_yellow").unwrap();
+        let shaded = |buf: &tui::Buffer| -> Vec<_> {
+            (0..2)
+                .flat_map(|y| (0..12).map(move |x| (x, y)))
+                .filter(|&(x, y)| buf.get(x, y).bg == matched.0)
+                .collect()
+        };
+
+        let search = EditorCommand::Search("a;".into());
+        editor.command(&mut buffer, search).unwrap();
+        let buf = render(&buffer, &editor, area);
+        assert_eq!(shaded(&buf), vec![(8, 0), (9, 0), (8, 1), (9, 1)]);
+
+        // an edit leaves them unshaded until they're found again
+        let insert = EditorCommand::InsertChar('x');
+        editor.command(&mut buffer, insert).unwrap();
+        assert_eq!(shaded(&render(&buffer, &editor, area)), vec![]);
+        editor.refresh_search(&buffer);
+        assert_eq!(shaded(&render(&buffer, &editor, area)).len(), 4);
+
+        editor
+            .command(&mut buffer, EditorCommand::ClearSearch)
+            .unwrap();
+        assert_eq!(shaded(&render(&buffer, &editor, area)), vec![]);
+    }
+
+    #[test]
+    fn visual_line_shades_whole_lines() {
+        use editor::EditorCommand;
+
+        let (text, cursor) = test_support::buffer_with_cursor("ab\ncde|f\ng");
+        let mut buffer = Buffer::new(Default::default(), text.as_str().into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let area = tui::Rect::new(0, 0, 6, 3);
+        let selected = Theme::default().palette("bg_visual_blue").unwrap();
+        let shaded = |buf: &tui::Buffer, y| (0..6).all(|x| buf.get(x, y).bg == selected.0);
+
+        editor.set_cursor(cursor);
+        editor
+            .command(&mut buffer, EditorCommand::SelectLine)
+            .unwrap();
+        editor
+            .command(&mut buffer, EditorCommand::CursorMove(editor::Direction::Up))
+            .unwrap();
+        let buf = render(&buffer, &editor, area);
+        assert!(shaded(&buf, 0) && shaded(&buf, 1));
+        assert!(!shaded(&buf, 2));
+    }
+
+    #[test]
+    fn cursor_beyond_u16_lines() {
+        use std::fmt::Write;
+
+        let mut contents = String::new();
+        for i in 0..100_000 {
+            writeln!(contents, "line {}", i).unwrap();
+        }
+        let buffer = Buffer::new(Default::default(), contents.as_str().into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(tore::Point { line: 70_000, column: 3 });
+
+        let theme = Theme::default();
+        let area = tui::Rect::new(2, 1, 20, 24);
+        let snapshot = editor.snapshot(&buffer);
+        let pane = EditorPane::new(&theme, &snapshot);
+        assert_eq!(pane.screen_offset(area), editor::Point { line: 69_977, column: 0 });
+        assert_eq!(pane.screen_cursor_position(area, editor.cursor()), CursorPoint { x: 5, y: 24 });
+
+        let buf = render(&buffer, &editor, area);
+        let rows = rows(&buf);
+        assert_eq!(rows[0].trim_end(), "line 69977");
+        assert_eq!(rows[23].trim_end(), "line 70000");
+
+        // a column past u16::MAX scrolls horizontally instead of wrapping
+        editor.set_cursor(tore::Point { line: 70_000, column: 80_000 });
+        let snapshot = editor.snapshot(&buffer);
+        let pane = EditorPane::new(&theme, &snapshot);
+        assert_eq!(pane.screen_offset(area), editor::Point { line: 69_977, column: 79_981 });
+        assert_eq!(
+            pane.screen_cursor_position(area, editor.cursor()),
+            CursorPoint { x: 21, y: 24 }
+        );
+        render(&buffer, &editor, area);
+
+        assert_eq!(CursorPoint::new(70_000, 1), CursorPoint { x: u16::MAX, y: 1 });
+    }
+
+    #[test]
+    fn scrollbar() {
+        let theme = Theme::default();
+        let thumb = theme.palette("grey0").unwrap();
+        let contents = ["0123456789abcdef"; 1000].join("\n");
+        let buffer = Buffer::new(Default::default(), contents.as_str().into());
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        let area = tui::Rect::new(0, 0, 12, 20);
+        let thumb_rows = |buf: &tui::Buffer| -> Vec<u16> {
+            (0..20).filter(|&y| buf.get(11, y).bg == thumb.0).collect()
+        };
+
+        for (line, expected) in [(0, vec![0]), (519, vec![10]), (999, vec![19])] {
+            editor.set_cursor(tore::Point { line, column: 0 });
+            let buf = render(&buffer, &editor, area);
+            assert_eq!(thumb_rows(&buf), expected, "cursor on line {}", line);
+            // the text gives up the last column
+            assert_eq!(rows(&buf)[19], "0123456789a ");
+        }
+
+        // a buffer that fits has no scrollbar and keeps every column
+        let buffer = Buffer::new(Default::default(), ["line"; 5].join("\n").as_str().into());
+        let editor = Editor::new(Default::default(), buffer.id);
+        let buf = render(&buffer, &editor, area);
+        assert!(thumb_rows(&buf).is_empty());
+        let bg = theme.palette("bg0").unwrap();
+        assert!((0..20).all(|y| buf.get(11, y).bg == bg.0));
+    }
+
+    #[test]
+    fn gutter_marks_changes() {
+        let theme = Theme::default();
+        let color = |name| theme.palette(name).unwrap().0;
+        let saved = "a\nb\nc\nd\n";
+        let path = std::path::PathBuf::from("a.txt");
+        let mut buffer = Buffer::open(Default::default(), path, saved.into(), Default::default());
+        buffer
+            .apply_edits(&[(0..0, "new\n"), (2..3, "B"), (6..8, "")])
+            .unwrap();
+        assert_eq!(buffer.contents.to_string(), "new\na\nB\nc\n");
+        let changes = editor::line_diff(buffer.saved().unwrap(), &buffer.contents);
+        buffer.set_line_diff(buffer.version, changes);
+
+        let editor = Editor::new(Default::default(), buffer.id);
+        let area = tui::Rect::new(0, 0, 8, 6);
+        let gutter = |buffer: &Buffer| {
+            let buf = render(buffer, &editor, area);
+            let marks = (0..6).map(|y| (buf.get(0, y).symbol.clone(), buf.get(0, y).fg));
+            (marks.collect::<Vec<_>>(), rows(&buf))
+        };
+        let (marks, rows) = gutter(&buffer);
+        let none = (" ".to_string(), tui::Color::Reset);
+        assert_eq!(
+            marks,
+            vec![
+                ("▎".to_string(), color("bg_green")),
+                none.clone(),
+                ("▎".to_string(), color("bg_yellow")),
+                ("▁".to_string(), color("bg_red")),
+                none.clone(),
+                none.clone(),
+            ]
+        );
+        assert_eq!(rows[0], "▎new    ");
+
+        // an edit moves the markers along until the diff is made again
+        buffer.apply_edits(&[(4..4, "\n")]).unwrap();
+        let (marks, _) = gutter(&buffer);
+        let marked: Vec<_> = marks.iter().map(|(symbol, _)| symbol.as_str()).collect();
+        assert_eq!(marked, ["▎", " ", " ", "▎", "▁", " "]);
+
+        // a buffer that has never been saved has no gutter
+        let buffer = Buffer::new(Default::default(), saved.into());
+        assert_eq!(gutter(&buffer).1[0], "a       ");
+    }
+}
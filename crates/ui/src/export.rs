@@ -0,0 +1,332 @@
+use std::io::{self, Write};
+use std::ops::Range;
+
+use editor::Buffer;
+use ratatui::prelude as tui;
+use rope::compat::RopeSlice;
+
+use crate::editor_pane::HighlightSweep;
+use crate::Theme;
+
+/// Where an export writes the text it's given, a run drawn alike at a time.
+pub trait ExportSink {
+    /// Writes `text`, which never has a line break, drawn with `style`.
+    fn text(&mut self, text: &str, style: tui::Style) -> io::Result<()>;
+
+    /// Ends the line written so far.
+    fn line_break(&mut self) -> io::Result<()>;
+
+    /// Writes whatever follows the last line.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes `lines` of the buffer to `sink` as the theme draws them: each line's highlights are
+/// looked up once and swept across it, as they are on screen, so only a line is ever read at a
+/// time. With `numbers`, each line starts with its number, as the gutter has it. Lines past the
+/// end are left out.
+pub fn export(
+    buffer: &Buffer,
+    theme: &Theme,
+    lines: Range<usize>,
+    numbers: bool,
+    sink: &mut impl ExportSink,
+) -> io::Result<()> {
+    // a line break ends the last line rather than starting one to export
+    let contents = &buffer.contents;
+    let len_lines = contents.len_lines() - usize::from(contents.ends_with_line_break());
+    let lines = lines.start..lines.end.min(len_lines);
+    let width = lines.end.to_string().len();
+    let linenr = theme.style("ui.linenr");
+    let style = |shown: Option<&str>| shown.map_or_else(tui::Style::default, |s| theme.style(s));
+    let mut run = String::new();
+    for n in lines {
+        if numbers {
+            sink.text(&format!("{:>width$} ", n + 1, width = width), linenr)?;
+        }
+        let line = buffer.contents.line(n);
+        let text = line.slice(..line.len_chars() - line_break_len(line));
+        let start = buffer.contents.line_to_byte(n);
+        let highlights = buffer.highlights_in(start..start + text.len_bytes());
+        let mut sweep = HighlightSweep::new(&highlights);
+        let mut shown = None;
+        let mut offset = start;
+        for chunk in text.chunks() {
+            for c in chunk.chars() {
+                let name = sweep.shown(offset..offset + c.len_utf8());
+                if name != shown && !run.is_empty() {
+                    sink.text(&run, style(shown))?;
+                    run.clear();
+                }
+                shown = name;
+                run.push(c);
+                offset += c.len_utf8();
+            }
+        }
+        if !run.is_empty() {
+            sink.text(&run, style(shown))?;
+            run.clear();
+        }
+        sink.line_break()?;
+    }
+    sink.finish()
+}
+
+/// How many chars of line break `line` ends with.
+fn line_break_len(line: RopeSlice<'_>) -> usize {
+    let mut chars = line.chars_at(line.len_chars()).reversed();
+    match (chars.next(), chars.next()) {
+        (Some('\n'), Some('\r')) => 2,
+        (Some('\n'), _) => 1,
+        _ => 0,
+    }
+}
+
+/// The red, green and blue of a color, if it has them; a theme's colors always do.
+fn rgb(color: Option<tui::Color>) -> Option<(u8, u8, u8)> {
+    match color? {
+        tui::Color::Rgb(r, g, b) => Some((r, g, b)),
+        _ => None,
+    }
+}
+
+/// Writes an HTML page with the text in a `<pre>`, each run in a `<span>` styled inline, so the
+/// page needs nothing else to look as it does in the editor. Long lines wrap at words, keeping
+/// their whitespace.
+pub struct HtmlSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> HtmlSink<W> {
+    /// Starts the page, titled `title`, in the theme's colors, with tabs `tab_width` wide.
+    pub fn new(mut out: W, theme: &Theme, title: &str, tab_width: usize) -> io::Result<Self> {
+        let background = theme.style("ui.background");
+        let fg = theme.palette("fg0").map(|color| color.0);
+        let page = css(tui::Style { fg: background.fg.or(fg), ..background });
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html>")?;
+        writeln!(out, "<head>")?;
+        writeln!(out, "<meta charset=\"utf-8\">")?;
+        writeln!(out, "<title>{}</title>", escape(title))?;
+        writeln!(out, "</head>")?;
+        writeln!(out, "<body style=\"margin:0;{}\">", page)?;
+        write!(
+            out,
+            "<pre style=\"margin:0;padding:1em;white-space:pre-wrap;tab-size:{}\">",
+            tab_width
+        )?;
+        Ok(Self { out })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> ExportSink for HtmlSink<W> {
+    fn text(&mut self, text: &str, style: tui::Style) -> io::Result<()> {
+        match css(style) {
+            css if css.is_empty() => write!(self.out, "{}", escape(text)),
+            css => write!(self.out, "<span style=\"{}\">{}</span>", css, escape(text)),
+        }
+    }
+
+    fn line_break(&mut self) -> io::Result<()> {
+        writeln!(self.out)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        writeln!(self.out, "</pre>")?;
+        writeln!(self.out, "</body>")?;
+        writeln!(self.out, "</html>")
+    }
+}
+
+/// The inline CSS for a style's colors and modifiers.
+fn css(style: tui::Style) -> String {
+    let mut css = String::new();
+    let (fg, bg) = if style.add_modifier.contains(tui::Modifier::REVERSED) {
+        (style.bg, style.fg)
+    } else {
+        (style.fg, style.bg)
+    };
+    if let Some((r, g, b)) = rgb(fg) {
+        css += &format!("color:#{:02x}{:02x}{:02x};", r, g, b);
+    }
+    if let Some((r, g, b)) = rgb(bg) {
+        css += &format!("background:#{:02x}{:02x}{:02x};", r, g, b);
+    }
+    let modifiers = [
+        (tui::Modifier::BOLD, "font-weight:bold;"),
+        (tui::Modifier::DIM, "opacity:0.6;"),
+        (tui::Modifier::ITALIC, "font-style:italic;"),
+        (tui::Modifier::UNDERLINED, "text-decoration:underline;"),
+        (tui::Modifier::CROSSED_OUT, "text-decoration:line-through;"),
+    ];
+    for (modifier, property) in modifiers {
+        if style.add_modifier.contains(modifier) {
+            css += property;
+        }
+    }
+    css.pop();
+    css
+}
+
+/// `text` with the chars HTML gives meaning to written as entities.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes the text with ANSI escapes for its colors and modifiers, for a terminal or anything
+/// else that reads them. Every run styled is reset after, so each line stands on its own.
+pub struct AnsiSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> AnsiSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> ExportSink for AnsiSink<W> {
+    fn text(&mut self, text: &str, style: tui::Style) -> io::Result<()> {
+        let mut codes = vec![];
+        let modifiers = [
+            (tui::Modifier::BOLD, "1"),
+            (tui::Modifier::DIM, "2"),
+            (tui::Modifier::ITALIC, "3"),
+            (tui::Modifier::UNDERLINED, "4"),
+            (tui::Modifier::REVERSED, "7"),
+            (tui::Modifier::CROSSED_OUT, "9"),
+        ];
+        for (modifier, code) in modifiers {
+            if style.add_modifier.contains(modifier) {
+                codes.push(code.to_string());
+            }
+        }
+        if let Some((r, g, b)) = rgb(style.fg) {
+            codes.push(format!("38;2;{};{};{}", r, g, b));
+        }
+        if let Some((r, g, b)) = rgb(style.bg) {
+            codes.push(format!("48;2;{};{};{}", r, g, b));
+        }
+        if codes.is_empty() {
+            write!(self.out, "{}", text)
+        } else {
+            write!(self.out, "\x1b[{}m{}\x1b[0m", codes.join(";"), text)
+        }
+    }
+
+    fn line_break(&mut self) -> io::Result<()> {
+        writeln!(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "fn main() {\n    let s = \"a<b && c\";\n}\n";
+
+    fn highlighted() -> Buffer {
+        let mut buffer = Buffer::new(Default::default(), SOURCE.into());
+        let mut highlights = editor::Highlights::new();
+        highlights.insert(0..2, "keyword".to_string());
+        highlights.insert(3..7, "function".to_string());
+        highlights.insert(16..19, "keyword".to_string());
+        highlights.insert(24..34, "string".to_string());
+        // a comment over the string, but starting later, so the string still shows
+        highlights.insert(26..28, "comment".to_string());
+        buffer.command(editor::BufferCommand::Highlight(0, highlights));
+        buffer
+    }
+
+    fn html(buffer: &Buffer, lines: Range<usize>, numbers: bool) -> String {
+        let theme = Theme::default();
+        let mut sink = HtmlSink::new(vec![], &theme, "main.rs", 4).unwrap();
+        export(buffer, &theme, lines, numbers, &mut sink).unwrap();
+        String::from_utf8(sink.into_inner()).unwrap()
+    }
+
+    fn color(scope: &str) -> String {
+        Theme::default().scheme_entry(scope).unwrap().1.to_string()
+    }
+
+    #[test]
+    fn html_spans_in_order_and_escaped() {
+        let buffer = highlighted();
+        let html = html(&buffer, 0..usize::MAX, false);
+        let span = |scope: &str, text: &str| {
+            format!("<span style=\"color:{}\">{}</span>", color(scope), text)
+        };
+        let body = [
+            format!("{} {}() {{", span("keyword", "fn"), span("function", "main")),
+            format!(
+                "    {} s = {};",
+                span("keyword", "let"),
+                span("string", "&quot;a&lt;b &amp;&amp; c&quot;")
+            ),
+            "}".to_string(),
+        ]
+        .join("\n");
+        assert!(html.contains(&format!(";tab-size:4\">{}\n</pre>", body)), "{}", html);
+        assert!(html.contains("white-space:pre-wrap"), "{}", html);
+        assert!(html.contains("<title>main.rs</title>"), "{}", html);
+        assert!(html.ends_with("</pre>\n</body>\n</html>\n"), "{}", html);
+    }
+
+    #[test]
+    fn html_of_some_lines_numbered() {
+        let buffer = highlighted();
+        let html = html(&buffer, 1..2, true);
+        let linenr = format!("<span style=\"color:{}\">2 </span>", color("ui.linenr"));
+        let start = html.find("<pre").unwrap();
+        let pre = &html[start..];
+        assert!(pre.contains(&format!("\">{}    <span", linenr)), "{}", pre);
+        assert!(!pre.contains("main"), "{}", pre);
+        assert!(!pre.contains('}'), "{}", pre);
+    }
+
+    #[test]
+    fn ansi_shares_the_runs() {
+        let buffer = highlighted();
+        let theme = Theme::default();
+        let mut sink = AnsiSink::new(vec![]);
+        export(&buffer, &theme, 0..1, false, &mut sink).unwrap();
+        let ansi = String::from_utf8(sink.into_inner()).unwrap();
+        let sgr = |scope: &str| {
+            let (r, g, b) = rgb(theme.style(scope).fg).unwrap();
+            format!("\x1b[38;2;{};{};{}m", r, g, b)
+        };
+        assert_eq!(
+            ansi,
+            format!("{}fn\x1b[0m {}main\x1b[0m() {{\n", sgr("keyword"), sgr("function"))
+        );
+    }
+
+    #[test]
+    fn line_breaks_left_to_the_sink() {
+        let buffer = Buffer::new(Default::default(), "a\r\nb\nc".into());
+        let theme = Theme::default();
+        let mut sink = AnsiSink::new(vec![]);
+        export(&buffer, &theme, 0..usize::MAX, false, &mut sink).unwrap();
+        let lines = String::from_utf8(sink.into_inner()).unwrap();
+        assert_eq!(lines, "a\nb\nc\n");
+    }
+}
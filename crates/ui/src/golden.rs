@@ -0,0 +1,135 @@
+//! Golden frames: what a widget draws, kept as text under `testdata/frames` so a change to how
+//! something renders shows up as a diff of the frames it touches.
+//!
+//! A frame file starts with where the cursor is and its shape, then has the symbol in each cell,
+//! a row to a line between `|`s. Under `styles` are the same rows with a letter for each cell's
+//! style instead, lettered in the order they first turn up, and a legend of what each letter is.
+
+use crossterm::cursor::SetCursorStyle;
+use ratatui::backend::TestBackend;
+use ratatui::buffer::Cell;
+use ratatui::prelude as tui;
+use std::fmt::Write;
+use std::path::Path;
+use tore::CursorPoint;
+
+use crate::Color;
+
+/// Where frames are kept, relative to the crate.
+const FRAMES_DIR: &str = "testdata/frames";
+/// Set to write the frames drawn over the ones kept, rather than compare them.
+const UPDATE_VAR: &str = "UPDATE_FRAMES";
+
+/// Draws a `width` by `height` frame on a test terminal with `render`, as a frame file has it.
+pub(crate) fn draw(
+    width: u16,
+    height: u16,
+    render: impl FnOnce(&mut tui::Buffer, tui::Rect) -> (CursorPoint, SetCursorStyle),
+) -> String {
+    let mut term = tui::Terminal::new(TestBackend::new(width, height)).unwrap();
+    let mut cursor = None;
+    term.draw(|frame| {
+        let area = frame.size();
+        cursor = Some(render(frame.buffer_mut(), area));
+    })
+    .unwrap();
+    encode(term.backend().buffer(), cursor.unwrap())
+}
+
+/// Checks `frame` against the one kept as `name`, or keeps it when `UPDATE_FRAMES` is set.
+#[track_caller]
+pub(crate) fn assert_frame(name: &str, frame: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join(FRAMES_DIR)
+        .join(format!("{}.txt", name));
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).expect("create frames directory");
+        std::fs::write(&path, frame).expect("write frame");
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        panic!("{}: {}; set {}=1 to keep what's drawn", path.display(), err, UPDATE_VAR)
+    });
+    assert!(
+        frame == expected,
+        "frame {} changed; set {}=1 to keep it\n--- kept\n{}--- drawn\n{}",
+        name,
+        UPDATE_VAR,
+        expected,
+        frame
+    );
+}
+
+fn encode(buf: &tui::Buffer, (cursor, shape): (CursorPoint, SetCursorStyle)) -> String {
+    let area = buf.area;
+    let cells = |y| (area.left()..area.right()).map(move |x| buf.get(x, y));
+    let mut frame = String::new();
+    writeln!(frame, "cursor {},{} {}", cursor.x, cursor.y, shape_name(shape)).unwrap();
+    for y in area.top()..area.bottom() {
+        let row: String = cells(y).map(|cell| cell.symbol.as_str()).collect();
+        writeln!(frame, "|{}|", row).unwrap();
+    }
+
+    frame.push_str("styles\n");
+    let mut styles: Vec<String> = vec![];
+    for y in area.top()..area.bottom() {
+        let mut row = String::new();
+        for cell in cells(y) {
+            let style = describe(cell);
+            let i = match styles.iter().position(|s| *s == style) {
+                Some(i) => i,
+                None => {
+                    styles.push(style);
+                    styles.len() - 1
+                }
+            };
+            row.push(letter(i));
+        }
+        writeln!(frame, "|{}|", row).unwrap();
+    }
+    for (i, style) in styles.iter().enumerate() {
+        writeln!(frame, "{} {}", letter(i), style).unwrap();
+    }
+    frame
+}
+
+fn letter(i: usize) -> char {
+    let letters = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    char::from(*letters.get(i).expect("too many styles in one frame"))
+}
+
+/// A cell's colors and modifiers, leaving out whatever's the terminal's default.
+fn describe(cell: &Cell) -> String {
+    let mut parts = vec![];
+    if cell.fg != tui::Color::Reset {
+        parts.push(format!("fg={}", Color(cell.fg)));
+    }
+    if cell.bg != tui::Color::Reset {
+        parts.push(format!("bg={}", Color(cell.bg)));
+    }
+    let modifiers = [
+        (tui::Modifier::BOLD, "bold"),
+        (tui::Modifier::DIM, "dim"),
+        (tui::Modifier::ITALIC, "italic"),
+        (tui::Modifier::UNDERLINED, "underlined"),
+        (tui::Modifier::REVERSED, "reversed"),
+    ];
+    for (modifier, name) in modifiers {
+        if cell.modifier.contains(modifier) {
+            parts.push(name.to_string());
+        }
+    }
+    if parts.is_empty() {
+        return "plain".to_string();
+    }
+    parts.join(" ")
+}
+
+fn shape_name(shape: SetCursorStyle) -> &'static str {
+    match shape {
+        SetCursorStyle::BlinkingBlock | SetCursorStyle::SteadyBlock => "block",
+        SetCursorStyle::BlinkingBar | SetCursorStyle::SteadyBar => "bar",
+        SetCursorStyle::BlinkingUnderScore | SetCursorStyle::SteadyUnderScore => "underscore",
+        SetCursorStyle::DefaultUserShape => "default",
+    }
+}
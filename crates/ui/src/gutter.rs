@@ -0,0 +1,299 @@
+use std::ops::Range;
+
+use editor::{BufferSnapshot, LineChangeKind, Severity};
+use ratatui::prelude as tui;
+
+use crate::Theme;
+
+/// A column of the gutter down the left of an editor pane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GutterColumn {
+    /// One mark per line, from whichever provider claims it.
+    Signs,
+    /// Line numbers, wide enough for the last line.
+    Numbers,
+    /// Reserved for fold markers; blank until there's folding.
+    Folds,
+}
+
+/// The width of each column of a gutter for some buffer, left to right.
+pub type GutterWidths = Vec<(GutterColumn, u16)>;
+
+/// A mark in the sign column. Where providers mark the same line, the highest priority wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sign {
+    pub symbol: char,
+    /// The palette color it's drawn in.
+    pub color: &'static str,
+    pub priority: u8,
+}
+
+/// Something the sign column reads marks from as it's drawn, like a buffer's line diff.
+pub trait SignProvider: Sync {
+    /// Whether there's anything to mark in `buffer`; the sign column only takes space when some
+    /// provider has.
+    fn active(&self, buffer: &BufferSnapshot) -> bool;
+
+    /// Calls `mark` with each of the signs on `lines` of `buffer`, and the line it's on.
+    fn signs(
+        &self,
+        buffer: &BufferSnapshot,
+        lines: Range<usize>,
+        mark: &mut dyn FnMut(usize, Sign),
+    );
+}
+
+/// Marks the lines that changed since the buffer was saved: a bar beside added and modified
+/// lines, and a line under the one lines were removed after, or over the first line.
+pub struct DiffSigns;
+
+impl SignProvider for DiffSigns {
+    fn active(&self, buffer: &BufferSnapshot) -> bool {
+        buffer.has_saved()
+    }
+
+    fn signs(
+        &self,
+        buffer: &BufferSnapshot,
+        lines: Range<usize>,
+        mark: &mut dyn FnMut(usize, Sign),
+    ) {
+        for change in buffer.line_diff() {
+            let (symbol, color, marked) = match change.kind {
+                LineChangeKind::Added => ('▎', "bg_green", change.lines.clone()),
+                LineChangeKind::Modified => ('▎', "bg_yellow", change.lines.clone()),
+                LineChangeKind::Removed => match change.lines.start {
+                    0 => ('▔', "bg_red", 0..1),
+                    at => ('▁', "bg_red", at - 1..at),
+                },
+            };
+            let sign = Sign { symbol, color, priority: 0 };
+            for line in marked.start.max(lines.start)..marked.end.min(lines.end) {
+                mark(line, sign);
+            }
+        }
+    }
+}
+
+/// Marks the first line of each diagnostic, by how serious it is. Any diagnostic outranks a
+/// diff marker.
+pub struct DiagnosticSigns;
+
+impl SignProvider for DiagnosticSigns {
+    fn active(&self, buffer: &BufferSnapshot) -> bool {
+        !buffer.diagnostics().is_empty()
+    }
+
+    fn signs(
+        &self,
+        buffer: &BufferSnapshot,
+        lines: Range<usize>,
+        mark: &mut dyn FnMut(usize, Sign),
+    ) {
+        for diagnostic in buffer.diagnostics() {
+            let line = diagnostic.lines.start;
+            if !lines.contains(&line) {
+                continue;
+            }
+            let (symbol, color) = match diagnostic.severity {
+                Severity::Error => ('E', "red"),
+                Severity::Warning => ('W', "yellow"),
+                Severity::Info => ('I', "blue"),
+                Severity::Hint => ('H', "aqua"),
+            };
+            let priority = 1 + diagnostic.severity as u8;
+            mark(line, Sign { symbol, color, priority });
+        }
+    }
+}
+
+/// The columns down the left of an editor pane, and where the sign column gets its marks.
+#[derive(Clone, Copy)]
+pub struct Gutter<'a> {
+    columns: &'a [GutterColumn],
+    providers: &'a [&'a dyn SignProvider],
+}
+
+impl Gutter<'static> {
+    pub const PROVIDERS: &'static [&'static dyn SignProvider] = &[&DiagnosticSigns, &DiffSigns];
+
+    /// Just the sign column, marked by the built-in providers.
+    pub const DEFAULT: Self = Self::new(&[GutterColumn::Signs], Self::PROVIDERS);
+}
+
+impl Default for Gutter<'static> {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl<'a> Gutter<'a> {
+    pub const fn new(columns: &'a [GutterColumn], providers: &'a [&'a dyn SignProvider]) -> Self {
+        Self { columns, providers }
+    }
+
+    /// How wide each column is for `buffer`, left to right; zero for those with nothing to show.
+    pub fn widths(&self, buffer: &BufferSnapshot) -> GutterWidths {
+        let signs = self
+            .providers
+            .iter()
+            .any(|provider| provider.active(buffer));
+        let digits = buffer.contents.len_lines().max(1).ilog10() as u16 + 1;
+        let width = |column| match column {
+            GutterColumn::Signs => u16::from(signs),
+            GutterColumn::Numbers => digits + 1,
+            GutterColumn::Folds => 1,
+        };
+        self.columns
+            .iter()
+            .map(|&column| (column, width(column)))
+            .collect()
+    }
+
+    /// Draws the columns, at `widths` from `Gutter::widths`, for the lines from `first_line`.
+    pub(crate) fn render(
+        &self,
+        buf: &mut tui::Buffer,
+        area: tui::Rect,
+        widths: &[(GutterColumn, u16)],
+        buffer: &BufferSnapshot,
+        first_line: usize,
+        theme: &Theme,
+    ) {
+        buf.set_style(area, tui::Style::reset().patch(theme.style("ui.gutter")));
+        let lines = first_line..first_line + usize::from(area.height);
+        let row = |line: usize| area.top() + (line - first_line) as u16;
+        let mut x = area.left();
+        for &(column, width) in widths {
+            let column_area = tui::Rect { x, width, ..area }.intersection(area);
+            x += width;
+            for y in column_area.top()..column_area.bottom() {
+                for x in column_area.left()..column_area.right() {
+                    buf.get_mut(x, y).set_char(' ');
+                }
+            }
+            if column_area.is_empty() {
+                continue;
+            }
+            match column {
+                GutterColumn::Signs => {
+                    let mut claimed: Vec<Option<Sign>> = vec![None; lines.len()];
+                    for provider in self.providers {
+                        provider.signs(buffer, lines.clone(), &mut |line, sign| {
+                            let slot = &mut claimed[line - lines.start];
+                            if slot.map_or(true, |held| sign.priority > held.priority) {
+                                *slot = Some(sign);
+                            }
+                        });
+                    }
+                    for (line, sign) in lines.clone().zip(claimed) {
+                        let Some(sign) = sign else { continue };
+                        let color = theme.palette(sign.color).unwrap();
+                        buf.get_mut(column_area.left(), row(line))
+                            .set_char(sign.symbol)
+                            .set_fg(color.0);
+                    }
+                }
+                GutterColumn::Numbers => {
+                    let style = theme.style("ui.linenr");
+                    let end = lines.end.min(buffer.contents.len_lines());
+                    let digits = usize::from(column_area.width.saturating_sub(1));
+                    for line in lines.start..end {
+                        let number = format!("{:>digits$}", line + 1);
+                        buf.set_stringn(
+                            column_area.left(),
+                            row(line),
+                            number,
+                            usize::from(column_area.width),
+                            style,
+                        );
+                    }
+                }
+                GutterColumn::Folds => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::{Buffer, Diagnostic};
+
+    fn cells(gutter: &Gutter, buffer: &Buffer, height: u16) -> Vec<String> {
+        let theme = Theme::default();
+        let buffer = buffer.snapshot();
+        let widths = gutter.widths(&buffer);
+        let width = widths.iter().map(|(_, width)| width).sum();
+        let area = tui::Rect::new(0, 0, width, height);
+        let mut buf = tui::Buffer::empty(area);
+        gutter.render(&mut buf, area, &widths, &buffer, 0, &theme);
+        let row = |y| (0..width).map(|x| buf.get(x, y).symbol.as_str()).collect();
+        (0..height).map(row).collect()
+    }
+
+    #[test]
+    fn signs_by_priority() {
+        let theme = Theme::default();
+        let path = std::path::PathBuf::from("a.txt");
+        let mut buffer =
+            Buffer::open(Default::default(), path, "a\nb\nc\n".into(), Default::default());
+        buffer.apply_edits(&[(2..3, "B"), (4..5, "C")]).unwrap();
+        let changes = editor::line_diff(buffer.saved().unwrap(), &buffer.contents);
+        buffer.set_line_diff(buffer.version, changes);
+        let diagnostic = |line: usize, severity| Diagnostic {
+            lines: line..line + 1,
+            severity,
+            message: String::new(),
+        };
+        let diagnostics = vec![
+            diagnostic(1, Severity::Warning),
+            diagnostic(1, Severity::Error),
+            diagnostic(3, Severity::Hint),
+        ];
+        buffer.set_diagnostics(buffer.version, diagnostics);
+
+        let columns = [GutterColumn::Signs, GutterColumn::Numbers];
+        let gutter = Gutter::new(&columns, Gutter::PROVIDERS);
+        let widths = gutter.widths(&buffer.snapshot());
+        assert_eq!(widths, [(GutterColumn::Signs, 1), (GutterColumn::Numbers, 2)]);
+        // the error outranks both the warning and the diff marker on its line; the diff still
+        // marks the next, and the last line has a number but no sign
+        assert_eq!(cells(&gutter, &buffer, 5), [" 1 ", "E2 ", "▎3 ", "H4 ", "   "]);
+
+        let area = tui::Rect::new(0, 0, 3, 3);
+        let mut buf = tui::Buffer::empty(area);
+        gutter.render(&mut buf, area, &widths, &buffer.snapshot(), 0, &theme);
+        assert_eq!(buf.get(0, 1).fg, theme.palette("red").unwrap().0);
+        assert_eq!(buf.get(0, 2).fg, theme.palette("bg_yellow").unwrap().0);
+    }
+
+    #[test]
+    fn width_follows_providers() {
+        let mut buffer = Buffer::new(Default::default(), "a\n".repeat(8).as_str().into());
+        let columns = [GutterColumn::Numbers, GutterColumn::Signs];
+        let gutter = Gutter::new(&columns, Gutter::PROVIDERS);
+        let width = |buffer: &Buffer| -> Vec<u16> {
+            gutter
+                .widths(&buffer.snapshot())
+                .into_iter()
+                .map(|(_, width)| width)
+                .collect()
+        };
+        // never saved, so no diff, and nothing diagnosed: no sign column
+        assert_eq!(width(&buffer), [2, 0]);
+
+        let diagnostic = Diagnostic { lines: 0..1, severity: Severity::Info, message: "".into() };
+        buffer.set_diagnostics(buffer.version, vec![diagnostic]);
+        assert_eq!(width(&buffer), [2, 1]);
+        assert_eq!(cells(&gutter, &buffer, 1), ["1 I"]);
+
+        // the numbers widen with the tenth line
+        buffer.apply_edits(&[(16..16, "a\n")]).unwrap();
+        assert_eq!(width(&buffer), [3, 1]);
+        assert_eq!(cells(&gutter, &buffer, 2), [" 1 I", " 2  "]);
+
+        buffer.set_diagnostics(buffer.version, vec![]);
+        assert_eq!(width(&buffer), [3, 0]);
+    }
+}
@@ -0,0 +1,164 @@
+use crossterm::cursor::SetCursorStyle;
+use editor::{BufferSnapshot, EditorSnapshot, HEX_ROW_BYTES};
+use ratatui::prelude as tui;
+use tore::CursorPoint;
+
+use crate::Theme;
+
+/// A binary buffer drawn like `hexdump -C`: a row for every sixteen bytes, with the offset of the
+/// first, the bytes in hex, and the bytes as ASCII. The cursor is on the hex digits of its byte,
+/// and the byte is marked in the ASCII too.
+pub(crate) struct HexPane<'a> {
+    theme: &'a Theme,
+    buffer: &'a BufferSnapshot,
+    editor: &'a EditorSnapshot,
+}
+
+/// How many columns the offset at the start of a row takes.
+const OFFSET_WIDTH: u16 = 8;
+
+impl<'a> HexPane<'a> {
+    pub(crate) fn new(theme: &'a Theme, editor: &'a EditorSnapshot) -> Self {
+        Self { theme, buffer: &editor.buffer, editor }
+    }
+
+    /// The byte the cursor is on, as far as the last one.
+    fn cursor(&self) -> usize {
+        let contents = &self.buffer.contents;
+        let cursor = self.editor.cursor();
+        let line = cursor.line.min(contents.len_lines() - 1);
+        let offset = contents.line_to_char(line) + cursor.column;
+        offset.min(contents.len_chars().saturating_sub(1))
+    }
+
+    /// The bytes of `row`.
+    fn bytes(&self, row: usize) -> Vec<u8> {
+        let contents = &self.buffer.contents;
+        let start = (row * HEX_ROW_BYTES).min(contents.len_chars());
+        let end = (start + HEX_ROW_BYTES).min(contents.len_chars());
+        let chars = contents.slice(start..end).chars();
+        chars.map(|c| u8::try_from(c).unwrap_or(b'?')).collect()
+    }
+
+    pub(crate) fn render(
+        self,
+        buf: &mut tui::Buffer,
+        dims: tui::Rect,
+    ) -> (CursorPoint, SetCursorStyle) {
+        if dims.is_empty() {
+            let corner = CursorPoint::new(dims.x.into(), dims.y.into());
+            return (corner, SetCursorStyle::BlinkingBlock);
+        }
+        let background = self.theme.style("ui.background");
+        let virtual_text = self.theme.style("ui.virtual");
+        let offsets = self.theme.style("ui.linenr");
+        let cursor = self.cursor();
+        let rows = editor::hex_rows(self.buffer.contents.len_chars());
+        let height = usize::from(dims.height);
+        let cursor_row = cursor / HEX_ROW_BYTES;
+        let top = if cursor_row >= height {
+            cursor_row + 1 - height
+        } else {
+            0
+        };
+
+        for (yoffset, y) in (dims.top()..dims.bottom()).enumerate() {
+            // the buffer is reused between frames; clear whatever the last one left in this row.
+            let area = tui::Rect::new(dims.x, y, dims.width, 1);
+            buf.set_style(area, tui::Style::reset().patch(background));
+            for x in area.left()..area.right() {
+                buf.get_mut(x, y).set_char(' ');
+            }
+            let row = top + yoffset;
+            if row >= rows {
+                buf.get_mut(dims.x, y).set_char('~').set_style(virtual_text);
+                continue;
+            }
+            let text = editor::hex_row(&self.bytes(row), row * HEX_ROW_BYTES);
+            buf.set_stringn(dims.x, y, text, dims.width.into(), tui::Style::default());
+            let offset = tui::Rect { width: OFFSET_WIDTH.min(dims.width), ..area };
+            buf.set_style(offset, offsets);
+        }
+        self.render_search_matches(buf, dims, top);
+
+        // the cursor's row is always in view
+        let y = dims.top() + u16::try_from(cursor_row - top).unwrap_or_default();
+        let ascii = editor::ascii_column(cursor);
+        if let Some(x) = u16::try_from(ascii).ok().filter(|x| *x < dims.width) {
+            let selected = self.theme.style("ui.selection");
+            buf.get_mut(dims.x + x, y).set_style(selected);
+        }
+        let x = usize::from(dims.left()) + editor::hex_column(cursor);
+        (CursorPoint::new(x, y.into()), SetCursorStyle::BlinkingBlock)
+    }
+
+    /// Shades both the hex digits and the ASCII of the bytes the editor's search matched, on the
+    /// rows from `top`.
+    fn render_search_matches(&self, buf: &mut tui::Buffer, area: tui::Rect, top: usize) {
+        let Some(search) = self.editor.search() else {
+            return;
+        };
+        if search.version != self.buffer.version {
+            return;
+        }
+        let matched = self.theme.style("ui.search");
+        let start = top * HEX_ROW_BYTES;
+        let end = start + usize::from(area.height) * HEX_ROW_BYTES;
+        let matches = search.matches();
+        let first = matches.partition_point(|m| m.end <= start);
+        for m in matches[first..].iter().take_while(|m| m.start < end) {
+            for byte in m.start.max(start)..m.end.min(end) {
+                let y = area.top() + ((byte - start) / HEX_ROW_BYTES) as u16;
+                let hex = editor::hex_column(byte);
+                for x in [hex, hex + 1, editor::ascii_column(byte)] {
+                    if let Ok(x) = u16::try_from(x) {
+                        if x < area.width {
+                            buf.get_mut(area.x + x, y).set_style(matched);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::{Buffer, Editor};
+
+    fn rows(buf: &tui::Buffer, area: tui::Rect) -> Vec<String> {
+        let row = |y| -> String {
+            let symbols = (area.left()..area.right()).map(|x| buf.get(x, y).symbol.as_str());
+            symbols.collect::<String>().trim_end().to_string()
+        };
+        (area.top()..area.bottom()).map(row).collect()
+    }
+
+    #[test]
+    fn scrolls_to_the_cursor_byte() {
+        let text: String = (0..40u8).map(char::from).collect();
+        let contents = editor::BufferContents::from(text.as_str());
+        let format = editor::Format { encoding: editor::Encoding::Binary, bom: false };
+        let buffer = Buffer::open(Default::default(), "a.bin".into(), contents, format);
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        // byte 0x21 is on the last row, which is short
+        let line = buffer.contents.char_to_line(0x21);
+        let column = 0x21 - buffer.contents.line_to_char(line);
+        editor.set_cursor(tore::Point { line, column });
+
+        let theme = Theme::default();
+        let area = tui::Rect::new(0, 0, 80, 2);
+        let mut buf = tui::Buffer::empty(area);
+        let snapshot = editor.snapshot(&buffer);
+        let (cursor, _) = HexPane::new(&theme, &snapshot).render(&mut buf, area);
+        let expected = [
+            "00000010  10 11 12 13 14 15 16 17  18 19 1a 1b 1c 1d 1e 1f  |................|",
+            "00000020  20 21 22 23 24 25 26 27                           | !\"#$%&'|",
+        ];
+        assert_eq!(rows(&buf, area), expected);
+        assert_eq!(cursor, CursorPoint { x: 13, y: 1 });
+        assert_eq!(buf.get(62, 1).symbol, "!");
+        assert_eq!(buf.get(62, 1).bg, theme.style("ui.selection").bg.unwrap());
+    }
+}
@@ -1,7 +1,26 @@
+mod cell_cursor;
 mod editor_pane;
+mod export;
+#[cfg(test)]
+mod golden;
+mod gutter;
+mod hex_pane;
+mod list_chars;
+mod overlay;
+mod scrollbar;
 mod selector_pane;
+mod status_line;
 mod theme;
 
-pub use editor_pane::EditorPane;
+pub use cell_cursor::draw_cell_cursor;
+pub use editor_pane::{scroll_top, EditorPane};
+pub use export::{export, AnsiSink, ExportSink, HtmlSink};
+pub use gutter::{
+    DiagnosticSigns, DiffSigns, Gutter, GutterColumn, GutterWidths, Sign, SignProvider,
+};
+pub use list_chars::ListChars;
+pub use overlay::Overlay;
+pub use scrollbar::Scrollbar;
 pub use selector_pane::SelectorPane;
-pub use theme::Theme;
+pub use status_line::StatusLine;
+pub use theme::{Color, ColorRef, StyleSpec, Theme};
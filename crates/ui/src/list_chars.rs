@@ -0,0 +1,75 @@
+/// What whitespace is drawn as with `:set list`, like vim's `listchars`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListChars {
+    /// A tab's glyph, and what fills the rest of it out to its tab stop. A tab takes the one
+    /// cell in a pane, as every char does, so only the glyph shows.
+    pub tab: (char, char),
+    /// Spaces after the last char of a line that isn't whitespace.
+    pub trail: char,
+    /// No-break spaces, wherever they are.
+    pub nbsp: char,
+    /// Drawn just past the end of each line that has a line break; none by default.
+    pub eol: Option<char>,
+}
+
+impl Default for ListChars {
+    fn default() -> Self {
+        Self { tab: ('→', '·'), trail: '·', nbsp: '␣', eol: None }
+    }
+}
+
+impl ListChars {
+    /// Sets the chars named in `value`, written like vim's: `tab:→·,trail:·,nbsp:␣,eol:¬`. Those
+    /// it doesn't name are left as they are, and `eol:` with nothing after it draws no end of
+    /// line. Fails with what's wrong if any of it isn't understood, having changed nothing.
+    pub fn set(&mut self, value: &str) -> Result<(), String> {
+        let mut chars = self.clone();
+        for entry in value.split(',').filter(|entry| !entry.is_empty()) {
+            let bad = || format!("bad listchars entry '{}'", entry);
+            let (key, glyphs) = entry.split_once(':').ok_or_else(bad)?;
+            let glyphs: Vec<char> = glyphs.chars().collect();
+            match (key, glyphs.as_slice()) {
+                ("tab", &[glyph, fill]) => chars.tab = (glyph, fill),
+                ("trail", &[glyph]) => chars.trail = glyph,
+                ("nbsp", &[glyph]) => chars.nbsp = glyph,
+                ("eol", &[glyph]) => chars.eol = Some(glyph),
+                ("eol", &[]) => chars.eol = None,
+                ("tab" | "trail" | "nbsp" | "eol", _) => return Err(bad()),
+                (key, _) => return Err(format!("unknown listchars key '{}'", key)),
+            }
+        }
+        *self = chars;
+        Ok(())
+    }
+
+    /// What `grapheme` is drawn as, and the theme scope it's drawn with, if it's whitespace
+    /// that's shown; `trailing` is whether nothing but whitespace follows it on its line.
+    pub(crate) fn glyph(&self, grapheme: &str, trailing: bool) -> Option<(char, &'static str)> {
+        match grapheme {
+            "\t" => Some((self.tab.0, "ui.whitespace")),
+            " " if trailing => Some((self.trail, "ui.whitespace.trail")),
+            "\u{a0}" | "\u{202f}" => Some((self.nbsp, "ui.whitespace.nbsp")),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_like_vim() {
+        let mut chars = ListChars::default();
+        chars.set("tab:>-,eol:$").unwrap();
+        assert_eq!(chars, ListChars { tab: ('>', '-'), eol: Some('$'), ..Default::default() });
+        chars.set("eol:,trail:~").unwrap();
+        assert_eq!((chars.eol, chars.trail), (None, '~'));
+
+        let kept = chars.clone();
+        assert_eq!(chars.set("nbsp:+,tab:>"), Err("bad listchars entry 'tab:>'".into()));
+        assert_eq!(chars.set("space:."), Err("unknown listchars key 'space'".into()));
+        assert_eq!(chars.set("trail"), Err("bad listchars entry 'trail'".into()));
+        assert_eq!(chars, kept);
+    }
+}
@@ -0,0 +1,101 @@
+use ratatui::prelude as tui;
+
+/// Sizing for panes drawn over the editors, like the command selector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overlay {
+    /// The widest the pane gets, borders included; it never takes more than 60% of the frame.
+    pub max_width: u16,
+    /// The most result rows shown at once.
+    pub max_rows: u16,
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Self { max_width: 80, max_rows: 13 }
+    }
+}
+
+/// Rows between the bottom of an overlay and the bottom of the frame.
+const BOTTOM_MARGIN: u16 = 1;
+
+/// Where the parts of an overlay pane go: a bordered box holding the query row and, below a
+/// separator row, the results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct OverlayLayout {
+    pub border: tui::Rect,
+    pub query: tui::Rect,
+    pub results: Option<tui::Rect>,
+}
+
+impl Overlay {
+    /// The most results an overlay shows at once in `frame`.
+    pub fn rows(&self, frame: tui::Rect) -> usize {
+        self.layout(frame, usize::MAX)
+            .and_then(|layout| layout.results)
+            .map_or(0, |results| results.height.into())
+    }
+
+    /// Lays out a pane listing `num_results` at the top of `frame`, centered, showing as many
+    /// results as fit above the margin. None if the frame can't hold even the query row.
+    pub(crate) fn layout(&self, frame: tui::Rect, num_results: usize) -> Option<OverlayLayout> {
+        let width = self.max_width.min(frame.width * 3 / 5);
+        if width < 3 || frame.height < 3 {
+            return None;
+        }
+        let x = frame.x + (frame.width - width) / 2;
+        let y = frame.y;
+
+        // borders, the query, and the separator take four rows
+        let fits = frame.height.saturating_sub(BOTTOM_MARGIN + 4);
+        let rows = tore::to_screen(num_results).min(self.max_rows).min(fits);
+        let height = match rows {
+            0 => 3,
+            rows => rows + 4,
+        };
+
+        let border = tui::Rect::new(x, y, width, height);
+        let query = tui::Rect::new(x + 1, y + 1, width - 2, 1);
+        let results = (rows > 0).then(|| tui::Rect::new(x + 1, y + 3, width - 2, rows));
+        Some(OverlayLayout { border, query, results })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_fits_frame() {
+        let overlay = Overlay::default();
+        for (width, height) in [(300, 80), (80, 24), (60, 8)] {
+            let frame = tui::Rect::new(0, 0, width, height);
+            for num_results in [0, 1, 5, 100] {
+                let layout = overlay.layout(frame, num_results).unwrap();
+                assert_eq!(frame.intersection(layout.border), layout.border);
+                assert!(layout.border.bottom() + BOTTOM_MARGIN <= frame.bottom());
+                // centered
+                let (left, right) = (layout.border.left(), frame.right() - layout.border.right());
+                assert!(left.abs_diff(right) <= 1, "{:?} in {:?}", layout.border, frame);
+
+                let rows = layout.results.map_or(0, |r| r.height);
+                assert!(usize::from(rows) <= num_results);
+                assert!(rows <= overlay.max_rows);
+                if let Some(results) = layout.results {
+                    assert!(results.bottom() < layout.border.bottom());
+                }
+            }
+        }
+
+        let frame = tui::Rect::new(0, 0, 300, 80);
+        let layout = overlay.layout(frame, 100).unwrap();
+        assert_eq!(layout.border, tui::Rect::new(110, 0, 80, 17));
+        let frame = tui::Rect::new(0, 0, 60, 8);
+        let layout = overlay.layout(frame, 100).unwrap();
+        assert_eq!(layout.border, tui::Rect::new(12, 0, 36, 7));
+        assert_eq!(layout.results.map(|r| r.height), Some(3));
+        assert_eq!(overlay.rows(frame), 3);
+        assert_eq!(overlay.rows(tui::Rect::new(0, 0, 300, 80)), 13);
+
+        assert_eq!(overlay.layout(tui::Rect::new(0, 0, 60, 2), 1), None);
+    }
+}
@@ -0,0 +1,86 @@
+use ratatui::prelude as tui;
+use std::ops::Range;
+
+/// A one-column scrollbar: a track as tall as the pane, with a thumb standing for the part of
+/// the content in view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scrollbar {
+    /// How many rows of content there are.
+    pub len: usize,
+    /// The first row in view.
+    pub offset: usize,
+    /// How many rows are in view.
+    pub viewport: usize,
+}
+
+impl Scrollbar {
+    pub fn new(len: usize, offset: usize, viewport: usize) -> Self {
+        Self { len, offset, viewport }
+    }
+
+    /// Whether any of the content is out of view; there's nothing to show otherwise.
+    pub fn is_visible(&self) -> bool {
+        self.len > self.viewport
+    }
+
+    /// The rows of a track `height` rows tall that the thumb covers. It's never less than a row,
+    /// and it reaches the bottom of the track only once the last row is in view.
+    pub fn thumb(&self, height: u16) -> Range<u16> {
+        if !self.is_visible() || height == 0 {
+            return 0..height;
+        }
+        let track = usize::from(height);
+        let size = (track * self.viewport / self.len).clamp(1, track);
+        let start = if self.offset + self.viewport >= self.len {
+            track - size
+        } else {
+            (track * self.offset / self.len).min(track - size)
+        };
+        tore::to_screen(start)..tore::to_screen(start + size)
+    }
+
+    /// Draws the track and thumb down `area`'s leftmost column.
+    pub(crate) fn render(
+        &self,
+        buf: &mut tui::Buffer,
+        area: tui::Rect,
+        track: tui::Style,
+        thumb: tui::Style,
+    ) {
+        let rows = self.thumb(area.height);
+        for (row, y) in (area.top()..area.bottom()).enumerate() {
+            let style = if rows.contains(&tore::to_screen(row)) {
+                thumb
+            } else {
+                track
+            };
+            buf.get_mut(area.left(), y)
+                .set_char(' ')
+                .set_style(tui::Style::reset().patch(style));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thumb_follows_offset() {
+        let thumb = |offset| Scrollbar::new(1000, offset, 20).thumb(20);
+        assert_eq!(thumb(0), 0..1);
+        assert_eq!(thumb(49), 0..1);
+        assert_eq!(thumb(50), 1..2);
+        assert_eq!(thumb(500), 10..11);
+        assert_eq!(thumb(979), 19..20);
+        assert_eq!(thumb(980), 19..20);
+
+        // the thumb grows with the share of the content in view
+        assert_eq!(Scrollbar::new(100, 0, 50).thumb(50), 0..25);
+        assert_eq!(Scrollbar::new(100, 25, 50).thumb(50), 12..37);
+        assert_eq!(Scrollbar::new(100, 50, 50).thumb(50), 25..50);
+
+        assert!(!Scrollbar::new(5, 0, 20).is_visible());
+        assert!(!Scrollbar::new(20, 0, 20).is_visible());
+    }
+}
@@ -5,14 +5,13 @@ use ratatui::prelude as tui;
 use selector::Selector;
 use tore::CursorPoint;
 
-use crate::theme::Color;
+use crate::overlay::Overlay;
 
 #[derive(Debug)]
 pub struct Theme {
-    bg: Color,
-    fg: Color,
-    bg_selected: Color,
-    fg_highlight: Color,
+    menu: tui::Style,
+    selected: tui::Style,
+    overlay: Overlay,
 }
 
 // pub trait Renderer<Id> {
@@ -28,76 +27,54 @@ pub struct SelectorPane<'a, Id: Eq + Copy> {
 
 impl<'a, Id: Eq + Copy> SelectorPane<'a, Id> {
     pub fn new(theme: &crate::Theme, selector: &'a Selector<Id>) -> Self {
-        let bg = theme.palette("bg0").unwrap();
-        let bg_selected = theme.palette("bg1").unwrap();
-        let fg = theme.palette("fg0").unwrap();
-        let fg_highlight = theme.palette("yellow").unwrap();
-        let theme = Theme { bg, fg, bg_selected, fg_highlight };
+        let theme = Theme {
+            menu: tui::Style::reset().patch(theme.style("ui.menu")),
+            selected: tui::Style::reset().patch(theme.style("ui.menu.selected")),
+            overlay: theme.overlay(),
+        };
         Self { theme, selector }
     }
 
+    /// Draws the query and `results`, which start with the selector's entries but may go on past
+    /// them, like hints that can't be focused. They're walked rather than collected, so drawing
+    /// them allocates nothing of its own.
     #[tracing::instrument(skip(self, buf, results, render))]
-    pub fn render<R>(
+    pub fn render<T, I, R>(
         self,
         buf: &mut tui::Buffer,
         area: tui::Rect,
-        results: &Vec<Id>,
+        results: I,
         render: R,
     ) -> (CursorPoint, SetCursorStyle)
     where
-        R: Fn(tui::Rect, &mut tui::Buffer, Id) -> (),
+        I: Iterator<Item = T> + Clone,
+        R: Fn(tui::Rect, &mut tui::Buffer, T),
     {
-        let area = self.layout(area, results.len());
-        let (query_area, results_area) = Self::split_sections(area);
-        self.render_borders(buf, area);
-        self.render_query(buf, query_area);
-        if results_area.is_some() {
-            self.render_results(buf, results_area.unwrap(), results, render);
+        let Some(layout) = self.theme.overlay.layout(area, results.clone().count()) else {
+            let cursor_pos = CursorPoint::new(area.x.into(), area.y.into());
+            return (cursor_pos, SetCursorStyle::BlinkingBlock);
+        };
+        self.render_borders(buf, layout.border);
+        self.render_query(buf, layout.query);
+        if let Some(results_area) = layout.results {
+            self.render_results(buf, results_area, results, render);
         }
 
-        let cursor_pos = self.cursor_pos(self.selector.cursor, query_area);
+        let cursor_pos = self.cursor_pos(layout.query);
         (cursor_pos, SetCursorStyle::BlinkingBlock)
     }
 
-    fn cursor_pos(&self, cursor: tore::Point, area: tui::Rect) -> CursorPoint {
-        let x = area.left() + (self.selector.query_prefix.len() as u16) + cursor.column as u16;
-        let y = area.top();
-        CursorPoint { x, y }
-    }
-
-    fn layout(&self, dims: tui::Rect, num_results: usize) -> tui::Rect {
-        use std::cmp::min;
-
-        const WIDTH_RATIO: u16 = 4;
-        let y = 0; // dims.height / 5;
-        let x0 = dims.width / WIDTH_RATIO;
-        let x1 = x0 * (WIDTH_RATIO - 1);
-        let width = min(x1 - x0, 80) + (self.selector.query_prefix.len() as u16) + 2;
-        let height = if num_results > 0 {
-            (num_results.clamp(1, 13) as u16) + 4
-        } else {
-            3
-        };
-
-        tui::Rect::new(x0, y, width, height)
-    }
-
-    fn split_sections(area: tui::Rect) -> (tui::Rect, Option<tui::Rect>) {
-        let query = tui::Rect::new(area.x + 1, area.y + 1, area.width - 2, 1);
-        let results = if area.height > 4 {
-            Some(tui::Rect::new(area.x + 1, area.y + 3, area.width - 2, area.height - 4))
-        } else {
-            None
-        };
-        (query, results)
+    fn cursor_pos(&self, area: tui::Rect) -> CursorPoint {
+        let column = self.selector.query.cursor_column();
+        let x = usize::from(area.left()) + self.selector.query_prefix.len() + column;
+        let y = usize::from(area.top());
+        CursorPoint::new(x, y)
     }
 
     fn render_borders(&self, buf: &mut tui::Buffer, area: tui::Rect) {
         use ratatui::symbols;
 
-        let style = tui::Style::reset()
-            .fg(self.theme.fg.into())
-            .bg(self.theme.bg.into());
+        let style = self.theme.menu;
 
         for y in area.top()..area.bottom() {
             // left-vertical border
@@ -153,12 +130,16 @@ impl<'a, Id: Eq + Copy> SelectorPane<'a, Id> {
 
     fn render_query(&self, buf: &mut tui::Buffer, area: tui::Rect) {
         let range = area.left()..area.right();
-        let style = tui::Style::reset()
-            .fg(self.theme.fg.into())
-            .bg(self.theme.bg.into());
+        let style = self.theme.menu;
 
         let query_prefix = self.selector.query_prefix.as_bytes().as_bstr().graphemes();
-        let query = self.selector.query.as_bytes().as_bstr().graphemes();
+        let query = self
+            .selector
+            .query
+            .as_str()
+            .as_bytes()
+            .as_bstr()
+            .graphemes();
         let mut query = query_prefix.chain(query);
         for x in range {
             let c = query.next().unwrap_or(" ");
@@ -166,25 +147,30 @@ impl<'a, Id: Eq + Copy> SelectorPane<'a, Id> {
         }
     }
 
-    fn render_results<R>(
-        &self,
-        buf: &mut tui::Buffer,
-        area: tui::Rect,
-        results: &Vec<Id>,
-        render: R,
-    ) where
-        R: Fn(tui::Rect, &mut tui::Buffer, Id) -> (),
+    fn render_results<T, I, R>(&self, buf: &mut tui::Buffer, area: tui::Rect, results: I, render: R)
+    where
+        I: Iterator<Item = T> + Clone,
+        R: Fn(tui::Rect, &mut tui::Buffer, T),
     {
-        let style = tui::Style::reset()
-            .fg(self.theme.fg.into())
-            .bg(self.theme.bg.into());
+        let style = self.theme.menu;
 
-        let has_results = !results.is_empty();
+        let has_results = results.clone().next().is_some();
         if !has_results {
             return;
         }
 
-        let mut results = results.into_iter();
+        // the selector scrolls with its focus, but only this knows how many rows are in view
+        let rows = usize::from(area.height);
+        let focused = self.selector.focused_index();
+        let start = match focused {
+            Some(index) => self
+                .selector
+                .scroll()
+                .min(index)
+                .max((index + 1).saturating_sub(rows)),
+            None => 0,
+        };
+        let mut results = results.enumerate().skip(start);
         if !has_results {
             let mut graphemes = " No matches".as_bytes().as_bstr().graphemes();
             for x in area.left()..area.right() {
@@ -195,26 +181,19 @@ impl<'a, Id: Eq + Copy> SelectorPane<'a, Id> {
             }
         } else {
             for y in area.top()..area.bottom() {
-                let result: Option<&Id> = results.next();
-                let (prefix, bg) = match (self.selector.focused, result) {
+                let result = results.next();
+                let style = match (focused, &result) {
                     (None, Some(_)) => unreachable!("something must be focused if results exist"),
-                    (Some(selected), Some(result)) => {
-                        if selected == *result {
-                            ("", self.theme.bg_selected)
-                        } else {
-                            (" ", self.theme.bg)
-                        }
-                    }
-                    (_, None) => (" ", self.theme.bg),
+                    (Some(selected), Some((index, _))) if selected == *index => self.theme.selected,
+                    _ => self.theme.menu,
                 };
-                if let Some(result) = result {
-                    let graphemes = prefix.as_bytes().as_bstr().graphemes();
-                    let mut len = 0;
-                    for (i, symbol) in graphemes.enumerate() {
-                        len += 1;
-                        buf.get_mut(area.left() + i as u16, y).set_symbol(symbol);
-                    }
-                    render(tui::Rect::new(area.left() + len, y, area.width, 1), buf, *result);
+                for x in area.left()..area.right() {
+                    buf.get_mut(x, y).set_style(style).set_symbol(" ");
+                }
+                // the focused entry is told apart by its background alone, after a column of
+                // padding like every other
+                if let Some((_, result)) = result {
+                    render(tui::Rect::new(area.left() + 1, y, area.width, 1), buf, result);
                 }
 
                 // let (content_prefix, bg) = match (self.selector.selected, &result) {
@@ -262,3 +241,96 @@ impl<'a, Id: Eq + Copy> SelectorPane<'a, Id> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_within_frame() {
+        let theme = crate::Theme::default();
+        let mut selector = Selector::new(":");
+        let entries: Vec<usize> = (0..100).collect();
+        selector.command(selector::Command::SetEntries(entries.clone()));
+        selector.focused = Some(0);
+
+        for (width, height) in [(300, 80), (80, 24), (60, 8)] {
+            let frame = tui::Rect::new(0, 0, width, height);
+            let mut buf = tui::Buffer::empty(frame);
+            let rendered = std::cell::RefCell::new(vec![]);
+            let pane = SelectorPane::new(&theme, &selector);
+            let (cursor, _) =
+                pane.render(&mut buf, frame, entries.iter().copied(), |area, _, id| {
+                    rendered.borrow_mut().push((area, id))
+                });
+            assert!(cursor.x < width && cursor.y < height);
+
+            let rendered = rendered.into_inner();
+            let rows = usize::from(height - 5).min(13);
+            assert_eq!(rendered.len(), rows, "{}x{}", width, height);
+            for (area, _) in rendered.iter() {
+                assert!(area.bottom() < height - 1, "{:?} in {}x{}", area, width, height);
+            }
+            // nothing is drawn on the last row, which is left as a margin
+            let last_row: String = (0..width)
+                .map(|x| buf.get(x, height - 1).symbol.as_str())
+                .collect();
+            assert_eq!(last_row.trim(), "");
+        }
+    }
+
+    #[test]
+    fn focused_entry_in_view() {
+        let theme = crate::Theme::default();
+        let frame = tui::Rect::new(0, 0, 80, 24);
+        let mut selector = Selector::new(":");
+        let entries: Vec<usize> = (0..100).collect();
+        selector.command(selector::Command::SetEntries(entries.clone()));
+        selector.set_page_size(theme.overlay().rows(frame));
+
+        let rendered = |selector: &Selector<usize>| {
+            let mut buf = tui::Buffer::empty(frame);
+            let rendered = std::cell::RefCell::new(vec![]);
+            SelectorPane::new(&theme, selector).render(
+                &mut buf,
+                frame,
+                entries.iter().copied(),
+                |_, _, id| rendered.borrow_mut().push(id),
+            );
+            rendered.into_inner()
+        };
+        selector.command(selector::Command::Focus(selector::Direction::Next));
+        assert_eq!(rendered(&selector), (0..13).collect::<Vec<_>>());
+        selector.command(selector::Command::FocusPage(selector::Direction::Next));
+        assert_eq!(rendered(&selector), (2..15).collect::<Vec<_>>());
+        selector.command(selector::Command::FocusLast);
+        assert_eq!(rendered(&selector), (87..100).collect::<Vec<_>>());
+
+        // a page size gone stale still shows the focused entry
+        selector.set_page_size(50);
+        selector.command(selector::Command::FocusFirst);
+        selector.command(selector::Command::FocusPage(selector::Direction::Next));
+        assert_eq!(rendered(&selector), (38..51).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn frame_with_cell_cursor() {
+        let theme = crate::Theme::default();
+        let mut selector = Selector::new(":");
+        let entries = vec![0, 1];
+        selector.command(selector::Command::SetEntries(entries.clone()));
+        selector.command(selector::Command::Insert('w'));
+        selector.command(selector::Command::Insert('r'));
+
+        let labels = ["write", "wrap"];
+        let frame = crate::golden::draw(20, 7, |buf, area| {
+            let pane = SelectorPane::new(&theme, &selector);
+            let cursor = pane.render(buf, area, entries.iter().copied(), |area, buf, id| {
+                buf.set_string(area.x, area.y, labels[id], tui::Style::default());
+            });
+            crate::draw_cell_cursor(buf, area, cursor);
+            cursor
+        });
+        crate::golden::assert_frame("cell_cursor_palette", &frame);
+    }
+}
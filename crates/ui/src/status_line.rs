@@ -0,0 +1,457 @@
+use editor::{Buffer, Editor};
+use ratatui::prelude as tui;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Mode,
+    File,
+    /// The symbol the cursor is in, as the last parse found it.
+    Symbol,
+    Message,
+    /// How long the last input took to reach the screen.
+    Latency,
+    Encoding,
+    Diagnostics,
+    Search,
+    Position,
+    Percent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Segments in display order, with their alignment group and truncation priority. When the line
+/// is too narrow the lowest priority segments are dropped first, rightmost first among equals. A
+/// message lasts only until the next key, so it's kept over the file name.
+const SEGMENTS: [(Segment, Align, u8); 10] = [
+    (Segment::Mode, Align::Left, 4),
+    (Segment::File, Align::Left, 2),
+    (Segment::Symbol, Align::Left, 0),
+    (Segment::Message, Align::Left, 3),
+    (Segment::Latency, Align::Right, 0),
+    (Segment::Encoding, Align::Right, 0),
+    (Segment::Diagnostics, Align::Right, 0),
+    (Segment::Search, Align::Right, 1),
+    (Segment::Position, Align::Right, 3),
+    (Segment::Percent, Align::Right, 1),
+];
+
+/// The columns past which the position shows only that the cursor is beyond it, as on the one
+/// line of a minified file.
+const COLUMN_LIMIT: usize = 100_000;
+
+#[derive(Debug)]
+struct Theme {
+    line: tui::Style,
+    message: tui::Style,
+    diagnostics: tui::Style,
+    position: tui::Style,
+    normal: tui::Style,
+    insert: tui::Style,
+    replace: tui::Style,
+    visual: tui::Style,
+}
+
+pub struct StatusLine<'a> {
+    theme: Theme,
+    buffer: &'a Buffer,
+    editor: &'a Editor,
+    diagnostics: usize,
+    message: Option<&'a str>,
+    symbol: Option<&'a str>,
+    latency: Option<std::time::Duration>,
+    /// Whether the app has focus; the line is dimmed when it hasn't.
+    focused: bool,
+}
+
+impl<'a> StatusLine<'a> {
+    pub fn new(theme: &crate::Theme, buffer: &'a Buffer, editor: &'a Editor) -> Self {
+        let theme = Theme {
+            line: tui::Style::reset().patch(theme.style("ui.statusline")),
+            message: theme.style("ui.statusline.message"),
+            diagnostics: theme.style("ui.statusline.diagnostics"),
+            position: theme.style("ui.statusline.position"),
+            normal: theme.style("ui.statusline.normal"),
+            insert: theme.style("ui.statusline.insert"),
+            replace: theme.style("ui.statusline.replace"),
+            visual: theme.style("ui.statusline.visual"),
+        };
+        Self {
+            theme,
+            buffer,
+            editor,
+            diagnostics: 0,
+            message: None,
+            symbol: None,
+            latency: None,
+            focused: true,
+        }
+    }
+
+    pub fn diagnostics(mut self, count: usize) -> Self {
+        self.diagnostics = count;
+        self
+    }
+
+    pub fn message(mut self, message: Option<&'a str>) -> Self {
+        self.message = message;
+        self
+    }
+
+    pub fn symbol(mut self, symbol: Option<&'a str>) -> Self {
+        self.symbol = symbol;
+        self
+    }
+
+    pub fn latency(mut self, latency: Option<std::time::Duration>) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn focused(mut self, focused: bool) -> Self {
+        self.focused = focused;
+        self
+    }
+
+    /// Renders `segment`; an empty list hides the segment.
+    fn segment(&self, segment: Segment) -> Vec<tui::Span<'static>> {
+        let style = self.theme.line;
+        let cursor = self.editor.cursor();
+        let len_lines = self.buffer.contents.len_lines().max(1);
+        match segment {
+            Segment::Mode => {
+                let (name, mode) = match self.editor.mode {
+                    editor::Mode::Normal => ("NORMAL", self.theme.normal),
+                    editor::Mode::Insert => ("INSERT", self.theme.insert),
+                    editor::Mode::Replace => ("REPLACE", self.theme.replace),
+                    editor::Mode::VisualLine => ("V-LINE", self.theme.visual),
+                };
+                vec![tui::Span::styled(format!(" {} ", name), style.patch(mode))]
+            }
+            Segment::File => {
+                let name = match &self.buffer.path {
+                    None => "[scratch]".to_string(),
+                    Some(path) => path.display().to_string(),
+                };
+                let read_only = if self.buffer.read_only { " [ro]" } else { "" };
+                let file = tui::Span::styled(format!(" {}{} ", name, read_only), style);
+                match self.buffer.disk_state {
+                    // changed on disk and here, so neither saving nor reading it again is safe
+                    editor::DiskState::Conflicted => {
+                        let conflict = style.patch(self.theme.diagnostics);
+                        vec![file, tui::Span::styled("[conflict] ", conflict)]
+                    }
+                    _ => vec![file],
+                }
+            }
+            Segment::Symbol => match self.symbol {
+                None => vec![],
+                Some(symbol) => vec![tui::Span::styled(format!("› {} ", symbol), style)],
+            },
+            Segment::Message => match self.message {
+                None => vec![],
+                Some(message) => {
+                    let style = style.patch(self.theme.message);
+                    vec![tui::Span::styled(format!(" {} ", message), style)]
+                }
+            },
+            Segment::Latency => match self.latency {
+                None => vec![],
+                Some(latency) => {
+                    let ms = latency.as_secs_f64() * 1000.0;
+                    vec![tui::Span::styled(format!(" {:.1}ms ", ms), style)]
+                }
+            },
+            // plain UTF-8 ending in a line break is the norm, so only anything else is worth the
+            // space; like vim, a file without a final line break is noeol
+            Segment::Encoding => {
+                let mut format = vec![];
+                if !self.buffer.format.is_default() {
+                    format.push(self.buffer.format.to_string());
+                }
+                if !self.buffer.had_final_newline && !self.buffer.is_binary() {
+                    format.push("noeol".to_string());
+                }
+                if format.is_empty() {
+                    vec![]
+                } else {
+                    vec![tui::Span::styled(format!(" {} ", format.join(" ")), style)]
+                }
+            }
+            Segment::Diagnostics if self.diagnostics == 0 => vec![],
+            Segment::Diagnostics => {
+                let style = style.patch(self.theme.diagnostics);
+                vec![tui::Span::styled(
+                    format!(" ⚠ {} ", self.diagnostics),
+                    style,
+                )]
+            }
+            Segment::Search => match self.editor.search() {
+                None => vec![],
+                Some(search) => {
+                    // without the matches around the cursor, which one it's on isn't known
+                    let index = self.editor.search_index(self.buffer);
+                    let index = index.map_or("?".to_string(), |index| index.to_string());
+                    let more = if search.counted_all() { "" } else { "+" };
+                    let count = format!(" ‹{}/{}{}› ", index, search.total(), more);
+                    vec![tui::Span::styled(count, style)]
+                }
+            },
+            // a binary buffer's lines are wherever its bytes happen to be line breaks, so what
+            // counts is the byte the cursor is on
+            Segment::Position if self.buffer.is_binary() => {
+                let contents = &self.buffer.contents;
+                let offset = contents.line_to_char(cursor.line) + cursor.column;
+                let position = format!(" {:#x}/{:#x} ", offset, contents.len_chars());
+                vec![tui::Span::styled(
+                    position,
+                    style.patch(self.theme.position),
+                )]
+            }
+            Segment::Position => {
+                let style = style.patch(self.theme.position);
+                // past the limit the exact column is no use to read, and takes the room of others
+                let column = if cursor.column < COLUMN_LIMIT {
+                    (cursor.column + 1).to_string()
+                } else {
+                    format!(">{}", COLUMN_LIMIT)
+                };
+                let position = format!(" {}/{}:{} ", cursor.line + 1, len_lines, column);
+                vec![tui::Span::styled(position, style)]
+            }
+            Segment::Percent => {
+                let percent = (cursor.line + 1) * 100 / len_lines;
+                vec![tui::Span::styled(format!(" {}% ", percent), style)]
+            }
+        }
+    }
+
+    /// The segments that fit in `width`, in display order.
+    fn layout(&self, width: u16) -> Vec<(Segment, Align, Vec<tui::Span<'static>>)> {
+        let mut segments: Vec<_> = SEGMENTS
+            .iter()
+            .map(|(segment, align, priority)| (*segment, *align, *priority, self.segment(*segment)))
+            .filter(|(.., spans)| !spans.is_empty())
+            .collect();
+
+        let span_width = |spans: &[tui::Span]| spans.iter().map(|s| s.width()).sum::<usize>();
+        let mut total: usize = segments.iter().map(|(.., spans)| span_width(spans)).sum();
+        while total > width.into() {
+            let Some(idx) = segments
+                .iter()
+                .enumerate()
+                .rev()
+                .min_by_key(|(_, (.., priority, _))| *priority)
+                .map(|(idx, _)| idx)
+            else {
+                break;
+            };
+            let (.., spans) = segments.remove(idx);
+            total -= span_width(&spans);
+        }
+
+        segments
+            .into_iter()
+            .map(|(segment, align, _, spans)| (segment, align, spans))
+            .collect()
+    }
+
+    #[tracing::instrument(skip(self, buf))]
+    pub fn render(self, buf: &mut tui::Buffer, area: tui::Rect) {
+        buf.set_style(area, self.theme.line);
+
+        let segments = self.layout(area.width);
+        let (left, right): (Vec<_>, Vec<_>) = segments
+            .into_iter()
+            .partition(|(_, align, _)| *align == Align::Left);
+
+        let y = area.top();
+        let mut x = area.left();
+        for span in left.iter().flat_map(|(.., spans)| spans) {
+            (x, _) = buf.set_span(x, y, span, area.right().saturating_sub(x));
+        }
+
+        let right_width: usize = right
+            .iter()
+            .flat_map(|(.., spans)| spans)
+            .map(|s| s.width())
+            .sum();
+        let mut x = area
+            .right()
+            .saturating_sub(tore::to_screen(right_width))
+            .max(x);
+        for span in right.iter().flat_map(|(.., spans)| spans) {
+            (x, _) = buf.set_span(x, y, span, area.right().saturating_sub(x));
+        }
+        if !self.focused {
+            buf.set_style(area, tui::Style::default().add_modifier(tui::Modifier::DIM));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> (Buffer, Editor) {
+        let contents = ["line"; 100].join("\n");
+        let path = std::path::PathBuf::from("src/main.rs");
+        let contents = contents.as_str().into();
+        let mut buffer = Buffer::open(Default::default(), path, contents, Default::default());
+        // as if it had ended with a line break, which is too usual to show
+        buffer.had_final_newline = true;
+        let mut editor = Editor::new(Default::default(), buffer.id);
+        editor.set_cursor(tore::Point { line: 41, column: 4 });
+        (buffer, editor)
+    }
+
+    fn render(status: StatusLine, width: u16) -> String {
+        let area = tui::Rect::new(0, 0, width, 1);
+        let mut buf = tui::Buffer::empty(area);
+        status.render(&mut buf, area);
+        (0..width).map(|x| buf.get(x, 0).symbol.as_str()).collect()
+    }
+
+    #[test]
+    fn truncate_segments() {
+        use Segment::*;
+
+        let theme = crate::Theme::default();
+        let (buffer, editor) = fixture();
+        let status = || StatusLine::new(&theme, &buffer, &editor).diagnostics(2);
+        let kept = |width| -> Vec<_> {
+            status()
+                .layout(width)
+                .into_iter()
+                .map(|(s, ..)| s)
+                .collect()
+        };
+
+        assert_eq!(kept(120), vec![Mode, File, Diagnostics, Position, Percent]);
+        assert_eq!(kept(60), vec![Mode, File, Diagnostics, Position, Percent]);
+        assert_eq!(kept(25), vec![Mode, Position]);
+
+        let line = render(status(), 120);
+        assert!(line.starts_with(" NORMAL  src/main.rs "), "{:?}", line);
+        assert!(line.ends_with(" ⚠ 2  42/100:5  42% "), "{:?}", line);
+
+        let line = render(status(), 60);
+        assert_eq!(line.len(), 60 + "⚠".len() - 1);
+        assert!(line.ends_with(" ⚠ 2  42/100:5  42% "), "{:?}", line);
+
+        let line = render(status(), 25);
+        assert!(line.starts_with(" NORMAL "), "{:?}", line);
+        assert!(line.ends_with(" 42/100:5 "), "{:?}", line);
+        assert!(!line.contains("main.rs"), "{:?}", line);
+
+        let (mut buffer, editor) = fixture();
+        buffer.format = editor::Format { encoding: editor::Encoding::Utf16Le, bom: true };
+        buffer.read_only = true;
+        let line = render(StatusLine::new(&theme, &buffer, &editor), 60);
+        assert!(line.starts_with(" NORMAL  src/main.rs [ro] "), "{:?}", line);
+        assert!(line.ends_with(" utf-16le bom  42/100:5  42% "), "{:?}", line);
+
+        let (mut buffer, editor) = fixture();
+        buffer.disk_state = editor::DiskState::Conflicted;
+        let line = render(StatusLine::new(&theme, &buffer, &editor), 60);
+        assert!(line.starts_with(" NORMAL  src/main.rs [conflict] "), "{:?}", line);
+        let (buffer, editor) = fixture();
+
+        // without diagnostics there is room for the file name again
+        let status = StatusLine::new(&theme, &buffer, &editor);
+        assert_eq!(
+            status
+                .layout(36)
+                .into_iter()
+                .map(|(s, ..)| s)
+                .collect::<Vec<_>>(),
+            vec![Mode, File, Position, Percent]
+        );
+    }
+
+    #[test]
+    fn symbol_dropped_first() {
+        use Segment::*;
+
+        let theme = crate::Theme::default();
+        let (buffer, editor) = fixture();
+        let status = || StatusLine::new(&theme, &buffer, &editor).symbol(Some("impl Editor"));
+        let line = render(status(), 60);
+        assert!(line.starts_with(" NORMAL  src/main.rs › impl Editor "), "{:?}", line);
+
+        let kept = |width| -> Vec<_> {
+            status()
+                .layout(width)
+                .into_iter()
+                .map(|(s, ..)| s)
+                .collect()
+        };
+        assert_eq!(kept(60), vec![Mode, File, Symbol, Position, Percent]);
+        assert_eq!(kept(40), vec![Mode, File, Position, Percent]);
+    }
+
+    #[test]
+    fn latency_shown() {
+        let theme = crate::Theme::default();
+        let (buffer, editor) = fixture();
+        let latency = Some(std::time::Duration::from_micros(3300));
+        let status = StatusLine::new(&theme, &buffer, &editor).latency(latency);
+        let line = render(status, 60);
+        assert!(line.ends_with(" 3.3ms  42/100:5  42% "), "{:?}", line);
+    }
+
+    #[test]
+    fn noeol() {
+        let theme = crate::Theme::default();
+        let (mut buffer, editor) = fixture();
+        let status = |buffer: &Buffer| render(StatusLine::new(&theme, buffer, &editor), 60);
+        assert!(!status(&buffer).contains("noeol"));
+
+        buffer.had_final_newline = false;
+        let line = status(&buffer);
+        assert!(line.ends_with(" noeol  42/100:5  42% "), "{:?}", line);
+        buffer.format = editor::Format { encoding: editor::Encoding::Utf16Le, bom: true };
+        let line = status(&buffer);
+        assert!(line.ends_with(" utf-16le bom noeol  42/100:5  42% "), "{:?}", line);
+    }
+
+    #[test]
+    fn dimmed_out_of_focus() {
+        let theme = crate::Theme::default();
+        let (buffer, editor) = fixture();
+        let area = tui::Rect::new(0, 0, 60, 1);
+        for focused in [true, false] {
+            let mut buf = tui::Buffer::empty(area);
+            let status = StatusLine::new(&theme, &buffer, &editor).focused(focused);
+            status.render(&mut buf, area);
+            let dimmed = (0..60).filter(|x| buf.get(*x, 0).modifier.contains(tui::Modifier::DIM));
+            assert_eq!(dimmed.count(), if focused { 0 } else { 60 });
+        }
+    }
+
+    #[test]
+    fn search_count() {
+        let theme = crate::Theme::default();
+        let (mut buffer, mut editor) = fixture();
+        let status =
+            |buffer: &Buffer, editor: &Editor| render(StatusLine::new(&theme, buffer, editor), 60);
+        assert!(!status(&buffer, &editor).contains('‹'));
+
+        let search = editor::EditorCommand::Search("line".into());
+        editor.command(&mut buffer, search).unwrap();
+        let line = status(&buffer, &editor);
+        assert!(line.ends_with(" ‹43/100›  43/100:1  43% "), "{:?}", line);
+
+        editor.set_cursor(tore::Point { line: 41, column: 2 });
+        let line = status(&buffer, &editor);
+        assert!(line.ends_with(" ‹42/100›  42/100:3  42% "), "{:?}", line);
+
+        editor
+            .command(&mut buffer, editor::EditorCommand::ClearSearch)
+            .unwrap();
+        assert!(!status(&buffer, &editor).contains('‹'));
+    }
+}
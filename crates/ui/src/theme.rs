@@ -1,6 +1,27 @@
 use std::collections::HashMap;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+use ratatui::prelude as tui;
+
+use crate::Overlay;
+
+/// The theme drawn with unless another is given.
+const DEFAULT: &str = include_str!("../themes/default.toml");
+
+/// The modifiers a style can set, by the names a theme gives them.
+const MODIFIERS: [(&str, tui::Modifier); 9] = [
+    ("bold", tui::Modifier::BOLD),
+    ("dim", tui::Modifier::DIM),
+    ("italic", tui::Modifier::ITALIC),
+    ("underlined", tui::Modifier::UNDERLINED),
+    ("slow_blink", tui::Modifier::SLOW_BLINK),
+    ("rapid_blink", tui::Modifier::RAPID_BLINK),
+    ("reversed", tui::Modifier::REVERSED),
+    ("hidden", tui::Modifier::HIDDEN),
+    ("crossed_out", tui::Modifier::CROSSED_OUT),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Color(pub ratatui::style::Color);
 
 impl From<Color> for ratatui::style::Color {
@@ -9,97 +30,421 @@ impl From<Color> for ratatui::style::Color {
     }
 }
 
-impl From<&str> for Color {
-    fn from(src: &str) -> Self {
-        let r = u8::from_str_radix(&src[1..3], 16).expect("valid hex red value");
-        let g = u8::from_str_radix(&src[3..5], 16).expect("valid hex green value");
-        let b = u8::from_str_radix(&src[5..7], 16).expect("valid hex blue value");
-        Color(ratatui::style::Color::Rgb(r, g, b))
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            ratatui::style::Color::Rgb(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+            color => write!(f, "{:?}", color),
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// Reads a `#rrggbb` color.
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("'{}' isn't a #rrggbb color", src);
+        let hex = src
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(bad)?;
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).expect("hex digits");
+        Ok(Color(ratatui::style::Color::Rgb(channel(0), channel(2), channel(4))))
+    }
+}
+
+/// A color in a style: the name of one in the theme's palette, or one of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColorRef {
+    Palette(String),
+    Rgb(Color),
+}
+
+impl ColorRef {
+    /// A `#rrggbb` color, or else the name of one in the palette.
+    fn parse(src: &str) -> Result<Self, String> {
+        if src.starts_with('#') {
+            src.parse().map(ColorRef::Rgb)
+        } else {
+            Ok(ColorRef::Palette(src.to_string()))
+        }
+    }
+}
+
+impl std::fmt::Display for ColorRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorRef::Palette(name) => f.write_str(name),
+            ColorRef::Rgb(color) => write!(f, "{}", color),
+        }
+    }
+}
+
+/// How a theme draws a scope: its colors, where it sets them, and the modifiers it adds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StyleSpec {
+    pub fg: Option<ColorRef>,
+    pub bg: Option<ColorRef>,
+    pub modifiers: tui::Modifier,
+}
+
+impl StyleSpec {
+    /// Reads a style written either as just its foreground color, or as an inline table like
+    /// `{ fg = "grey0", bg = "#282828", italic = true }`.
+    fn parse(value: Value<'_>) -> Result<Self, String> {
+        let entries = match value {
+            Value::Str(fg) => {
+                return Ok(Self { fg: Some(ColorRef::parse(fg)?), ..Self::default() })
+            }
+            Value::Bool(_) => return Err("a style is a color or an inline table".to_string()),
+            Value::Table(entries) => entries,
+        };
+        let mut spec = Self::default();
+        for (key, value) in entries {
+            match (key, value) {
+                ("fg", Value::Str(color)) => spec.fg = Some(ColorRef::parse(color)?),
+                ("bg", Value::Str(color)) => spec.bg = Some(ColorRef::parse(color)?),
+                ("fg" | "bg", _) => return Err(format!("'{}' is a color", key)),
+                (key, value) => {
+                    let (_, modifier) = MODIFIERS
+                        .iter()
+                        .find(|(name, _)| *name == key)
+                        .ok_or_else(|| format!("unknown style key '{}'", key))?;
+                    match value {
+                        Value::Bool(true) => spec.modifiers.insert(*modifier),
+                        Value::Bool(false) => spec.modifiers.remove(*modifier),
+                        _ => return Err(format!("'{}' is true or false", key)),
+                    }
+                }
+            }
+        }
+        Ok(spec)
+    }
+
+    fn colors(&self) -> impl Iterator<Item = &ColorRef> {
+        self.fg.iter().chain(self.bg.iter())
     }
 }
 
 #[derive(Debug)]
 pub struct Theme {
     palette: HashMap<String, Color>,
-    scheme: HashMap<String, String>,
+    scheme: HashMap<String, StyleSpec>,
+    overlay: Overlay,
 }
 
 impl Theme {
-    pub(crate) fn scheme(&self, name: &str) -> Option<Color> {
-        self.scheme.get(name).map(|n| self.palette[n])
+    /// Reads a theme written in TOML: a `[palette]` table of `#rrggbb` colors by name, and a
+    /// `[scheme]` table of styles by scope, each a color or an inline table of `fg`, `bg` and
+    /// modifier flags. A color in the scheme may be the name of one in the palette. Only as much
+    /// of TOML as themes need is understood: tables, keys, strings, booleans, inline tables and
+    /// comments. A bare dotted key is taken as the scope it spells.
+    pub fn from_toml(src: &str) -> Result<Self, String> {
+        let mut palette = HashMap::new();
+        let mut scheme = HashMap::new();
+        let mut table = None;
+        for (n, line) in src.lines().enumerate() {
+            let fail = |err: String| format!("line {}: {}", n + 1, err);
+            let mut line = Line(line);
+            if line.at_end() {
+                continue;
+            }
+            if line.eat('[') {
+                let name = line.key().map_err(fail)?;
+                if !line.eat(']') || !line.at_end() {
+                    return Err(fail("a table header is a name in [ ]".to_string()));
+                }
+                match name {
+                    "palette" | "scheme" => table = Some(name),
+                    name => return Err(fail(format!("unknown table '{}'", name))),
+                }
+                continue;
+            }
+
+            let (key, value) = line.entry().map_err(fail)?;
+            let set_twice = || fail(format!("'{}' is set twice", key));
+            match table {
+                Some("palette") => {
+                    let Value::Str(color) = value else {
+                        return Err(fail(format!("'{}' isn't a #rrggbb color", key)));
+                    };
+                    let color = color.parse::<Color>().map_err(fail)?;
+                    if palette.insert(key.to_string(), color).is_some() {
+                        return Err(set_twice());
+                    }
+                }
+                Some(_) => {
+                    let spec = StyleSpec::parse(value).map_err(fail)?;
+                    if scheme.insert(key.to_string(), spec).is_some() {
+                        return Err(set_twice());
+                    }
+                }
+                None => return Err(fail(format!("'{}' isn't in a table", key))),
+            }
+        }
+
+        let mut scopes: Vec<_> = scheme.iter().collect();
+        scopes.sort_by_key(|(scope, _)| *scope);
+        for (scope, spec) in scopes {
+            for color in spec.colors() {
+                if let ColorRef::Palette(name) = color {
+                    if !palette.contains_key(name) {
+                        return Err(format!(
+                            "'{}' is drawn in '{}', which isn't in the palette",
+                            scope, name
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(Self { palette, scheme, overlay: Overlay::default() })
+    }
+
+    /// The style the scheme gives `scope`, or else the one it gives the nearest scope `scope` is
+    /// under: `keyword.control.rust` is drawn as `keyword.control` is, or as `keyword` is.
+    pub fn spec(&self, scope: &str) -> Option<&StyleSpec> {
+        let mut scope = scope;
+        loop {
+            if let Some(spec) = self.scheme.get(scope) {
+                return Some(spec);
+            }
+            (scope, _) = scope.rsplit_once('.')?;
+        }
+    }
+
+    /// What `scope` is drawn with, as [`Theme::spec`] finds it. Only the colors it sets are set,
+    /// so it's laid over whatever the cell already has; a scope the scheme has nothing for sets
+    /// nothing.
+    pub fn style(&self, scope: &str) -> tui::Style {
+        let Some(spec) = self.spec(scope) else {
+            return tui::Style::default();
+        };
+        let mut style = tui::Style::default().add_modifier(spec.modifiers);
+        if let Some(fg) = &spec.fg {
+            style = style.fg(self.color(fg).0);
+        }
+        if let Some(bg) = &spec.bg {
+            style = style.bg(self.color(bg).0);
+        }
+        style
+    }
+
+    /// The foreground the scheme gives the highlight `name`, and its color.
+    pub fn scheme_entry(&self, name: &str) -> Option<(&ColorRef, Color)> {
+        let fg = self.spec(name)?.fg.as_ref()?;
+        Some((fg, self.color(fg)))
     }
 
     pub(crate) fn palette(&self, name: &str) -> Option<Color> {
         self.palette.get(name).copied()
     }
+
+    /// `color`, looked up in the palette if it's named; every name in the scheme is checked to be
+    /// there when the theme is read.
+    fn color(&self, color: &ColorRef) -> Color {
+        match color {
+            ColorRef::Palette(name) => self.palette[name],
+            ColorRef::Rgb(color) => *color,
+        }
+    }
+
+    pub fn overlay(&self) -> Overlay {
+        self.overlay
+    }
+
+    pub fn with_overlay(self, overlay: Overlay) -> Self {
+        Self { overlay, ..self }
+    }
 }
 
 impl Default for Theme {
     fn default() -> Self {
-        let palette = HashMap::from([
-            ("bg0".into(), "#282828".into()),
-            ("bg1".into(), "#32302f".into()),
-            ("bg2".into(), "#32302f".into()),
-            ("bg3".into(), "#45403d".into()),
-            ("bg4".into(), "#45403d".into()),
-            ("bg5".into(), "#5a524c".into()),
-            ("bg_statusline1".into(), "#32302f".into()),
-            ("bg_statusline2".into(), "#3a3735".into()),
-            ("bg_statusline3".into(), "#504945".into()),
-            ("bg_diff_green".into(), "#34381b".into()),
-            ("bg_visual_green".into(), "#3b4439".into()),
-            ("bg_diff_red".into(), "#402120".into()),
-            ("bg_visual_red".into(), "#4c3432".into()),
-            ("bg_diff_blue".into(), "#0e363e".into()),
-            ("bg_visual_blue".into(), "#374141".into()),
-            ("bg_visual_yellow".into(), "#4f422e".into()),
-            ("bg_current_word".into(), "#3c3836".into()),
-            ("fg0".into(), "#ebdbb2".into()),
-            ("fg1".into(), "#ebdbb2".into()),
-            ("red".into(), "#fb4934".into()),
-            ("orange".into(), "#fe8019".into()),
-            ("yellow".into(), "#fabd2f".into()),
-            ("green".into(), "#b8bb26".into()),
-            ("aqua".into(), "#8ec07c".into()),
-            ("blue".into(), "#83a598".into()),
-            ("purple".into(), "#d3869b".into()),
-            ("bg_red".into(), "#cc241d".into()),
-            ("bg_green".into(), "#b8bb26".into()),
-            ("bg_yellow".into(), "#fabd2f".into()),
-            ("grey0".into(), "#7c6f64".into()),
-            ("grey1".into(), "#928374".into()),
-            ("grey2".into(), "#a89984".into()),
-        ]);
-        let scheme = HashMap::from([
-            ("type".into(), "yellow".into()),
-            ("constant".into(), "purple".into()),
-            ("constant.numeric".into(), "purple".into()),
-            ("constant.character.escape".into(), "orange".into()),
-            ("string".into(), "green".into()),
-            ("string.regexp".into(), "blue".into()),
-            ("comment".into(), "grey0".into()),
-            ("variable".into(), "fg0".into()),
-            ("variable.builtin".into(), "blue".into()),
-            ("variable.parameter".into(), "fg0".into()),
-            ("variable.other.member".into(), "fg0".into()),
-            ("label".into(), "aqua".into()),
-            ("punctuation".into(), "grey2".into()),
-            ("punctuation.delimiter".into(), "grey2".into()),
-            ("punctuation.bracket".into(), "fg0".into()),
-            ("keyword".into(), "red".into()),
-            ("keyword.directive".into(), "aqua".into()),
-            ("operator".into(), "orange".into()),
-            ("function".into(), "green".into()),
-            ("function.builtin".into(), "blue".into()),
-            ("function.macro".into(), "aqua".into()),
-            ("tag".into(), "yellow".into()),
-            ("namespace".into(), "aqua".into()),
-            ("attribute".into(), "aqua".into()),
-            ("constructor".into(), "yellow".into()),
-            ("module".into(), "blue".into()),
-            ("special".into(), "orange".into()),
-        ]);
-
-        Self { palette, scheme }
+        Self::from_toml(DEFAULT).expect("the default theme reads")
+    }
+}
+
+/// A value in a theme: a string, a boolean, or an inline table of either.
+#[derive(Debug, PartialEq)]
+enum Value<'a> {
+    Str(&'a str),
+    Bool(bool),
+    Table(Vec<(&'a str, Value<'a>)>),
+}
+
+/// What's left to read of a line of a theme.
+struct Line<'a>(&'a str);
+
+impl<'a> Line<'a> {
+    /// Whether there's nothing left but whitespace and a comment.
+    fn at_end(&mut self) -> bool {
+        self.0 = self.0.trim_start();
+        self.0.is_empty() || self.0.starts_with('#')
+    }
+
+    /// Reads `c`, after any whitespace, if it's next.
+    fn eat(&mut self, c: char) -> bool {
+        self.0 = self.0.trim_start();
+        match self.0.strip_prefix(c) {
+            Some(rest) => {
+                self.0 = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// A `key = value` that's all there is on the line.
+    fn entry(&mut self) -> Result<(&'a str, Value<'a>), String> {
+        let key = self.key()?;
+        if !self.eat('=') {
+            return Err(format!("'{}' has no value", key));
+        }
+        let value = self.value()?;
+        if !self.at_end() {
+            return Err(format!("'{}' is followed by more than its value", key));
+        }
+        Ok((key, value))
+    }
+
+    fn key(&mut self) -> Result<&'a str, String> {
+        self.0 = self.0.trim_start();
+        if self.0.starts_with('"') {
+            return self.string();
+        }
+        let len = self
+            .0
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')))
+            .unwrap_or(self.0.len());
+        let (key, rest) = self.0.split_at(len);
+        if key.is_empty() {
+            return Err("expected a key".to_string());
+        }
+        self.0 = rest;
+        Ok(key)
+    }
+
+    /// A string in double quotes; themes have no need of escapes, so there are none.
+    fn string(&mut self) -> Result<&'a str, String> {
+        if !self.eat('"') {
+            return Err("expected a string".to_string());
+        }
+        let (string, rest) = self.0.split_once('"').ok_or("a string isn't closed")?;
+        self.0 = rest;
+        Ok(string)
+    }
+
+    fn value(&mut self) -> Result<Value<'a>, String> {
+        self.0 = self.0.trim_start();
+        for (word, value) in [("true", true), ("false", false)] {
+            if let Some(rest) = self.0.strip_prefix(word) {
+                self.0 = rest;
+                return Ok(Value::Bool(value));
+            }
+        }
+        if !self.eat('{') {
+            return self.string().map(Value::Str);
+        }
+        let mut entries = vec![];
+        if self.eat('}') {
+            return Ok(Value::Table(entries));
+        }
+        loop {
+            let key = self.key()?;
+            if !self.eat('=') {
+                return Err(format!("'{}' has no value", key));
+            }
+            match self.value()? {
+                Value::Table(_) => return Err(format!("'{}' can't be a table", key)),
+                value => entries.push((key, value)),
+            }
+            if self.eat('}') {
+                return Ok(Value::Table(entries));
+            }
+            if !self.eat(',') {
+                return Err("expected , or } in an inline table".to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THEME: &str = r##"
+        # a comment, and a table after a blank line
+
+        [palette]
+        grey0 = "#7c6f64"
+        red = "#fb4934"   # trailing comments are fine too
+
+        [scheme]
+        "comment" = { fg = "grey0", italic = true }
+        keyword = { fg = "red", bold = true, underlined = true, dim = false }
+        "keyword.control" = "#ff0000"
+        "ui.selection" = { bg = "#374141" }
+        "ui.plain" = {}
+    "##;
+
+    #[test]
+    fn reads_styles_with_modifiers() {
+        let theme = Theme::from_toml(THEME).unwrap();
+        let grey = ColorRef::Palette("grey0".to_string());
+        let comment =
+            StyleSpec { fg: Some(grey), modifiers: tui::Modifier::ITALIC, ..Default::default() };
+        assert_eq!(theme.spec("comment"), Some(&comment));
+        assert_eq!(
+            theme.style("keyword"),
+            tui::Style::default()
+                .fg(tui::Color::Rgb(0xfb, 0x49, 0x34))
+                .add_modifier(tui::Modifier::BOLD | tui::Modifier::UNDERLINED)
+        );
+        let red = tui::Style::default().fg(tui::Color::Rgb(0xff, 0, 0));
+        assert_eq!(theme.style("keyword.control"), red);
+        let selection = tui::Style::default().bg(tui::Color::Rgb(0x37, 0x41, 0x41));
+        assert_eq!(theme.style("ui.selection"), selection);
+        assert_eq!(theme.style("ui.plain"), tui::Style::default());
+    }
+
+    #[test]
+    fn scopes_fall_back_to_their_parents() {
+        let theme = Theme::from_toml(THEME).unwrap();
+        let comment = theme.style("comment");
+        assert!(comment.add_modifier.contains(tui::Modifier::ITALIC));
+        assert_eq!(theme.style("comment.line.documentation"), comment);
+        // the nearest scope that's set wins, without taking anything from further up
+        assert_eq!(theme.style("keyword.control.rust"), theme.style("keyword.control"));
+        assert_eq!(theme.style("keyword.control").add_modifier, tui::Modifier::empty());
+        assert_eq!(theme.style("string"), tui::Style::default());
+        assert_eq!(theme.style("commentary"), tui::Style::default());
+
+        let (entry, color) = theme.scheme_entry("comment.block").unwrap();
+        assert_eq!((entry.to_string(), color.to_string()), ("grey0".into(), "#7c6f64".into()));
+    }
+
+    #[test]
+    fn bad_themes_say_where() {
+        let read = |src: &str| Theme::from_toml(src).unwrap_err();
+        assert_eq!(read("[colors]"), "line 1: unknown table 'colors'");
+        assert_eq!(read("a = \"red\""), "line 1: 'a' isn't in a table");
+        assert_eq!(read("[palette]\nred = \"#fb49\""), "line 2: '#fb49' isn't a #rrggbb color");
+        assert_eq!(
+            read("[scheme]\ncomment = { fg = \"grey0\", slanted = true }"),
+            "line 2: unknown style key 'slanted'"
+        );
+        assert_eq!(
+            read("[scheme]\ncomment = { italic = \"yes\" }"),
+            "line 2: 'italic' is true or false"
+        );
+        assert_eq!(
+            read("[scheme]\ncomment = { fg = \"grey0\""),
+            "line 2: expected , or } in an inline table"
+        );
+        assert_eq!(
+            read("[scheme]\ncomment = \"grey0\""),
+            "'comment' is drawn in 'grey0', which isn't in the palette"
+        );
+        assert_eq!(read("[palette]\na = \"#000000\"\na = \"#000000\""), "line 3: 'a' is set twice");
     }
 }